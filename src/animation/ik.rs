@@ -0,0 +1,142 @@
+use glam::{Mat4, Quat, Vec3};
+
+use super::{Pose, Skeleton};
+use crate::debug_lines::DebugLines;
+
+/// Analytic IK for a three-joint chain such as hip, knee and ankle or shoulder, elbow and wrist.
+/// Run it on the sampled pose before building the skinning palette:
+///
+/// `animator.update(dt)`, then `ik.solve(&skeleton, &mut pose)`, then `skeleton.palette(&pose)`.
+///
+/// Targets are in model space; bring world-space targets in with the inverse of the character's
+/// model matrix.
+#[derive(Clone, Debug)]
+pub struct TwoBoneIk {
+    pub root: usize,
+    pub mid: usize,
+    pub end: usize,
+    /// Where the end joint should be.
+    pub target: Vec3,
+    /// A point the middle joint bends towards, e.g. in front of the knee.
+    pub pole: Vec3,
+    /// Model-space rotation for the end joint, e.g. to align a foot with the ground.
+    pub end_rotation: Option<Quat>,
+    /// Blend between the animated pose (0) and the solved one (1).
+    pub weight: f32,
+}
+
+impl TwoBoneIk {
+    /// The chain ending at `end`, with its parent as the middle joint and grandparent as the
+    /// root. `None` if `end` has no grandparent.
+    pub fn new(skeleton: &Skeleton, end: usize) -> Option<Self> {
+        let mid = skeleton.parents[end]?;
+        let root = skeleton.parents[mid]?;
+        Some(TwoBoneIk {
+            root,
+            mid,
+            end,
+            target: Vec3::ZERO,
+            pole: Vec3::Z,
+            end_rotation: None,
+            weight: 1.0,
+        })
+    }
+
+    /// Moves `target` and `pole` to where the chain currently is, so a weight of 1 changes
+    /// nothing until they're set.
+    pub fn reset_to(&mut self, skeleton: &Skeleton, pose: &Pose) {
+        let model = skeleton.model_transforms(pose);
+        let [a, b, c] = [self.root, self.mid, self.end].map(|joint| model[joint].w_axis.truncate());
+        self.target = c;
+        self.pole = b + (b - (a + c) * 0.5);
+    }
+
+    pub fn solve(&self, skeleton: &Skeleton, pose: &mut Pose) {
+        if self.weight <= 0.0 {
+            return;
+        }
+        let model = skeleton.model_transforms(pose);
+        let position = |joint: usize| model[joint].w_axis.truncate();
+        let rotation = |joint: usize| model[joint].to_scale_rotation_translation().1;
+        let (a, b, c) = (position(self.root), position(self.mid), position(self.end));
+        let parent_rotation = skeleton.parents[self.root].map_or(Quat::IDENTITY, rotation);
+        let (mut root_rotation, mut mid_rotation) = (rotation(self.root), rotation(self.mid));
+
+        let upper = (b - a).length();
+        let lower = (c - b).length();
+        if upper <= f32::EPSILON || lower <= f32::EPSILON {
+            return;
+        }
+        // keep the chain from locking straight or folding onto itself
+        const SLACK: f32 = 1e-4;
+        let reach = (self.target - a).length().clamp((upper - lower).abs() + SLACK, upper + lower - SLACK);
+
+        // bend the root and middle joints in the current plane of the chain to get the length right
+        let angle = |x: Vec3, y: Vec3| x.normalize().dot(y.normalize()).clamp(-1.0, 1.0).acos();
+        let law_of_cosines = |adjacent1: f32, adjacent2: f32, opposite: f32| {
+            ((adjacent1 * adjacent1 + adjacent2 * adjacent2 - opposite * opposite) / (2.0 * adjacent1 * adjacent2))
+                .clamp(-1.0, 1.0)
+                .acos()
+        };
+        let mut axis = (c - a).cross(b - a);
+        if axis.length_squared() <= f32::EPSILON {
+            // straight chain: any plane containing the pole will do
+            axis = (c - a).cross(self.pole - a);
+        }
+        let Some(axis) = axis.try_normalize() else {
+            return;
+        };
+        let root_bend = Quat::from_axis_angle(axis, law_of_cosines(upper, reach, lower) - angle(c - a, b - a));
+        let mid_bend = Quat::from_axis_angle(axis, law_of_cosines(upper, lower, reach) - angle(a - b, c - b));
+        root_rotation = root_bend * root_rotation;
+        mid_rotation = mid_bend * root_bend * mid_rotation;
+        let (b, c) = (a + root_bend * (b - a), a + root_bend * (b - a) + mid_bend * root_bend * (c - b));
+
+        // swing the whole chain at the target
+        let Some(direction) = (self.target - a).try_normalize() else {
+            return;
+        };
+        let swing = Quat::from_rotation_arc((c - a).normalize(), direction);
+        root_rotation = swing * root_rotation;
+        mid_rotation = swing * mid_rotation;
+        let b = a + swing * (b - a);
+
+        // twist around the root-target axis until the middle joint points at the pole
+        let flatten = |v: Vec3| v - direction * v.dot(direction);
+        if let (Some(from), Some(to)) = (flatten(b - a).try_normalize(), flatten(self.pole - a).try_normalize()) {
+            let twist = Quat::from_axis_angle(direction, from.cross(to).dot(direction).atan2(from.dot(to)));
+            root_rotation = twist * root_rotation;
+            mid_rotation = twist * mid_rotation;
+        }
+
+        let blend = |local: &mut Quat, solved: Quat| {
+            let solved = if local.dot(solved) < 0.0 { -solved } else { solved };
+            *local = local.lerp(solved, self.weight.min(1.0)).normalize();
+        };
+        blend(&mut pose.joints[self.root].rotation, parent_rotation.inverse() * root_rotation);
+        blend(&mut pose.joints[self.mid].rotation, root_rotation.inverse() * mid_rotation);
+        if let Some(end_rotation) = self.end_rotation {
+            blend(&mut pose.joints[self.end].rotation, mid_rotation.inverse() * end_rotation);
+        }
+    }
+
+    /// Draws the target, the pole, the line the chain bends along and the chain itself, all
+    /// transformed by the character's `model` matrix.
+    pub fn debug_draw(&self, skeleton: &Skeleton, pose: &Pose, model: Mat4, lines: &mut DebugLines) {
+        const CHAIN: [f32; 4] = [0.2, 0.8, 1.0, 1.0];
+        const TARGET: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+        const POLE: [f32; 4] = [1.0, 0.9, 0.2, 1.0];
+
+        let transforms = skeleton.model_transforms(pose);
+        let [a, b, c] = [self.root, self.mid, self.end].map(|joint| model.transform_point3(transforms[joint].w_axis.truncate()));
+        let (target, pole) = (model.transform_point3(self.target), model.transform_point3(self.pole));
+        let size = ((b - a).length() + (c - b).length()) * 0.1;
+
+        lines.line(a, b, CHAIN);
+        lines.line(b, c, CHAIN);
+        lines.cross(target, size, TARGET);
+        lines.line(c, target, TARGET);
+        lines.cross(pole, size, POLE);
+        lines.line(b, pole, POLE);
+    }
+}
@@ -0,0 +1,266 @@
+pub mod ik;
+pub mod state_machine;
+
+use glam::{Mat4, Quat, Vec3};
+
+/// A joint's transform relative to its parent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JointTransform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for JointTransform {
+    fn default() -> Self {
+        JointTransform::IDENTITY
+    }
+}
+
+impl JointTransform {
+    pub const IDENTITY: JointTransform = JointTransform {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    pub fn lerp(&self, other: &JointTransform, t: f32) -> JointTransform {
+        JointTransform {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: nlerp(self.rotation, other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+
+    /// The transform that takes `reference` to `self`, for additive animation.
+    pub fn difference(&self, reference: &JointTransform) -> JointTransform {
+        JointTransform {
+            translation: self.translation - reference.translation,
+            rotation: (reference.rotation.inverse() * self.rotation).normalize(),
+            scale: self.scale / reference.scale,
+        }
+    }
+
+    /// Applies a difference made by `difference`, scaled by `weight`.
+    pub fn add(&self, delta: &JointTransform, weight: f32) -> JointTransform {
+        let delta = JointTransform::IDENTITY.lerp(delta, weight);
+        JointTransform {
+            translation: self.translation + delta.translation,
+            rotation: (self.rotation * delta.rotation).normalize(),
+            scale: self.scale * delta.scale,
+        }
+    }
+}
+
+/// Normalized lerp along the shorter arc; close enough to slerp for the small angles between
+/// neighbouring keyframes and blended poses, and much cheaper.
+fn nlerp(from: Quat, to: Quat, t: f32) -> Quat {
+    let to = if from.dot(to) < 0.0 { -to } else { to };
+    from.lerp(to, t).normalize()
+}
+
+/// Per-joint weights for a layer, e.g. 1 for the spine and arms and 0 for the legs to play an
+/// upper-body animation on top of locomotion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JointMask(pub Vec<f32>);
+
+impl JointMask {
+    /// `joint` and all its descendants at `weight`, everything else at 0.
+    pub fn subtree(skeleton: &Skeleton, joint: usize, weight: f32) -> Self {
+        let mut weights = vec![0.0; skeleton.len()];
+        weights[joint] = weight;
+        // parents come before children, so one pass reaches the whole subtree
+        for index in joint + 1..skeleton.len() {
+            if let Some(parent) = skeleton.parents[index] {
+                weights[index] = weights[parent];
+            }
+        }
+        JointMask(weights)
+    }
+
+    pub fn weight(&self, joint: usize) -> f32 {
+        self.0.get(joint).copied().unwrap_or(0.0)
+    }
+}
+
+/// Local transforms for every joint of a skeleton.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pose {
+    pub joints: Vec<JointTransform>,
+}
+
+impl Pose {
+    /// Blends towards `other` by `weight`, per joint scaled by `mask` if given.
+    pub fn blend(&mut self, other: &Pose, weight: f32, mask: Option<&JointMask>) {
+        for (index, (joint, target)) in self.joints.iter_mut().zip(&other.joints).enumerate() {
+            let weight = weight * mask.map_or(1.0, |mask| mask.weight(index));
+            if weight > 0.0 {
+                *joint = joint.lerp(target, weight.min(1.0));
+            }
+        }
+    }
+
+    /// Adds a pose of differences (see `JointTransform::difference`) on top of this one.
+    pub fn add(&mut self, delta: &Pose, weight: f32, mask: Option<&JointMask>) {
+        for (index, (joint, delta)) in self.joints.iter_mut().zip(&delta.joints).enumerate() {
+            let weight = weight * mask.map_or(1.0, |mask| mask.weight(index));
+            if weight > 0.0 {
+                *joint = joint.add(delta, weight);
+            }
+        }
+    }
+}
+
+/// The joint hierarchy of a skinned mesh.
+#[derive(Clone, Debug)]
+pub struct Skeleton {
+    pub names: Vec<String>,
+    /// Every parent comes before its children.
+    pub parents: Vec<Option<usize>>,
+    pub inverse_bind: Vec<Mat4>,
+    /// The pose joints take when no clip animates them.
+    pub rest_pose: Pose,
+}
+
+impl Skeleton {
+    pub fn new(names: Vec<String>, parents: Vec<Option<usize>>, inverse_bind: Vec<Mat4>, rest_pose: Pose) -> Self {
+        assert!(
+            parents.iter().enumerate().all(|(index, parent)| parent.is_none_or(|parent| parent < index)),
+            "skeleton joints must be sorted parents first"
+        );
+        assert!(names.len() == parents.len() && inverse_bind.len() == parents.len() && rest_pose.joints.len() == parents.len());
+        Skeleton {
+            names,
+            parents,
+            inverse_bind,
+            rest_pose,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    /// Model-space transforms of every joint in `pose`.
+    pub fn model_transforms(&self, pose: &Pose) -> Vec<Mat4> {
+        let mut transforms: Vec<Mat4> = Vec::with_capacity(self.len());
+        for (joint, parent) in pose.joints.iter().zip(&self.parents) {
+            let local = joint.matrix();
+            transforms.push(match parent {
+                Some(parent) => transforms[*parent] * local,
+                None => local,
+            });
+        }
+        transforms
+    }
+
+    /// Joint matrices for `SkinnedMesh::set_palette`.
+    pub fn palette(&self, pose: &Pose) -> Vec<Mat4> {
+        self.model_transforms(pose)
+            .iter()
+            .zip(&self.inverse_bind)
+            .map(|(model, inverse_bind)| *model * *inverse_bind)
+            .collect()
+    }
+}
+
+/// Keyframes for one property, with times in seconds, sorted.
+#[derive(Clone, Debug, Default)]
+pub struct Track<T> {
+    pub times: Vec<f32>,
+    pub values: Vec<T>,
+}
+
+impl<T: Copy> Track<T> {
+    fn sample(&self, time: f32, interpolate: impl Fn(T, T, f32) -> T) -> Option<T> {
+        let last = self.times.len().checked_sub(1)?;
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 {
+            return Some(self.values[0]);
+        }
+        if next > last {
+            return Some(self.values[last]);
+        }
+        let (t0, t1) = (self.times[next - 1], self.times[next]);
+        let t = if t1 > t0 { (time - t0) / (t1 - t0) } else { 0.0 };
+        Some(interpolate(self.values[next - 1], self.values[next], t))
+    }
+}
+
+/// The keyframes animating one joint; empty tracks leave that property alone.
+#[derive(Clone, Debug, Default)]
+pub struct Channel {
+    pub joint: usize,
+    pub translation: Track<Vec3>,
+    pub rotation: Track<Quat>,
+    pub scale: Track<Vec3>,
+}
+
+/// A named moment in a clip, such as a footstep or the frame an attack connects.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationEvent {
+    pub name: String,
+    /// Position in the clip, 0 to 1.
+    pub time: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Length in seconds.
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+    pub events: Vec<AnimationEvent>,
+}
+
+impl AnimationClip {
+    /// Overwrites the joints this clip animates with their values at `time`, in seconds.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in &self.channels {
+            let Some(joint) = pose.joints.get_mut(channel.joint) else {
+                continue;
+            };
+            if let Some(translation) = channel.translation.sample(time, Vec3::lerp) {
+                joint.translation = translation;
+            }
+            if let Some(rotation) = channel.rotation.sample(time, nlerp) {
+                joint.rotation = rotation;
+            }
+            if let Some(scale) = channel.scale.sample(time, Vec3::lerp) {
+                joint.scale = scale;
+            }
+        }
+    }
+
+    /// Events that playback moving forward from `from` to `to` seconds passes, including one at
+    /// `from` itself. A looping clip repeats its events every cycle; a clip played once fires
+    /// events at its very end when playback reaches it.
+    pub fn events_between(&self, from: f32, to: f32, looping: bool) -> impl Iterator<Item = &AnimationEvent> {
+        let (from, to) = if self.duration > 0.0 && to > from {
+            (from / self.duration, to / self.duration)
+        } else {
+            (0.0, 0.0)
+        };
+        self.events.iter().flat_map(move |event| {
+            let count = if looping {
+                ((to - event.time).ceil() - (from - event.time).ceil()).max(0.0) as usize
+            } else {
+                let time = event.time.clamp(0.0, 1.0 - f32::EPSILON);
+                usize::from(from <= time && time < to)
+            };
+            std::iter::repeat_n(event, count)
+        })
+    }
+}
@@ -0,0 +1,430 @@
+use std::{collections::HashMap, sync::Arc};
+
+use super::{AnimationClip, JointMask, Pose, Skeleton};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parameter {
+    Float(f32),
+    Bool(bool),
+    /// Set until a transition consumes it.
+    Trigger(bool),
+}
+
+/// Values game code sets to drive transitions, such as speed, grounded or jump.
+#[derive(Clone, Debug, Default)]
+pub struct Parameters {
+    values: HashMap<String, Parameter>,
+}
+
+impl Parameters {
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.values.insert(name.to_owned(), Parameter::Float(value));
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.values.insert(name.to_owned(), Parameter::Bool(value));
+    }
+
+    pub fn set_trigger(&mut self, name: &str) {
+        self.values.insert(name.to_owned(), Parameter::Trigger(true));
+    }
+
+    pub fn reset_trigger(&mut self, name: &str) {
+        self.values.insert(name.to_owned(), Parameter::Trigger(false));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Parameter> {
+        self.values.get(name).copied()
+    }
+
+    /// Missing and non-float parameters read as 0.
+    pub fn float(&self, name: &str) -> f32 {
+        match self.get(name) {
+            Some(Parameter::Float(value)) => value,
+            _ => 0.0,
+        }
+    }
+
+    pub fn bool(&self, name: &str) -> bool {
+        matches!(self.get(name), Some(Parameter::Bool(true)))
+    }
+
+    pub fn triggered(&self, name: &str) -> bool {
+        matches!(self.get(name), Some(Parameter::Trigger(true)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    Greater(String, f32),
+    Less(String, f32),
+    Bool(String, bool),
+    Trigger(String),
+}
+
+impl Condition {
+    fn holds(&self, parameters: &Parameters) -> bool {
+        match self {
+            Condition::Greater(name, value) => parameters.float(name) > *value,
+            Condition::Less(name, value) => parameters.float(name) < *value,
+            Condition::Bool(name, value) => parameters.bool(name) == *value,
+            Condition::Trigger(name) => parameters.triggered(name),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StateId(usize);
+
+#[derive(Clone, Debug)]
+pub struct AnimationState {
+    pub name: String,
+    pub clip: Arc<AnimationClip>,
+    /// Playback rate; 1 plays the clip as authored.
+    pub speed: f32,
+    pub looping: bool,
+}
+
+impl AnimationState {
+    pub fn new(name: &str, clip: Arc<AnimationClip>) -> Self {
+        AnimationState {
+            name: name.to_owned(),
+            clip,
+            speed: 1.0,
+            looping: true,
+        }
+    }
+
+    pub fn once(mut self) -> Self {
+        self.looping = false;
+        self
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Where in the clip `time` seconds of playback lands.
+    fn clip_time(&self, time: f32) -> f32 {
+        let duration = self.clip.duration;
+        if duration <= 0.0 {
+            0.0
+        } else if self.looping {
+            time.rem_euclid(duration)
+        } else {
+            time.clamp(0.0, duration)
+        }
+    }
+
+    /// Progress through the current loop, 0 to 1.
+    fn normalized_time(&self, time: f32) -> f32 {
+        if self.clip.duration <= 0.0 {
+            1.0
+        } else {
+            self.clip_time(time) / self.clip.duration
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Transition {
+    /// `None` transitions from any state other than `to`.
+    pub from: Option<StateId>,
+    pub to: StateId,
+    /// Crossfade length in seconds.
+    pub duration: f32,
+    /// Only taken once the source state has played this far, 0 to 1. With no conditions the
+    /// transition is taken as soon as that point is reached.
+    pub exit_time: Option<f32>,
+    /// All of these must hold.
+    pub conditions: Vec<Condition>,
+}
+
+impl Transition {
+    pub fn new(from: StateId, to: StateId, duration: f32) -> Self {
+        Transition {
+            from: Some(from),
+            to,
+            duration,
+            exit_time: None,
+            conditions: Vec::new(),
+        }
+    }
+
+    pub fn from_any(to: StateId, duration: f32) -> Self {
+        Transition {
+            from: None,
+            to,
+            duration,
+            exit_time: None,
+            conditions: Vec::new(),
+        }
+    }
+
+    pub fn when(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn at_exit_time(mut self, exit_time: f32) -> Self {
+        self.exit_time = Some(exit_time);
+        self
+    }
+}
+
+/// States and the transitions between them. One machine can be shared by every character
+/// using it; the playback state lives in the `Animator`.
+#[derive(Clone, Debug, Default)]
+pub struct StateMachine {
+    states: Vec<AnimationState>,
+    transitions: Vec<Transition>,
+}
+
+impl StateMachine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The first state added is the one playback starts in.
+    pub fn add_state(&mut self, state: AnimationState) -> StateId {
+        self.states.push(state);
+        StateId(self.states.len() - 1)
+    }
+
+    /// Transitions are checked in the order they were added and the first that applies wins.
+    pub fn add_transition(&mut self, transition: Transition) {
+        self.transitions.push(transition);
+    }
+
+    pub fn state(&self, id: StateId) -> &AnimationState {
+        &self.states[id.0]
+    }
+
+    pub fn find(&self, name: &str) -> Option<StateId> {
+        self.states.iter().position(|s| s.name == name).map(StateId)
+    }
+
+    fn next_transition(&self, playing: &Playing, parameters: &Parameters) -> Option<&Transition> {
+        let state = self.state(playing.state);
+        self.transitions.iter().find(|transition| {
+            let from = transition.from.map_or(transition.to != playing.state, |from| from == playing.state);
+            from && transition
+                .exit_time
+                .is_none_or(|exit_time| state.normalized_time(playing.time) >= exit_time)
+                && transition.conditions.iter().all(|condition| condition.holds(parameters))
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayerBlend {
+    /// Blends towards the layer's pose by its weight, replacing the layers below.
+    Override,
+    /// Adds the layer's motion relative to the first frame of each clip on top of the layers
+    /// below, e.g. breathing or a flinch.
+    Additive,
+}
+
+/// An animation event reached during the last `Animator::update`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FiredEvent {
+    pub layer: usize,
+    pub state: StateId,
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Playing {
+    state: StateId,
+    /// Seconds of playback, already scaled by the state's speed.
+    time: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Fade {
+    from: Playing,
+    elapsed: f32,
+    duration: f32,
+}
+
+pub struct AnimationLayer {
+    pub machine: Arc<StateMachine>,
+    pub weight: f32,
+    pub blend: LayerBlend,
+    pub mask: Option<JointMask>,
+    playing: Playing,
+    fade: Option<Fade>,
+}
+
+impl AnimationLayer {
+    pub fn new(machine: Arc<StateMachine>) -> Self {
+        assert!(!machine.states.is_empty(), "a state machine needs at least one state");
+        AnimationLayer {
+            machine,
+            weight: 1.0,
+            blend: LayerBlend::Override,
+            mask: None,
+            playing: Playing {
+                state: StateId(0),
+                time: 0.0,
+            },
+            fade: None,
+        }
+    }
+
+    pub fn additive(mut self) -> Self {
+        self.blend = LayerBlend::Additive;
+        self
+    }
+
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_mask(mut self, mask: JointMask) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    pub fn current_state(&self) -> StateId {
+        self.playing.state
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    /// Switches to `state` right away, crossfading over `duration` seconds.
+    pub fn play(&mut self, state: StateId, duration: f32) {
+        self.fade = (duration > 0.0).then_some(Fade {
+            from: self.playing,
+            elapsed: 0.0,
+            duration,
+        });
+        self.playing = Playing { state, time: 0.0 };
+    }
+
+    fn update(&mut self, index: usize, dt: f32, parameters: &mut Parameters, events: &mut Vec<FiredEvent>) {
+        let machine = self.machine.clone();
+        // during a crossfade only the state with the larger share fires events, so blending walk
+        // into run doesn't play two sets of footsteps
+        let dominant = match &self.fade {
+            Some(fade) if fade.elapsed < fade.duration * 0.5 => fade.from,
+            _ => self.playing,
+        };
+        let state = machine.state(dominant.state);
+        if self.weight > 0.0 {
+            let end = dominant.time + dt * state.speed;
+            events.extend(state.clip.events_between(dominant.time, end, state.looping).map(|event| FiredEvent {
+                layer: index,
+                state: dominant.state,
+                name: event.name.clone(),
+            }));
+        }
+
+        self.playing.time += dt * machine.state(self.playing.state).speed;
+        if let Some(fade) = &mut self.fade {
+            fade.from.time += dt * machine.state(fade.from.state).speed;
+            fade.elapsed += dt;
+            if fade.elapsed >= fade.duration {
+                self.fade = None;
+            }
+        }
+
+        // a crossfade runs to the end before the next transition can start
+        if self.fade.is_some() {
+            return;
+        }
+        if let Some(transition) = machine.next_transition(&self.playing, parameters) {
+            for condition in &transition.conditions {
+                if let Condition::Trigger(name) = condition {
+                    parameters.reset_trigger(name);
+                }
+            }
+            self.play(transition.to, transition.duration);
+        }
+    }
+
+    fn sample_state(&self, playing: &Playing, rest: &Pose, pose: &mut Pose) {
+        let state = self.machine.state(playing.state);
+        pose.clone_from(rest);
+        state.clip.sample(state.clip_time(playing.time), pose);
+        if self.blend == LayerBlend::Additive {
+            let mut reference = rest.clone();
+            state.clip.sample(0.0, &mut reference);
+            for (joint, reference) in pose.joints.iter_mut().zip(&reference.joints) {
+                *joint = joint.difference(reference);
+            }
+        }
+    }
+
+    fn sample(&self, rest: &Pose, pose: &mut Pose, scratch: &mut Pose) {
+        self.sample_state(&self.playing, rest, pose);
+        if let Some(fade) = &self.fade {
+            self.sample_state(&fade.from, rest, scratch);
+            scratch.blend(pose, fade.elapsed / fade.duration, None);
+            std::mem::swap(pose, scratch);
+        }
+    }
+}
+
+/// Plays a stack of state machine layers on one skeleton. Layers apply bottom to top, so the
+/// first is usually full-body locomotion and later ones masked or additive overlays.
+pub struct Animator {
+    pub parameters: Parameters,
+    pub layers: Vec<AnimationLayer>,
+    rest: Pose,
+    pose: Pose,
+    layer_pose: Pose,
+    scratch: Pose,
+    events: Vec<FiredEvent>,
+}
+
+impl Animator {
+    pub fn new(skeleton: &Skeleton) -> Self {
+        Animator {
+            parameters: Parameters::default(),
+            layers: Vec::new(),
+            rest: skeleton.rest_pose.clone(),
+            pose: skeleton.rest_pose.clone(),
+            layer_pose: Pose::default(),
+            scratch: Pose::default(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn add_layer(&mut self, layer: AnimationLayer) -> usize {
+        self.layers.push(layer);
+        self.layers.len() - 1
+    }
+
+    /// Advances every layer, takes transitions whose conditions hold and samples the result.
+    pub fn update(&mut self, dt: f32) {
+        self.pose.clone_from(&self.rest);
+        self.events.clear();
+        for (index, layer) in self.layers.iter_mut().enumerate() {
+            layer.update(index, dt, &mut self.parameters, &mut self.events);
+            if layer.weight <= 0.0 {
+                continue;
+            }
+            layer.sample(&self.rest, &mut self.layer_pose, &mut self.scratch);
+            match layer.blend {
+                LayerBlend::Override => self.pose.blend(&self.layer_pose, layer.weight, layer.mask.as_ref()),
+                LayerBlend::Additive => self.pose.add(&self.layer_pose, layer.weight, layer.mask.as_ref()),
+            }
+        }
+    }
+
+    /// The blended local pose from the last update, ready for `Skeleton::palette`.
+    pub fn pose(&self) -> &Pose {
+        &self.pose
+    }
+
+    /// Events crossed during the last update, in layer order, for gameplay and audio to react
+    /// to; footsteps, hit frames and the like.
+    pub fn events(&self) -> &[FiredEvent] {
+        &self.events
+    }
+}
@@ -0,0 +1,347 @@
+mod pool;
+pub mod texture;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Weak},
+};
+
+use crate::{
+    gpu_error,
+    load_profile::{LoadStage, LoadTimer},
+    loading::{LoadPhase, ProgressReporter},
+};
+
+/// A type `Assets` can load from files: decoded from the file's bytes on a worker thread, then
+/// finished on the main thread, e.g. uploaded to the GPU.
+pub trait Asset: Sized + 'static {
+    /// Options a load is made with. The same file loaded with different settings is a
+    /// different asset.
+    type Settings: Clone + Hash + Eq + Send + 'static;
+    /// The decoded file, passed from the worker thread to `finish`.
+    type Data: Send + 'static;
+    /// What `finish` needs from the main thread.
+    type Context<'a>;
+    /// The kind of asset in the load profile.
+    const KIND: &'static str;
+
+    fn decode(bytes: &[u8], settings: &Self::Settings) -> Result<Self::Data, String>;
+
+    fn finish(context: &mut Self::Context<'_>, label: &str, data: Self::Data) -> Result<Self, String>;
+
+    /// Called on the main thread when the asset is dropped, e.g. to untrack its memory.
+    fn unload(self, _context: &mut Self::Context<'_>) {}
+}
+
+/// A reference to an asset in an `Assets`. The asset stays loaded while any clone of a handle
+/// to it is alive and is dropped on the `update` after the last one is.
+pub struct Handle<T> {
+    id: Arc<u64>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(id: Arc<u64>) -> Self {
+        Handle { id, marker: PhantomData }
+    }
+
+    /// Unique within the `Assets` it came from.
+    pub fn id(&self) -> u64 {
+        *self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle::new(self.id.clone())
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.id())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed,
+}
+
+enum State<T> {
+    Loading,
+    Loaded(T),
+    Failed(String),
+}
+
+struct Entry<T: Asset> {
+    handle: Weak<u64>,
+    key: Option<(PathBuf, T::Settings)>,
+    state: State<T>,
+    reporter: Option<ProgressReporter>,
+}
+
+type Decoded<T> = (u64, Result<<T as Asset>::Data, String>, LoadTimer);
+
+/// The loaded assets of one type, by handle. Loading the same file with the same settings
+/// again, while a handle to it is alive, returns that handle rather than reading the file
+/// again, whether the first load has finished or not.
+///
+/// Files are read and decoded on a shared pool of worker threads and finished on `update`,
+/// which the engine calls every frame for the assets it owns.
+pub struct Assets<T: Asset> {
+    next_id: u64,
+    entries: HashMap<u64, Entry<T>>,
+    paths: HashMap<(PathBuf, T::Settings), u64>,
+    sender: mpsc::Sender<Decoded<T>>,
+    receiver: mpsc::Receiver<Decoded<T>>,
+}
+
+impl<T: Asset> Assets<T> {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Assets {
+            next_id: 0,
+            entries: HashMap::new(),
+            paths: HashMap::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    fn insert(&mut self, key: Option<(PathBuf, T::Settings)>, state: State<T>) -> Handle<T> {
+        let id = Arc::new(self.next_id);
+        self.next_id += 1;
+        if let Some(key) = &key {
+            self.paths.insert(key.clone(), *id);
+        }
+        self.entries.insert(
+            *id,
+            Entry {
+                handle: Arc::downgrade(&id),
+                key,
+                state,
+                reporter: None,
+            },
+        );
+        Handle::new(id)
+    }
+
+    /// Adds an asset made in code rather than loaded from a file.
+    pub fn add(&mut self, asset: T) -> Handle<T> {
+        self.insert(None, State::Loaded(asset))
+    }
+
+    /// Starts loading `path` in the background, unless it already is or has been; failed loads
+    /// are retried.
+    pub fn load(&mut self, path: impl AsRef<Path>, settings: T::Settings) -> Handle<T> {
+        let path = path.as_ref();
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+        let key = (path.clone(), settings.clone());
+        if let Some(&id) = self.paths.get(&key) {
+            if let Some(entry) = self.entries.get_mut(&id) {
+                // still cached if the last handle was dropped since the last update
+                let shared = entry.handle.upgrade().unwrap_or_else(|| {
+                    let shared = Arc::new(id);
+                    entry.handle = Arc::downgrade(&shared);
+                    shared
+                });
+                if matches!(entry.state, State::Failed(_)) {
+                    entry.state = State::Loading;
+                    self.spawn(id, path, settings);
+                }
+                return Handle::new(shared);
+            }
+        }
+        let handle = self.insert(Some(key), State::Loading);
+        self.spawn(handle.id(), path, settings);
+        handle
+    }
+
+    /// `load`, as a task of `phase` that finishes once the asset is ready to use.
+    pub fn load_tracked(&mut self, path: impl AsRef<Path>, settings: T::Settings, phase: &mut LoadPhase) -> Handle<T> {
+        let handle = self.load(path.as_ref(), settings);
+        if let Some(entry) = self.entries.get_mut(&handle.id()) {
+            if matches!(entry.state, State::Loading) && entry.reporter.is_none() {
+                entry.reporter = Some(phase.track(path.as_ref().display().to_string(), 1.0));
+            }
+        }
+        handle
+    }
+
+    fn spawn(&self, id: u64, path: PathBuf, settings: T::Settings) {
+        let sender = self.sender.clone();
+        pool::spawn(move || {
+            let mut timer = LoadTimer::for_path(T::KIND, &path);
+            let result = std::fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| {
+                    timer.add_file_bytes(bytes.len());
+                    timer.end(LoadStage::Read);
+                    // a decoder bug must not take the worker down with the load
+                    panic::catch_unwind(AssertUnwindSafe(|| T::decode(&bytes, &settings)))
+                        .unwrap_or_else(|_| Err("the decoder panicked".into()))
+                });
+            timer.end(LoadStage::Decode);
+            sender.send((id, result, timer)).ok();
+        });
+    }
+
+    /// Finishes the loads that have been decoded and drops the assets no handle refers to any
+    /// more.
+    pub fn update(&mut self, context: &mut T::Context<'_>) {
+        while let Ok((id, result, mut timer)) = self.receiver.try_recv() {
+            // dropped while it was loading
+            let Some(entry) = self.entries.get_mut(&id) else {
+                continue;
+            };
+            let label = entry.key.as_ref().map_or_else(String::new, |(path, _)| path.display().to_string());
+            let result = result.and_then(|data| T::finish(context, &label, data));
+            timer.end(LoadStage::Upload);
+            match result {
+                Ok(asset) => {
+                    timer.finish();
+                    entry.state = State::Loaded(asset);
+                    if let Some(reporter) = entry.reporter.take() {
+                        reporter.finish();
+                    }
+                }
+                Err(e) => {
+                    match entry.reporter.take() {
+                        // the load phase reports it
+                        Some(reporter) => reporter.fail(e.clone()),
+                        None => log::error!("Failed to load {}: {}", label, e),
+                    }
+                    entry.state = State::Failed(e);
+                }
+            }
+        }
+
+        let dropped: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.handle.strong_count() == 0)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in dropped {
+            let Some(entry) = self.entries.remove(&id) else {
+                continue;
+            };
+            if let Some(key) = &entry.key {
+                self.paths.remove(key);
+            }
+            if let Some(reporter) = &entry.reporter {
+                reporter.fail("every handle was dropped");
+            }
+            if let State::Loaded(asset) = entry.state {
+                asset.unload(context);
+            }
+        }
+    }
+
+    /// The asset, once it has loaded.
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        match &self.entries.get(&handle.id())?.state {
+            State::Loaded(asset) => Some(asset),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T> {
+        match &mut self.entries.get_mut(&handle.id())?.state {
+            State::Loaded(asset) => Some(asset),
+            _ => None,
+        }
+    }
+
+    /// `Failed` as well for handles of another `Assets`.
+    pub fn state(&self, handle: &Handle<T>) -> LoadState {
+        match self.entries.get(&handle.id()).map(|entry| &entry.state) {
+            Some(State::Loading) => LoadState::Loading,
+            Some(State::Loaded(_)) => LoadState::Loaded,
+            Some(State::Failed(_)) | None => LoadState::Failed,
+        }
+    }
+
+    /// Why the load failed.
+    pub fn error(&self, handle: &Handle<T>) -> Option<&str> {
+        match &self.entries.get(&handle.id())?.state {
+            State::Failed(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// The file the asset was loaded from; `None` for added ones.
+    pub fn path(&self, handle: &Handle<T>) -> Option<&Path> {
+        self.entries.get(&handle.id())?.key.as_ref().map(|(path, _)| path.as_path())
+    }
+
+    fn loaded_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.entries.values_mut().filter_map(|entry| match &mut entry.state {
+            State::Loaded(asset) => Some(asset),
+            _ => None,
+        })
+    }
+
+    /// Whether any load has yet to finish.
+    pub fn is_loading(&self) -> bool {
+        self.entries.values().any(|entry| matches!(entry.state, State::Loading))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T: Asset> Default for Assets<T> {
+    fn default() -> Self {
+        Assets::new()
+    }
+}
+
+/// WGSL shaders, compiled into modules on the device.
+impl Asset for wgpu::ShaderModule {
+    type Settings = ();
+    type Data = String;
+    type Context<'a> = &'a wgpu::Device;
+    const KIND: &'static str = "shader";
+
+    fn decode(bytes: &[u8], _settings: &()) -> Result<String, String> {
+        String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn finish(device: &mut &wgpu::Device, label: &str, source: String) -> Result<Self, String> {
+        gpu_error::capture(device, format!("compiling {}", label), || {
+            device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            })
+        })
+        .map_err(|e| e.to_string())
+    }
+}
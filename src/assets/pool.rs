@@ -0,0 +1,53 @@
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The worker threads every `Assets` reads and decodes files on: one per core, minus the one
+/// the main thread keeps busy.
+struct WorkerPool {
+    sender: Mutex<mpsc::Sender<Job>>,
+    workers: usize,
+}
+
+impl WorkerPool {
+    fn new() -> Self {
+        let count = std::thread::available_parallelism().map_or(2, |cores| cores.get()).saturating_sub(1).max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut workers = 0;
+        for index in 0..count {
+            let receiver = receiver.clone();
+            let spawned = std::thread::Builder::new().name(format!("asset worker {}", index)).spawn(move || loop {
+                // the lock is only held while waiting, never while a job runs
+                let job = match receiver.lock() {
+                    Ok(receiver) => receiver.recv(),
+                    Err(_) => return,
+                };
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => return,
+                }
+            });
+            match spawned {
+                Ok(_) => workers += 1,
+                Err(e) => log::error!("Failed to start asset worker: {}", e),
+            }
+        }
+        WorkerPool {
+            sender: Mutex::new(sender),
+            workers,
+        }
+    }
+}
+
+/// Runs `job` on a worker thread, or right away if none could be started.
+pub(super) fn spawn(job: impl FnOnce() + Send + 'static) {
+    static POOL: OnceLock<WorkerPool> = OnceLock::new();
+    let pool = POOL.get_or_init(WorkerPool::new);
+    if pool.workers == 0 {
+        return job();
+    }
+    if let Ok(sender) = pool.sender.lock() {
+        sender.send(Box::new(job)).ok();
+    }
+}
@@ -0,0 +1,351 @@
+use super::TextureError;
+
+/// Decompresses a zlib stream, as PNG stores its pixels in, failing once it would produce more
+/// than `limit` bytes. The checksum is not verified: PNG chunks carry CRCs of their own.
+pub(super) fn zlib_decompress(input: &[u8], limit: usize) -> Result<Vec<u8>, TextureError> {
+    let [cmf, flags, ..] = *input else {
+        return Err(error("truncated zlib header"));
+    };
+    if cmf & 0x0f != 8 || (u16::from(cmf) << 8 | u16::from(flags)) % 31 != 0 {
+        return Err(error("not a deflate stream"));
+    }
+    if flags & 0x20 != 0 {
+        return Err(error("preset dictionaries are not supported"));
+    }
+    inflate(&input[2..], limit)
+}
+
+fn error(message: &str) -> TextureError {
+    TextureError::Format(format!("invalid compressed data: {}", message))
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    bits: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        BitReader {
+            bytes,
+            position: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn refill(&mut self) {
+        while self.count <= 56 {
+            let byte = self.bytes.get(self.position).copied().unwrap_or(0);
+            self.position += 1;
+            self.bits |= u64::from(byte) << self.count;
+            self.count += 8;
+        }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, TextureError> {
+        if count == 0 {
+            return Ok(0);
+        }
+        if self.count < count {
+            self.refill();
+        }
+        // reading zeros past the end is allowed while refilling, using them is not
+        if self.position > self.bytes.len() + 8 {
+            return Err(error("unexpected end of stream"));
+        }
+        let value = (self.bits & ((1 << count) - 1)) as u32;
+        self.bits >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        let skip = self.count % 8;
+        self.bits >>= skip;
+        self.count -= skip;
+    }
+
+    fn byte(&mut self) -> Result<u8, TextureError> {
+        self.bits(8).map(|value| value as u8)
+    }
+}
+
+/// Canonical Huffman decoding table: symbols sorted by code, with the number of codes of each
+/// length.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, TextureError> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, TextureError> {
+        // codes are read bit by bit, most significant first
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(error("invalid Huffman code"))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// The order code length code lengths are stored in.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn inflate(input: &[u8], limit: usize) -> Result<Vec<u8>, TextureError> {
+    let mut reader = BitReader::new(input);
+    let mut output = Vec::with_capacity((input.len() * 4).min(limit));
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let length = reader.bits(16)?;
+                let complement = reader.bits(16)?;
+                if length != !complement & 0xffff {
+                    return Err(error("stored block length mismatch"));
+                }
+                if output.len() + length as usize > limit {
+                    return Err(error("more data than expected"));
+                }
+                for _ in 0..length {
+                    output.push(reader.byte()?);
+                }
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literals = Huffman::new(&lengths)?;
+                let distances = Huffman::new(&[5; 30])?;
+                inflate_block(&mut reader, &mut output, limit, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut output, limit, &literals, &distances)?;
+            }
+            _ => return Err(error("invalid block type")),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), TextureError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..index].last().ok_or_else(|| error("repeat without a previous length"))?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > lengths.len() {
+            return Err(error("code lengths overflow"));
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    let (literals, distances) = lengths.split_at(literal_count);
+    Ok((Huffman::new(literals)?, Huffman::new(distances)?))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    limit: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), TextureError> {
+    loop {
+        if output.len() > limit {
+            return Err(error("more data than expected"));
+        }
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err(error("invalid length symbol"));
+                }
+                let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(error("invalid distance symbol"));
+                }
+                let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                if distance > output.len() {
+                    return Err(error("distance before the start of the output"));
+                }
+                // the match may overlap what it writes, so copy byte by byte
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// `count` words from a small vocabulary, picked by an LCG, as `DYNAMIC` was compressed from.
+    fn words(count: usize) -> Vec<u8> {
+        let words: [&[u8]; 6] = [b"mesh", b"texture", b"shader", b"pass", b"light", b"frame"];
+        let mut x: u64 = 1;
+        let mut picked = Vec::new();
+        for _ in 0..count {
+            x = (x * 1103515245 + 12345) % (1 << 31);
+            picked.push(words[((x >> 16) % 6) as usize]);
+        }
+        picked.join(&b' ')
+    }
+
+    /// `words(300)` compressed by zlib at level 9, which uses a dynamic Huffman block.
+    const DYNAMIC: [u8; 284] = [
+        0x78, 0xda, 0x8d, 0x54, 0x5b, 0x0a, 0xc3, 0x30, 0x0c, 0xbb, 0x4a, 0xae, 0x56, 0x58, 0xb6, 0x0e, 0x56, 0x18, 0x4d, 0x07,
+        0x3b, 0xfe, 0x20, 0x8f, 0x45, 0xb2, 0xe5, 0xd2, 0x9f, 0x24, 0xad, 0x5f, 0xb2, 0xac, 0xa4, 0xac, 0xcb, 0x2d, 0xef, 0xe9,
+        0xf5, 0x7c, 0xac, 0x47, 0x7a, 0x2f, 0xa5, 0xa4, 0x23, 0x7f, 0x8f, 0xcf, 0x9e, 0xff, 0xfb, 0x7d, 0x5f, 0xb6, 0x9c, 0xb6,
+        0x5c, 0xd6, 0x66, 0xaf, 0xa7, 0xba, 0x08, 0x8f, 0xba, 0xb4, 0xef, 0xb6, 0xd6, 0x90, 0xb9, 0x54, 0x7b, 0xc1, 0x9a, 0xd6,
+        0x6d, 0x16, 0x6a, 0x96, 0xe6, 0xd5, 0x43, 0xfa, 0xd6, 0x2c, 0x22, 0x0d, 0x86, 0x0c, 0x74, 0xae, 0x6e, 0xdf, 0x66, 0xc9,
+        0xe1, 0x89, 0x06, 0x2a, 0x31, 0x31, 0x89, 0x9a, 0xd5, 0xd8, 0x7e, 0x08, 0x56, 0x2c, 0x9b, 0x76, 0x87, 0xe8, 0x09, 0xd4,
+        0x37, 0x83, 0x2b, 0x82, 0x82, 0xba, 0xdc, 0x35, 0xe5, 0x07, 0xa0, 0xa2, 0xee, 0x29, 0x34, 0xe2, 0x01, 0x66, 0x45, 0xc9,
+        0x14, 0x81, 0xfd, 0x4c, 0xc9, 0xcc, 0x80, 0xa3, 0x02, 0x44, 0xb2, 0x40, 0x03, 0x74, 0x79, 0x76, 0xc0, 0xc8, 0xf3, 0xf6,
+        0xd3, 0x8d, 0x44, 0x1c, 0xb4, 0xe5, 0xd9, 0x17, 0x70, 0x55, 0xef, 0x5c, 0x02, 0x70, 0x8b, 0xde, 0x84, 0x2f, 0x1c, 0xd9,
+        0x2a, 0x74, 0x1e, 0x26, 0x71, 0x6c, 0x9d, 0x35, 0x07, 0xaa, 0xba, 0x20, 0xe9, 0xf0, 0x1e, 0x32, 0xf1, 0xf6, 0x62, 0x73,
+        0x32, 0x12, 0xc0, 0x74, 0x1d, 0x66, 0xad, 0x54, 0x3b, 0x4b, 0x27, 0x0f, 0x7b, 0xbb, 0x02, 0x35, 0x32, 0x14, 0x08, 0x12,
+        0x03, 0x2e, 0xe1, 0x83, 0xe9, 0x2d, 0xe2, 0xd6, 0xca, 0x37, 0xee, 0x84, 0x49, 0x18, 0x45, 0x78, 0xa3, 0x1c, 0xa9, 0x73,
+        0x72, 0x34, 0x3e, 0x4b, 0x49, 0x74, 0xff, 0x94, 0xca, 0xa8, 0x09, 0x8a, 0x11, 0xc3, 0x10, 0xce, 0x42, 0xab, 0x1e, 0xd6,
+        0xa5, 0x07, 0x21, 0x7a, 0x91, 0x18, 0xbb, 0xeb, 0xd0, 0xbe, 0x5d, 0xa8, 0x4a, 0xad, 0xef, 0x48, 0x37, 0xfd, 0xe3, 0x07,
+        0x27, 0x2f, 0xb3, 0x0f,
+    ];
+
+    /// Wraps `data` in stored blocks, the way the PNG tests write their image data.
+    pub(in crate::assets::texture) fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x78, 0x01];
+        let chunks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(65535).collect() };
+        for (index, chunk) in chunks.iter().enumerate() {
+            out.push(u8::from(index + 1 == chunks.len()));
+            let length = chunk.len() as u16;
+            out.extend_from_slice(&length.to_le_bytes());
+            out.extend_from_slice(&(!length).to_le_bytes());
+            out.extend_from_slice(chunk);
+        }
+        // the checksum isn't verified
+        out.extend_from_slice(&[0; 4]);
+        out
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        let expected = words(300);
+        assert_eq!(zlib_decompress(&DYNAMIC, expected.len()).unwrap(), expected);
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        // "abcabcabcabcabc" compressed by zlib, a literal run and a back reference
+        let compressed = [0x78, 0x9c, 0x4b, 0x4c, 0x4a, 0x4e, 0x44, 0x42, 0x00, 0x2d, 0xf5, 0x05, 0xbf];
+        assert_eq!(zlib_decompress(&compressed, 100).unwrap(), b"abcabcabcabcabc");
+    }
+
+    #[test]
+    fn stored_blocks() {
+        let data: Vec<u8> = (0..70000u32).map(|i| (i * 31 % 251) as u8).collect();
+        assert_eq!(zlib_decompress(&zlib_stored(&data), data.len()).unwrap(), data);
+        assert_eq!(zlib_decompress(&zlib_stored(&[]), 0).unwrap(), b"");
+    }
+
+    #[test]
+    fn output_beyond_the_limit_is_rejected() {
+        let expected = words(300);
+        assert!(zlib_decompress(&DYNAMIC, expected.len() / 2).is_err());
+        assert!(zlib_decompress(&zlib_stored(&expected), expected.len() - 1).is_err());
+    }
+
+    #[test]
+    fn corrupt_headers_are_rejected() {
+        assert!(zlib_decompress(&[], 10).is_err());
+        assert!(zlib_decompress(&[0x78], 10).is_err());
+        // not deflate, and a bad header checksum
+        assert!(zlib_decompress(&[0x79, 0x01, 0x01, 0x00, 0x00, 0xff, 0xff], 10).is_err());
+        assert!(zlib_decompress(&[0x78, 0x02, 0x01, 0x00, 0x00, 0xff, 0xff], 10).is_err());
+    }
+
+    #[test]
+    fn truncated_streams_fail() {
+        for length in 0..DYNAMIC.len() - 4 {
+            assert!(zlib_decompress(&DYNAMIC[..length], 4096).is_err(), "{} bytes decoded", length);
+        }
+    }
+
+    #[test]
+    fn corrupt_streams_never_panic() {
+        for index in 2..DYNAMIC.len() {
+            for flip in [0x01, 0x10, 0xff] {
+                let mut corrupt = DYNAMIC;
+                corrupt[index] ^= flip;
+                zlib_decompress(&corrupt, 4096).ok();
+            }
+        }
+    }
+}
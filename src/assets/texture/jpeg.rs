@@ -0,0 +1,689 @@
+use super::{check_dimensions, ImageData, TextureError, MAX_DIMENSION};
+
+fn error(message: impl Into<String>) -> TextureError {
+    TextureError::Format(format!("invalid JPEG: {}", message.into()))
+}
+
+/// Largest magnitude categories a baseline file codes DC differences and AC coefficients in;
+/// larger ones only come from corrupt tables.
+const MAX_DC_SIZE: u8 = 11;
+const MAX_AC_SIZE: u8 = 10;
+
+/// Zigzag order of the coefficients of an 8x8 block.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21,
+    28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61,
+    54, 47, 55, 62, 63,
+];
+
+#[derive(Clone, Default)]
+struct Huffman {
+    /// Maximum code of each length, -1 if there are none.
+    max_code: [i32; 17],
+    /// Index into `values` of the first code of each length, minus that code.
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8; 16], values: Vec<u8>) -> Self {
+        let mut table = Huffman {
+            max_code: [-1; 17],
+            offset: [0; 17],
+            values,
+        };
+        let (mut code, mut index) = (0i32, 0i32);
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            if count > 0 {
+                table.offset[length] = index - code;
+                code += count;
+                index += count;
+                table.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant: usize,
+    dc_table: usize,
+    ac_table: usize,
+    /// Blocks across and down, padded to whole MCUs.
+    blocks_wide: usize,
+    blocks_high: usize,
+    /// Decoded samples, `blocks_wide * 8` wide.
+    samples: Vec<u8>,
+    dc: i32,
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<u32, TextureError> {
+        if self.count == 0 {
+            let byte = *self.bytes.get(self.position).ok_or_else(|| error("truncated scan"))?;
+            self.position += 1;
+            if byte == 0xff {
+                match self.bytes.get(self.position) {
+                    Some(0) => self.position += 1,
+                    // a marker inside the entropy data: pad with ones, as decoders do
+                    _ => {
+                        self.position -= 1;
+                        self.bits = 0xff;
+                        self.count = 8;
+                        return self.bit();
+                    }
+                }
+            }
+            self.bits = u32::from(byte);
+            self.count = 8;
+        }
+        self.count -= 1;
+        Ok((self.bits >> self.count) & 1)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, TextureError> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = value << 1 | self.bit()?;
+        }
+        Ok(value)
+    }
+
+    fn decode(&mut self, table: &Huffman) -> Result<u8, TextureError> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = code << 1 | self.bit()? as i32;
+            if code <= table.max_code[length] {
+                let index = (code + table.offset[length]) as usize;
+                return table.values.get(index).copied().ok_or_else(|| error("invalid Huffman code"));
+            }
+        }
+        Err(error("invalid Huffman code"))
+    }
+
+    /// Reads a `size` bit magnitude and extends its sign.
+    fn receive_extend(&mut self, size: u8) -> Result<i32, TextureError> {
+        if size == 0 {
+            return Ok(0);
+        }
+        let value = self.bits(u32::from(size))? as i32;
+        Ok(if value < 1 << (size - 1) { value - (1 << size) + 1 } else { value })
+    }
+
+    /// Skips to the restart marker that must follow and past it.
+    fn restart(&mut self) -> Result<(), TextureError> {
+        self.count = 0;
+        while self.position + 1 < self.bytes.len() {
+            if self.bytes[self.position] == 0xff && (0xd0..=0xd7).contains(&self.bytes[self.position + 1]) {
+                self.position += 2;
+                return Ok(());
+            }
+            self.position += 1;
+        }
+        Err(error("missing restart marker"))
+    }
+}
+
+/// Decodes a baseline or extended sequential JPEG with Huffman coding, grayscale or with three
+/// components (YCbCr, or RGB when an Adobe marker says so), with any chroma subsampling.
+/// Progressive and arithmetic coded files are rejected.
+pub(super) fn decode(bytes: &[u8]) -> Result<ImageData, TextureError> {
+    if !bytes.starts_with(&[0xff, 0xd8]) {
+        return Err(error("missing start of image"));
+    }
+    let mut quant = [[0u16; 64]; 4];
+    let mut dc_tables: [Huffman; 4] = Default::default();
+    let mut ac_tables: [Huffman; 4] = Default::default();
+    let mut components: Vec<Component> = Vec::new();
+    let (mut width, mut height) = (0usize, 0usize);
+    let mut restart_interval = 0usize;
+    let mut adobe_transform = None;
+    let mut decoded = false;
+    let mut position = 2;
+
+    while position + 4 <= bytes.len() {
+        if bytes[position] != 0xff {
+            position += 1;
+            continue;
+        }
+        let marker = bytes[position + 1];
+        position += 2;
+        if marker == 0xff || marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+            if marker == 0xff {
+                position -= 1;
+            }
+            continue;
+        }
+        if marker == 0xd9 {
+            break;
+        }
+        let length = u16::from_be_bytes([bytes[position], bytes[position + 1]]) as usize;
+        let segment = bytes.get(position + 2..position + length).ok_or_else(|| error("truncated segment"))?;
+        position += length;
+        match marker {
+            0xdb => {
+                let mut data = segment;
+                while !data.is_empty() {
+                    let precision = data[0] >> 4;
+                    let table = (data[0] & 3) as usize;
+                    let size = if precision == 0 { 64 } else { 128 };
+                    let values = data.get(1..1 + size).ok_or_else(|| error("short quantization table"))?;
+                    for i in 0..64 {
+                        quant[table][ZIGZAG[i]] = if precision == 0 {
+                            u16::from(values[i])
+                        } else {
+                            u16::from_be_bytes([values[i * 2], values[i * 2 + 1]])
+                        };
+                    }
+                    data = &data[1 + size..];
+                }
+            }
+            0xc4 => {
+                let mut data = segment;
+                while data.len() >= 17 {
+                    let class = data[0] >> 4;
+                    let table = (data[0] & 3) as usize;
+                    let mut counts = [0u8; 16];
+                    counts.copy_from_slice(&data[1..17]);
+                    let total: usize = counts.iter().map(|&count| count as usize).sum();
+                    let values = data.get(17..17 + total).ok_or_else(|| error("short Huffman table"))?.to_vec();
+                    let huffman = Huffman::new(&counts, values);
+                    if class == 0 {
+                        dc_tables[table] = huffman;
+                    } else {
+                        ac_tables[table] = huffman;
+                    }
+                    data = &data[17 + total..];
+                }
+            }
+            0xc0 | 0xc1 => {
+                if segment.len() < 6 || segment[0] != 8 {
+                    return Err(error("only 8-bit samples are supported"));
+                }
+                if !components.is_empty() {
+                    return Err(error("more than one frame"));
+                }
+                height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+                width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+                let count = segment[5] as usize;
+                if height == 0 {
+                    return Err(error("DNL markers are not supported"));
+                }
+                check_dimensions(width as u32, height as u32, MAX_DIMENSION).map_err(|e| error(e.to_string()))?;
+                if count != 1 && count != 3 {
+                    return Err(error(format!("{} components", count)));
+                }
+                for i in 0..count {
+                    let data = segment.get(6 + i * 3..9 + i * 3).ok_or_else(|| error("short frame header"))?;
+                    components.push(Component {
+                        id: data[0],
+                        h: (data[1] >> 4).clamp(1, 4) as usize,
+                        v: (data[1] & 15).clamp(1, 4) as usize,
+                        quant: (data[2] & 3) as usize,
+                        dc_table: 0,
+                        ac_table: 0,
+                        blocks_wide: 0,
+                        blocks_high: 0,
+                        samples: Vec::new(),
+                        dc: 0,
+                    });
+                }
+            }
+            0xc2 | 0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => {
+                return Err(error("progressive, lossless and arithmetic coded files are not supported"));
+            }
+            0xdd if segment.len() >= 2 => {
+                restart_interval = u16::from_be_bytes([segment[0], segment[1]]) as usize;
+            }
+            0xee if segment.len() >= 12 && segment.starts_with(b"Adobe") => {
+                adobe_transform = Some(segment[11]);
+            }
+            0xda => {
+                if components.is_empty() {
+                    return Err(error("scan before frame header"));
+                }
+                let count = *segment.first().ok_or_else(|| error("short scan header"))? as usize;
+                let mut scan = Vec::with_capacity(count);
+                for i in 0..count {
+                    let data = segment.get(1 + i * 2..3 + i * 2).ok_or_else(|| error("short scan header"))?;
+                    let index = components
+                        .iter()
+                        .position(|component| component.id == data[0])
+                        .ok_or_else(|| error("scan of an unknown component"))?;
+                    components[index].dc_table = (data[1] >> 4 & 3) as usize;
+                    components[index].ac_table = (data[1] & 3) as usize;
+                    scan.push(index);
+                }
+                let tables = Tables {
+                    quant: &quant,
+                    dc: &dc_tables,
+                    ac: &ac_tables,
+                };
+                position = decode_scan(bytes, position, &mut components, &scan, width, height, restart_interval, &tables)?;
+                decoded = true;
+            }
+            _ => {}
+        }
+    }
+    if !decoded {
+        return Err(error("no image data"));
+    }
+
+    let h_max = components.iter().map(|component| component.h).max().unwrap_or(1);
+    let v_max = components.iter().map(|component| component.v).max().unwrap_or(1);
+    // upsample each component to full resolution, nearest neighbour
+    let sample = |component: &Component, x: usize, y: usize| -> u8 {
+        let cx = x * component.h / h_max;
+        let cy = y * component.v / v_max;
+        component.samples[cy * component.blocks_wide * 8 + cx]
+    };
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    // three components are YCbCr unless an Adobe marker says they are RGB
+    let ycbcr = adobe_transform != Some(0);
+    for y in 0..height {
+        for x in 0..width {
+            if components.len() == 1 {
+                let gray = sample(&components[0], x, y);
+                pixels.extend_from_slice(&[gray, gray, gray, 255]);
+                continue;
+            }
+            let (c0, c1, c2) = (
+                sample(&components[0], x, y) as f32,
+                sample(&components[1], x, y) as f32,
+                sample(&components[2], x, y) as f32,
+            );
+            let [r, g, b] = if ycbcr {
+                [
+                    c0 + 1.402 * (c2 - 128.0),
+                    c0 - 0.344_136 * (c1 - 128.0) - 0.714_136 * (c2 - 128.0),
+                    c0 + 1.772 * (c1 - 128.0),
+                ]
+            } else {
+                [c0, c1, c2]
+            };
+            pixels.extend_from_slice(&[r.round().clamp(0.0, 255.0) as u8, g.round().clamp(0.0, 255.0) as u8, b.round().clamp(0.0, 255.0) as u8, 255]);
+        }
+    }
+    Ok(ImageData {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    })
+}
+
+struct Tables<'a> {
+    quant: &'a [[u16; 64]; 4],
+    dc: &'a [Huffman; 4],
+    ac: &'a [Huffman; 4],
+}
+
+/// Decodes the entropy coded data of a scan starting at `position`, returning where it ends.
+#[allow(clippy::too_many_arguments)]
+fn decode_scan(
+    bytes: &[u8],
+    position: usize,
+    components: &mut [Component],
+    scan: &[usize],
+    width: usize,
+    height: usize,
+    restart_interval: usize,
+    tables: &Tables,
+) -> Result<usize, TextureError> {
+    let h_max = components.iter().map(|component| component.h).max().unwrap_or(1);
+    let v_max = components.iter().map(|component| component.v).max().unwrap_or(1);
+    let mcus_wide = width.div_ceil(8 * h_max);
+    let mcus_high = height.div_ceil(8 * v_max);
+    for component in components.iter_mut() {
+        // components may come in scans of their own, keep the ones decoded before
+        if component.samples.is_empty() {
+            component.blocks_wide = mcus_wide * component.h;
+            component.blocks_high = mcus_high * component.v;
+            component.samples = vec![0; component.blocks_wide * component.blocks_high * 64];
+        }
+        component.dc = 0;
+    }
+    let basis = idct_basis();
+
+    let mut reader = BitReader {
+        bytes,
+        position,
+        bits: 0,
+        count: 0,
+    };
+    let mut block = [0i32; 64];
+    // a scan of a single component codes its blocks in raster order rather than in MCUs
+    let single = scan.len() == 1;
+    let (units_wide, units_high) = if single {
+        let component = &components[scan[0]];
+        ((width * component.h).div_ceil(8 * h_max), (height * component.v).div_ceil(8 * v_max))
+    } else {
+        (mcus_wide, mcus_high)
+    };
+    for unit in 0..units_wide * units_high {
+        if restart_interval > 0 && unit > 0 && unit % restart_interval == 0 {
+            reader.restart()?;
+            for component in components.iter_mut() {
+                component.dc = 0;
+            }
+        }
+        let (unit_x, unit_y) = (unit % units_wide, unit / units_wide);
+        for &index in scan {
+            let component = &mut components[index];
+            let (h, v) = if single { (1, 1) } else { (component.h, component.v) };
+            for by in 0..v {
+                for bx in 0..h {
+                    decode_block(&mut reader, component, tables, &mut block)?;
+                    let (block_x, block_y) = (unit_x * h + bx, unit_y * v + by);
+                    let stride = component.blocks_wide * 8;
+                    let origin = block_y * 8 * stride + block_x * 8;
+                    idct(&basis, &block, &mut component.samples[origin..], stride);
+                }
+            }
+        }
+    }
+    // leave the reader's position at the next marker
+    let mut end = reader.position;
+    while end + 1 < bytes.len() && !(bytes[end] == 0xff && bytes[end + 1] != 0 && !(0xd0..=0xd7).contains(&bytes[end + 1])) {
+        end += 1;
+    }
+    Ok(end)
+}
+
+fn decode_block(reader: &mut BitReader, component: &mut Component, tables: &Tables, block: &mut [i32; 64]) -> Result<(), TextureError> {
+    let quant = &tables.quant[component.quant];
+    block.fill(0);
+    let size = reader.decode(&tables.dc[component.dc_table])?;
+    if size > MAX_DC_SIZE {
+        return Err(error(format!("DC difference of {} bits", size)));
+    }
+    // a valid file keeps the sum in range, a corrupt one must not overflow it
+    component.dc = component.dc.wrapping_add(reader.receive_extend(size)?);
+    block[0] = component.dc.wrapping_mul(i32::from(quant[0]));
+    let mut k = 1;
+    while k < 64 {
+        let symbol = reader.decode(&tables.ac[component.ac_table])?;
+        let (run, size) = ((symbol >> 4) as usize, symbol & 15);
+        if size == 0 {
+            if run == 15 {
+                k += 16;
+                continue;
+            }
+            break;
+        }
+        if size > MAX_AC_SIZE {
+            return Err(error(format!("AC coefficient of {} bits", size)));
+        }
+        k += run;
+        if k > 63 {
+            return Err(error("coefficient index out of range"));
+        }
+        block[ZIGZAG[k]] = reader.receive_extend(size)? * i32::from(quant[ZIGZAG[k]]);
+        k += 1;
+    }
+    Ok(())
+}
+
+/// cos((2x + 1) u pi / 16) scaled by C(u) / 2, indexed `[x][u]`.
+fn idct_basis() -> [[f32; 8]; 8] {
+    let mut table = [[0f32; 8]; 8];
+    for (x, row) in table.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            let scale = if u == 0 { std::f32::consts::FRAC_1_SQRT_2 } else { 1.0 } * 0.5;
+            *value = scale * ((2 * x + 1) as f32 * u as f32 * std::f32::consts::PI / 16.0).cos();
+        }
+    }
+    table
+}
+
+/// Inverse DCT of a dequantized block into `output`, rows `stride` apart, level shifted to
+/// 0-255. The separable float version: simple and exact enough for textures.
+fn idct(table: &[[f32; 8]; 8], block: &[i32; 64], output: &mut [u8], stride: usize) {
+    let mut rows = [0f32; 64];
+    for v in 0..8 {
+        for x in 0..8 {
+            rows[v * 8 + x] = (0..8).map(|u| table[x][u] * block[v * 8 + u] as f32).sum();
+        }
+    }
+    for y in 0..8 {
+        for x in 0..8 {
+            let value: f32 = (0..8).map(|v| table[y][v] * rows[v * 8 + x]).sum();
+            output[y * stride + x] = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bits: u32,
+        count: u32,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, count: u32) {
+            for i in (0..count).rev() {
+                self.bits = self.bits << 1 | (value >> i & 1);
+                self.count += 1;
+                if self.count == 8 {
+                    self.push(self.bits as u8);
+                }
+            }
+        }
+
+        fn push(&mut self, byte: u8) {
+            self.bytes.push(byte);
+            if byte == 0xff {
+                self.bytes.push(0);
+            }
+            self.bits = 0;
+            self.count = 0;
+        }
+
+        /// Pads the last byte with ones.
+        fn flush(&mut self) {
+            if self.count > 0 {
+                let byte = (self.bits << (8 - self.count) | (0xff >> self.count)) as u8;
+                self.push(byte);
+            }
+        }
+    }
+
+    fn segment(out: &mut Vec<u8>, marker: u8, data: &[u8]) {
+        out.extend_from_slice(&[0xff, marker]);
+        out.extend_from_slice(&(data.len() as u16 + 2).to_be_bytes());
+        out.extend_from_slice(data);
+    }
+
+    /// A baseline JPEG of flat 8x8 blocks: only DC coefficients, quantized by 1, each coded
+    /// with a 4-bit code equal to its size. `level(component, x, y)` is block `(x, y)`'s value.
+    fn encode(width: u16, height: u16, components: &[(u8, u8)], restart_interval: u16, level: impl Fn(usize, usize, usize) -> u8) -> Vec<u8> {
+        let mut out = vec![0xff, 0xd8];
+        let mut quant = vec![0];
+        quant.extend_from_slice(&[1; 64]);
+        segment(&mut out, 0xdb, &quant);
+        let mut frame = vec![8];
+        frame.extend_from_slice(&height.to_be_bytes());
+        frame.extend_from_slice(&width.to_be_bytes());
+        frame.push(components.len() as u8);
+        for (index, &(h, v)) in components.iter().enumerate() {
+            frame.extend_from_slice(&[index as u8 + 1, h << 4 | v, 0]);
+        }
+        segment(&mut out, 0xc0, &frame);
+        // DC: sizes 0-11, all four bits long; AC: just end of block, one bit long
+        let mut huffman = vec![0x00, 0, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        huffman.extend(0..12);
+        huffman.extend_from_slice(&[0x10, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00]);
+        segment(&mut out, 0xc4, &huffman);
+        if restart_interval > 0 {
+            segment(&mut out, 0xdd, &restart_interval.to_be_bytes());
+        }
+        let mut scan = vec![components.len() as u8];
+        for index in 0..components.len() {
+            scan.extend_from_slice(&[index as u8 + 1, 0x00]);
+        }
+        scan.extend_from_slice(&[0, 63, 0]);
+        segment(&mut out, 0xda, &scan);
+
+        let h_max = components.iter().map(|&(h, _)| h as usize).max().unwrap();
+        let v_max = components.iter().map(|&(_, v)| v as usize).max().unwrap();
+        let mcus_wide = (width as usize).div_ceil(8 * h_max);
+        let mcus_high = (height as usize).div_ceil(8 * v_max);
+        let mut writer = BitWriter {
+            bytes: Vec::new(),
+            bits: 0,
+            count: 0,
+        };
+        let mut predictions = vec![0i32; components.len()];
+        for mcu in 0..mcus_wide * mcus_high {
+            if restart_interval > 0 && mcu > 0 && mcu % restart_interval as usize == 0 {
+                writer.flush();
+                writer.bytes.extend_from_slice(&[0xff, 0xd0 + ((mcu / restart_interval as usize - 1) % 8) as u8]);
+                predictions.fill(0);
+            }
+            let (mcu_x, mcu_y) = (mcu % mcus_wide, mcu / mcus_wide);
+            for (index, &(h, v)) in components.iter().enumerate() {
+                let (h, v) = (h as usize, v as usize);
+                for by in 0..v {
+                    for bx in 0..h {
+                        let dc = 8 * (i32::from(level(index, mcu_x * h + bx, mcu_y * v + by)) - 128);
+                        let difference = dc - predictions[index];
+                        predictions[index] = dc;
+                        let size = 32 - difference.unsigned_abs().leading_zeros();
+                        writer.write(size, 4);
+                        let magnitude = if difference < 0 { difference + (1 << size) - 1 } else { difference };
+                        writer.write(magnitude as u32, size);
+                        // end of block
+                        writer.write(0, 1);
+                    }
+                }
+            }
+        }
+        writer.flush();
+        out.extend_from_slice(&writer.bytes);
+        out.extend_from_slice(&[0xff, 0xd9]);
+        out
+    }
+
+    fn gray_level(_: usize, x: usize, y: usize) -> u8 {
+        (x * 70 + y * 45) as u8
+    }
+
+    fn gray_file() -> Vec<u8> {
+        encode(20, 12, &[(1, 1)], 0, gray_level)
+    }
+
+    #[test]
+    fn grayscale_with_partial_blocks() {
+        let image = decode(&gray_file()).unwrap();
+        assert_eq!((image.width, image.height), (20, 12));
+        for y in 0..12 {
+            for x in 0..20 {
+                let level = gray_level(0, x / 8, y / 8);
+                let offset = (y * 20 + x) * 4;
+                assert_eq!(image.pixels[offset..offset + 4], [level, level, level, 255], "pixel {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn subsampled_color() {
+        // 4:2:0, neutral chroma in the left MCU, red in the right one
+        let level = |component: usize, x: usize, y: usize| match component {
+            0 => (60 + x * 30 + y * 20) as u8,
+            1 => 128,
+            _ if x == 0 => 128,
+            _ => 200,
+        };
+        let image = decode(&encode(32, 16, &[(2, 2), (1, 1), (1, 1)], 0, level)).unwrap();
+        for y in 0..16 {
+            for x in 0..32 {
+                let luma = level(0, x / 8, y / 8) as f32;
+                let cr = level(2, x / 16, 0) as f32 - 128.0;
+                let expected = [luma + 1.402 * cr, luma - 0.714_136 * cr, luma];
+                let pixel = &image.pixels[(y * 32 + x) * 4..][..4];
+                for (channel, expected) in expected.iter().enumerate() {
+                    let expected = expected.round().clamp(0.0, 255.0);
+                    assert!((pixel[channel] as f32 - expected).abs() <= 2.0, "pixel {}, {}: {:?}", x, y, pixel);
+                }
+                assert_eq!(pixel[3], 255);
+            }
+        }
+    }
+
+    #[test]
+    fn restart_markers_reset_prediction() {
+        let plain = decode(&gray_file()).unwrap();
+        for interval in [1, 2, 5] {
+            let restarted = decode(&encode(20, 12, &[(1, 1)], interval, gray_level)).unwrap();
+            assert_eq!(restarted.pixels, plain.pixels, "restart interval {}", interval);
+        }
+    }
+
+    #[test]
+    fn oversized_dc_sizes_are_rejected() {
+        let mut file = gray_file();
+        // every DC code now decodes to a 15-bit difference
+        let table = file.windows(5).position(|window| window == [0xff, 0xc4, 0, 49, 0x00]).unwrap() + 21;
+        file[table..table + 12].fill(15);
+        let Err(TextureError::Format(message)) = decode(&file) else {
+            panic!("an oversized DC difference decoded");
+        };
+        assert!(message.contains("DC difference"), "{}", message);
+    }
+
+    #[test]
+    fn oversized_dimensions_are_rejected() {
+        let mut file = gray_file();
+        let frame = file.windows(2).position(|window| window == [0xff, 0xc0]).unwrap();
+        file[frame + 5..frame + 9].fill(0xff);
+        let Err(TextureError::Format(message)) = decode(&file) else {
+            panic!("a 65535x65535 image decoded");
+        };
+        assert!(message.contains("larger"), "{}", message);
+    }
+
+    #[test]
+    fn unsupported_files_are_rejected() {
+        assert!(decode(b"").is_err());
+        assert!(decode(&[0xff, 0xd8, 0xff, 0xd9]).is_err());
+        let mut progressive = gray_file();
+        let frame = progressive.windows(2).position(|window| window == [0xff, 0xc0]).unwrap();
+        progressive[frame + 1] = 0xc2;
+        assert!(decode(&progressive).is_err());
+    }
+
+    #[test]
+    fn truncated_files_never_panic() {
+        let file = encode(32, 16, &[(2, 2), (1, 1), (1, 1)], 1, |component, x, y| (component * 50 + x * 30 + y * 90) as u8);
+        for length in 0..file.len() {
+            decode(&file[..length]).ok();
+        }
+    }
+
+    #[test]
+    fn corrupt_files_never_panic() {
+        let file = encode(32, 16, &[(2, 2), (1, 1), (1, 1)], 2, |component, x, y| (component * 50 + x * 30 + y * 90) as u8);
+        for index in 2..file.len() {
+            for flip in [0x01, 0x10, 0x80, 0xff] {
+                let mut corrupt = file.clone();
+                corrupt[index] ^= flip;
+                decode(&corrupt).ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use super::{check_dimensions, TextureError, MAX_DIMENSION};
+
+pub(super) const IDENTIFIER: [u8; 12] = [0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n'];
+
+fn error(message: impl Into<String>) -> TextureError {
+    TextureError::Format(format!("invalid KTX2: {}", message.into()))
+}
+
+/// A KTX2 texture's levels, ready to upload as they are.
+pub(super) struct Ktx2Image {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed rows of blocks of each mip level, most detailed first.
+    pub levels: Vec<Vec<u8>>,
+    /// The file stores only the base level and asks for the rest to be generated.
+    pub generate_mips: bool,
+}
+
+/// The texture format of a Vulkan format number, for the formats the loader supports.
+fn texture_format(vk_format: u32) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat::*;
+    Some(match vk_format {
+        9 => R8Unorm,
+        16 => Rg8Unorm,
+        37 => Rgba8Unorm,
+        43 => Rgba8UnormSrgb,
+        44 => Bgra8Unorm,
+        50 => Bgra8UnormSrgb,
+        97 => Rgba16Float,
+        109 => Rgba32Float,
+        131 | 133 => Bc1RgbaUnorm,
+        132 | 134 => Bc1RgbaUnormSrgb,
+        135 => Bc2RgbaUnorm,
+        136 => Bc2RgbaUnormSrgb,
+        137 => Bc3RgbaUnorm,
+        138 => Bc3RgbaUnormSrgb,
+        139 => Bc4RUnorm,
+        141 => Bc5RgUnorm,
+        143 => Bc6hRgbUfloat,
+        145 => Bc7RgbaUnorm,
+        146 => Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+/// Reads a 2D KTX2 texture without supercompression: a single face and layer, in one of the
+/// uncompressed 8-bit, half or float formats or a BC format.
+pub(super) fn decode(bytes: &[u8]) -> Result<Ktx2Image, TextureError> {
+    if !bytes.starts_with(&IDENTIFIER) {
+        return Err(error("missing identifier"));
+    }
+    let u32_at = |offset: usize| -> Result<u32, TextureError> {
+        let field = bytes.get(offset..offset + 4).ok_or_else(|| error("truncated header"))?;
+        Ok(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+    };
+    let u64_at = |offset: usize| -> Result<u64, TextureError> { Ok(u64::from(u32_at(offset)?) | u64::from(u32_at(offset + 4)?) << 32) };
+
+    let vk_format = u32_at(12)?;
+    let (width, height, depth) = (u32_at(20)?, u32_at(24)?, u32_at(28)?);
+    let (layers, faces, level_count, supercompression) = (u32_at(32)?, u32_at(36)?, u32_at(40)?, u32_at(44)?);
+    let format = texture_format(vk_format).ok_or_else(|| error(format!("unsupported format {}", vk_format)))?;
+    if supercompression != 0 {
+        return Err(error("supercompressed files are not supported"));
+    }
+    if height == 0 || depth > 1 || layers > 1 || faces != 1 {
+        return Err(error("only 2D textures of a single layer and face are supported"));
+    }
+    check_dimensions(width, height, MAX_DIMENSION).map_err(|e| error(e.to_string()))?;
+
+    // 0 levels means the base level only, with the rest to be generated
+    let generate_mips = level_count == 0;
+    let level_count = level_count.max(1);
+    let full_mip_count = 32 - width.max(height).leading_zeros();
+    if level_count > full_mip_count {
+        return Err(error(format!("{} levels for a {}x{} texture", level_count, width, height)));
+    }
+    let (block_width, block_height) = format.describe().block_dimensions;
+    let block_size = format.describe().block_size as usize;
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+        let index = 80 + level as usize * 24;
+        let (offset, length) = (u64_at(index)? as usize, u64_at(index + 8)? as usize);
+        let (level_width, level_height) = ((width >> level).max(1), (height >> level).max(1));
+        let blocks = level_width.div_ceil(u32::from(block_width)) as usize * level_height.div_ceil(u32::from(block_height)) as usize;
+        if length < blocks * block_size {
+            return Err(error(format!("level {} is too short", level)));
+        }
+        let data = bytes.get(offset..offset + blocks * block_size).ok_or_else(|| error(format!("level {} is truncated", level)))?;
+        levels.push(data.to_vec());
+    }
+    Ok(Ktx2Image {
+        format,
+        width,
+        height,
+        levels,
+        generate_mips,
+    })
+}
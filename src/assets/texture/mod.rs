@@ -0,0 +1,526 @@
+mod inflate;
+mod jpeg;
+mod ktx2;
+mod png;
+
+use std::{collections::HashMap, fmt, num::NonZeroU32, path::Path, sync::Arc};
+
+use super::{Asset, Assets};
+use crate::{
+    gpu_memory::{AllocationId, GpuMemory, MemoryCategory},
+    load_profile::{LoadStage, LoadTimer},
+    postprocess::{fullscreen_module, FullscreenPipeline},
+    texture_streaming::{desired_first_mip, MipChain, StreamedTexture},
+};
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Format(String),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Io(e) => write!(f, "{}", e),
+            TextureError::Format(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+/// Largest width or height the decoders accept, checked before allocating anything for the
+/// image: what wgpu's default limits allow, and small enough that a corrupt header can't ask
+/// for gigabytes.
+const MAX_DIMENSION: u32 = 8192;
+
+fn check_dimensions(width: u32, height: u32, max_dimension: u32) -> Result<(), TextureError> {
+    if width == 0 || height == 0 {
+        return Err(TextureError::Format("empty image".into()));
+    }
+    if width > max_dimension || height > max_dimension {
+        return Err(TextureError::Format(format!("{}x{} is larger than {} pixels", width, height, max_dimension)));
+    }
+    Ok(())
+}
+
+/// An 8-bit RGBA image, as PNG and JPEG files decode to.
+struct ImageData {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureOptions {
+    /// Whether PNG and JPEG texels are sRGB encoded color, as for albedo and emissive maps,
+    /// rather than linear data such as normals or roughness. KTX2 files state their own format.
+    pub srgb: bool,
+    /// Whether to fill in the mip chain below the base level, on the GPU. KTX2 files that store
+    /// their mips are uploaded with them instead.
+    pub mipmaps: bool,
+    /// Whether `Assets<Texture>` uploads only the levels up to `STREAMED_RESIDENT_SIZE` pixels
+    /// at first and streams in more detail as `Texture::request_detail` asks for it. Only KTX2
+    /// files that store their mips can be streamed; anything else is uploaded whole.
+    pub streamed: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions {
+            srgb: true,
+            mipmaps: true,
+            streamed: false,
+        }
+    }
+}
+
+impl TextureOptions {
+    /// For data textures: linear, with mipmaps.
+    pub fn linear() -> Self {
+        TextureOptions {
+            srgb: false,
+            ..Default::default()
+        }
+    }
+
+    /// These options with `streamed` set.
+    pub fn with_streaming(self) -> Self {
+        TextureOptions { streamed: true, ..self }
+    }
+}
+
+/// Size in pixels below which the levels of a streamed texture are uploaded right away.
+pub const STREAMED_RESIDENT_SIZE: u32 = 128;
+
+/// A decoded image, ready to upload: the base level and any mips the file stored.
+pub struct TextureData {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed rows of texels, or blocks for compressed formats, of each stored mip
+    /// level, most detailed first.
+    pub levels: Vec<Vec<u8>>,
+    /// Whether the levels below the stored ones are generated on upload.
+    pub generate_mips: bool,
+    /// Whether `Assets<Texture>` streams the stored levels in, see `TextureOptions::streamed`.
+    pub streamed: bool,
+}
+
+impl TextureData {
+    /// Decodes a PNG, JPEG or KTX2 file, told apart by their signatures.
+    pub fn decode(bytes: &[u8], options: TextureOptions) -> Result<Self, TextureError> {
+        if bytes.starts_with(&ktx2::IDENTIFIER) {
+            let image = ktx2::decode(bytes)?;
+            return Ok(TextureData {
+                format: image.format,
+                width: image.width,
+                height: image.height,
+                generate_mips: options.mipmaps && (image.generate_mips || image.levels.len() == 1),
+                streamed: options.streamed,
+                levels: image.levels,
+            });
+        }
+        let image = if bytes.starts_with(&png::SIGNATURE) {
+            png::decode(bytes)?
+        } else if bytes.starts_with(&[0xff, 0xd8]) {
+            jpeg::decode(bytes)?
+        } else {
+            return Err(TextureError::Format("not a PNG, JPEG or KTX2 file".into()));
+        };
+        Ok(TextureData::rgba8(image.width, image.height, image.pixels, options))
+    }
+
+    /// Wraps tightly packed 8-bit RGBA texels, e.g. generated ones.
+    pub fn rgba8(width: u32, height: u32, pixels: Vec<u8>, options: TextureOptions) -> Self {
+        TextureData {
+            format: if options.srgb {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            },
+            width,
+            height,
+            levels: vec![pixels],
+            generate_mips: options.mipmaps,
+            streamed: false,
+        }
+    }
+
+    /// Levels in the full mip chain of the base level.
+    fn full_mip_count(&self) -> u32 {
+        32 - self.width.max(self.height).leading_zeros()
+    }
+
+    /// Bytes of `level` when tightly packed.
+    fn level_bytes(&self, level: u32) -> usize {
+        let size = self.level_size(level);
+        let description = self.format.describe();
+        let (block_width, block_height) = description.block_dimensions;
+        size.width.div_ceil(u32::from(block_width)) as usize
+            * size.height.div_ceil(u32::from(block_height)) as usize
+            * description.block_size as usize
+    }
+
+    /// Checks the size and levels against each other, since anyone can fill them in.
+    fn validate(&self, max_dimension: u32) -> Result<(), TextureError> {
+        check_dimensions(self.width, self.height, max_dimension)?;
+        if self.levels.is_empty() || self.levels.len() > self.full_mip_count() as usize {
+            return Err(TextureError::Format(format!(
+                "{} mip levels for a {}x{} texture",
+                self.levels.len(),
+                self.width,
+                self.height
+            )));
+        }
+        for (level, texels) in self.levels.iter().enumerate() {
+            let expected = self.level_bytes(level as u32);
+            if texels.len() != expected {
+                return Err(TextureError::Format(format!(
+                    "mip level {} has {} bytes rather than {}",
+                    level,
+                    texels.len(),
+                    expected
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn level_size(&self, level: u32) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: (self.width >> level).max(1),
+            height: (self.height >> level).max(1),
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+enum Storage {
+    Whole(wgpu::Texture),
+    Streamed(StreamedTexture),
+}
+
+/// A loaded texture. The view covers every resident mip level and can be shared with
+/// materials; pass `texture` to `AppContext::register_ui_texture` to show it in egui.
+///
+/// A streamed texture gets a new texture and view whenever `Assets::stream` changes its
+/// resident levels, so whatever holds them has to fetch them again.
+pub struct Texture {
+    storage: Storage,
+    view: Arc<wgpu::TextureView>,
+    pub width: u32,
+    pub height: u32,
+    pub mip_level_count: u32,
+    pub format: wgpu::TextureFormat,
+    allocation: AllocationId,
+}
+
+impl Texture {
+    pub fn texture(&self) -> &wgpu::Texture {
+        match &self.storage {
+            Storage::Whole(texture) => texture,
+            Storage::Streamed(streamed) => streamed.texture(),
+        }
+    }
+
+    /// For `PbrMaterial::with_texture`.
+    pub fn view(&self) -> Arc<wgpu::TextureView> {
+        self.view.clone()
+    }
+
+    /// Most detailed mip level on the GPU, 0 unless streamed.
+    pub fn first_mip(&self) -> u32 {
+        match &self.storage {
+            Storage::Whole(_) => 0,
+            Storage::Streamed(streamed) => streamed.first_mip(),
+        }
+    }
+
+    /// Asks a streamed texture for the detail it needs to cover about `width` by `height`
+    /// pixels on screen. Does nothing for textures that were uploaded whole.
+    pub fn request_detail(&mut self, width: f32, height: f32) {
+        if let Storage::Streamed(streamed) = &mut self.storage {
+            let size = wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            };
+            streamed.request(desired_first_mip(size, self.mip_level_count, width, height));
+        }
+    }
+
+    /// Where `upload` tracked it; release it when dropping a texture loaded outside `Assets`.
+    pub fn allocation(&self) -> AllocationId {
+        self.allocation
+    }
+}
+
+/// What finishing a texture load needs, for `Assets<Texture>`.
+pub struct TextureContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub loader: &'a mut TextureLoader,
+    pub memory: &'a mut GpuMemory,
+}
+
+impl Asset for Texture {
+    type Settings = TextureOptions;
+    type Data = TextureData;
+    type Context<'a> = TextureContext<'a>;
+    const KIND: &'static str = "texture";
+
+    fn decode(bytes: &[u8], options: &TextureOptions) -> Result<TextureData, String> {
+        TextureData::decode(bytes, *options).map_err(|e| e.to_string())
+    }
+
+    fn finish(context: &mut TextureContext, label: &str, data: TextureData) -> Result<Self, String> {
+        if data.streamed && !data.generate_mips && data.levels.len() > 1 {
+            return Texture::streamed(context, label, data);
+        }
+        context
+            .loader
+            .upload(context.device, context.queue, context.memory, label, &data)
+            .map_err(|e| e.to_string())
+    }
+
+    fn unload(self, context: &mut TextureContext) {
+        context.memory.release(self.allocation);
+    }
+}
+
+impl Texture {
+    // the stored levels smaller than `STREAMED_RESIDENT_SIZE` now, the rest once requested
+    fn streamed(context: &mut TextureContext, label: &str, data: TextureData) -> Result<Self, String> {
+        data.validate(context.device.limits().max_texture_dimension_2d).map_err(|e| e.to_string())?;
+        let description = data.format.describe();
+        if !context.device.features().contains(description.required_features) {
+            return Err(format!("{:?} textures are not supported by this device", data.format));
+        }
+        let mip_level_count = data.levels.len() as u32;
+        let small = (0..mip_level_count)
+            .filter(|&level| {
+                let size = data.level_size(level);
+                size.width.max(size.height) <= STREAMED_RESIDENT_SIZE
+            })
+            .count() as u32;
+        // block compressed textures can only be recreated from a level of whole blocks
+        let (block_width, block_height) = description.block_dimensions;
+        let whole_blocks = (0..mip_level_count)
+            .take_while(|&level| {
+                let size = data.level_size(level);
+                size.width.is_multiple_of(u32::from(block_width)) && size.height.is_multiple_of(u32::from(block_height))
+            })
+            .count() as u32;
+        let resident_mips = small.max(mip_level_count - whole_blocks.saturating_sub(1));
+        let desc = wgpu::TextureDescriptor {
+            label: Some(label),
+            size: data.level_size(0),
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: data.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        };
+        let (width, height, format) = (data.width, data.height, data.format);
+        let source = Arc::new(MipChain(data.levels));
+        let streamed = StreamedTexture::new(context.device, context.queue, context.memory, label, &desc, resident_mips, source)?;
+        Ok(Texture {
+            view: Arc::new(streamed.texture().create_view(&wgpu::TextureViewDescriptor::default())),
+            allocation: streamed.allocation(),
+            storage: Storage::Streamed(streamed),
+            width,
+            height,
+            mip_level_count,
+            format,
+        })
+    }
+}
+
+impl Assets<Texture> {
+    /// Uploads the mips streamed in since the last call and drops the ones the memory budget
+    /// evicts, recording the copies into `encoder`. Returns true if any texture's view changed.
+    /// The engine calls it every frame for the textures it owns.
+    pub fn stream(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, memory: &mut GpuMemory) -> bool {
+        let evictions = memory.plan_evictions();
+        let mut changed = false;
+        for texture in self.loaded_mut() {
+            let Storage::Streamed(streamed) = &mut texture.storage else {
+                continue;
+            };
+            let mut reallocated = streamed.update(device, queue, encoder, memory);
+            if let Some(eviction) = evictions.iter().find(|change| change.texture == streamed.allocation()) {
+                reallocated |= streamed.evict_to(device, encoder, memory, eviction.first_mip);
+            }
+            if reallocated {
+                texture.view = Arc::new(streamed.texture().create_view(&wgpu::TextureViewDescriptor::default()));
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Uploads textures and generates their mip chains, with a pipeline per texture format that
+/// is built the first time it is needed.
+pub struct TextureLoader {
+    layout: wgpu::BindGroupLayout,
+    module: wgpu::ShaderModule,
+    pipelines: HashMap<wgpu::TextureFormat, FullscreenPipeline>,
+}
+
+impl TextureLoader {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mipmap layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                // read with textureLoad, so float formats that can't be filtered work too
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        TextureLoader {
+            layout,
+            module: fullscreen_module(device, "mipmap", include_str!("../../shaders/mipmap.wgsl")),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Loads a PNG, JPEG or KTX2 file.
+    pub fn load(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        memory: &mut GpuMemory,
+        path: impl AsRef<Path>,
+        options: TextureOptions,
+    ) -> Result<Texture, TextureError> {
+        let path = path.as_ref();
+        let mut timer = LoadTimer::for_path("texture", path);
+        let bytes = std::fs::read(path).map_err(TextureError::Io)?;
+        timer.add_file_bytes(bytes.len());
+        timer.end(LoadStage::Read);
+        let data = TextureData::decode(&bytes, options)?;
+        timer.end(LoadStage::Decode);
+        let texture = self.upload(device, queue, memory, &path.to_string_lossy(), &data)?;
+        timer.add_upload_bytes(data.levels.iter().map(Vec::len).sum());
+        timer.end(LoadStage::Upload);
+        timer.finish();
+        Ok(texture)
+    }
+
+    /// Creates a texture from `data`, writing its stored levels and rendering the rest of the
+    /// mip chain if it asks for them. Fails for block compressed formats the device can't
+    /// sample, sizes it can't create and levels that don't match the size. The texture is
+    /// tracked in `memory` under `MemoryCategory::Textures`.
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        memory: &mut GpuMemory,
+        label: &str,
+        data: &TextureData,
+    ) -> Result<Texture, TextureError> {
+        data.validate(device.limits().max_texture_dimension_2d)?;
+        let description = data.format.describe();
+        if !device.features().contains(description.required_features) {
+            return Err(TextureError::Format(format!("{:?} textures are not supported by this device", data.format)));
+        }
+        // only renderable formats can have their mips generated
+        let generate = data.generate_mips && description.block_dimensions == (1, 1);
+        let mip_level_count = if generate { data.full_mip_count() } else { data.levels.len() as u32 };
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        if generate {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
+        let desc = wgpu::TextureDescriptor {
+            label: Some(label),
+            size: data.level_size(0),
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: data.format,
+            usage,
+        };
+        let texture = device.create_texture(&desc);
+
+        let (block_width, block_height) = description.block_dimensions;
+        for (level, texels) in data.levels.iter().enumerate().take(mip_level_count as usize) {
+            let size = data.level_size(level as u32);
+            let blocks_wide = size.width.div_ceil(u32::from(block_width));
+            let blocks_high = size.height.div_ceil(u32::from(block_height));
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                texels,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(blocks_wide * u32::from(description.block_size)),
+                    rows_per_image: NonZeroU32::new(blocks_high),
+                },
+                // the physical size, whole blocks
+                wgpu::Extent3d {
+                    width: blocks_wide * u32::from(block_width),
+                    height: blocks_high * u32::from(block_height),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        if generate {
+            self.generate_mips(device, queue, &texture, data.format, data.levels.len() as u32, mip_level_count);
+        }
+
+        Ok(Texture {
+            view: Arc::new(texture.create_view(&wgpu::TextureViewDescriptor::default())),
+            storage: Storage::Whole(texture),
+            width: data.width,
+            height: data.height,
+            mip_level_count,
+            format: data.format,
+            allocation: memory.track_texture(MemoryCategory::Textures, &desc),
+        })
+    }
+
+    /// Renders levels `first..count` of `texture`, each from the one above.
+    fn generate_mips(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture, format: wgpu::TextureFormat, first: u32, count: u32) {
+        let layout = &self.layout;
+        let module = &self.module;
+        let pipeline = self
+            .pipelines
+            .entry(format)
+            .or_insert_with(|| FullscreenPipeline::new(device, "mipmap", module, "fs_main", &[layout], format, None));
+        let level_view = |level: u32| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("mip level"),
+                base_mip_level: level,
+                mip_level_count: NonZeroU32::new(1),
+                ..Default::default()
+            })
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("mipmaps") });
+        for level in first.max(1)..count {
+            let source = level_view(level - 1);
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("mipmap bind group"),
+                layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source),
+                }],
+            });
+            pipeline.draw(&mut encoder, &level_view(level), &[&bind_group], None);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+}
@@ -0,0 +1,452 @@
+use super::{check_dimensions, inflate::zlib_decompress, ImageData, TextureError, MAX_DIMENSION};
+
+pub(super) const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+fn error(message: impl Into<String>) -> TextureError {
+    TextureError::Format(format!("invalid PNG: {}", message.into()))
+}
+
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            0 | 3 => 1,
+            2 => 3,
+            4 => 2,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    fn row_bytes(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+/// Decodes a PNG of any color type and bit depth, interlaced or not, to 8-bit RGBA. 16-bit
+/// channels keep their high byte; gamma and color profile chunks are ignored.
+pub(super) fn decode(bytes: &[u8]) -> Result<ImageData, TextureError> {
+    if !bytes.starts_with(&SIGNATURE) {
+        return Err(error("missing signature"));
+    }
+    let mut header = None;
+    let mut palette: Vec<[u8; 4]> = Vec::new();
+    let mut transparent: Option<[u16; 3]> = None;
+    let mut compressed = Vec::new();
+    let mut rest = &bytes[SIGNATURE.len()..];
+    loop {
+        if rest.len() < 12 {
+            return Err(error("truncated chunk"));
+        }
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let kind = &rest[4..8];
+        let data = rest.get(8..8 + length).ok_or_else(|| error("truncated chunk"))?;
+        rest = rest.get(12 + length..).ok_or_else(|| error("truncated chunk"))?;
+        match kind {
+            b"IHDR" => {
+                if data.len() < 13 {
+                    return Err(error("short header"));
+                }
+                let parsed = Header {
+                    width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                    height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+                    bit_depth: data[8],
+                    color_type: data[9],
+                    interlaced: data[12] == 1,
+                };
+                let valid_depth = match parsed.color_type {
+                    0 => matches!(parsed.bit_depth, 1 | 2 | 4 | 8 | 16),
+                    3 => matches!(parsed.bit_depth, 1 | 2 | 4 | 8),
+                    2 | 4 | 6 => matches!(parsed.bit_depth, 8 | 16),
+                    _ => false,
+                };
+                if !valid_depth {
+                    return Err(error(format!("color type {} at bit depth {}", parsed.color_type, parsed.bit_depth)));
+                }
+                check_dimensions(parsed.width, parsed.height, MAX_DIMENSION).map_err(|e| error(e.to_string()))?;
+                header = Some(parsed);
+            }
+            b"PLTE" => {
+                palette = data.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect();
+            }
+            b"tRNS" => match header.as_ref().map(|header| header.color_type) {
+                Some(3) => {
+                    for (entry, &alpha) in palette.iter_mut().zip(data) {
+                        entry[3] = alpha;
+                    }
+                }
+                Some(0) if data.len() >= 2 => {
+                    let gray = u16::from_be_bytes([data[0], data[1]]);
+                    transparent = Some([gray; 3]);
+                }
+                Some(2) if data.len() >= 6 => {
+                    let channel = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+                    transparent = Some([channel(0), channel(2), channel(4)]);
+                }
+                _ => {}
+            },
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ if kind[0] & 0x20 == 0 => {
+                return Err(error(format!("unknown critical chunk {}", String::from_utf8_lossy(kind))));
+            }
+            _ => {}
+        }
+    }
+    let header = header.ok_or_else(|| error("missing header"))?;
+    if header.color_type == 3 && palette.is_empty() {
+        return Err(error("missing palette"));
+    }
+    // (x offset, y offset, x step, y step) of each Adam7 pass
+    const PASSES: [(u32, u32, u32, u32); 7] =
+        [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+    let pass_size = |(x0, y0, dx, dy): (u32, u32, u32, u32)| {
+        let (width, height) = ((header.width + dx - 1 - x0) / dx, (header.height + dy - 1 - y0) / dy);
+        if width == 0 || height == 0 {
+            (width, height, 0)
+        } else {
+            (width, height, (header.row_bytes(width) + 1) * height as usize)
+        }
+    };
+    // a filter byte and the packed samples of every row, of every pass when interlaced
+    let expected = if header.interlaced {
+        PASSES.iter().map(|&pass| pass_size(pass).2).sum()
+    } else {
+        (header.row_bytes(header.width) + 1) * header.height as usize
+    };
+    let filtered = zlib_decompress(&compressed, expected)?;
+    if filtered.len() < expected {
+        return Err(error("truncated image data"));
+    }
+
+    let mut image = ImageData {
+        width: header.width,
+        height: header.height,
+        pixels: vec![0; header.width as usize * header.height as usize * 4],
+    };
+    let context = Pixels {
+        header: &header,
+        palette: &palette,
+        transparent,
+    };
+    if header.interlaced {
+        let mut offset = 0;
+        for (x0, y0, dx, dy) in PASSES {
+            let (width, height, size) = pass_size((x0, y0, dx, dy));
+            if size == 0 {
+                continue;
+            }
+            let data = filtered.get(offset..offset + size).ok_or_else(|| error("truncated image data"))?;
+            offset += size;
+            let rows = unfilter(&header, width, height, data)?;
+            context.write(&mut image, &rows, width, height, |x, y| (x0 + x * dx, y0 + y * dy));
+        }
+    } else {
+        let rows = unfilter(&header, header.width, header.height, &filtered)?;
+        context.write(&mut image, &rows, header.width, header.height, |x, y| (x, y));
+    }
+    Ok(image)
+}
+
+/// Undoes the per-row filters of one (sub)image, returning its rows back to back.
+fn unfilter(header: &Header, width: u32, height: u32, data: &[u8]) -> Result<Vec<u8>, TextureError> {
+    let stride = header.row_bytes(width);
+    // filters work on whole bytes, with the byte of the previous pixel
+    let pixel_bytes = (header.bits_per_pixel() / 8).max(1);
+    if data.len() < (stride + 1) * height as usize {
+        return Err(error("truncated image data"));
+    }
+    let mut rows = vec![0u8; stride * height as usize];
+    for y in 0..height as usize {
+        let filter = data[y * (stride + 1)];
+        let source = &data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (previous, current) = rows.split_at_mut(y * stride);
+        let above = if y == 0 { None } else { Some(&previous[(y - 1) * stride..]) };
+        let row = &mut current[..stride];
+        for x in 0..stride {
+            let a = if x >= pixel_bytes { row[x - pixel_bytes] } else { 0 };
+            let b = above.map_or(0, |above| above[x]);
+            let c = if x >= pixel_bytes { above.map_or(0, |above| above[x - pixel_bytes]) } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(error(format!("unknown filter {}", filter))),
+            };
+            row[x] = source[x].wrapping_add(predicted);
+        }
+    }
+    Ok(rows)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = ((p - i16::from(a)).abs(), (p - i16::from(b)).abs(), (p - i16::from(c)).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+struct Pixels<'a> {
+    header: &'a Header,
+    palette: &'a [[u8; 4]],
+    transparent: Option<[u16; 3]>,
+}
+
+impl Pixels<'_> {
+    /// Converts unfiltered rows to RGBA, placing pixel `(x, y)` at `position(x, y)`.
+    fn write(&self, image: &mut ImageData, rows: &[u8], width: u32, height: u32, position: impl Fn(u32, u32) -> (u32, u32)) {
+        let header = self.header;
+        let stride = header.row_bytes(width);
+        let depth = header.bit_depth as usize;
+        let max = (1u32 << depth) - 1;
+        for y in 0..height {
+            let row = &rows[y as usize * stride..][..stride];
+            // the sample at `index` within the row, at full precision
+            let sample = |index: usize| -> u16 {
+                match depth {
+                    16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+                    8 => u16::from(row[index]),
+                    _ => {
+                        let bit = index * depth;
+                        u16::from((row[bit / 8] >> (8 - depth - bit % 8)) & max as u8)
+                    }
+                }
+            };
+            let to_8 = |value: u16| -> u8 {
+                match depth {
+                    16 => (value >> 8) as u8,
+                    _ => (u32::from(value) * 255 / max) as u8,
+                }
+            };
+            for x in 0..width {
+                let i = x as usize;
+                let rgba = match header.color_type {
+                    0 => {
+                        let gray = sample(i);
+                        let alpha = if self.transparent == Some([gray; 3]) { 0 } else { 255 };
+                        let gray = to_8(gray);
+                        [gray, gray, gray, alpha]
+                    }
+                    2 => {
+                        let rgb = [sample(i * 3), sample(i * 3 + 1), sample(i * 3 + 2)];
+                        let alpha = if self.transparent == Some(rgb) { 0 } else { 255 };
+                        [to_8(rgb[0]), to_8(rgb[1]), to_8(rgb[2]), alpha]
+                    }
+                    3 => self.palette.get(sample(i) as usize).copied().unwrap_or([0, 0, 0, 255]),
+                    4 => {
+                        let gray = to_8(sample(i * 2));
+                        [gray, gray, gray, to_8(sample(i * 2 + 1))]
+                    }
+                    _ => [to_8(sample(i * 4)), to_8(sample(i * 4 + 1)), to_8(sample(i * 4 + 2)), to_8(sample(i * 4 + 3))],
+                };
+                let (px, py) = position(x, y);
+                let offset = (py as usize * image.width as usize + px as usize) * 4;
+                image.pixels[offset..offset + 4].copy_from_slice(&rgba);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::texture::inflate::tests::zlib_stored;
+
+    fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        // CRCs aren't verified
+        out.extend_from_slice(&[0; 4]);
+    }
+
+    /// A PNG of already filtered rows, with `extra` chunks before the image data.
+    fn encode(width: u32, height: u32, bit_depth: u8, color_type: u8, interlaced: bool, extra: &[(&[u8; 4], Vec<u8>)], filtered: &[u8]) -> Vec<u8> {
+        let mut out = SIGNATURE.to_vec();
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, u8::from(interlaced)]);
+        chunk(&mut out, b"IHDR", &header);
+        for (kind, data) in extra {
+            chunk(&mut out, kind, data);
+        }
+        chunk(&mut out, b"IDAT", &zlib_stored(filtered));
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    /// Filters packed rows, row `y` with `filter(y)`, the inverse of `unfilter`.
+    fn filter(rows: &[u8], stride: usize, pixel_bytes: usize, filter: impl Fn(usize) -> u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (y, row) in rows.chunks(stride).enumerate() {
+            let kind = filter(y);
+            out.push(kind);
+            for x in 0..stride {
+                let a = if x >= pixel_bytes { row[x - pixel_bytes] } else { 0 };
+                let b = if y > 0 { rows[(y - 1) * stride + x] } else { 0 };
+                let c = if y > 0 && x >= pixel_bytes { rows[(y - 1) * stride + x - pixel_bytes] } else { 0 };
+                let predicted = match kind {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                out.push(row[x].wrapping_sub(predicted));
+            }
+        }
+        out
+    }
+
+    fn pattern(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height * 4).map(|i| (i * 37 % 256) as u8 ^ (i / 7) as u8).collect()
+    }
+
+    fn rgba_file() -> (Vec<u8>, Vec<u8>) {
+        let pixels = pattern(7, 5);
+        let filtered = filter(&pixels, 28, 4, |y| (y % 5) as u8);
+        (encode(7, 5, 8, 6, false, &[], &filtered), pixels)
+    }
+
+    #[test]
+    fn rgba8_with_every_filter() {
+        let (file, pixels) = rgba_file();
+        let image = decode(&file).unwrap();
+        assert_eq!((image.width, image.height), (7, 5));
+        assert_eq!(image.pixels, pixels);
+    }
+
+    #[test]
+    fn one_bit_gray() {
+        // 10 pixels a row: two bytes, the last 6 bits padding
+        let rows = [0b1010_0000, 0b1100_0000, 0b0101_1111, 0b0011_1111];
+        let filtered = filter(&rows, 2, 1, |_| 1);
+        let image = decode(&encode(10, 2, 1, 0, false, &[], &filtered)).unwrap();
+        let gray: Vec<u8> = image.pixels.chunks(4).map(|pixel| pixel[0]).collect();
+        let expected: Vec<u8> = [1, 0, 1, 0, 0, 0, 0, 0, 1, 1, 0, 1, 0, 1, 1, 1, 1, 1, 0, 0]
+            .iter()
+            .map(|&bit| bit * 255)
+            .collect();
+        assert_eq!(gray, expected);
+        assert!(image.pixels.chunks(4).all(|pixel| pixel[3] == 255));
+    }
+
+    #[test]
+    fn palette_with_transparency() {
+        let palette = vec![255, 0, 0, 0, 255, 0, 0, 0, 255, 10, 20, 30];
+        // alpha for the first two entries only
+        let transparency = vec![0, 128];
+        // 2 bits per pixel, indices 0 1 2 3 / 3 2 1 0 and a padded fifth pixel
+        let rows = [0b0001_1011, 0b0000_0000, 0b1110_0100, 0b1100_0000];
+        let filtered = filter(&rows, 2, 1, |_| 0);
+        let file = encode(5, 2, 2, 3, false, &[(b"PLTE", palette), (b"tRNS", transparency)], &filtered);
+        let image = decode(&file).unwrap();
+        let pixel = |i: usize| &image.pixels[i * 4..i * 4 + 4];
+        assert_eq!(pixel(0), [255, 0, 0, 0]);
+        assert_eq!(pixel(1), [0, 255, 0, 128]);
+        assert_eq!(pixel(2), [0, 0, 255, 255]);
+        assert_eq!(pixel(3), [10, 20, 30, 255]);
+        assert_eq!(pixel(4), [255, 0, 0, 0]);
+        assert_eq!(pixel(5), [10, 20, 30, 255]);
+        assert_eq!(pixel(9), [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn sixteen_bit_rgb_keeps_the_high_byte() {
+        let samples: [u16; 6] = [0x1234, 0xabcd, 0x00ff, 0xff00, 0x8000, 0x7fff];
+        let rows: Vec<u8> = samples.iter().flat_map(|sample| sample.to_be_bytes()).collect();
+        let filtered = filter(&rows, 12, 6, |_| 4);
+        // the second pixel is the transparent color
+        let transparent: Vec<u8> = samples[3..].iter().flat_map(|sample| sample.to_be_bytes()).collect();
+        let image = decode(&encode(2, 1, 16, 2, false, &[(b"tRNS", transparent)], &filtered)).unwrap();
+        assert_eq!(image.pixels, [0x12, 0xab, 0x00, 255, 0xff, 0x80, 0x7f, 0]);
+    }
+
+    #[test]
+    fn interlaced_matches_the_plain_image() {
+        let (width, height) = (13, 11);
+        let pixels = pattern(width, height);
+        let passes = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+        let mut filtered = Vec::new();
+        for (x0, y0, dx, dy) in passes {
+            let mut rows = Vec::new();
+            for y in (y0..height).step_by(dy) {
+                for x in (x0..width).step_by(dx) {
+                    let offset = ((y * width + x) * 4) as usize;
+                    rows.extend_from_slice(&pixels[offset..offset + 4]);
+                }
+            }
+            let pass_width = (x0..width).step_by(dx).count();
+            if !rows.is_empty() {
+                filtered.extend(filter(&rows, pass_width * 4, 4, |y| (y % 5) as u8));
+            }
+        }
+        let image = decode(&encode(width, height, 8, 6, true, &[], &filtered)).unwrap();
+        assert_eq!(image.pixels, pixels);
+    }
+
+    #[test]
+    fn oversized_dimensions_are_rejected_before_allocating() {
+        let file = encode(100_000, 100_000, 8, 6, false, &[], &[]);
+        let Err(TextureError::Format(message)) = decode(&file) else {
+            panic!("a 100000x100000 image decoded");
+        };
+        assert!(message.contains("larger"), "{}", message);
+    }
+
+    #[test]
+    fn short_image_data_is_rejected() {
+        let pixels = pattern(7, 5);
+        let filtered = filter(&pixels[..28 * 4], 28, 4, |_| 0);
+        assert!(decode(&encode(7, 5, 8, 6, false, &[], &filtered)).is_err());
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected() {
+        // 16-bit palettes and 4-bit RGB don't exist
+        assert!(decode(&encode(1, 1, 16, 3, false, &[], &[0, 0, 0])).is_err());
+        assert!(decode(&encode(1, 1, 4, 2, false, &[], &[0, 0])).is_err());
+        assert!(decode(&encode(0, 1, 8, 6, false, &[], &[0])).is_err());
+        // a palette image without a palette
+        assert!(decode(&encode(1, 1, 8, 3, false, &[], &[0, 0])).is_err());
+        assert!(decode(b"\x89PNG\r\n\x1a\n").is_err());
+    }
+
+    #[test]
+    fn truncated_files_fail() {
+        let (file, _) = rgba_file();
+        for length in 0..file.len() - 12 {
+            assert!(decode(&file[..length]).is_err(), "{} bytes decoded", length);
+        }
+    }
+
+    #[test]
+    fn corrupt_files_never_panic() {
+        let (file, _) = rgba_file();
+        for index in SIGNATURE.len()..file.len() {
+            for flip in [0x01, 0x80, 0xff] {
+                let mut corrupt = file.clone();
+                corrupt[index] ^= flip;
+                decode(&corrupt).ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::{fmt, ops::Range};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PipelineId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialId(pub u32);
+
+/// A vertex and index buffer pair. Skinned meshes get one per skinned instance, since each
+/// writes its own skinning output, so they only batch with themselves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshId(pub u32);
+
+/// One object to draw. `instance` is the caller's index for its per-object data, e.g. a slot
+/// in the transform buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawItem {
+    pub pipeline: PipelineId,
+    pub material: MaterialId,
+    pub mesh: MeshId,
+    pub instance: u32,
+}
+
+/// What has to be rebound before drawing a batch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StateChanges {
+    pub pipeline: bool,
+    pub material: bool,
+    pub mesh: bool,
+}
+
+/// Consecutive draws sharing pipeline, material and mesh, drawn as one instanced call.
+/// `instances` indexes `DrawQueue::instances`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DrawBatch {
+    pub pipeline: PipelineId,
+    pub material: MaterialId,
+    pub mesh: MeshId,
+    pub instances: Range<u32>,
+    pub changes: StateChanges,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    pub draws: usize,
+    pub batches: usize,
+    pub pipeline_changes: usize,
+    pub material_changes: usize,
+    pub mesh_changes: usize,
+}
+
+impl BatchStats {
+    pub fn state_changes(&self) -> usize {
+        self.pipeline_changes + self.material_changes + self.mesh_changes
+    }
+}
+
+impl fmt::Display for BatchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} draws in {} batches, {} state changes ({} pipeline, {} material, {} mesh)",
+            self.draws,
+            self.batches,
+            self.state_changes(),
+            self.pipeline_changes,
+            self.material_changes,
+            self.mesh_changes
+        )
+    }
+}
+
+/// Collects a frame's opaque draws and sorts them by pipeline, then material, then mesh, so
+/// every pipeline is bound once and materials and meshes change as rarely as possible.
+/// Transparent draws need back to front order instead and don't belong in here.
+#[derive(Default)]
+pub struct DrawQueue {
+    items: Vec<DrawItem>,
+    instances: Vec<u32>,
+    batches: Vec<DrawBatch>,
+    stats: BatchStats,
+}
+
+impl DrawQueue {
+    pub fn new() -> Self {
+        DrawQueue::default()
+    }
+
+    pub fn push(&mut self, item: DrawItem) {
+        self.items.push(item);
+    }
+
+    /// Sorts and groups everything pushed since the last call, then empties the queue for the
+    /// next frame. The batches and instance order stay available until then.
+    pub fn build(&mut self) -> &[DrawBatch] {
+        self.items.sort_unstable_by_key(|item| (item.pipeline, item.material, item.mesh, item.instance));
+        self.instances.clear();
+        self.batches.clear();
+
+        let mut previous: Option<DrawItem> = None;
+        for item in self.items.drain(..) {
+            let same_batch = previous
+                .is_some_and(|p| p.pipeline == item.pipeline && p.material == item.material && p.mesh == item.mesh);
+            if same_batch {
+                self.batches.last_mut().unwrap().instances.end += 1;
+            } else {
+                let pipeline = previous.is_none_or(|p| p.pipeline != item.pipeline);
+                let changes = StateChanges {
+                    pipeline,
+                    // another pipeline's layout may not be compatible with the bound material
+                    material: pipeline || previous.is_some_and(|p| p.material != item.material),
+                    mesh: previous.is_none_or(|p| p.mesh != item.mesh),
+                };
+                let start = self.instances.len() as u32;
+                self.batches.push(DrawBatch {
+                    pipeline: item.pipeline,
+                    material: item.material,
+                    mesh: item.mesh,
+                    instances: start..start + 1,
+                    changes,
+                });
+            }
+            self.instances.push(item.instance);
+            previous = Some(item);
+        }
+
+        self.stats = BatchStats {
+            draws: self.instances.len(),
+            batches: self.batches.len(),
+            pipeline_changes: self.batches.iter().filter(|b| b.changes.pipeline).count(),
+            material_changes: self.batches.iter().filter(|b| b.changes.material).count(),
+            mesh_changes: self.batches.iter().filter(|b| b.changes.mesh).count(),
+        };
+        &self.batches
+    }
+
+    pub fn batches(&self) -> &[DrawBatch] {
+        &self.batches
+    }
+
+    /// Per-object indices in batch order, to upload as the instance buffer so each batch's
+    /// instances are contiguous.
+    pub fn instances(&self) -> &[u32] {
+        &self.instances
+    }
+
+    /// Counts from the last `build`.
+    pub fn stats(&self) -> BatchStats {
+        self.stats
+    }
+}
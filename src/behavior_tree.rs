@@ -0,0 +1,543 @@
+use std::{fmt, path::Path};
+
+use crate::load_profile::{LoadStage, LoadTimer};
+
+#[derive(Debug)]
+pub enum BehaviorTreeError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    /// The tree refers to a leaf action nothing registered.
+    UnknownAction(String),
+}
+
+impl fmt::Display for BehaviorTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BehaviorTreeError::Io(e) => write!(f, "{}", e),
+            BehaviorTreeError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            BehaviorTreeError::UnknownAction(name) => write!(f, "no action \"{}\" is registered", name),
+        }
+    }
+}
+
+impl std::error::Error for BehaviorTreeError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Success,
+    Failure,
+    /// Not done yet; the node is ticked again next time.
+    Running,
+}
+
+/// A behavior tree as authored.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    /// Runs its children in order, failing as soon as one fails.
+    Sequence(Vec<Node>),
+    /// Tries its children in order, succeeding as soon as one succeeds. Like a sequence, it
+    /// resumes a running child on the next tick instead of checking the ones before it again.
+    Selector(Vec<Node>),
+    /// Runs all its children at once: fails when any fails, succeeds once all have.
+    Parallel(Vec<Node>),
+    Inverter(Box<Node>),
+    /// Succeeds once its child finishes, whether it succeeded or not.
+    Succeed(Box<Node>),
+    /// Runs its child this many times in a row, one run per tick, or until it fails; zero
+    /// repeats forever.
+    Repeat(u32, Box<Node>),
+    /// Keeps running for this many seconds, then succeeds.
+    Wait(f32),
+    /// A leaf registered from Rust with `BehaviorActions::register`. Conditions are actions
+    /// that never return `Running`.
+    Action(String),
+}
+
+impl Node {
+    /// The tree as a single RON enum value, e.g.
+    /// `Selector([Sequence([Action("see_enemy"), Action("chase")]), Repeat(0, Action("patrol"))])`.
+    /// Trailing commas and `//` comments are allowed.
+    pub fn parse(text: &str) -> Result<Self, BehaviorTreeError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let node = parser.node()?;
+        if let Some((line, token)) = parser.tokens.get(parser.position) {
+            return Err(BehaviorTreeError::Parse {
+                line: *line,
+                message: format!("unexpected {} after the tree", token),
+            });
+        }
+        Ok(node)
+    }
+
+    /// RON text that `parse` reads back, one node per line.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        self.write(&mut text, 0);
+        text.push('\n');
+        text
+    }
+
+    fn write(&self, text: &mut String, depth: usize) {
+        let list = |text: &mut String, name: &str, children: &[Node]| {
+            text.push_str(name);
+            text.push_str("([\n");
+            for child in children {
+                text.push_str(&"    ".repeat(depth + 1));
+                child.write(text, depth + 1);
+                text.push_str(",\n");
+            }
+            text.push_str(&"    ".repeat(depth));
+            text.push_str("])");
+        };
+        match self {
+            Node::Sequence(children) => list(text, "Sequence", children),
+            Node::Selector(children) => list(text, "Selector", children),
+            Node::Parallel(children) => list(text, "Parallel", children),
+            Node::Inverter(child) | Node::Succeed(child) => {
+                text.push_str(if matches!(self, Node::Inverter(_)) { "Inverter(" } else { "Succeed(" });
+                child.write(text, depth);
+                text.push(')');
+            }
+            Node::Repeat(times, child) => {
+                text.push_str(&format!("Repeat({}, ", times));
+                child.write(text, depth);
+                text.push(')');
+            }
+            Node::Wait(seconds) => text.push_str(&format!("Wait({:?})", seconds)),
+            Node::Action(name) => text.push_str(&format!("Action({:?})", name)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(String),
+    Punct(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(ident) => write!(f, "'{}'", ident),
+            Token::Str(string) => write!(f, "{:?}", string),
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Punct(punct) => write!(f, "'{}'", punct),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, BehaviorTreeError> {
+    let mut tokens = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| BehaviorTreeError::Parse { line: index + 1, message };
+        let mut chars = line.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            match c {
+                c if c.is_whitespace() => {}
+                '/' if line[start..].starts_with("//") => break,
+                '(' | ')' | '[' | ']' | ',' => tokens.push((index + 1, Token::Punct(c))),
+                '"' => {
+                    let mut string = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, '\\')) => match chars.next() {
+                                Some((_, escaped @ ('"' | '\\'))) => string.push(escaped),
+                                Some((_, 'n')) => string.push('\n'),
+                                _ => return Err(error("unsupported escape in string".into())),
+                            },
+                            Some((_, c)) => string.push(c),
+                            None => return Err(error("unterminated string".into())),
+                        }
+                    }
+                    tokens.push((index + 1, Token::Str(string)));
+                }
+                _ => {
+                    let mut end = start + c.len_utf8();
+                    while let Some(&(next, c)) = chars.peek() {
+                        if !(c.is_alphanumeric() || c == '_' || c == '.' || c == '-') {
+                            break;
+                        }
+                        end = next + c.len_utf8();
+                        chars.next();
+                    }
+                    let word = &line[start..end];
+                    if c.is_alphabetic() || c == '_' {
+                        tokens.push((index + 1, Token::Ident(word.to_owned())));
+                    } else if c.is_ascii_digit() || c == '-' || c == '.' {
+                        tokens.push((index + 1, Token::Number(word.to_owned())));
+                    } else {
+                        return Err(error(format!("unexpected '{}'", c)));
+                    }
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+}
+
+impl Parser {
+    fn error(&self, message: String) -> BehaviorTreeError {
+        let line = self
+            .tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |(line, _)| *line);
+        BehaviorTreeError::Parse { line, message }
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, BehaviorTreeError> {
+        match self.tokens.get(self.position) {
+            Some((_, token)) => {
+                self.position += 1;
+                Ok(token.clone())
+            }
+            None => Err(self.error(format!("expected {}, found the end of the file", expected))),
+        }
+    }
+
+    fn punct(&mut self, punct: char) -> Result<(), BehaviorTreeError> {
+        match self.next(&format!("'{}'", punct))? {
+            Token::Punct(found) if found == punct => Ok(()),
+            token => {
+                self.position -= 1;
+                Err(self.error(format!("expected '{}', found {}", punct, token)))
+            }
+        }
+    }
+
+    fn peek_punct(&self, punct: char) -> bool {
+        matches!(self.tokens.get(self.position), Some((_, Token::Punct(found))) if *found == punct)
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, BehaviorTreeError> {
+        match self.next("a number")? {
+            Token::Number(number) => number.parse().map_err(|_| {
+                self.position -= 1;
+                self.error(format!("'{}' is not a valid number here", number))
+            }),
+            token => {
+                self.position -= 1;
+                Err(self.error(format!("expected a number, found {}", token)))
+            }
+        }
+    }
+
+    fn node(&mut self) -> Result<Node, BehaviorTreeError> {
+        let name = match self.next("a node")? {
+            Token::Ident(name) => name,
+            token => {
+                self.position -= 1;
+                return Err(self.error(format!("expected a node, found {}", token)));
+            }
+        };
+        self.punct('(')?;
+        let node = match name.as_str() {
+            "Sequence" => Node::Sequence(self.list()?),
+            "Selector" => Node::Selector(self.list()?),
+            "Parallel" => Node::Parallel(self.list()?),
+            "Inverter" => Node::Inverter(Box::new(self.node()?)),
+            "Succeed" => Node::Succeed(Box::new(self.node()?)),
+            "Repeat" => {
+                let times = self.number()?;
+                self.punct(',')?;
+                Node::Repeat(times, Box::new(self.node()?))
+            }
+            "Wait" => Node::Wait(self.number()?),
+            "Action" => match self.next("an action name")? {
+                Token::Str(name) => Node::Action(name),
+                token => {
+                    self.position -= 1;
+                    return Err(self.error(format!("expected an action name in quotes, found {}", token)));
+                }
+            },
+            _ => {
+                self.position -= 2;
+                return Err(self.error(format!("unknown node '{}'", name)));
+            }
+        };
+        if self.peek_punct(',') {
+            self.position += 1;
+        }
+        self.punct(')')?;
+        Ok(node)
+    }
+
+    fn list(&mut self) -> Result<Vec<Node>, BehaviorTreeError> {
+        self.punct('[')?;
+        let mut nodes = Vec::new();
+        while !self.peek_punct(']') {
+            nodes.push(self.node()?);
+            if !self.peek_punct(']') {
+                self.punct(',')?;
+            }
+        }
+        self.punct(']')?;
+        Ok(nodes)
+    }
+}
+
+type ActionFn<C> = Box<dyn Fn(&mut C, f32) -> Status>;
+
+/// The leaf actions trees can use, by name. `C` is whatever an action needs to see and change,
+/// e.g. the agent and the world around it.
+pub struct BehaviorActions<C> {
+    names: Vec<String>,
+    actions: Vec<ActionFn<C>>,
+}
+
+impl<C> Default for BehaviorActions<C> {
+    fn default() -> Self {
+        BehaviorActions {
+            names: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+}
+
+impl<C> BehaviorActions<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers an action, replacing an earlier one with the same name. It gets the tick's
+    /// `dt` and returns `Running` until it is done.
+    pub fn register(&mut self, name: impl Into<String>, action: impl Fn(&mut C, f32) -> Status + 'static) {
+        let name = name.into();
+        match self.names.iter().position(|registered| *registered == name) {
+            Some(index) => self.actions[index] = Box::new(action),
+            None => {
+                self.names.push(name);
+                self.actions.push(Box::new(action));
+            }
+        }
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|registered| registered == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Compiled {
+    Sequence(Vec<usize>),
+    Selector(Vec<usize>),
+    Parallel(Vec<usize>),
+    Inverter(usize),
+    Succeed(usize),
+    Repeat(u32, usize),
+    Wait(f32),
+    Action(usize),
+}
+
+/// A tree ready to run, with its actions looked up. The tree itself holds no progress, so one
+/// can drive any number of agents, each with its own `BehaviorState`.
+#[derive(Clone, Debug)]
+pub struct BehaviorTree {
+    nodes: Vec<Compiled>,
+    /// The last node of each node's subtree, since children are numbered after their parent.
+    ends: Vec<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct NodeState {
+    /// The running child of a sequence or selector, or a repeat's count.
+    cursor: u32,
+    elapsed: f32,
+    /// A parallel's child that already succeeded.
+    done: bool,
+}
+
+/// Where one agent is in a `BehaviorTree`.
+#[derive(Clone, Debug)]
+pub struct BehaviorState {
+    nodes: Vec<NodeState>,
+}
+
+impl BehaviorState {
+    /// Forgets all progress, so the next tick starts from the root.
+    pub fn reset(&mut self) {
+        self.nodes.fill(NodeState::default());
+    }
+}
+
+impl BehaviorTree {
+    pub fn new<C>(root: &Node, actions: &BehaviorActions<C>) -> Result<Self, BehaviorTreeError> {
+        let mut tree = BehaviorTree {
+            nodes: Vec::new(),
+            ends: Vec::new(),
+        };
+        tree.compile(root, actions)?;
+        Ok(tree)
+    }
+
+    pub fn parse<C>(text: &str, actions: &BehaviorActions<C>) -> Result<Self, BehaviorTreeError> {
+        Self::new(&Node::parse(text)?, actions)
+    }
+
+    pub fn load<C>(path: impl AsRef<Path>, actions: &BehaviorActions<C>) -> Result<Self, BehaviorTreeError> {
+        let path = path.as_ref();
+        let mut timer = LoadTimer::for_path("behavior tree", path);
+        let text = std::fs::read_to_string(path).map_err(BehaviorTreeError::Io)?;
+        timer.add_file_bytes(text.len());
+        timer.end(LoadStage::Read);
+        let tree = Self::parse(&text, actions)?;
+        timer.end(LoadStage::Decode);
+        timer.finish();
+        Ok(tree)
+    }
+
+    fn compile<C>(&mut self, node: &Node, actions: &BehaviorActions<C>) -> Result<usize, BehaviorTreeError> {
+        let index = self.nodes.len();
+        self.nodes.push(Compiled::Wait(0.0));
+        self.ends.push(index);
+        let compiled = match node {
+            Node::Sequence(children) | Node::Selector(children) | Node::Parallel(children) => {
+                let children = children
+                    .iter()
+                    .map(|child| self.compile(child, actions))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match node {
+                    Node::Sequence(_) => Compiled::Sequence(children),
+                    Node::Selector(_) => Compiled::Selector(children),
+                    _ => Compiled::Parallel(children),
+                }
+            }
+            Node::Inverter(child) => Compiled::Inverter(self.compile(child, actions)?),
+            Node::Succeed(child) => Compiled::Succeed(self.compile(child, actions)?),
+            Node::Repeat(times, child) => Compiled::Repeat(*times, self.compile(child, actions)?),
+            Node::Wait(seconds) => Compiled::Wait(*seconds),
+            Node::Action(name) => {
+                Compiled::Action(actions.index(name).ok_or_else(|| BehaviorTreeError::UnknownAction(name.clone()))?)
+            }
+        };
+        self.nodes[index] = compiled;
+        self.ends[index] = self.nodes.len() - 1;
+        Ok(index)
+    }
+
+    /// A fresh state for an agent running this tree.
+    pub fn state(&self) -> BehaviorState {
+        BehaviorState {
+            nodes: vec![NodeState::default(); self.nodes.len()],
+        }
+    }
+
+    /// Advances `state` by one tick of `dt` seconds. Call from the fixed update so the tree
+    /// runs at the same rate whatever the frame rate. `actions` has to be the registry the tree
+    /// was built with.
+    pub fn tick<C>(&self, state: &mut BehaviorState, actions: &BehaviorActions<C>, context: &mut C, dt: f32) -> Status {
+        let status = self.tick_node(0, state, actions, context, dt);
+        if status != Status::Running {
+            state.reset();
+        }
+        status
+    }
+
+    fn reset_subtree(&self, node: usize, state: &mut BehaviorState) {
+        state.nodes[node..=self.ends[node]].fill(NodeState::default());
+    }
+
+    fn tick_node<C>(
+        &self,
+        node: usize,
+        state: &mut BehaviorState,
+        actions: &BehaviorActions<C>,
+        context: &mut C,
+        dt: f32,
+    ) -> Status {
+        match &self.nodes[node] {
+            Compiled::Sequence(children) | Compiled::Selector(children) => {
+                // a sequence moves on while children succeed, a selector while they fail
+                let proceed = if matches!(self.nodes[node], Compiled::Sequence(_)) {
+                    Status::Success
+                } else {
+                    Status::Failure
+                };
+                let mut cursor = state.nodes[node].cursor as usize;
+                while let Some(&child) = children.get(cursor) {
+                    match self.tick_node(child, state, actions, context, dt) {
+                        Status::Running => {
+                            state.nodes[node].cursor = cursor as u32;
+                            return Status::Running;
+                        }
+                        status if status == proceed => cursor += 1,
+                        status => {
+                            state.nodes[node].cursor = 0;
+                            return status;
+                        }
+                    }
+                }
+                state.nodes[node].cursor = 0;
+                proceed
+            }
+            Compiled::Parallel(children) => {
+                let mut running = false;
+                for &child in children {
+                    if state.nodes[child].done {
+                        continue;
+                    }
+                    match self.tick_node(child, state, actions, context, dt) {
+                        Status::Success => state.nodes[child].done = true,
+                        Status::Running => running = true,
+                        Status::Failure => {
+                            // stop the children still running, so a later run starts them over
+                            self.reset_subtree(node, state);
+                            return Status::Failure;
+                        }
+                    }
+                }
+                if running {
+                    return Status::Running;
+                }
+                self.reset_subtree(node, state);
+                Status::Success
+            }
+            Compiled::Inverter(child) => match self.tick_node(*child, state, actions, context, dt) {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            },
+            Compiled::Succeed(child) => match self.tick_node(*child, state, actions, context, dt) {
+                Status::Running => Status::Running,
+                _ => Status::Success,
+            },
+            Compiled::Repeat(times, child) => match self.tick_node(*child, state, actions, context, dt) {
+                Status::Running => Status::Running,
+                Status::Failure => {
+                    state.nodes[node].cursor = 0;
+                    Status::Failure
+                }
+                Status::Success => {
+                    state.nodes[node].cursor += 1;
+                    if *times != 0 && state.nodes[node].cursor >= *times {
+                        state.nodes[node].cursor = 0;
+                        Status::Success
+                    } else {
+                        Status::Running
+                    }
+                }
+            },
+            Compiled::Wait(seconds) => {
+                state.nodes[node].elapsed += dt;
+                if state.nodes[node].elapsed >= *seconds {
+                    state.nodes[node].elapsed = 0.0;
+                    Status::Success
+                } else {
+                    Status::Running
+                }
+            }
+            Compiled::Action(action) => (actions.actions[*action])(context, dt),
+        }
+    }
+}
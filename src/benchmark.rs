@@ -0,0 +1,237 @@
+use std::{
+    fmt::Write as _,
+    path::{Path as FsPath, PathBuf},
+};
+
+use glam::Vec3;
+
+use crate::{
+    camera::Camera,
+    render_stats::RenderStats,
+    spline::{CatmullRom, Path},
+};
+
+/// Settings for a benchmark run, from the command line:
+///
+/// `--benchmark [--scene FILE] [--frames N] [--warmup N] [--camera-path FILE] [--output FILE] [--headless]`
+///
+/// The camera path file holds one `x y z` control point per line; the camera loops through
+/// them on a closed spline. Results go to CSV unless the output file ends in `.json`.
+#[derive(Clone, Debug)]
+pub struct BenchmarkOptions {
+    /// Passed to `App::load_scene` before the first frame; the engine itself has no scene
+    /// format.
+    pub scene: Option<PathBuf>,
+    pub frames: u32,
+    /// Frames rendered before measuring starts, so shader compiles and uploads don't skew it.
+    pub warmup: u32,
+    pub camera_path: Vec<Vec3>,
+    pub output: PathBuf,
+    /// Keep the window hidden.
+    pub headless: bool,
+}
+
+impl Default for BenchmarkOptions {
+    fn default() -> Self {
+        // a slow orbit around the origin
+        let camera_path = (0..8)
+            .map(|i| {
+                let angle = i as f32 / 8.0 * std::f32::consts::TAU;
+                Vec3::new(angle.cos() * 10.0, 3.0, angle.sin() * 10.0)
+            })
+            .collect();
+        BenchmarkOptions {
+            scene: None,
+            frames: 1000,
+            warmup: 60,
+            camera_path,
+            output: PathBuf::from("benchmark.csv"),
+            headless: false,
+        }
+    }
+}
+
+impl BenchmarkOptions {
+    /// `Ok(None)` when `--benchmark` isn't among `args`.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = BenchmarkOptions::default();
+        let mut enabled = false;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+            match arg.as_str() {
+                "--benchmark" => enabled = true,
+                "--headless" => options.headless = true,
+                "--scene" => options.scene = Some(PathBuf::from(value("--scene")?)),
+                "--output" => options.output = PathBuf::from(value("--output")?),
+                "--frames" => options.frames = parse_count("--frames", &value("--frames")?)?,
+                "--warmup" => options.warmup = parse_count("--warmup", &value("--warmup")?)?,
+                "--camera-path" => {
+                    let path = value("--camera-path")?;
+                    options.camera_path = load_camera_path(FsPath::new(&path))?;
+                }
+                _ => {}
+            }
+        }
+        Ok(enabled.then_some(options))
+    }
+}
+
+fn parse_count(name: &str, value: &str) -> Result<u32, String> {
+    value.parse().map_err(|_| format!("{} expects a whole number, found '{}'", name, value))
+}
+
+fn load_camera_path(path: &FsPath) -> Result<Vec<Vec3>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read camera path {}: {}", path.display(), e))?;
+    let mut points = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values: Result<Vec<f32>, _> = line.split_whitespace().map(str::parse).collect();
+        match values.as_deref() {
+            Ok([x, y, z]) => points.push(Vec3::new(*x, *y, *z)),
+            _ => return Err(format!("{} line {}: expected 'x y z'", path.display(), index + 1)),
+        }
+    }
+    if points.len() < 2 {
+        return Err(format!("{}: a camera path needs at least two points", path.display()));
+    }
+    Ok(points)
+}
+
+/// Frame time statistics in milliseconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    pub frames: usize,
+    pub mean: f32,
+    pub min: f32,
+    pub max: f32,
+    pub median: f32,
+    pub p95: f32,
+    pub p99: f32,
+    pub std_dev: f32,
+}
+
+impl FrameStats {
+    pub fn from_times(times_ms: &[f32]) -> Self {
+        if times_ms.is_empty() {
+            return FrameStats::default();
+        }
+        let mut sorted = times_ms.to_vec();
+        sorted.sort_by(f32::total_cmp);
+        let percentile = |p: f32| sorted[((sorted.len() - 1) as f32 * p).round() as usize];
+        let mean = sorted.iter().sum::<f32>() / sorted.len() as f32;
+        let variance = sorted.iter().map(|t| (t - mean) * (t - mean)).sum::<f32>() / sorted.len() as f32;
+        FrameStats {
+            frames: sorted.len(),
+            mean,
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            median: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            std_dev: variance.sqrt(),
+        }
+    }
+}
+
+/// Drives a benchmark run: where the camera is each frame, and the frame times measured.
+pub struct Benchmark {
+    pub options: BenchmarkOptions,
+    path: Path,
+    frame: u32,
+    times_ms: Vec<f32>,
+    render_stats: Vec<RenderStats>,
+}
+
+impl Benchmark {
+    pub fn new(options: BenchmarkOptions) -> Self {
+        let path = Path::new(CatmullRom::closed(options.camera_path.clone()));
+        Benchmark {
+            times_ms: Vec::with_capacity(options.frames as usize),
+            render_stats: Vec::with_capacity(options.frames as usize),
+            options,
+            path,
+            frame: 0,
+        }
+    }
+
+    /// The camera for the current frame, moving along the path at constant speed and looking
+    /// where it's going. The path runs once over the measured frames.
+    pub fn camera(&self, base: &Camera) -> Camera {
+        let measured = self.frame.saturating_sub(self.options.warmup);
+        let distance = self.path.length() * measured as f32 / self.options.frames.max(1) as f32;
+        let mut camera = base.clone();
+        camera.position = self.path.position_at_distance(distance);
+        let forward = self.path.tangent_at_distance(distance);
+        if forward != Vec3::ZERO {
+            camera.forward = forward;
+        }
+        camera
+    }
+
+    /// Records one frame's time and what it drew. Returns true once every frame has been
+    /// measured.
+    pub fn record(&mut self, frame_time: f32, render_stats: RenderStats) -> bool {
+        if self.frame >= self.options.warmup {
+            self.times_ms.push(frame_time * 1000.0);
+            self.render_stats.push(render_stats);
+        }
+        self.frame += 1;
+        self.is_finished()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.times_ms.len() >= self.options.frames as usize
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats::from_times(&self.times_ms)
+    }
+
+    /// Writes the measured frames to the output file and returns the summary.
+    pub fn write_results(&self) -> std::io::Result<FrameStats> {
+        let stats = self.stats();
+        let json = self.options.output.extension().is_some_and(|extension| extension == "json");
+        let mut text = String::new();
+        if json {
+            let scene = self.options.scene.as_ref().map_or("null".to_owned(), |scene| format!("{:?}", scene.display().to_string()));
+            let times: Vec<String> = self.times_ms.iter().map(|t| format!("{:.4}", t)).collect();
+            writeln!(text, "{{").ok();
+            writeln!(text, "  \"scene\": {},", scene).ok();
+            writeln!(text, "  \"frames\": {},", stats.frames).ok();
+            for (name, value) in [
+                ("mean_ms", stats.mean),
+                ("min_ms", stats.min),
+                ("max_ms", stats.max),
+                ("median_ms", stats.median),
+                ("p95_ms", stats.p95),
+                ("p99_ms", stats.p99),
+                ("std_dev_ms", stats.std_dev),
+            ] {
+                writeln!(text, "  \"{}\": {:.4},", name, value).ok();
+            }
+            // mean and peak of each counter, e.g. "draw_calls_mean" and "draw_calls_max"
+            for (index, (name, _)) in RenderStats::default().counters().iter().enumerate() {
+                let values = self.render_stats.iter().map(|stats| stats.counters()[index].1);
+                let mean = values.clone().sum::<u64>() as f64 / self.render_stats.len().max(1) as f64;
+                let max = values.max().unwrap_or(0);
+                writeln!(text, "  \"{}_mean\": {:.2},", name, mean).ok();
+                writeln!(text, "  \"{}_max\": {},", name, max).ok();
+            }
+            writeln!(text, "  \"frame_times_ms\": [{}]", times.join(", ")).ok();
+            writeln!(text, "}}").ok();
+        } else {
+            let names: Vec<_> = RenderStats::default().counters().iter().map(|(name, _)| *name).collect();
+            writeln!(text, "frame,frame_time_ms,{}", names.join(",")).ok();
+            for (frame, (time, stats)) in self.times_ms.iter().zip(&self.render_stats).enumerate() {
+                let counters: Vec<_> = stats.counters().iter().map(|(_, value)| value.to_string()).collect();
+                writeln!(text, "{},{:.4},{}", frame, time, counters.join(",")).ok();
+            }
+        }
+        std::fs::write(&self.options.output, text)?;
+        Ok(stats)
+    }
+}
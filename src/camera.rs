@@ -0,0 +1,580 @@
+use std::collections::HashMap;
+
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::{mesh_renderer::depth_texture, postprocess::fullscreen_module, render_layers::RenderLayers};
+
+/// Part of a render target, in fractions of its size with the origin top left, so split-screen
+/// layouts survive window resizes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub const FULL: Viewport = Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    /// Viewport `index` of `count` side-by-side (or stacked, with `vertical`) split-screen views.
+    pub fn split(index: u32, count: u32, vertical: bool) -> Self {
+        let size = 1.0 / count.max(1) as f32;
+        let offset = index.min(count.saturating_sub(1)) as f32 * size;
+        if vertical {
+            Viewport {
+                x: 0.0,
+                y: offset,
+                width: 1.0,
+                height: size,
+            }
+        } else {
+            Viewport {
+                x: offset,
+                y: 0.0,
+                width: size,
+                height: 1.0,
+            }
+        }
+    }
+
+    /// A picture-in-picture inset of `size` (a fraction of the target) in the top right corner,
+    /// `margin` away from the edges.
+    pub fn inset(size: f32, margin: f32) -> Self {
+        Viewport {
+            x: 1.0 - size - margin,
+            y: margin,
+            width: size,
+            height: size,
+        }
+    }
+
+    /// The rectangle in whole pixels, clamped to the target and at least one pixel large.
+    pub fn pixels(&self, (target_width, target_height): (u32, u32)) -> (u32, u32, u32, u32) {
+        let left = ((self.x * target_width as f32).round() as u32).min(target_width.saturating_sub(1));
+        let top = ((self.y * target_height as f32).round() as u32).min(target_height.saturating_sub(1));
+        let right = (((self.x + self.width) * target_width as f32).round() as u32).clamp(left + 1, target_width.max(left + 1));
+        let bottom = (((self.y + self.height) * target_height as f32).round() as u32).clamp(top + 1, target_height.max(top + 1));
+        (left, top, right - left, bottom - top)
+    }
+
+    /// The viewport covering a rectangle given in pixels, such as the area an egui panel leaves
+    /// for the scene (egui's points times `pixels_per_point`).
+    pub fn from_pixels(left: f32, top: f32, width: f32, height: f32, (target_width, target_height): (u32, u32)) -> Self {
+        let (target_width, target_height) = (target_width.max(1) as f32, target_height.max(1) as f32);
+        Viewport {
+            x: left / target_width,
+            y: top / target_height,
+            width: width / target_width,
+            height: height / target_height,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        *self == Viewport::FULL
+    }
+}
+
+/// A half-line from `origin` along the normalized `direction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// Distance along the ray to the plane through `point` facing `normal`, if the ray hits it
+    /// in front of its origin.
+    pub fn intersect_plane(&self, point: Vec3, normal: Vec3) -> Option<f32> {
+        let facing = self.direction.dot(normal);
+        if facing.abs() < f32::EPSILON {
+            return None;
+        }
+        let distance = (point - self.origin).dot(normal) / facing;
+        (distance >= 0.0).then_some(distance)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective { fov_y: f32, near: f32, far: f32 },
+    /// `height` is the visible height in world units; the width follows the aspect ratio.
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+impl Projection {
+    /// The near and far plane distances.
+    pub fn depth_range(&self) -> (f32, f32) {
+        match *self {
+            Projection::Perspective { near, far, .. } | Projection::Orthographic { near, far, .. } => (near, far),
+        }
+    }
+
+    pub fn matrix(&self, aspect: f32) -> Mat4 {
+        match *self {
+            Projection::Perspective { fov_y, near, far } => Mat4::perspective_rh(fov_y, aspect, near, far),
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect;
+                Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, near, far)
+            }
+        }
+    }
+}
+
+/// Where a camera's image goes. Offscreen targets are created by the application in
+/// `CameraTargets` and looked up there by key, e.g. to show a security camera feed on a monitor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CameraTarget {
+    Surface,
+    Offscreen(u32),
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub position: Vec3,
+    pub forward: Vec3,
+    pub up: Vec3,
+    pub projection: Projection,
+    pub viewport: Viewport,
+    pub target: CameraTarget,
+    /// Clears the viewport's color first; `None` draws over what earlier cameras left there.
+    /// Depth is cleared either way.
+    pub clear: Option<wgpu::Color>,
+    /// Objects render in this camera only if they share a layer with it.
+    pub layers: RenderLayers,
+    /// Cameras render in increasing order, so overlays such as a PiP view go last.
+    pub order: i32,
+    pub active: bool,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            position: Vec3::new(0.0, 0.0, 5.0),
+            forward: -Vec3::Z,
+            up: Vec3::Y,
+            projection: Projection::Perspective {
+                fov_y: 60f32.to_radians(),
+                near: 0.1,
+                far: 1000.0,
+            },
+            viewport: Viewport::FULL,
+            target: CameraTarget::Surface,
+            clear: Some(wgpu::Color::BLACK),
+            layers: RenderLayers::ALL.without(RenderLayers::EDITOR | RenderLayers::MINIMAP),
+            order: 0,
+            active: true,
+        }
+    }
+}
+
+impl Camera {
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position, self.position + self.forward, self.up)
+    }
+
+    /// View-projection for rendering into a target of `target_size`, with the aspect ratio of
+    /// this camera's viewport.
+    pub fn view_projection(&self, target_size: (u32, u32)) -> Mat4 {
+        let (_, _, width, height) = self.viewport.pixels(target_size);
+        self.projection.matrix(width as f32 / height as f32) * self.view()
+    }
+
+    /// The ray through `cursor`, in pixels of a target of `target_size` with the origin top left,
+    /// starting on the near plane. `None` if the cursor is outside this camera's viewport.
+    pub fn viewport_to_world_ray(&self, cursor: Vec2, target_size: (u32, u32)) -> Option<Ray> {
+        let (left, top, width, height) = self.viewport.pixels(target_size);
+        let local = (cursor - Vec2::new(left as f32, top as f32)) / Vec2::new(width as f32, height as f32);
+        if local.x < 0.0 || local.y < 0.0 || local.x > 1.0 || local.y > 1.0 {
+            return None;
+        }
+        let ndc = Vec2::new(local.x * 2.0 - 1.0, 1.0 - local.y * 2.0);
+        let inverse = self.view_projection(target_size).inverse();
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+        Some(Ray {
+            origin: near,
+            direction: (far - near).normalize_or_zero(),
+        })
+    }
+
+    /// Where `point` lands in a target of `target_size`, in pixels with the origin top left.
+    /// `None` if it is behind the camera; points off to the side map outside the viewport.
+    pub fn world_to_viewport(&self, point: Vec3, target_size: (u32, u32)) -> Option<Vec2> {
+        let clip = self.view_projection(target_size) * point.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate().truncate() / clip.w;
+        let (left, top, width, height) = self.viewport.pixels(target_size);
+        Some(Vec2::new(
+            left as f32 + (ndc.x + 1.0) * 0.5 * width as f32,
+            top as f32 + (1.0 - ndc.y) * 0.5 * height as f32,
+        ))
+    }
+
+    pub fn sees(&self, layers: RenderLayers) -> bool {
+        self.layers.intersects(layers)
+    }
+
+    /// The right-handed basis the camera looks along, `forward` first.
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = self.forward.normalize_or_zero();
+        let right = forward.cross(self.up).normalize_or_zero();
+        (forward, right, right.cross(forward))
+    }
+}
+
+/// A camera's matrices as shaders see them, in `CameraBuffer`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub view_projection: [[f32; 4]; 4],
+    pub inverse_view_projection: [[f32; 4]; 4],
+    /// World position, w is 1.
+    pub position: [f32; 4],
+}
+
+impl CameraUniform {
+    pub fn new(camera: &Camera, target_size: (u32, u32)) -> Self {
+        let (_, _, width, height) = camera.viewport.pixels(target_size);
+        let view = camera.view();
+        let projection = camera.projection.matrix(width as f32 / height as f32);
+        let view_projection = projection * view;
+        CameraUniform {
+            view: view.to_cols_array_2d(),
+            projection: projection.to_cols_array_2d(),
+            view_projection: view_projection.to_cols_array_2d(),
+            inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+            position: camera.position.extend(1.0).into(),
+        }
+    }
+}
+
+/// A uniform buffer holding one camera's `CameraUniform`, with a bind group for pipelines to
+/// bind it from any stage.
+pub struct CameraBuffer {
+    buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl CameraBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("camera"),
+            size: std::mem::size_of::<CameraUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("camera layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<CameraUniform>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("camera bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        CameraBuffer {
+            buffer,
+            layout,
+            bind_group,
+        }
+    }
+
+    /// Writes `camera`'s matrices for rendering into a target of `target_size`.
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera, target_size: (u32, u32)) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&CameraUniform::new(camera, target_size)));
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Layout of `bind_group`, for building pipeline layouts.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CameraId(usize);
+
+/// Every camera in the scene.
+#[derive(Default)]
+pub struct Cameras {
+    cameras: Vec<Option<Camera>>,
+}
+
+impl Cameras {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, camera: Camera) -> CameraId {
+        match self.cameras.iter().position(Option::is_none) {
+            Some(index) => {
+                self.cameras[index] = Some(camera);
+                CameraId(index)
+            }
+            None => {
+                self.cameras.push(Some(camera));
+                CameraId(self.cameras.len() - 1)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: CameraId) -> Option<Camera> {
+        self.cameras.get_mut(id.0)?.take()
+    }
+
+    pub fn get(&self, id: CameraId) -> Option<&Camera> {
+        self.cameras.get(id.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: CameraId) -> Option<&mut Camera> {
+        self.cameras.get_mut(id.0)?.as_mut()
+    }
+
+    /// Active cameras in render order; cameras with equal `order` keep insertion order.
+    pub fn render_order(&self) -> Vec<(CameraId, &Camera)> {
+        let mut active: Vec<_> = self
+            .cameras
+            .iter()
+            .enumerate()
+            .filter_map(|(index, camera)| camera.as_ref().filter(|c| c.active).map(|c| (CameraId(index), c)))
+            .collect();
+        active.sort_by_key(|(_, camera)| camera.order);
+        active
+    }
+}
+
+/// A texture `CameraTarget::Offscreen` cameras render into, with its depth buffer. Holds the
+/// linear HDR image, not tonemapped, for sampling like any other texture.
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    depth: wgpu::TextureView,
+    size: (u32, u32),
+}
+
+impl OffscreenTarget {
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub(crate) fn depth(&self) -> &wgpu::TextureView {
+        &self.depth
+    }
+}
+
+/// The textures of offscreen cameras, by their `CameraTarget::Offscreen` key. Cameras whose
+/// target hasn't been created aren't rendered.
+pub struct CameraTargets {
+    format: wgpu::TextureFormat,
+    targets: HashMap<u32, OffscreenTarget>,
+}
+
+impl CameraTargets {
+    /// `format` is the color format cameras render in, the mesh renderer's.
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        CameraTargets {
+            format,
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Creates the target for `key`, replacing the one there was.
+    pub fn create(&mut self, device: &wgpu::Device, key: u32, (width, height): (u32, u32)) -> &OffscreenTarget {
+        let size = (width.max(1), height.max(1));
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("camera target"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        });
+        let target = OffscreenTarget {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            depth: depth_texture(device, size),
+            texture,
+            size,
+        };
+        self.targets.insert(key, target);
+        &self.targets[&key]
+    }
+
+    pub fn get(&self, key: u32) -> Option<&OffscreenTarget> {
+        self.targets.get(&key)
+    }
+
+    pub fn remove(&mut self, key: u32) -> Option<OffscreenTarget> {
+        self.targets.remove(&key)
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+}
+
+/// Starts render passes limited to one camera's viewport. Clearing a viewport that doesn't
+/// cover the whole target can't use the attachment load op, so it draws a viewport-sized
+/// quad instead.
+pub struct ViewportPasses {
+    clear: wgpu::RenderPipeline,
+    clear_depth: wgpu::RenderPipeline,
+    clear_color_only: wgpu::RenderPipeline,
+    sample_count: u32,
+}
+
+impl ViewportPasses {
+    /// `depth_format` and `sample_count` are those of the attachments later passed to `begin`.
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        let module = fullscreen_module(device, "viewport clear", include_str!("shaders/viewport_clear.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("viewport clear"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        // the shader outputs 1, so the result is exactly the blend constant
+        let constant = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Constant,
+            dst_factor: wgpu::BlendFactor::Zero,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let depth_stencil = wgpu::DepthStencilState {
+            format: depth_format,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        };
+        let pipeline = |entry_point, depth_stencil, write_mask| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("viewport clear"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_fullscreen",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil,
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point,
+                    targets: &[wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: Some(wgpu::BlendState {
+                            color: constant,
+                            alpha: constant,
+                        }),
+                        write_mask,
+                    }],
+                }),
+                multiview: None,
+            })
+        };
+
+        ViewportPasses {
+            clear: pipeline("fs_clear", Some(depth_stencil.clone()), wgpu::ColorWrites::ALL),
+            clear_depth: pipeline("fs_clear", Some(depth_stencil), wgpu::ColorWrites::empty()),
+            clear_color_only: pipeline("fs_clear_color", None, wgpu::ColorWrites::ALL),
+            sample_count,
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Begins a pass for `camera` with the viewport and scissor set to its rectangle, its color
+    /// cleared per `camera.clear` and its depth cleared.
+    pub fn begin<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        camera: &Camera,
+        color: &'a wgpu::TextureView,
+        depth: Option<&'a wgpu::TextureView>,
+        target_size: (u32, u32),
+    ) -> wgpu::RenderPass<'a> {
+        let full = camera.viewport.is_full();
+        let full_clear = camera.clear.filter(|_| full);
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("camera"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: color,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: full_clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: depth.map(|view| wgpu::RenderPassDepthStencilAttachment {
+                view,
+                depth_ops: Some(wgpu::Operations {
+                    load: if full { wgpu::LoadOp::Clear(1.0) } else { wgpu::LoadOp::Load },
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        let (x, y, width, height) = camera.viewport.pixels(target_size);
+        pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+        pass.set_scissor_rect(x, y, width, height);
+        if !full {
+            let pipeline = match (camera.clear, depth.is_some()) {
+                (Some(_), true) => Some(&self.clear),
+                (Some(_), false) => Some(&self.clear_color_only),
+                (None, true) => Some(&self.clear_depth),
+                (None, false) => None,
+            };
+            if let Some(pipeline) = pipeline {
+                pass.set_pipeline(pipeline);
+                pass.set_blend_constant(camera.clear.unwrap_or(wgpu::Color::BLACK));
+                pass.draw(0..3, 0..1);
+            }
+        }
+        pass
+    }
+}
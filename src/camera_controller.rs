@@ -0,0 +1,173 @@
+use glam::{Vec2, Vec3};
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::{camera::Camera, input::Input};
+
+// stops just short of straight up or down, where the view's up vector degenerates
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+/// Direction for `yaw` radians left of -Z and `pitch` radians above the horizon.
+fn direction(yaw: f32, pitch: f32) -> Vec3 {
+    Vec3::new(-yaw.sin() * pitch.cos(), pitch.sin(), -yaw.cos() * pitch.cos())
+}
+
+/// The inverse of `direction`.
+fn yaw_pitch(direction: Vec3) -> (f32, f32) {
+    let direction = direction.normalize_or_zero();
+    ((-direction.x).atan2(-direction.z), direction.y.clamp(-1.0, 1.0).asin())
+}
+
+/// Circles around a target: drag to rotate, drag with the middle button to pan and scroll to
+/// zoom. For model viewers, editors and strategy games.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+    /// Radians around Y, 0 looking along -Z.
+    pub yaw: f32,
+    /// Radians above the horizon the camera looks up from; positive looks down on the target.
+    pub pitch: f32,
+    pub rotate_button: MouseButton,
+    pub pan_button: MouseButton,
+    /// Radians per pixel of mouse motion.
+    pub sensitivity: f32,
+    /// Fraction of the distance each scrolled line zooms by.
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        OrbitController {
+            target: Vec3::ZERO,
+            distance: 5.0,
+            yaw: 0.0,
+            pitch: 0.3,
+            rotate_button: MouseButton::Left,
+            pan_button: MouseButton::Middle,
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+            max_distance: 1000.0,
+        }
+    }
+}
+
+impl OrbitController {
+    /// Orbits `target` from where `camera` is now.
+    pub fn looking_at(camera: &Camera, target: Vec3) -> Self {
+        let offset = target - camera.position;
+        let (yaw, pitch) = yaw_pitch(offset);
+        OrbitController {
+            target,
+            distance: offset.length(),
+            yaw,
+            pitch: -pitch,
+            ..Self::default()
+        }
+    }
+
+    pub fn update(&mut self, input: &Input, camera: &mut Camera) {
+        let delta = input.mouse_delta();
+        if input.button_held(self.rotate_button) {
+            self.yaw -= delta.x * self.sensitivity;
+            self.pitch = (self.pitch + delta.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        if input.button_held(self.pan_button) && delta != Vec2::ZERO {
+            let (_, right, up) = camera.basis();
+            // the target follows the cursor at roughly the same speed at any zoom
+            let scale = self.distance * self.sensitivity * 0.2;
+            self.target += (up * delta.y - right * delta.x) * scale;
+        }
+        let zoom = (1.0 - self.zoom_speed.clamp(0.0, 0.9)).powf(input.scroll().y);
+        self.distance = (self.distance * zoom).clamp(self.min_distance, self.max_distance.max(self.min_distance));
+        self.apply(camera);
+    }
+
+    /// Places `camera` without reading input, e.g. after changing the target in code.
+    pub fn apply(&self, camera: &mut Camera) {
+        let forward = direction(self.yaw, -self.pitch);
+        camera.position = self.target - forward * self.distance;
+        camera.forward = forward;
+        camera.up = Vec3::Y;
+    }
+}
+
+/// Free flight: WASD to move, E and Q (or space and control) to rise and sink, shift to go
+/// faster, and the mouse to look around while `look_button` is held. Scrolling changes the
+/// speed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlyController {
+    /// Radians around Y, 0 looking along -Z.
+    pub yaw: f32,
+    /// Radians above the horizon.
+    pub pitch: f32,
+    /// Units per second.
+    pub speed: f32,
+    /// Speed multiplier while shift is held.
+    pub boost: f32,
+    /// Radians per pixel of mouse motion.
+    pub sensitivity: f32,
+    /// `None` always looks around, for first-person games that grab the cursor.
+    pub look_button: Option<MouseButton>,
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        FlyController {
+            yaw: 0.0,
+            pitch: 0.0,
+            speed: 5.0,
+            boost: 4.0,
+            sensitivity: 0.003,
+            look_button: Some(MouseButton::Right),
+        }
+    }
+}
+
+impl FlyController {
+    /// Starts looking the way `camera` does.
+    pub fn from_camera(camera: &Camera) -> Self {
+        let (yaw, pitch) = yaw_pitch(camera.forward);
+        FlyController {
+            yaw,
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+            ..Self::default()
+        }
+    }
+
+    pub fn update(&mut self, input: &Input, camera: &mut Camera, dt: f32) {
+        if self.look_button.is_none_or(|button| input.button_held(button)) {
+            let delta = input.mouse_delta();
+            self.yaw -= delta.x * self.sensitivity;
+            self.pitch = (self.pitch - delta.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        let scroll = input.scroll().y;
+        if scroll != 0.0 {
+            self.speed = (self.speed * 1.2f32.powf(scroll)).clamp(0.01, 10_000.0);
+        }
+
+        let forward = direction(self.yaw, self.pitch);
+        let right = direction(self.yaw - std::f32::consts::FRAC_PI_2, 0.0);
+        let axis = |positive: &[VirtualKeyCode], negative: &[VirtualKeyCode]| {
+            let held = |keys: &[VirtualKeyCode]| keys.iter().any(|&key| input.key_held(key)) as i32 as f32;
+            held(positive) - held(negative)
+        };
+        let movement = forward * axis(&[VirtualKeyCode::W, VirtualKeyCode::Up], &[VirtualKeyCode::S, VirtualKeyCode::Down])
+            + right * axis(&[VirtualKeyCode::D, VirtualKeyCode::Right], &[VirtualKeyCode::A, VirtualKeyCode::Left])
+            + Vec3::Y
+                * axis(
+                    &[VirtualKeyCode::E, VirtualKeyCode::Space],
+                    &[VirtualKeyCode::Q, VirtualKeyCode::LControl],
+                );
+        let boost = if input.key_held(VirtualKeyCode::LShift) || input.key_held(VirtualKeyCode::RShift) {
+            self.boost
+        } else {
+            1.0
+        };
+        camera.position += movement.normalize_or_zero() * self.speed * boost * dt;
+        camera.forward = forward;
+        camera.up = Vec3::Y;
+    }
+}
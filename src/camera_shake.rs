@@ -0,0 +1,145 @@
+use glam::{Quat, Vec3};
+
+use crate::camera::Camera;
+
+/// Smooth 1D gradient noise in roughly [-1, 1].
+fn noise(x: f32, seed: u32) -> f32 {
+    let gradient = |i: i32| {
+        let mut h = (i as u32).wrapping_mul(0x9E37_79B1) ^ seed.wrapping_mul(0x85EB_CA77);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x2C1B_3C6D);
+        h ^= h >> 12;
+        (h & 0xffff) as f32 / 32767.5 - 1.0
+    };
+    let i = x.floor();
+    let f = x - i;
+    let fade = f * f * (3.0 - 2.0 * f);
+    let a = gradient(i as i32) * f;
+    let b = gradient(i as i32 + 1) * (f - 1.0);
+    (a + (b - a) * fade) * 2.0
+}
+
+/// Trauma-based procedural shake: hits add trauma, which decays over time, and the shake
+/// strength is trauma squared so small hits stay subtle while big ones feel violent. Motion
+/// comes from smooth noise rather than random jitter, so it reads as a shake instead of noise.
+#[derive(Clone, Debug)]
+pub struct CameraShake {
+    trauma: f32,
+    /// Trauma lost per second.
+    pub decay: f32,
+    /// Largest yaw, pitch and roll at full trauma, in radians.
+    pub max_angles: Vec3,
+    /// Largest offset at full trauma along the camera's right, up and forward axes.
+    pub max_offset: Vec3,
+    /// Noise frequency in Hz; higher is more jittery.
+    pub frequency: f32,
+    time: f32,
+    seed: u32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        CameraShake {
+            trauma: 0.0,
+            decay: 1.5,
+            max_angles: Vec3::new(0.05, 0.05, 0.08),
+            max_offset: Vec3::new(0.1, 0.1, 0.0),
+            frequency: 15.0,
+            time: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl CameraShake {
+    /// Each shake with a different `seed` moves differently, e.g. per split-screen player.
+    pub fn new(seed: u32) -> Self {
+        CameraShake {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    /// Adds trauma, capped at 1. An explosion might add 0.6, a footstep of a giant 0.2.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+        self.trauma = (self.trauma - self.decay * dt).max(0.0);
+    }
+
+    /// The current offset in camera space and the rotation to apply to the camera's axes.
+    pub fn offsets(&self) -> (Vec3, Vec3) {
+        let shake = self.trauma * self.trauma;
+        let t = self.time * self.frequency;
+        let channel = |index: u32| noise(t, self.seed.wrapping_mul(6).wrapping_add(index)) * shake;
+        let angles = Vec3::new(channel(0), channel(1), channel(2)) * self.max_angles;
+        let offset = Vec3::new(channel(3), channel(4), channel(5)) * self.max_offset;
+        (offset, angles)
+    }
+
+    /// A copy of `camera` with the shake applied. Keep the unshaken camera as the source of
+    /// truth so the shake never accumulates into gameplay code.
+    pub fn apply(&self, camera: &Camera) -> Camera {
+        let mut shaken = camera.clone();
+        if self.trauma <= 0.0 {
+            return shaken;
+        }
+        let (offset, angles) = self.offsets();
+        let forward = camera.forward.normalize_or_zero();
+        let right = forward.cross(camera.up).normalize_or_zero();
+        let up = right.cross(forward);
+
+        let rotation = Quat::from_axis_angle(up, angles.x)
+            * Quat::from_axis_angle(right, angles.y)
+            * Quat::from_axis_angle(forward, angles.z);
+        shaken.position += right * offset.x + up * offset.y + forward * offset.z;
+        shaken.forward = rotation * forward;
+        shaken.up = rotation * up;
+        shaken
+    }
+}
+
+/// A one-shot value that jumps to `strength` and eases back to zero over `duration`, for
+/// driving post effects from gameplay, e.g. a hit that briefly raises motion blur or grading.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Impulse {
+    strength: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Impulse {
+    /// Restarts the impulse; a stronger impulse overrides a weaker one still running.
+    pub fn trigger(&mut self, strength: f32, duration: f32) {
+        if strength >= self.value() {
+            *self = Impulse {
+                strength,
+                duration: duration.max(0.0001),
+                elapsed: 0.0,
+            };
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+    }
+
+    pub fn value(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 0.0;
+        }
+        let remaining = 1.0 - self.elapsed / self.duration;
+        self.strength * remaining * remaining
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.elapsed < self.duration
+    }
+}
@@ -0,0 +1,154 @@
+/// Optional features the engine makes use of when the adapter has them. None of these are
+/// required; subsystems check `Capabilities` and fall back when a feature is missing.
+pub const DESIRED_FEATURES: wgpu::Features = wgpu::Features::from_bits_truncate(
+    wgpu::Features::TIMESTAMP_QUERY.bits()
+        | wgpu::Features::POLYGON_MODE_LINE.bits()
+        | wgpu::Features::TEXTURE_COMPRESSION_BC.bits()
+        | wgpu::Features::TEXTURE_COMPRESSION_ETC2.bits()
+        | wgpu::Features::TEXTURE_COMPRESSION_ASTC_LDR.bits()
+        | wgpu::Features::INDIRECT_FIRST_INSTANCE.bits()
+        | wgpu::Features::MULTI_DRAW_INDIRECT.bits(),
+);
+
+/// What the device was actually created with, so subsystems can degrade gracefully.
+///
+/// There are no ray query or mesh shading checks: wgpu 0.12 exposes neither on any backend, so
+/// shadows always come from the shadow maps and contact shadows, ambient occlusion only from
+/// materials' occlusion maps, and meshlets go through `gpu_driven::GeometryPath`.
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    pub adapter: wgpu::AdapterInfo,
+    pub features: wgpu::Features,
+    pub limits: wgpu::Limits,
+    pub downlevel: wgpu::DownlevelCapabilities,
+}
+
+impl Capabilities {
+    /// Intersects `DESIRED_FEATURES` with what `adapter` supports and picks the default limits,
+    /// or the downlevel defaults on adapters that can't meet them. Texture size limits always
+    /// follow the adapter since the swapchain may need to be as large as the display.
+    pub fn negotiate(adapter: &wgpu::Adapter) -> Self {
+        let supported = adapter.limits();
+        let limits = if fits(&wgpu::Limits::default(), &supported) {
+            wgpu::Limits::default()
+        } else {
+            wgpu::Limits::downlevel_defaults()
+        }
+        .using_resolution(supported);
+
+        Capabilities {
+            adapter: adapter.get_info(),
+            features: DESIRED_FEATURES & adapter.features(),
+            limits,
+            downlevel: adapter.get_downlevel_properties(),
+        }
+    }
+
+    pub fn device_descriptor(&self) -> wgpu::DeviceDescriptor<'static> {
+        wgpu::DeviceDescriptor {
+            features: self.features,
+            limits: self.limits.clone(),
+            label: Some("engine device"),
+        }
+    }
+
+    pub fn has(&self, features: wgpu::Features) -> bool {
+        self.features.contains(features)
+    }
+
+    pub fn timestamps(&self) -> bool {
+        self.has(wgpu::Features::TIMESTAMP_QUERY)
+    }
+
+    pub fn wireframe(&self) -> bool {
+        self.has(wgpu::Features::POLYGON_MODE_LINE)
+    }
+
+    pub fn multi_draw_indirect(&self) -> bool {
+        self.has(wgpu::Features::MULTI_DRAW_INDIRECT | wgpu::Features::INDIRECT_FIRST_INSTANCE)
+    }
+
+    pub fn compute(&self) -> bool {
+        self.downlevel
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+    }
+
+    /// Cube map arrays. Missing on some GLES devices, so the engine's own cubes are stored as
+    /// layers of 2D arrays instead.
+    pub fn cube_arrays(&self) -> bool {
+        self.downlevel
+            .flags
+            .contains(wgpu::DownlevelFlags::CUBE_ARRAY_TEXTURES)
+    }
+
+    /// Storage buffers in every stage and indirect draws, which the GPU-driven path culls and
+    /// draws with.
+    pub fn gpu_driven(&self) -> bool {
+        self.compute()
+            && self.downlevel.flags.contains(
+                wgpu::DownlevelFlags::INDIRECT_EXECUTION
+                    | wgpu::DownlevelFlags::VERTEX_STORAGE
+                    | wgpu::DownlevelFlags::FRAGMENT_STORAGE,
+            )
+    }
+
+    /// Best block-compressed texture family available, if any.
+    pub fn texture_compression(&self) -> Option<TextureCompression> {
+        if self.has(wgpu::Features::TEXTURE_COMPRESSION_BC) {
+            Some(TextureCompression::Bc)
+        } else if self.has(wgpu::Features::TEXTURE_COMPRESSION_ASTC_LDR) {
+            Some(TextureCompression::Astc)
+        } else if self.has(wgpu::Features::TEXTURE_COMPRESSION_ETC2) {
+            Some(TextureCompression::Etc2)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureCompression {
+    Bc,
+    Etc2,
+    Astc,
+}
+
+/// Whether an adapter reporting `supported` can create a device with `desired`.
+fn fits(desired: &wgpu::Limits, supported: &wgpu::Limits) -> bool {
+    macro_rules! check {
+        (max: $($max:ident),*; min: $($min:ident),*) => {
+            $(desired.$max <= supported.$max)&&* && $(desired.$min >= supported.$min)&&*
+        };
+    }
+
+    check!(
+        max: max_texture_dimension_1d,
+            max_texture_dimension_2d,
+            max_texture_dimension_3d,
+            max_texture_array_layers,
+            max_bind_groups,
+            max_dynamic_uniform_buffers_per_pipeline_layout,
+            max_dynamic_storage_buffers_per_pipeline_layout,
+            max_sampled_textures_per_shader_stage,
+            max_samplers_per_shader_stage,
+            max_storage_buffers_per_shader_stage,
+            max_storage_textures_per_shader_stage,
+            max_uniform_buffers_per_shader_stage,
+            max_uniform_buffer_binding_size,
+            max_storage_buffer_binding_size,
+            max_vertex_buffers,
+            max_vertex_attributes,
+            max_vertex_buffer_array_stride,
+            max_push_constant_size,
+            max_inter_stage_shader_components,
+            max_compute_workgroup_storage_size,
+            max_compute_invocations_per_workgroup,
+            max_compute_workgroup_size_x,
+            max_compute_workgroup_size_y,
+            max_compute_workgroup_size_z,
+            max_compute_workgroups_per_dimension;
+        min: min_uniform_buffer_offset_alignment,
+            min_storage_buffer_offset_alignment
+    )
+}
@@ -0,0 +1,424 @@
+use glam::{Mat4, Vec3};
+
+use crate::{
+    camera::Camera,
+    capabilities::Capabilities,
+    noise::{NoiseLayer, NoiseTextureDescriptor, NoiseTextureGenerator, TilingNoise},
+    postprocess::{
+        depth_texture_entry, fullscreen_module, linear_sampler, sampler_entry, texture_entry, uniform_entry,
+        FullscreenPipeline, RenderTarget,
+    },
+    sky::SunLight,
+};
+
+/// March resolution and step counts. Lower tiers march fewer, coarser pixels and lean on
+/// reprojection more, which mostly shows as smearing while the camera turns quickly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloudQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl CloudQuality {
+    /// How many times smaller than the output the clouds are marched, in each direction.
+    pub fn resolution_divisor(self) -> u32 {
+        match self {
+            CloudQuality::Low => 4,
+            CloudQuality::Medium | CloudQuality::High => 2,
+        }
+    }
+
+    pub fn steps(self) -> u32 {
+        match self {
+            CloudQuality::Low => 32,
+            CloudQuality::Medium => 64,
+            CloudQuality::High => 96,
+        }
+    }
+
+    fn light_steps(self) -> u32 {
+        match self {
+            CloudQuality::Low => 3,
+            CloudQuality::Medium => 5,
+            CloudQuality::High => 6,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CloudSettings {
+    pub enabled: bool,
+    pub quality: CloudQuality,
+    /// Altitude of the cloud bases.
+    pub bottom: f32,
+    /// Altitude the tallest clouds reach.
+    pub top: f32,
+    /// From a clear sky at 0 to overcast at 1.
+    pub coverage: f32,
+    /// Extinction per world unit inside a fully dense cloud.
+    pub density: f32,
+    /// World units one repeat of the cloud shape noise covers.
+    pub shape_size: f32,
+    /// World units one repeat of the detail noise covers; it erodes the shapes' edges.
+    pub detail_size: f32,
+    /// How much the detail noise erodes, from 0 to 1.
+    pub detail_strength: f32,
+    /// World units one repeat of the coverage pattern covers.
+    pub weather_size: f32,
+    /// How fast the clouds drift, in world units per second.
+    pub wind: Vec3,
+    /// Henyey-Greenstein anisotropy: towards 1 the clouds glow at their edges around the sun.
+    pub anisotropy: f32,
+    /// Sky light reaching the clouds regardless of the sun.
+    pub ambient: Vec3,
+    /// How far from the camera clouds are drawn; they fade out towards it.
+    pub max_distance: f32,
+    /// Share of each new frame in the accumulated result. Lower is smoother but smears more;
+    /// 1 turns reprojection off.
+    pub temporal_blend: f32,
+}
+
+impl Default for CloudSettings {
+    fn default() -> Self {
+        CloudSettings {
+            enabled: true,
+            quality: CloudQuality::Medium,
+            bottom: 1500.0,
+            top: 4000.0,
+            coverage: 0.5,
+            density: 0.02,
+            shape_size: 6000.0,
+            detail_size: 800.0,
+            detail_strength: 0.35,
+            weather_size: 40000.0,
+            wind: Vec3::new(10.0, 0.0, 3.0),
+            anisotropy: 0.6,
+            ambient: Vec3::new(0.08, 0.1, 0.13),
+            max_distance: 30000.0,
+            temporal_blend: 0.1,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    inverse_view_projection: [[f32; 4]; 4],
+    previous_view_projection: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    ambient: [f32; 4],
+    wind: [f32; 4],
+    // bottom, top, max distance, coverage
+    layer: [f32; 4],
+    // inverse shape, detail and weather sizes, detail strength
+    shape: [f32; 4],
+    // density, anisotropy
+    lighting: [f32; 4],
+    // steps, light steps, frame, temporal blend
+    march: [f32; 4],
+}
+
+struct Targets {
+    size: (u32, u32),
+    current: RenderTarget,
+    // written alternately; the one not written this frame is the history
+    resolved: [RenderTarget; 2],
+}
+
+/// Ray-marched volumetric clouds in a layer between two altitudes, shaped by tiling noise
+/// textures and lit by the sun. Marched at a fraction of the output resolution with a jittered
+/// start each frame and accumulated over frames by reprojection. Runs on the HDR scene before
+/// tonemapping, after the `Sky`, whose `sun_light` it takes.
+pub struct Clouds {
+    pub settings: CloudSettings,
+    march: FullscreenPipeline,
+    reproject: FullscreenPipeline,
+    apply: FullscreenPipeline,
+    march_layout: wgpu::BindGroupLayout,
+    reproject_layout: wgpu::BindGroupLayout,
+    apply_layout: wgpu::BindGroupLayout,
+    noise_sampler: wgpu::Sampler,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+    shape: wgpu::TextureView,
+    detail: wgpu::TextureView,
+    targets: Option<Targets>,
+    previous_view_projection: Option<Mat4>,
+    wind_offset: Vec3,
+    frame: u32,
+}
+
+impl Clouds {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    /// Also generates the noise textures, on the GPU when `capabilities` allow compute shaders
+    /// and otherwise on the CPU, at a lower resolution since that is slow.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        capabilities: &Capabilities,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let noise_volume = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D3,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = |label, entries: &[wgpu::BindGroupLayoutEntry]| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries,
+            })
+        };
+        let march_layout = layout(
+            "clouds march layout",
+            &[uniform_entry(0), sampler_entry(1), noise_volume(2), noise_volume(3), depth_texture_entry(4)],
+        );
+        let reproject_layout = layout(
+            "clouds reproject layout",
+            &[uniform_entry(0), sampler_entry(5), texture_entry(6), texture_entry(7)],
+        );
+        let apply_layout = layout(
+            "clouds apply layout",
+            &[uniform_entry(0), sampler_entry(5), texture_entry(8), texture_entry(9)],
+        );
+
+        let module = fullscreen_module(device, "clouds", include_str!("shaders/clouds.wgsl"));
+        let march = FullscreenPipeline::new(device, "clouds march", &module, "fs_march", &[&march_layout], Self::FORMAT, None);
+        let reproject =
+            FullscreenPipeline::new(device, "clouds reproject", &module, "fs_reproject", &[&reproject_layout], Self::FORMAT, None);
+        let apply = FullscreenPipeline::new(device, "clouds apply", &module, "fs_apply", &[&apply_layout], output_format, None);
+
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("clouds params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let noise_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("clouds noise sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let generator = capabilities.compute().then(|| NoiseTextureGenerator::new(device));
+        let worley = |frequency| Some(NoiseLayer::new(TilingNoise::Worley, frequency).with_octaves(3, 0.5).inverted());
+        let shape = NoiseTextureDescriptor {
+            size: if generator.is_some() { 128 } else { 64 },
+            three_d: true,
+            // the shader erodes the Perlin octaves with the Worley ones into Perlin-Worley
+            channels: [
+                Some(NoiseLayer::new(TilingNoise::Perlin, 4).with_octaves(4, 0.5)),
+                worley(4),
+                worley(8),
+                worley(16),
+            ],
+        };
+        let detail = NoiseTextureDescriptor {
+            size: 32,
+            three_d: true,
+            channels: [worley(2), worley(4), worley(8), None],
+        };
+
+        Clouds {
+            settings: CloudSettings::default(),
+            march,
+            reproject,
+            apply,
+            march_layout,
+            reproject_layout,
+            apply_layout,
+            noise_sampler,
+            sampler: linear_sampler(device),
+            uniform,
+            shape: noise_texture(device, queue, generator.as_ref(), "clouds shape noise", &shape),
+            detail: noise_texture(device, queue, generator.as_ref(), "clouds detail noise", &detail),
+            targets: None,
+            previous_view_projection: None,
+            wind_offset: Vec3::ZERO,
+            frame: 0,
+        }
+    }
+
+    fn ensure_targets(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let divisor = self.settings.quality.resolution_divisor();
+        let size = (width.div_ceil(divisor).max(1), height.div_ceil(divisor).max(1));
+        if !matches!(&self.targets, Some(targets) if targets.size == size) {
+            let create = |label| RenderTarget::new(device, label, size.0, size.1, Self::FORMAT);
+            self.targets = Some(Targets {
+                size,
+                current: create("clouds march"),
+                resolved: [create("clouds resolved a"), create("clouds resolved b")],
+            });
+            // nothing to reproject from at the new size
+            self.previous_view_projection = None;
+        }
+    }
+
+    /// Forgets the accumulated clouds, e.g. after a camera cut, so the previous view doesn't
+    /// smear into the new one.
+    pub fn reset_history(&mut self) {
+        self.previous_view_projection = None;
+    }
+
+    /// Writes `color` with clouds over it to `output`, moving them along by `dt` seconds of
+    /// wind. When disabled this is a plain copy so the pass can stay in the chain.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        (width, height): (u32, u32),
+        camera: &Camera,
+        sun: &SunLight,
+        dt: f32,
+        output: &wgpu::TextureView,
+    ) {
+        let settings = self.settings;
+        self.ensure_targets(device, width, height);
+        self.wind_offset -= settings.wind * dt;
+
+        let view_projection = camera.view_projection((width, height));
+        let temporal_blend = match self.previous_view_projection {
+            Some(_) => settings.temporal_blend.clamp(0.01, 1.0),
+            None => 1.0,
+        };
+        let params = Params {
+            inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+            previous_view_projection: self.previous_view_projection.unwrap_or(view_projection).to_cols_array_2d(),
+            camera_position: camera.position.extend(1.0).into(),
+            sun_direction: (-sun.direction).normalize_or_zero().extend(0.0).into(),
+            sun_color: sun.color.extend(0.0).into(),
+            ambient: settings.ambient.extend(0.0).into(),
+            wind: self.wind_offset.extend(0.0).into(),
+            layer: [
+                settings.bottom,
+                settings.top.max(settings.bottom + 1.0),
+                settings.max_distance.max(1.0),
+                settings.coverage.clamp(0.0, 1.0),
+            ],
+            shape: [
+                1.0 / settings.shape_size.max(1.0),
+                1.0 / settings.detail_size.max(1.0),
+                1.0 / settings.weather_size.max(1.0),
+                settings.detail_strength.clamp(0.0, 1.0),
+            ],
+            lighting: [settings.density.max(0.0), settings.anisotropy.clamp(-0.99, 0.99), 0.0, 0.0],
+            march: [
+                if settings.enabled { settings.quality.steps() as f32 } else { 0.0 },
+                settings.quality.light_steps() as f32,
+                (self.frame % 1024) as f32,
+                temporal_blend,
+            ],
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&params));
+
+        let targets = self.targets.as_ref().unwrap();
+        let written = (self.frame % 2) as usize;
+        let bind_group = |layout, entries: &[wgpu::BindGroupEntry]| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("clouds bind group"),
+                layout,
+                entries,
+            })
+        };
+        let uniform = wgpu::BindGroupEntry {
+            binding: 0,
+            resource: self.uniform.as_entire_binding(),
+        };
+        let sampler = |binding, sampler| wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::Sampler(sampler),
+        };
+        let view = |binding, view| wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        };
+
+        let march_group = bind_group(
+            &self.march_layout,
+            &[uniform.clone(), sampler(1, &self.noise_sampler), view(2, &self.shape), view(3, &self.detail), view(4, depth)],
+        );
+        self.march.draw(encoder, &targets.current.view, &[&march_group], Some(wgpu::Color::BLACK));
+
+        let reproject_group = bind_group(
+            &self.reproject_layout,
+            &[
+                uniform.clone(),
+                sampler(5, &self.sampler),
+                view(6, &targets.current.view),
+                view(7, &targets.resolved[1 - written].view),
+            ],
+        );
+        self.reproject.draw(encoder, &targets.resolved[written].view, &[&reproject_group], Some(wgpu::Color::BLACK));
+
+        let apply_group = bind_group(
+            &self.apply_layout,
+            &[uniform, sampler(5, &self.sampler), view(8, color), view(9, &targets.resolved[written].view)],
+        );
+        self.apply.draw(encoder, output, &[&apply_group], Some(wgpu::Color::BLACK));
+
+        self.previous_view_projection = Some(view_projection);
+        self.frame = self.frame.wrapping_add(1);
+    }
+}
+
+fn noise_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    generator: Option<&NoiseTextureGenerator>,
+    label: &str,
+    descriptor: &NoiseTextureDescriptor,
+) -> wgpu::TextureView {
+    let texture = match generator {
+        Some(generator) => {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some(label) });
+            let texture = generator.generate(device, &mut encoder, label, descriptor);
+            queue.submit(std::iter::once(encoder.finish()));
+            texture
+        }
+        None => {
+            let size = wgpu::Extent3d {
+                width: descriptor.size,
+                height: descriptor.size,
+                depth_or_array_layers: descriptor.size,
+            };
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            });
+            queue.write_texture(
+                texture.as_image_copy(),
+                bytemuck::cast_slice(&descriptor.texels()),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(descriptor.size * 4),
+                    rows_per_image: std::num::NonZeroU32::new(descriptor.size),
+                },
+                size,
+            );
+            texture
+        }
+    };
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
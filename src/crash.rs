@@ -0,0 +1,286 @@
+use std::{
+    backtrace::Backtrace,
+    collections::VecDeque,
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::render_stats::RenderStats;
+
+const LOG_LINES: usize = 200;
+
+struct CrashState {
+    directory: Option<PathBuf>,
+    adapter: Option<wgpu::AdapterInfo>,
+    frame: u64,
+    frame_time: f32,
+    sections: Vec<(String, String)>,
+    render_stats: Option<RenderStats>,
+    last_frame: Option<(u32, u32, Vec<u8>)>,
+}
+
+static STATE: Mutex<CrashState> = Mutex::new(CrashState {
+    directory: None,
+    adapter: None,
+    frame: 0,
+    frame_time: 0.0,
+    sections: Vec::new(),
+    render_stats: None,
+    last_frame: None,
+});
+static LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Keeps the most recent log lines for crash reports and prints warnings and errors.
+struct CrashLogger;
+
+static LOGGER: CrashLogger = CrashLogger;
+
+impl log::Log for CrashLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!("[{} {}] {}", record.level(), record.target(), record.args());
+        if record.level() <= log::Level::Warn {
+            eprintln!("{}", line);
+        }
+        if let Ok(mut log) = LOG.lock() {
+            if log.len() == LOG_LINES {
+                log.pop_front();
+            }
+            log.push_back(line);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs a panic hook that writes a crash report into `directory`, tells the user where it
+/// went with a message box and exits. Also installs a logger so the report can include the last
+/// log lines, unless the application already set one.
+pub fn install(directory: impl Into<PathBuf>) {
+    if let Ok(mut state) = STATE.lock() {
+        state.directory = Some(directory.into());
+    }
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Info);
+    }
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        let backtrace = Backtrace::force_capture();
+        match write_report(&info.to_string(), &backtrace) {
+            Ok(path) => message_box(&format!(
+                "The engine crashed and has to close.\n\nA crash report was written to {}",
+                path.display()
+            )),
+            Err(e) => {
+                eprintln!("Failed to write a crash report: {}", e);
+                message_box("The engine crashed and has to close.");
+            }
+        }
+        std::process::exit(101);
+    }));
+}
+
+pub fn set_adapter(adapter: &wgpu::AdapterInfo) {
+    if let Ok(mut state) = STATE.lock() {
+        state.adapter = Some(adapter.clone());
+    }
+}
+
+/// Call once a frame so the report says how far the engine got.
+pub fn frame(index: u64, frame_time: f32) {
+    if let Ok(mut state) = STATE.lock() {
+        state.frame = index;
+        state.frame_time = frame_time;
+    }
+}
+
+/// Adds a titled block of text to the report, replacing an earlier one with the same title.
+pub fn set_section(title: &str, text: impl Into<String>) {
+    if let Ok(mut state) = STATE.lock() {
+        let text = text.into();
+        match state.sections.iter_mut().find(|(name, _)| name == title) {
+            Some((_, existing)) => *existing = text,
+            None => state.sections.push((title.to_owned(), text)),
+        }
+    }
+}
+
+/// What the last rendered frame drew, listed with the frame in the report.
+pub fn set_render_stats(stats: RenderStats) {
+    if let Ok(mut state) = STATE.lock() {
+        state.render_stats = Some(stats);
+    }
+}
+
+/// The last presented frame as tightly packed RGBA8, saved next to the report as PNG.
+pub fn set_last_frame(width: u32, height: u32, rgba: Vec<u8>) {
+    if let Ok(mut state) = STATE.lock() {
+        state.last_frame = Some((width, height, rgba));
+    }
+}
+
+fn write_report(panic: &str, backtrace: &Backtrace) -> io::Result<PathBuf> {
+    // the panic may have happened while the state was locked, so don't wait for it
+    let state = STATE.try_lock().map_err(|_| io::Error::other("crash state is locked"))?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let directory = state.directory.as_deref().unwrap_or(Path::new("crash_reports"));
+    std::fs::create_dir_all(directory)?;
+    let path = directory.join(format!("crash-{}.txt", timestamp));
+
+    let mut report = String::new();
+    writeln!(report, "Crash report, {} seconds since the Unix epoch", timestamp).ok();
+    writeln!(report, "Thread: {}", std::thread::current().name().unwrap_or("unnamed")).ok();
+    writeln!(report, "{}", panic).ok();
+
+    writeln!(report, "\n== Adapter ==").ok();
+    match &state.adapter {
+        Some(adapter) => {
+            writeln!(report, "{} ({:?}, {:?})", adapter.name, adapter.device_type, adapter.backend).ok();
+            writeln!(report, "vendor {:#06x}, device {:#06x}", adapter.vendor, adapter.device).ok();
+        }
+        None => {
+            writeln!(report, "not created yet").ok();
+        }
+    }
+
+    writeln!(report, "\n== Frame ==").ok();
+    writeln!(report, "frame {}, last frame time {:.3} ms", state.frame, state.frame_time * 1000.0).ok();
+    if let Some(stats) = &state.render_stats {
+        for (name, value) in stats.counters() {
+            writeln!(report, "{}: {}", name, value).ok();
+        }
+    }
+    for (title, text) in &state.sections {
+        writeln!(report, "\n== {} ==\n{}", title, text.trim_end()).ok();
+    }
+
+    writeln!(report, "\n== Recent log ==").ok();
+    match LOG.try_lock() {
+        Ok(log) => log.iter().for_each(|line| {
+            writeln!(report, "{}", line).ok();
+        }),
+        Err(_) => {
+            writeln!(report, "unavailable").ok();
+        }
+    }
+
+    if let Some((width, height, rgba)) = &state.last_frame {
+        let image = path.with_extension("png");
+        match write_png(&image, *width, *height, rgba) {
+            Ok(()) => writeln!(report, "\nLast frame saved to {}", image.display()).ok(),
+            Err(e) => writeln!(report, "\nFailed to save the last frame: {}", e).ok(),
+        };
+    }
+
+    writeln!(report, "\n== Backtrace ==\n{}", backtrace).ok();
+    std::fs::write(&path, report)?;
+    Ok(path)
+}
+
+/// Writes tightly packed RGBA8 pixels as an uncompressed PNG.
+pub fn write_png(path: &Path, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    let row = width as usize * 4;
+    if width == 0 || height == 0 || rgba.len() != row * height as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "pixel data doesn't match the image size"));
+    }
+    // every row starts with filter type 0, none
+    let mut raw = Vec::with_capacity((row + 1) * height as usize);
+    for pixels in rgba.chunks_exact(row) {
+        raw.push(0);
+        raw.extend_from_slice(pixels);
+    }
+    // zlib stream of stored deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.len().div_ceil(0xffff);
+    for (index, block) in raw.chunks(0xffff).enumerate() {
+        let length = block.len() as u16;
+        zlib.push((index + 1 == blocks) as u8);
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel RGBA, default compression and filtering, not interlaced
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, data) in [(b"IHDR", &header), (b"IDAT", &zlib), (b"IEND", &Vec::new())] {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+    std::fs::write(path, png)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(windows)]
+fn message_box(text: &str) {
+    #[link(name = "user32")]
+    extern "system" {
+        fn MessageBoxW(window: *mut std::ffi::c_void, text: *const u16, caption: *const u16, kind: u32) -> i32;
+    }
+    let wide = |text: &str| text.encode_utf16().chain(std::iter::once(0)).collect::<Vec<u16>>();
+    const MB_ICONERROR: u32 = 0x10;
+    unsafe {
+        MessageBoxW(std::ptr::null_mut(), wide(text).as_ptr(), wide("Crash").as_ptr(), MB_ICONERROR);
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn message_box(text: &str) {
+    let script = format!("display alert \"Crash\" message {:?} as critical", text);
+    std::process::Command::new("osascript").args(["-e", &script]).status().ok();
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn message_box(text: &str) {
+    // whichever dialog tool the desktop has; the report is on disk either way
+    let tools: [(&str, &[&str]); 3] = [
+        ("zenity", &["--error", "--title=Crash", "--text"]),
+        ("kdialog", &["--title", "Crash", "--error"]),
+        ("xmessage", &["-center"]),
+    ];
+    for (tool, args) in tools {
+        // a tool that is missing or can't open a window fails, so the next one gets a go
+        if std::process::Command::new(tool).args(args).arg(text).status().is_ok_and(|status| status.success()) {
+            return;
+        }
+    }
+}
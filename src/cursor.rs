@@ -0,0 +1,122 @@
+use winit::{
+    event::{Event, WindowEvent},
+    window::Window,
+};
+
+pub use winit::window::CursorIcon;
+
+use crate::sprite::UvRect;
+
+/// A cursor image drawn at the pointer in software, since winit can only show the system's
+/// cursor icons. The hardware cursor is hidden while one is active.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CustomCursor {
+    /// The texture's id among the UI renderer's user textures.
+    pub texture: u64,
+    pub uv: UvRect,
+    /// Size in logical pixels.
+    pub size: [f32; 2],
+    /// The point that sits under the pointer, in logical pixels from the image's top left.
+    pub hotspot: [f32; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cursor {
+    Icon(CursorIcon),
+    Custom(CustomCursor),
+    Hidden,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Cursor::Icon(CursorIcon::Default)
+    }
+}
+
+/// Decides which cursor the window shows. Game code picks one with `set`; while the pointer
+/// is over the UI, whatever the UI asks for wins, so text fields still get a caret and panel
+/// edges a resize arrow on top of a game's custom crosshair.
+#[derive(Debug, Default)]
+pub struct Cursors {
+    game: Cursor,
+    active: Cursor,
+    /// What was last applied to the window: the icon, or `None` while it is hidden.
+    applied: Option<Option<CursorIcon>>,
+    /// Pointer position in physical pixels, `None` while it is outside the window.
+    position: Option<(f64, f64)>,
+}
+
+impl Cursors {
+    pub fn new() -> Self {
+        Cursors::default()
+    }
+
+    /// The cursor to show while the UI doesn't ask for one. Stays until changed.
+    pub fn set(&mut self, cursor: Cursor) {
+        self.game = cursor;
+    }
+
+    pub fn handle_event<E>(&mut self, event: &Event<'_, E>) {
+        if let Event::WindowEvent { event, .. } = event {
+            match event {
+                WindowEvent::CursorMoved { position, .. } => self.position = Some((position.x, position.y)),
+                WindowEvent::CursorLeft { .. } => self.position = None,
+                _ => {}
+            }
+        }
+    }
+
+    /// Picks between the UI's request from this frame, `None` if it has no preference, and the
+    /// game's cursor, then updates the window if that changed what it should show.
+    pub fn apply(&mut self, window: &Window, ui: Option<Cursor>) {
+        self.active = ui.unwrap_or(self.game);
+        let icon = match self.active {
+            Cursor::Icon(icon) => Some(icon),
+            Cursor::Custom(_) | Cursor::Hidden => None,
+        };
+        if self.applied == Some(icon) {
+            return;
+        }
+        match icon {
+            Some(icon) => {
+                window.set_cursor_icon(icon);
+                window.set_cursor_visible(true);
+            }
+            None => window.set_cursor_visible(false),
+        }
+        self.applied = Some(icon);
+    }
+
+    /// The cursor shown since the last `apply`.
+    pub fn active(&self) -> Cursor {
+        self.active
+    }
+
+    /// The custom cursor to draw and where its top left goes in physical pixels, if one is
+    /// active and the pointer is in the window.
+    pub fn software_cursor(&self, scale_factor: f64) -> Option<(CustomCursor, [f32; 2])> {
+        let Cursor::Custom(cursor) = self.active else {
+            return None;
+        };
+        let (x, y) = self.position?;
+        let scale = scale_factor as f32;
+        Some((cursor, [x as f32 - cursor.hotspot[0] * scale, y as f32 - cursor.hotspot[1] * scale]))
+    }
+
+    /// Draws the software cursor on top of everything else in the UI.
+    #[cfg(feature = "egui")]
+    pub fn paint(&self, context: &egui::CtxRef) {
+        let pixels_per_point = context.pixels_per_point();
+        let Some((cursor, [x, y])) = self.software_cursor(pixels_per_point as f64) else {
+            return;
+        };
+        let min = egui::pos2(x / pixels_per_point, y / pixels_per_point);
+        let rect = egui::Rect::from_min_size(min, egui::vec2(cursor.size[0], cursor.size[1]));
+        let uv = egui::Rect::from_min_max(egui::pos2(cursor.uv.min[0], cursor.uv.min[1]), egui::pos2(cursor.uv.max[0], cursor.uv.max[1]));
+        let mut mesh = egui::epaint::Mesh::with_texture(egui::TextureId::User(cursor.texture));
+        mesh.add_rect_with_uv(rect, uv, egui::Color32::WHITE);
+        context
+            .layer_painter(egui::LayerId::new(egui::Order::Debug, egui::Id::new("software cursor")))
+            .add(egui::Shape::mesh(mesh));
+    }
+}
@@ -0,0 +1,175 @@
+use glam::{Mat4, Vec3};
+
+use crate::dynamic_buffer::{DynamicBuffer, DynamicSlice, FRAMES_IN_FLIGHT};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+impl LineVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Line segments collected during a frame, for visualizing things that have no mesh: IK
+/// targets, probe bounds, paths.
+#[derive(Clone, Debug, Default)]
+pub struct DebugLines {
+    vertices: Vec<LineVertex>,
+}
+
+impl DebugLines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: [f32; 4]) {
+        self.vertices.push(LineVertex {
+            position: from.into(),
+            color,
+        });
+        self.vertices.push(LineVertex { position: to.into(), color });
+    }
+
+    /// Three axis-aligned strokes of `size` centered on `position`.
+    pub fn cross(&mut self, position: Vec3, size: f32, color: [f32; 4]) {
+        let half = size * 0.5;
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.line(position - axis * half, position + axis * half, color);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    pub fn vertices(&self) -> &[LineVertex] {
+        &self.vertices
+    }
+}
+
+/// Draws `DebugLines` into an existing scene pass, depth tested against the scene without
+/// writing depth.
+pub struct DebugLineRenderer {
+    pipeline: wgpu::RenderPipeline,
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    vertices: DynamicBuffer,
+    lines: Option<DynamicSlice>,
+    count: u32,
+}
+
+impl DebugLineRenderer {
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat, depth_format: Option<wgpu::TextureFormat>) -> Self {
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("shaders/debug_lines.wgsl"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("debug lines layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug lines pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("debug lines"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[LineVertex::layout()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            multiview: None,
+        });
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("debug lines view"),
+            size: std::mem::size_of::<Mat4>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("debug lines bind group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+        });
+
+        DebugLineRenderer {
+            pipeline,
+            uniform,
+            bind_group,
+            vertices: DynamicBuffer::new(device, "debug lines", wgpu::BufferUsages::VERTEX, FRAMES_IN_FLIGHT),
+            lines: None,
+            count: 0,
+        }
+    }
+
+    /// Uploads this frame's lines; call before starting the pass `draw` records into.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view_projection: Mat4, lines: &DebugLines) {
+        self.count = lines.vertices.len() as u32;
+        self.vertices.begin_frame();
+        self.lines = None;
+        if lines.is_empty() {
+            return;
+        }
+        self.lines = Some(self.vertices.push(&lines.vertices));
+        self.vertices.finish(device, queue);
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&view_projection));
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        let Some(vertices) = self.lines.and_then(|lines| self.vertices.slice(lines)) else {
+            return;
+        };
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, vertices);
+        pass.draw(0..self.count, 0..1);
+    }
+}
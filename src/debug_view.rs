@@ -0,0 +1,312 @@
+use std::{cell::Cell, num::NonZeroU64, rc::Rc};
+
+use crate::{
+    lights::MAX_LIGHTS,
+    postprocess::{fullscreen_module, uniform_entry, FullscreenPipeline},
+    readback::Readbacks,
+};
+
+/// WGSL with `debug_output`, which the mesh shaders return instead of their color in debug
+/// variants. `MeshRenderer` prepends it together with the view's `#ifdef` name.
+pub const DEBUG_VIEW_SHADER: &str = include_str!("shaders/debug_view.wgsl");
+
+/// Values are counted into this many bins, the last one holding everything above.
+pub const BIN_COUNT: usize = 16;
+
+const WORKGROUP_SIZE: u32 = 16;
+
+/// What the meshes show instead of their shading.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DebugView {
+    #[default]
+    Off,
+    /// How many fragments each pixel is drawn by, depth test off, from blue (once) to white.
+    Overdraw,
+    /// How many lights reach each surface.
+    LightCount,
+    /// The directional light's cascade each surface is shadowed by, tinting the lit scene.
+    ShadowCascades,
+    /// The mip level the albedo texture is sampled at, or a 1024 texel texture's without one,
+    /// tinting the lit scene.
+    MipLevels,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 5] = [
+        DebugView::Off,
+        DebugView::Overdraw,
+        DebugView::LightCount,
+        DebugView::ShadowCascades,
+        DebugView::MipLevels,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugView::Off => "Off",
+            DebugView::Overdraw => "Overdraw",
+            DebugView::LightCount => "Light count",
+            DebugView::ShadowCascades => "Shadow cascades",
+            DebugView::MipLevels => "Mip levels",
+        }
+    }
+
+    /// The `#ifdef` name `DEBUG_VIEW_SHADER` switches on, besides `DEBUG_VIEW`.
+    pub fn shader_feature(self) -> Option<&'static str> {
+        match self {
+            DebugView::Off => None,
+            DebugView::Overdraw => Some("DEBUG_OVERDRAW"),
+            DebugView::LightCount => Some("DEBUG_LIGHT_COUNT"),
+            DebugView::ShadowCascades => Some("DEBUG_SHADOW_CASCADES"),
+            DebugView::MipLevels => Some("DEBUG_MIP_LEVELS"),
+        }
+    }
+
+    /// Whether the view is drawn over the normally shaded frame rather than replacing it.
+    pub fn tints_scene(self) -> bool {
+        matches!(self, DebugView::ShadowCascades | DebugView::MipLevels)
+    }
+
+    // where the heat ramp reaches white
+    fn max_value(self) -> f32 {
+        match self {
+            DebugView::Overdraw => 8.0,
+            DebugView::LightCount => MAX_LIGHTS as f32,
+            _ => 10.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    view: u32,
+    bins: u32,
+    max_value: f32,
+    opacity: f32,
+}
+
+/// Debug visualizations of the frame's meshes. `MeshRenderer::render_debug_view` draws one
+/// value per pixel into a `FORMAT` texture; `render` then histograms them in a compute pass and
+/// colors them over the frame. The histogram is read back for the debug menu a few frames later.
+pub struct DebugViews {
+    pub view: DebugView,
+    uniform: wgpu::Buffer,
+    histogram: Option<wgpu::Buffer>,
+    layout: wgpu::BindGroupLayout,
+    histogram_pipeline: Option<wgpu::ComputePipeline>,
+    resolve: FullscreenPipeline,
+    readbacks: Readbacks,
+    counts: Rc<Cell<[u32; BIN_COUNT]>>,
+}
+
+impl DebugViews {
+    /// Format of the value target: the value in red, coverage in green.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+    /// Without `compute` support the views are still drawn, without a histogram.
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat, compute: bool) -> Self {
+        let module = fullscreen_module(device, "debug view", include_str!("shaders/debug_view_resolve.wgsl"));
+        let visibility = if compute {
+            wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE
+        } else {
+            wgpu::ShaderStages::FRAGMENT
+        };
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                visibility,
+                ..uniform_entry(0)
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ];
+        if compute {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: NonZeroU64::new(BIN_COUNT as u64 * 4),
+                },
+                count: None,
+            });
+        }
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("debug view layout"),
+            entries: &entries,
+        });
+        let resolve = FullscreenPipeline::new(
+            device,
+            "debug view",
+            &module,
+            "fs_main",
+            &[&layout],
+            output_format,
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+        );
+        let histogram_pipeline = compute.then(|| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("debug view histogram layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("debug view histogram"),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: "build_histogram",
+            })
+        });
+
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("debug view params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let histogram = compute.then(|| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("debug view histogram"),
+                size: BIN_COUNT as u64 * 4,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            })
+        });
+
+        DebugViews {
+            view: DebugView::Off,
+            uniform,
+            histogram,
+            layout,
+            histogram_pipeline,
+            resolve,
+            readbacks: Readbacks::new(),
+            counts: Rc::new(Cell::new([0; BIN_COUNT])),
+        }
+    }
+
+    // `values` comes from the frame's transient textures, so it is bound anew each frame
+    fn bind_group(&self, device: &wgpu::Device, values: &wgpu::TextureView) -> wgpu::BindGroup {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: self.uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(values),
+            },
+        ];
+        entries.extend(self.histogram.as_ref().map(|histogram| wgpu::BindGroupEntry {
+            binding: 2,
+            resource: histogram.as_entire_binding(),
+        }));
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("debug view bind group"),
+            layout: &self.layout,
+            entries: &entries,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.view != DebugView::Off
+    }
+
+    /// Histograms `values`, `size` texels drawn by `MeshRenderer::render_debug_view`, and colors
+    /// them over `output`, clearing it first with `clear`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        values: &wgpu::TextureView,
+        (width, height): (u32, u32),
+        output: &wgpu::TextureView,
+        clear: Option<wgpu::Color>,
+    ) {
+        let params = Params {
+            view: self.view as u32,
+            bins: BIN_COUNT as u32,
+            max_value: self.view.max_value(),
+            opacity: if self.view.tints_scene() { 0.4 } else { 1.0 },
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&params));
+        let bind_group = self.bind_group(device, values);
+
+        if let (Some(pipeline), Some(histogram)) = (&self.histogram_pipeline, &self.histogram) {
+            queue.write_buffer(histogram, 0, &[0; BIN_COUNT * 4]);
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("debug view histogram"),
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch(width.div_ceil(WORKGROUP_SIZE), height.div_ceil(WORKGROUP_SIZE), 1);
+            drop(pass);
+
+            // one readback at a time is plenty for a debug readout
+            if self.readbacks.pending() == 0 {
+                let counts = self.counts.clone();
+                let size = BIN_COUNT as u64 * 4;
+                self.readbacks.read_buffer(device, encoder, histogram, 0, size, move |result| match result {
+                    Ok(bytes) => counts.set(bytemuck::pod_read_unaligned(&bytes)),
+                    Err(e) => log::error!("Failed to read back the debug view histogram: {}", e),
+                });
+            }
+        }
+
+        self.resolve.draw(encoder, output, &[&bind_group], clear);
+    }
+
+    /// Call right after submitting the frame `render` recorded into.
+    pub fn submitted(&mut self) {
+        self.readbacks.submitted();
+    }
+
+    /// Picks up finished histogram readbacks. Call once per frame.
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        self.readbacks.poll(device);
+    }
+
+    /// Covered pixels per value, as of a few frames ago: overdraw count, light count, cascade
+    /// (0 past the last) or mip level.
+    pub fn histogram(&self) -> [u32; BIN_COUNT] {
+        self.counts.get()
+    }
+
+    #[cfg(feature = "egui")]
+    /// The debug menu: picks the view and shows its histogram.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Debug view")
+            .selected_text(self.view.name())
+            .show_ui(ui, |ui| {
+                for view in DebugView::ALL {
+                    ui.selectable_value(&mut self.view, view, view.name());
+                }
+            });
+        if !self.is_enabled() || self.histogram_pipeline.is_none() {
+            return;
+        }
+        let counts = self.histogram();
+        let total = counts.iter().sum::<u32>().max(1);
+        for (value, &count) in counts.iter().enumerate().filter(|(_, &count)| count > 0) {
+            let label = if value == BIN_COUNT - 1 {
+                format!("{}+", value)
+            } else {
+                value.to_string()
+            };
+            ui.add(
+                egui::ProgressBar::new(count as f32 / total as f32)
+                    .text(format!("{}: {:.1}%", label, 100.0 * count as f32 / total as f32)),
+            );
+        }
+    }
+}
@@ -0,0 +1,134 @@
+/// How many frames a `DynamicBuffer` cycles through by default: the frame being recorded plus
+/// the ones the GPU may still be reading.
+pub const FRAMES_IN_FLIGHT: usize = 3;
+
+/// Where one `push` landed in the current frame's buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DynamicSlice {
+    pub offset: wgpu::BufferAddress,
+    pub size: wgpu::BufferAddress,
+}
+
+impl DynamicSlice {
+    /// The offset to pass for a binding with `has_dynamic_offset`.
+    pub fn dynamic_offset(&self) -> u32 {
+        self.offset as u32
+    }
+}
+
+/// A bump allocator for data that only lives for one frame: debug line vertices, sprite and
+/// particle instances, per-draw uniforms. Each frame packs its allocations into one of several
+/// buffers, round robin, so writing this frame's data never waits on the GPU still reading an
+/// earlier frame's, and buffers are only created when a frame needs more room than before.
+///
+/// wgpu has no persistently mapped buffers; allocations are staged on the CPU and uploaded with
+/// a single `write_buffer` in `finish`.
+pub struct DynamicBuffer {
+    label: String,
+    usage: wgpu::BufferUsages,
+    alignment: wgpu::BufferAddress,
+    buffers: Vec<Option<wgpu::Buffer>>,
+    capacities: Vec<wgpu::BufferAddress>,
+    current: usize,
+    staging: Vec<u8>,
+}
+
+impl DynamicBuffer {
+    /// `usage` is what the data is bound as, e.g. `VERTEX` or `UNIFORM`; allocations are aligned
+    /// to what the device requires for offsets into it.
+    pub fn new(device: &wgpu::Device, label: impl Into<String>, usage: wgpu::BufferUsages, frames: usize) -> Self {
+        let limits = device.limits();
+        let mut alignment = wgpu::COPY_BUFFER_ALIGNMENT;
+        if usage.contains(wgpu::BufferUsages::UNIFORM) {
+            alignment = alignment.max(limits.min_uniform_buffer_offset_alignment as u64);
+        }
+        if usage.contains(wgpu::BufferUsages::STORAGE) {
+            alignment = alignment.max(limits.min_storage_buffer_offset_alignment as u64);
+        }
+        let frames = frames.max(1);
+        DynamicBuffer {
+            label: label.into(),
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            alignment,
+            buffers: (0..frames).map(|_| None).collect(),
+            capacities: vec![0; frames],
+            current: 0,
+            staging: Vec::new(),
+        }
+    }
+
+    /// Moves on to the next frame's buffer and forgets the previous frame's allocations.
+    pub fn begin_frame(&mut self) {
+        self.current = (self.current + 1) % self.buffers.len();
+        self.staging.clear();
+    }
+
+    pub fn push<T: bytemuck::Pod>(&mut self, data: &[T]) -> DynamicSlice {
+        self.push_bytes(bytemuck::cast_slice(data))
+    }
+
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> DynamicSlice {
+        let offset = (self.staging.len() as u64).next_multiple_of(self.alignment);
+        self.staging.resize(offset as usize, 0);
+        self.staging.extend_from_slice(bytes);
+        DynamicSlice {
+            offset,
+            size: bytes.len() as u64,
+        }
+    }
+
+    /// Bytes allocated so far this frame, including alignment padding.
+    pub fn len(&self) -> wgpu::BufferAddress {
+        self.staging.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staging.is_empty()
+    }
+
+    /// Bytes of every frame's buffer together, for memory tracking.
+    pub fn capacity(&self) -> wgpu::BufferAddress {
+        self.capacities.iter().sum()
+    }
+
+    /// Uploads this frame's allocations, growing the frame's buffer first if they don't fit.
+    /// Call once everything is pushed and before binding anything from `buffer`; bind groups
+    /// referring to it must be created after this, since growing replaces the buffer.
+    pub fn finish(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.staging.is_empty() {
+            return;
+        }
+        let size = (self.staging.len() as u64).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+        self.staging.resize(size as usize, 0);
+        if size > self.capacities[self.current] {
+            let capacity = size.next_power_of_two();
+            self.buffers[self.current] = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&self.label),
+                size: capacity,
+                usage: self.usage,
+                mapped_at_creation: false,
+            }));
+            self.capacities[self.current] = capacity;
+        }
+        if let Some(buffer) = &self.buffers[self.current] {
+            queue.write_buffer(buffer, 0, &self.staging);
+        }
+    }
+
+    /// The current frame's buffer, or `None` before the first `finish` that had data.
+    pub fn buffer(&self) -> Option<&wgpu::Buffer> {
+        self.buffers[self.current].as_ref()
+    }
+
+    pub fn slice(&self, slice: DynamicSlice) -> Option<wgpu::BufferSlice<'_>> {
+        self.buffer().map(|buffer| buffer.slice(slice.offset..slice.offset + slice.size))
+    }
+
+    pub fn binding(&self, slice: DynamicSlice) -> Option<wgpu::BufferBinding<'_>> {
+        self.buffer().map(|buffer| wgpu::BufferBinding {
+            buffer,
+            offset: slice.offset,
+            size: wgpu::BufferSize::new(slice.size),
+        })
+    }
+}
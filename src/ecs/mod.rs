@@ -0,0 +1,401 @@
+mod schedule;
+mod snapshot;
+mod storage;
+mod tags;
+
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use snapshot::SnapshotType;
+use storage::{ErasedStorage, Storage};
+use tags::{ErasedTags, Names};
+pub use schedule::{Schedule, ScheduleError, System, SystemContext};
+pub use snapshot::{SnapshotHistory, WorldSnapshot};
+pub use tags::Tag;
+
+/// Anything that can be attached to an entity. Implemented for every thread-safe type, so
+/// components are plain structs without registration.
+pub trait Component: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Component for T {}
+
+/// A handle to a game object. Handles of despawned entities stay invalid even after their slot
+/// is reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    /// Slot of the entity, unique among the live ones; for indexing side tables.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+/// A point in the world's history of changes, from `World::change_tick`. The default is before
+/// everything, so a system's first look sees every component as added.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Tick(u64);
+
+/// The world's current tick. Starts after `Tick::default()`.
+struct ChangeTick(AtomicU64);
+
+impl Default for ChangeTick {
+    fn default() -> Self {
+        ChangeTick(AtomicU64::new(1))
+    }
+}
+
+impl ChangeTick {
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Mutable access to a component that marks it changed when it is written through, so reading
+/// with `get_mut` alone doesn't count as a change.
+pub struct Mut<'a, T> {
+    value: &'a mut T,
+    changed: &'a mut u64,
+    tick: u64,
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        *self.changed = self.tick;
+        self.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// Components spawned together, e.g. `(Transform::default(), MeshInstance::new(mesh))`.
+/// Implemented for tuples of up to eight components.
+pub trait Bundle {
+    fn insert_into(self, world: &mut World, entity: Entity);
+}
+
+macro_rules! impl_bundle {
+    ($($name:ident),*) => {
+        impl<$($name: Component),*> Bundle for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn insert_into(self, world: &mut World, entity: Entity) {
+                let ($($name,)*) = self;
+                $(world.insert(entity, $name);)*
+            }
+        }
+    };
+}
+
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
+
+/// Every entity and its components. Each component type is stored densely, so queries iterate
+/// packed arrays; queries over several types walk the first and look the others up. Entities
+/// can also be named and tagged, with indexes to find them by either.
+///
+/// Components remember when they were added and last written to, and removals are logged, so
+/// systems can work on just what changed: keep the `Tick` from `change_tick` and pass it to
+/// `query_added`, `query_changed` or `removed` next time.
+///
+/// The whole state can be copied into a `WorldSnapshot` and restored later, for the component
+/// types registered with `snapshot_component`.
+#[derive(Default)]
+pub struct World {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    count: usize,
+    storages: HashMap<TypeId, Box<dyn ErasedStorage>>,
+    names: Names,
+    tags: HashMap<TypeId, Box<dyn ErasedTags>>,
+    tick: ChangeTick,
+    frame_start: u64,
+    snapshot_types: HashMap<TypeId, SnapshotType>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new entity without components.
+    pub fn spawn_empty(&mut self) -> Entity {
+        self.count += 1;
+        if let Some(index) = self.free.pop() {
+            self.alive[index as usize] = true;
+            return Entity {
+                index,
+                generation: self.generations[index as usize],
+            };
+        }
+        self.generations.push(0);
+        self.alive.push(true);
+        Entity {
+            index: self.generations.len() as u32 - 1,
+            generation: 0,
+        }
+    }
+
+    pub fn spawn(&mut self, bundle: impl Bundle) -> Entity {
+        let entity = self.spawn_empty();
+        bundle.insert_into(self, entity);
+        entity
+    }
+
+    /// Removes `entity` and all its components. Returns false if it was already gone.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        let tick = self.tick.get();
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(entity, tick);
+        }
+        self.names.remove(entity);
+        for tags in self.tags.values_mut() {
+            tags.remove_entity(entity);
+        }
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(entity.index);
+        self.count -= 1;
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        self.alive.get(index).copied().unwrap_or(false) && self.generations[index] == entity.generation
+    }
+
+    /// Number of live entities.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Every live entity, in slot order.
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive.iter().enumerate().filter(|(_, alive)| **alive).map(|(index, _)| Entity {
+            index: index as u32,
+            generation: self.generations[index],
+        })
+    }
+
+    /// Despawns everything. Old handles stay invalid.
+    pub fn clear(&mut self) {
+        let entities: Vec<_> = self.entities().collect();
+        for entity in entities {
+            self.despawn(entity);
+        }
+        for storage in self.storages.values_mut() {
+            storage.clear();
+        }
+        self.names.clear();
+        for tags in self.tags.values_mut() {
+            tags.clear();
+        }
+    }
+
+    fn storage<T: Component>(&self) -> Option<&Storage<T>> {
+        let storage = self.storages.get(&TypeId::of::<T>())?;
+        storage.as_any().downcast_ref()
+    }
+
+    fn storage_mut<T: Component>(&mut self) -> Option<&mut Storage<T>> {
+        let storage = self.storages.get_mut(&TypeId::of::<T>())?;
+        storage.as_any_mut().downcast_mut()
+    }
+
+    /// Attaches `component` to `entity`, returning the one of the same type it replaced. Adding
+    /// to a despawned entity does nothing.
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) -> Option<T> {
+        if !self.is_alive(entity) {
+            log::warn!("Ignoring a {} added to despawned entity {}", type_name::<T>(), entity);
+            return None;
+        }
+        let tick = self.tick.get();
+        let storage = self
+            .storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Storage::<T>::new()));
+        storage.as_any_mut().downcast_mut::<Storage<T>>().unwrap().insert(entity, component, tick)
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let tick = self.tick.get();
+        self.storage_mut::<T>()?.remove(entity, tick)
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+        self.storage::<T>()?.get(entity)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<Mut<'_, T>> {
+        let tick = self.tick.get();
+        let (value, changed) = self.storage_mut::<T>()?.get_mut(entity)?;
+        Some(Mut { value, changed, tick })
+    }
+
+    pub fn has<T: Component>(&self, entity: Entity) -> bool {
+        self.storage::<T>().is_some_and(|storage| storage.contains(entity))
+    }
+
+    /// Number of entities with a `T`.
+    pub fn count<T: Component>(&self) -> usize {
+        self.storage::<T>().map_or(0, |storage| storage.components.len())
+    }
+
+    /// Every entity with a `T`, in no particular order.
+    pub fn query<T: Component>(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.storage::<T>()
+            .into_iter()
+            .flat_map(|storage| storage.entities.iter().copied().zip(&storage.components))
+    }
+
+    pub fn query_mut<T: Component>(&mut self) -> impl Iterator<Item = (Entity, Mut<'_, T>)> {
+        let tick = self.tick.get();
+        self.storage_mut::<T>()
+            .into_iter()
+            .flat_map(move |storage| storage.iter_mut(tick))
+    }
+
+    /// Every entity with both an `A` and a `B`. Put the rarer component first, it is the one
+    /// iterated.
+    pub fn query2<A: Component, B: Component>(&self) -> impl Iterator<Item = (Entity, &A, &B)> {
+        let b = self.storage::<B>();
+        self.query::<A>()
+            .filter_map(move |(entity, a)| Some((entity, a, b?.get(entity)?)))
+    }
+
+    /// Like `query2`, with the `A`s mutable, e.g. `query2_mut::<Transform, Velocity>()` to move
+    /// things. Panics if `A` and `B` are the same type.
+    pub fn query2_mut<A: Component, B: Component>(&mut self) -> impl Iterator<Item = (Entity, Mut<'_, A>, &B)> {
+        assert_ne!(
+            TypeId::of::<A>(),
+            TypeId::of::<B>(),
+            "query2_mut::<{0}, {0}> would alias",
+            type_name::<A>()
+        );
+        let storages = match self.storages.get_disjoint_mut([&TypeId::of::<A>(), &TypeId::of::<B>()]) {
+            [Some(a), Some(b)] => {
+                let a = a.as_any_mut().downcast_mut::<Storage<A>>().unwrap();
+                let b = b.as_any().downcast_ref::<Storage<B>>().unwrap();
+                Some((a, b))
+            }
+            _ => None,
+        };
+        let tick = self.tick.get();
+        storages.into_iter().flat_map(move |(a, b)| {
+            a.iter_mut(tick)
+                .filter_map(move |(entity, component)| Some((entity, component, b.get(entity)?)))
+        })
+    }
+
+    /// Every entity with an `A`, a `B` and a `C`.
+    pub fn query3<A: Component, B: Component, C: Component>(&self) -> impl Iterator<Item = (Entity, &A, &B, &C)> {
+        let c = self.storage::<C>();
+        self.query2::<A, B>()
+            .filter_map(move |(entity, a, b)| Some((entity, a, b, c?.get(entity)?)))
+    }
+}
+
+impl World {
+    /// The tick to compare against next time, e.g. at the end of a system that looked at what
+    /// changed. Changes made after this call are newer than it.
+    pub fn change_tick(&self) -> Tick {
+        Tick(self.tick.0.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Every `T` added since `since`.
+    pub fn query_added<T: Component>(&self, since: Tick) -> impl Iterator<Item = (Entity, &T)> {
+        self.storage::<T>().into_iter().flat_map(move |storage| {
+            (0..storage.entities.len())
+                .filter(move |&index| storage.added[index] > since.0)
+                .map(|index| (storage.entities[index], &storage.components[index]))
+        })
+    }
+
+    /// Every `T` added, replaced or written through a `Mut` since `since`.
+    pub fn query_changed<T: Component>(&self, since: Tick) -> impl Iterator<Item = (Entity, &T)> {
+        self.storage::<T>().into_iter().flat_map(move |storage| {
+            (0..storage.entities.len())
+                .filter(move |&index| storage.changed[index] > since.0)
+                .map(|index| (storage.entities[index], &storage.components[index]))
+        })
+    }
+
+    /// Entities that lost their `T` since `since`, including by being despawned. The log only
+    /// reaches back to the start of the previous frame, see `end_frame`.
+    pub fn removed<T: Component>(&self, since: Tick) -> impl Iterator<Item = Entity> + '_ {
+        self.storage::<T>()
+            .into_iter()
+            .flat_map(move |storage| storage.removed.iter().filter(move |(_, tick)| *tick > since.0))
+            .map(|&(entity, _)| entity)
+    }
+
+    pub fn is_added<T: Component>(&self, entity: Entity, since: Tick) -> bool {
+        self.storage::<T>()
+            .and_then(|storage| storage.dense_index(entity).map(|index| storage.added[index]))
+            .is_some_and(|tick| tick > since.0)
+    }
+
+    pub fn is_changed<T: Component>(&self, entity: Entity, since: Tick) -> bool {
+        self.storage::<T>()
+            .and_then(|storage| storage.dense_index(entity).map(|index| storage.changed[index]))
+            .is_some_and(|tick| tick > since.0)
+    }
+
+    /// Forgets removals from before the previous frame. The engine calls it once a frame, so
+    /// systems that look every frame never miss one.
+    pub fn end_frame(&mut self) {
+        for storage in self.storages.values_mut() {
+            storage.trim_removed(self.frame_start);
+        }
+        self.frame_start = self.tick.get();
+    }
+}
+
+impl fmt::Debug for World {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("World")
+            .field("entities", &self.count)
+            .field("component_types", &self.storages.len())
+            .finish()
+    }
+}
@@ -0,0 +1,547 @@
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use super::{storage::ErasedStorage, Component, Entity, Mut, Storage, World};
+
+type Storages = HashMap<TypeId, Box<dyn ErasedStorage>>;
+type Command = Box<dyn FnOnce(&mut World) + Send>;
+type ParallelRun = Box<dyn FnMut(&mut SystemContext) + Send>;
+type ExclusiveRun = Box<dyn FnMut(&mut World, f32) + Send>;
+
+/// A component type a system touches.
+struct Access {
+    id: TypeId,
+    // only shown in the UI
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    name: &'static str,
+    // for writes the world has no storage for yet
+    new_storage: fn() -> Box<dyn ErasedStorage>,
+}
+
+impl Access {
+    fn of<T: Component>() -> Self {
+        Access {
+            id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+            new_storage: || Box::new(Storage::<T>::new()),
+        }
+    }
+}
+
+enum Run {
+    Parallel(ParallelRun),
+    Exclusive(ExclusiveRun),
+}
+
+/// A named piece of game logic and the component types it touches. Systems that don't write
+/// anything another one reads or writes can run at the same time, so the declarations have to
+/// be complete: a `SystemContext` panics on access that wasn't declared.
+pub struct System {
+    name: String,
+    reads: Vec<Access>,
+    writes: Vec<Access>,
+    after: Vec<String>,
+    before: Vec<String>,
+    run: Run,
+    last_time: Duration,
+}
+
+impl System {
+    pub fn new(name: impl Into<String>, run: impl FnMut(&mut SystemContext) + Send + 'static) -> Self {
+        Self::with_run(name.into(), Run::Parallel(Box::new(run)))
+    }
+
+    /// A system with the whole world to itself, for spawning, despawning and anything else that
+    /// can't be declared up front. It never runs alongside another.
+    pub fn exclusive(name: impl Into<String>, run: impl FnMut(&mut World, f32) + Send + 'static) -> Self {
+        Self::with_run(name.into(), Run::Exclusive(Box::new(run)))
+    }
+
+    fn with_run(name: String, run: Run) -> Self {
+        System {
+            name,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            after: Vec::new(),
+            before: Vec::new(),
+            run,
+            last_time: Duration::ZERO,
+        }
+    }
+
+    pub fn reads<T: Component>(mut self) -> Self {
+        self.reads.push(Access::of::<T>());
+        self
+    }
+
+    pub fn writes<T: Component>(mut self) -> Self {
+        self.writes.push(Access::of::<T>());
+        self
+    }
+
+    /// Runs after the system called `name`, even if they don't conflict.
+    pub fn after(mut self, name: impl Into<String>) -> Self {
+        self.after.push(name.into());
+        self
+    }
+
+    /// Runs before the system called `name`, even if they don't conflict.
+    pub fn before(mut self, name: impl Into<String>) -> Self {
+        self.before.push(name.into());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_exclusive(&self) -> bool {
+        matches!(self.run, Run::Exclusive(_))
+    }
+
+    fn conflicts(&self, other: &System) -> bool {
+        let writes_any = |system: &System, types: &[Access]| {
+            system.writes.iter().any(|write| types.iter().any(|other| write.id == other.id))
+        };
+        self.is_exclusive()
+            || other.is_exclusive()
+            || writes_any(self, &other.reads)
+            || writes_any(self, &other.writes)
+            || writes_any(other, &self.reads)
+    }
+}
+
+/// What a system sees of the world: queries over the types it declared, and a queue of
+/// structural changes applied once its batch is done.
+pub struct SystemContext<'w> {
+    world: &'w World,
+    writes: &'w mut Storages,
+    system: &'w str,
+    reads: &'w [Access],
+    commands: &'w mut Vec<Command>,
+    dt: f32,
+}
+
+impl SystemContext<'_> {
+    /// Seconds since the schedule last ran.
+    pub fn dt(&self) -> f32 {
+        self.dt
+    }
+
+    fn storage<T: Component>(&self) -> Option<&Storage<T>> {
+        let id = TypeId::of::<T>();
+        if let Some(storage) = self.writes.get(&id) {
+            return storage.as_any().downcast_ref();
+        }
+        self.check_read::<T>();
+        self.world.storage::<T>()
+    }
+
+    fn storage_mut<T: Component>(&mut self) -> &mut Storage<T> {
+        match self.writes.get_mut(&TypeId::of::<T>()) {
+            Some(storage) => storage.as_any_mut().downcast_mut().unwrap(),
+            None => panic!("system \"{}\" writes {} without declaring it", self.system, type_name::<T>()),
+        }
+    }
+
+    fn check_read<T: Component>(&self) {
+        if !self.reads.iter().any(|read| read.id == TypeId::of::<T>()) {
+            panic!("system \"{}\" reads {} without declaring it", self.system, type_name::<T>());
+        }
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+        self.storage::<T>()?.get(entity)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<Mut<'_, T>> {
+        let tick = self.world.tick.get();
+        let (value, changed) = self.storage_mut::<T>().get_mut(entity)?;
+        Some(Mut { value, changed, tick })
+    }
+
+    pub fn query<T: Component>(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.storage::<T>()
+            .into_iter()
+            .flat_map(|storage| storage.entities.iter().copied().zip(&storage.components))
+    }
+
+    pub fn query_mut<T: Component>(&mut self) -> impl Iterator<Item = (Entity, Mut<'_, T>)> {
+        let tick = self.world.tick.get();
+        self.storage_mut::<T>().iter_mut(tick)
+    }
+
+    /// Every entity with both, the `A`s mutable, like `World::query2_mut`.
+    pub fn query2_mut<A: Component, B: Component>(&mut self) -> impl Iterator<Item = (Entity, Mut<'_, A>, &B)> {
+        assert_ne!(
+            TypeId::of::<A>(),
+            TypeId::of::<B>(),
+            "query2_mut::<{0}, {0}> would alias",
+            type_name::<A>()
+        );
+        let tick = self.world.tick.get();
+        // B is read from the world unless this system writes it too
+        if !self.writes.contains_key(&TypeId::of::<B>()) {
+            self.check_read::<B>();
+        }
+        let (a, b) = match self.writes.get_disjoint_mut([&TypeId::of::<A>(), &TypeId::of::<B>()]) {
+            [Some(a), Some(b)] => (a, Some(b.as_any().downcast_ref::<Storage<B>>().unwrap())),
+            [Some(a), None] => (a, self.world.storage::<B>()),
+            [None, _] => panic!("system \"{}\" writes {} without declaring it", self.system, type_name::<A>()),
+        };
+        let a = a.as_any_mut().downcast_mut::<Storage<A>>().unwrap();
+        Some(a).zip(b).into_iter().flat_map(move |(a, b)| {
+            a.iter_mut(tick)
+                .filter_map(move |(entity, component)| Some((entity, component, b.get(entity)?)))
+        })
+    }
+
+    /// Runs `command` on the whole world after this system's batch, e.g. to spawn or despawn.
+    /// Commands run in schedule order.
+    pub fn defer(&mut self, command: impl FnOnce(&mut World) + Send + 'static) {
+        self.commands.push(Box::new(command));
+    }
+}
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    DuplicateName(String),
+    /// An ordering constraint names a system that isn't in the schedule.
+    UnknownSystem { system: String, constraint: String },
+    /// The systems whose ordering constraints go round in a circle.
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::DuplicateName(name) => write!(f, "two systems are called \"{}\"", name),
+            ScheduleError::UnknownSystem { system, constraint } => {
+                write!(f, "system \"{}\" is ordered against unknown system \"{}\"", system, constraint)
+            }
+            ScheduleError::Cycle(systems) => write!(f, "systems are ordered in a cycle: {}", systems.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+/// Systems run in the order they were added, except that ones without conflicting access
+/// are grouped into batches that run in parallel on scoped threads. `after` and `before`
+/// add ordering on top; conflicting systems keep their relative order.
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<System>,
+    batches: Option<Vec<Vec<usize>>>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, system: System) -> &mut Self {
+        self.systems.push(system);
+        self.batches = None;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.systems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.systems.is_empty()
+    }
+
+    /// Orders the systems and groups them into batches. `run` does this the first time after a
+    /// change; call it directly to check the constraints up front.
+    pub fn build(&mut self) -> Result<(), ScheduleError> {
+        let mut index = HashMap::new();
+        for (i, system) in self.systems.iter().enumerate() {
+            if index.insert(system.name.as_str(), i).is_some() {
+                return Err(ScheduleError::DuplicateName(system.name.clone()));
+            }
+        }
+        let lookup = |system: &System, name: &String| {
+            index.get(name.as_str()).copied().ok_or_else(|| ScheduleError::UnknownSystem {
+                system: system.name.clone(),
+                constraint: name.clone(),
+            })
+        };
+        let count = self.systems.len();
+        let mut successors = vec![Vec::new(); count];
+        for (i, system) in self.systems.iter().enumerate() {
+            for name in &system.after {
+                successors[lookup(system, name)?].push(i);
+            }
+            for name in &system.before {
+                successors[i].push(lookup(system, name)?);
+            }
+        }
+
+        // order by the constraints, otherwise by insertion
+        let mut incoming = vec![0; count];
+        for &next in successors.iter().flatten() {
+            incoming[next] += 1;
+        }
+        let mut order = Vec::with_capacity(count);
+        let mut placed = vec![false; count];
+        while order.len() < count {
+            let Some(next) = (0..count).find(|&i| !placed[i] && incoming[i] == 0) else {
+                let cycle = (0..count).filter(|&i| !placed[i]).map(|i| self.systems[i].name.clone()).collect();
+                return Err(ScheduleError::Cycle(cycle));
+            };
+            placed[next] = true;
+            order.push(next);
+            for &successor in &successors[next] {
+                incoming[successor] -= 1;
+            }
+        }
+
+        // each system goes one batch after the latest one it has to follow
+        let mut batch_of = vec![0; count];
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+        for (position, &i) in order.iter().enumerate() {
+            let mut batch = 0;
+            for &earlier in &order[..position] {
+                let constrained = successors[earlier].contains(&i);
+                if constrained || self.systems[earlier].conflicts(&self.systems[i]) {
+                    batch = batch.max(batch_of[earlier] + 1);
+                }
+            }
+            batch_of[i] = batch;
+            if batch == batches.len() {
+                batches.push(Vec::new());
+            }
+            batches[batch].push(i);
+        }
+        self.batches = Some(batches);
+        Ok(())
+    }
+
+    /// Runs every system once. Returns without running anything if the schedule can't be
+    /// built.
+    pub fn run(&mut self, world: &mut World, dt: f32) {
+        if self.batches.is_none() {
+            if let Err(e) = self.build() {
+                log::warn!("Not running the schedule: {}", e);
+                return;
+            }
+        }
+        let batches = self.batches.take().unwrap();
+        for batch in &batches {
+            self.run_batch(world, batch, dt);
+        }
+        self.batches = Some(batches);
+    }
+
+    fn run_batch(&mut self, world: &mut World, batch: &[usize], dt: f32) {
+        if let [single] = batch {
+            if let Run::Exclusive(run) = &mut self.systems[*single].run {
+                let start = Instant::now();
+                run(world, dt);
+                self.systems[*single].last_time = start.elapsed();
+                return;
+            }
+        }
+
+        // each system takes the storages it writes out of the world, so the rest can be shared
+        let mut parts: Vec<(&mut System, Storages, Vec<Command>)> = self
+            .systems
+            .iter_mut()
+            .enumerate()
+            .filter(|(i, _)| batch.contains(i))
+            .map(|(_, system)| {
+                let writes = system
+                    .writes
+                    .iter()
+                    .map(|write| {
+                        let storage = world.storages.remove(&write.id).unwrap_or_else(write.new_storage);
+                        (write.id, storage)
+                    })
+                    .collect();
+                (system, writes, Vec::new())
+            })
+            .collect();
+
+        let shared: &World = world;
+        std::thread::scope(|scope| {
+            let mut parts = parts.iter_mut();
+            let first = parts.next();
+            for part in parts {
+                scope.spawn(move || run_system(shared, part, dt));
+            }
+            if let Some(part) = first {
+                run_system(shared, part, dt);
+            }
+        });
+
+        let mut commands = Vec::new();
+        for (_, writes, system_commands) in parts {
+            world.storages.extend(writes);
+            commands.extend(system_commands);
+        }
+        for command in commands {
+            command(world);
+        }
+    }
+
+    /// The batches in the order they run, with each system's name and how long it took last
+    /// time.
+    pub fn batches(&self) -> impl Iterator<Item = Vec<(&str, Duration)>> + '_ {
+        self.batches.iter().flatten().map(|batch| {
+            batch
+                .iter()
+                .map(|&i| (self.systems[i].name.as_str(), self.systems[i].last_time))
+                .collect()
+        })
+    }
+
+    #[cfg(feature = "egui")]
+    /// The batches as rows of systems, for a debug panel. Hovering a system shows its access.
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        if self.batches.is_none() {
+            ui.label("Not built yet");
+            return;
+        }
+        for (number, batch) in self.batches.iter().flatten().enumerate() {
+            ui.horizontal_wrapped(|ui| {
+                ui.label(format!("{}:", number + 1));
+                for &i in batch {
+                    let system = &self.systems[i];
+                    let label = format!("{} {:.2} ms", system.name, system.last_time.as_secs_f64() * 1000.0);
+                    let button = if system.is_exclusive() {
+                        egui::Button::new(egui::RichText::new(label).color(egui::Color32::YELLOW))
+                    } else {
+                        egui::Button::new(label)
+                    };
+                    ui.add(button).on_hover_ui(|ui| {
+                        if system.is_exclusive() {
+                            ui.label("Exclusive");
+                        }
+                        for read in &system.reads {
+                            ui.label(format!("reads {}", read.name));
+                        }
+                        for write in &system.writes {
+                            ui.label(format!("writes {}", write.name));
+                        }
+                    });
+                }
+            });
+        }
+    }
+}
+
+fn run_system(world: &World, (system, writes, commands): &mut (&mut System, Storages, Vec<Command>), dt: f32) {
+    let Run::Parallel(run) = &mut system.run else {
+        unreachable!("exclusive systems run alone");
+    };
+    let start = Instant::now();
+    run(&mut SystemContext {
+        world,
+        writes,
+        system: &system.name,
+        reads: &system.reads,
+        commands,
+        dt,
+    });
+    system.last_time = start.elapsed();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Position(f32);
+    struct Velocity(f32);
+    struct Health;
+
+    fn batch_names(schedule: &Schedule) -> Vec<Vec<&str>> {
+        schedule.batches().map(|batch| batch.into_iter().map(|(name, _)| name).collect()).collect()
+    }
+
+    #[test]
+    fn batches_systems_without_conflicts() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add(System::new("move", |_| {}).reads::<Velocity>().writes::<Position>())
+            .add(System::new("regenerate", |_| {}).writes::<Health>())
+            .add(System::new("draw", |_| {}).reads::<Position>())
+            .add(System::new("report", |_| {}).reads::<Health>().reads::<Position>());
+        schedule.build().unwrap();
+        assert_eq!(batch_names(&schedule), [vec!["move", "regenerate"], vec!["draw", "report"]]);
+    }
+
+    #[test]
+    fn exclusive_systems_run_alone() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add(System::new("a", |_| {}).reads::<Position>())
+            .add(System::exclusive("spawn", |_, _| {}))
+            .add(System::new("b", |_| {}).reads::<Position>());
+        schedule.build().unwrap();
+        assert_eq!(batch_names(&schedule), [vec!["a"], vec!["spawn"], vec!["b"]]);
+    }
+
+    #[test]
+    fn ordering_constraints() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add(System::new("late", |_| {}).after("early"))
+            .add(System::new("early", |_| {}))
+            .add(System::new("first", |_| {}).before("early"))
+            .add(System::new("free", |_| {}));
+        schedule.build().unwrap();
+        assert_eq!(batch_names(&schedule), [vec!["first", "free"], vec!["early"], vec!["late"]]);
+    }
+
+    #[test]
+    fn rejects_bad_constraints() {
+        let mut schedule = Schedule::new();
+        schedule.add(System::new("a", |_| {})).add(System::new("a", |_| {}));
+        assert!(matches!(schedule.build(), Err(ScheduleError::DuplicateName(name)) if name == "a"));
+
+        let mut schedule = Schedule::new();
+        schedule.add(System::new("a", |_| {}).after("missing"));
+        assert!(matches!(schedule.build(), Err(ScheduleError::UnknownSystem { constraint, .. }) if constraint == "missing"));
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add(System::new("a", |_| {}).after("b"))
+            .add(System::new("b", |_| {}).after("a"))
+            .add(System::new("c", |_| {}));
+        assert!(matches!(schedule.build(), Err(ScheduleError::Cycle(systems)) if systems == ["a", "b"]));
+    }
+
+    #[test]
+    fn runs_batches_in_order() {
+        let mut world = World::new();
+        let entity = world.spawn((Position(0.0), Velocity(2.0)));
+        let mut schedule = Schedule::new();
+        schedule
+            .add(System::new("move", |context| {
+                let dt = context.dt();
+                let velocities: Vec<_> = context.query::<Velocity>().map(|(entity, velocity)| (entity, velocity.0)).collect();
+                for (entity, velocity) in velocities {
+                    if let Some(mut position) = context.get_mut::<Position>(entity) {
+                        position.0 += velocity * dt;
+                    }
+                }
+            })
+            .reads::<Velocity>()
+            .writes::<Position>())
+            .add(System::new("double", |context| {
+                for (_, mut position) in context.query_mut::<Position>() {
+                    position.0 *= 2.0;
+                }
+            })
+            .writes::<Position>());
+        schedule.run(&mut world, 0.5);
+        assert_eq!(world.get::<Position>(entity).map(|position| position.0), Some(2.0));
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    any::{type_name, TypeId},
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use super::{
+    storage::{ErasedStorage, Storage},
+    tags::{ErasedTags, Names},
+    Component, Entity, World,
+};
+
+type RestoreStorage = fn(Option<&dyn ErasedStorage>, &dyn ErasedStorage, u64) -> Box<dyn ErasedStorage>;
+
+/// How to copy one registered component type's storage in and out of snapshots.
+#[derive(Clone, Copy)]
+pub(crate) struct SnapshotType {
+    name: &'static str,
+    copy: fn(&dyn ErasedStorage) -> Box<dyn ErasedStorage>,
+    restore: RestoreStorage,
+}
+
+impl SnapshotType {
+    fn of<T: Component + Clone>() -> Self {
+        SnapshotType {
+            name: type_name::<T>(),
+            copy: |storage| Box::new(downcast::<T>(storage).clone()),
+            restore: |current, snapshot, tick| {
+                let current = current.map(downcast::<T>);
+                Box::new(downcast::<T>(snapshot).restored(current, tick))
+            },
+        }
+    }
+}
+
+fn downcast<T: Component>(storage: &dyn ErasedStorage) -> &Storage<T> {
+    storage.as_any().downcast_ref().unwrap()
+}
+
+impl<T: Clone> Storage<T> {
+    /// A copy of this snapshotted storage to put back in place of `current`. Everything
+    /// restored counts as changed at `tick`, and as added unless `current` already had it;
+    /// whatever `current` has that the snapshot doesn't is logged as removed.
+    fn restored(&self, current: Option<&Storage<T>>, tick: u64) -> Storage<T> {
+        let mut restored = self.clone();
+        restored.removed = current.map_or_else(Vec::new, |current| current.removed.clone());
+        for (index, &entity) in restored.entities.iter().enumerate() {
+            let before = current.and_then(|current| current.dense_index(entity).map(|index| current.added[index]));
+            restored.added[index] = before.unwrap_or(tick);
+            restored.changed[index] = tick;
+        }
+        if let Some(current) = current {
+            let gone = current.entities.iter().filter(|&&entity| self.dense_index(entity).is_none());
+            restored.removed.extend(gone.map(|&entity| (entity, tick)));
+        }
+        restored
+    }
+}
+
+/// The world as it was at `World::snapshot`: its entities, names, tags and the components of
+/// every type registered with `World::snapshot_component`. Held in memory, to go back to with
+/// `World::restore` as often as needed.
+pub struct WorldSnapshot {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    count: usize,
+    storages: HashMap<TypeId, Box<dyn ErasedStorage>>,
+    names: Names,
+    tags: HashMap<TypeId, Box<dyn ErasedTags>>,
+}
+
+impl WorldSnapshot {
+    /// Number of entities captured.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+impl fmt::Debug for WorldSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorldSnapshot")
+            .field("entities", &self.count)
+            .field("component_types", &self.storages.len())
+            .finish()
+    }
+}
+
+impl World {
+    /// Includes `T` in snapshots. Components aren't required to be `Clone`, so each type that
+    /// should be captured is registered once, usually right after creating the world.
+    pub fn snapshot_component<T: Component + Clone>(&mut self) {
+        self.snapshot_types.insert(TypeId::of::<T>(), SnapshotType::of::<T>());
+    }
+
+    /// Names of the component types snapshots capture, for the editor.
+    pub fn snapshot_components(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.snapshot_types.values().map(|snapshot_type| snapshot_type.name)
+    }
+
+    /// Copies the current state into memory, e.g. before entering play mode in the editor or
+    /// each tick for rollback. Costs a clone of every registered component.
+    pub fn snapshot(&self) -> WorldSnapshot {
+        let storages = self
+            .storages
+            .iter()
+            .filter_map(|(id, storage)| Some((*id, (self.snapshot_types.get(id)?.copy)(storage.as_ref()))))
+            .collect();
+        WorldSnapshot {
+            generations: self.generations.clone(),
+            alive: self.alive.clone(),
+            free: self.free.clone(),
+            count: self.count,
+            storages,
+            names: self.names.clone(),
+            tags: self.tags.iter().map(|(id, tags)| (*id, tags.clone_box())).collect(),
+        }
+    }
+
+    /// Goes back to `snapshot`. Entities spawned since are gone and despawned ones are back
+    /// with their old handles. Registered components come back as they were, counting as
+    /// changed; components of unregistered types stay on the entities that exist in both
+    /// states and are dropped from the rest. Change ticks keep counting up, so systems tracking
+    /// changes see the restore like any other edit.
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        let tick = self.tick.get();
+        self.generations.clone_from(&snapshot.generations);
+        self.alive.clone_from(&snapshot.alive);
+        self.free.clone_from(&snapshot.free);
+        self.count = snapshot.count;
+        self.names = snapshot.names.clone();
+        self.tags = snapshot.tags.iter().map(|(id, tags)| (*id, tags.clone_box())).collect();
+
+        for (id, snapshot_type) in &self.snapshot_types {
+            let current = self.storages.remove(id);
+            match snapshot.storages.get(id) {
+                Some(saved) => {
+                    let restored = (snapshot_type.restore)(current.as_deref(), saved.as_ref(), tick);
+                    self.storages.insert(*id, restored);
+                }
+                None => {
+                    // the type was registered or first used after the snapshot
+                    if let Some(mut current) = current {
+                        current.remove_all(tick);
+                        self.storages.insert(*id, current);
+                    }
+                }
+            }
+        }
+        let (generations, alive) = (&self.generations, &self.alive);
+        let is_alive = |entity: Entity| {
+            let index = entity.index as usize;
+            alive.get(index).copied().unwrap_or(false) && generations[index] == entity.generation
+        };
+        for (id, storage) in &mut self.storages {
+            if !self.snapshot_types.contains_key(id) {
+                storage.retain_entities(&is_alive, tick);
+            }
+        }
+    }
+}
+
+/// The last few snapshots of a world, one per step, for undoing simulation steps or rolling
+/// back to the last confirmed network tick.
+pub struct SnapshotHistory {
+    capacity: usize,
+    snapshots: VecDeque<(u64, WorldSnapshot)>,
+}
+
+impl SnapshotHistory {
+    /// Keeps up to `capacity` snapshots, dropping the oldest.
+    pub fn new(capacity: usize) -> Self {
+        SnapshotHistory {
+            capacity: capacity.max(1),
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// Snapshots `world` as step `step`, forgetting any later steps recorded before, which a
+    /// rollback made obsolete.
+    pub fn record(&mut self, step: u64, world: &World) {
+        self.snapshots.retain(|(recorded, _)| *recorded < step);
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back((step, world.snapshot()));
+    }
+
+    /// Restores `world` to the latest recorded step at or before `step`, dropping the ones
+    /// after it. Returns the step restored, or `None` if the history doesn't reach back that
+    /// far.
+    pub fn rollback(&mut self, step: u64, world: &mut World) -> Option<u64> {
+        self.snapshots.retain(|(recorded, _)| *recorded <= step);
+        let (restored, snapshot) = self.snapshots.back()?;
+        world.restore(snapshot);
+        Some(*restored)
+    }
+
+    /// Undoes the latest recorded step: restores the one before it and forgets the latest.
+    /// Returns the step restored.
+    pub fn undo(&mut self, world: &mut World) -> Option<u64> {
+        if self.snapshots.len() < 2 {
+            return None;
+        }
+        self.snapshots.pop_back();
+        let (restored, snapshot) = self.snapshots.back()?;
+        world.restore(snapshot);
+        Some(*restored)
+    }
+
+    /// The snapshot recorded for exactly `step`.
+    pub fn get(&self, step: u64) -> Option<&WorldSnapshot> {
+        self.snapshots.iter().find(|(recorded, _)| *recorded == step).map(|(_, snapshot)| snapshot)
+    }
+
+    /// The oldest and latest recorded steps.
+    pub fn range(&self) -> Option<(u64, u64)> {
+        Some((self.snapshots.front()?.0, self.snapshots.back()?.0))
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
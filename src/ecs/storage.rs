@@ -0,0 +1,160 @@
+use std::any::Any;
+
+use super::{Entity, Mut};
+
+const EMPTY: u32 = u32::MAX;
+
+/// One component type's values, packed densely for iteration with a sparse index by entity
+/// for lookups. Each value carries the ticks it was added and last changed at, and removals
+/// are logged until the world trims them.
+#[derive(Clone)]
+pub(crate) struct Storage<T> {
+    pub(crate) entities: Vec<Entity>,
+    pub(crate) components: Vec<T>,
+    pub(crate) added: Vec<u64>,
+    pub(crate) changed: Vec<u64>,
+    pub(crate) removed: Vec<(Entity, u64)>,
+    sparse: Vec<u32>,
+}
+
+impl<T> Storage<T> {
+    pub(crate) fn new() -> Self {
+        Storage {
+            entities: Vec::new(),
+            components: Vec::new(),
+            added: Vec::new(),
+            changed: Vec::new(),
+            removed: Vec::new(),
+            sparse: Vec::new(),
+        }
+    }
+
+    pub(crate) fn dense_index(&self, entity: Entity) -> Option<usize> {
+        let index = *self.sparse.get(entity.index as usize)?;
+        // a stale entity can share the slot with the live one
+        (index != EMPTY && self.entities[index as usize] == entity).then_some(index as usize)
+    }
+
+    pub(crate) fn get(&self, entity: Entity) -> Option<&T> {
+        self.dense_index(entity).map(|index| &self.components[index])
+    }
+
+    /// The component and its changed tick.
+    pub(crate) fn get_mut(&mut self, entity: Entity) -> Option<(&mut T, &mut u64)> {
+        self.dense_index(entity)
+            .map(|index| (&mut self.components[index], &mut self.changed[index]))
+    }
+
+    /// Every component, marked changed at `tick` when written through.
+    pub(crate) fn iter_mut(&mut self, tick: u64) -> impl Iterator<Item = (Entity, Mut<'_, T>)> {
+        self.entities
+            .iter()
+            .copied()
+            .zip(self.components.iter_mut().zip(&mut self.changed))
+            .map(move |(entity, (value, changed))| (entity, Mut { value, changed, tick }))
+    }
+
+    /// Returns the component it replaced, which counts as a change rather than an addition.
+    pub(crate) fn insert(&mut self, entity: Entity, component: T, tick: u64) -> Option<T> {
+        if let Some(index) = self.dense_index(entity) {
+            self.changed[index] = tick;
+            return Some(std::mem::replace(&mut self.components[index], component));
+        }
+        let slot = entity.index as usize;
+        if slot >= self.sparse.len() {
+            self.sparse.resize(slot + 1, EMPTY);
+        }
+        // a stale entity's component in this slot belongs to nobody anymore
+        self.remove_slot(slot, tick);
+        self.sparse[slot] = self.entities.len() as u32;
+        self.entities.push(entity);
+        self.components.push(component);
+        self.added.push(tick);
+        self.changed.push(tick);
+        None
+    }
+
+    pub(crate) fn remove(&mut self, entity: Entity, tick: u64) -> Option<T> {
+        let index = self.dense_index(entity)?;
+        Some(self.remove_dense(index, tick))
+    }
+
+    fn remove_slot(&mut self, slot: usize, tick: u64) {
+        if let Some(&index) = self.sparse.get(slot) {
+            if index != EMPTY {
+                self.remove_dense(index as usize, tick);
+            }
+        }
+    }
+
+    fn remove_dense(&mut self, index: usize, tick: u64) -> T {
+        let entity = self.entities.swap_remove(index);
+        self.sparse[entity.index as usize] = EMPTY;
+        if let Some(moved) = self.entities.get(index) {
+            self.sparse[moved.index as usize] = index as u32;
+        }
+        self.added.swap_remove(index);
+        self.changed.swap_remove(index);
+        self.removed.push((entity, tick));
+        self.components.swap_remove(index)
+    }
+}
+
+/// What the world needs from a storage without knowing its component type.
+pub(crate) trait ErasedStorage: Send + Sync {
+    fn remove_entity(&mut self, entity: Entity, tick: u64);
+    fn contains(&self, entity: Entity) -> bool;
+    /// Drops every value, keeping the removal log.
+    fn clear(&mut self);
+    /// Removes every value, logging each removal at `tick`.
+    fn remove_all(&mut self, tick: u64);
+    /// Removes the values of entities `keep` rejects, logging them at `tick`.
+    fn retain_entities(&mut self, keep: &dyn Fn(Entity) -> bool, tick: u64);
+    /// Forgets removals logged before `tick`.
+    fn trim_removed(&mut self, tick: u64);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Send + Sync + 'static> ErasedStorage for Storage<T> {
+    fn remove_entity(&mut self, entity: Entity, tick: u64) {
+        self.remove(entity, tick);
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.dense_index(entity).is_some()
+    }
+
+    fn clear(&mut self) {
+        self.entities.clear();
+        self.components.clear();
+        self.added.clear();
+        self.changed.clear();
+        self.sparse.clear();
+    }
+
+    fn remove_all(&mut self, tick: u64) {
+        self.retain_entities(&|_| false, tick);
+    }
+
+    fn retain_entities(&mut self, keep: &dyn Fn(Entity) -> bool, tick: u64) {
+        // backwards, so swap removal only moves entities already looked at
+        for index in (0..self.entities.len()).rev() {
+            if !keep(self.entities[index]) {
+                self.remove_dense(index, tick);
+            }
+        }
+    }
+
+    fn trim_removed(&mut self, tick: u64) {
+        self.removed.retain(|&(_, removed)| removed >= tick);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
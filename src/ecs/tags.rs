@@ -0,0 +1,212 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+};
+
+use super::{Entity, World};
+
+/// A label entities can be found by, e.g. a game's `enum Tag { Enemy, Pickup }`. Implemented
+/// for every hashable thread-safe type. String tags work too, but `&'static str` and `String`
+/// are different tag types.
+pub trait Tag: Clone + Eq + Hash + fmt::Debug + Send + Sync + 'static {}
+
+impl<T: Clone + Eq + Hash + fmt::Debug + Send + Sync + 'static> Tag for T {}
+
+/// One tag type's entities, both ways round.
+#[derive(Clone)]
+pub(crate) struct TagIndex<T> {
+    entities: HashMap<T, Vec<Entity>>,
+    tags: HashMap<Entity, Vec<T>>,
+}
+
+impl<T: Tag> TagIndex<T> {
+    fn new() -> Self {
+        TagIndex {
+            entities: HashMap::new(),
+            tags: HashMap::new(),
+        }
+    }
+
+    /// Returns false if the entity already had the tag.
+    fn add(&mut self, entity: Entity, tag: T) -> bool {
+        let tags = self.tags.entry(entity).or_default();
+        if tags.contains(&tag) {
+            return false;
+        }
+        tags.push(tag.clone());
+        self.entities.entry(tag).or_default().push(entity);
+        true
+    }
+
+    fn remove(&mut self, entity: Entity, tag: &T) -> bool {
+        let Some(tags) = self.tags.get_mut(&entity) else {
+            return false;
+        };
+        let Some(position) = tags.iter().position(|t| t == tag) else {
+            return false;
+        };
+        tags.swap_remove(position);
+        if tags.is_empty() {
+            self.tags.remove(&entity);
+        }
+        self.unlink(entity, tag);
+        true
+    }
+
+    fn unlink(&mut self, entity: Entity, tag: &T) {
+        if let Some(entities) = self.entities.get_mut(tag) {
+            // keeps tagging order, which `query_tagged` promises
+            entities.retain(|&e| e != entity);
+            if entities.is_empty() {
+                self.entities.remove(tag);
+            }
+        }
+    }
+}
+
+/// What the world needs from a tag index without knowing its tag type.
+pub(crate) trait ErasedTags: Send + Sync {
+    fn remove_entity(&mut self, entity: Entity);
+    fn clear(&mut self);
+    fn clone_box(&self) -> Box<dyn ErasedTags>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Tag> ErasedTags for TagIndex<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        for tag in self.tags.remove(&entity).unwrap_or_default() {
+            self.unlink(entity, &tag);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entities.clear();
+        self.tags.clear();
+    }
+
+    fn clone_box(&self) -> Box<dyn ErasedTags> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Names of entities and the reverse lookup. Several entities can share a name.
+#[derive(Clone, Default)]
+pub(crate) struct Names {
+    names: HashMap<Entity, String>,
+    entities: HashMap<String, Vec<Entity>>,
+}
+
+impl Names {
+    fn set(&mut self, entity: Entity, name: String) {
+        self.remove(entity);
+        self.entities.entry(name.clone()).or_default().push(entity);
+        self.names.insert(entity, name);
+    }
+
+    pub(crate) fn remove(&mut self, entity: Entity) -> Option<String> {
+        let name = self.names.remove(&entity)?;
+        if let Some(entities) = self.entities.get_mut(&name) {
+            entities.retain(|&e| e != entity);
+            if entities.is_empty() {
+                self.entities.remove(&name);
+            }
+        }
+        Some(name)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.names.clear();
+        self.entities.clear();
+    }
+}
+
+impl World {
+    /// Names `entity`, replacing its old name, for `find_by_name` and the editor. Names needn't
+    /// be unique. Naming a despawned entity does nothing.
+    pub fn set_name(&mut self, entity: Entity, name: impl Into<String>) {
+        if !self.is_alive(entity) {
+            log::warn!("Ignoring a name given to despawned entity {}", entity);
+            return;
+        }
+        self.names.set(entity, name.into());
+    }
+
+    pub fn remove_name(&mut self, entity: Entity) -> Option<String> {
+        self.names.remove(entity)
+    }
+
+    pub fn name(&self, entity: Entity) -> Option<&str> {
+        self.names.names.get(&entity).map(String::as_str)
+    }
+
+    /// The first entity given `name` that still has it.
+    pub fn find_by_name(&self, name: &str) -> Option<Entity> {
+        self.names.entities.get(name)?.first().copied()
+    }
+
+    /// Every entity named `name`, in the order they were named.
+    pub fn find_all_by_name(&self, name: &str) -> &[Entity] {
+        self.names.entities.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    /// Every named entity, in no particular order, e.g. to filter for a search box.
+    pub fn names(&self) -> impl Iterator<Item = (Entity, &str)> {
+        self.names.names.iter().map(|(&entity, name)| (entity, name.as_str()))
+    }
+
+    fn tag_index<T: Tag>(&self) -> Option<&TagIndex<T>> {
+        self.tags.get(&TypeId::of::<T>())?.as_any().downcast_ref()
+    }
+
+    /// Tags `entity` with `tag`. Returns false if it already had it or is despawned.
+    pub fn add_tag<T: Tag>(&mut self, entity: Entity, tag: T) -> bool {
+        if !self.is_alive(entity) {
+            log::warn!("Ignoring tag {:?} added to despawned entity {}", tag, entity);
+            return false;
+        }
+        let index = self.tags.entry(TypeId::of::<T>()).or_insert_with(|| Box::new(TagIndex::<T>::new()));
+        index.as_any_mut().downcast_mut::<TagIndex<T>>().unwrap().add(entity, tag)
+    }
+
+    /// Returns false if `entity` didn't have `tag`.
+    pub fn remove_tag<T: Tag>(&mut self, entity: Entity, tag: &T) -> bool {
+        let Some(index) = self.tags.get_mut(&TypeId::of::<T>()) else {
+            return false;
+        };
+        index.as_any_mut().downcast_mut::<TagIndex<T>>().unwrap().remove(entity, tag)
+    }
+
+    pub fn has_tag<T: Tag>(&self, entity: Entity, tag: &T) -> bool {
+        self.tag_index::<T>()
+            .and_then(|index| index.tags.get(&entity))
+            .is_some_and(|tags| tags.contains(tag))
+    }
+
+    /// The tags of type `T` on `entity`.
+    pub fn tags<T: Tag>(&self, entity: Entity) -> &[T] {
+        self.tag_index::<T>()
+            .and_then(|index| index.tags.get(&entity))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Every entity tagged `tag`, in the order they were tagged. Costs only the number of
+    /// matches, however big the world is.
+    pub fn query_tagged<T: Tag>(&self, tag: &T) -> impl Iterator<Item = Entity> + '_ {
+        self.tag_index::<T>()
+            .and_then(|index| index.entities.get(tag))
+            .into_iter()
+            .flatten()
+            .copied()
+    }
+}
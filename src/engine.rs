@@ -0,0 +1,1858 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use winit::{
+    event::Event::*,
+    event_loop::{ControlFlow, EventLoop},
+    window::Window,
+};
+
+#[cfg(feature = "egui")]
+use crate::{gpu_memory, load_profile, ui::egui_backend::EguiBackend};
+use crate::{
+    assets::{
+        texture::{Texture, TextureContext, TextureLoader},
+        Assets,
+    },
+    batching::DrawQueue,
+    benchmark::Benchmark,
+    camera::{Camera, CameraBuffer, CameraTarget, CameraTargets, Cameras, ViewportPasses},
+    camera_shake::CameraShake,
+    capabilities::Capabilities,
+    crash,
+    cursor::Cursors,
+    debug_view::DebugViews,
+    ecs::{Schedule, World},
+    environment::EnvironmentMap,
+    fixed_timestep::FixedTimestep,
+    gpu_driven::{GeometryPath, GpuDrivenRenderer},
+    gpu_error,
+    gpu_memory::GpuMemory,
+    input::Input,
+    light_profiles::{LightProfile, LightProfileContext},
+    lights::LightKind,
+    loading::{LoadPhase, LoadingScreen},
+    mesh_renderer::{LitIncludes, MeshRenderer, MeshView, LIT_INCLUDES, MATERIAL_INCLUDES},
+    oit::{TransparencyMode, WeightedBlendedOit},
+    pipeline::PipelineCompiler,
+    postprocess::{
+        auto_exposure::{AutoExposure, AutoExposureSettings},
+        bloom::Bloom,
+        color_grading::ColorGrading,
+        contact_shadows::{ContactShadowQuality, ContactShadows},
+        dither::Dither,
+        dof::DepthOfField,
+        fxaa::Fxaa,
+        motion_blur::{CameraVelocity, MotionBlur},
+        motion_vectors::{CameraHistory, MotionVectors},
+        outline::{Outline, OutlineStyle},
+        screen_flash::ScreenFlash,
+        stack::{PostContext, PostStack, PostStage},
+        tonemap::{TonemapSettings, Tonemapper},
+        upscale::{ResolutionScaler, ResolutionSettings},
+        vignette::Vignette,
+        volumetric_fog::VolumetricFog,
+    },
+    project_dirs::ProjectDirs,
+    random::Random,
+    readback::Readbacks,
+    replay::InputEvent,
+    render_graph::{GraphStats, RenderGraph, TextureHandle},
+    render_hooks::{HookContext, HookPoint, RenderHooks},
+    render_layers::RenderLayers,
+    render_stats::RenderStats,
+    render_world::RenderWorld,
+    scene,
+    shader_reload::{ShaderId, ShaderWatcher, SHADER_DIRECTORY},
+    shadows::ShadowSettings,
+    timers::Timers,
+    transient::{TransientDesc, TransientPool},
+    tween::Tweens,
+    ui::{compositor::UiCompositor, UiBackend, WindowUis},
+};
+
+/// A game or tool built on the engine. Every callback has an empty default, so an app only
+/// implements what it needs.
+pub trait App: 'static {
+    /// Called once the window and device exist, before the first frame, to create resources.
+    fn init(&mut self, _context: &mut AppContext) {}
+
+    /// Loads the scene at `path`, for a benchmark run with `--scene`. Called once after `init`;
+    /// an error ends the run. Apps without scene files keep the default, which refuses.
+    fn load_scene(&mut self, _context: &mut AppContext, path: &Path) -> Result<(), String> {
+        Err(format!("this app can't load scenes such as {}", path.display()))
+    }
+
+    /// Advances the simulation by one step of exactly `dt` seconds, `1 / fixed_update_rate`.
+    /// Runs as many times each frame as whole steps have passed, possibly none, before
+    /// `update`. Per-frame input like `key_pressed` is seen by every step of the frame. Timers
+    /// have already been advanced by the step.
+    fn fixed_update(&mut self, _context: &mut AppContext, _dt: f32) {}
+
+    /// Called every frame before anything is drawn, with the seconds since the previous one.
+    /// Tweens have already been advanced and the fixed steps run; `FollowPath`s are advanced and
+    /// the schedule runs right after, then the world's `scene` components are extracted for
+    /// rendering.
+    fn update(&mut self, _context: &mut AppContext, _dt: f32) {}
+
+    /// Builds this frame's UI.
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, _context: &egui::CtxRef) {}
+
+    /// Records the frame's rendering into `context.encoder`. Queued meshes, including the
+    /// world's, are drawn over it afterwards, and the UI over those.
+    fn render(&mut self, _context: &mut RenderContext) {}
+}
+
+/// What `App::init` and `App::update` get to work with.
+pub struct AppContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub capabilities: &'a Capabilities,
+    /// The window's; the scene itself is rendered in `Tonemapper::HDR_FORMAT`.
+    pub surface_format: wgpu::TextureFormat,
+    pub window: &'a Window,
+    /// Where the game's assets, settings and saves are.
+    pub dirs: &'a ProjectDirs,
+    /// Keyboard and mouse state, without what the UI captured.
+    pub input: &'a Input,
+    /// In `App::fixed_update`, the window's input since the previous step, without what the UI
+    /// captured, for a `replay::Recorder`. Empty in every other call.
+    pub step_inputs: &'a [InputEvent],
+    /// The game objects. The engine draws their `scene` components every frame.
+    pub world: &'a mut World,
+    /// The view the engine renders meshes from; a `camera_controller` can drive it. A camera
+    /// entity in `world` overrides it.
+    pub camera: &'a mut Camera,
+    /// Shakes `camera` as it is rendered, leaving it as the app set it; add trauma on hits.
+    pub camera_shake: &'a mut CameraShake,
+    /// More cameras, rendered after `camera` in `order`, like the world's other camera
+    /// entities: into their viewport of the window, or into `camera_targets`.
+    pub cameras: &'a mut Cameras,
+    /// The textures offscreen cameras render into.
+    pub camera_targets: &'a mut CameraTargets,
+    pub meshes: &'a mut MeshRenderer,
+    /// Meshlet meshes culled and drawn on the GPU into the scene after `meshes`, depth tested
+    /// against them. `None` unless `EngineConfig::gpu_driven` is set and the
+    /// adapter supports it.
+    pub gpu_driven: Option<&'a mut GpuDrivenRenderer>,
+    pub tweens: &'a mut Tweens,
+    pub timers: &'a mut Timers,
+    /// Gameplay randomness, seeded from `EngineConfig::seed`; draw from it rather than another
+    /// generator so sessions replay exactly.
+    pub random: &'a mut Random,
+    pub cursors: &'a mut Cursors,
+    /// How far this frame is between the last fixed update and the next, 0 to 1, for drawing
+    /// simulated objects between their previous and current state.
+    pub alpha: f32,
+    /// Watches the engine's shader files; apps can watch their own to rebuild pipelines when
+    /// they are edited.
+    pub shaders: &'a mut ShaderWatcher,
+    /// Systems the engine runs on `world` every frame after `App::update`, in parallel where
+    /// their access allows.
+    pub schedule: &'a mut Schedule,
+    /// Passes the engine runs at fixed points in every frame, see `render_hooks::HookPoint`.
+    pub render_hooks: &'a mut RenderHooks,
+    /// Which debug visualization, if any, the meshes are drawn with; also in the stats panel.
+    pub debug_views: &'a mut DebugViews,
+    /// Samples per pixel the scene is rendered with: 1 (off) or 4, others rounding down.
+    /// Takes effect from the next frame; also in the stats panel. Set it here rather than on
+    /// `meshes`, which the engine keeps in line with it.
+    pub msaa_samples: &'a mut u32,
+    /// How the HDR scene is mapped to the display; also in the stats panel.
+    pub tonemapping: &'a mut TonemapSettings,
+    /// Metering that adapts the tonemapper's exposure to the scene, or a fixed EV; `None`
+    /// without compute shaders. Also in the stats panel.
+    pub exposure: Option<&'a mut AutoExposureSettings>,
+    /// The fraction of the window's resolution the scene renders at, and how it is scaled up;
+    /// also in the stats panel.
+    pub resolution: &'a mut ResolutionSettings,
+    /// How draws on `RenderLayers::OUTLINED` are outlined, in pixels of the internal
+    /// resolution; also in the stats panel.
+    pub outline: &'a mut OutlineStyle,
+    /// The post effects, to start with volumetric fog (only with compute shaders), depth of
+    /// field and motion blur, all off, bloom, vignette, color grading, off, FXAA, a
+    /// `ScreenFlash` and dithering; also in the stats panel. `PostStack::find_mut` reaches an
+    /// effect's own API, e.g. to give `ColorGrading` a LUT or trigger the flash.
+    pub post: &'a mut PostStack,
+    /// The loads the game waits on, e.g. the assets of the first scene. While any is pending
+    /// the engine shows `loading_screen` instead of the game's UI.
+    pub loading: &'a mut LoadPhase,
+    pub loading_screen: &'a mut LoadingScreen,
+    /// Textures loaded in the background and shared by handle; finished before every
+    /// `App::update`.
+    pub textures: &'a mut Assets<Texture>,
+    /// Shader modules loaded in the background and shared by handle, like `textures`.
+    pub shader_modules: &'a mut Assets<wgpu::ShaderModule>,
+    /// IES profiles loaded in the background into `MeshRenderer::light_profiles`, for `Light`s
+    /// to use by their id.
+    pub light_profiles: &'a mut Assets<LightProfile>,
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    ui: &'a mut WindowUis<RedrawEvent>,
+}
+
+impl AppContext<'_> {
+    /// Makes `texture` drawable in the window's UI with `egui::Image`. `None` without a UI that
+    /// can show textures.
+    #[cfg(feature = "egui")]
+    pub fn register_ui_texture(&mut self, texture: &wgpu::Texture) -> Option<egui::TextureId> {
+        self.ui.get_mut(self.window.id())?.register_texture(self.device, texture)
+    }
+}
+
+/// One frame's render target and the encoder to record into.
+pub struct RenderContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    /// The HDR texture the scene is rendered into this frame, in `Tonemapper::HDR_FORMAT`,
+    /// tonemapped into the window's surface before the UI. Multisampled with MSAA.
+    pub target: &'a wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    /// Samples per pixel of `target`, for the app's pipelines to match, see
+    /// `AppContext::msaa_samples`.
+    pub sample_count: u32,
+    /// Size of `target`, the window's scaled by `AppContext::resolution`.
+    pub size: winit::dpi::PhysicalSize<u32>,
+    pub camera: &'a Camera,
+    /// `camera`'s matrices for this frame, for the app's own pipelines to bind.
+    pub camera_buffer: &'a CameraBuffer,
+    pub meshes: &'a mut MeshRenderer,
+    pub draw_queue: &'a mut DrawQueue,
+    pub stats: &'a mut RenderStats,
+    /// What the engine extracted from the world for this frame. The world itself isn't
+    /// available while rendering.
+    pub render_world: &'a RenderWorld,
+    /// See `AppContext::alpha`.
+    pub alpha: f32,
+    clear: Option<wgpu::Color>,
+}
+
+impl RenderContext<'_> {
+    /// The load op for the first pass that draws into `target`: clears it the first time it
+    /// is asked for and loads after that, so passes can stack without knowing their order.
+    pub fn target_load_op(&mut self) -> wgpu::LoadOp<wgpu::Color> {
+        match self.clear.take() {
+            Some(color) => wgpu::LoadOp::Clear(color),
+            None => wgpu::LoadOp::Load,
+        }
+    }
+}
+
+/// Graphics APIs the engine can run on. Adapters are looked for on the chosen one first, then
+/// on the platform's native APIs and finally on anything wgpu supports, so a machine without,
+/// say, Vulkan still starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphicsBackend {
+    /// The platform's native APIs: Vulkan, Metal or DX12.
+    Auto,
+    Vulkan,
+    Dx12,
+    Metal,
+    /// OpenGL, or GLES through ANGLE.
+    Gl,
+}
+
+impl GraphicsBackend {
+    fn backends(self) -> wgpu::Backends {
+        match self {
+            GraphicsBackend::Auto => wgpu::Backends::PRIMARY,
+            GraphicsBackend::Vulkan => wgpu::Backends::VULKAN,
+            GraphicsBackend::Dx12 => wgpu::Backends::DX12,
+            GraphicsBackend::Metal => wgpu::Backends::METAL,
+            GraphicsBackend::Gl => wgpu::Backends::GL,
+        }
+    }
+
+    /// The sets of backends to try in order.
+    fn fallbacks(self) -> Vec<wgpu::Backends> {
+        let mut order = vec![self.backends()];
+        for fallback in [wgpu::Backends::PRIMARY, wgpu::Backends::all()] {
+            if !order.contains(&fallback) {
+                order.push(fallback);
+            }
+        }
+        order
+    }
+}
+
+/// How the engine sets itself up in `Engine::run`. Start from `EngineConfig::default()` and
+/// change what the app needs with the `with_` methods; `with_overrides` then lets players
+/// pick another backend or present mode from the command line or environment.
+pub struct EngineConfig {
+    pub title: String,
+    /// Initial inner size of the window.
+    pub size: winit::dpi::PhysicalSize<u32>,
+    pub backend: GraphicsBackend,
+    pub power_preference: wgpu::PowerPreference,
+    /// `Fifo` waits for vertical sync. Modes the surface doesn't support fall back to it.
+    pub present_mode: wgpu::PresentMode,
+    /// What the window is cleared to before the app renders.
+    pub clear_color: wgpu::Color,
+    /// Shows the engine's frame stats panel next to the app's UI.
+    pub stats_panel: bool,
+    /// Records a benchmark and exits once it is done, see `BenchmarkOptions::from_args`.
+    pub benchmark: Option<Benchmark>,
+    /// Fixed updates per second, see `App::fixed_update`.
+    pub fixed_update_rate: f32,
+    /// Rebuilds pipelines when their files in `shader_reload::SHADER_DIRECTORY` change. On in
+    /// debug builds.
+    pub shader_hot_reload: bool,
+    /// The directional light's shadow maps; can be changed later through
+    /// `MeshRenderer::shadows`.
+    pub shadows: ShadowSettings,
+    /// Equirectangular `.hdr` image the meshes are lit by and drawn in front of, see
+    /// `MeshRenderer::set_environment`. Relative to `dirs.assets_dir()`.
+    pub environment: Option<PathBuf>,
+    /// Samples per pixel of the scene, and of the UI, which keeps its count when the scene's
+    /// changes through `AppContext::msaa_samples`. 1 turns MSAA off.
+    pub msaa_samples: u32,
+    /// Where assets are loaded from and settings and saves go, passed on in `AppContext::dirs`.
+    pub dirs: ProjectDirs,
+    /// Creates the experimental `GpuDrivenRenderer` on adapters that can run it, handed to the
+    /// app as `AppContext::gpu_driven`.
+    pub gpu_driven: bool,
+    /// What `AppContext::random` starts from; different every run unless set.
+    pub seed: u64,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            title: "wgpu-engine".into(),
+            size: winit::dpi::PhysicalSize::new(1280, 720),
+            backend: GraphicsBackend::Auto,
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            present_mode: wgpu::PresentMode::Fifo,
+            clear_color: wgpu::Color::BLACK,
+            stats_panel: true,
+            benchmark: None,
+            fixed_update_rate: 60.0,
+            shader_hot_reload: cfg!(debug_assertions),
+            shadows: ShadowSettings::default(),
+            environment: None,
+            msaa_samples: 1,
+            dirs: ProjectDirs::new("", "wgpu-engine"),
+            gpu_driven: false,
+            seed: Random::entropy_seed(),
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.size = winit::dpi::PhysicalSize::new(width, height);
+        self
+    }
+
+    pub fn with_backend(mut self, backend: GraphicsBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn with_power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    pub fn with_present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    pub fn with_clear_color(mut self, clear_color: wgpu::Color) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    /// Shadow maps of `resolution` pixels for each of `cascades` cascades.
+    pub fn with_shadows(mut self, resolution: u32, cascades: u32) -> Self {
+        self.shadows.resolution = resolution;
+        self.shadows.cascades = cascades;
+        self
+    }
+
+    /// Screen-space contact shadows of `quality` from the sun, on top of its shadow maps.
+    pub fn with_contact_shadows(mut self, quality: ContactShadowQuality) -> Self {
+        self.shadows.contact.enabled = true;
+        self.shadows.contact.quality = quality;
+        self
+    }
+
+    /// Opts in to the GPU-driven meshlet path, see `gpu_driven`.
+    pub fn with_gpu_driven(mut self) -> Self {
+        self.gpu_driven = true;
+        self
+    }
+
+    pub fn with_environment(mut self, path: impl Into<PathBuf>) -> Self {
+        self.environment = Some(path.into());
+        self
+    }
+
+    /// Multisampling with `samples` samples per pixel: 4, or 1 for none. Other counts are
+    /// rounded down to one of those, the only ones every adapter renders every format with.
+    pub fn with_msaa(mut self, samples: u32) -> Self {
+        self.msaa_samples = samples;
+        self
+    }
+
+    pub fn with_dirs(mut self, dirs: ProjectDirs) -> Self {
+        self.dirs = dirs;
+        self
+    }
+
+    /// Starts `AppContext::random` from `seed`, e.g. a recorded session's.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Applies the `WGPU_ENGINE_BACKEND`, `WGPU_ENGINE_POWER`, `WGPU_ENGINE_VSYNC`,
+    /// `WGPU_ENGINE_SIZE`, `WGPU_ENGINE_MSAA` and `WGPU_ENGINE_ASSETS` environment variables,
+    /// then the `--backend`, `--power`, `--vsync`, `--size`, `--msaa` and `--assets` arguments
+    /// among `args`, which win over the environment. Other arguments are ignored.
+    ///
+    /// Backends are `auto`, `vulkan`, `dx12`, `metal` and `gl`; power is `high` or `low`;
+    /// vsync is `on`, `off` or `mailbox`; sizes are like `1920x1080`; MSAA is `off` or `4`;
+    /// assets is the directory to load assets from.
+    pub fn with_overrides(mut self, args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        const OPTIONS: [(&str, &str); 6] = [
+            ("--backend", "WGPU_ENGINE_BACKEND"),
+            ("--power", "WGPU_ENGINE_POWER"),
+            ("--vsync", "WGPU_ENGINE_VSYNC"),
+            ("--size", "WGPU_ENGINE_SIZE"),
+            ("--msaa", "WGPU_ENGINE_MSAA"),
+            ("--assets", "WGPU_ENGINE_ASSETS"),
+        ];
+        for (option, variable) in OPTIONS {
+            if let Ok(value) = std::env::var(variable) {
+                self.apply_override(option, &value).map_err(|e| format!("{}: {}", variable, e))?;
+            }
+        }
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if OPTIONS.iter().any(|&(option, _)| option == arg) {
+                let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                self.apply_override(&arg, &value).map_err(|e| format!("{}: {}", arg, e))?;
+            }
+        }
+        Ok(self)
+    }
+
+    fn apply_override(&mut self, option: &str, value: &str) -> Result<(), String> {
+        match option {
+            "--backend" => {
+                self.backend = match value.to_ascii_lowercase().as_str() {
+                    "auto" => GraphicsBackend::Auto,
+                    "vulkan" => GraphicsBackend::Vulkan,
+                    "dx12" => GraphicsBackend::Dx12,
+                    "metal" => GraphicsBackend::Metal,
+                    "gl" => GraphicsBackend::Gl,
+                    _ => return Err(format!("unknown backend '{}', expected auto, vulkan, dx12, metal or gl", value)),
+                }
+            }
+            "--power" => {
+                self.power_preference = match value.to_ascii_lowercase().as_str() {
+                    "high" => wgpu::PowerPreference::HighPerformance,
+                    "low" => wgpu::PowerPreference::LowPower,
+                    _ => return Err(format!("unknown power preference '{}', expected high or low", value)),
+                }
+            }
+            "--vsync" => {
+                self.present_mode = match value.to_ascii_lowercase().as_str() {
+                    "on" => wgpu::PresentMode::Fifo,
+                    "off" => wgpu::PresentMode::Immediate,
+                    "mailbox" => wgpu::PresentMode::Mailbox,
+                    _ => return Err(format!("unknown vsync mode '{}', expected on, off or mailbox", value)),
+                }
+            }
+            "--size" => {
+                let size = value.split_once('x').and_then(|(width, height)| {
+                    Some((width.trim().parse::<u32>().ok()?, height.trim().parse::<u32>().ok()?))
+                });
+                match size {
+                    Some((width, height)) if width > 0 && height > 0 => {
+                        self.size = winit::dpi::PhysicalSize::new(width, height)
+                    }
+                    _ => return Err(format!("expected a size like 1280x720, found '{}'", value)),
+                }
+            }
+            "--msaa" => {
+                self.msaa_samples = match value.to_ascii_lowercase().as_str() {
+                    "off" | "1" => 1,
+                    "4" => 4,
+                    _ => return Err(format!("unknown MSAA mode '{}', expected off or 4", value)),
+                }
+            }
+            "--assets" => {
+                // the one path given relative to the working directory, as usual on a command line
+                let directory = std::path::absolute(value).map_err(|e| format!("invalid path '{}': {}", value, e))?;
+                if !directory.is_dir() {
+                    return Err(format!("'{}' is not a directory", value));
+                }
+                self.dirs = self.dirs.clone().with_assets(directory);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+enum RedrawEvent {
+    RequestRedraw,
+}
+
+pub struct Engine;
+
+impl Engine {
+    /// Opens the window, sets up the device and runs `app` until the window closes.
+    pub fn run<A: App>(config: EngineConfig, mut app: A) -> ! {
+        let EngineConfig {
+            title,
+            size,
+            backend,
+            power_preference,
+            present_mode,
+            clear_color,
+            stats_panel,
+            mut benchmark,
+            fixed_update_rate,
+            shader_hot_reload,
+            shadows,
+            environment,
+            msaa_samples,
+            dirs,
+            gpu_driven,
+            seed,
+        } = config;
+        let event_loop = EventLoop::with_user_event();
+        let window = winit::window::WindowBuilder::new()
+            .with_visible(!benchmark.as_ref().is_some_and(|b| b.options.headless))
+            .with_decorations(true)
+            .with_resizable(true)
+            .with_transparent(false)
+            .with_title(title)
+            .with_inner_size(size)
+            .build(&event_loop)
+            .unwrap();
+
+        // vsync would cap every measured frame at the refresh rate
+        let present_mode = if benchmark.is_some() {
+            wgpu::PresentMode::Immediate
+        } else {
+            present_mode
+        };
+        let mut render_state = pollster::block_on(RenderState::new(&window, backend, power_preference, present_mode, msaa_samples));
+        render_state.clear_color = clear_color;
+        render_state.stats_panel = stats_panel;
+        render_state.shaders.enabled = shader_hot_reload;
+        render_state.meshes.shadows.settings = shadows;
+        render_state.random.reseed(seed);
+        if gpu_driven {
+            match GeometryPath::select(&render_state.capabilities) {
+                GeometryPath::GpuDriven => {
+                    render_state.gpu_driven = Some(GpuDrivenRenderer::new(&render_state.device, Tonemapper::HDR_FORMAT));
+                }
+                GeometryPath::Vertex => {
+                    log::warn!("The adapter can't run the GPU-driven path; meshes have to be drawn through `AppContext::meshes`")
+                }
+            }
+        }
+        if let Some(path) = environment.map(|path| dirs.asset(path)) {
+            match EnvironmentMap::load(&render_state.device, &render_state.queue, &path) {
+                Ok(environment) => {
+                    let (device, queue) = (&render_state.device, &render_state.queue);
+                    render_state.meshes.set_environment(device, queue, Some(Arc::new(environment)));
+                }
+                Err(e) => log::error!("Failed to load environment map {}: {}", path.display(), e),
+            }
+        }
+        let mut tweens = Tweens::new();
+        let mut timers = Timers::new();
+        let mut input = Input::new();
+        let mut world = World::new();
+        scene::register_snapshot_components(&mut world);
+        let mut fixed = FixedTimestep::new(fixed_update_rate);
+        app.init(&mut render_state.app_context(&window, &dirs, &input, &mut world, &mut tweens, &mut timers));
+        if let Some(scene) = benchmark.as_ref().and_then(|benchmark| benchmark.options.scene.as_deref()) {
+            let mut context = render_state.app_context(&window, &dirs, &input, &mut world, &mut tweens, &mut timers);
+            if let Err(e) = app.load_scene(&mut context, scene) {
+                log::error!("Failed to load benchmark scene {}: {}", scene.display(), e);
+                std::process::exit(1);
+            }
+        }
+
+        let mut time = std::time::Instant::now();
+        let start_time = time;
+        let mut frame = 0;
+        // carried over frames without a fixed step
+        let mut step_inputs = Vec::new();
+
+        event_loop.run(move |event, _, control_flow| {
+            render_state.ui.handle_event(&event);
+            render_state.cursors.handle_event(&event);
+            let captured = render_state.ui.captures_event(&event);
+            input.handle_event(&event, captured);
+            if let (WindowEvent { event, .. }, false) = (&event, captured) {
+                step_inputs.extend(InputEvent::from_window_event(event));
+            }
+            match event {
+                RedrawRequested(..) => {
+                    let dt = time.elapsed().as_secs_f32();
+                    time = std::time::Instant::now();
+                    frame += 1;
+                    crash::frame(frame, dt);
+
+                    tweens.update(dt);
+                    render_state.reload_shaders(dt);
+                    render_state.update_assets();
+                    render_state.meshes.begin_frame();
+                    let steps = fixed.advance(dt);
+                    render_state.alpha = fixed.alpha();
+                    let mut context = render_state.app_context(&window, &dirs, &input, &mut world, &mut tweens, &mut timers);
+                    for step in 0..steps {
+                        context.step_inputs = if step == 0 { &step_inputs } else { &[] };
+                        context.timers.update(fixed.step());
+                        app.fixed_update(&mut context, fixed.step());
+                    }
+                    context.step_inputs = &[];
+                    app.update(&mut context, dt);
+                    scene::follow_path_system(&mut world, dt);
+                    render_state.schedule.run(&mut world, dt);
+                    render_state.extract(&world);
+                    // after the extract, so the world's camera doesn't take over from the path
+                    if let Some(benchmark) = &benchmark {
+                        render_state.camera = benchmark.camera(&render_state.camera);
+                    }
+                    render_state.update(&start_time);
+                    render_state.render(&window, &mut app);
+                    crash::set_render_stats(render_state.stats);
+                    input.end_frame();
+                    world.end_frame();
+                    if steps > 0 {
+                        step_inputs.clear();
+                    }
+
+                    if let Some(benchmark) = &mut benchmark {
+                        if benchmark.record(dt, render_state.stats) {
+                            match benchmark.write_results() {
+                                Ok(stats) => println!(
+                                    "Benchmark: {} frames, mean {:.3} ms, median {:.3} ms, p95 {:.3} ms, p99 {:.3} ms, \
+                                     max {:.3} ms; written to {}",
+                                    stats.frames,
+                                    stats.mean,
+                                    stats.median,
+                                    stats.p95,
+                                    stats.p99,
+                                    stats.max,
+                                    benchmark.options.output.display()
+                                ),
+                                Err(e) => log::error!(
+                                    "Failed to write benchmark results to {}: {}",
+                                    benchmark.options.output.display(),
+                                    e
+                                ),
+                            }
+                            *control_flow = ControlFlow::Exit;
+                        }
+                    }
+                }
+                MainEventsCleared | UserEvent(RedrawEvent::RequestRedraw) => {
+                    window.request_redraw();
+                }
+                WindowEvent { event, .. } => match event {
+                    winit::event::WindowEvent::Resized(size) => {
+                        render_state.resize(size);
+                    }
+                    winit::event::WindowEvent::CloseRequested => {
+                        *control_flow = ControlFlow::Exit;
+                    }
+                    _ => {}
+                },
+                _ => (),
+            }
+        })
+    }
+}
+
+struct RenderState {
+    size: winit::dpi::PhysicalSize<u32>,
+    pending_size: Option<winit::dpi::PhysicalSize<u32>>,
+    surface: wgpu::Surface,
+    device: Arc<wgpu::Device>,
+    queue: wgpu::Queue,
+    capabilities: Capabilities,
+    gpu_memory: GpuMemory,
+    draw_queue: DrawQueue,
+    stats: RenderStats,
+    surface_config: wgpu::SurfaceConfiguration,
+    meshes: MeshRenderer,
+    camera: Camera,
+    camera_shake: CameraShake,
+    camera_buffer: CameraBuffer,
+    cameras: Cameras,
+    camera_targets: CameraTargets,
+    // this frame's cameras other than `camera`, in render order, each with its own view
+    frame_cameras: Vec<Camera>,
+    camera_views: Vec<MeshView>,
+    // for the scene's sample count and for offscreen targets
+    viewport_passes: ViewportPasses,
+    offscreen_passes: ViewportPasses,
+    clear_color: wgpu::Color,
+    // only shown in the UI
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    stats_panel: bool,
+
+    time: f64,
+    // seconds since the previous frame, for adapting the exposure
+    delta_time: f32,
+    previous_ui_draw_time: Option<f32>,
+    ui: WindowUis<RedrawEvent>,
+    ui_compositor: UiCompositor,
+    cursors: Cursors,
+    loading: LoadPhase,
+    // only shown in the UI
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    loading_screen: LoadingScreen,
+    textures: Assets<Texture>,
+    texture_loader: TextureLoader,
+    shader_modules: Assets<wgpu::ShaderModule>,
+    light_profiles: Assets<LightProfile>,
+    shaders: ShaderWatcher,
+    mesh_shader: ShaderId,
+    material_shader: ShaderId,
+    tonemap_shader: ShaderId,
+    skybox_shader: ShaderId,
+    // by effect name, watched once the effect is in `post`
+    post_shaders: HashMap<String, ShaderId>,
+    alpha: f32,
+    random: Random,
+    schedule: Schedule,
+    render_world: RenderWorld,
+    render_hooks: RenderHooks,
+    debug_views: DebugViews,
+    // copies of the presented frame for the crash report, taken about once a second
+    last_frame: Readbacks,
+    last_frame_time: f64,
+    // what `meshes` is switched to before the next frame
+    msaa_samples: u32,
+    tonemapper: Tonemapper,
+    auto_exposure: Option<AutoExposure>,
+    resolution: ResolutionScaler,
+    // the main camera's matrices this frame and last, for the velocity and effects reading it
+    camera_history: CameraHistory,
+    motion_vectors: MotionVectors,
+    camera_velocity: CameraVelocity,
+    outline: Outline,
+    // follows `meshes.shadows.settings.contact`
+    contact_shadows: ContactShadows,
+    // created by the first frame with weighted blended transparent meshes
+    oit: Option<WeightedBlendedOit>,
+    gpu_driven: Option<GpuDrivenRenderer>,
+    post: PostStack,
+    transients: TransientPool,
+    // the last frame's, for the stats panel
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    graph_stats: GraphStats,
+}
+
+/// The scene's textures besides its color that post effects can read this frame.
+#[derive(Clone, Copy)]
+struct PostInputs {
+    depth: Option<TextureHandle>,
+    velocity: Option<TextureHandle>,
+}
+
+/// What the frame's render graph passes share.
+struct Frame<'f, A> {
+    state: &'f mut RenderState,
+    app: &'f mut A,
+}
+
+/// `samples` rounded down to 1 or 4, the sample counts WebGPU guarantees for every renderable
+/// format. wgpu 0.12 can't tell which others an adapter supports for a format.
+fn supported_sample_count(samples: u32) -> u32 {
+    if samples >= 4 {
+        4
+    } else {
+        1
+    }
+}
+
+impl RenderState {
+    async fn new(
+        window: &Window,
+        backend: GraphicsBackend,
+        power_preference: wgpu::PowerPreference,
+        present_mode: wgpu::PresentMode,
+        msaa_samples: u32,
+    ) -> Self {
+        let msaa_samples = supported_sample_count(msaa_samples);
+        let size = window.inner_size();
+        let mut found = None;
+        for backends in backend.fallbacks() {
+            let instance = wgpu::Instance::new(backends);
+            let surface = unsafe { instance.create_surface(window) };
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference,
+                    compatible_surface: Some(&surface),
+                    force_fallback_adapter: false,
+                })
+                .await;
+            match adapter {
+                Some(adapter) => {
+                    found = Some((surface, adapter));
+                    break;
+                }
+                None => log::warn!("No graphics adapter found for {:?}, trying the next backends", backends),
+            }
+        }
+        let Some((surface, adapter)) = found else {
+            log::error!("No graphics adapter found on any backend");
+            std::process::exit(1);
+        };
+
+        let capabilities = Capabilities::negotiate(&adapter);
+        crash::set_adapter(&capabilities.adapter);
+        crash::set_section("Features", format!("{:?}", capabilities.features));
+        let (device, queue) = adapter.request_device(&capabilities.device_descriptor(), None).await.unwrap();
+        gpu_error::install_handler(&device);
+        // shared with the pipeline compiler's threads
+        let device = Arc::new(device);
+
+        let surface_format = match crate::surface::select_format(&surface, &adapter) {
+            Ok(format) => format,
+            Err(e) => {
+                log::error!("Failed to configure the window surface: {}", e);
+                std::process::exit(1);
+            }
+        };
+        let mut surface_config = wgpu::SurfaceConfiguration {
+            // copied from for the crash report's last frame
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: surface_format,
+            width: size.width,
+            height: size.height,
+            present_mode,
+        };
+        let mut configured = gpu_error::capture(&device, "configuring the window surface", || {
+            surface.configure(&device, &surface_config)
+        });
+        if configured.is_err() {
+            log::info!("The window surface can't be copied from, crash reports won't include the last frame");
+            surface_config.usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+            configured = gpu_error::capture(&device, "configuring the window surface", || {
+                surface.configure(&device, &surface_config)
+            });
+        }
+        if configured.is_err() && present_mode != wgpu::PresentMode::Fifo {
+            // every surface supports Fifo
+            log::warn!("Present mode {:?} is not supported, falling back to Fifo", present_mode);
+            surface_config.present_mode = wgpu::PresentMode::Fifo;
+            configured = gpu_error::capture(&device, "configuring the window surface", || {
+                surface.configure(&device, &surface_config)
+            });
+        }
+        if let Err(e) = configured {
+            log::error!("Failed to configure the window surface: {}", e);
+            std::process::exit(1);
+        }
+
+        #[cfg(feature = "egui")]
+        let ui: Box<dyn UiBackend<RedrawEvent>> = match EguiBackend::new(&device, UiCompositor::LAYER_FORMAT, msaa_samples, window) {
+            Ok(backend) => Box::new(backend),
+            Err(e) => {
+                log::error!("Failed to create the UI renderer: {}", e);
+                std::process::exit(1);
+            }
+        };
+        #[cfg(not(feature = "egui"))]
+        let ui: Box<dyn UiBackend<RedrawEvent>> = Box::new(crate::ui::NoUi);
+        let mut uis = WindowUis::new();
+        uis.insert(window.id(), ui);
+
+        let mut shaders = ShaderWatcher::new(SHADER_DIRECTORY);
+        let mesh_files: Vec<_> = LIT_INCLUDES.into_iter().chain(["mesh.wgsl"]).collect();
+        let mesh_shader = shaders.watch("mesh", &mesh_files);
+        let material_files: Vec<_> = LIT_INCLUDES.into_iter().chain(MATERIAL_INCLUDES).chain(["pbr.wgsl"]).collect();
+        let material_shader = shaders.watch("pbr", &material_files);
+        let tonemap_shader = shaders.watch("tonemap", &["fullscreen.wgsl", "tonemap.wgsl"]);
+        let skybox_shader = shaders.watch("skybox", &["fullscreen.wgsl", "skybox.wgsl"]);
+
+        let mut depth_of_field = DepthOfField::new(&device, Tonemapper::HDR_FORMAT);
+        // off until a camera asks for it, like the fog below
+        depth_of_field.settings.enabled = false;
+        let mut motion_blur = MotionBlur::new(&device, Tonemapper::HDR_FORMAT);
+        motion_blur.settings.enabled = false;
+        // neutral until graded, so there's no point paying for the pass
+        let mut color_grading = ColorGrading::new(&device, &queue, surface_format);
+        color_grading.settings.enabled = false;
+        let mut post = PostStack::new()
+            .with(depth_of_field)
+            .with(motion_blur)
+            .with(Bloom::new(&device, Tonemapper::HDR_FORMAT))
+            .with(Vignette::new(&device, Tonemapper::HDR_FORMAT))
+            .with(color_grading)
+            .with(Fxaa::new(&device, surface_format))
+            .with(ScreenFlash::new(&device, surface_format))
+            .with(Dither::new(&device, &queue, surface_format));
+        if capabilities.compute() {
+            // off until a scene asks for it, since it changes how everything looks
+            let mut fog = VolumetricFog::new(&device, Tonemapper::HDR_FORMAT);
+            fog.settings.enabled = false;
+            post.insert(0, fog);
+        }
+
+        RenderState {
+            size,
+            pending_size: None,
+            meshes: MeshRenderer::new(&device, Tonemapper::HDR_FORMAT).with_pipeline_compiler(PipelineCompiler::new(device.clone())),
+            debug_views: DebugViews::new(&device, surface_format, capabilities.compute()),
+            last_frame: Readbacks::new(),
+            last_frame_time: f64::NEG_INFINITY,
+            ui_compositor: UiCompositor::new(&device, surface_format),
+            tonemapper: Tonemapper::new(&device, surface_format),
+            auto_exposure: capabilities.compute().then(|| AutoExposure::new(&device)),
+            resolution: ResolutionScaler::new(&device, surface_format),
+            camera_history: CameraHistory::new(),
+            motion_vectors: MotionVectors::new(&device),
+            camera_velocity: CameraVelocity::new(&device),
+            outline: Outline::new(&device, surface_format, 1, 1),
+            contact_shadows: ContactShadows::new(&device, Tonemapper::HDR_FORMAT),
+            oit: None,
+            gpu_driven: None,
+            texture_loader: TextureLoader::new(&device),
+            post,
+            camera: Camera::default(),
+            camera_shake: CameraShake::default(),
+            camera_buffer: CameraBuffer::new(&device),
+            cameras: Cameras::new(),
+            camera_targets: CameraTargets::new(Tonemapper::HDR_FORMAT),
+            frame_cameras: Vec::new(),
+            camera_views: Vec::new(),
+            viewport_passes: ViewportPasses::new(&device, Tonemapper::HDR_FORMAT, MeshRenderer::DEPTH_FORMAT, 1),
+            offscreen_passes: ViewportPasses::new(&device, Tonemapper::HDR_FORMAT, MeshRenderer::DEPTH_FORMAT, 1),
+            surface,
+            device,
+            queue,
+            capabilities,
+            gpu_memory: GpuMemory::default(),
+            draw_queue: DrawQueue::new(),
+            stats: RenderStats::default(),
+            surface_config,
+            clear_color: wgpu::Color::BLACK,
+            stats_panel: true,
+
+            time: 0.0,
+            delta_time: 0.0,
+            previous_ui_draw_time: None,
+            ui: uis,
+            cursors: Cursors::new(),
+            loading: LoadPhase::new(),
+            loading_screen: LoadingScreen::default(),
+            textures: Assets::new(),
+            shader_modules: Assets::new(),
+            light_profiles: Assets::new(),
+            shaders,
+            mesh_shader,
+            material_shader,
+            tonemap_shader,
+            skybox_shader,
+            post_shaders: HashMap::new(),
+            alpha: 0.0,
+            random: Random::default(),
+            schedule: Schedule::new(),
+            render_world: RenderWorld::new(),
+            render_hooks: RenderHooks::new(),
+            transients: TransientPool::new(),
+            graph_stats: GraphStats::default(),
+            msaa_samples,
+        }
+    }
+
+    fn app_context<'a>(
+        &'a mut self,
+        window: &'a Window,
+        dirs: &'a ProjectDirs,
+        input: &'a Input,
+        world: &'a mut World,
+        tweens: &'a mut Tweens,
+        timers: &'a mut Timers,
+    ) -> AppContext<'a> {
+        AppContext {
+            device: &self.device,
+            queue: &self.queue,
+            capabilities: &self.capabilities,
+            surface_format: self.surface_config.format,
+            window,
+            dirs,
+            input,
+            step_inputs: &[],
+            world,
+            camera: &mut self.camera,
+            camera_shake: &mut self.camera_shake,
+            cameras: &mut self.cameras,
+            camera_targets: &mut self.camera_targets,
+            meshes: &mut self.meshes,
+            gpu_driven: self.gpu_driven.as_mut(),
+            tweens,
+            timers,
+            random: &mut self.random,
+            cursors: &mut self.cursors,
+            alpha: self.alpha,
+            shaders: &mut self.shaders,
+            schedule: &mut self.schedule,
+            render_hooks: &mut self.render_hooks,
+            debug_views: &mut self.debug_views,
+            msaa_samples: &mut self.msaa_samples,
+            tonemapping: &mut self.tonemapper.settings,
+            exposure: self.auto_exposure.as_mut().map(|auto_exposure| &mut auto_exposure.settings),
+            resolution: &mut self.resolution.settings,
+            outline: &mut self.outline.style,
+            post: &mut self.post,
+            loading: &mut self.loading,
+            loading_screen: &mut self.loading_screen,
+            textures: &mut self.textures,
+            shader_modules: &mut self.shader_modules,
+            light_profiles: &mut self.light_profiles,
+            ui: &mut self.ui,
+        }
+    }
+
+    /// Records the new size; the surface is reconfigured once, right before the next frame.
+    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.pending_size = Some(new_size);
+    }
+
+    fn apply_pending_resize(&mut self) {
+        if let Some(new_size) = self.pending_size.take() {
+            if new_size != self.size {
+                self.configure_surface(new_size);
+            }
+        }
+    }
+
+    fn configure_surface(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.surface_config.width = new_size.width;
+            self.surface_config.height = new_size.height;
+            let (surface, device, config) = (&self.surface, &self.device, &self.surface_config);
+            if let Err(e) = gpu_error::capture(device, "reconfiguring the window surface", || {
+                surface.configure(device, config)
+            }) {
+                log::error!("{}", e);
+            }
+        }
+    }
+
+    /// Copies what the frame draws out of `world`; nothing after this looks at the world.
+    fn extract(&mut self, world: &World) {
+        self.render_world.extract(world);
+        self.render_world.submit(&mut self.camera, &mut self.meshes);
+    }
+
+    /// Collects the frame's cameras other than the main one, the world's and the app's, in
+    /// render order, and makes sure each has a view of the sample count it renders with.
+    fn prepare_cameras(&mut self) {
+        self.frame_cameras.clear();
+        self.frame_cameras.extend(self.render_world.cameras.iter().cloned());
+        self.frame_cameras.extend(self.cameras.render_order().into_iter().map(|(_, camera)| camera.clone()));
+        // stable, so among equal orders the world's cameras go first
+        self.frame_cameras.sort_by_key(|camera| camera.order);
+
+        let sample_count = self.meshes.sample_count();
+        if self.viewport_passes.sample_count() != sample_count {
+            self.viewport_passes = ViewportPasses::new(&self.device, Tonemapper::HDR_FORMAT, MeshRenderer::DEPTH_FORMAT, sample_count);
+        }
+        for (index, camera) in self.frame_cameras.iter().enumerate() {
+            // offscreen targets are single-sampled
+            let surface = camera.target == CameraTarget::Surface;
+            let samples = if surface { sample_count } else { 1 };
+            if self.camera_views.get(index).is_some_and(|view| view.sample_count() == samples) {
+                continue;
+            }
+            let view = if surface { self.meshes.create_main_view(&self.device) } else { self.meshes.create_view(&self.device) };
+            match self.camera_views.get_mut(index) {
+                Some(old) => *old = view,
+                None => self.camera_views.push(view),
+            }
+        }
+    }
+
+    fn reload_shaders(&mut self, dt: f32) {
+        self.shaders.update(dt);
+        let lit = [self.mesh_shader, self.material_shader];
+        if lit.iter().any(|&id| self.shaders.is_changed(id)) {
+            // the two share their includes, so an edit to any file rebuilds both
+            let shaders = &self.shaders;
+            let result = LitIncludes::read(|file| shaders.read(file))
+                .and_then(|includes| Ok((includes, shaders.read("mesh.wgsl")?, shaders.read("pbr.wgsl")?)))
+                .map_err(|e| e.to_string())
+                .and_then(|(includes, mesh, material)| {
+                    self.meshes.set_lit_shaders(&self.device, includes, &mesh, &material).map_err(|e| e.to_string())
+                });
+            for id in lit {
+                if result.is_ok() || self.shaders.is_changed(id) {
+                    self.shaders.report(id, result.clone());
+                }
+            }
+        }
+        if self.shaders.is_changed(self.tonemap_shader) {
+            self.shaders.reload(&self.device, self.tonemap_shader, |module| self.tonemapper.set_shader(&self.device, module));
+        }
+        if self.shaders.is_changed(self.skybox_shader) {
+            self.shaders.reload(&self.device, self.skybox_shader, |module| self.meshes.set_skybox_shader(&self.device, module));
+        }
+        for effect in self.post.iter_mut() {
+            let Some(file) = effect.shader_file() else {
+                continue;
+            };
+            let id = match self.post_shaders.get(effect.name()) {
+                Some(&id) => id,
+                None => {
+                    let id = self.shaders.watch(effect.name(), &["fullscreen.wgsl", file]);
+                    self.post_shaders.insert(effect.name().to_string(), id);
+                    id
+                }
+            };
+            if self.shaders.is_changed(id) {
+                self.shaders.reload(&self.device, id, |module| effect.set_shader(&self.device, module));
+            }
+        }
+    }
+
+    /// Finishes the assets decoded since the last frame, which also finishes their load phase
+    /// tasks.
+    fn update_assets(&mut self) {
+        self.textures.update(&mut TextureContext {
+            device: &self.device,
+            queue: &self.queue,
+            loader: &mut self.texture_loader,
+            memory: &mut self.gpu_memory,
+        });
+        self.shader_modules.update(&mut &*self.device);
+        self.light_profiles.update(&mut LightProfileContext {
+            queue: &self.queue,
+            profiles: &mut self.meshes.light_profiles,
+        });
+    }
+
+    fn update(&mut self, start_time: &std::time::Instant) {
+        let time = start_time.elapsed().as_secs_f64();
+        self.delta_time = (time - self.time) as f32;
+        self.time = time;
+        self.loading.update();
+        self.camera_shake.update(self.delta_time);
+        if let Some(flash) = self.post.find_mut::<ScreenFlash>() {
+            flash.update(self.delta_time);
+        }
+    }
+
+    fn acquire_frame(&mut self, window: &Window) -> Option<wgpu::SurfaceTexture> {
+        match self.surface.get_current_texture() {
+            Ok(frame) => return Some(frame),
+            Err(wgpu::SurfaceError::Outdated) => {}
+            Err(e) => {
+                log::error!("Dropped frame with error: {}", e);
+                return None;
+            }
+        }
+
+        // the window changed size before its resize event reached us; catch up and retry once
+        // instead of presenting nothing until the next event
+        self.pending_size = None;
+        self.configure_surface(window.inner_size());
+        match self.surface.get_current_texture() {
+            Ok(frame) => Some(frame),
+            Err(wgpu::SurfaceError::Outdated) => None,
+            Err(e) => {
+                log::error!("Dropped frame with error: {}", e);
+                None
+            }
+        }
+    }
+
+    fn render(&mut self, window: &Window, app: &mut impl App) {
+        // the frame sees the shaken camera, the app and the world keep theirs
+        let steady = self.camera.clone();
+        self.camera = self.camera_shake.apply(&steady);
+        self.render_frame(window, app);
+        self.camera = steady;
+    }
+
+    fn render_frame(&mut self, window: &Window, app: &mut impl App) {
+        self.apply_pending_resize();
+        self.gpu_memory.begin_frame();
+        self.stats = RenderStats::default();
+        self.debug_views.poll(&self.device);
+        self.last_frame.poll(&self.device);
+        let output_frame = match self.acquire_frame(window) {
+            Some(frame) => frame,
+            None => return,
+        };
+        let output_view = output_frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("encoder"),
+        });
+        self.camera_buffer.update(&self.queue, &self.camera, self.render_size());
+        let matrices = self.camera_history.advance(&self.camera, self.render_size());
+        self.motion_vectors.update(&self.queue, matrices);
+        self.meshes.set_sample_count(&self.device, supported_sample_count(self.msaa_samples));
+        self.meshes.prepare(&self.device, &self.queue, &mut self.gpu_memory);
+        self.meshes.skin(&mut encoder);
+        self.textures.stream(&self.device, &self.queue, &mut encoder, &mut self.gpu_memory);
+        self.prepare_cameras();
+
+        let graph = self.frame_graph(window, &output_view);
+        let mut transients = std::mem::take(&mut self.transients);
+        let stats = match graph.compile(&self.device, &mut transients, &mut self.gpu_memory) {
+            Ok(graph) => Some(graph.execute(&mut encoder, &mut Frame { state: self, app })),
+            Err(e) => {
+                log::warn!("Skipping frame: {}", e);
+                None
+            }
+        };
+        self.transients = transients;
+        self.graph_stats = stats.unwrap_or_default();
+        self.capture_last_frame(&mut encoder, &output_frame.texture);
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.debug_views.submitted();
+        self.last_frame.submitted();
+
+        output_frame.present();
+    }
+
+    /// Copies the frame out for the crash report, unless the surface can't be copied from or the
+    /// last copy is recent or still in flight.
+    fn capture_last_frame(&mut self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        if !self.surface_config.usage.contains(wgpu::TextureUsages::COPY_SRC)
+            || self.time - self.last_frame_time < 1.0
+            || self.last_frame.pending() > 0
+        {
+            return;
+        }
+        self.last_frame_time = self.time;
+        let size = (self.surface_config.width, self.surface_config.height);
+        self.last_frame
+            .read_texture(&self.device, encoder, texture, self.surface_config.format, (0, 0), size, |data| {
+                if let Ok(data) = data {
+                    if let Some(pixels) = data.rgba8() {
+                        crash::set_last_frame(data.width, data.height, pixels.concat());
+                    }
+                }
+            });
+    }
+
+    /// The frame's passes. Which ones are added depends on what there is to draw; passes that
+    /// share no textures run in the order they are added here.
+    fn frame_graph<'a, 'f, A: App>(&self, window: &'a Window, output_view: &'a wgpu::TextureView) -> RenderGraph<'a, Frame<'f, A>> {
+        let mut graph = RenderGraph::new();
+        let target_size = (self.size.width, self.size.height);
+        // the scene renders at the internal resolution up to tonemapping, then is scaled up to
+        // the surface's
+        let render_size = self.render_size();
+        let scaled = render_size != target_size;
+        // whatever draws into the scene first, be it the app, a hook, the skybox or the meshes,
+        // clears it. The scene is drawn in HDR, with MSAA into a multisampled texture resolved
+        // into `hdr`, then goes through the HDR post effects, tonemapping, the upscale to the
+        // surface's size and the display effects into the surface before the UI.
+        let surface = graph.import("surface", output_view, Some(self.clear_color));
+        let hdr = graph.create_cleared(
+            "hdr",
+            TransientDesc::attachment(render_size.0, render_size.1, Tonemapper::HDR_FORMAT),
+            self.clear_color,
+        );
+        let sample_count = self.meshes.sample_count();
+        let scene = if sample_count > 1 {
+            let desc = TransientDesc {
+                sample_count,
+                ..TransientDesc::attachment(render_size.0, render_size.1, Tonemapper::HDR_FORMAT)
+            };
+            graph.create_cleared("scene", desc, self.clear_color)
+        } else {
+            hdr
+        };
+        let depth = graph.create(
+            "depth",
+            TransientDesc {
+                sample_count,
+                ..TransientDesc::attachment(render_size.0, render_size.1, MeshRenderer::DEPTH_FORMAT)
+            },
+        );
+
+        graph.pass("shadows").run(move |pass, frame: &mut Frame<A>| {
+            let state = &mut *frame.state;
+            state.meshes.render_shadows(&state.device, &state.queue, pass.encoder, &state.camera, render_size, &mut state.stats);
+            state.meshes.render_reflection_probes(&state.device, &state.queue, pass.encoder, &state.camera, &mut state.stats);
+            state.meshes.render_reflection(&state.device, &state.queue, pass.encoder, &state.camera, render_size, &mut state.stats);
+        });
+        graph.pass("app").reads(scene).writes(scene).run(move |pass, frame: &mut Frame<A>| {
+            let state = &mut *frame.state;
+            let clear = pass.clear(scene);
+            let mut context = RenderContext {
+                device: &state.device,
+                queue: &state.queue,
+                target: pass.view(scene),
+                encoder: pass.encoder,
+                format: Tonemapper::HDR_FORMAT,
+                sample_count,
+                size: winit::dpi::PhysicalSize::new(render_size.0, render_size.1),
+                camera: &state.camera,
+                camera_buffer: &state.camera_buffer,
+                meshes: &mut state.meshes,
+                draw_queue: &mut state.draw_queue,
+                stats: &mut state.stats,
+                render_world: &state.render_world,
+                alpha: state.alpha,
+                clear: clear.take(),
+            };
+            frame.app.render(&mut context);
+            clear.set(context.clear);
+            state.draw_queue.build();
+            state.stats.record_batches(state.draw_queue.stats());
+        });
+
+        // the mesh pass clears the depth buffer, so hooks before it only write it
+        let mut depth_written = self.hook_pass(&mut graph, HookPoint::BeforeOpaque, scene, depth);
+        if self.meshes.environment().is_some() {
+            graph.pass("skybox").reads(scene).writes(scene).run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                let (target, clear) = (pass.view(scene), pass.clear(scene));
+                if clear.get().is_some() && state.meshes.render_skybox(&state.queue, pass.encoder, target, &state.camera, render_size) {
+                    clear.set(None);
+                }
+            });
+        }
+        let debug_view = self.debug_views.view;
+        let draws_meshes = !self.meshes.is_empty() && (!self.debug_views.is_enabled() || debug_view.tints_scene());
+        if draws_meshes {
+            depth_written = true;
+            graph.pass("meshes").reads(scene).writes(scene).writes(depth).run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                let (target, load, depth) = (pass.view(scene), pass.load_op(scene), pass.view(depth));
+                state.meshes.render(
+                    &state.device,
+                    &state.queue,
+                    pass.encoder,
+                    target,
+                    load,
+                    depth,
+                    &state.camera,
+                    render_size,
+                    &mut state.stats,
+                );
+            });
+        }
+        if self.frame_cameras.iter().any(|camera| camera.target == CameraTarget::Surface) {
+            depth_written = true;
+            graph.pass("cameras").reads(scene).writes(scene).writes(depth).run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                // clearing the whole scene later would wipe these cameras out
+                pass.flush_clear(scene);
+                state.meshes.prepare(&state.device, &state.queue, &mut state.gpu_memory);
+                let (target, depth) = (pass.view(scene), pass.view(depth));
+                let cameras = state.frame_cameras.iter().zip(&state.camera_views);
+                for (camera, view) in cameras.filter(|(camera, _)| camera.target == CameraTarget::Surface) {
+                    state.meshes.render_camera(
+                        &state.queue,
+                        pass.encoder,
+                        view,
+                        &state.viewport_passes,
+                        target,
+                        depth,
+                        camera,
+                        render_size,
+                        &mut state.stats,
+                    );
+                }
+            });
+        }
+        if self.frame_cameras.iter().any(|camera| camera.target != CameraTarget::Surface) {
+            graph.pass("offscreen cameras").run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                state.meshes.prepare(&state.device, &state.queue, &mut state.gpu_memory);
+                for (camera, view) in state.frame_cameras.iter().zip(&state.camera_views) {
+                    // cameras whose target the app hasn't created are skipped
+                    let CameraTarget::Offscreen(key) = camera.target else {
+                        continue;
+                    };
+                    let Some(target) = state.camera_targets.get(key) else {
+                        continue;
+                    };
+                    state.meshes.render_camera(
+                        &state.queue,
+                        pass.encoder,
+                        view,
+                        &state.offscreen_passes,
+                        &target.view,
+                        target.depth(),
+                        camera,
+                        target.size(),
+                        &mut state.stats,
+                    );
+                }
+            });
+        }
+        let debug_values = (!self.meshes.is_empty() && self.debug_views.is_enabled()).then(|| {
+            let values = graph.create("debug view values", TransientDesc::attachment(target_size.0, target_size.1, DebugViews::FORMAT));
+            // single-sampled whatever the scene's sample count
+            let depth = graph.create("debug view depth", TransientDesc::attachment(target_size.0, target_size.1, MeshRenderer::DEPTH_FORMAT));
+            graph.pass("debug view").writes(values).writes(depth).run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                let (target, depth) = (pass.view(values), pass.view(depth));
+                state.meshes.render_debug_view(
+                    &state.device,
+                    &state.queue,
+                    pass.encoder,
+                    debug_view,
+                    target,
+                    depth,
+                    &state.camera,
+                    target_size,
+                    &mut state.stats,
+                );
+            });
+            values
+        });
+        // the GPU-driven clusters are depth tested against the meshes and add their own depth
+        if self.gpu_driven.as_ref().is_some_and(|gpu_driven| gpu_driven.cluster_count() > 0) {
+            let mut pass = graph.pass("gpu driven").reads(scene).writes(scene).writes(depth);
+            let depth_load = if depth_written {
+                pass = pass.reads(depth);
+                wgpu::LoadOp::Load
+            } else {
+                wgpu::LoadOp::Clear(1.0)
+            };
+            depth_written = true;
+            pass.run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                pass.flush_clear(scene);
+                let Some(gpu_driven) = &mut state.gpu_driven else {
+                    return;
+                };
+                gpu_driven.prepare(&state.device, &state.queue, &state.camera, render_size, sample_count);
+                gpu_driven.render(pass.encoder, pass.view(scene), pass.view(depth), depth_load);
+            });
+        }
+        depth_written |= self.hook_pass(&mut graph, HookPoint::AfterOpaque, scene, depth);
+        // weighted blended transparency accumulates over the opaque depth here and is resolved
+        // into `hdr` before the HDR effects
+        let oit = draws_meshes && self.meshes.transparency() == TransparencyMode::WeightedBlended && self.meshes.has_transparent();
+        if oit {
+            graph.pass("oit accumulation").reads(depth).run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                let oit = state
+                    .oit
+                    .get_or_insert_with(|| WeightedBlendedOit::new(&state.device, Tonemapper::HDR_FORMAT, render_size.0, render_size.1, sample_count));
+                oit.resize(&state.device, render_size.0, render_size.1, sample_count);
+                let mut transparent = oit.begin_pass(pass.encoder, pass.view(depth));
+                state.meshes.render_transparent(&mut transparent, &state.camera, &mut state.stats);
+            });
+        }
+        // post-processing hooks draw into the scene, before the post effects
+        depth_written |= self.hook_pass(&mut graph, HookPoint::BeforePost, scene, depth);
+        // effects can only sample depth that was drawn and isn't multisampled
+        let scene_depth = (depth_written && sample_count == 1).then_some(depth);
+        // the velocity is only rendered for effects that read it, over the scene's depth
+        let reads_velocity = |stage| self.post.active(stage).into_iter().any(|index| self.post.reads_velocity(index));
+        let velocity = match scene_depth {
+            Some(depth) if reads_velocity(PostStage::Hdr) || (!scaled && reads_velocity(PostStage::Display)) => {
+                let velocity = graph.create("velocity", TransientDesc::attachment(render_size.0, render_size.1, CameraVelocity::FORMAT));
+                graph.pass("velocity").reads(depth).writes(velocity).run(move |pass, frame: &mut Frame<A>| {
+                    let state = &mut *frame.state;
+                    let (target, depth) = (pass.view(velocity), pass.view(depth));
+                    let matrices = *state.motion_vectors.matrices();
+                    let previous_view_projection = matrices.previous_view_projection();
+                    state.camera_velocity.render(
+                        &state.device,
+                        &state.queue,
+                        pass.encoder,
+                        depth,
+                        target,
+                        matrices.view_projection(),
+                        previous_view_projection,
+                    );
+                    state.meshes.render_velocity(
+                        &state.device,
+                        &state.queue,
+                        pass.encoder,
+                        target,
+                        depth,
+                        &state.camera,
+                        previous_view_projection,
+                        render_size,
+                        &mut state.stats,
+                    );
+                });
+                Some(velocity)
+            }
+            _ => None,
+        };
+        let scene_inputs = PostInputs {
+            depth: scene_depth,
+            velocity,
+        };
+
+        if scene != hdr {
+            graph.pass("msaa resolve").reads(scene).writes(hdr).run(move |pass, _: &mut Frame<A>| {
+                let (multisampled, target, load) = (pass.view(scene), pass.view(hdr), pass.load_op(scene));
+                pass.clear(hdr).set(None);
+                // draws nothing; the resolve happens as the pass ends
+                pass.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("msaa resolve"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: multisampled,
+                        resolve_target: Some(target),
+                        ops: wgpu::Operations { load, store: false },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+            });
+        }
+        // darkens the lit scene where a short ray towards the sun is blocked, under the effects
+        if let Some(depth) = scene_depth.filter(|_| self.meshes.shadows.settings.contact.enabled) {
+            graph.pass("contact shadows").reads(hdr).reads(depth).writes(hdr).run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                pass.flush_clear(hdr);
+                let (target, depth) = (pass.view(hdr), pass.view(depth));
+                let Some(sun) = state.meshes.lights.iter().find(|light| light.kind == LightKind::Directional) else {
+                    return;
+                };
+                let direction = sun.direction;
+                state.contact_shadows.settings = state.meshes.shadows.settings.contact;
+                state.contact_shadows.render(&state.device, &state.queue, pass.encoder, depth, render_size, &state.camera, direction);
+                state.contact_shadows.apply(&state.device, pass.encoder, target);
+            });
+        }
+        if oit {
+            graph.pass("oit resolve").reads(hdr).writes(hdr).run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                pass.flush_clear(hdr);
+                if let Some(oit) = &state.oit {
+                    oit.resolve(&state.device, pass.encoder, pass.view(hdr));
+                }
+            });
+        }
+        let hdr = self.post_passes(&mut graph, PostStage::Hdr, hdr, Tonemapper::HDR_FORMAT, render_size, scene_inputs, None);
+        // the display effects run at full size, where the scene's depth and velocity don't fit a
+        // scaled scene
+        let display_inputs = if scaled {
+            PostInputs {
+                depth: None,
+                velocity: None,
+            }
+        } else {
+            scene_inputs
+        };
+        let display_effects = self.active_effects(PostStage::Display, display_inputs);
+        let format = self.surface_config.format;
+        // with display effects the full-size image goes through them on its way to the surface
+        let display = if display_effects.is_empty() {
+            surface
+        } else {
+            graph.create("display", TransientDesc::attachment(target_size.0, target_size.1, format))
+        };
+        let tonemapped = if scaled {
+            graph.create("tonemapped", TransientDesc::attachment(render_size.0, render_size.1, format))
+        } else {
+            display
+        };
+        graph.pass("tonemap").reads(hdr).writes(tonemapped).run(move |pass, frame: &mut Frame<A>| {
+            let state = &mut *frame.state;
+            // the scene may not have been drawn into, but still has to be cleared
+            pass.flush_clear(hdr);
+            let (source, target) = (pass.view(hdr), pass.view(tonemapped));
+            pass.clear(tonemapped).set(None);
+            let metered = match &mut state.auto_exposure {
+                Some(auto_exposure) if auto_exposure.settings.enabled => {
+                    auto_exposure.update(&state.device, &state.queue, pass.encoder, source, render_size, state.delta_time);
+                    Some(auto_exposure.exposure_buffer())
+                }
+                _ => None,
+            };
+            state.tonemapper.render(&state.device, &state.queue, pass.encoder, source, target, metered);
+        });
+        // over the tonemapped scene, so the outline is scaled and anti-aliased with it
+        if self.meshes.draws_on(RenderLayers::OUTLINED) {
+            graph.pass("outline").reads(tonemapped).writes(tonemapped).run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                let target = pass.view(tonemapped);
+                state.outline.resize(&state.device, render_size.0, render_size.1);
+                state.meshes.render_mask(
+                    &state.device,
+                    &state.queue,
+                    pass.encoder,
+                    state.outline.mask(),
+                    &state.camera,
+                    RenderLayers::OUTLINED,
+                    render_size,
+                    &mut state.stats,
+                );
+                state.outline.render(&state.device, &state.queue, pass.encoder, target);
+            });
+        }
+        if scaled {
+            graph.pass("upscale").reads(tonemapped).writes(display).run(move |pass, frame: &mut Frame<A>| {
+                let state = &*frame.state;
+                let (source, target) = (pass.view(tonemapped), pass.view(display));
+                pass.clear(display).set(None);
+                state.resolution.upscale(&state.device, &state.queue, pass.encoder, source, target, target_size);
+            });
+        }
+        if !display_effects.is_empty() {
+            self.post_passes(&mut graph, PostStage::Display, display, format, target_size, display_inputs, Some(surface));
+        }
+        // the debug views are drawn in display range, over the tonemapped scene
+        if let Some(values) = debug_values {
+            graph.pass("debug view resolve").reads(values).reads(surface).writes(surface).run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                let (values, target, clear) = (pass.view(values), pass.view(surface), pass.clear(surface).take());
+                state.debug_views.render(&state.device, &state.queue, pass.encoder, values, target_size, target, clear);
+            });
+        }
+
+        if cfg!(feature = "egui") {
+            // the UI goes into a layer of its own, so it composites the same way whatever the
+            // surface format
+            let layer = graph.create("ui layer", TransientDesc::attachment(target_size.0, target_size.1, UiCompositor::LAYER_FORMAT));
+            graph.pass("ui").writes(layer).ui().run(move |pass, frame: &mut Frame<A>| {
+                let (target, clear) = (pass.view(layer), pass.clear(layer));
+                frame.state.render_ui(window, frame.app, pass.encoder, target, clear);
+            });
+            graph.pass("ui composite").reads(layer).reads(surface).writes(surface).ui().run(move |pass, frame: &mut Frame<A>| {
+                let state = &*frame.state;
+                let (layer, target, clear) = (pass.view(layer), pass.view(surface), pass.clear(surface).take());
+                state.ui_compositor.composite(&state.device, pass.encoder, layer, target, clear);
+            });
+        } else {
+            graph.pass("ui").reads(surface).writes(surface).ui().run(move |pass, frame: &mut Frame<A>| {
+                let (target, clear) = (pass.view(surface), pass.clear(surface));
+                frame.state.render_ui(window, frame.app, pass.encoder, target, clear);
+            });
+        }
+        self.hook_pass(&mut graph, HookPoint::AfterUi, surface, depth);
+        graph
+    }
+
+    /// The size the scene renders at, before it is scaled up to the surface's.
+    fn render_size(&self) -> (u32, u32) {
+        self.resolution.settings.internal_size((self.size.width, self.size.height))
+    }
+
+    /// Adds a pass per enabled effect of `stage`, starting from `input`. Each draws into a new
+    /// texture of `format` and `size`, the last one into `output` if given, which is returned.
+    #[allow(clippy::too_many_arguments)]
+    fn post_passes<'a, A: App>(
+        &self,
+        graph: &mut RenderGraph<'a, Frame<'_, A>>,
+        stage: PostStage,
+        input: TextureHandle,
+        format: wgpu::TextureFormat,
+        size: (u32, u32),
+        inputs: PostInputs,
+        output: Option<TextureHandle>,
+    ) -> TextureHandle {
+        let effects = self.active_effects(stage, inputs);
+        let mut color = input;
+        for (i, &index) in effects.iter().enumerate() {
+            let source = color;
+            color = match output {
+                Some(output) if i + 1 == effects.len() => output,
+                _ => graph.create("post effect", TransientDesc::attachment(size.0, size.1, format)),
+            };
+            let target = color;
+            let depth = inputs.depth.filter(|_| self.post.reads_depth(index));
+            let velocity = inputs.velocity.filter(|_| self.post.reads_velocity(index));
+            let mut pass = graph.pass(self.post.name(index)).reads(source).writes(target);
+            for input in depth.into_iter().chain(velocity) {
+                pass = pass.reads(input);
+            }
+            pass.run(move |pass, frame: &mut Frame<A>| {
+                let state = &mut *frame.state;
+                pass.flush_clear(source);
+                pass.clear(target).set(None);
+                let (input, output) = (pass.view(source), pass.view(target));
+                let depth = depth.map(|depth| pass.view(depth));
+                let velocity = velocity.map(|velocity| pass.view(velocity));
+                let motion_vectors = velocity.map(|velocity| state.motion_vectors.bind_group(&state.device, velocity));
+                let mut context = PostContext {
+                    device: &state.device,
+                    queue: &state.queue,
+                    encoder: pass.encoder,
+                    size,
+                    camera: &state.camera,
+                    matrices: *state.motion_vectors.matrices(),
+                    lights: &state.meshes.lights,
+                    depth,
+                    velocity,
+                    motion_vectors: motion_vectors.as_ref(),
+                };
+                state.post.render(index, &mut context, input, output);
+            });
+        }
+        color
+    }
+
+    /// The enabled effects of `stage` that can run this frame, given the scene's `inputs`.
+    fn active_effects(&self, stage: PostStage, inputs: PostInputs) -> Vec<usize> {
+        let mut effects = self.post.active(stage);
+        effects.retain(|&index| {
+            (inputs.depth.is_some() || !self.post.reads_depth(index)) && (inputs.velocity.is_some() || !self.post.reads_velocity(index))
+        });
+        effects
+    }
+
+    /// Adds a pass running the hooks at `point` over `target`, if there are any; true if it did.
+    fn hook_pass<'a, A: App>(
+        &self,
+        graph: &mut RenderGraph<'a, Frame<'_, A>>,
+        point: HookPoint,
+        target: TextureHandle,
+        depth: TextureHandle,
+    ) -> bool {
+        if self.render_hooks.names(point).next().is_none() {
+            return false;
+        }
+        // after the UI the target is the surface itself
+        let (format, sample_count, (width, height)) = if point == HookPoint::AfterUi {
+            (self.surface_config.format, 1, (self.size.width, self.size.height))
+        } else {
+            (Tonemapper::HDR_FORMAT, self.meshes.sample_count(), self.render_size())
+        };
+        let mut pass = graph.pass(format!("{:?} hooks", point)).reads(target).writes(target).writes(depth);
+        if point != HookPoint::BeforeOpaque {
+            pass = pass.reads(depth);
+        }
+        if point == HookPoint::AfterUi {
+            pass = pass.ui();
+        }
+        pass.run(move |pass, frame: &mut Frame<A>| {
+            let state = &mut *frame.state;
+            let (target, depth, clear) = (pass.view(target), pass.view(depth), pass.clear(target));
+            let mut context = HookContext::new(
+                point,
+                &state.device,
+                &state.queue,
+                pass.encoder,
+                target,
+                format,
+                sample_count,
+                depth,
+                winit::dpi::PhysicalSize::new(width, height),
+                &state.camera,
+                &state.camera_buffer,
+                &state.meshes,
+                &state.render_world,
+                &mut state.stats,
+                clear,
+            );
+            state.render_hooks.run(&mut context);
+        });
+        true
+    }
+
+    #[cfg_attr(not(feature = "egui"), allow(unused_variables))]
+    fn render_ui(
+        &mut self,
+        window: &Window,
+        app: &mut impl App,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        clear: &Cell<Option<wgpu::Color>>,
+    ) {
+        let ui_start_time = std::time::Instant::now();
+        let window_id = window.id();
+        if let Some(ui) = self.ui.get_mut(window_id) {
+            ui.begin_frame(self.time);
+        }
+        #[cfg(feature = "egui")]
+        if let Some(context) = self.ui.get_mut(window_id).and_then(|ui| ui.egui_context()) {
+            if self.loading_screen.is_shown(&self.loading) {
+                self.loading_screen.ui(&context, &self.loading);
+            } else {
+                if self.stats_panel {
+                    self.stats_panel(&context);
+                }
+                self.shaders.error_window(&context);
+                app.ui(&context);
+            }
+            self.cursors.paint(&context);
+        }
+        if let Some(ui) = self.ui.get_mut(window_id) {
+            ui.end_frame(window);
+            self.cursors.apply(window, ui.cursor());
+        }
+        self.previous_ui_draw_time = Some(ui_start_time.elapsed().as_secs_f32());
+
+        if let Some(ui) = self.ui.get_mut(window_id) {
+            ui.render(&self.device, &self.queue, encoder, target, clear.take());
+        }
+    }
+
+    #[cfg(feature = "egui")]
+    fn stats_panel(&mut self, context: &egui::CtxRef) {
+        egui::SidePanel::left("engine stats").show(context, |ui| {
+            ui.heading("Engine");
+            ui.label(format!("Frame time: {} ms", self.previous_ui_draw_time.unwrap_or(0.0) * 1000.0));
+            ui.label(format!("Adapter: {} ({:?})", self.capabilities.adapter.name, self.capabilities.adapter.backend));
+            let geometry_path = if self.gpu_driven.is_some() { GeometryPath::GpuDriven } else { GeometryPath::Vertex };
+            ui.label(format!("Geometry path: {:?}", geometry_path));
+            ui.label(format!(
+                "GPU memory: {} / {}",
+                gpu_memory::format_bytes(self.gpu_memory.total()),
+                gpu_memory::format_bytes(self.gpu_memory.budget.total)
+            ));
+            for warning in self.gpu_memory.warnings() {
+                ui.colored_label(egui::Color32::YELLOW, warning);
+            }
+            ui.label(format!("Batching: {}", self.draw_queue.stats()));
+            let msaa_name = |samples: u32| if samples > 1 { format!("{}x", samples) } else { "Off".to_string() };
+            egui::ComboBox::from_label("MSAA")
+                .selected_text(msaa_name(self.msaa_samples))
+                .show_ui(ui, |ui| {
+                    for samples in [1, 4] {
+                        ui.selectable_value(&mut self.msaa_samples, samples, msaa_name(samples));
+                    }
+                });
+            self.stats.ui(ui);
+            if !self.schedule.is_empty() {
+                ui.collapsing("Systems", |ui| self.schedule.ui(ui));
+            }
+            if !self.render_hooks.is_empty() {
+                ui.collapsing("Render hooks", |ui| {
+                    for point in HookPoint::ALL {
+                        for name in self.render_hooks.names(point) {
+                            ui.label(format!("{:?}: {}", point, name));
+                        }
+                    }
+                });
+            }
+            ui.collapsing("Resolution", |ui| self.resolution.settings.ui(ui));
+            ui.collapsing("Tonemapping", |ui| self.tonemapper.settings.ui(ui));
+            if let Some(auto_exposure) = &mut self.auto_exposure {
+                ui.collapsing("Exposure", |ui| auto_exposure.settings.ui(ui));
+            }
+            ui.collapsing("Post-processing", |ui| self.post.ui(ui));
+            ui.collapsing("Outline", |ui| self.outline.style.ui(ui));
+            ui.collapsing("Contact shadows", |ui| self.meshes.shadows.settings.contact.ui(ui));
+            ui.collapsing("Render graph", |ui| self.graph_stats.ui(ui));
+            ui.collapsing("Asset loads", |ui| load_profile::report(10).ui(ui));
+            ui.collapsing("Debug view", |ui| self.debug_views.ui(ui));
+        });
+    }
+}
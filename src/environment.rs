@@ -0,0 +1,473 @@
+use std::{num::NonZeroU32, path::Path};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Camera,
+    lightmap::{HdrImage, LightmapError},
+    load_profile::{LoadStage, LoadTimer},
+    postprocess::{fullscreen_module, sampler_entry, texture_entry, uniform_entry, FullscreenPipeline},
+};
+
+/// WGSL for mesh shaders lit by the environment: declares `environment_diffuse(normal)`,
+/// `environment_reflection(direction, roughness)` and `environment_brdf(n_dot_v, roughness)`,
+/// bound with the view at group 0 from binding 2 on.
+pub const ENVIRONMENT_SHADER: &str = include_str!("shaders/environment.wgsl");
+
+/// Face size of the irradiance cube.
+pub const IRRADIANCE_SIZE: u32 = 32;
+/// Face size of the prefiltered specular cube's first mip, which is for mirror-like surfaces.
+pub const SPECULAR_SIZE: u32 = 128;
+/// Mips of the specular cube, from roughness 0 to 1.
+pub const SPECULAR_MIPS: u32 = 6;
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct BakeParams {
+    face: u32,
+    roughness: f32,
+    source_level: f32,
+    source_size: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyboxParams {
+    inverse_view_projection: [[f32; 4]; 4],
+    intensity: [f32; 4],
+}
+
+fn cube_texture(device: &wgpu::Device, label: &str, size: u32, mip_levels: u32, usage: wgpu::TextureUsages) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: mip_levels,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage,
+    })
+}
+
+fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn face_view(texture: &wgpu::Texture, face: u32, mip: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("environment face"),
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_mip_level: mip,
+        mip_level_count: NonZeroU32::new(1),
+        base_array_layer: face,
+        array_layer_count: NonZeroU32::new(1),
+        ..Default::default()
+    })
+}
+
+fn cube_sampler(device: &wgpu::Device, label: &str) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some(label),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+fn cube_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::Cube,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+/// An HDR environment as a cube for the skybox, with the irradiance and prefiltered specular
+/// cubes image-based lighting reads, all baked on the GPU when it is created. Light meshes
+/// with it through `MeshRenderer::set_environment`.
+pub struct EnvironmentMap {
+    resolution: u32,
+    skybox_view: wgpu::TextureView,
+    irradiance: wgpu::Texture,
+    specular: wgpu::Texture,
+}
+
+impl EnvironmentMap {
+    /// Loads an equirectangular Radiance `.hdr` image, e.g. one from Poly Haven.
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: impl AsRef<Path>) -> Result<Self, LightmapError> {
+        let path = path.as_ref();
+        let mut timer = LoadTimer::for_path("environment", path);
+        let image = HdrImage::load_timed(path, &mut timer)?;
+        let environment = Self::from_equirect(device, queue, &image);
+        // the equirect as RGBA32F; the cubes are baked from it on the GPU
+        timer.add_upload_bytes(image.pixels.len() * 16);
+        timer.end(LoadStage::Upload);
+        timer.finish();
+        Ok(environment)
+    }
+
+    /// Bakes `image`, a latitude-longitude panorama with +Y at the top row, into cubes. The
+    /// skybox gets faces a quarter of the image's width, a power of two.
+    pub fn from_equirect(device: &wgpu::Device, queue: &wgpu::Queue, image: &HdrImage) -> Self {
+        let max_size = device.limits().max_texture_dimension_2d;
+        let resolution = (image.width / 4).next_power_of_two().clamp(16, max_size.min(2048));
+        let mip_levels = resolution.trailing_zeros() + 1;
+
+        let pixels: Vec<[f32; 4]> = image.pixels.iter().map(|&[r, g, b]| [r, g, b, 1.0]).collect();
+        let equirect = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("environment equirect"),
+                size: wgpu::Extent3d {
+                    width: image.width,
+                    height: image.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            bytemuck::cast_slice(&pixels),
+        );
+        let equirect_view = equirect.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC;
+        let skybox = cube_texture(device, "environment skybox", resolution, mip_levels, usage);
+        let irradiance = cube_texture(device, "environment irradiance", IRRADIANCE_SIZE, 1, usage);
+        let specular = cube_texture(device, "environment specular", SPECULAR_SIZE, SPECULAR_MIPS, usage);
+        let skybox_view = cube_view(&skybox);
+
+        let module = fullscreen_module(device, "environment bake", include_str!("shaders/environment_bake.wgsl"));
+        let equirect_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("environment equirect layout"),
+            entries: &[
+                uniform_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let convolve_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("environment convolve layout"),
+            entries: &[uniform_entry(0), cube_entry(2), sampler_entry(3)],
+        });
+        let mip_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("environment mip layout"),
+            entries: &[sampler_entry(0), texture_entry(1)],
+        });
+        let pipeline = |label, module: &wgpu::ShaderModule, entry_point, layout| {
+            FullscreenPipeline::new(device, label, module, entry_point, &[layout], FORMAT, None)
+        };
+        let equirect_pipeline = pipeline("environment equirect", &module, "fs_equirect", &equirect_layout);
+        let irradiance_pipeline = pipeline("environment irradiance", &module, "fs_irradiance", &convolve_layout);
+        let prefilter_pipeline = pipeline("environment prefilter", &module, "fs_prefilter", &convolve_layout);
+        let mip_module = fullscreen_module(device, "environment mips", include_str!("shaders/reflection_downsample.wgsl"));
+        let mip_pipeline = pipeline("environment mips", &mip_module, "fs_main", &mip_layout);
+        let sampler = cube_sampler(device, "environment bake sampler");
+
+        let params = |face, roughness, source_level| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("environment bake params"),
+                contents: bytemuck::bytes_of(&BakeParams {
+                    face,
+                    roughness,
+                    source_level,
+                    source_size: resolution as f32,
+                }),
+                usage: wgpu::BufferUsages::UNIFORM,
+            })
+        };
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("environment bake"),
+        });
+
+        for face in 0..6 {
+            let params = params(face, 0.0, 0.0);
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("environment equirect bind group"),
+                layout: &equirect_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&equirect_view),
+                    },
+                ],
+            });
+            equirect_pipeline.draw(&mut encoder, &face_view(&skybox, face, 0), &[&bind_group], Some(wgpu::Color::BLACK));
+
+            // the convolutions sample lower mips to keep bright spots from aliasing
+            for mip in 1..mip_levels {
+                let source = face_view(&skybox, face, mip - 1);
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("environment mip bind group"),
+                    layout: &mip_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&source),
+                        },
+                    ],
+                });
+                mip_pipeline.draw(&mut encoder, &face_view(&skybox, face, mip), &[&bind_group], Some(wgpu::Color::BLACK));
+            }
+        }
+
+        let convolve = |encoder: &mut wgpu::CommandEncoder, pipeline: &FullscreenPipeline, target: &wgpu::TextureView, params: wgpu::Buffer| {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("environment convolve bind group"),
+                layout: &convolve_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&skybox_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            });
+            pipeline.draw(encoder, target, &[&bind_group], Some(wgpu::Color::BLACK));
+        };
+        // about four skybox texels per irradiance sample is plenty for something this smooth
+        let irradiance_level = (resolution as f32 / IRRADIANCE_SIZE as f32).log2().max(0.0);
+        for face in 0..6 {
+            convolve(&mut encoder, &irradiance_pipeline, &face_view(&irradiance, face, 0), params(face, 1.0, irradiance_level));
+            for mip in 0..SPECULAR_MIPS {
+                let roughness = mip as f32 / (SPECULAR_MIPS - 1) as f32;
+                convolve(&mut encoder, &prefilter_pipeline, &face_view(&specular, face, mip), params(face, roughness, 0.0));
+            }
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        EnvironmentMap {
+            resolution,
+            skybox_view,
+            irradiance,
+            specular,
+        }
+    }
+
+    /// Face size of the skybox cube.
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    /// The environment itself, with a mip chain.
+    pub fn skybox(&self) -> &wgpu::TextureView {
+        &self.skybox_view
+    }
+}
+
+/// The irradiance and specular cubes the mesh shaders read through `ENVIRONMENT_SHADER`. They
+/// keep their textures when the environment changes, so the view bind groups that hold them
+/// never need rebuilding; `copy_from` fills them from an `EnvironmentMap`.
+pub struct EnvironmentLighting {
+    irradiance: wgpu::Texture,
+    specular: wgpu::Texture,
+    irradiance_view: wgpu::TextureView,
+    specular_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+}
+
+impl EnvironmentLighting {
+    /// Starts out black.
+    pub fn new(device: &wgpu::Device) -> Self {
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        let irradiance = cube_texture(device, "environment lighting irradiance", IRRADIANCE_SIZE, 1, usage);
+        let specular = cube_texture(device, "environment lighting specular", SPECULAR_SIZE, SPECULAR_MIPS, usage);
+        EnvironmentLighting {
+            irradiance_view: cube_view(&irradiance),
+            specular_view: cube_view(&specular),
+            irradiance,
+            specular,
+            sampler: cube_sampler(device, "environment lighting sampler"),
+        }
+    }
+
+    /// Layout entries for the irradiance cube, specular cube and sampler, from `first_binding`.
+    pub fn layout_entries(first_binding: u32) -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            cube_entry(first_binding),
+            cube_entry(first_binding + 1),
+            sampler_entry(first_binding + 2),
+        ]
+    }
+
+    pub fn bind_group_entries(&self, first_binding: u32) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: first_binding,
+                resource: wgpu::BindingResource::TextureView(&self.irradiance_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 1,
+                resource: wgpu::BindingResource::TextureView(&self.specular_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: first_binding + 2,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+
+    pub fn copy_from(&self, encoder: &mut wgpu::CommandEncoder, environment: &EnvironmentMap) {
+        let copies = [
+            (&environment.irradiance, &self.irradiance, IRRADIANCE_SIZE, 1),
+            (&environment.specular, &self.specular, SPECULAR_SIZE, SPECULAR_MIPS),
+        ];
+        for (source, target, size, mip_levels) in copies {
+            for mip in 0..mip_levels {
+                let mip_size = (size >> mip).max(1);
+                encoder.copy_texture_to_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: source,
+                        mip_level: mip,
+                        origin: wgpu::Origin3d::ZERO,
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::ImageCopyTexture {
+                        texture: target,
+                        mip_level: mip,
+                        origin: wgpu::Origin3d::ZERO,
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::Extent3d {
+                        width: mip_size,
+                        height: mip_size,
+                        depth_or_array_layers: 6,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Draws an `EnvironmentMap` as the background, like `sky::Sky` draws the procedural sky.
+pub struct Skybox {
+    pipeline: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    uniform: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+impl Skybox {
+    /// Draws into targets of `output_format` with `sample_count` samples.
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        let module = fullscreen_module(device, "skybox", include_str!("shaders/skybox.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("skybox layout"),
+            entries: &[uniform_entry(0), cube_entry(1), sampler_entry(2)],
+        });
+        let target = wgpu::ColorTargetState {
+            format: output_format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        };
+        let pipeline = FullscreenPipeline::multisampled(device, "skybox", &module, "fs_main", &[&layout], target, sample_count);
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("skybox params"),
+            size: std::mem::size_of::<SkyboxParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Skybox {
+            pipeline,
+            layout,
+            uniform,
+            sampler: cube_sampler(device, "skybox sampler"),
+            bind_group: None,
+        }
+    }
+
+    /// Rebuilds the pipeline from `module`, an edited `shaders/skybox.wgsl` after
+    /// `FULLSCREEN_SHADER`.
+    pub fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.pipeline.set_module(device, module);
+    }
+
+    /// Shows `environment` from the next `render` on; `None` stops drawing.
+    pub fn set_environment(&mut self, device: &wgpu::Device, environment: Option<&EnvironmentMap>) {
+        self.bind_group = environment.map(|environment| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("skybox bind group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(environment.skybox()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            })
+        });
+    }
+
+    /// Fills `output`, a target of `target_size`, with the environment as seen by `camera`,
+    /// its radiance scaled by `intensity`. Returns false, drawing nothing, without an
+    /// environment.
+    pub fn render(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        target_size: (u32, u32),
+        output: &wgpu::TextureView,
+        intensity: f32,
+    ) -> bool {
+        let Some(bind_group) = &self.bind_group else {
+            return false;
+        };
+        let params = SkyboxParams {
+            inverse_view_projection: camera.view_projection(target_size).inverse().to_cols_array_2d(),
+            intensity: [intensity, 0.0, 0.0, 0.0],
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&params));
+        self.pipeline.draw(encoder, output, &[bind_group], Some(wgpu::Color::BLACK));
+        true
+    }
+}
@@ -0,0 +1,63 @@
+/// Turns variable frame times into whole simulation steps of one fixed length, so physics and
+/// gameplay advance the same way whatever the frame rate. The time left over after the last
+/// step is kept for the next frame, and `alpha` says how far into the coming step the frame
+/// is, for drawing between the last two simulated states.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedTimestep {
+    step: f32,
+    accumulator: f32,
+    /// Most steps one frame may run. After a hitch longer than this many steps the rest of the
+    /// time is dropped, slowing the simulation down instead of falling ever further behind.
+    pub max_steps: u32,
+}
+
+impl FixedTimestep {
+    /// Steps `rate` times per second.
+    pub fn new(rate: f32) -> Self {
+        FixedTimestep {
+            step: 1.0 / rate.max(1.0),
+            accumulator: 0.0,
+            max_steps: 8,
+        }
+    }
+
+    /// Seconds per step.
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    pub fn rate(&self) -> f32 {
+        1.0 / self.step
+    }
+
+    /// Changes the rate, keeping how far into the current step the accumulated time is.
+    pub fn set_rate(&mut self, rate: f32) {
+        let alpha = self.alpha();
+        self.step = 1.0 / rate.max(1.0);
+        self.accumulator = alpha * self.step;
+    }
+
+    /// Adds a frame of `dt` seconds and returns how many steps it completes.
+    pub fn advance(&mut self, dt: f32) -> u32 {
+        self.accumulator += dt.max(0.0);
+        let steps = (self.accumulator / self.step).floor() as u32;
+        if steps > self.max_steps {
+            self.accumulator = 0.0;
+            return self.max_steps;
+        }
+        self.accumulator -= steps as f32 * self.step;
+        // rounding can leave a hair under zero or a whole step
+        self.accumulator = self.accumulator.clamp(0.0, self.step);
+        steps
+    }
+
+    /// How far the frame is between the last step and the next one, 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).min(1.0)
+    }
+
+    /// Drops the accumulated time, e.g. after loading so the first frame doesn't catch up.
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+}
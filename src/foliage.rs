@@ -0,0 +1,452 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Vec2, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    mesh::{Mesh, MeshData, MeshPipelines},
+    random::Rng,
+    render_stats::RenderStats,
+    sky::SunLight,
+    vertex_layout::VertexLayouts,
+};
+
+/// How much foliage grows where, over a rectangle of the ground: 0 is bare, 1 is as dense as
+/// the layer gets. Painted by hand or derived from terrain slope, height or splat maps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DensityMap {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+    /// World XZ of the map's first texel corner.
+    pub origin: Vec2,
+    /// World extent the map covers along X and Z.
+    pub size: Vec2,
+}
+
+impl DensityMap {
+    /// A map of `width` by `height` texels, all at `value`.
+    pub fn new(width: u32, height: u32, origin: Vec2, size: Vec2, value: f32) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        DensityMap {
+            width,
+            height,
+            values: vec![value.clamp(0.0, 1.0); (width * height) as usize],
+            origin,
+            size,
+        }
+    }
+
+    /// A map filled by calling `density` with every texel center's world XZ.
+    pub fn from_fn(width: u32, height: u32, origin: Vec2, size: Vec2, density: impl Fn(Vec2) -> f32) -> Self {
+        let mut map = DensityMap::new(width, height, origin, size, 0.0);
+        for y in 0..map.height {
+            for x in 0..map.width {
+                let uv = (Vec2::new(x as f32, y as f32) + 0.5) / Vec2::new(map.width as f32, map.height as f32);
+                map.set(x, y, density(origin + uv * size));
+            }
+        }
+        map
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.values[(y.min(self.height - 1) * self.width + x.min(self.width - 1)) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, value: f32) {
+        if x < self.width && y < self.height {
+            self.values[(y * self.width + x) as usize] = value.clamp(0.0, 1.0);
+        }
+    }
+
+    /// The bilinearly filtered density at world XZ `position`; 0 outside the map.
+    pub fn sample(&self, position: Vec2) -> f32 {
+        let uv = (position - self.origin) / self.size;
+        if uv.x < 0.0 || uv.y < 0.0 || uv.x > 1.0 || uv.y > 1.0 {
+            return 0.0;
+        }
+        let texel = (uv * Vec2::new(self.width as f32, self.height as f32) - 0.5).max(Vec2::ZERO);
+        let (x, y) = (texel.x as u32, texel.y as u32);
+        let f = texel - Vec2::new(x as f32, y as f32);
+        let top = self.get(x, y) + (self.get(x + 1, y) - self.get(x, y)) * f.x;
+        let bottom = self.get(x, y + 1) + (self.get(x + 1, y + 1) - self.get(x, y + 1)) * f.x;
+        top + (bottom - top) * f.y
+    }
+}
+
+/// How one kind of plant is scattered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScatterSettings {
+    /// Instances per square world unit where the density map is 1.
+    pub instances_per_unit: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// Instances are grouped into square chunks of this size, the unit of distance culling.
+    pub chunk_size: f32,
+    /// The same seed and map always scatter the same instances.
+    pub seed: u64,
+}
+
+impl Default for ScatterSettings {
+    fn default() -> Self {
+        ScatterSettings {
+            instances_per_unit: 8.0,
+            min_scale: 0.7,
+            max_scale: 1.3,
+            chunk_size: 16.0,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FoliageSettings {
+    /// Where instances start thinning out, in world units from the camera.
+    pub fade_start: f32,
+    /// Where the last instances are gone and chunks stop being drawn.
+    pub fade_end: f32,
+    /// Direction the wind blows in on the XZ plane; its length is how far the tips bend.
+    pub wind: Vec2,
+    /// Sways per second, roughly.
+    pub wind_frequency: f32,
+    /// Mesh height at and above which vertices sway fully; the mesh's base stays put.
+    pub sway_height: f32,
+    /// Colors at the base and the top of the mesh, multiplied with its vertex colors.
+    pub base_color: [f32; 4],
+    pub tip_color: [f32; 4],
+    pub ambient: Vec3,
+}
+
+impl Default for FoliageSettings {
+    fn default() -> Self {
+        FoliageSettings {
+            fade_start: 40.0,
+            fade_end: 80.0,
+            wind: Vec2::new(0.15, 0.05),
+            wind_frequency: 1.5,
+            sway_height: 1.0,
+            base_color: [0.08, 0.2, 0.04, 1.0],
+            tip_color: [0.35, 0.55, 0.15, 1.0],
+            ambient: Vec3::splat(0.15),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct FoliageInstance {
+    offset_scale: [f32; 4],
+    // yaw, sway phase, fade threshold, color variation
+    params: [f32; 4],
+}
+
+impl FoliageInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![8 => Float32x4, 9 => Float32x4];
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ViewUniform {
+    view_projection: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    ambient: [f32; 4],
+    wind: [f32; 4],
+    fade: [f32; 4],
+    base_color: [f32; 4],
+    tip_color: [f32; 4],
+}
+
+struct Chunk {
+    min: Vec2,
+    max: Vec2,
+    instances: wgpu::Buffer,
+    count: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FoliageLayerId(u32);
+
+struct Layer {
+    id: FoliageLayerId,
+    mesh: Arc<Mesh>,
+    chunks: Vec<Chunk>,
+}
+
+/// Grass and other vegetation scattered over the ground by density maps and drawn with GPU
+/// instancing, one draw per chunk in range. Like `DebugLineRenderer` it draws into an existing
+/// scene pass: call `prepare` once a frame, then `draw` inside the pass.
+pub struct Foliage {
+    pub settings: FoliageSettings,
+    layouts: VertexLayouts,
+    pipelines: MeshPipelines,
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    layers: Vec<Layer>,
+    next_id: u32,
+    // this frame's draws: layer index, pipeline and chunk index
+    visible: Vec<(usize, Arc<wgpu::RenderPipeline>, usize)>,
+}
+
+impl Foliage {
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("foliage layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("foliage pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipelines = MeshPipelines::new("foliage", include_str!("shaders/foliage.wgsl"), move |device, module, vertex_buffer| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("foliage"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module,
+                    entry_point: "vs_main",
+                    buffers: &[
+                        vertex_buffer,
+                        wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<FoliageInstance>() as wgpu::BufferAddress,
+                            step_mode: wgpu::VertexStepMode::Instance,
+                            attributes: &FoliageInstance::ATTRIBUTES,
+                        },
+                    ],
+                },
+                // blades are flat, so both sides are drawn
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: depth_format,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: color_format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                multiview: None,
+            })
+        });
+
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("foliage view"),
+            size: std::mem::size_of::<ViewUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("foliage bind group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+        });
+
+        Foliage {
+            settings: FoliageSettings::default(),
+            layouts: VertexLayouts::new(),
+            pipelines,
+            uniform,
+            bind_group,
+            layers: Vec::new(),
+            next_id: 0,
+            visible: Vec::new(),
+        }
+    }
+
+    /// Uploads a plant mesh for `scatter`. Its origin is where it meets the ground, Y up.
+    pub fn upload(&mut self, device: &wgpu::Device, label: &str, data: &MeshData) -> Arc<Mesh> {
+        Arc::new(Mesh::new(device, &mut self.layouts, label, data))
+    }
+
+    /// Scatters instances of `mesh` over `density`, standing each on the ground at the height
+    /// `ground` gives for a world XZ position, and adds them as a new layer.
+    pub fn scatter(
+        &mut self,
+        device: &wgpu::Device,
+        mesh: &Arc<Mesh>,
+        density: &DensityMap,
+        settings: &ScatterSettings,
+        ground: impl Fn(Vec2) -> f32,
+    ) -> FoliageLayerId {
+        let chunk_size = settings.chunk_size.max(1.0);
+        let chunks_x = (density.size.x / chunk_size).ceil().max(1.0) as u32;
+        let chunks_z = (density.size.y / chunk_size).ceil().max(1.0) as u32;
+        let mut chunks = Vec::new();
+        let mut instances = Vec::new();
+        for cz in 0..chunks_z {
+            for cx in 0..chunks_x {
+                // a stream per chunk keeps chunks the same when the map is edited elsewhere
+                let mut rng = Rng::with_stream(settings.seed, (cz * chunks_x + cx) as u64);
+                let min = density.origin + Vec2::new(cx as f32, cz as f32) * chunk_size;
+                let max = (min + Vec2::splat(chunk_size)).min(density.origin + density.size);
+                let area = (max - min).x * (max - min).y;
+                let candidates = (area * settings.instances_per_unit.max(0.0)).round() as u32;
+
+                instances.clear();
+                for _ in 0..candidates {
+                    let position = Vec2::new(rng.range_f32(min.x, max.x), rng.range_f32(min.y, max.y));
+                    if rng.next_f32() >= density.sample(position) {
+                        continue;
+                    }
+                    let scale = rng.range_f32(settings.min_scale, settings.max_scale.max(settings.min_scale));
+                    instances.push(FoliageInstance {
+                        offset_scale: [position.x, ground(position), position.y, scale],
+                        params: [
+                            rng.range_f32(0.0, std::f32::consts::TAU),
+                            rng.range_f32(0.0, std::f32::consts::TAU),
+                            rng.next_f32() * 0.9,
+                            rng.next_f32(),
+                        ],
+                    });
+                }
+                if instances.is_empty() {
+                    continue;
+                }
+                chunks.push(Chunk {
+                    min,
+                    max,
+                    instances: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("foliage instances"),
+                        contents: bytemuck::cast_slice(&instances),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    }),
+                    count: instances.len() as u32,
+                });
+            }
+        }
+
+        let id = FoliageLayerId(self.next_id);
+        self.next_id += 1;
+        self.layers.push(Layer {
+            id,
+            mesh: mesh.clone(),
+            chunks,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: FoliageLayerId) {
+        self.layers.retain(|layer| layer.id != id);
+    }
+
+    pub fn clear(&mut self) {
+        self.layers.clear();
+    }
+
+    /// Instances across every layer.
+    pub fn instance_count(&self) -> u32 {
+        self.layers.iter().flat_map(|layer| &layer.chunks).map(|chunk| chunk.count).sum()
+    }
+
+    /// Picks the chunks within the fade distance of `camera_position` and uploads the view;
+    /// call before starting the pass `draw` records into. `time` in seconds drives the wind.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_projection: Mat4,
+        camera_position: Vec3,
+        sun: &SunLight,
+        time: f32,
+    ) {
+        let settings = self.settings;
+        let fade_end = settings.fade_end.max(settings.fade_start + 0.01);
+        let view = ViewUniform {
+            view_projection: view_projection.to_cols_array_2d(),
+            camera_position: camera_position.extend(1.0).into(),
+            light_direction: sun.direction.normalize_or_zero().extend(0.0).into(),
+            light_color: sun.color.extend(0.0).into(),
+            ambient: settings.ambient.extend(0.0).into(),
+            wind: [settings.wind.x, settings.wind.y, time, settings.wind_frequency * std::f32::consts::TAU],
+            fade: [settings.fade_start, fade_end, settings.sway_height.max(0.001), 0.0],
+            base_color: settings.base_color,
+            tip_color: settings.tip_color,
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&view));
+
+        self.visible.clear();
+        let camera = Vec2::new(camera_position.x, camera_position.z);
+        for (index, layer) in self.layers.iter().enumerate() {
+            let pipeline = match self.pipelines.get(device, &self.layouts, layer.mesh.layout) {
+                Ok(pipeline) => pipeline,
+                Err(e) => {
+                    log::warn!("Skipping foliage layer: {}", e);
+                    continue;
+                }
+            };
+            for (chunk_index, chunk) in layer.chunks.iter().enumerate() {
+                let nearest = camera.clamp(chunk.min, chunk.max);
+                if nearest.distance(camera) < fade_end {
+                    self.visible.push((index, pipeline.clone(), chunk_index));
+                }
+            }
+        }
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, stats: &mut RenderStats) {
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        for (layer, pipeline, chunk) in &self.visible {
+            let layer = &self.layers[*layer];
+            let chunk = &layer.chunks[*chunk];
+            pass.set_pipeline(pipeline);
+            pass.set_vertex_buffer(0, layer.mesh.vertex_buffer.slice(..));
+            pass.set_vertex_buffer(1, chunk.instances.slice(..));
+            pass.set_index_buffer(layer.mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            pass.draw_indexed(0..layer.mesh.index_count, 0, 0..chunk.count);
+            stats.record_draw(layer.mesh.triangles(), chunk.count);
+        }
+    }
+}
+
+/// A grass blade `height` tall and `width` wide at its base, tapering to a point over
+/// `segments` rows so it bends smoothly in the wind. Faces +Z.
+pub fn grass_blade(width: f32, height: f32, segments: u32) -> MeshData {
+    let segments = segments.max(1);
+    let mut data = MeshData::default();
+    for row in 0..=segments {
+        let t = row as f32 / segments as f32;
+        let half = width * 0.5 * (1.0 - t);
+        // a slight curve forwards, so blades don't look like paper
+        let z = t * t * height * 0.15;
+        for side in [-1.0, 1.0] {
+            data.positions.push([side * half, t * height, z]);
+            data.normals.push([0.0, 0.3, 1.0]);
+            data.uvs.push([side * 0.5 + 0.5, 1.0 - t]);
+        }
+    }
+    for row in 0..segments {
+        let first = row * 2;
+        data.indices.extend([first, first + 1, first + 3, first, first + 3, first + 2]);
+    }
+    data
+}
@@ -0,0 +1,139 @@
+use glam::Vec3;
+
+pub const MAX_MESHLET_VERTICES: usize = 64;
+/// Keeps a meshlet's triangle index within the 7 bits the visibility buffer stores it in.
+pub const MAX_MESHLET_TRIANGLES: usize = 124;
+
+/// A cluster of up to `MAX_MESHLET_TRIANGLES` triangles over at most `MAX_MESHLET_VERTICES`
+/// vertices, with the bounds it is culled by.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Meshlet {
+    pub center: [f32; 3],
+    pub radius: f32,
+    /// Average facing of the triangles; with `cone_cutoff` the whole cluster can be rejected
+    /// when seen from behind.
+    pub cone_axis: [f32; 3],
+    /// Sine of the widest angle between a triangle normal and the axis, 1 to never cull.
+    pub cone_cutoff: f32,
+    /// Start of the mesh vertex indices in `MeshletMesh::data`.
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    /// Start of the packed triangles in `MeshletMesh::data`, three local indices a word.
+    pub triangle_offset: u32,
+    pub triangle_count: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshletVertex {
+    pub position: [f32; 4],
+    pub normal: [f32; 4],
+}
+
+/// An indexed mesh split into meshlets for the GPU-driven path.
+#[derive(Clone, Debug, Default)]
+pub struct MeshletMesh {
+    pub vertices: Vec<MeshletVertex>,
+    pub meshlets: Vec<Meshlet>,
+    /// Each meshlet's vertex indices followed by its triangles.
+    pub data: Vec<u32>,
+}
+
+impl MeshletMesh {
+    /// Greedily fills meshlets in index order, so meshes already optimized for vertex cache
+    /// locality give the tightest clusters.
+    pub fn build(positions: &[[f32; 3]], normals: &[[f32; 3]], indices: &[u32]) -> Self {
+        assert_eq!(positions.len(), normals.len(), "every vertex needs a normal");
+        let mut mesh = MeshletMesh {
+            vertices: positions
+                .iter()
+                .zip(normals)
+                .map(|(&[px, py, pz], &[nx, ny, nz])| MeshletVertex {
+                    position: [px, py, pz, 1.0],
+                    normal: [nx, ny, nz, 0.0],
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let mut vertices: Vec<u32> = Vec::with_capacity(MAX_MESHLET_VERTICES);
+        let mut triangles: Vec<[u8; 3]> = Vec::with_capacity(MAX_MESHLET_TRIANGLES);
+        for triangle in indices.chunks_exact(3) {
+            let new_vertices = triangle
+                .iter()
+                .enumerate()
+                .filter(|&(i, index)| !vertices.contains(index) && !triangle[..i].contains(index))
+                .count();
+            if vertices.len() + new_vertices > MAX_MESHLET_VERTICES || triangles.len() == MAX_MESHLET_TRIANGLES {
+                mesh.push_meshlet(&vertices, &triangles);
+                vertices.clear();
+                triangles.clear();
+            }
+            let mut local = [0u8; 3];
+            for (slot, &index) in local.iter_mut().zip(triangle) {
+                *slot = match vertices.iter().position(|&v| v == index) {
+                    Some(position) => position as u8,
+                    None => {
+                        vertices.push(index);
+                        (vertices.len() - 1) as u8
+                    }
+                };
+            }
+            triangles.push(local);
+        }
+        if !triangles.is_empty() {
+            mesh.push_meshlet(&vertices, &triangles);
+        }
+        mesh
+    }
+
+    fn push_meshlet(&mut self, vertices: &[u32], triangles: &[[u8; 3]]) {
+        let point = |vertex: u32| Vec3::from_slice(&self.vertices[vertex as usize].position[..3]);
+        let position = |local: u8| point(vertices[local as usize]);
+
+        let center = vertices.iter().fold(Vec3::ZERO, |sum, &v| sum + point(v)) / vertices.len() as f32;
+        let radius = vertices.iter().map(|&v| point(v).distance(center)).fold(0.0, f32::max);
+
+        let normals: Vec<Vec3> = triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                (position(b) - position(a))
+                    .cross(position(c) - position(a))
+                    .normalize_or_zero()
+            })
+            .collect();
+        let axis = normals.iter().sum::<Vec3>().normalize_or_zero();
+        let min_dot = normals.iter().map(|n| n.dot(axis)).fold(1.0, f32::min);
+        // a cone wider than a hemisphere can always be seen from the front somewhere
+        let cone_cutoff = if axis == Vec3::ZERO || min_dot <= 0.0 {
+            1.0
+        } else {
+            (1.0 - min_dot * min_dot).sqrt()
+        };
+
+        self.meshlets.push(Meshlet {
+            center: center.into(),
+            radius,
+            cone_axis: axis.into(),
+            cone_cutoff,
+            vertex_offset: self.data.len() as u32,
+            vertex_count: vertices.len() as u32,
+            triangle_offset: (self.data.len() + vertices.len()) as u32,
+            triangle_count: triangles.len() as u32,
+        });
+        self.data.extend_from_slice(vertices);
+        self.data.extend(
+            triangles
+                .iter()
+                .map(|&[a, b, c]| a as u32 | (b as u32) << 8 | (c as u32) << 16),
+        );
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.meshlets
+            .iter()
+            .map(|meshlet| meshlet.triangle_count as usize)
+            .sum()
+    }
+}
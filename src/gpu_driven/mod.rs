@@ -0,0 +1,685 @@
+pub mod meshlets;
+
+use glam::{Mat4, Vec3, Vec4};
+
+use self::meshlets::{Meshlet, MeshletMesh, MeshletVertex};
+use crate::{
+    camera::Camera,
+    capabilities::Capabilities,
+    mesh_renderer::MeshRenderer,
+    postprocess::fullscreen_module,
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+const COMMON_SHADER: &str = include_str!("../shaders/gpu_driven.wgsl");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GpuMeshId(usize);
+
+#[derive(Clone, Copy, Debug)]
+pub struct GpuInstance {
+    pub mesh: GpuMeshId,
+    pub transform: Mat4,
+    pub color: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GpuDrivenSettings {
+    pub frustum_culling: bool,
+    /// Rejects clusters whose triangles all face away from the camera.
+    pub cone_culling: bool,
+    /// Direction the light travels in.
+    pub light_direction: Vec3,
+    /// Color times intensity, in the same units as the HDR scene.
+    pub light_color: Vec3,
+    pub ambient: Vec3,
+}
+
+impl Default for GpuDrivenSettings {
+    fn default() -> Self {
+        GpuDrivenSettings {
+            frustum_culling: true,
+            cone_culling: true,
+            light_direction: Vec3::new(-0.4, -1.0, -0.3).normalize(),
+            light_color: Vec3::splat(2.0),
+            ambient: Vec3::splat(0.1),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    view_projection: [[f32; 4]; 4],
+    inverse_view_projection: [[f32; 4]; 4],
+    frustum: [[f32; 4]; 6],
+    camera_position: [f32; 4],
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    ambient: [f32; 4],
+    counts: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct InstanceData {
+    model: [[f32; 4]; 4],
+    color: [f32; 4],
+    meshlets: [u32; 4],
+}
+
+/// Frustum planes of `view_projection` for depth in 0 to 1, normalized, pointing inwards.
+fn frustum_planes(view_projection: Mat4) -> [[f32; 4]; 6] {
+    let rows = view_projection.transpose();
+    let (x, y, z, w) = (rows.x_axis, rows.y_axis, rows.z_axis, rows.w_axis);
+    [w + x, w - x, w + y, w - y, z, w - z]
+        .map(|plane: Vec4| (plane / plane.truncate().length().max(f32::EPSILON)).into())
+}
+
+/// How meshlet geometry reaches the rasterizer. wgpu 0.12 has no task or mesh shader stage on
+/// any backend, so there is no mesh shading path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeometryPath {
+    /// Ordinary vertex and index buffers, drawn per mesh by `MeshRenderer`.
+    Vertex,
+    /// `GpuDrivenRenderer`: compute culling into one indirect draw of a visibility buffer.
+    GpuDriven,
+}
+
+impl GeometryPath {
+    /// The GPU-driven path where the device supports it, otherwise the vertex path. The engine
+    /// creates its `GpuDrivenRenderer` by this when `EngineConfig::gpu_driven` is set.
+    pub fn select(capabilities: &Capabilities) -> Self {
+        if capabilities.gpu_driven() {
+            GeometryPath::GpuDriven
+        } else {
+            GeometryPath::Vertex
+        }
+    }
+}
+
+struct Buffers {
+    instances: wgpu::Buffer,
+    clusters: wgpu::Buffer,
+    visible: wgpu::Buffer,
+    draw: wgpu::Buffer,
+    /// Clusters the instance buffers have room for.
+    capacity: usize,
+    scene_group: wgpu::BindGroup,
+    cull_group: wgpu::BindGroup,
+    raster_group: wgpu::BindGroup,
+}
+
+struct Targets {
+    size: (u32, u32),
+    visibility: wgpu::TextureView,
+    depth: wgpu::TextureView,
+}
+
+/// Experimental GPU-driven geometry path for very dense scenes. Meshes are split into meshlets
+/// that live in storage buffers together with the instances; each frame a compute pass culls
+/// every cluster against the frustum and its normal cone, one indirect draw rasterizes the
+/// survivors into a visibility buffer of triangle ids, and a fullscreen resolve shades each
+/// pixel from the triangle it refetches, depth testing against the scene drawn before it.
+/// Check `Capabilities::gpu_driven` first.
+pub struct GpuDrivenRenderer {
+    pub settings: GpuDrivenSettings,
+    cull: wgpu::ComputePipeline,
+    raster: wgpu::RenderPipeline,
+    resolve_module: wgpu::ShaderModule,
+    resolve_pipeline_layout: wgpu::PipelineLayout,
+    output_format: wgpu::TextureFormat,
+    // rebuilt when the scene's sample count changes
+    resolve: Option<(u32, wgpu::RenderPipeline)>,
+    scene_layout: wgpu::BindGroupLayout,
+    cull_layout: wgpu::BindGroupLayout,
+    raster_layout: wgpu::BindGroupLayout,
+    resolve_layout: wgpu::BindGroupLayout,
+    uniform: wgpu::Buffer,
+    meshes: Vec<(u32, u32)>,
+    vertices: Vec<MeshletVertex>,
+    meshlets: Vec<Meshlet>,
+    data: Vec<u32>,
+    instances: Vec<GpuInstance>,
+    cluster_count: usize,
+    geometry_dirty: bool,
+    instances_dirty: bool,
+    buffers: Option<Buffers>,
+    targets: Option<Targets>,
+    resolve_group: Option<wgpu::BindGroup>,
+}
+
+impl GpuDrivenRenderer {
+    pub const VISIBILITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let storage = |binding, visibility, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = |label, entries: &[wgpu::BindGroupLayoutEntry]| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries,
+            })
+        };
+        let all = wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX_FRAGMENT;
+        let scene_layout = layout(
+            "gpu driven scene layout",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: all,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, all, true),
+                storage(2, all, true),
+                storage(3, all, true),
+                storage(4, all, true),
+                storage(5, all, true),
+            ],
+        );
+        let cull_layout = layout(
+            "gpu cull layout",
+            &[
+                storage(0, wgpu::ShaderStages::COMPUTE, false),
+                storage(1, wgpu::ShaderStages::COMPUTE, false),
+            ],
+        );
+        let raster_layout = layout(
+            "visibility raster layout",
+            &[storage(0, wgpu::ShaderStages::VERTEX, true)],
+        );
+        let resolve_layout = layout(
+            "visibility resolve layout",
+            &[
+                storage(0, wgpu::ShaderStages::FRAGMENT, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        );
+
+        let module = |label, source: &str| {
+            device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", COMMON_SHADER, source).into()),
+            })
+        };
+        let pipeline_layout = |label, layouts: &[&wgpu::BindGroupLayout]| {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: layouts,
+                push_constant_ranges: &[],
+            })
+        };
+
+        let cull_module = module("gpu cull", include_str!("../shaders/gpu_cull.wgsl"));
+        let cull = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("gpu cull"),
+            layout: Some(&pipeline_layout("gpu cull", &[&scene_layout, &cull_layout])),
+            module: &cull_module,
+            entry_point: "cull",
+        });
+
+        let raster_module = module("visibility raster", include_str!("../shaders/visibility.wgsl"));
+        let raster = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("visibility raster"),
+            layout: Some(&pipeline_layout("visibility raster", &[&scene_layout, &raster_layout])),
+            vertex: wgpu::VertexState {
+                module: &raster_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Self::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &raster_module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: Self::VISIBILITY_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            multiview: None,
+        });
+
+        let resolve_source = format!(
+            "{}\n{}",
+            COMMON_SHADER,
+            include_str!("../shaders/visibility_resolve.wgsl")
+        );
+        let resolve_module = fullscreen_module(device, "visibility resolve", &resolve_source);
+        let resolve_pipeline_layout = pipeline_layout("visibility resolve", &[&scene_layout, &resolve_layout]);
+
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu driven params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        GpuDrivenRenderer {
+            settings: GpuDrivenSettings::default(),
+            cull,
+            raster,
+            resolve_module,
+            resolve_pipeline_layout,
+            output_format,
+            resolve: None,
+            scene_layout,
+            cull_layout,
+            raster_layout,
+            resolve_layout,
+            uniform,
+            meshes: Vec::new(),
+            vertices: Vec::new(),
+            meshlets: Vec::new(),
+            data: Vec::new(),
+            instances: Vec::new(),
+            cluster_count: 0,
+            geometry_dirty: true,
+            instances_dirty: true,
+            buffers: None,
+            targets: None,
+            resolve_group: None,
+        }
+    }
+
+    /// Appends the mesh to the shared geometry buffers, uploaded on the next `prepare`.
+    pub fn add_mesh(&mut self, mesh: &MeshletMesh) -> GpuMeshId {
+        let base_vertex = self.vertices.len() as u32;
+        let base_data = self.data.len() as u32;
+        let first_meshlet = self.meshlets.len() as u32;
+        self.vertices.extend_from_slice(&mesh.vertices);
+        self.meshlets.extend(mesh.meshlets.iter().map(|meshlet| Meshlet {
+            vertex_offset: meshlet.vertex_offset + base_data,
+            triangle_offset: meshlet.triangle_offset + base_data,
+            ..*meshlet
+        }));
+        // vertex indices become global; packed triangles stay local to their meshlet
+        let mut data = mesh.data.clone();
+        for meshlet in &mesh.meshlets {
+            let start = meshlet.vertex_offset as usize;
+            for index in &mut data[start..start + meshlet.vertex_count as usize] {
+                *index += base_vertex;
+            }
+        }
+        self.data.extend(data);
+        self.meshes.push((first_meshlet, mesh.meshlets.len() as u32));
+        self.geometry_dirty = true;
+        GpuMeshId(self.meshes.len() - 1)
+    }
+
+    /// Replaces every instance. Call again whenever one moves.
+    pub fn set_instances(&mut self, instances: &[GpuInstance]) {
+        self.instances.clear();
+        self.instances.extend_from_slice(instances);
+        self.cluster_count = instances
+            .iter()
+            .map(|instance| self.meshes[instance.mesh.0].1 as usize)
+            .sum();
+        self.instances_dirty = true;
+    }
+
+    /// Clusters submitted for culling each frame.
+    pub fn cluster_count(&self) -> usize {
+        self.cluster_count
+    }
+
+    /// Depth of the last frame, for drawing other geometry on top.
+    pub fn depth(&self) -> Option<&wgpu::TextureView> {
+        self.targets.as_ref().map(|targets| &targets.depth)
+    }
+
+    fn create_buffers(&self, device: &wgpu::Device, capacity: usize) -> Buffers {
+        use wgpu::util::DeviceExt;
+        // bindings can't be empty, so every buffer holds at least one element
+        let init = |label, contents: &[u8], usage| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: if contents.is_empty() { &[0; 64] } else { contents },
+                usage,
+            })
+        };
+        let sized = |label, size: usize, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: size as u64,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let meshlets = init(
+            "meshlets",
+            bytemuck::cast_slice(&self.meshlets),
+            wgpu::BufferUsages::STORAGE,
+        );
+        let data = init(
+            "meshlet data",
+            bytemuck::cast_slice(&self.data),
+            wgpu::BufferUsages::STORAGE,
+        );
+        let vertices = init(
+            "meshlet vertices",
+            bytemuck::cast_slice(&self.vertices),
+            wgpu::BufferUsages::STORAGE,
+        );
+        let copy_storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        let instances = sized(
+            "gpu instances",
+            capacity * std::mem::size_of::<InstanceData>(),
+            copy_storage,
+        );
+        let clusters = sized("clusters", capacity * 8, copy_storage);
+        let visible = sized("visible clusters", capacity * 4, wgpu::BufferUsages::STORAGE);
+        let draw = sized("cluster draw", 16, copy_storage | wgpu::BufferUsages::INDIRECT);
+
+        let group = |layout, buffers: &[&wgpu::Buffer]| {
+            let entries: Vec<wgpu::BindGroupEntry> = buffers
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: buffer.as_entire_binding(),
+                })
+                .collect();
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("gpu driven bind group"),
+                layout,
+                entries: &entries,
+            })
+        };
+        let scene_group = group(
+            &self.scene_layout,
+            &[&self.uniform, &meshlets, &instances, &clusters, &data, &vertices],
+        );
+        let cull_group = group(&self.cull_layout, &[&visible, &draw]);
+        let raster_group = group(&self.raster_layout, &[&visible]);
+
+        Buffers {
+            instances,
+            clusters,
+            visible,
+            draw,
+            capacity,
+            scene_group,
+            cull_group,
+            raster_group,
+        }
+    }
+
+    fn resolve_pipeline(&self, device: &wgpu::Device, sample_count: u32) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("visibility resolve"),
+            layout: Some(&self.resolve_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.resolve_module,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            // the resolve writes the visibility depth, so clusters behind the scene are hidden
+            // and what is drawn after sees them
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: MeshRenderer::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.resolve_module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: self.output_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            multiview: None,
+        })
+    }
+
+    fn ensure_targets(&mut self, device: &wgpu::Device, (width, height): (u32, u32)) {
+        if matches!(&self.targets, Some(targets) if targets.size == (width, height)) {
+            return;
+        }
+        let create = |label, format| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        self.targets = Some(Targets {
+            size: (width, height),
+            visibility: create("visibility buffer", Self::VISIBILITY_FORMAT),
+            depth: create("visibility depth", Self::DEPTH_FORMAT),
+        });
+        self.resolve_group = None;
+    }
+
+    /// Uploads changed geometry and instances and this frame's view. The target is the whole
+    /// of `target_size` with `sample_count` samples, viewed through `camera`'s viewport.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        target_size: (u32, u32),
+        sample_count: u32,
+    ) {
+        let needed = self.cluster_count.max(1);
+        if self.geometry_dirty || self.buffers.as_ref().is_none_or(|buffers| buffers.capacity < needed) {
+            let capacity = self
+                .buffers
+                .as_ref()
+                .map_or(0, |buffers| buffers.capacity)
+                .max(needed.next_power_of_two());
+            self.buffers = Some(self.create_buffers(device, capacity));
+            self.geometry_dirty = false;
+            self.instances_dirty = true;
+            self.resolve_group = None;
+        }
+        self.ensure_targets(device, (target_size.0.max(1), target_size.1.max(1)));
+        if self.resolve.as_ref().is_none_or(|(samples, _)| *samples != sample_count) {
+            self.resolve = Some((sample_count, self.resolve_pipeline(device, sample_count)));
+        }
+
+        let buffers = self.buffers.as_ref().unwrap();
+        if self.instances_dirty {
+            let mut instances = Vec::with_capacity(self.instances.len());
+            let mut clusters = Vec::with_capacity(self.cluster_count);
+            for (index, instance) in self.instances.iter().enumerate() {
+                let (first, count) = self.meshes[instance.mesh.0];
+                instances.push(InstanceData {
+                    model: instance.transform.to_cols_array_2d(),
+                    color: instance.color,
+                    meshlets: [first, count, 0, 0],
+                });
+                clusters.extend((first..first + count).map(|meshlet| [index as u32, meshlet]));
+            }
+            queue.write_buffer(&buffers.instances, 0, bytemuck::cast_slice(&instances));
+            queue.write_buffer(&buffers.clusters, 0, bytemuck::cast_slice(&clusters));
+            self.instances_dirty = false;
+        }
+
+        let view_projection = camera.view_projection(target_size);
+        let settings = &self.settings;
+        let params = Params {
+            view_projection: view_projection.to_cols_array_2d(),
+            inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+            frustum: frustum_planes(view_projection),
+            camera_position: camera.position.extend(1.0).into(),
+            light_direction: settings.light_direction.normalize_or_zero().extend(0.0).into(),
+            light_color: settings.light_color.extend(0.0).into(),
+            ambient: settings.ambient.extend(0.0).into(),
+            counts: [
+                self.cluster_count as u32,
+                settings.frustum_culling as u32,
+                settings.cone_culling as u32,
+                0,
+            ],
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&params));
+        // the cull pass counts visible vertices up from zero
+        queue.write_buffer(&buffers.draw, 0, bytemuck::cast_slice(&[0u32, 1, 0, 0]));
+
+        if self.resolve_group.is_none() {
+            let targets = self.targets.as_ref().unwrap();
+            self.resolve_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("visibility resolve bind group"),
+                layout: &self.resolve_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffers.visible.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&targets.visibility),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&targets.depth),
+                    },
+                ],
+            }));
+        }
+    }
+
+    /// Culls, rasterizes the visibility buffer and resolves it into `output`, keeping what is
+    /// already there where no cluster was drawn or `depth` is nearer. `depth` is the scene's
+    /// depth buffer, in `MeshRenderer::DEPTH_FORMAT` with the sample count given to `prepare`,
+    /// and gets the clusters' depth.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        depth_load: wgpu::LoadOp<f32>,
+    ) {
+        let (Some(buffers), Some(targets), Some(resolve_group), Some((_, resolve))) =
+            (&self.buffers, &self.targets, &self.resolve_group, &self.resolve)
+        else {
+            return;
+        };
+        if self.cluster_count == 0 {
+            return;
+        }
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("gpu cull"),
+            });
+            pass.set_pipeline(&self.cull);
+            pass.set_bind_group(0, &buffers.scene_group, &[]);
+            pass.set_bind_group(1, &buffers.cull_group, &[]);
+            pass.dispatch((self.cluster_count as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("visibility buffer"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &targets.visibility,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // 0 marks empty pixels
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &targets.depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            pass.set_pipeline(&self.raster);
+            pass.set_bind_group(0, &buffers.scene_group, &[]);
+            pass.set_bind_group(1, &buffers.raster_group, &[]);
+            pass.draw_indirect(&buffers.draw, 0);
+        }
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("visibility resolve"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: depth_load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        pass.set_pipeline(resolve);
+        pass.set_bind_group(0, &buffers.scene_group, &[]);
+        pass.set_bind_group(1, resolve_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
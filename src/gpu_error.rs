@@ -0,0 +1,55 @@
+use std::fmt;
+
+/// A wgpu error together with what the engine was doing when it happened.
+#[derive(Debug)]
+pub struct GpuError {
+    pub context: String,
+    pub error: wgpu::Error,
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} while {}: {}", kind(&self.error), self.context, self.error)
+    }
+}
+
+impl std::error::Error for GpuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+fn kind(error: &wgpu::Error) -> &'static str {
+    match error {
+        wgpu::Error::OutOfMemory { .. } => "out of memory",
+        wgpu::Error::Validation { .. } => "validation error",
+    }
+}
+
+/// Replaces wgpu's default handler, which panics on the first error that isn't caught by an
+/// error scope. Errors are logged instead so a bad draw or resource shows up in the console
+/// without taking the whole engine down.
+pub fn install_handler(device: &wgpu::Device) {
+    device.on_uncaptured_error(|error| {
+        log::error!("Uncaptured wgpu {}: {}", kind(&error), error);
+    });
+}
+
+/// Runs `f` inside validation and out-of-memory error scopes, returning the first error raised
+/// by anything it created or recorded on `device`.
+pub fn capture<T>(device: &wgpu::Device, context: impl Into<String>, f: impl FnOnce() -> T) -> Result<T, GpuError> {
+    device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = f();
+    // both futures are already resolved on native backends; block_on just unwraps them
+    let validation = pollster::block_on(device.pop_error_scope());
+    let out_of_memory = pollster::block_on(device.pop_error_scope());
+
+    match validation.or(out_of_memory) {
+        Some(error) => Err(GpuError {
+            context: context.into(),
+            error,
+        }),
+        None => Ok(value),
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MemoryCategory {
+    Textures,
+    Meshes,
+    RenderTargets,
+    Uniforms,
+    Other,
+}
+
+impl MemoryCategory {
+    pub const ALL: [MemoryCategory; 5] = [
+        MemoryCategory::Textures,
+        MemoryCategory::Meshes,
+        MemoryCategory::RenderTargets,
+        MemoryCategory::Uniforms,
+        MemoryCategory::Other,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AllocationId(u64);
+
+/// Bytes occupied by mip levels `first_mip..mip_level_count` of a texture described by `desc`.
+pub fn texture_size(desc: &wgpu::TextureDescriptor, first_mip: u32) -> u64 {
+    let info = desc.format.describe();
+    let (block_width, block_height) = (info.block_dimensions.0 as u64, info.block_dimensions.1 as u64);
+    let is_3d = desc.dimension == wgpu::TextureDimension::D3;
+    (first_mip..desc.mip_level_count)
+        .map(|level| {
+            let size = desc.size.mip_level_size(level, is_3d);
+            let blocks_x = (size.width as u64).div_ceil(block_width);
+            let blocks_y = (size.height as u64).div_ceil(block_height);
+            blocks_x * blocks_y * size.depth_or_array_layers as u64 * info.block_size as u64
+        })
+        .sum::<u64>()
+        * desc.sample_count as u64
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryBudget {
+    /// Soft limit for everything the engine allocates.
+    pub total: u64,
+    /// Optional per-category limits on top of `total`.
+    pub per_category: HashMap<MemoryCategory, u64>,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        MemoryBudget {
+            total: 1024 * 1024 * 1024,
+            per_category: HashMap::new(),
+        }
+    }
+}
+
+struct Allocation {
+    category: MemoryCategory,
+    bytes: u64,
+}
+
+/// A texture whose top mip levels may be dropped to get back under budget.
+struct Residency {
+    desc: wgpu::TextureDescriptor<'static>,
+    /// Most detailed mip level currently resident.
+    first_mip: u32,
+    /// Never drop below this many of the smallest mips.
+    min_resident_mips: u32,
+    last_used_frame: u64,
+}
+
+impl Residency {
+    fn lowest_first_mip(&self) -> u32 {
+        self.desc.mip_level_count.saturating_sub(self.min_resident_mips.max(1))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MipChange {
+    pub texture: AllocationId,
+    /// Most detailed mip the texture should keep resident from now on.
+    pub first_mip: u32,
+}
+
+/// Tracks how much VRAM the engine has allocated per category and decides which textures give
+/// up detail when the budget is exceeded. Owners of evictable textures apply the returned
+/// `MipChange`s by recreating or re-streaming the texture, then report back with `set_first_mip`.
+#[derive(Default)]
+pub struct GpuMemory {
+    pub budget: MemoryBudget,
+    allocations: HashMap<AllocationId, Allocation>,
+    residency: HashMap<AllocationId, Residency>,
+    usage: HashMap<MemoryCategory, u64>,
+    next_id: u64,
+    frame: u64,
+}
+
+impl GpuMemory {
+    pub fn new(budget: MemoryBudget) -> Self {
+        GpuMemory {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    fn allocate(&mut self, category: MemoryCategory, bytes: u64) -> AllocationId {
+        let id = AllocationId(self.next_id);
+        self.next_id += 1;
+        self.allocations.insert(id, Allocation { category, bytes });
+        *self.usage.entry(category).or_default() += bytes;
+        id
+    }
+
+    pub fn track_buffer(&mut self, category: MemoryCategory, desc: &wgpu::BufferDescriptor) -> AllocationId {
+        self.allocate(category, desc.size)
+    }
+
+    pub fn track_texture(&mut self, category: MemoryCategory, desc: &wgpu::TextureDescriptor) -> AllocationId {
+        self.allocate(category, texture_size(desc, 0))
+    }
+
+    /// Tracks a texture in `MemoryCategory::Textures` that the eviction policy is allowed to shrink.
+    pub fn track_evictable_texture(&mut self, desc: &wgpu::TextureDescriptor, min_resident_mips: u32) -> AllocationId {
+        let id = self.allocate(MemoryCategory::Textures, texture_size(desc, 0));
+        let desc = wgpu::TextureDescriptor {
+            label: None,
+            size: desc.size,
+            mip_level_count: desc.mip_level_count,
+            sample_count: desc.sample_count,
+            dimension: desc.dimension,
+            format: desc.format,
+            usage: desc.usage,
+        };
+        self.residency.insert(
+            id,
+            Residency {
+                desc,
+                first_mip: 0,
+                min_resident_mips,
+                last_used_frame: self.frame,
+            },
+        );
+        id
+    }
+
+    pub fn release(&mut self, id: AllocationId) {
+        if let Some(allocation) = self.allocations.remove(&id) {
+            *self.usage.entry(allocation.category).or_default() -= allocation.bytes;
+        }
+        self.residency.remove(&id);
+    }
+
+    /// Marks an evictable texture as used this frame, protecting it from eviction.
+    pub fn touch(&mut self, id: AllocationId) {
+        if let Some(residency) = self.residency.get_mut(&id) {
+            residency.last_used_frame = self.frame;
+        }
+    }
+
+    /// Records that the texture now has `first_mip..` resident and updates its tracked size.
+    pub fn set_first_mip(&mut self, id: AllocationId, first_mip: u32) {
+        let (residency, allocation) = match (self.residency.get_mut(&id), self.allocations.get_mut(&id)) {
+            (Some(residency), Some(allocation)) => (residency, allocation),
+            _ => return,
+        };
+        residency.first_mip = first_mip.min(residency.desc.mip_level_count.saturating_sub(1));
+        let bytes = texture_size(&residency.desc, residency.first_mip);
+        let usage = self.usage.entry(allocation.category).or_default();
+        *usage = *usage - allocation.bytes + bytes;
+        allocation.bytes = bytes;
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn usage(&self, category: MemoryCategory) -> u64 {
+        self.usage.get(&category).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.usage.values().sum()
+    }
+
+    fn over_budget(&self, total: u64, textures: u64) -> bool {
+        let texture_budget = self.budget.per_category.get(&MemoryCategory::Textures);
+        total > self.budget.total || texture_budget.is_some_and(|&budget| textures > budget)
+    }
+
+    /// Drops the most detailed mip of the least recently used textures, one level at a time,
+    /// until the projected usage fits the budget or nothing more can be evicted. Textures used
+    /// this frame are left alone.
+    pub fn plan_evictions(&self) -> Vec<MipChange> {
+        let mut total = self.total();
+        let mut textures = self.usage(MemoryCategory::Textures);
+        if !self.over_budget(total, textures) {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<_> = self
+            .residency
+            .iter()
+            .filter(|(_, r)| r.last_used_frame < self.frame && r.first_mip < r.lowest_first_mip())
+            .map(|(&id, r)| (id, r, r.first_mip))
+            .collect();
+        candidates.sort_by_key(|(id, r, _)| (r.last_used_frame, id.0));
+
+        let mut changed = HashMap::new();
+        'evict: loop {
+            let mut progressed = false;
+            for (id, residency, first_mip) in &mut candidates {
+                if *first_mip >= residency.lowest_first_mip() {
+                    continue;
+                }
+                let saved = texture_size(&residency.desc, *first_mip) - texture_size(&residency.desc, *first_mip + 1);
+                *first_mip += 1;
+                total -= saved;
+                textures -= saved;
+                changed.insert(*id, *first_mip);
+                progressed = true;
+                if !self.over_budget(total, textures) {
+                    break 'evict;
+                }
+            }
+            if !progressed {
+                break;
+            }
+        }
+
+        let mut changes: Vec<_> = changed
+            .into_iter()
+            .map(|(texture, first_mip)| MipChange { texture, first_mip })
+            .collect();
+        changes.sort_by_key(|change| change.texture.0);
+        changes
+    }
+
+    /// Restores detail to recently used, partially evicted textures while it fits in the budget,
+    /// most recently used first.
+    pub fn plan_stream_in(&self) -> Vec<MipChange> {
+        let mut total = self.total();
+        let mut textures = self.usage(MemoryCategory::Textures);
+        let mut candidates: Vec<_> = self.residency.iter().filter(|(_, r)| r.first_mip > 0).collect();
+        candidates.sort_by_key(|(id, r)| (std::cmp::Reverse(r.last_used_frame), id.0));
+
+        let mut changes = Vec::new();
+        for (&id, residency) in candidates {
+            let grown = texture_size(&residency.desc, residency.first_mip - 1) - texture_size(&residency.desc, residency.first_mip);
+            if self.over_budget(total + grown, textures + grown) {
+                break;
+            }
+            total += grown;
+            textures += grown;
+            changes.push(MipChange {
+                texture: id,
+                first_mip: residency.first_mip - 1,
+            });
+        }
+        changes
+    }
+
+    /// Human readable warnings for every limit that is currently exceeded.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let total = self.total();
+        if total > self.budget.total {
+            warnings.push(format!(
+                "GPU memory over budget: {} / {}",
+                format_bytes(total),
+                format_bytes(self.budget.total)
+            ));
+        }
+        for category in MemoryCategory::ALL {
+            if let Some(&budget) = self.budget.per_category.get(&category) {
+                let usage = self.usage(category);
+                if usage > budget {
+                    warnings.push(format!(
+                        "{:?} over budget: {} / {}",
+                        category,
+                        format_bytes(usage),
+                        format_bytes(budget)
+                    ));
+                }
+            }
+        }
+        warnings
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
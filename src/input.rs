@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+
+use glam::Vec2;
+use winit::event::{DeviceEvent, ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+/// Keyboard and mouse state for the current frame, fed the window's events by the engine.
+/// "Pressed" and "released" only hold for the frame they happened in; "held" until released.
+#[derive(Clone, Debug, Default)]
+pub struct Input {
+    keys_held: HashSet<VirtualKeyCode>,
+    keys_pressed: HashSet<VirtualKeyCode>,
+    keys_released: HashSet<VirtualKeyCode>,
+    buttons_held: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    cursor: Option<Vec2>,
+    mouse_delta: Vec2,
+    scroll: Vec2,
+    focused: bool,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the state with `event`. Events the UI `captured` only release keys and
+    /// buttons, so typing in a text field doesn't move the camera but nothing stays stuck.
+    pub fn handle_event<E>(&mut self, event: &Event<'_, E>, captured: bool) {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::KeyboardInput { input, .. } => {
+                    let Some(key) = input.virtual_keycode else {
+                        return;
+                    };
+                    match input.state {
+                        ElementState::Pressed if !captured => {
+                            // key repeat sends presses while held
+                            if self.keys_held.insert(key) {
+                                self.keys_pressed.insert(key);
+                            }
+                        }
+                        ElementState::Pressed => {}
+                        ElementState::Released => {
+                            if self.keys_held.remove(&key) {
+                                self.keys_released.insert(key);
+                            }
+                        }
+                    }
+                }
+                WindowEvent::MouseInput { state, button, .. } => match state {
+                    ElementState::Pressed if !captured => {
+                        self.buttons_held.insert(*button);
+                        self.buttons_pressed.insert(*button);
+                    }
+                    ElementState::Pressed => {}
+                    ElementState::Released => {
+                        if self.buttons_held.remove(button) {
+                            self.buttons_released.insert(*button);
+                        }
+                    }
+                },
+                WindowEvent::CursorMoved { position, .. } => {
+                    self.cursor = Some(Vec2::new(position.x as f32, position.y as f32));
+                }
+                WindowEvent::CursorLeft { .. } => self.cursor = None,
+                WindowEvent::MouseWheel { delta, .. } if !captured => {
+                    self.scroll += match delta {
+                        MouseScrollDelta::LineDelta(x, y) => Vec2::new(*x, *y),
+                        // same conversion as replays
+                        MouseScrollDelta::PixelDelta(position) => Vec2::new(position.x as f32, position.y as f32) / 20.0,
+                    };
+                }
+                WindowEvent::Focused(focused) => {
+                    self.focused = *focused;
+                    if !focused {
+                        // releases that happen while unfocused never arrive
+                        self.keys_released.extend(self.keys_held.drain());
+                        self.buttons_released.extend(self.buttons_held.drain());
+                    }
+                }
+                _ => {}
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } if self.focused => {
+                self.mouse_delta += Vec2::new(delta.0 as f32, delta.1 as f32);
+            }
+            _ => {}
+        }
+    }
+
+    /// Forgets this frame's presses, releases and motion; the engine calls it after each frame.
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll = Vec2::ZERO;
+    }
+
+    pub fn key_held(&self, key: VirtualKeyCode) -> bool {
+        self.keys_held.contains(&key)
+    }
+
+    pub fn key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    pub fn button_held(&self, button: MouseButton) -> bool {
+        self.buttons_held.contains(&button)
+    }
+
+    pub fn button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    pub fn button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Cursor position in physical pixels from the window's top left, `None` outside it.
+    pub fn cursor(&self) -> Option<Vec2> {
+        self.cursor
+    }
+
+    /// Raw mouse motion this frame, unaffected by the cursor hitting the screen edge.
+    pub fn mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+
+    /// Wheel motion this frame in lines, positive y away from the user.
+    pub fn scroll(&self) -> Vec2 {
+        self.scroll
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+}
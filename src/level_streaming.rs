@@ -0,0 +1,364 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc},
+};
+
+use glam::{Vec2, Vec3};
+
+use crate::{
+    ecs::{Entity, World},
+    mesh::{Mesh, MeshData},
+    mesh_renderer::MeshRenderer,
+    scene::{MeshInstance, Transform},
+};
+
+/// A cell of the level's grid on the XZ plane. Chunk (0, 0) spans 0 to `chunk_size` on both
+/// axes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ChunkCoord {
+    pub fn new(x: i32, z: i32) -> Self {
+        ChunkCoord { x, z }
+    }
+
+    /// The chunk `position` is in.
+    pub fn containing(position: Vec3, chunk_size: f32) -> Self {
+        ChunkCoord {
+            x: (position.x / chunk_size).floor() as i32,
+            z: (position.z / chunk_size).floor() as i32,
+        }
+    }
+
+    /// World position of the chunk's corner with the lowest X and Z, at height 0.
+    pub fn origin(&self, chunk_size: f32) -> Vec3 {
+        Vec3::new(self.x as f32 * chunk_size, 0.0, self.z as f32 * chunk_size)
+    }
+
+    /// Horizontal distance from `position` to the nearest point of the chunk, 0 inside it.
+    pub fn distance(&self, position: Vec3, chunk_size: f32) -> f32 {
+        let min = Vec2::new(self.x as f32, self.z as f32) * chunk_size;
+        let point = Vec2::new(position.x, position.z);
+        (point - point.clamp(min, min + chunk_size)).length()
+    }
+}
+
+/// One object of a chunk: an instance of one of its meshes.
+#[derive(Clone, Debug)]
+pub struct ChunkObject {
+    /// Index into `ChunkData::meshes`.
+    pub mesh: usize,
+    /// In world space.
+    pub transform: Transform,
+    /// Linear RGBA tint.
+    pub color: [f32; 4],
+}
+
+/// What a chunk holds, still on the CPU. Meshes are listed once however many objects use them.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkData {
+    pub meshes: Vec<MeshData>,
+    pub objects: Vec<ChunkObject>,
+}
+
+impl ChunkData {
+    /// Bytes of vertex and index data uploading the meshes takes.
+    pub fn upload_size(&self) -> usize {
+        self.meshes.iter().map(mesh_size).sum()
+    }
+}
+
+fn mesh_size(data: &MeshData) -> usize {
+    data.layout().stride() as usize * data.vertex_count() + data.indices.len() * 4
+}
+
+/// Where chunks come from, e.g. files on disk or a generator. Called on worker threads, so it
+/// may block; chunks with no content should return an empty `ChunkData` rather than an error.
+pub trait ChunkSource: Send + Sync + 'static {
+    fn load_chunk(&self, coord: ChunkCoord) -> Result<ChunkData, String>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LevelStreamingSettings {
+    /// Edge length of a chunk in world units.
+    pub chunk_size: f32,
+    /// Chunks closer than this to the focus point are loaded.
+    pub load_radius: f32,
+    /// Loaded chunks farther than this are unloaded. Keep it above `load_radius` so chunks on
+    /// the border don't reload every time the focus wobbles across it.
+    pub unload_radius: f32,
+    /// Chunks being read by worker threads at once.
+    pub max_loads_in_flight: usize,
+    /// Bytes of mesh data uploaded per frame. At least one mesh is uploaded every frame, so a
+    /// mesh larger than the budget still gets through.
+    pub upload_budget: usize,
+}
+
+impl Default for LevelStreamingSettings {
+    fn default() -> Self {
+        LevelStreamingSettings {
+            chunk_size: 32.0,
+            load_radius: 64.0,
+            unload_radius: 80.0,
+            max_loads_in_flight: 4,
+            upload_budget: 1 << 20,
+        }
+    }
+}
+
+/// Where a chunk is in its life. Chunks out of range have no status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkStatus {
+    /// Being read by a worker thread.
+    Loading,
+    /// Read, with its meshes being uploaded a few per frame.
+    Uploading,
+    /// Its objects are in the world.
+    Loaded,
+    /// The source returned an error; it is retried once the chunk goes out of range and comes
+    /// back.
+    Failed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkEvent {
+    Loaded(ChunkCoord),
+    Unloaded(ChunkCoord),
+    Failed(ChunkCoord),
+}
+
+enum ChunkState {
+    Loading,
+    Uploading { data: ChunkData, meshes: Vec<Arc<Mesh>> },
+    Loaded { entities: Vec<Entity> },
+    Failed,
+}
+
+type LoadedChunk = (ChunkCoord, Result<ChunkData, String>);
+
+/// Loads the chunks of a level around a focus point, usually the camera, and unloads the ones
+/// left behind. Chunks are read from the `ChunkSource` on worker threads, nearest first, and
+/// their meshes uploaded within a per-frame byte budget, so walking through a large level never
+/// stalls a frame on disk or on buffer creation.
+///
+/// Loaded chunks become entities with a `Transform` and a `MeshInstance`, one per object, which
+/// the engine draws like any other. Unloading despawns them.
+pub struct LevelStreamer {
+    pub settings: LevelStreamingSettings,
+    source: Arc<dyn ChunkSource>,
+    chunks: HashMap<ChunkCoord, ChunkState>,
+    sender: mpsc::Sender<LoadedChunk>,
+    receiver: mpsc::Receiver<LoadedChunk>,
+    in_flight: usize,
+    events: Vec<ChunkEvent>,
+}
+
+impl LevelStreamer {
+    pub fn new(source: Arc<dyn ChunkSource>, settings: LevelStreamingSettings) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        LevelStreamer {
+            settings,
+            source,
+            chunks: HashMap::new(),
+            sender,
+            receiver,
+            in_flight: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Streams around `focus`: takes in chunks the workers finished, unloads the ones out of
+    /// range, starts loading the nearest missing ones and spends the frame's upload budget.
+    pub fn update(&mut self, device: &wgpu::Device, meshes: &mut MeshRenderer, world: &mut World, focus: Vec3) {
+        self.events.clear();
+        self.receive();
+        self.unload_distant(world, focus);
+        self.request_nearby(focus);
+        self.upload(device, meshes, world, focus);
+    }
+
+    fn receive(&mut self) {
+        while let Ok((coord, result)) = self.receiver.try_recv() {
+            self.in_flight -= 1;
+            // chunks that went out of range while loading were dropped
+            let Some(state) = self.chunks.get_mut(&coord) else {
+                continue;
+            };
+            if !matches!(state, ChunkState::Loading) {
+                continue;
+            }
+            match result {
+                Ok(data) => {
+                    *state = ChunkState::Uploading {
+                        data,
+                        meshes: Vec::new(),
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to load chunk ({}, {}): {}", coord.x, coord.z, e);
+                    *state = ChunkState::Failed;
+                    self.events.push(ChunkEvent::Failed(coord));
+                }
+            }
+        }
+    }
+
+    fn unload_distant(&mut self, world: &mut World, focus: Vec3) {
+        let LevelStreamingSettings {
+            chunk_size,
+            load_radius,
+            unload_radius,
+            ..
+        } = self.settings;
+        let unload_radius = unload_radius.max(load_radius);
+        let distant: Vec<ChunkCoord> = self
+            .chunks
+            .keys()
+            .filter(|coord| coord.distance(focus, chunk_size) > unload_radius)
+            .copied()
+            .collect();
+        for coord in distant {
+            self.unload(world, coord);
+        }
+    }
+
+    fn unload(&mut self, world: &mut World, coord: ChunkCoord) {
+        if let Some(ChunkState::Loaded { entities }) = self.chunks.remove(&coord) {
+            for entity in entities {
+                world.despawn(entity);
+            }
+            self.events.push(ChunkEvent::Unloaded(coord));
+        }
+    }
+
+    fn request_nearby(&mut self, focus: Vec3) {
+        let free = self.settings.max_loads_in_flight.saturating_sub(self.in_flight);
+        if free == 0 {
+            return;
+        }
+        let LevelStreamingSettings {
+            chunk_size,
+            load_radius,
+            ..
+        } = self.settings;
+        let center = ChunkCoord::containing(focus, chunk_size);
+        let reach = (load_radius / chunk_size).ceil() as i32 + 1;
+        let mut missing: Vec<(f32, ChunkCoord)> = (-reach..=reach)
+            .flat_map(|z| (-reach..=reach).map(move |x| ChunkCoord::new(center.x + x, center.z + z)))
+            .filter(|coord| !self.chunks.contains_key(coord))
+            .map(|coord| (coord.distance(focus, chunk_size), coord))
+            .filter(|&(distance, _)| distance <= load_radius)
+            .collect();
+        missing.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        for (_, coord) in missing.into_iter().take(free) {
+            let source = self.source.clone();
+            let sender = self.sender.clone();
+            std::thread::spawn(move || {
+                // the streamer may be gone by now, which is fine
+                let _ = sender.send((coord, source.load_chunk(coord)));
+            });
+            self.chunks.insert(coord, ChunkState::Loading);
+            self.in_flight += 1;
+        }
+    }
+
+    fn upload(&mut self, device: &wgpu::Device, renderer: &mut MeshRenderer, world: &mut World, focus: Vec3) {
+        let chunk_size = self.settings.chunk_size;
+        let mut uploading: Vec<(f32, ChunkCoord)> = self
+            .chunks
+            .iter()
+            .filter(|(_, state)| matches!(state, ChunkState::Uploading { .. }))
+            .map(|(coord, _)| (coord.distance(focus, chunk_size), *coord))
+            .collect();
+        uploading.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut budget = self.settings.upload_budget;
+        let mut uploaded_any = false;
+        for (_, coord) in uploading {
+            let state = self.chunks.get_mut(&coord).unwrap();
+            let ChunkState::Uploading { data, meshes } = state else {
+                unreachable!();
+            };
+            while let Some(mesh) = data.meshes.get(meshes.len()) {
+                let size = mesh_size(mesh);
+                if uploaded_any && size > budget {
+                    return;
+                }
+                let label = format!("Chunk ({}, {}) mesh {}", coord.x, coord.z, meshes.len());
+                meshes.push(renderer.upload(device, &label, mesh));
+                budget = budget.saturating_sub(size);
+                uploaded_any = true;
+            }
+
+            let entities = data
+                .objects
+                .iter()
+                .filter_map(|object| {
+                    let Some(mesh) = meshes.get(object.mesh) else {
+                        log::warn!(
+                            "Chunk ({}, {}) has an object using missing mesh {}",
+                            coord.x, coord.z, object.mesh
+                        );
+                        return None;
+                    };
+                    let instance = MeshInstance::new(mesh.clone()).with_color(object.color);
+                    Some(world.spawn((object.transform, instance)))
+                })
+                .collect();
+            *state = ChunkState::Loaded { entities };
+            self.events.push(ChunkEvent::Loaded(coord));
+        }
+    }
+
+    /// Unloads every chunk, e.g. before switching levels. Loads still in flight are dropped
+    /// when they finish.
+    pub fn unload_all(&mut self, world: &mut World) {
+        let coords: Vec<ChunkCoord> = self.chunks.keys().copied().collect();
+        for coord in coords {
+            self.unload(world, coord);
+        }
+    }
+
+    pub fn status(&self, coord: ChunkCoord) -> Option<ChunkStatus> {
+        self.chunks.get(&coord).map(|state| match state {
+            ChunkState::Loading => ChunkStatus::Loading,
+            ChunkState::Uploading { .. } => ChunkStatus::Uploading,
+            ChunkState::Loaded { .. } => ChunkStatus::Loaded,
+            ChunkState::Failed => ChunkStatus::Failed,
+        })
+    }
+
+    /// The entities a loaded chunk spawned; empty if it isn't loaded.
+    pub fn entities(&self, coord: ChunkCoord) -> &[Entity] {
+        match self.chunks.get(&coord) {
+            Some(ChunkState::Loaded { entities }) => entities,
+            _ => &[],
+        }
+    }
+
+    /// Every loaded chunk, in no particular order.
+    pub fn loaded(&self) -> impl Iterator<Item = ChunkCoord> + '_ {
+        self.chunks
+            .iter()
+            .filter(|(_, state)| matches!(state, ChunkState::Loaded { .. }))
+            .map(|(coord, _)| *coord)
+    }
+
+    /// True when no chunk in range is still loading or uploading, e.g. to hold a loading
+    /// screen until the surroundings are in.
+    pub fn is_settled(&self) -> bool {
+        !self
+            .chunks
+            .values()
+            .any(|state| matches!(state, ChunkState::Loading | ChunkState::Uploading { .. }))
+    }
+
+    /// Chunks that loaded, unloaded or failed during the last `update`.
+    pub fn events(&self) -> &[ChunkEvent] {
+        &self.events
+    }
+}
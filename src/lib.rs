@@ -0,0 +1,71 @@
+pub mod animation;
+pub mod assets;
+pub mod batching;
+pub mod behavior_tree;
+pub mod benchmark;
+pub mod camera;
+pub mod camera_controller;
+pub mod camera_shake;
+pub mod capabilities;
+pub mod clouds;
+pub mod crash;
+pub mod cursor;
+pub mod debug_lines;
+pub mod debug_view;
+pub mod dynamic_buffer;
+pub mod ecs;
+pub mod engine;
+pub mod environment;
+pub mod fixed_timestep;
+pub mod foliage;
+pub mod gpu_driven;
+pub mod gpu_error;
+pub mod gpu_memory;
+pub mod input;
+pub mod level_streaming;
+pub mod light_probes;
+pub mod light_profiles;
+pub mod lightmap;
+pub mod lights;
+pub mod load_profile;
+pub mod loading;
+pub mod local_shadows;
+pub mod material;
+pub mod mesh;
+pub mod mesh_renderer;
+pub mod minimap;
+pub mod noise;
+pub mod oit;
+pub mod particles;
+pub mod pipeline;
+pub mod planar_reflection;
+pub mod postprocess;
+pub mod project_dirs;
+pub mod random;
+pub mod readback;
+pub mod reflection_probes;
+pub mod render_graph;
+pub mod render_hooks;
+pub mod render_layers;
+pub mod render_stats;
+pub mod render_world;
+pub mod replay;
+pub mod save;
+pub mod scene;
+pub mod shader_reload;
+pub mod shader_variants;
+pub mod shadows;
+pub mod skinning;
+pub mod sky;
+pub mod spline;
+pub mod sprite;
+pub mod steering;
+pub mod surface;
+pub mod texture_streaming;
+pub mod time_of_day;
+pub mod timers;
+pub mod transient;
+pub mod tween;
+pub mod ui;
+pub mod vertex_layout;
+pub mod weather;
@@ -0,0 +1,395 @@
+use std::f32::consts::PI;
+
+use glam::{UVec3, Vec3};
+
+use crate::postprocess::{color_grading::f16_bits, sampler_entry};
+
+/// WGSL for lit shaders: declares `ProbeLighting`, `probe_diffuse(lighting, normal)` and, with a
+/// `LightProbeTexture` at bindings 17 to 19 of `LIGHT_PROBE_GROUP`,
+/// `probe_grid_irradiance(position, normal, fallback)`.
+pub const LIGHT_PROBE_SHADER: &str = include_str!("shaders/light_probes.wgsl");
+
+/// Bind group index `LIGHT_PROBE_SHADER` declares the grid at, the lighting group's.
+pub const LIGHT_PROBE_GROUP: u32 = 2;
+
+/// Second-order (nine coefficient) spherical harmonics of incoming radiance.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sh9 {
+    pub coefficients: [Vec3; 9],
+}
+
+fn sh_basis(d: Vec3) -> [f32; 9] {
+    [
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+/// Cosine lobe convolution per coefficient, divided by pi so evaluating gives the light
+/// reflected by a white Lambertian surface.
+const DIFFUSE_BANDS: [f32; 9] = [1.0, 2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 0.25, 0.25, 0.25, 0.25, 0.25];
+
+impl Sh9 {
+    /// Ambient light of the same radiance from every direction.
+    pub fn uniform(radiance: Vec3) -> Self {
+        let mut sh = Sh9::default();
+        sh.coefficients[0] = radiance * (0.282095 * 4.0 * PI);
+        sh
+    }
+
+    /// Splats radiance arriving from `direction` (unit length), weighted by the solid angle it
+    /// covers. The weights should add up to 4 pi over a full sphere.
+    pub fn add_sample(&mut self, direction: Vec3, radiance: Vec3, weight: f32) {
+        for (coefficient, basis) in self.coefficients.iter_mut().zip(sh_basis(direction)) {
+            *coefficient += radiance * (basis * weight);
+        }
+    }
+
+    /// Projects a cubemap capture, faces ordered +X, -X, +Y, -Y, +Z, -Z with rows top first,
+    /// each `size * size` linear texels.
+    pub fn from_cubemap(size: u32, faces: [&[[f32; 3]]; 6]) -> Self {
+        let mut sh = Sh9::default();
+        let texel = 2.0 / size as f32;
+        for (face, pixels) in faces.iter().enumerate() {
+            for y in 0..size {
+                for x in 0..size {
+                    let u = (x as f32 + 0.5) * texel - 1.0;
+                    let v = (y as f32 + 0.5) * texel - 1.0;
+                    let direction = match face {
+                        0 => Vec3::new(1.0, -v, -u),
+                        1 => Vec3::new(-1.0, -v, u),
+                        2 => Vec3::new(u, 1.0, v),
+                        3 => Vec3::new(u, -1.0, -v),
+                        4 => Vec3::new(u, -v, 1.0),
+                        _ => Vec3::new(-u, -v, -1.0),
+                    };
+                    // solid angle of the texel, from its projection onto the unit sphere
+                    let weight = texel * texel / direction.length_squared().powf(1.5);
+                    let radiance = Vec3::from(pixels[(y * size + x) as usize]);
+                    sh.add_sample(direction.normalize(), radiance, weight);
+                }
+            }
+        }
+        sh
+    }
+
+    /// Light reflected towards the viewer by a white Lambertian surface facing `normal`.
+    /// Multiply by albedo for the ambient term.
+    pub fn diffuse(&self, normal: Vec3) -> Vec3 {
+        let basis = sh_basis(normal.normalize_or_zero());
+        let mut result = Vec3::ZERO;
+        for i in 0..9 {
+            result += self.coefficients[i] * (basis[i] * DIFFUSE_BANDS[i]);
+        }
+        result.max(Vec3::ZERO)
+    }
+
+    pub fn lerp(&self, other: &Sh9, t: f32) -> Sh9 {
+        let mut result = *self;
+        for (a, b) in result.coefficients.iter_mut().zip(other.coefficients) {
+            *a = a.lerp(b, t);
+        }
+        result
+    }
+
+    /// Uniform-buffer form for `LIGHT_PROBE_SHADER`, with the diffuse convolution applied.
+    pub fn to_gpu(&self) -> ProbeLighting {
+        let mut coefficients = [[0.0; 4]; 9];
+        for i in 0..9 {
+            coefficients[i] = (self.coefficients[i] * DIFFUSE_BANDS[i]).extend(0.0).into();
+        }
+        ProbeLighting { coefficients }
+    }
+}
+
+/// Matches `ProbeLighting` in `LIGHT_PROBE_SHADER`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ProbeLighting {
+    pub coefficients: [[f32; 4]; 9],
+}
+
+/// Irradiance probes on a regular grid over a level's static environment. A dynamic object
+/// samples the grid at its center each frame, so it picks up the bounce light and shadowing
+/// of wherever it moves to.
+pub struct LightProbeGrid {
+    origin: Vec3,
+    spacing: Vec3,
+    counts: UVec3,
+    probes: Vec<Sh9>,
+    /// Next probe `update_progressive` recaptures.
+    cursor: usize,
+}
+
+impl LightProbeGrid {
+    /// Covers `min..max` with probes at most `spacing` apart; every probe starts unlit.
+    pub fn new(min: Vec3, max: Vec3, spacing: f32) -> Self {
+        let extent = (max - min).max(Vec3::ZERO);
+        let counts = (extent / spacing.max(0.01)).ceil().as_uvec3() + UVec3::ONE;
+        let spacing = extent / (counts - UVec3::ONE).max(UVec3::ONE).as_vec3();
+        LightProbeGrid {
+            origin: min,
+            spacing,
+            counts,
+            probes: vec![Sh9::default(); (counts.x * counts.y * counts.z) as usize],
+            cursor: 0,
+        }
+    }
+
+    pub fn counts(&self) -> UVec3 {
+        self.counts
+    }
+
+    /// Position of the first probe, the grid's minimum corner.
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    /// Distance between neighboring probes along each axis.
+    pub fn spacing(&self) -> Vec3 {
+        self.spacing
+    }
+
+    pub fn len(&self) -> usize {
+        self.probes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.probes.is_empty()
+    }
+
+    fn index(&self, cell: UVec3) -> usize {
+        (cell.x + cell.y * self.counts.x + cell.z * self.counts.x * self.counts.y) as usize
+    }
+
+    pub fn probe_position(&self, index: usize) -> Vec3 {
+        let index = index as u32;
+        let layer = self.counts.x * self.counts.y;
+        let cell = UVec3::new(index % self.counts.x, index % layer / self.counts.x, index / layer);
+        self.origin + cell.as_vec3() * self.spacing
+    }
+
+    pub fn probe(&self, index: usize) -> &Sh9 {
+        &self.probes[index]
+    }
+
+    pub fn set_probe(&mut self, index: usize, sh: Sh9) {
+        self.probes[index] = sh;
+    }
+
+    /// Captures every probe at once, e.g. when baking at load time. `capture` renders or
+    /// traces the environment around a position and projects it, typically with
+    /// `Sh9::from_cubemap`.
+    pub fn bake(&mut self, mut capture: impl FnMut(Vec3) -> Sh9) {
+        for index in 0..self.probes.len() {
+            self.probes[index] = capture(self.probe_position(index));
+        }
+    }
+
+    /// Recaptures the next `count` probes round robin, so lighting changes such as a moving
+    /// sun spread through the grid over a few frames instead of stalling one.
+    pub fn update_progressive(&mut self, count: usize, mut capture: impl FnMut(Vec3) -> Sh9) {
+        for _ in 0..count.min(self.probes.len()) {
+            self.probes[self.cursor] = capture(self.probe_position(self.cursor));
+            self.cursor = (self.cursor + 1) % self.probes.len();
+        }
+    }
+
+    /// Trilinear blend of the eight probes around `position`; positions outside the grid use
+    /// the nearest edge.
+    pub fn sample(&self, position: Vec3) -> Sh9 {
+        let max_cell = (self.counts - UVec3::ONE).as_vec3();
+        let local = ((position - self.origin) / self.spacing.max(Vec3::splat(f32::EPSILON))).clamp(Vec3::ZERO, max_cell);
+        let base = local.floor().min((max_cell - Vec3::ONE).max(Vec3::ZERO));
+        let t = (local - base).min(Vec3::ONE);
+        let base = base.as_uvec3();
+        let corner = |x, y, z| {
+            let cell = (base + UVec3::new(x, y, z)).min(self.counts - UVec3::ONE);
+            &self.probes[self.index(cell)]
+        };
+
+        let x00 = corner(0, 0, 0).lerp(corner(1, 0, 0), t.x);
+        let x10 = corner(0, 1, 0).lerp(corner(1, 1, 0), t.x);
+        let x01 = corner(0, 0, 1).lerp(corner(1, 0, 1), t.x);
+        let x11 = corner(0, 1, 1).lerp(corner(1, 1, 1), t.x);
+        x00.lerp(&x10, t.y).lerp(&x01.lerp(&x11, t.y), t.z)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct GridParams {
+    // w is 1 with a grid
+    origin: [f32; 4],
+    spacing: [f32; 4],
+    counts: [f32; 4],
+}
+
+/// A `LightProbeGrid` uploaded for `LIGHT_PROBE_SHADER`: the coefficients, already convolved
+/// for diffuse, in a 3D texture of nine slabs side by side along x, one per coefficient, so the
+/// sampler blends between probes. Without a grid, shaders use their fallback.
+pub struct LightProbeTexture {
+    uniform: wgpu::Buffer,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    counts: UVec3,
+}
+
+impl LightProbeTexture {
+    /// Half floats are filterable everywhere, unlike 32-bit float textures.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light probe grid"),
+            size: std::mem::size_of::<GridParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("light probe sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let (texture, view) = Self::create_texture(device, UVec3::ONE);
+        LightProbeTexture {
+            uniform,
+            texture,
+            view,
+            sampler,
+            counts: UVec3::ONE,
+        }
+    }
+
+    fn create_texture(device: &wgpu::Device, counts: UVec3) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("light probes"),
+            size: wgpu::Extent3d {
+                width: counts.x * 9,
+                height: counts.y,
+                depth_or_array_layers: counts.z,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    /// Uploads every probe of `grid`, or switches the shaders to their fallback with `None`.
+    /// Call it again after `bake` or `update_progressive` changed the probes. Grids too wide
+    /// for the device's 3D textures, nine texels per probe along x, are left out.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, grid: Option<&LightProbeGrid>) {
+        let grid = grid.filter(|grid| {
+            let fits = grid.counts.x * 9 <= device.limits().max_texture_dimension_3d;
+            if !fits {
+                log::warn!("Light probe grid of {} probes along x is too wide to upload", grid.counts.x);
+            }
+            fits && !grid.is_empty()
+        });
+        let Some(grid) = grid else {
+            queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&GridParams::default()));
+            return;
+        };
+        if grid.counts != self.counts {
+            (self.texture, self.view) = Self::create_texture(device, grid.counts);
+            self.counts = grid.counts;
+        }
+
+        let counts = grid.counts;
+        let mut texels = vec![[0u16; 4]; (counts.x * 9 * counts.y * counts.z) as usize];
+        for (index, probe) in grid.probes.iter().enumerate() {
+            let index = index as u32;
+            let layer = counts.x * counts.y;
+            let (x, y, z) = (index % counts.x, index % layer / counts.x, index / layer);
+            for (coefficient, value) in probe.to_gpu().coefficients.into_iter().enumerate() {
+                let column = coefficient as u32 * counts.x + x;
+                let [r, g, b, _] = value;
+                texels[(column + (y + z * counts.y) * counts.x * 9) as usize] = [f16_bits(r), f16_bits(g), f16_bits(b), 0];
+            }
+        }
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&texels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(counts.x * 9 * 8),
+                rows_per_image: std::num::NonZeroU32::new(counts.y),
+            },
+            wgpu::Extent3d {
+                width: counts.x * 9,
+                height: counts.y,
+                depth_or_array_layers: counts.z,
+            },
+        );
+        let params = GridParams {
+            origin: grid.origin.extend(1.0).into(),
+            spacing: grid.spacing.extend(0.0).into(),
+            counts: counts.as_vec3().extend(0.0).into(),
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&params));
+    }
+
+    /// The entries `LIGHT_PROBE_SHADER` expects, for building a combined lighting layout.
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 17,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 18,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            sampler_entry(19),
+        ]
+    }
+
+    /// The resources of `layout_entries`, for a combined lighting bind group. They change when
+    /// `upload` resizes the texture.
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 17,
+                resource: self.uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 18,
+                resource: wgpu::BindingResource::TextureView(&self.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 19,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+}
@@ -0,0 +1,426 @@
+use std::{fmt, path::Path};
+
+use crate::{
+    assets::Asset,
+    load_profile::{LoadStage, LoadTimer},
+    postprocess::color_grading::f16_bits,
+};
+
+/// WGSL for lit shaders: `ies_attenuation(profile, to_surface, forward, up)`,
+/// `light_cookie(cookie, to_surface, forward, up, tan_half_angle)` and `light_up(forward)`, with
+/// bindings 7 to 10 of `LIGHT_PROFILE_GROUP`, after the shadows. `LIGHTS_SHADER` applies them to
+/// lights with a profile or cookie, so it goes before that.
+pub const LIGHT_PROFILE_SHADER: &str = include_str!("shaders/light_profiles.wgsl");
+
+/// Bind group index `LIGHT_PROFILE_SHADER` declares its bindings at.
+pub const LIGHT_PROFILE_GROUP: u32 = 2;
+
+/// Horizontal and vertical resolution profiles are baked at.
+pub const PROFILE_SIZE: (u32, u32) = (128, 64);
+
+#[derive(Debug)]
+pub enum IesError {
+    Io(std::io::Error),
+    Format(String),
+}
+
+impl fmt::Display for IesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IesError::Io(e) => write!(f, "{}", e),
+            IesError::Format(message) => write!(f, "invalid IES profile: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for IesError {}
+
+/// A photometric profile from an IESNA LM-63 `.ies` file, as published by fixture
+/// manufacturers. Only type C photometry is supported, which nearly every architectural and
+/// entertainment fixture uses: vertical angles run from 0 straight down the light's axis to 180
+/// straight back, horizontal angles around the axis.
+#[derive(Clone, Debug)]
+pub struct IesProfile {
+    /// Degrees, ascending.
+    pub vertical_angles: Vec<f32>,
+    /// Degrees, ascending. A last angle of 0, 90 or 180 means the remaining directions mirror
+    /// the ones given.
+    pub horizontal_angles: Vec<f32>,
+    /// Candela per horizontal angle, each with one value per vertical angle, multiplier applied.
+    pub candela: Vec<Vec<f32>>,
+}
+
+impl IesProfile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, IesError> {
+        let path = path.as_ref();
+        let mut timer = LoadTimer::for_path("light profile", path);
+        let source = std::fs::read_to_string(path).map_err(IesError::Io)?;
+        timer.add_file_bytes(source.len());
+        timer.end(LoadStage::Read);
+        let profile = Self::parse(&source)?;
+        timer.end(LoadStage::Decode);
+        timer.finish();
+        Ok(profile)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, IesError> {
+        let error = |message: &str| IesError::Format(message.into());
+        // keywords come first, then everything after the TILT line is whitespace separated
+        let mut lines = source.lines();
+        let tilt = loop {
+            let line = lines.next().ok_or_else(|| error("missing TILT line"))?.trim();
+            if let Some(tilt) = line.strip_prefix("TILT=") {
+                break tilt.trim().to_string();
+            }
+        };
+        let mut numbers = lines.flat_map(str::split_whitespace).map(|word| {
+            word.parse::<f32>()
+                .map_err(|_| IesError::Format(format!("expected a number, found \"{}\"", word)))
+        });
+        let mut next = || numbers.next().unwrap_or_else(|| Err(error("unexpected end of file")));
+
+        if tilt == "INCLUDE" {
+            // lamp to luminaire geometry, then the tilt angle and factor tables
+            next()?;
+            let count = next()? as usize;
+            for _ in 0..count * 2 {
+                next()?;
+            }
+        } else if tilt != "NONE" {
+            return Err(error("TILT files are not supported"));
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()?;
+        // units, width, length, height, ballast factor, future use, input watts
+        for _ in 0..7 {
+            next()?;
+        }
+        if photometric_type != 1.0 {
+            return Err(error("only type C photometry is supported"));
+        }
+        if vertical_count < 2 || horizontal_count < 1 {
+            return Err(error("too few angles"));
+        }
+
+        let mut read = |count: usize| (0..count).map(|_| next()).collect::<Result<Vec<f32>, IesError>>();
+        let vertical_angles = read(vertical_count)?;
+        let horizontal_angles = read(horizontal_count)?;
+        let candela = (0..horizontal_count)
+            .map(|_| Ok(read(vertical_count)?.into_iter().map(|value| value * multiplier).collect()))
+            .collect::<Result<_, IesError>>()?;
+
+        let ascending = |angles: &[f32]| angles.windows(2).all(|pair| pair[0] < pair[1]);
+        if !ascending(&vertical_angles) || !ascending(&horizontal_angles) {
+            return Err(error("angles must be ascending"));
+        }
+        Ok(IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+        })
+    }
+
+    pub fn max_candela(&self) -> f32 {
+        self.candela.iter().flatten().fold(0.0, |max, &value| max.max(value))
+    }
+
+    /// Candela towards `vertical` and `horizontal`, in degrees, interpolated between the
+    /// measured angles.
+    pub fn candela(&self, vertical: f32, horizontal: f32) -> f32 {
+        let horizontal = horizontal.rem_euclid(360.0);
+        let horizontal = match self.horizontal_angles.last().copied().unwrap_or(0.0) {
+            last if last <= 0.0 => 0.0,
+            // quadrant symmetry
+            last if last <= 90.0 => {
+                let folded = horizontal % 180.0;
+                if folded > 90.0 { 180.0 - folded } else { folded }
+            }
+            // bilateral symmetry
+            last if last <= 180.0 => {
+                if horizontal > 180.0 { 360.0 - horizontal } else { horizontal }
+            }
+            _ => horizontal,
+        };
+        let (h0, h1, ht) = bracket(&self.horizontal_angles, horizontal);
+        let (v0, v1, vt) = bracket(&self.vertical_angles, vertical);
+        let row = |h: usize| self.candela[h][v0] + (self.candela[h][v1] - self.candela[h][v0]) * vt;
+        row(h0) + (row(h1) - row(h0)) * ht
+    }
+
+    /// Candela over horizontal angles 0 to 360 in x and vertical angles 0 to 180 in y, at
+    /// texel centers, divided by the peak so the brightest direction is 1.
+    pub fn bake(&self, width: u32, height: u32) -> Vec<f32> {
+        let scale = 1.0 / self.max_candela().max(f32::EPSILON);
+        (0..height)
+            .flat_map(|y| {
+                (0..width).map(move |x| {
+                    let horizontal = (x as f32 + 0.5) / width as f32 * 360.0;
+                    let vertical = (y as f32 + 0.5) / height as f32 * 180.0;
+                    self.candela(vertical, horizontal) * scale
+                })
+            })
+            .collect()
+    }
+}
+
+/// Indices either side of `value` in the ascending `angles` and how far between them it lies,
+/// clamped to the ends.
+fn bracket(angles: &[f32], value: f32) -> (usize, usize, f32) {
+    let upper = angles.partition_point(|&angle| angle <= value);
+    if upper == 0 {
+        (0, 0, 0.0)
+    } else if upper == angles.len() {
+        (upper - 1, upper - 1, 0.0)
+    } else {
+        let (a, b) = (angles[upper - 1], angles[upper]);
+        (upper - 1, upper, (value - a) / (b - a))
+    }
+}
+
+/// Layer of a profile in the profile array, for a light's shader data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProfileId(pub u32);
+
+/// Layer of a cookie in the cookie array, for a light's shader data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CookieId(pub u32);
+
+/// An IES profile baked into `MeshRenderer::light_profiles`, loaded through
+/// `Assets<LightProfile>`. Give its `id` to a `Light`. The layer isn't reused once the asset is
+/// dropped.
+pub struct LightProfile {
+    pub id: ProfileId,
+    /// The peak of the profile, which the baked layer is divided by.
+    pub max_candela: f32,
+}
+
+/// What `LightProfile` assets are baked with.
+pub struct LightProfileContext<'a> {
+    pub queue: &'a wgpu::Queue,
+    pub profiles: &'a mut LightProfiles,
+}
+
+impl Asset for LightProfile {
+    type Settings = ();
+    type Data = IesProfile;
+    type Context<'a> = LightProfileContext<'a>;
+    const KIND: &'static str = "light profile";
+
+    fn decode(bytes: &[u8], _settings: &()) -> Result<IesProfile, String> {
+        let source = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+        IesProfile::parse(source).map_err(|e| e.to_string())
+    }
+
+    fn finish(context: &mut LightProfileContext, _label: &str, profile: IesProfile) -> Result<Self, String> {
+        let id = context
+            .profiles
+            .add_profile(context.queue, &profile)
+            .ok_or_else(|| "every light profile layer is taken".to_string())?;
+        Ok(LightProfile {
+            id,
+            max_candela: profile.max_candela(),
+        })
+    }
+}
+
+/// IES profiles and light cookies in two texture arrays. Cookies are square RGBA textures
+/// projected along a spot light's cone, tinting and masking its light like a gobo.
+pub struct LightProfiles {
+    profiles: wgpu::Texture,
+    cookies: wgpu::Texture,
+    profile_view: wgpu::TextureView,
+    cookie_view: wgpu::TextureView,
+    profile_sampler: wgpu::Sampler,
+    cookie_sampler: wgpu::Sampler,
+    profile_count: u32,
+    profile_capacity: u32,
+    cookie_count: u32,
+    cookie_capacity: u32,
+    cookie_size: u32,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl LightProfiles {
+    pub const PROFILE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+    pub const COOKIE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn new(device: &wgpu::Device, profile_capacity: u32, cookie_size: u32, cookie_capacity: u32) -> Self {
+        let (profile_capacity, cookie_capacity) = (profile_capacity.max(1), cookie_capacity.max(1));
+        let array = |label, (width, height), layers, format| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: layers,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            })
+        };
+        let profiles = array("ies profiles", PROFILE_SIZE, profile_capacity, Self::PROFILE_FORMAT);
+        let cookies = array("light cookies", (cookie_size, cookie_size), cookie_capacity, Self::COOKIE_FORMAT);
+        let array_view = |texture: &wgpu::Texture| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            })
+        };
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("light profile layout"),
+            entries: &Self::layout_entries(),
+        });
+        let sampler = |label, address_mode_u| {
+            device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some(label),
+                address_mode_u,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            })
+        };
+        // profiles wrap around the light's axis horizontally
+        let profile_sampler = sampler("ies profile sampler", wgpu::AddressMode::Repeat);
+        let cookie_sampler = sampler("light cookie sampler", wgpu::AddressMode::ClampToEdge);
+        let (profile_view, cookie_view) = (array_view(&profiles), array_view(&cookies));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("light profile bind group"),
+            layout: &layout,
+            entries: &Self::entries(&profile_view, &cookie_view, &profile_sampler, &cookie_sampler),
+        });
+
+        LightProfiles {
+            profiles,
+            cookies,
+            profile_view,
+            cookie_view,
+            profile_sampler,
+            cookie_sampler,
+            profile_count: 0,
+            profile_capacity,
+            cookie_count: 0,
+            cookie_capacity,
+            cookie_size,
+            layout,
+            bind_group,
+        }
+    }
+
+    /// The entries `LIGHT_PROFILE_SHADER` expects, for building a combined lighting layout.
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 4] {
+        let array = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        [array(7), array(8), sampler(9), sampler(10)]
+    }
+
+    /// The resources of `layout_entries`, for a combined lighting bind group.
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 4] {
+        Self::entries(&self.profile_view, &self.cookie_view, &self.profile_sampler, &self.cookie_sampler)
+    }
+
+    fn entries<'a>(
+        profiles: &'a wgpu::TextureView,
+        cookies: &'a wgpu::TextureView,
+        profile_sampler: &'a wgpu::Sampler,
+        cookie_sampler: &'a wgpu::Sampler,
+    ) -> [wgpu::BindGroupEntry<'a>; 4] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::TextureView(profiles),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::TextureView(cookies),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: wgpu::BindingResource::Sampler(profile_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: wgpu::BindingResource::Sampler(cookie_sampler),
+            },
+        ]
+    }
+
+    /// For lit pipelines that use nothing else from the lighting group, at `LIGHT_PROFILE_GROUP`.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Bakes `profile` into the next free layer. Returns `None` when every layer is taken.
+    pub fn add_profile(&mut self, queue: &wgpu::Queue, profile: &IesProfile) -> Option<ProfileId> {
+        if self.profile_count == self.profile_capacity {
+            return None;
+        }
+        let (width, height) = PROFILE_SIZE;
+        let texels: Vec<u16> = profile.bake(width, height).into_iter().map(f16_bits).collect();
+        write_layer(queue, &self.profiles, self.profile_count, (width, height), 2, bytemuck::cast_slice(&texels));
+        self.profile_count += 1;
+        Some(ProfileId(self.profile_count - 1))
+    }
+
+    /// Uploads a cookie of `cookie_size` squared sRGB RGBA pixels, top row first. Returns `None`
+    /// when every layer is taken.
+    pub fn add_cookie(&mut self, queue: &wgpu::Queue, rgba: &[u8]) -> Option<CookieId> {
+        let size = self.cookie_size;
+        assert_eq!(rgba.len(), (size * size * 4) as usize, "cookie must be {0}x{0} RGBA", size);
+        if self.cookie_count == self.cookie_capacity {
+            return None;
+        }
+        write_layer(queue, &self.cookies, self.cookie_count, (size, size), 4, rgba);
+        self.cookie_count += 1;
+        Some(CookieId(self.cookie_count - 1))
+    }
+}
+
+fn write_layer(queue: &wgpu::Queue, texture: &wgpu::Texture, layer: u32, (width, height): (u32, u32), texel_bytes: u32, data: &[u8]) {
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d { x: 0, y: 0, z: layer },
+            aspect: wgpu::TextureAspect::All,
+        },
+        data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(width * texel_bytes),
+            rows_per_image: std::num::NonZeroU32::new(height),
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
@@ -0,0 +1,285 @@
+use std::{fmt, path::Path, sync::Arc};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    load_profile::{LoadStage, LoadTimer},
+    vertex_layout::{VertexLayout, VertexSemantic},
+};
+
+/// WGSL for static mesh shaders that read a baked lightmap: `lightmap_irradiance(uv, intensity)`
+/// and `apply_lightmap`. The lightmap binds with the material, at bindings 7 and 8 of
+/// `LIGHTMAP_GROUP` after its texture slots; the PBR shader includes it for materials with one.
+pub const LIGHTMAP_SHADER: &str = include_str!("shaders/lightmap.wgsl");
+
+/// Bind group index `LIGHTMAP_SHADER` declares its bindings at, the material's.
+pub const LIGHTMAP_GROUP: u32 = 3;
+
+#[derive(Debug)]
+pub enum LightmapError {
+    Io(std::io::Error),
+    Format(String),
+}
+
+impl fmt::Display for LightmapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LightmapError::Io(e) => write!(f, "{}", e),
+            LightmapError::Format(message) => write!(f, "invalid lightmap: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for LightmapError {}
+
+/// Vertex of a lightmapped static mesh: the material UVs plus a second, non-overlapping UV set
+/// into the lightmap atlas.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightmappedVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub lightmap_uv: [f32; 2],
+}
+
+impl LightmappedVertex {
+    /// The lightmap UVs are the second UV set, so mesh shaders read them with `vertex_uv2`.
+    pub fn layout() -> VertexLayout {
+        let semantics = [VertexSemantic::Position, VertexSemantic::Normal, VertexSemantic::Uv0, VertexSemantic::Uv1];
+        VertexLayout::new(semantics).unwrap()
+    }
+}
+
+/// Linear HDR pixels, top row first, as decoded from a Radiance `.hdr` file. This is what
+/// external bakers such as Blender and Bakery export lightmaps as.
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 3]>,
+}
+
+impl HdrImage {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LightmapError> {
+        let path = path.as_ref();
+        let mut timer = LoadTimer::for_path("hdr image", path);
+        let image = Self::load_timed(path, &mut timer)?;
+        timer.finish();
+        Ok(image)
+    }
+
+    /// `load`, timing the read and decode stages with `timer`, for loaders that go on to
+    /// upload the image.
+    pub(crate) fn load_timed(path: &Path, timer: &mut LoadTimer) -> Result<Self, LightmapError> {
+        let bytes = std::fs::read(path).map_err(LightmapError::Io)?;
+        timer.add_file_bytes(bytes.len());
+        timer.end(LoadStage::Read);
+        let image = Self::parse(&bytes)?;
+        timer.end(LoadStage::Decode);
+        Ok(image)
+    }
+
+    /// Decodes a Radiance RGBE image, flat or run-length encoded.
+    pub fn parse(bytes: &[u8]) -> Result<Self, LightmapError> {
+        let error = |message: &str| LightmapError::Format(message.into());
+        let mut rest = bytes;
+        let mut next_line = || -> Result<&str, LightmapError> {
+            let end = rest.iter().position(|&b| b == b'\n').ok_or_else(|| error("truncated header"))?;
+            let line = std::str::from_utf8(&rest[..end]).map_err(|_| error("header is not text"))?;
+            rest = &rest[end + 1..];
+            Ok(line)
+        };
+
+        let magic = next_line()?;
+        if magic != "#?RADIANCE" && magic != "#?RGBE" {
+            return Err(error("not a Radiance HDR file"));
+        }
+        loop {
+            let line = next_line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(error("only RGBE pixels are supported"));
+                }
+            }
+        }
+        let resolution: Vec<&str> = next_line()?.split_whitespace().collect();
+        let (height, width) = match resolution.as_slice() {
+            ["-Y", height, "+X", width] => (height.parse::<u32>().ok(), width.parse::<u32>().ok()),
+            _ => return Err(error("only -Y +X scanline order is supported")),
+        };
+        let (width, height) = width.zip(height).ok_or_else(|| error("invalid resolution"))?;
+
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        let mut scanline = vec![[0u8; 4]; width as usize];
+        let mut data = rest;
+        for _ in 0..height {
+            data = read_scanline(data, &mut scanline).ok_or_else(|| error("truncated pixel data"))?;
+            pixels.extend(scanline.iter().map(|&rgbe| decode_rgbe(rgbe)));
+        }
+
+        Ok(HdrImage { width, height, pixels })
+    }
+}
+
+fn read_scanline<'a>(data: &'a [u8], scanline: &mut [[u8; 4]]) -> Option<&'a [u8]> {
+    let width = scanline.len();
+    let rle = (8..0x8000).contains(&width) && data.len() >= 4 && data[0] == 2 && data[1] == 2 && data[2] & 0x80 == 0;
+    if !rle {
+        let bytes = data.get(..width * 4)?;
+        for (pixel, rgbe) in scanline.iter_mut().zip(bytes.chunks_exact(4)) {
+            pixel.copy_from_slice(rgbe);
+        }
+        return Some(&data[width * 4..]);
+    }
+    if (data[2] as usize) << 8 | data[3] as usize != width {
+        return None;
+    }
+
+    // each channel is stored separately as runs and literal spans
+    let mut data = &data[4..];
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, tail) = data.split_first()?;
+            if count > 128 {
+                let count = (count - 128) as usize;
+                let (&value, tail) = tail.split_first()?;
+                for pixel in scanline.get_mut(x..x + count)? {
+                    pixel[channel] = value;
+                }
+                x += count;
+                data = tail;
+            } else {
+                let count = count as usize;
+                if count == 0 {
+                    return None;
+                }
+                for (pixel, &value) in scanline.get_mut(x..x + count)?.iter_mut().zip(tail.get(..count)?) {
+                    pixel[channel] = value;
+                }
+                x += count;
+                data = &tail[count..];
+            }
+        }
+    }
+    Some(data)
+}
+
+fn decode_rgbe([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    if e == 0 {
+        return [0.0; 3];
+    }
+    let scale = 2f32.powi(e as i32 - (128 + 8));
+    [r as f32 * scale, g as f32 * scale, b as f32 * scale]
+}
+
+/// Packs a linear color into `Rgb9e5Ufloat`, which keeps lightmaps HDR at four bytes a texel.
+fn encode_rgb9e5(color: [f32; 3]) -> u32 {
+    const MANTISSA_BITS: i32 = 9;
+    const BIAS: i32 = 15;
+    const MAX: f32 = 65408.0;
+
+    let [r, g, b] = color.map(|c| if c.is_nan() { 0.0 } else { c.clamp(0.0, MAX) });
+    let max = r.max(g).max(b);
+    let mut exponent = (max.log2().floor() as i32).max(-BIAS - 1) + 1 + BIAS;
+    let mut denominator = 2f32.powi(exponent - BIAS - MANTISSA_BITS);
+    if (max / denominator + 0.5).floor() as i32 == 1 << MANTISSA_BITS {
+        denominator *= 2.0;
+        exponent += 1;
+    }
+    let mantissa = |c: f32| (c / denominator + 0.5).floor() as u32;
+    mantissa(r) | mantissa(g) << 9 | mantissa(b) << 18 | (exponent as u32) << 27
+}
+
+/// A baked lightmap texture holding the indirect (and static direct) lighting for the static
+/// meshes whose second UV set maps into it.
+pub struct Lightmap {
+    pub texture: wgpu::Texture,
+    /// For `PbrMaterial::with_lightmap`.
+    pub view: Arc<wgpu::TextureView>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Lightmap {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgb9e5Ufloat;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, label: &str, image: &HdrImage) -> Self {
+        let texels: Vec<u32> = image.pixels.iter().map(|&p| encode_rgb9e5(p)).collect();
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: image.width,
+                    height: image.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            },
+            bytemuck::cast_slice(&texels),
+        );
+        let view = Arc::new(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        Lightmap {
+            texture,
+            view,
+            width: image.width,
+            height: image.height,
+        }
+    }
+
+    /// Imports an externally baked `.hdr` lightmap.
+    /// The texture and sampler entries `LIGHTMAP_SHADER` expects, for the material layout.
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 8,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ]
+    }
+
+    /// The sampler every lightmapped material shares: linear and clamped, so charts at the
+    /// atlas edge don't wrap around.
+    pub fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("lightmap sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        })
+    }
+
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: impl AsRef<Path>) -> Result<Self, LightmapError> {
+        let path = path.as_ref();
+        let mut timer = LoadTimer::for_path("lightmap", path);
+        let image = HdrImage::load_timed(path, &mut timer)?;
+        let lightmap = Self::new(device, queue, &path.to_string_lossy(), &image);
+        // one RGB9E5 texel each
+        timer.add_upload_bytes(image.pixels.len() * 4);
+        timer.end(LoadStage::Upload);
+        timer.finish();
+        Ok(lightmap)
+    }
+}
@@ -0,0 +1,152 @@
+use bytemuck::Zeroable;
+use glam::Vec3;
+
+use crate::light_profiles::{CookieId, ProfileId};
+
+/// WGSL for lit shaders: the frame's lights at binding 1 of group 0, next to the view uniform,
+/// with `light_count()`, `light_kind(index)`, `light_shadow_index(index)` and
+/// `light_sample(index, position)`.
+pub const LIGHTS_SHADER: &str = include_str!("shaders/lights.wgsl");
+
+/// Lights a frame can have; further ones are ignored.
+pub const MAX_LIGHTS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Parallel rays from infinitely far away, like the sun. Ignores position and range.
+    Directional,
+    /// Shines in every direction from its position.
+    Point,
+    /// A cone from its position, full brightness within the inner half angle and fading out
+    /// towards the outer one, both in radians.
+    Spot { inner_angle: f32, outer_angle: f32 },
+}
+
+/// A light as the renderer takes it for one frame, placed in the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameLight {
+    pub kind: LightKind,
+    pub position: Vec3,
+    /// Direction the light travels in.
+    pub direction: Vec3,
+    /// Linear RGB, intensity applied. Point and spot lights have this at one unit away and fall
+    /// off with the square of the distance.
+    pub color: Vec3,
+    /// Distance at which point and spot lights have faded out completely.
+    pub range: f32,
+    /// Shapes a point or spot light's brightness by direction; point lights need a direction
+    /// for it.
+    pub profile: Option<ProfileId>,
+    /// Projected along a spot light's cone; other lights ignore it.
+    pub cookie: Option<CookieId>,
+}
+
+impl FrameLight {
+    // `shadow` is the light's index among the lights of its kind
+    fn uniform(&self, shadow: usize) -> LightUniform {
+        let (kind, spot) = match self.kind {
+            LightKind::Directional => (0.0, [0.0; 4]),
+            LightKind::Point => (1.0, [0.0, 0.0, shadow as f32, 0.0]),
+            LightKind::Spot { inner_angle, outer_angle } => {
+                let outer = outer_angle.clamp(0.0, std::f32::consts::FRAC_PI_2);
+                // smoothstep needs the edges apart
+                let inner = inner_angle.clamp(0.0, outer);
+                let (cos_inner, cos_outer) = (inner.cos(), outer.cos());
+                (2.0, [cos_inner, cos_outer.min(cos_inner - 0.0001), shadow as f32, 0.0])
+            }
+        };
+        LightUniform {
+            position_range: self.position.extend(self.range.max(0.01)).into(),
+            direction_kind: self.direction.normalize_or_zero().extend(kind).into(),
+            color: self.color.extend(0.0).into(),
+            spot,
+            profile: [
+                self.profile.map_or(-1.0, |ProfileId(layer)| layer as f32),
+                self.cookie.map_or(-1.0, |CookieId(layer)| layer as f32),
+                0.0,
+                0.0,
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightUniform {
+    position_range: [f32; 4],
+    direction_kind: [f32; 4],
+    color: [f32; 4],
+    spot: [f32; 4],
+    profile: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsUniform {
+    header: [u32; 4],
+    lights: [LightUniform; MAX_LIGHTS],
+}
+
+/// The uniform buffer `LIGHTS_SHADER` reads the lights from.
+pub struct LightBuffer {
+    buffer: wgpu::Buffer,
+    warned: bool,
+}
+
+impl LightBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        LightBuffer {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("lights"),
+                size: std::mem::size_of::<LightsUniform>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            warned: false,
+        }
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Replaces the lights with the first `MAX_LIGHTS` of `lights`. Their spot and point lights
+    /// are numbered separately, in order, for `LOCAL_SHADOW_SHADER` to find their shadows by.
+    pub fn write(&mut self, queue: &wgpu::Queue, lights: &[FrameLight]) {
+        if lights.len() > MAX_LIGHTS && !self.warned {
+            log::warn!("Only the first {} of {} lights are drawn", MAX_LIGHTS, lights.len());
+            self.warned = true;
+        }
+        let mut uniform = LightsUniform::zeroed();
+        let count = lights.len().min(MAX_LIGHTS);
+        uniform.header[0] = count as u32;
+        let (mut spots, mut points) = (0, 0);
+        for (slot, light) in uniform.lights.iter_mut().zip(lights) {
+            let counter = match light.kind {
+                LightKind::Directional => None,
+                LightKind::Point => Some(&mut points),
+                LightKind::Spot { .. } => Some(&mut spots),
+            };
+            let shadow = counter.map_or(0, |counter| {
+                *counter += 1;
+                *counter - 1
+            });
+            *slot = light.uniform(shadow);
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// The layout entry of the buffer at `binding`, which is 1 in `LIGHTS_SHADER`.
+    pub fn layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<LightsUniform>() as u64),
+            },
+            count: None,
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    collections::VecDeque,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Loads kept for `LoadReport::slowest` and `loads`; totals count every load ever recorded.
+const KEPT_LOADS: usize = 1024;
+
+/// The steps an asset load is timed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadStage {
+    /// Reading the file.
+    Read,
+    /// Parsing and converting it into what the engine uses.
+    Decode,
+    /// Creating the GPU resources, including any baking done on the GPU. Measured on the CPU:
+    /// this is the time to issue the uploads, the copies themselves run once they're submitted.
+    Upload,
+}
+
+/// How long one asset took to load, and how big it was.
+#[derive(Clone, Debug, Default)]
+pub struct AssetLoad {
+    /// What kind of asset, like `"mesh"` or `"environment"`.
+    pub kind: &'static str,
+    /// The file it came from, or the label it was created with.
+    pub name: String,
+    /// Bytes read from disk.
+    pub file_bytes: u64,
+    /// Bytes handed to the GPU.
+    pub upload_bytes: u64,
+    pub read: Duration,
+    pub decode: Duration,
+    pub upload: Duration,
+}
+
+impl AssetLoad {
+    pub fn total(&self) -> Duration {
+        self.read + self.decode + self.upload
+    }
+}
+
+/// Times a load stage by stage; `finish` adds it to the profile. Loads that fail are dropped
+/// without being recorded.
+pub struct LoadTimer {
+    load: AssetLoad,
+    mark: Instant,
+}
+
+impl LoadTimer {
+    pub fn start(kind: &'static str, name: impl Into<String>) -> Self {
+        LoadTimer {
+            load: AssetLoad {
+                kind,
+                name: name.into(),
+                ..AssetLoad::default()
+            },
+            mark: Instant::now(),
+        }
+    }
+
+    /// Like `start`, named after `path`.
+    pub fn for_path(kind: &'static str, path: &Path) -> Self {
+        Self::start(kind, path.display().to_string())
+    }
+
+    /// Ends `stage`, which is counted from the start or from the end of the previous stage.
+    /// A stage ended more than once adds up.
+    pub fn end(&mut self, stage: LoadStage) {
+        let now = Instant::now();
+        let elapsed = now - self.mark;
+        self.mark = now;
+        match stage {
+            LoadStage::Read => self.load.read += elapsed,
+            LoadStage::Decode => self.load.decode += elapsed,
+            LoadStage::Upload => self.load.upload += elapsed,
+        }
+    }
+
+    pub fn add_file_bytes(&mut self, bytes: usize) {
+        self.load.file_bytes += bytes as u64;
+    }
+
+    pub fn add_upload_bytes(&mut self, bytes: usize) {
+        self.load.upload_bytes += bytes as u64;
+    }
+
+    pub fn finish(self) {
+        record(self.load);
+    }
+}
+
+/// The sums over every asset of a kind, or over all of them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoadTotals {
+    pub assets: u64,
+    pub file_bytes: u64,
+    pub upload_bytes: u64,
+    pub read: Duration,
+    pub decode: Duration,
+    pub upload: Duration,
+}
+
+impl LoadTotals {
+    fn add(&mut self, load: &AssetLoad) {
+        self.assets += 1;
+        self.file_bytes += load.file_bytes;
+        self.upload_bytes += load.upload_bytes;
+        self.read += load.read;
+        self.decode += load.decode;
+        self.upload += load.upload;
+    }
+
+    pub fn total(&self) -> Duration {
+        self.read + self.decode + self.upload
+    }
+}
+
+struct Profile {
+    totals: LoadTotals,
+    kinds: Vec<(&'static str, LoadTotals)>,
+    loads: VecDeque<AssetLoad>,
+}
+
+static PROFILE: Mutex<Profile> = Mutex::new(Profile {
+    totals: LoadTotals {
+        assets: 0,
+        file_bytes: 0,
+        upload_bytes: 0,
+        read: Duration::ZERO,
+        decode: Duration::ZERO,
+        upload: Duration::ZERO,
+    },
+    kinds: Vec::new(),
+    loads: VecDeque::new(),
+});
+
+/// Adds a load timed some other way, e.g. by a game's own loaders.
+pub fn record(load: AssetLoad) {
+    let Ok(mut profile) = PROFILE.lock() else {
+        return;
+    };
+    profile.totals.add(&load);
+    match profile.kinds.iter_mut().find(|(kind, _)| *kind == load.kind) {
+        Some((_, totals)) => totals.add(&load),
+        None => {
+            let mut totals = LoadTotals::default();
+            totals.add(&load);
+            profile.kinds.push((load.kind, totals));
+        }
+    }
+    if profile.loads.len() == KEPT_LOADS {
+        profile.loads.pop_front();
+    }
+    profile.loads.push_back(load);
+}
+
+/// The most recent loads, oldest first.
+pub fn loads() -> Vec<AssetLoad> {
+    PROFILE.lock().map(|profile| profile.loads.iter().cloned().collect()).unwrap_or_default()
+}
+
+/// Forgets everything recorded so far, e.g. before loading a level to profile just that.
+pub fn clear() {
+    if let Ok(mut profile) = PROFILE.lock() {
+        profile.totals = LoadTotals::default();
+        profile.kinds.clear();
+        profile.loads.clear();
+    }
+}
+
+/// What loading has cost so far, with the `slowest` loads first.
+pub fn report(slowest: usize) -> LoadReport {
+    let Ok(profile) = PROFILE.lock() else {
+        return LoadReport::default();
+    };
+    let mut loads: Vec<_> = profile.loads.iter().cloned().collect();
+    loads.sort_by_key(|load| std::cmp::Reverse(load.total()));
+    loads.truncate(slowest);
+    let mut kinds = profile.kinds.clone();
+    kinds.sort_by_key(|(_, totals)| std::cmp::Reverse(totals.total()));
+    LoadReport {
+        totals: profile.totals,
+        kinds,
+        slowest: loads,
+    }
+}
+
+/// A summary of the asset loads recorded so far, see `report`.
+#[derive(Clone, Debug, Default)]
+pub struct LoadReport {
+    pub totals: LoadTotals,
+    /// Per kind of asset, the most expensive first.
+    pub kinds: Vec<(&'static str, LoadTotals)>,
+    /// The slowest of the recent loads, slowest first.
+    pub slowest: Vec<AssetLoad>,
+}
+
+impl LoadReport {
+    #[cfg(feature = "egui")]
+    /// Totals, then the slowest loads stage by stage, for the stats panel.
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        use crate::gpu_memory::format_bytes;
+
+        let ms = |duration: Duration| format!("{:.1}", duration.as_secs_f64() * 1000.0);
+        let totals = self.totals;
+        ui.label(format!(
+            "{} assets, {} read, {} uploaded",
+            totals.assets,
+            format_bytes(totals.file_bytes),
+            format_bytes(totals.upload_bytes)
+        ));
+        ui.label(format!(
+            "{} ms: read {}, decode {}, upload {}",
+            ms(totals.total()),
+            ms(totals.read),
+            ms(totals.decode),
+            ms(totals.upload)
+        ));
+        egui::Grid::new("load kinds").striped(true).show(ui, |ui| {
+            for header in ["Kind", "Assets", "Size", "Total ms", "Upload ms"] {
+                ui.strong(header);
+            }
+            ui.end_row();
+            for (kind, totals) in &self.kinds {
+                ui.label(*kind);
+                ui.label(totals.assets.to_string());
+                ui.label(format_bytes(totals.file_bytes.max(totals.upload_bytes)));
+                ui.label(ms(totals.total()));
+                ui.label(ms(totals.upload));
+                ui.end_row();
+            }
+        });
+        ui.separator();
+        egui::Grid::new("slowest loads").striped(true).show(ui, |ui| {
+            for header in ["Asset", "Size", "Read ms", "Decode ms", "Upload ms"] {
+                ui.strong(header);
+            }
+            ui.end_row();
+            for load in &self.slowest {
+                ui.label(&load.name).on_hover_text(load.kind);
+                ui.label(format_bytes(load.file_bytes.max(load.upload_bytes)));
+                ui.label(ms(load.read));
+                ui.label(ms(load.decode));
+                ui.label(ms(load.upload));
+                ui.end_row();
+            }
+        });
+    }
+}
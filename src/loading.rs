@@ -0,0 +1,245 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    time::Instant,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+enum TaskState {
+    /// Fraction done, 0 to 1.
+    Pending(f32),
+    Done,
+    Failed(String),
+}
+
+struct TaskShared {
+    name: String,
+    weight: f32,
+    state: Mutex<TaskState>,
+}
+
+impl TaskShared {
+    fn set(&self, state: TaskState) {
+        if let Ok(mut current) = self.state.lock() {
+            // a finished task stays finished
+            if matches!(*current, TaskState::Pending(_)) {
+                *current = state;
+            }
+        }
+    }
+
+    fn state(&self) -> TaskState {
+        self.state.lock().map_or(TaskState::Failed("task state is poisoned".into()), |state| state.clone())
+    }
+}
+
+/// Reports the progress of one task of a `LoadPhase`, from any thread. A reporter dropped
+/// without `finish` or `fail` leaves its task pending.
+#[derive(Clone)]
+pub struct ProgressReporter {
+    task: Arc<TaskShared>,
+}
+
+impl ProgressReporter {
+    /// How much of the task is done, 0 to 1.
+    pub fn set(&self, fraction: f32) {
+        self.task.set(TaskState::Pending(fraction.clamp(0.0, 1.0)));
+    }
+
+    pub fn finish(&self) {
+        self.task.set(TaskState::Done);
+    }
+
+    pub fn fail(&self, message: impl Into<String>) {
+        self.task.set(TaskState::Failed(message.into()));
+    }
+}
+
+/// The result of a task started with `LoadPhase::spawn`.
+pub struct LoadHandle<T> {
+    receiver: mpsc::Receiver<T>,
+    result: Option<T>,
+}
+
+impl<T> LoadHandle<T> {
+    /// The result once the task has succeeded, taken out of the handle; `None` while it is
+    /// still running, if it failed, and every time after the result was taken.
+    pub fn take(&mut self) -> Option<T> {
+        if let Ok(result) = self.receiver.try_recv() {
+            self.result = Some(result);
+        }
+        self.result.take()
+    }
+}
+
+/// The loads a game waits for before it can start, e.g. a level's assets, gathered into one
+/// progress value for a loading screen. Tasks run on worker threads with `spawn`, or anywhere
+/// else with a reporter from `track`, and are weighted by how long they are expected to take.
+///
+/// Once every task has finished the phase is over: `progress` is 1 and the tasks are cleared
+/// on the next `update`, so the next load starts from 0 again.
+pub struct LoadPhase {
+    tasks: Vec<Arc<TaskShared>>,
+    failures: Vec<(String, String)>,
+    started: Option<Instant>,
+}
+
+impl LoadPhase {
+    pub fn new() -> Self {
+        LoadPhase {
+            tasks: Vec::new(),
+            failures: Vec::new(),
+            started: None,
+        }
+    }
+
+    /// Adds a task that reports its own progress through the returned reporter.
+    pub fn track(&mut self, name: impl Into<String>, weight: f32) -> ProgressReporter {
+        let task = Arc::new(TaskShared {
+            name: name.into(),
+            weight: weight.max(0.0),
+            state: Mutex::new(TaskState::Pending(0.0)),
+        });
+        self.tasks.push(task.clone());
+        self.started.get_or_insert_with(Instant::now);
+        ProgressReporter { task }
+    }
+
+    /// Runs `work` on a worker thread as a task of the phase. `work` may report partial
+    /// progress through the reporter it is given; it finishes when `work` returns.
+    pub fn spawn<T: Send + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        weight: f32,
+        work: impl FnOnce(&ProgressReporter) -> Result<T, String> + Send + 'static,
+    ) -> LoadHandle<T> {
+        let name = name.into();
+        let reporter = self.track(name.clone(), weight);
+        let (sender, receiver) = mpsc::channel();
+        let thread_reporter = reporter.clone();
+        let spawned = std::thread::Builder::new().name(format!("load: {}", name)).spawn(move || {
+            match work(&thread_reporter) {
+                Ok(result) => {
+                    // the result has to be there by the time the task counts as done
+                    sender.send(result).ok();
+                    thread_reporter.finish();
+                }
+                Err(e) => thread_reporter.fail(e),
+            }
+        });
+        if let Err(e) = spawned {
+            reporter.fail(e.to_string());
+        }
+        LoadHandle { receiver, result: None }
+    }
+
+    /// Collects failures and ends the phase once every task has finished. The engine calls it
+    /// every frame.
+    pub fn update(&mut self) {
+        for task in &self.tasks {
+            if let TaskState::Failed(message) = task.state() {
+                if !self.failures.iter().any(|(name, _)| *name == task.name) {
+                    log::error!("Failed to load {}: {}", task.name, message);
+                    self.failures.push((task.name.clone(), message));
+                }
+            }
+        }
+        if !self.is_loading() {
+            self.tasks.clear();
+            self.started = None;
+        }
+    }
+
+    /// Whether any task is still pending.
+    pub fn is_loading(&self) -> bool {
+        self.tasks.iter().any(|task| matches!(task.state(), TaskState::Pending(_)))
+    }
+
+    /// How much of the phase is done, 0 to 1, weighting each task's own progress; failed tasks
+    /// count as done. 1 when there is nothing to load.
+    pub fn progress(&self) -> f32 {
+        let total: f32 = self.tasks.iter().map(|task| task.weight).sum();
+        if total <= 0.0 {
+            return if self.is_loading() { 0.0 } else { 1.0 };
+        }
+        let done: f32 = self
+            .tasks
+            .iter()
+            .map(|task| match task.state() {
+                TaskState::Pending(fraction) => fraction * task.weight,
+                TaskState::Done | TaskState::Failed(_) => task.weight,
+            })
+            .sum();
+        (done / total).clamp(0.0, 1.0)
+    }
+
+    /// The names of the pending tasks, in the order they were added.
+    pub fn pending(&self) -> Vec<&str> {
+        self.tasks
+            .iter()
+            .filter(|task| matches!(task.state(), TaskState::Pending(_)))
+            .map(|task| task.name.as_str())
+            .collect()
+    }
+
+    /// The tasks that failed, with their error, since the last `clear_failures`.
+    pub fn failures(&self) -> &[(String, String)] {
+        &self.failures
+    }
+
+    pub fn clear_failures(&mut self) {
+        self.failures.clear();
+    }
+
+    /// Seconds since the phase's first task was added.
+    pub fn elapsed(&self) -> f32 {
+        self.started.map_or(0.0, |started| started.elapsed().as_secs_f32())
+    }
+}
+
+impl Default for LoadPhase {
+    fn default() -> Self {
+        LoadPhase::new()
+    }
+}
+
+/// The screen the engine shows instead of the game while its `LoadPhase` is loading: a title,
+/// an animated progress bar and the task being waited on.
+#[derive(Clone, Debug)]
+pub struct LoadingScreen {
+    /// Off leaves the game's own UI up while loading, e.g. for a custom loading screen.
+    pub enabled: bool,
+    pub title: String,
+}
+
+impl Default for LoadingScreen {
+    fn default() -> Self {
+        LoadingScreen {
+            enabled: true,
+            title: "Loading".into(),
+        }
+    }
+}
+
+impl LoadingScreen {
+    /// Whether the screen covers the game this frame.
+    pub fn is_shown(&self, phase: &LoadPhase) -> bool {
+        self.enabled && phase.is_loading()
+    }
+
+    #[cfg(feature = "egui")]
+    /// Fills the window with the screen, over everything else.
+    pub fn ui(&self, context: &egui::CtxRef, phase: &LoadPhase) {
+        egui::CentralPanel::default().show(context, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(ui.available_height() * 0.4);
+                ui.heading(&self.title);
+                ui.add_space(12.0);
+                let width = ui.available_width().min(400.0);
+                ui.add(egui::ProgressBar::new(phase.progress()).desired_width(width).show_percentage().animate(true));
+                if let Some(task) = phase.pending().first() {
+                    ui.label(*task);
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,536 @@
+use glam::{Mat4, Vec3};
+
+use crate::camera::{Camera, Projection};
+
+/// WGSL for lit shaders: `spot_shadow(index, position)`, `point_shadow(index, position)` and
+/// `local_shadow(light, position)` for a light of `LIGHTS_SHADER`, with bindings 3 to 6 of
+/// `LOCAL_SHADOW_GROUP`. `SHADOW_SHADER` uses bindings 0 to 2 of the same group, so a lighting
+/// layout can combine `LocalShadows::layout_entries` with its.
+pub const LOCAL_SHADOW_SHADER: &str = include_str!("shaders/local_shadows.wgsl");
+
+/// Bind group index `LOCAL_SHADOW_SHADER` declares its bindings at.
+pub const LOCAL_SHADOW_GROUP: u32 = 2;
+
+/// Spot lights with a shadow the shader can read; further casters are ignored.
+pub const MAX_SPOT_SHADOWS: usize = 16;
+/// Point lights with a shadow the shader can read, each six layers of the face array.
+pub const MAX_POINT_SHADOWS: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpotShadowCaster {
+    pub position: Vec3,
+    pub direction: Vec3,
+    /// Half angle of the cone, in radians.
+    pub outer_angle: f32,
+    pub range: f32,
+    /// Moves receivers towards the light before the depth test, in world units.
+    pub bias: f32,
+}
+
+impl SpotShadowCaster {
+    /// View-projection fitted to the cone: its field of view spans the outer angle and its far
+    /// plane sits at the light's range.
+    pub fn view_projection(&self) -> Mat4 {
+        let direction = self.direction.normalize_or_zero();
+        let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+        let fov = (self.outer_angle * 2.0).clamp(0.01, std::f32::consts::PI - 0.01);
+        let near = (self.range * 0.01).max(0.05);
+        Mat4::perspective_rh(fov, 1.0, near, self.range.max(near * 2.0))
+            * Mat4::look_at_rh(self.position, self.position + direction, up)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PointShadowCaster {
+    pub position: Vec3,
+    pub range: f32,
+    /// Moves receivers towards the light before the depth test, in world units.
+    pub bias: f32,
+}
+
+impl PointShadowCaster {
+    fn near(&self) -> f32 {
+        (self.range * 0.01).max(0.05)
+    }
+
+    fn far(&self) -> f32 {
+        self.range.max(self.near() * 2.0)
+    }
+
+    /// View-projection for each cube face, in +X, -X, +Y, -Y, +Z, -Z order.
+    pub fn face_view_projections(&self) -> [Mat4; 6] {
+        let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, self.near(), self.far());
+        let faces = [
+            (Vec3::X, Vec3::Y),
+            (-Vec3::X, Vec3::Y),
+            (Vec3::Y, -Vec3::Z),
+            (-Vec3::Y, Vec3::Z),
+            (Vec3::Z, Vec3::Y),
+            (-Vec3::Z, Vec3::Y),
+        ];
+        faces.map(|(forward, up)| projection * Mat4::look_at_rh(self.position, self.position + forward, up))
+    }
+}
+
+/// Shadow map resolution for a light whose influence is a sphere at `center` with `radius`,
+/// from how tall that sphere appears to `camera`: the power of two nearest its height in pixels,
+/// within `min..=max`. Lights the camera is inside of get `max`.
+pub fn coverage_resolution(camera: &Camera, target_size: (u32, u32), center: Vec3, radius: f32, min: u32, max: u32) -> u32 {
+    let (_, _, _, height) = camera.viewport.pixels(target_size);
+    let distance = camera.position.distance(center);
+    let fraction = match camera.projection {
+        _ if distance <= radius => return max,
+        Projection::Perspective { fov_y, .. } => radius / (distance * (fov_y * 0.5).tan()),
+        Projection::Orthographic { height, .. } => radius * 2.0 / height,
+    };
+    let pixels = (fraction.min(1.0) * height as f32) as u32;
+    pixels.max(1).next_power_of_two().clamp(min, max)
+}
+
+/// A square region of the shadow atlas, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasTile {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+}
+
+/// Packs power-of-two square tiles into a square atlas. Tiles are placed largest first along a
+/// Z-order curve, which leaves no gaps for power-of-two sizes. When the requests don't fit, the
+/// largest tiles are halved first, so every light keeps some shadow before any keeps a big one.
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowAtlas {
+    pub size: u32,
+    /// Smallest tile handed out; requests below it are rounded up.
+    pub min_tile: u32,
+}
+
+impl ShadowAtlas {
+    /// One tile per requested size, in request order, or `None` for requests left over once
+    /// every tile is down to `min_tile`.
+    pub fn pack(&self, requests: &[u32]) -> Vec<Option<AtlasTile>> {
+        let min_tile = self.min_tile.next_power_of_two().min(self.size);
+        let cells = |size: u32| ((size / min_tile) as u64).pow(2);
+        let capacity = cells(self.size);
+
+        let mut sizes: Vec<u32> = requests
+            .iter()
+            .map(|&size| size.next_power_of_two().clamp(min_tile, self.size))
+            .collect();
+        let mut order: Vec<usize> = (0..requests.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(sizes[i]));
+        let mut used: u64 = sizes.iter().map(|&size| cells(size)).sum();
+        while used > capacity {
+            // halving the last of the largest tiles keeps `order` sorted
+            let largest = sizes[order[0]];
+            if largest == min_tile {
+                break;
+            }
+            let last_of_largest = order.iter().rposition(|&i| sizes[i] == largest).unwrap();
+            sizes[order[last_of_largest]] = largest / 2;
+            used -= cells(largest) - cells(largest / 2);
+        }
+
+        let mut tiles = vec![None; requests.len()];
+        // position along the Z-order curve, in units of min_tile cells; descending sizes keep
+        // it aligned to every later tile
+        let mut cursor = 0u64;
+        for i in order {
+            if cursor + cells(sizes[i]) > capacity {
+                break;
+            }
+            let (x, y) = morton_decode(cursor);
+            tiles[i] = Some(AtlasTile {
+                x: x * min_tile,
+                y: y * min_tile,
+                size: sizes[i],
+            });
+            cursor += cells(sizes[i]);
+        }
+        tiles
+    }
+}
+
+fn morton_decode(code: u64) -> (u32, u32) {
+    let compact = |mut v: u64| {
+        v &= 0x5555_5555_5555_5555;
+        v = (v | (v >> 1)) & 0x3333_3333_3333_3333;
+        v = (v | (v >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v >> 4)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v >> 8)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v >> 16)) & 0x0000_0000_ffff_ffff;
+        v as u32
+    };
+    (compact(code), compact(code >> 1))
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LocalShadowSettings {
+    /// Largest atlas tile a spot light gets, when it covers the screen.
+    pub max_spot_resolution: u32,
+    pub min_spot_resolution: u32,
+    /// The bias `MeshRenderer` gives the casters it makes from its lights.
+    pub bias: f32,
+}
+
+impl Default for LocalShadowSettings {
+    fn default() -> Self {
+        LocalShadowSettings {
+            max_spot_resolution: 1024,
+            min_spot_resolution: 64,
+            bias: 0.05,
+        }
+    }
+}
+
+/// Which caster a `ShadowTarget` belongs to, as an index into the slices given to `update`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadowCaster {
+    Spot(usize),
+    /// A point light and the cube face, in +X, -X, +Y, -Y, +Z, -Z order.
+    Point(usize, usize),
+}
+
+/// One shadow view to render: draw the casters depth-only into `depth`, with the viewport and
+/// scissor set to `viewport` and the depth attachment loaded, not cleared.
+pub struct ShadowTarget<'a> {
+    pub caster: ShadowCaster,
+    pub depth: &'a wgpu::TextureView,
+    /// x, y, width and height in pixels.
+    pub viewport: (u32, u32, u32, u32),
+    pub view_projection: Mat4,
+    pub position: Vec3,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuSpotShadow {
+    view_projection: [[f32; 4]; 4],
+    atlas_rect: [f32; 4],
+    position_bias: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuPointShadow {
+    faces: [[[f32; 4]; 4]; 6],
+    position_bias: [f32; 4],
+    params: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuShadowData {
+    header: [u32; 4],
+    spots: [GpuSpotShadow; MAX_SPOT_SHADOWS],
+    points: [GpuPointShadow; MAX_POINT_SHADOWS],
+}
+
+struct PlannedView {
+    caster: ShadowCaster,
+    viewport: (u32, u32, u32, u32),
+    view_projection: Mat4,
+    position: Vec3,
+}
+
+/// Shadow maps for spot and point lights. Spot lights share one depth atlas, each with a tile
+/// sized by how much of the screen its cone covers; point lights get six layers each of a depth
+/// array, one per cube face, which unlike cube arrays every device supports.
+pub struct LocalShadows {
+    pub settings: LocalShadowSettings,
+    atlas: ShadowAtlas,
+    atlas_view: wgpu::TextureView,
+    faces: wgpu::Texture,
+    face_array: wgpu::TextureView,
+    cube_resolution: u32,
+    cube_capacity: u32,
+    uniform: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    views: Vec<PlannedView>,
+}
+
+impl LocalShadows {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// `atlas_size` is the side of the spot light atlas; `cube_resolution` the face size of the
+    /// point light cubes, of which there are `cube_capacity`, at most `MAX_POINT_SHADOWS`.
+    pub fn new(device: &wgpu::Device, atlas_size: u32, cube_resolution: u32, cube_capacity: u32) -> Self {
+        let cube_capacity = cube_capacity.clamp(1, MAX_POINT_SHADOWS as u32);
+        let depth_texture = |label, size, layers| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: layers,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            })
+        };
+        let atlas_view =
+            depth_texture("spot shadow atlas", atlas_size, 1).create_view(&wgpu::TextureViewDescriptor::default());
+        let faces = depth_texture("point shadow faces", cube_resolution, cube_capacity * 6);
+        let face_array = faces.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("point shadow face array"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("local shadow data"),
+            size: std::mem::size_of::<GpuShadowData>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("local shadow layout"),
+            entries: &Self::layout_entries(),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow comparison sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("local shadow bind group"),
+            layout: &layout,
+            entries: &Self::entries(&uniform, &atlas_view, &face_array, &sampler),
+        });
+
+        LocalShadows {
+            settings: LocalShadowSettings::default(),
+            atlas: ShadowAtlas {
+                size: atlas_size,
+                min_tile: 32,
+            },
+            atlas_view,
+            faces,
+            face_array,
+            cube_resolution,
+            cube_capacity,
+            uniform,
+            sampler,
+            layout,
+            bind_group,
+            views: Vec::new(),
+        }
+    }
+
+    /// The entries `LOCAL_SHADOW_SHADER` expects, for building a combined lighting layout.
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 4] {
+        let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Depth,
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            texture(4, wgpu::TextureViewDimension::D2),
+            texture(5, wgpu::TextureViewDimension::D2Array),
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+        ]
+    }
+
+    /// The resources of `layout_entries`, for a combined lighting bind group.
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 4] {
+        Self::entries(&self.uniform, &self.atlas_view, &self.face_array, &self.sampler)
+    }
+
+    fn entries<'a>(
+        uniform: &'a wgpu::Buffer,
+        atlas: &'a wgpu::TextureView,
+        faces: &'a wgpu::TextureView,
+        sampler: &'a wgpu::Sampler,
+    ) -> [wgpu::BindGroupEntry<'a>; 4] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(atlas),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::TextureView(faces),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ]
+    }
+
+    /// For lit pipelines with these bindings alone, at `LOCAL_SHADOW_GROUP`.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn atlas(&self) -> &wgpu::TextureView {
+        &self.atlas_view
+    }
+
+    /// The point lights' cube faces, six layers per light.
+    pub fn faces(&self) -> &wgpu::TextureView {
+        &self.face_array
+    }
+
+    /// Sizes and packs this frame's shadow views for the casters seen by `camera` and uploads
+    /// what the shader needs. Shader indices match the slice indices; casters past the limits
+    /// or that didn't fit in the atlas are unshadowed.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        target_size: (u32, u32),
+        spots: &[SpotShadowCaster],
+        points: &[PointShadowCaster],
+    ) {
+        let settings = self.settings;
+        let spots = &spots[..spots.len().min(MAX_SPOT_SHADOWS)];
+        let points = &points[..points.len().min(self.cube_capacity as usize)];
+        let resolutions: Vec<u32> = spots
+            .iter()
+            .map(|spot| {
+                // the cone's bounding sphere: centered halfway down its axis
+                let center = spot.position + spot.direction.normalize_or_zero() * spot.range * 0.5;
+                let radius = spot.range * 0.5 / spot.outer_angle.cos().max(0.1);
+                let max = settings.max_spot_resolution.min(self.atlas.size);
+                coverage_resolution(camera, target_size, center, radius, settings.min_spot_resolution.min(max), max)
+            })
+            .collect();
+        let tiles = self.atlas.pack(&resolutions);
+
+        let mut data = GpuShadowData {
+            header: [spots.len() as u32, points.len() as u32, 0, 0],
+            spots: [GpuSpotShadow::default(); MAX_SPOT_SHADOWS],
+            points: [GpuPointShadow::default(); MAX_POINT_SHADOWS],
+        };
+        self.views.clear();
+        let atlas_size = self.atlas.size as f32;
+        for (index, (spot, tile)) in spots.iter().zip(tiles).enumerate() {
+            let Some(tile) = tile else {
+                continue;
+            };
+            let view_projection = spot.view_projection();
+            data.spots[index] = GpuSpotShadow {
+                view_projection: view_projection.to_cols_array_2d(),
+                atlas_rect: [
+                    tile.x as f32 / atlas_size,
+                    tile.y as f32 / atlas_size,
+                    tile.size as f32 / atlas_size,
+                    tile.size as f32 / atlas_size,
+                ],
+                position_bias: spot.position.extend(spot.bias).into(),
+            };
+            self.views.push(PlannedView {
+                caster: ShadowCaster::Spot(index),
+                viewport: (tile.x, tile.y, tile.size, tile.size),
+                view_projection,
+                position: spot.position,
+            });
+        }
+        for (index, point) in points.iter().enumerate() {
+            let faces = point.face_view_projections();
+            data.points[index] = GpuPointShadow {
+                faces: faces.map(|face| face.to_cols_array_2d()),
+                position_bias: point.position.extend(point.bias).into(),
+                params: [index as f32 * 6.0, 0.0, 0.0, 1.0],
+            };
+            for (face, view_projection) in faces.into_iter().enumerate() {
+                self.views.push(PlannedView {
+                    caster: ShadowCaster::Point(index, face),
+                    viewport: (0, 0, self.cube_resolution, self.cube_resolution),
+                    view_projection,
+                    position: point.position,
+                });
+            }
+        }
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&data));
+    }
+
+    fn face_view(&self, layer: u32) -> wgpu::TextureView {
+        self.faces.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("point shadow face"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: std::num::NonZeroU32::new(1),
+            ..Default::default()
+        })
+    }
+
+    /// Clears the maps and calls `render` for every view planned by the last `update`.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        mut render: impl FnMut(&mut wgpu::CommandEncoder, ShadowTarget),
+    ) {
+        let clear = |encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView| {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("clear local shadows"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+        };
+        clear(encoder, &self.atlas_view);
+        for view in &self.views {
+            let depth = match view.caster {
+                ShadowCaster::Spot(_) => None,
+                ShadowCaster::Point(index, face) => {
+                    let face_view = self.face_view(index as u32 * 6 + face as u32);
+                    clear(encoder, &face_view);
+                    Some(face_view)
+                }
+            };
+            render(
+                encoder,
+                ShadowTarget {
+                    caster: view.caster,
+                    depth: depth.as_ref().unwrap_or(&self.atlas_view),
+                    viewport: view.viewport,
+                    view_projection: view.view_projection,
+                    position: view.position,
+                },
+            );
+        }
+    }
+}
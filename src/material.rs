@@ -0,0 +1,439 @@
+use std::{collections::HashMap, sync::Arc};
+
+use glam::Vec3;
+
+use crate::lightmap::Lightmap;
+
+/// Light a surface gives off by itself, independent of scene lighting. Anything emissive is
+/// bright enough in HDR to bloom, and with `BloomSource::Emissive` it is the only thing that does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Emissive {
+    /// Linear color, usually a saturated tint; brightness comes from `strength`.
+    pub color: [f32; 3],
+    /// Multiplier on `color`, in the same HDR units as the lights. 0 turns emission off.
+    pub strength: f32,
+}
+
+impl Default for Emissive {
+    fn default() -> Self {
+        Emissive {
+            color: [1.0; 3],
+            strength: 0.0,
+        }
+    }
+}
+
+impl Emissive {
+    /// The radiance shaders add to the lit color and write to the emissive buffer.
+    pub fn radiance(&self) -> Vec3 {
+        Vec3::from(self.color) * self.strength.max(0.0)
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.radiance().max_element() > 0.0
+    }
+
+    #[cfg(feature = "egui")]
+    /// Color and strength controls, for the material editor.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Emissive");
+            ui.color_edit_button_rgb(&mut self.color);
+        });
+        ui.add(
+            egui::Slider::new(&mut self.strength, 0.0..=100.0)
+                .logarithmic(true)
+                .text("Emissive strength"),
+        );
+    }
+}
+
+/// The textures a `PbrMaterial` can sample, in glTF's metallic-roughness layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureSlot {
+    /// Base color in RGB and coverage in A. Use an sRGB format.
+    Albedo,
+    /// Tangent space normal, RGB mapping -1..1 to 0..1.
+    Normal,
+    /// Roughness in G and metallic in B, linear.
+    MetallicRoughness,
+    /// Ambient occlusion in R, linear.
+    Occlusion,
+    /// Emitted color, multiplied by the material's `emissive`. Use an sRGB format.
+    Emissive,
+}
+
+impl TextureSlot {
+    pub const ALL: [TextureSlot; 5] = [
+        TextureSlot::Albedo,
+        TextureSlot::Normal,
+        TextureSlot::MetallicRoughness,
+        TextureSlot::Occlusion,
+        TextureSlot::Emissive,
+    ];
+
+    /// The `#ifdef` name the PBR shader uses for the slot.
+    pub fn shader_feature(self) -> &'static str {
+        match self {
+            TextureSlot::Albedo => "ALBEDO_MAP",
+            TextureSlot::Normal => "NORMAL_MAP",
+            TextureSlot::MetallicRoughness => "METALLIC_ROUGHNESS_MAP",
+            TextureSlot::Occlusion => "OCCLUSION_MAP",
+            TextureSlot::Emissive => "EMISSIVE_MAP",
+        }
+    }
+
+    /// Binding in the material bind group; 0 is the uniform and 1 the sampler.
+    pub fn binding(self) -> u32 {
+        2 + self as u32
+    }
+}
+
+/// Which texture slots a material fills, and whether it has a lightmap or samples the planar
+/// reflection. Materials with the same
+/// features share a bind group layout and shader variant.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialFeatures(u8);
+
+impl MaterialFeatures {
+    // the bit after the texture slots'
+    const LIGHTMAP: u8 = 1 << TextureSlot::ALL.len();
+    const PLANAR_REFLECTION: u8 = Self::LIGHTMAP << 1;
+
+    pub fn with(self, slot: TextureSlot) -> Self {
+        MaterialFeatures(self.0 | 1 << slot as u8)
+    }
+
+    pub fn has(self, slot: TextureSlot) -> bool {
+        self.0 & (1 << slot as u8) != 0
+    }
+
+    pub fn slots(self) -> impl Iterator<Item = TextureSlot> {
+        TextureSlot::ALL.into_iter().filter(move |&slot| self.has(slot))
+    }
+
+    pub fn with_lightmap(self) -> Self {
+        MaterialFeatures(self.0 | Self::LIGHTMAP)
+    }
+
+    pub fn has_lightmap(self) -> bool {
+        self.0 & Self::LIGHTMAP != 0
+    }
+
+    pub fn with_planar_reflection(self) -> Self {
+        MaterialFeatures(self.0 | Self::PLANAR_REFLECTION)
+    }
+
+    pub fn has_planar_reflection(self) -> bool {
+        self.0 & Self::PLANAR_REFLECTION != 0
+    }
+
+    /// The `#ifdef` names the PBR shader is compiled with: the slots', `LIGHTMAP` and
+    /// `PLANAR_REFLECTION`.
+    pub fn shader_features(self) -> impl Iterator<Item = &'static str> {
+        self.slots()
+            .map(TextureSlot::shader_feature)
+            .chain(self.has_lightmap().then_some("LIGHTMAP"))
+            .chain(self.has_planar_reflection().then_some("PLANAR_REFLECTION"))
+    }
+}
+
+/// A metallic-roughness material as glTF describes one: factors, each multiplied by its
+/// texture where the slot is filled.
+#[derive(Clone, Debug)]
+pub struct PbrMaterial {
+    /// Linear RGBA.
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: Emissive,
+    /// Strength of the normal map's X and Y.
+    pub normal_scale: f32,
+    /// How much of the occlusion map applies, 0 to 1.
+    pub occlusion_strength: f32,
+    /// Fragments with less alpha are discarded, for cutouts like leaves and fences. 0 keeps
+    /// everything.
+    pub alpha_cutoff: f32,
+    pub textures: [Option<Arc<wgpu::TextureView>>; 5],
+    /// Samples every texture; a repeating trilinear sampler when `None`.
+    pub sampler: Option<Arc<wgpu::Sampler>>,
+    /// Baked indirect light, e.g. a `Lightmap`'s view, read with the mesh's second UV set. It
+    /// replaces the ambient and environment diffuse light on meshes that have one.
+    pub lightmap: Option<Arc<wgpu::TextureView>>,
+    /// Scales the lightmap, e.g. to match a bake done at a different exposure.
+    pub lightmap_intensity: f32,
+    /// Mirrors and water: the specular reflection comes from the renderer's
+    /// `PlanarReflection` where it covers the surface, and the environment map elsewhere.
+    pub planar_reflection: bool,
+    /// How far the normal map bends the planar reflection, in texture space; around 0.02 for
+    /// water, 0 for a flat mirror.
+    pub reflection_distortion: f32,
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        PbrMaterial {
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 0.5,
+            emissive: Emissive::default(),
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            alpha_cutoff: 0.0,
+            textures: Default::default(),
+            sampler: None,
+            lightmap: None,
+            lightmap_intensity: 1.0,
+            planar_reflection: false,
+            reflection_distortion: 0.0,
+        }
+    }
+}
+
+impl PbrMaterial {
+    pub fn with_texture(mut self, slot: TextureSlot, view: Arc<wgpu::TextureView>) -> Self {
+        self.textures[slot as usize] = Some(view);
+        self
+    }
+
+    pub fn texture(&self, slot: TextureSlot) -> Option<&Arc<wgpu::TextureView>> {
+        self.textures[slot as usize].as_ref()
+    }
+
+    pub fn with_lightmap(mut self, view: Arc<wgpu::TextureView>, intensity: f32) -> Self {
+        self.lightmap = Some(view);
+        self.lightmap_intensity = intensity;
+        self
+    }
+
+    pub fn with_planar_reflection(mut self, distortion: f32) -> Self {
+        self.planar_reflection = true;
+        self.reflection_distortion = distortion;
+        self
+    }
+
+    pub fn features(&self) -> MaterialFeatures {
+        let features = TextureSlot::ALL
+            .into_iter()
+            .filter(|&slot| self.texture(slot).is_some())
+            .fold(MaterialFeatures::default(), MaterialFeatures::with);
+        let features = match self.lightmap {
+            Some(_) => features.with_lightmap(),
+            None => features,
+        };
+        match self.planar_reflection {
+            true => features.with_planar_reflection(),
+            false => features,
+        }
+    }
+
+    fn uniform(&self) -> MaterialUniform {
+        MaterialUniform {
+            base_color: self.base_color,
+            emissive: self.emissive.radiance().extend(0.0).into(),
+            factors: [
+                self.metallic.clamp(0.0, 1.0),
+                self.roughness.clamp(0.0, 1.0),
+                self.normal_scale,
+                self.occlusion_strength.clamp(0.0, 1.0),
+            ],
+            alpha: [self.alpha_cutoff.max(0.0), 0.0, 0.0, 0.0],
+            lighting: [self.lightmap_intensity.max(0.0), self.reflection_distortion, 0.0, 0.0],
+        }
+    }
+
+    #[cfg(feature = "egui")]
+    /// Factor controls, for the material editor. Call `Material::update` after a change.
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = self.uniform();
+        ui.horizontal(|ui| {
+            ui.label("Base color");
+            ui.color_edit_button_rgba_unmultiplied(&mut self.base_color);
+        });
+        ui.add(egui::Slider::new(&mut self.metallic, 0.0..=1.0).text("Metallic"));
+        ui.add(egui::Slider::new(&mut self.roughness, 0.0..=1.0).text("Roughness"));
+        if self.texture(TextureSlot::Normal).is_some() {
+            ui.add(egui::Slider::new(&mut self.normal_scale, 0.0..=2.0).text("Normal scale"));
+        }
+        if self.texture(TextureSlot::Occlusion).is_some() {
+            ui.add(egui::Slider::new(&mut self.occlusion_strength, 0.0..=1.0).text("Occlusion"));
+        }
+        ui.add(egui::Slider::new(&mut self.alpha_cutoff, 0.0..=1.0).text("Alpha cutoff"));
+        if self.lightmap.is_some() {
+            ui.add(egui::Slider::new(&mut self.lightmap_intensity, 0.0..=4.0).text("Lightmap"));
+        }
+        if self.planar_reflection {
+            ui.add(egui::Slider::new(&mut self.reflection_distortion, 0.0..=0.1).text("Reflection distortion"));
+        }
+        self.emissive.ui(ui);
+        bytemuck::bytes_of(&before) != bytemuck::bytes_of(&self.uniform())
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    base_color: [f32; 4],
+    // radiance, already multiplied by strength
+    emissive: [f32; 4],
+    // metallic, roughness, normal scale, occlusion strength
+    factors: [f32; 4],
+    // cutoff
+    alpha: [f32; 4],
+    // lightmap intensity, planar reflection distortion
+    lighting: [f32; 4],
+}
+
+/// Bind group layouts for materials, one per combination of features, plus the default
+/// sampler and the lightmap sampler they share.
+pub struct MaterialLayouts {
+    layouts: HashMap<MaterialFeatures, Arc<wgpu::BindGroupLayout>>,
+    sampler: Arc<wgpu::Sampler>,
+    lightmap_sampler: wgpu::Sampler,
+}
+
+impl MaterialLayouts {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("material sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        MaterialLayouts {
+            layouts: HashMap::new(),
+            sampler: Arc::new(sampler),
+            lightmap_sampler: Lightmap::create_sampler(device),
+        }
+    }
+
+    pub fn get(&mut self, device: &wgpu::Device, features: MaterialFeatures) -> Arc<wgpu::BindGroupLayout> {
+        self.layouts
+            .entry(features)
+            .or_insert_with(|| {
+                let mut entries = vec![
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<MaterialUniform>() as u64),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ];
+                entries.extend(features.slots().map(|slot| wgpu::BindGroupLayoutEntry {
+                    binding: slot.binding(),
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }));
+                if features.has_lightmap() {
+                    entries.extend(Lightmap::layout_entries());
+                }
+                Arc::new(device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("material layout"),
+                    entries: &entries,
+                }))
+            })
+            .clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.layouts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layouts.is_empty()
+    }
+}
+
+/// A `PbrMaterial` on the GPU, bound as group 3 of the PBR mesh shader. Create it with
+/// `MeshRenderer::create_material` and share it between every mesh that uses it.
+#[derive(Debug)]
+pub struct Material {
+    features: MaterialFeatures,
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Material {
+    pub fn new(device: &wgpu::Device, layouts: &mut MaterialLayouts, material: &PbrMaterial) -> Self {
+        use wgpu::util::DeviceExt;
+
+        let features = material.features();
+        let layout = layouts.get(device, features);
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("material"),
+            contents: bytemuck::bytes_of(&material.uniform()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = material.sampler.as_ref().unwrap_or(&layouts.sampler);
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ];
+        entries.extend(features.slots().map(|slot| wgpu::BindGroupEntry {
+            binding: slot.binding(),
+            resource: wgpu::BindingResource::TextureView(material.texture(slot).unwrap()),
+        }));
+        if let Some(lightmap) = &material.lightmap {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::TextureView(lightmap),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::Sampler(&layouts.lightmap_sampler),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("material bind group"),
+            layout: &layout,
+            entries: &entries,
+        });
+        Material {
+            features,
+            uniform,
+            bind_group,
+        }
+    }
+
+    /// Uploads changed factors. Textures are fixed; changing which slots are filled or the
+    /// lightmap needs a new `Material`.
+    pub fn update(&self, queue: &wgpu::Queue, material: &PbrMaterial) {
+        if material.features() != self.features {
+            log::warn!("Ignoring texture changes to a material; create a new one instead");
+        }
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&material.uniform()));
+    }
+
+    pub fn features(&self) -> MaterialFeatures {
+        self.features
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
@@ -0,0 +1,321 @@
+use std::{collections::HashMap, sync::Arc};
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    gpu_error,
+    load_profile::{LoadStage, LoadTimer},
+    pipeline::{AsyncPipeline, PipelineCompiler},
+    postprocess::color_grading::f16_bits,
+    shader_variants::{ShaderError, ShaderVariants, VariantKey},
+    vertex_layout::{VertexLayout, VertexLayoutId, VertexLayouts, VertexSemantic},
+};
+
+/// WGSL declaring `MeshVertex` and its accessors, with an `#ifdef` per optional attribute, and
+/// the `DrawInstance` input of `MeshRenderer` draws. `MeshPipelines` prepends it to mesh shaders.
+pub const MESH_VERTEX_SHADER: &str = include_str!("shaders/mesh_vertex.wgsl");
+
+/// The `#ifdef` names `MESH_VERTEX_SHADER` understands, one per optional `VertexSemantic`.
+pub const MESH_VERTEX_FEATURES: [&str; 5] = ["VERTEX_COLOR", "UV2", "VERTEX_TANGENT", "JOINTS", "WEIGHTS"];
+
+/// Mesh geometry as separate attribute streams, e.g. straight from an importer.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// Linear RGBA, 0 to 1.
+    pub colors: Option<Vec<[f32; 4]>>,
+    pub uv2: Option<Vec<[f32; 2]>>,
+    pub tangents: Option<Vec<[f32; 4]>>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// The layout holding exactly the streams this mesh has, in their default formats.
+    pub fn layout(&self) -> VertexLayout {
+        let optional = [
+            (self.colors.is_some(), VertexSemantic::Color),
+            (self.uv2.is_some(), VertexSemantic::Uv1),
+            (self.tangents.is_some(), VertexSemantic::Tangent),
+        ];
+        let semantics = VertexSemantic::REQUIRED
+            .into_iter()
+            .chain(optional.into_iter().filter(|&(present, _)| present).map(|(_, semantic)| semantic));
+        VertexLayout::new(semantics).unwrap()
+    }
+
+    /// An axis-aligned cube of edge `size` centered on the origin, with flat normals and each
+    /// face mapped to the whole UV square.
+    pub fn cube(size: f32) -> Self {
+        let half = size * 0.5;
+        let mut data = MeshData::default();
+        // normal, then the axes the face's U and V run along
+        let faces = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+        ];
+        for (normal, u, v) in faces {
+            let first = data.positions.len() as u32;
+            for (s, t) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                let (x, y) = (s * 2.0 - 1.0, t * 2.0 - 1.0);
+                data.positions.push(std::array::from_fn(|i| (normal[i] + u[i] * x + v[i] * y) * half));
+                data.normals.push(normal);
+                data.uvs.push([s, t]);
+            }
+            data.indices.extend([first, first + 2, first + 1, first, first + 3, first + 2]);
+        }
+        data
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Interleaves the streams into `layout`, converting to its formats. Attributes the layout
+    /// has but the mesh doesn't are zero, except colors, which are white.
+    pub fn vertex_bytes(&self, layout: &VertexLayout) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(layout.stride() as usize * self.vertex_count());
+        for index in 0..self.vertex_count() {
+            for element in layout.elements() {
+                let values: &[f32] = match element.semantic {
+                    VertexSemantic::Position => &self.positions[index],
+                    VertexSemantic::Normal => self.normals.get(index).map_or(&[], |v| v),
+                    VertexSemantic::Uv0 => self.uvs.get(index).map_or(&[], |v| v),
+                    VertexSemantic::Color => match &self.colors {
+                        Some(colors) => colors.get(index).map_or(&[1.0; 4], |v| v),
+                        None => &[1.0; 4],
+                    },
+                    VertexSemantic::Uv1 => self.uv2.as_ref().and_then(|uv2| uv2.get(index)).map_or(&[], |v| v),
+                    VertexSemantic::Tangent => {
+                        self.tangents.as_ref().and_then(|tangents| tangents.get(index)).map_or(&[], |v| v)
+                    }
+                    VertexSemantic::Joints | VertexSemantic::Weights => &[],
+                };
+                write_attribute(&mut bytes, element.format, values);
+            }
+        }
+        bytes
+    }
+}
+
+/// Mesh geometry uploaded to the GPU, interleaved into the layout `MeshData::layout` gives.
+#[derive(Debug)]
+pub struct Mesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
+    pub layout: VertexLayoutId,
+    /// Size of the vertex and index buffers together.
+    pub bytes: u64,
+}
+
+impl Mesh {
+    /// Registers the mesh's layout with `layouts`, which pipelines are then looked up by.
+    pub fn new(device: &wgpu::Device, layouts: &mut VertexLayouts, label: &str, data: &MeshData) -> Self {
+        let mut timer = LoadTimer::start("mesh", label);
+        let layout = data.layout();
+        let vertex_bytes = data.vertex_bytes(&layout);
+        timer.end(LoadStage::Decode);
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: &vertex_bytes,
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&data.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let bytes = vertex_bytes.len() + std::mem::size_of_val(data.indices.as_slice());
+        timer.add_upload_bytes(bytes);
+        timer.end(LoadStage::Upload);
+        timer.finish();
+        Mesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: data.indices.len() as u32,
+            layout: layouts.register(layout),
+            bytes: bytes as u64,
+        }
+    }
+
+    pub fn triangles(&self) -> u32 {
+        self.index_count / 3
+    }
+}
+
+fn write_attribute(bytes: &mut Vec<u8>, format: wgpu::VertexFormat, values: &[f32]) {
+    use wgpu::VertexFormat::*;
+    let value = |i: usize| values.get(i).copied().unwrap_or(0.0);
+    let size = format.size() as usize;
+    match format {
+        Float32 | Float32x2 | Float32x3 | Float32x4 => (0..size / 4).for_each(|i| bytes.extend(value(i).to_le_bytes())),
+        Float16x2 | Float16x4 => (0..size / 2).for_each(|i| bytes.extend(f16_bits(value(i)).to_le_bytes())),
+        Unorm8x2 | Unorm8x4 => (0..size).for_each(|i| bytes.push((value(i).clamp(0.0, 1.0) * 255.0).round() as u8)),
+        Snorm8x2 | Snorm8x4 => {
+            (0..size).for_each(|i| bytes.push((value(i).clamp(-1.0, 1.0) * 127.0).round() as i8 as u8))
+        }
+        Unorm16x2 | Unorm16x4 => (0..size / 2)
+            .for_each(|i| bytes.extend(((value(i).clamp(0.0, 1.0) * 65535.0).round() as u16).to_le_bytes())),
+        Snorm16x2 | Snorm16x4 => (0..size / 2)
+            .for_each(|i| bytes.extend(((value(i).clamp(-1.0, 1.0) * 32767.0).round() as i16).to_le_bytes())),
+        Uint8x2 | Uint8x4 => (0..size).for_each(|i| bytes.push(value(i) as u8)),
+        Uint16x2 | Uint16x4 => (0..size / 2).for_each(|i| bytes.extend((value(i) as u16).to_le_bytes())),
+        Uint32 | Uint32x2 | Uint32x3 | Uint32x4 => (0..size / 4).for_each(|i| bytes.extend((value(i) as u32).to_le_bytes())),
+        // no semantic accepts these
+        _ => bytes.resize(bytes.len() + size, 0),
+    }
+}
+
+type BuildPipeline = dyn Fn(&wgpu::Device, &wgpu::ShaderModule, wgpu::VertexBufferLayout) -> wgpu::RenderPipeline + Send + Sync;
+
+/// The render pipelines of one mesh shader, one per vertex layout it is drawn with. Each is
+/// built the first time its layout is asked for, from the shader variant matching the layout's
+/// attributes.
+pub struct MeshPipelines {
+    variants: ShaderVariants,
+    // prepended to the shader, `MESH_VERTEX_SHADER` unless replaced
+    vertex: String,
+    build: Arc<BuildPipeline>,
+    pipelines: HashMap<VertexLayoutId, Arc<wgpu::RenderPipeline>>,
+    // started by `get_async` and moved into `pipelines` once built
+    compiling: HashMap<VertexLayoutId, AsyncPipeline<Arc<wgpu::RenderPipeline>>>,
+}
+
+impl MeshPipelines {
+    /// `source` is the shader without its vertex input, which is `MESH_VERTEX_SHADER`. `build`
+    /// creates a pipeline from a variant's module and the layout to use as its vertex buffer.
+    pub fn new(
+        label: impl Into<String>,
+        source: &str,
+        build: impl Fn(&wgpu::Device, &wgpu::ShaderModule, wgpu::VertexBufferLayout) -> wgpu::RenderPipeline + Send + Sync + 'static,
+    ) -> Self {
+        Self::with_vertex_shader(label, MESH_VERTEX_SHADER, source, build)
+    }
+
+    /// Like `new`, with `vertex`, e.g. an edited `shaders/mesh_vertex.wgsl`, in place of
+    /// `MESH_VERTEX_SHADER`.
+    pub fn with_vertex_shader(
+        label: impl Into<String>,
+        vertex: &str,
+        source: &str,
+        build: impl Fn(&wgpu::Device, &wgpu::ShaderModule, wgpu::VertexBufferLayout) -> wgpu::RenderPipeline + Send + Sync + 'static,
+    ) -> Self {
+        MeshPipelines {
+            variants: ShaderVariants::new(label, format!("{}\n{}", vertex, source), &MESH_VERTEX_FEATURES),
+            vertex: vertex.to_string(),
+            build: Arc::new(build),
+            pipelines: HashMap::new(),
+            compiling: HashMap::new(),
+        }
+    }
+
+    pub fn get(
+        &mut self,
+        device: &wgpu::Device,
+        layouts: &VertexLayouts,
+        id: VertexLayoutId,
+    ) -> Result<Arc<wgpu::RenderPipeline>, ShaderError> {
+        if let Some(pipeline) = self.pipelines.get(&id) {
+            return Ok(pipeline.clone());
+        }
+
+        let layout = layouts.get(id);
+        let key = self.variants.key(&layout.shader_features())?;
+        let module = self.variants.get(device, key)?;
+        let pipeline = Arc::new((self.build)(device, &module, layout.buffer_layout()));
+        self.pipelines.insert(id, pipeline.clone());
+        Ok(pipeline)
+    }
+
+    /// Like `get`, but builds the pipeline on one of `compiler`'s threads, returning `None`
+    /// until it is ready. The shader variant is still compiled here, so its errors are returned.
+    pub fn get_async(
+        &mut self,
+        device: &wgpu::Device,
+        compiler: &PipelineCompiler,
+        layouts: &VertexLayouts,
+        id: VertexLayoutId,
+    ) -> Result<Option<Arc<wgpu::RenderPipeline>>, ShaderError> {
+        if let Some(pipeline) = self.pipelines.get(&id) {
+            return Ok(Some(pipeline.clone()));
+        }
+        if let Some(compiling) = self.compiling.get_mut(&id) {
+            if let Some(pipeline) = compiling.get() {
+                let pipeline = pipeline.clone();
+                self.compiling.remove(&id);
+                self.pipelines.insert(id, pipeline.clone());
+                return Ok(Some(pipeline));
+            }
+            // a failed build was reported by its thread; keep it so it isn't retried every frame
+            return Ok(None);
+        }
+
+        let layout = layouts.get(id).clone();
+        let key = self.variants.key(&layout.shader_features())?;
+        let module = self.variants.get(device, key)?;
+        let build = self.build.clone();
+        let label = format!("{} {}", self.variants.label(), self.variants.describe(key));
+        let compiling = compiler.compile(label, move |device| Arc::new(build(device, &module, layout.buffer_layout())));
+        self.compiling.insert(id, compiling);
+        Ok(None)
+    }
+
+    /// Replaces the shader, dropping every cached pipeline.
+    pub fn set_source(&mut self, source: &str) {
+        self.variants.set_source(format!("{}\n{}", self.vertex, source));
+        self.pipelines.clear();
+        self.compiling.clear();
+    }
+
+    /// Like `set_source`, but first builds the new shader's pipeline for every layout already in
+    /// use, keeping the current shader if any of them fails. For reloading a shader while it is
+    /// being edited.
+    pub fn try_set_source(&mut self, device: &wgpu::Device, layouts: &VertexLayouts, source: &str) -> Result<(), ShaderError> {
+        let vertex = self.vertex.clone();
+        self.try_set_shaders(device, layouts, &vertex, source)
+    }
+
+    /// Like `try_set_source`, also replacing the vertex input as `with_vertex_shader` does.
+    pub fn try_set_shaders(
+        &mut self,
+        device: &wgpu::Device,
+        layouts: &VertexLayouts,
+        vertex: &str,
+        source: &str,
+    ) -> Result<(), ShaderError> {
+        let mut variants = ShaderVariants::new(self.variants.label(), format!("{}\n{}", vertex, source), &MESH_VERTEX_FEATURES);
+        if self.pipelines.is_empty() {
+            // nothing drawn yet, but compile errors should still show up now rather than at the first draw
+            variants.get(device, VariantKey::default())?;
+        }
+        let mut pipelines = HashMap::new();
+        for &id in self.pipelines.keys() {
+            let layout = layouts.get(id);
+            let key = variants.key(&layout.shader_features())?;
+            let module = variants.get(device, key)?;
+            let context = format!("building pipeline \"{}\" {}", variants.label(), variants.describe(key));
+            let pipeline = gpu_error::capture(device, context, || (self.build)(device, &module, layout.buffer_layout()))
+                .map_err(ShaderError::Compile)?;
+            pipelines.insert(id, Arc::new(pipeline));
+        }
+        self.variants = variants;
+        self.vertex = vertex.to_string();
+        self.pipelines = pipelines;
+        self.compiling.clear();
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+}
@@ -0,0 +1,2130 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+};
+
+use glam::{Mat4, Vec3};
+
+use crate::{
+    camera::{Camera, ViewportPasses},
+    debug_view::{DebugView, DebugViews, DEBUG_VIEW_SHADER},
+    dynamic_buffer::{DynamicBuffer, DynamicSlice, FRAMES_IN_FLIGHT},
+    environment::{EnvironmentLighting, EnvironmentMap, Skybox, ENVIRONMENT_SHADER},
+    gpu_memory::{AllocationId, GpuMemory, MemoryCategory},
+    light_probes::{LightProbeGrid, LightProbeTexture, LIGHT_PROBE_SHADER},
+    light_profiles::{LightProfiles, LIGHT_PROFILE_SHADER},
+    lightmap::LIGHTMAP_SHADER,
+    lights::{FrameLight, LightBuffer, LightKind, LIGHTS_SHADER},
+    local_shadows::{LocalShadows, PointShadowCaster, SpotShadowCaster, LOCAL_SHADOW_SHADER, MAX_POINT_SHADOWS, MAX_SPOT_SHADOWS},
+    material::{Material, MaterialFeatures, MaterialLayouts, PbrMaterial},
+    mesh::{Mesh, MeshData, MeshPipelines, MESH_VERTEX_SHADER},
+    oit::{TransparencyMode, WeightedBlendedOit, OIT_SHADER},
+    pipeline::PipelineCompiler,
+    planar_reflection::{PlanarReflection, Plane, PLANAR_REFLECTION_SHADER},
+    postprocess::{motion_blur::CameraVelocity, outline::Outline},
+    reflection_probes::{ReflectionProbes, REFLECTION_PROBE_SHADER},
+    render_layers::RenderLayers,
+    render_stats::RenderStats,
+    shader_variants::{self, ShaderError},
+    shadows::{DirectionalShadows, ShadowSettings, MAX_CASCADES, SHADOW_SHADER},
+    skinning::{GpuSkinning, SkinnedMesh, SkinnedVertex},
+    vertex_layout::{VertexLayoutId, VertexLayouts},
+    weather::SurfaceWeather,
+};
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ViewUniform {
+    view_projection: [[f32; 4]; 4],
+    // w is the environment intensity
+    ambient: [f32; 4],
+    // wetness, snow
+    weather: [f32; 4],
+    camera_position: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct VelocityViewUniform {
+    view_projection: [[f32; 4]; 4],
+    previous_view_projection: [[f32; 4]; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawUniform {
+    model: [[f32; 4]; 4],
+    normal_matrix: [[f32; 4]; 4],
+    color: [f32; 4],
+}
+
+impl DrawUniform {
+    fn new(transform: Mat4, color: [f32; 4]) -> Self {
+        DrawUniform {
+            model: transform.to_cols_array_2d(),
+            normal_matrix: transform.inverse().transpose().to_cols_array_2d(),
+            color,
+        }
+    }
+}
+
+/// One copy of a mesh in an instanced draw, see `MeshRenderer::draw_instanced`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawInstance {
+    model: [[f32; 4]; 4],
+    // the inverse transpose's first three columns
+    normal_matrix: [[f32; 4]; 3],
+    color: [f32; 4],
+}
+
+impl DrawInstance {
+    // after the mesh's own attributes, see `VertexSemantic::location`
+    const ATTRIBUTES: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+        8 => Float32x4,
+        9 => Float32x4,
+        10 => Float32x4,
+        11 => Float32x4,
+        12 => Float32x4,
+        13 => Float32x4,
+        14 => Float32x4,
+        15 => Float32x4,
+    ];
+
+    /// An instance placed by `transform` and tinted by `color`, linear RGBA.
+    pub fn new(transform: Mat4, color: [f32; 4]) -> Self {
+        let normal_matrix = transform.inverse().transpose().to_cols_array_2d();
+        DrawInstance {
+            model: transform.to_cols_array_2d(),
+            normal_matrix: [normal_matrix[0], normal_matrix[1], normal_matrix[2]],
+            color,
+        }
+    }
+
+    // the transform alone, for the velocity pass, which takes last frame's at 12 to 15
+    const MODEL_ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        8 => Float32x4,
+        9 => Float32x4,
+        10 => Float32x4,
+        11 => Float32x4,
+    ];
+    const PREVIOUS_MODEL_ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        12 => Float32x4,
+        13 => Float32x4,
+        14 => Float32x4,
+        15 => Float32x4,
+    ];
+
+    fn buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<DrawInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+
+    fn model_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            attributes: &Self::MODEL_ATTRIBUTES,
+            ..Self::buffer_layout()
+        }
+    }
+
+    // last frame's transforms, with the draw's applied, one matrix per instance
+    fn previous_model_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::PREVIOUS_MODEL_ATTRIBUTES,
+        }
+    }
+}
+
+/// Distance between the cascade view-projections in the shadow view buffer, the minimum
+/// uniform offset alignment.
+const SHADOW_VIEW_STRIDE: u64 = 256;
+
+/// Views in the shadow view buffer: the cascades, then the local shadows' spot tiles and point
+/// light faces.
+const SHADOW_VIEWS: usize = MAX_CASCADES + MAX_SPOT_SHADOWS + 6 * MAX_POINT_SHADOWS;
+
+struct QueuedDraw {
+    mesh: Arc<Mesh>,
+    uniform: DrawUniform,
+    instances: Vec<DrawInstance>,
+    material: Option<Arc<Material>>,
+    layers: RenderLayers,
+}
+
+// what `draw` calls are merged by: the mesh, the material and the layers
+type MergeKey = (*const Mesh, Option<*const Material>, RenderLayers);
+
+// what a draw's instance transforms are matched to last frame's by: its merge key and how many
+// draws with the same key were prepared before it
+type HistoryKey = (MergeKey, usize);
+
+struct PreparedDraw {
+    pipeline: Arc<wgpu::RenderPipeline>,
+    // for views other than the main one when it is multisampled
+    single_sampled: Option<Arc<wgpu::RenderPipeline>>,
+    mesh: Arc<Mesh>,
+    uniform: DynamicSlice,
+    instances: DynamicSlice,
+    previous_models: DynamicSlice,
+    instance_count: u32,
+    material: Option<Arc<Material>>,
+    layers: RenderLayers,
+}
+
+impl PreparedDraw {
+    fn pipeline(&self, sample_count: u32) -> &wgpu::RenderPipeline {
+        match &self.single_sampled {
+            Some(pipeline) if sample_count == 1 => pipeline,
+            _ => &self.pipeline,
+        }
+    }
+
+    /// Draws every instance, with the pipeline and bind groups already set.
+    fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, instances: &'a wgpu::Buffer, stats: &mut RenderStats) {
+        let instance_range = self.instances.offset..self.instances.offset + self.instances.size;
+        pass.set_vertex_buffer(0, self.mesh.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, instances.slice(instance_range));
+        pass.set_index_buffer(self.mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        pass.draw_indexed(0..self.mesh.index_count, 0, 0..self.instance_count);
+        stats.record_draw(self.mesh.triangles(), self.instance_count);
+    }
+
+    /// Like `draw`, with last frame's transforms for the velocity pipeline.
+    fn draw_velocity<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        instances: &'a wgpu::Buffer,
+        previous_models: &'a wgpu::Buffer,
+        stats: &mut RenderStats,
+    ) {
+        let previous_range = self.previous_models.offset..self.previous_models.offset + self.previous_models.size;
+        pass.set_vertex_buffer(2, previous_models.slice(previous_range));
+        self.draw(pass, instances, stats);
+    }
+}
+
+/// A viewpoint the frame's meshes are drawn from, with its own view uniform. The renderer has
+/// one for the main camera; make more with `MeshRenderer::create_view`, e.g. for a minimap.
+pub struct MeshView {
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    sample_count: u32,
+}
+
+impl MeshView {
+    /// Samples per pixel of the targets the view renders into.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+}
+
+/// Draws meshes with a basic lit shader into the window, depth tested: queue them with `draw`
+/// during the frame and the engine renders them before the UI. Every draw is instanced: `draw`
+/// calls with the same mesh, material and layers are merged into one, and `draw_instanced`
+/// queues many copies at once. Meant for games that don't
+/// need their own pipeline yet, and for debugging. Meshes drawn with a `Material` go through
+/// the PBR shader instead, one variant per combination of texture slots. Both are lit by
+/// `lights`, up to `lights::MAX_LIGHTS` of them: the first directional one casts the cascaded
+/// shadow of `shadows`, spot and point lights the shadows of `local_shadows`. With an environment map set, it lights them too and the engine
+/// draws it as the skybox, and `reflection_probes` replace its reflections where they cover a
+/// material. A light probe grid set with `set_light_probes` replaces its diffuse light. Materials with `PbrMaterial::planar_reflection` reflect the scene through
+/// `planar_reflection`.
+///
+/// The queued draws are uploaded by `prepare` and can then be rendered from several views with
+/// `render_view` until `begin_frame` starts the next frame. The main view can be multisampled,
+/// see `set_sample_count`; other views are not.
+///
+/// Meshes queued with `draw_transparent` are composited after the opaque ones as
+/// `set_transparency` picks: sorted and blended by every view, or with weighted blended OIT
+/// through `render_transparent`, which the engine does for the main view.
+pub struct MeshRenderer {
+    /// The frame's lights, usually set by `scene::light_system`. Starts with one white
+    /// directional light.
+    pub lights: Vec<FrameLight>,
+    /// Flat light added everywhere, on top of the environment's.
+    pub ambient: Vec3,
+    /// Scales the environment map's lighting and skybox.
+    pub environment_intensity: f32,
+    /// The first directional light's shadow, drawn by `render_shadows`.
+    pub shadows: DirectionalShadows,
+    /// The spot and point lights' shadows, drawn by `render_shadows` as long as `shadows` is
+    /// enabled.
+    pub local_shadows: LocalShadows,
+    /// The IES profiles and cookies `lights` can refer to; `Assets<LightProfile>` loads into it.
+    pub light_profiles: LightProfiles,
+    /// The mirror or water plane reflective materials show, drawn by `render_reflection`.
+    /// Without one they reflect the environment map.
+    pub planar_reflection: Option<PlanarReflection>,
+    /// Local reflections for materials, captured by `render_reflection_probes`. Starts empty,
+    /// with room for 8 probes at 128 pixels a face.
+    pub reflection_probes: ReflectionProbes,
+    /// Wet and snowy surfaces, usually `Weather::surface()`.
+    pub weather: SurfaceWeather,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+    layouts: VertexLayouts,
+    // by sample count
+    pipelines: HashMap<u32, MeshPipelines>,
+    view_layout: wgpu::BindGroupLayout,
+    main_view: MeshView,
+    light_buffer: LightBuffer,
+    environment: Option<Arc<EnvironmentMap>>,
+    environment_lighting: EnvironmentLighting,
+    skybox: Skybox,
+    draw_layout: wgpu::BindGroupLayout,
+    draw_uniforms: DynamicBuffer,
+    instances: DynamicBuffer,
+    draws: Vec<QueuedDraw>,
+    // where this frame's `draw` calls are merged into `draws`
+    merged: HashMap<MergeKey, usize>,
+    prepared: Vec<PreparedDraw>,
+    transparency: TransparencyMode,
+    // `transparency` as of `begin_frame`, which this frame's transparent draws are prepared for
+    frame_transparency: TransparencyMode,
+    transparent: Vec<QueuedDraw>,
+    // with the position they are sorted by
+    prepared_transparent: Vec<(Vec3, PreparedDraw)>,
+    // by sample count, for `TransparencyMode::WeightedBlended`
+    oit_pipelines: HashMap<u32, MeshPipelines>,
+    draw_bind_group: Option<wgpu::BindGroup>,
+    // each prepared instance's transform last frame, for the velocity pass
+    previous_models: DynamicBuffer,
+    model_history: HashMap<HistoryKey, Vec<[[f32; 4]; 4]>>,
+    next_model_history: HashMap<HistoryKey, Vec<[[f32; 4]; 4]>>,
+    velocity_pipelines: MeshPipelines,
+    velocity_view: wgpu::Buffer,
+    velocity_view_bind_group: wgpu::BindGroup,
+    mask_pipelines: MeshPipelines,
+    mask_view: wgpu::Buffer,
+    mask_view_bind_group: wgpu::BindGroup,
+    // uploaded meshes, tracked in `GpuMemory` on the next `prepare` and released once dropped
+    mesh_allocations: Vec<(Weak<Mesh>, Option<AllocationId>)>,
+    // the per-frame uniform and instance buffers, by their size when tracked
+    buffer_allocations: [Option<(u64, AllocationId)>; 2],
+    light_probes: LightProbeTexture,
+    // group 2 of lit pipelines: the directional and local shadows, the light profiles, the
+    // planar reflection, the reflection probes and the light probe grid
+    lighting_layout: wgpu::BindGroupLayout,
+    lighting_bind_group: wgpu::BindGroup,
+    // bound in place of `planar_reflection` when there is none, and of both reflections in
+    // `capture_lighting_bind_group` while rendering into them
+    no_reflection: PlanarReflection,
+    // only `None` while swapped in for `reflection_probes` during their capture
+    no_probes: Option<ReflectionProbes>,
+    capture_lighting_bind_group: wgpu::BindGroup,
+    reflection_view: MeshView,
+    // one per face captured in a frame
+    probe_views: Vec<MeshView>,
+    shadow_views: wgpu::Buffer,
+    shadow_view_bind_group: wgpu::BindGroup,
+    caster_pipelines: MeshPipelines,
+    includes: LitIncludes,
+    mesh_source: String,
+    debug_pipelines: HashMap<(DebugView, Option<MaterialFeatures>), MeshPipelines>,
+    materials: MaterialLayouts,
+    material_source: String,
+    material_pipelines: HashMap<(MaterialFeatures, u32), MeshPipelines>,
+    compiler: Option<PipelineCompiler>,
+    // created by the first `upload_skinned`
+    skinning: Option<GpuSkinning>,
+    // skinned meshes drawn this frame, dispatched by `skin`
+    skinned: Vec<Arc<SkinnedMesh>>,
+}
+
+impl MeshRenderer {
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Self {
+        let uniform_entry = |has_dynamic_offset, min_binding_size| wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset,
+                min_binding_size,
+            },
+            count: None,
+        };
+        let [irradiance_entry, specular_entry, sampler_entry] = EnvironmentLighting::layout_entries(2);
+        let view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh projection layout"),
+            entries: &[
+                uniform_entry(false, None),
+                LightBuffer::layout_entry(1),
+                irradiance_entry,
+                specular_entry,
+                sampler_entry,
+            ],
+        });
+        let draw_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh draw layout"),
+            entries: &[uniform_entry(true, wgpu::BufferSize::new(std::mem::size_of::<DrawUniform>() as u64))],
+        });
+        let shadows = DirectionalShadows::new(device, ShadowSettings::default());
+        let local_shadows = LocalShadows::new(device, 2048, 256, MAX_POINT_SHADOWS as u32);
+        let light_profiles = LightProfiles::new(device, 16, 256, 8);
+        let lighting_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh lighting layout"),
+            entries: &[
+                DirectionalShadows::layout_entries().as_slice(),
+                &LocalShadows::layout_entries(),
+                &LightProfiles::layout_entries(),
+                &PlanarReflection::layout_entries(),
+                &ReflectionProbes::layout_entries(),
+                &LightProbeTexture::layout_entries(),
+            ]
+            .concat(),
+        });
+        let no_reflection = PlanarReflection::new(device, Plane::horizontal(0.0));
+        let reflection_probes = ReflectionProbes::new(device, 128, 8);
+        let no_probes = ReflectionProbes::new(device, 8, 1);
+        let light_probes = LightProbeTexture::new(device);
+        let lighting = (&shadows, &local_shadows, &light_profiles, &light_probes);
+        let lighting_bind_group =
+            Self::create_lighting_bind_group(device, &lighting_layout, lighting, (&no_reflection, &reflection_probes));
+        let capture_lighting_bind_group =
+            Self::create_lighting_bind_group(device, &lighting_layout, lighting, (&no_reflection, &no_probes));
+        let no_probes = Some(no_probes);
+
+        // one view-projection per cascade and local shadow view, each at its own dynamic offset
+        let shadow_views = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadow views"),
+            size: SHADOW_VIEW_STRIDE * SHADOW_VIEWS as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let shadow_view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("shadow view layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                visibility: wgpu::ShaderStages::VERTEX,
+                ..uniform_entry(true, wgpu::BufferSize::new(64))
+            }],
+        });
+        let shadow_view_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("shadow view bind group"),
+            layout: &shadow_view_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &shadow_views,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(64),
+                }),
+            }],
+        });
+        let caster_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shadow caster pipeline layout"),
+            bind_group_layouts: &[&shadow_view_layout, &draw_layout],
+            push_constant_ranges: &[],
+        });
+        let caster_pipelines = MeshPipelines::new("shadow caster", include_str!("shaders/shadow_caster.wgsl"), move |device, module, vertex_buffer| {
+            build_caster_pipeline(device, &caster_layout, module, vertex_buffer)
+        });
+
+        // the view-projection of the velocity and mask passes, with last frame's for the former
+        let projection_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh projection layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                visibility: wgpu::ShaderStages::VERTEX,
+                ..uniform_entry(false, None)
+            }],
+        });
+        let velocity_view = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("mesh velocity view"),
+            size: std::mem::size_of::<VelocityViewUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let velocity_view_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("mesh velocity view bind group"),
+            layout: &projection_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: velocity_view.as_entire_binding(),
+            }],
+        });
+        let velocity_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("mesh velocity pipeline layout"),
+            bind_group_layouts: &[&projection_layout, &draw_layout],
+            push_constant_ranges: &[],
+        });
+        let velocity_pipelines = MeshPipelines::new("mesh velocity", include_str!("shaders/mesh_velocity.wgsl"), move |device, module, vertex_buffer| {
+            build_velocity_pipeline(device, &velocity_layout, module, vertex_buffer)
+        });
+        let mask_view = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("mesh mask view"),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mask_view_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("mesh mask view bind group"),
+            layout: &projection_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: mask_view.as_entire_binding(),
+            }],
+        });
+        let mask_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("mesh mask pipeline layout"),
+            bind_group_layouts: &[&projection_layout, &draw_layout],
+            push_constant_ranges: &[],
+        });
+        let mask_pipelines = MeshPipelines::new("mesh mask", include_str!("shaders/mesh_mask.wgsl"), move |device, module, vertex_buffer| {
+            build_mask_pipeline(device, &mask_layout, module, vertex_buffer)
+        });
+
+        let light_buffer = LightBuffer::new(device);
+        let environment_lighting = EnvironmentLighting::new(device);
+        let mut renderer = MeshRenderer {
+            lights: vec![FrameLight {
+                kind: LightKind::Directional,
+                position: Vec3::ZERO,
+                direction: Vec3::new(-0.4, -1.0, -0.6).normalize(),
+                color: Vec3::splat(1.0),
+                range: 0.0,
+                profile: None,
+                cookie: None,
+            }],
+            ambient: Vec3::splat(0.15),
+            environment_intensity: 1.0,
+            shadows,
+            local_shadows,
+            light_profiles,
+            planar_reflection: None,
+            reflection_probes,
+            light_probes,
+            weather: SurfaceWeather::default(),
+            color_format,
+            sample_count: 1,
+            layouts: VertexLayouts::new(),
+            pipelines: HashMap::new(),
+            main_view: Self::new_view(device, &view_layout, &light_buffer, &environment_lighting, 1),
+            reflection_view: Self::new_view(device, &view_layout, &light_buffer, &environment_lighting, 1),
+            probe_views: Vec::new(),
+            light_buffer,
+            environment: None,
+            environment_lighting,
+            skybox: Skybox::new(device, color_format, 1),
+            view_layout,
+            draw_layout,
+            draw_uniforms: DynamicBuffer::new(device, "mesh draws", wgpu::BufferUsages::UNIFORM, FRAMES_IN_FLIGHT),
+            instances: DynamicBuffer::new(device, "mesh instances", wgpu::BufferUsages::VERTEX, FRAMES_IN_FLIGHT),
+            draws: Vec::new(),
+            merged: HashMap::new(),
+            prepared: Vec::new(),
+            transparency: TransparencyMode::Sorted,
+            frame_transparency: TransparencyMode::Sorted,
+            transparent: Vec::new(),
+            prepared_transparent: Vec::new(),
+            oit_pipelines: HashMap::new(),
+            draw_bind_group: None,
+            previous_models: DynamicBuffer::new(device, "mesh previous models", wgpu::BufferUsages::VERTEX, FRAMES_IN_FLIGHT),
+            model_history: HashMap::new(),
+            next_model_history: HashMap::new(),
+            velocity_pipelines,
+            velocity_view,
+            velocity_view_bind_group,
+            mask_pipelines,
+            mask_view,
+            mask_view_bind_group,
+            mesh_allocations: Vec::new(),
+            buffer_allocations: [None; 2],
+            lighting_layout,
+            lighting_bind_group,
+            no_reflection,
+            no_probes,
+            capture_lighting_bind_group,
+            shadow_views,
+            shadow_view_bind_group,
+            caster_pipelines,
+            includes: LitIncludes::default(),
+            mesh_source: include_str!("shaders/mesh.wgsl").to_string(),
+            debug_pipelines: HashMap::new(),
+            materials: MaterialLayouts::new(device),
+            material_source: include_str!("shaders/pbr.wgsl").to_string(),
+            material_pipelines: HashMap::new(),
+            compiler: None,
+            skinning: None,
+            skinned: Vec::new(),
+        };
+        // built up front so `set_shader` has something to check edits against
+        let pipelines = renderer.new_lit_pipelines(device, "mesh", &renderer.includes.lit(&renderer.mesh_source), None, 1);
+        renderer.pipelines.insert(1, pipelines);
+        renderer
+    }
+
+    /// Builds the lit pipelines of new materials, vertex layouts and sample counts on
+    /// `compiler`'s threads instead of stalling the frame that first draws with them.
+    pub fn with_pipeline_compiler(mut self, compiler: PipelineCompiler) -> Self {
+        self.compiler = Some(compiler);
+        self
+    }
+
+    fn new_view(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        lights: &LightBuffer,
+        environment: &EnvironmentLighting,
+        sample_count: u32,
+    ) -> MeshView {
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("mesh view"),
+            size: std::mem::size_of::<ViewUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let [irradiance, specular, sampler] = environment.bind_group_entries(2);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("mesh view bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lights.buffer().as_entire_binding(),
+                },
+                irradiance,
+                specular,
+                sampler,
+            ],
+        });
+        MeshView {
+            uniform,
+            bind_group,
+            sample_count,
+        }
+    }
+
+    /// Replaces the mesh shader, e.g. with an edited `shaders/mesh.wgsl`. `source` is without
+    /// the vertex input and the lighting shaders the renderer prepends, the `LitIncludes`. On
+    /// error the current shader stays in use.
+    pub fn set_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<(), ShaderError> {
+        for pipelines in self.pipelines.values_mut() {
+            pipelines.try_set_source(device, &self.layouts, &self.includes.lit(source))?;
+        }
+        self.mesh_source = source.to_string();
+        self.debug_pipelines.clear();
+        self.oit_pipelines.clear();
+        Ok(())
+    }
+
+    /// Like `set_shader`, for the PBR shader of meshes drawn with a material, e.g. an edited
+    /// `shaders/pbr.wgsl`.
+    pub fn set_material_shader(&mut self, device: &wgpu::Device, source: &str) -> Result<(), ShaderError> {
+        for (&(features, _), pipelines) in &mut self.material_pipelines {
+            pipelines.try_set_source(device, &self.layouts, &self.includes.material(source, features)?)?;
+        }
+        self.material_source = source.to_string();
+        self.debug_pipelines.clear();
+        Ok(())
+    }
+
+    /// Replaces the `LitIncludes` together with the mesh and PBR shaders, for reloading edits to
+    /// any of them. Every lit pipeline is rebuilt; if one fails, they all stay as they were.
+    pub fn set_lit_shaders(
+        &mut self,
+        device: &wgpu::Device,
+        includes: LitIncludes,
+        mesh_source: &str,
+        material_source: &str,
+    ) -> Result<(), ShaderError> {
+        let includes = std::mem::replace(&mut self.includes, includes);
+        let mesh_source = std::mem::replace(&mut self.mesh_source, mesh_source.to_string());
+        let material_source = std::mem::replace(&mut self.material_source, material_source.to_string());
+        if let Err(e) = self.rebuild_lit_pipelines(device) {
+            self.includes = includes;
+            self.mesh_source = mesh_source;
+            self.material_source = material_source;
+            // these built before, so going back can't fail
+            let _ = self.rebuild_lit_pipelines(device);
+            return Err(e);
+        }
+        self.debug_pipelines.clear();
+        self.oit_pipelines.clear();
+        Ok(())
+    }
+
+    fn rebuild_lit_pipelines(&mut self, device: &wgpu::Device) -> Result<(), ShaderError> {
+        let includes = &self.includes;
+        for pipelines in self.pipelines.values_mut() {
+            pipelines.try_set_shaders(device, &self.layouts, &includes.vertex, &includes.lit(&self.mesh_source))?;
+        }
+        for (&(features, _), pipelines) in &mut self.material_pipelines {
+            let source = includes.material(&self.material_source, features)?;
+            pipelines.try_set_shaders(device, &self.layouts, &includes.vertex, &source)?;
+        }
+        Ok(())
+    }
+
+    // pipelines of the lit `source` for `sample_count` samples, with the material's bind group
+    fn new_lit_pipelines(
+        &self,
+        device: &wgpu::Device,
+        label: &'static str,
+        source: &str,
+        material_layout: Option<&wgpu::BindGroupLayout>,
+        sample_count: u32,
+    ) -> MeshPipelines {
+        let mut bind_group_layouts = vec![&self.view_layout, &self.draw_layout, &self.lighting_layout];
+        bind_group_layouts.extend(material_layout);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{} pipeline layout", label)),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        let color_format = self.color_format;
+        MeshPipelines::with_vertex_shader(label, &self.includes.vertex, source, move |device, module, vertex_buffer| {
+            build_pipeline(device, label, &pipeline_layout, module, vertex_buffer, color_format, sample_count)
+        })
+    }
+
+    // the mesh shader's pipeline, or the PBR shader's with the material's texture slots. With a
+    // pipeline compiler they are built in the background: a material draws with the mesh
+    // shader until its own is ready, and `None` skips draws until the mesh shader's is.
+    fn lit_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        features: Option<MaterialFeatures>,
+        layout: VertexLayoutId,
+        sample_count: u32,
+    ) -> Result<Option<Arc<wgpu::RenderPipeline>>, ShaderError> {
+        let pipelines = match features {
+            None => {
+                if !self.pipelines.contains_key(&sample_count) {
+                    let pipelines = self.new_lit_pipelines(device, "mesh", &self.includes.lit(&self.mesh_source), None, sample_count);
+                    self.pipelines.insert(sample_count, pipelines);
+                }
+                self.pipelines.get_mut(&sample_count).unwrap()
+            }
+            Some(features) => {
+                let key = (features, sample_count);
+                if !self.material_pipelines.contains_key(&key) {
+                    let source = self.includes.material(&self.material_source, features)?;
+                    let material_layout = self.materials.get(device, features);
+                    let pipelines = self.new_lit_pipelines(device, "pbr", &source, Some(&*material_layout), sample_count);
+                    self.material_pipelines.insert(key, pipelines);
+                }
+                self.material_pipelines.get_mut(&key).unwrap()
+            }
+        };
+        let pipeline = match &self.compiler {
+            Some(compiler) => pipelines.get_async(device, compiler, &self.layouts, layout)?,
+            None => Some(pipelines.get(device, &self.layouts, layout)?),
+        };
+        match pipeline {
+            None if features.is_some() => self.lit_pipeline(device, None, layout, sample_count),
+            pipeline => Ok(pipeline),
+        }
+    }
+
+    // the mesh or PBR shader's variant for `view`, with the material's texture slots
+    fn debug_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        view: DebugView,
+        features: Option<MaterialFeatures>,
+        layout: VertexLayoutId,
+    ) -> Result<Arc<wgpu::RenderPipeline>, ShaderError> {
+        let key = (view, features);
+        if !self.debug_pipelines.contains_key(&key) {
+            let defined = |name: &str| {
+                name == "DEBUG_VIEW"
+                    || Some(name) == view.shader_feature()
+                    || features.is_some_and(|features| features.shader_features().any(|feature| feature == name))
+            };
+            let (source, material_layout) = match features {
+                Some(features) => (&self.material_source, Some(self.materials.get(device, features))),
+                None => (&self.mesh_source, None),
+            };
+            let source = shader_variants::preprocess(source, defined)?;
+            let feature_shaders = features.map(|features| self.includes.features(features)).unwrap_or_default();
+            let source = format!("{}\n{}\n{}", self.includes.lit(DEBUG_VIEW_SHADER), feature_shaders, source);
+            let mut bind_group_layouts = vec![&self.view_layout, &self.draw_layout, &self.lighting_layout];
+            bind_group_layouts.extend(material_layout.as_deref());
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("debug view pipeline layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+            let pipelines = MeshPipelines::with_vertex_shader("debug view", &self.includes.vertex, &source, move |device, module, vertex_buffer| {
+                build_debug_pipeline(device, &pipeline_layout, module, vertex_buffer, view)
+            });
+            self.debug_pipelines.insert(key, pipelines);
+        }
+        self.debug_pipelines.get_mut(&key).unwrap().get(device, &self.layouts, layout)
+    }
+
+    // the mesh shader's weighted blended OIT variant, built like `lit_pipeline`'s
+    fn oit_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        layout: VertexLayoutId,
+        sample_count: u32,
+    ) -> Result<Option<Arc<wgpu::RenderPipeline>>, ShaderError> {
+        if !self.oit_pipelines.contains_key(&sample_count) {
+            let source = shader_variants::preprocess(&self.mesh_source, |name| name == "OIT")?;
+            let source = self.includes.lit(&format!("{}\n{}", OIT_SHADER, source));
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("mesh oit pipeline layout"),
+                bind_group_layouts: &[&self.view_layout, &self.draw_layout, &self.lighting_layout],
+                push_constant_ranges: &[],
+            });
+            let pipelines = MeshPipelines::with_vertex_shader("mesh oit", &self.includes.vertex, &source, move |device, module, vertex_buffer| {
+                build_oit_pipeline(device, &pipeline_layout, module, vertex_buffer, sample_count)
+            });
+            self.oit_pipelines.insert(sample_count, pipelines);
+        }
+        let pipelines = self.oit_pipelines.get_mut(&sample_count).unwrap();
+        match &self.compiler {
+            Some(compiler) => pipelines.get_async(device, compiler, &self.layouts, layout),
+            None => pipelines.get(device, &self.layouts, layout).map(Some),
+        }
+    }
+
+    /// Uploads `material` for drawing with `draw_material`.
+    pub fn create_material(&mut self, device: &wgpu::Device, material: &PbrMaterial) -> Arc<Material> {
+        Arc::new(Material::new(device, &mut self.materials, material))
+    }
+
+    /// Format of the targets the meshes can be rendered into.
+    pub fn color_format(&self) -> wgpu::TextureFormat {
+        self.color_format
+    }
+
+    /// Another view to `render_view` the meshes from, single-sampled.
+    pub fn create_view(&self, device: &wgpu::Device) -> MeshView {
+        Self::new_view(device, &self.view_layout, &self.light_buffer, &self.environment_lighting, 1)
+    }
+
+    /// Another view with the main view's current sample count, for more cameras drawing into
+    /// the main targets.
+    pub fn create_main_view(&self, device: &wgpu::Device) -> MeshView {
+        Self::new_view(device, &self.view_layout, &self.light_buffer, &self.environment_lighting, self.sample_count)
+    }
+
+    /// Renders the main view, and the skybox, with `sample_count` samples per pixel from the
+    /// next `prepare` on, into multisampled targets and depth buffers. 1 turns multisampling off.
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        if sample_count == self.sample_count {
+            return;
+        }
+        self.sample_count = sample_count;
+        self.main_view.sample_count = sample_count;
+        self.skybox = Skybox::new(device, self.color_format, sample_count);
+        self.skybox.set_environment(device, self.environment.as_deref());
+    }
+
+    /// Like `Skybox::set_shader`, until the next `set_sample_count` goes back to the built-in
+    /// shader.
+    pub fn set_skybox_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.skybox.set_shader(device, module);
+    }
+
+    /// Samples per pixel of the main view's targets.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Lights the meshes with `environment` and shows it as the skybox; `None` goes back to
+    /// `ambient` alone and the clear color.
+    pub fn set_environment(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, environment: Option<Arc<EnvironmentMap>>) {
+        if let Some(environment) = &environment {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("environment lighting"),
+            });
+            self.environment_lighting.copy_from(&mut encoder, environment);
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+        self.skybox.set_environment(device, environment.as_deref());
+        self.environment = environment;
+    }
+
+    pub fn environment(&self) -> Option<&Arc<EnvironmentMap>> {
+        self.environment.as_ref()
+    }
+
+    /// Fills `target`, with `sample_count` samples, with the environment map as seen by
+    /// `camera`, for drawing the meshes over. Returns false, drawing nothing, without one. The engine calls it in place of
+    /// clearing the window when nothing was drawn before the meshes.
+    pub fn render_skybox(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        camera: &Camera,
+        target_size: (u32, u32),
+    ) -> bool {
+        self.skybox.render(queue, encoder, camera, target_size, target, self.environment_intensity)
+    }
+
+    /// Uploads `data` for drawing with this renderer.
+    pub fn upload(&mut self, device: &wgpu::Device, label: &str, data: &MeshData) -> Arc<Mesh> {
+        let mesh = Arc::new(Mesh::new(device, &mut self.layouts, label, data));
+        self.mesh_allocations.push((Arc::downgrade(&mesh), None));
+        mesh
+    }
+
+    /// Uploads a mesh skinned by a compute pre-pass, which needs `Capabilities::compute`. Set its
+    /// palette and draw it with `draw_skinned` each frame.
+    pub fn upload_skinned(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        vertices: &[SkinnedVertex],
+        indices: &[u32],
+        joint_count: u32,
+    ) -> Arc<SkinnedMesh> {
+        let skinning = self.skinning.get_or_insert_with(|| GpuSkinning::new(device));
+        let mesh = Arc::new(skinning.create_mesh(device, &mut self.layouts, label, vertices, indices, joint_count));
+        self.mesh_allocations.push((Arc::downgrade(mesh.mesh()), None));
+        mesh
+    }
+
+    /// Queues `mesh` for this frame, placed by `transform` and tinted by `color`, linear RGBA.
+    pub fn draw(&mut self, mesh: &Arc<Mesh>, transform: Mat4, color: [f32; 4]) {
+        self.draw_on_layers(mesh, transform, color, RenderLayers::DEFAULT);
+    }
+
+    /// Like `draw`, seen only by views whose camera shares a layer with `layers`.
+    pub fn draw_on_layers(&mut self, mesh: &Arc<Mesh>, transform: Mat4, color: [f32; 4], layers: RenderLayers) {
+        self.queue(mesh, transform, color, None, layers);
+    }
+
+    /// Like `draw_on_layers`, shaded with `material`; `color` multiplies its base color.
+    pub fn draw_material(
+        &mut self,
+        mesh: &Arc<Mesh>,
+        transform: Mat4,
+        color: [f32; 4],
+        material: &Arc<Material>,
+        layers: RenderLayers,
+    ) {
+        self.queue(mesh, transform, color, Some(material.clone()), layers);
+    }
+
+    /// Queues `mesh`'s skinned output like `draw_material`, without a material if `None`, and
+    /// its skinning for `skin`. Its palette has to be set before.
+    pub fn draw_skinned(
+        &mut self,
+        mesh: &Arc<SkinnedMesh>,
+        transform: Mat4,
+        color: [f32; 4],
+        material: Option<&Arc<Material>>,
+        layers: RenderLayers,
+    ) {
+        if !self.skinned.iter().any(|skinned| Arc::ptr_eq(skinned, mesh)) {
+            self.skinned.push(mesh.clone());
+        }
+        self.queue(mesh.mesh(), transform, color, material.cloned(), layers);
+    }
+
+    /// Records the skinning pre-pass of the meshes queued with `draw_skinned` since the last
+    /// call. The engine calls it after `prepare`, before anything draws them.
+    pub fn skin(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let (Some(skinning), false) = (&self.skinning, self.skinned.is_empty()) else {
+            return;
+        };
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("skinning") });
+        skinning.dispatch(&mut pass, self.skinned.iter().map(|mesh| &**mesh));
+        drop(pass);
+        self.skinned.clear();
+    }
+
+    /// Queues `instances` of `mesh` as a single draw call, all placed by `transform` on top of
+    /// their own transforms. Shaded with `material` if set, like `draw_material`.
+    pub fn draw_instanced(
+        &mut self,
+        mesh: &Arc<Mesh>,
+        transform: Mat4,
+        instances: &[DrawInstance],
+        material: Option<&Arc<Material>>,
+        layers: RenderLayers,
+    ) {
+        if instances.is_empty() {
+            return;
+        }
+        self.draws.push(QueuedDraw {
+            mesh: mesh.clone(),
+            uniform: DrawUniform::new(transform, [1.0; 4]),
+            instances: instances.to_vec(),
+            material: material.cloned(),
+            layers,
+        });
+    }
+
+    /// Queues `mesh` like `draw_on_layers`, as a transparent surface: `color`'s alpha is its
+    /// opacity. It neither writes depth nor casts shadows, and is drawn after the opaque meshes
+    /// as `set_transparency` picks.
+    pub fn draw_transparent(&mut self, mesh: &Arc<Mesh>, transform: Mat4, color: [f32; 4], layers: RenderLayers) {
+        // not merged, so each is sorted on its own
+        self.transparent.push(QueuedDraw {
+            mesh: mesh.clone(),
+            uniform: DrawUniform::new(Mat4::IDENTITY, [1.0; 4]),
+            instances: vec![DrawInstance::new(transform, color)],
+            material: None,
+            layers,
+        });
+    }
+
+    /// How `draw_transparent` meshes are composited, `TransparencyMode::Sorted` at first.
+    pub fn transparency(&self) -> TransparencyMode {
+        self.transparency
+    }
+
+    /// Takes effect from the next `begin_frame`.
+    pub fn set_transparency(&mut self, mode: TransparencyMode) {
+        self.transparency = mode;
+    }
+
+    /// Whether any `draw_transparent` mesh is queued or prepared this frame.
+    pub fn has_transparent(&self) -> bool {
+        !self.transparent.is_empty() || !self.prepared_transparent.is_empty()
+    }
+
+    // adds an instance to the draw of the same mesh, material and layers, if there is one yet
+    fn queue(&mut self, mesh: &Arc<Mesh>, transform: Mat4, color: [f32; 4], material: Option<Arc<Material>>, layers: RenderLayers) {
+        let instance = DrawInstance::new(transform, color);
+        let key = (Arc::as_ptr(mesh), material.as_ref().map(Arc::as_ptr), layers);
+        if let Some(&index) = self.merged.get(&key) {
+            self.draws[index].instances.push(instance);
+            return;
+        }
+        self.merged.insert(key, self.draws.len());
+        self.draws.push(QueuedDraw {
+            mesh: mesh.clone(),
+            uniform: DrawUniform::new(Mat4::IDENTITY, [1.0; 4]),
+            instances: vec![instance],
+            material,
+            layers,
+        });
+    }
+
+    /// Draw calls queued or prepared this frame, after merging, transparent ones included.
+    pub fn len(&self) -> usize {
+        self.draws.len() + self.prepared.len() + self.transparent.len() + self.prepared_transparent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets the previous frame's draws. The engine calls it before `App::update`.
+    pub fn begin_frame(&mut self) {
+        self.draw_uniforms.begin_frame();
+        self.instances.begin_frame();
+        self.previous_models.begin_frame();
+        self.model_history = std::mem::take(&mut self.next_model_history);
+        self.prepared.clear();
+        self.prepared_transparent.clear();
+        self.frame_transparency = self.transparency;
+        self.draw_bind_group = None;
+    }
+
+    /// Brings `memory` up to date with the meshes uploaded or dropped and the per-frame buffers
+    /// grown since the last call.
+    fn track_memory(&mut self, memory: &mut GpuMemory) {
+        self.mesh_allocations.retain_mut(|(mesh, allocation)| match mesh.upgrade() {
+            Some(mesh) => {
+                allocation.get_or_insert_with(|| {
+                    memory.track_buffer(
+                        MemoryCategory::Meshes,
+                        &wgpu::BufferDescriptor {
+                            label: None,
+                            size: mesh.bytes,
+                            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::INDEX,
+                            mapped_at_creation: false,
+                        },
+                    )
+                });
+                true
+            }
+            None => {
+                if let Some(allocation) = allocation {
+                    memory.release(*allocation);
+                }
+                false
+            }
+        });
+
+        let buffers = [
+            (MemoryCategory::Uniforms, self.draw_uniforms.capacity()),
+            (MemoryCategory::Meshes, self.instances.capacity() + self.previous_models.capacity()),
+        ];
+        for (tracked, (category, bytes)) in self.buffer_allocations.iter_mut().zip(buffers) {
+            if tracked.is_some_and(|(tracked, _)| tracked == bytes) {
+                continue;
+            }
+            if let Some((_, allocation)) = tracked.take() {
+                memory.release(allocation);
+            }
+            let desc = wgpu::BufferDescriptor {
+                label: None,
+                size: bytes,
+                usage: wgpu::BufferUsages::empty(),
+                mapped_at_creation: false,
+            };
+            *tracked = Some((bytes, memory.track_buffer(category, &desc)));
+        }
+    }
+
+    /// Uploads the draws queued since the last call, adding them to the frame's. The engine
+    /// calls it before `App::render`, so apps can render the meshes into their own views, and
+    /// keeps `memory` up to date with the meshes and buffers the renderer holds.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, memory: &mut GpuMemory) {
+        self.track_memory(memory);
+        self.prepare_draws(device, queue);
+    }
+
+    fn prepare_draws(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.light_buffer.write(queue, &self.lights);
+        if self.draws.is_empty() && self.transparent.is_empty() {
+            return;
+        }
+        self.merged.clear();
+        for draw in std::mem::take(&mut self.draws) {
+            let features = draw.material.as_ref().map(|material| material.features());
+            let Some((pipeline, single_sampled)) = self.draw_pipelines(device, features, draw.mesh.layout) else {
+                continue;
+            };
+            let previous_models = self.push_previous_models(&draw);
+            self.prepared.push(PreparedDraw {
+                pipeline,
+                single_sampled,
+                mesh: draw.mesh,
+                uniform: self.draw_uniforms.push(&[draw.uniform]),
+                instances: self.instances.push(&draw.instances),
+                previous_models,
+                instance_count: draw.instances.len() as u32,
+                material: draw.material,
+                layers: draw.layers,
+            });
+        }
+        for draw in std::mem::take(&mut self.transparent) {
+            let pipelines = match self.frame_transparency {
+                TransparencyMode::Sorted => self.draw_pipelines(device, None, draw.mesh.layout),
+                // only drawn into the main view
+                TransparencyMode::WeightedBlended => match self.oit_pipeline(device, draw.mesh.layout, self.sample_count) {
+                    Ok(pipeline) => pipeline.map(|pipeline| (pipeline, None)),
+                    Err(e) => {
+                        log::warn!("Skipping transparent mesh draw: {}", e);
+                        None
+                    }
+                },
+            };
+            let Some((pipeline, single_sampled)) = pipelines else {
+                continue;
+            };
+            let instance = &draw.instances[0];
+            let position = Mat4::from_cols_array_2d(&draw.uniform.model) * Mat4::from_cols_array_2d(&instance.model);
+            // not drawn by the velocity pass, so without last frame's transforms
+            let previous_models = DynamicSlice { offset: 0, size: 0 };
+            self.prepared_transparent.push((
+                position.w_axis.truncate(),
+                PreparedDraw {
+                    pipeline,
+                    single_sampled,
+                    mesh: draw.mesh,
+                    uniform: self.draw_uniforms.push(&[draw.uniform]),
+                    instances: self.instances.push(&draw.instances),
+                    previous_models,
+                    instance_count: draw.instances.len() as u32,
+                    material: None,
+                    layers: draw.layers,
+                },
+            ));
+        }
+        self.draw_uniforms.finish(device, queue);
+        self.instances.finish(device, queue);
+        self.previous_models.finish(device, queue);
+        self.draw_bind_group = self.draw_uniforms.buffer().map(|buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("mesh draw bind group"),
+                layout: &self.draw_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<DrawUniform>() as u64),
+                    }),
+                }],
+            })
+        });
+    }
+
+    // the lit pipeline for the main view's sample count and, when it is multisampled, the
+    // single-sampled one for other views. `None` skips the draw, while it compiles or on error.
+    fn draw_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        features: Option<MaterialFeatures>,
+        layout: VertexLayoutId,
+    ) -> Option<(Arc<wgpu::RenderPipeline>, Option<Arc<wgpu::RenderPipeline>>)> {
+        let sample_count = self.sample_count;
+        let pipelines = self.lit_pipeline(device, features, layout, sample_count).and_then(|pipeline| {
+            let single_sampled = match sample_count > 1 {
+                true => self.lit_pipeline(device, features, layout, 1)?.map(Some),
+                false => Some(None),
+            };
+            Ok(pipeline.zip(single_sampled))
+        });
+        pipelines.unwrap_or_else(|e| {
+            log::warn!("Skipping mesh draw: {}", e);
+            None
+        })
+    }
+
+    // last frame's transforms of `draw`'s instances, or their current ones when the draw
+    // didn't exist or had a different instance count, remembering these for the next frame
+    fn push_previous_models(&mut self, draw: &QueuedDraw) -> DynamicSlice {
+        let transform = Mat4::from_cols_array_2d(&draw.uniform.model);
+        let models: Vec<[[f32; 4]; 4]> = draw
+            .instances
+            .iter()
+            .map(|instance| (transform * Mat4::from_cols_array_2d(&instance.model)).to_cols_array_2d())
+            .collect();
+        let merge_key = (Arc::as_ptr(&draw.mesh), draw.material.as_ref().map(Arc::as_ptr), draw.layers);
+        let occurrence = (0..).find(|&n| !self.next_model_history.contains_key(&(merge_key, n))).unwrap_or_default();
+        let slice = match self.model_history.get(&(merge_key, occurrence)) {
+            Some(previous) if previous.len() == models.len() => self.previous_models.push(previous),
+            _ => self.previous_models.push(&models),
+        };
+        self.next_model_history.insert((merge_key, occurrence), models);
+        slice
+    }
+
+    fn create_lighting_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        (shadows, local_shadows, light_profiles, light_probes): (&DirectionalShadows, &LocalShadows, &LightProfiles, &LightProbeTexture),
+        (reflection, probes): (&PlanarReflection, &ReflectionProbes),
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("mesh lighting bind group"),
+            layout,
+            entries: &[
+                shadows.bind_group_entries().as_slice(),
+                &local_shadows.bind_group_entries(),
+                &light_profiles.bind_group_entries(),
+                &reflection.bind_group_entries(),
+                &probes.bind_group_entries(),
+                &light_probes.bind_group_entries(),
+            ]
+            .concat(),
+        })
+    }
+
+    // the spot and point lights among the drawn ones, numbered like `LightBuffer::write` does
+    fn local_shadow_casters(&self) -> (Vec<SpotShadowCaster>, Vec<PointShadowCaster>) {
+        let bias = self.local_shadows.settings.bias;
+        let (mut spots, mut points) = (Vec::new(), Vec::new());
+        for light in self.lights.iter().take(crate::lights::MAX_LIGHTS) {
+            match light.kind {
+                LightKind::Directional => {}
+                LightKind::Point => points.push(PointShadowCaster {
+                    position: light.position,
+                    range: light.range,
+                    bias,
+                }),
+                LightKind::Spot { outer_angle, .. } => spots.push(SpotShadowCaster {
+                    position: light.position,
+                    direction: light.direction,
+                    outer_angle,
+                    range: light.range,
+                    bias,
+                }),
+            }
+        }
+        (spots, points)
+    }
+
+    /// Fits the shadow cascades of the first directional light to `camera`, plans the spot and
+    /// point lights' shadows, and draws the prepared draws on `shadows.settings.casters` layers
+    /// into them. The engine calls it after `prepare`, so meshes queued in `App::render` don't
+    /// cast shadows.
+    pub fn render_shadows(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        target_size: (u32, u32),
+        stats: &mut RenderStats,
+    ) {
+        let light = self
+            .lights
+            .iter()
+            .take(crate::lights::MAX_LIGHTS)
+            .position(|light| light.kind == LightKind::Directional)
+            .map(|index| (index, self.lights[index].direction));
+        self.shadows.update(device, queue, camera, target_size, light);
+        let (spots, points) = match self.shadows.settings.enabled {
+            true => self.local_shadow_casters(),
+            false => (Vec::new(), Vec::new()),
+        };
+        self.local_shadows.update(queue, camera, target_size, &spots, &points);
+        // the directional atlas may have been reallocated
+        self.update_lighting_bind_groups(device);
+
+        for (index, cascade) in self.shadows.cascades().iter().enumerate() {
+            let offset = index as u64 * SHADOW_VIEW_STRIDE;
+            queue.write_buffer(&self.shadow_views, offset, bytemuck::bytes_of(&cascade.view_projection.to_cols_array_2d()));
+        }
+        let casters = self.shadows.settings.casters;
+        let mut draws = Vec::new();
+        // still clears the atlases without any
+        let draw_bind_group = self.draw_bind_group.as_ref();
+        let instances = self.instances.buffer();
+        for draw in self.prepared.iter().filter(|draw| draw_bind_group.is_some() && casters.intersects(draw.layers)) {
+            match self.caster_pipelines.get(device, &self.layouts, draw.mesh.layout) {
+                Ok(pipeline) => draws.push((pipeline, draw)),
+                Err(e) => log::warn!("Skipping shadow caster: {}", e),
+            }
+        }
+        let shadow_view_bind_group = &self.shadow_view_bind_group;
+        if !self.shadows.cascades().is_empty() {
+            self.shadows.render(encoder, |pass, index, _| {
+                pass.set_bind_group(0, shadow_view_bind_group, &[(index as u64 * SHADOW_VIEW_STRIDE) as u32]);
+                for (pipeline, draw) in &draws {
+                    pass.set_pipeline(pipeline);
+                    pass.set_bind_group(1, draw_bind_group.unwrap(), &[draw.uniform.dynamic_offset()]);
+                    draw.draw(pass, instances.unwrap(), stats);
+                }
+                stats.shadow_casters += 1;
+            });
+        }
+
+        let mut view_index = MAX_CASCADES;
+        self.local_shadows.render(encoder, |encoder, target| {
+            let offset = view_index as u64 * SHADOW_VIEW_STRIDE;
+            view_index += 1;
+            queue.write_buffer(&self.shadow_views, offset, bytemuck::bytes_of(&target.view_projection.to_cols_array_2d()));
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("local shadow"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: target.depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            let (x, y, width, height) = target.viewport;
+            pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+            pass.set_scissor_rect(x, y, width, height);
+            pass.set_bind_group(0, shadow_view_bind_group, &[offset as u32]);
+            for (pipeline, draw) in &draws {
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(1, draw_bind_group.unwrap(), &[draw.uniform.dynamic_offset()]);
+                draw.draw(&mut pass, instances.unwrap(), stats);
+            }
+            stats.shadow_casters += 1;
+        });
+    }
+
+    fn update_lighting_bind_groups(&mut self, device: &wgpu::Device) {
+        let lighting = (&self.shadows, &self.local_shadows, &self.light_profiles, &self.light_probes);
+        let reflection = self.planar_reflection.as_ref().unwrap_or(&self.no_reflection);
+        self.lighting_bind_group =
+            Self::create_lighting_bind_group(device, &self.lighting_layout, lighting, (reflection, &self.reflection_probes));
+        if let Some(no_probes) = &self.no_probes {
+            self.capture_lighting_bind_group =
+                Self::create_lighting_bind_group(device, &self.lighting_layout, lighting, (&self.no_reflection, no_probes));
+        }
+    }
+
+    /// Lights the meshes' diffuse from `grid` in place of the environment map, or from the
+    /// environment map again with `None`. Call it again after the grid's probes changed.
+    pub fn set_light_probes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, grid: Option<&LightProbeGrid>) {
+        self.light_probes.upload(device, queue, grid);
+        self.update_lighting_bind_groups(device);
+    }
+
+    /// Captures the reflection probes waiting for it, drawing the prepared draws into each
+    /// face, and uploads the probes nearest `camera`. The engine calls it after `render_shadows`.
+    pub fn render_reflection_probes(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        stats: &mut RenderStats,
+    ) {
+        self.reflection_probes.update(queue, camera.position);
+        let faces = self.reflection_probes.pending_captures() * 6;
+        if faces == 0 {
+            return;
+        }
+        while self.probe_views.len() < faces {
+            let view = Self::new_view(device, &self.view_layout, &self.light_buffer, &self.environment_lighting, 1);
+            self.probe_views.push(view);
+        }
+        let Some(no_probes) = self.no_probes.take() else {
+            return;
+        };
+        let mut probes = std::mem::replace(&mut self.reflection_probes, no_probes);
+        let mut views = self.probe_views.iter();
+        probes.capture_pending(device, encoder, |encoder, target| {
+            let view = views.next().unwrap();
+            self.write_view(queue, view, target.view_projection, target.position);
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("reflection probe capture"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: target.color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: target.depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            self.draw_view(&mut pass, view, &self.capture_lighting_bind_group, camera, false, stats);
+        });
+        self.no_probes = Some(std::mem::replace(&mut self.reflection_probes, probes));
+    }
+
+    /// Renders `planar_reflection`, if there is one, for `camera`: the prepared draws it sees,
+    /// except reflective ones, mirrored about the plane, over transparent where the reflective
+    /// materials fall back to the environment map. The engine calls it after `render_shadows`.
+    pub fn render_reflection(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        target_size: (u32, u32),
+        stats: &mut RenderStats,
+    ) {
+        let Some(mut reflection) = self.planar_reflection.take() else {
+            return;
+        };
+        reflection.render(device, queue, encoder, camera, target_size, |encoder, target| {
+            self.write_view(queue, &self.reflection_view, target.view_projection, target.position);
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("planar reflection"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: target.color,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: target.depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            self.draw_view(&mut pass, &self.reflection_view, &self.capture_lighting_bind_group, camera, true, stats);
+        });
+        self.planar_reflection = Some(reflection);
+        // the reflection may have been resized
+        self.update_lighting_bind_groups(device);
+    }
+
+    /// Renders the frame's draws from `camera` into `target` through the main view, preparing
+    /// them first if anything was queued since `prepare`. Clears `depth` first.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+        depth: &wgpu::TextureView,
+        camera: &Camera,
+        target_size: (u32, u32),
+        stats: &mut RenderStats,
+    ) {
+        self.prepare_draws(device, queue);
+        self.render_view(queue, encoder, &self.main_view, target, load, depth, camera, target_size, stats);
+    }
+
+    fn write_view(&self, queue: &wgpu::Queue, view: &MeshView, view_projection: Mat4, position: Vec3) {
+        let uniform = ViewUniform {
+            view_projection: view_projection.to_cols_array_2d(),
+            ambient: self
+                .ambient
+                .extend(if self.environment.is_some() { self.environment_intensity } else { 0.0 })
+                .into(),
+            weather: [self.weather.wetness.clamp(0.0, 1.0), self.weather.snow.clamp(0.0, 1.0), 0.0, 0.0],
+            camera_position: position.extend(1.0).into(),
+        };
+        queue.write_buffer(&view.uniform, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// Like `render`, but through the debug variant of each draw's shader, writing `view`'s
+    /// values into `target`, a `DebugViews::FORMAT` texture. Clears both `target` and `depth`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_debug_view(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: DebugView,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        camera: &Camera,
+        target_size: (u32, u32),
+        stats: &mut RenderStats,
+    ) {
+        self.prepare_draws(device, queue);
+        let mut pipelines = Vec::with_capacity(self.prepared.len());
+        for index in 0..self.prepared.len() {
+            let draw = &self.prepared[index];
+            if !camera.sees(draw.layers) {
+                continue;
+            }
+            let (features, layout) = (draw.material.as_ref().map(|material| material.features()), draw.mesh.layout);
+            match self.debug_pipeline(device, view, features, layout) {
+                Ok(pipeline) => pipelines.push((pipeline, index)),
+                Err(e) => log::warn!("Skipping debug view draw: {}", e),
+            }
+        }
+        self.write_view(queue, &self.main_view, camera.view_projection(target_size), camera.position);
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug view"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        let (Some(draw_bind_group), Some(instances)) = (&self.draw_bind_group, self.instances.buffer()) else {
+            return;
+        };
+        pass.set_bind_group(0, &self.main_view.bind_group, &[]);
+        pass.set_bind_group(2, &self.lighting_bind_group, &[]);
+        for (pipeline, index) in &pipelines {
+            let draw = &self.prepared[*index];
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(1, draw_bind_group, &[draw.uniform.dynamic_offset()]);
+            if let Some(material) = &draw.material {
+                pass.set_bind_group(3, material.bind_group(), &[]);
+            }
+            draw.draw(&mut pass, instances, stats);
+        }
+    }
+
+    /// Draws the velocity of the prepared draws `camera` sees over `target`, a
+    /// `CameraVelocity::FORMAT` texture already holding the camera's motion, tested against
+    /// `depth` as the main view left it. Both single-sampled. Instances are matched to last
+    /// frame's by their draw's mesh, material and layers and their place in it, so objects
+    /// drawn in the same order every frame get their own motion.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_velocity(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        camera: &Camera,
+        previous_view_projection: Mat4,
+        target_size: (u32, u32),
+        stats: &mut RenderStats,
+    ) {
+        let mut pipelines = Vec::with_capacity(self.prepared.len());
+        for (index, draw) in self.prepared.iter().enumerate().filter(|(_, draw)| camera.sees(draw.layers)) {
+            match self.velocity_pipelines.get(device, &self.layouts, draw.mesh.layout) {
+                Ok(pipeline) => pipelines.push((pipeline, index)),
+                Err(e) => log::warn!("Skipping mesh velocity: {}", e),
+            }
+        }
+        let uniform = VelocityViewUniform {
+            view_projection: camera.view_projection(target_size).to_cols_array_2d(),
+            previous_view_projection: previous_view_projection.to_cols_array_2d(),
+        };
+        queue.write_buffer(&self.velocity_view, 0, bytemuck::bytes_of(&uniform));
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("mesh velocity"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        let (Some(draw_bind_group), Some(instances), Some(previous_models)) =
+            (&self.draw_bind_group, self.instances.buffer(), self.previous_models.buffer())
+        else {
+            return;
+        };
+        if !camera.viewport.is_full() {
+            let (x, y, width, height) = camera.viewport.pixels(target_size);
+            pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+            pass.set_scissor_rect(x, y, width, height);
+        }
+        pass.set_bind_group(0, &self.velocity_view_bind_group, &[]);
+        for (pipeline, index) in &pipelines {
+            let draw = &self.prepared[*index];
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(1, draw_bind_group, &[draw.uniform.dynamic_offset()]);
+            draw.draw_velocity(&mut pass, instances, previous_models, stats);
+        }
+    }
+
+    /// Whether any prepared draw is on one of `layers`.
+    pub fn draws_on(&self, layers: RenderLayers) -> bool {
+        self.prepared.iter().any(|draw| draw.layers.intersects(layers))
+    }
+
+    /// Clears `target`, an `Outline::MASK_FORMAT` texture, and draws into it the silhouettes of
+    /// the prepared draws on `layers` that `camera` sees, whole even where something is in front
+    /// of them: the mask for `Outline` to outline.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_mask(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        camera: &Camera,
+        layers: RenderLayers,
+        target_size: (u32, u32),
+        stats: &mut RenderStats,
+    ) {
+        let mut pipelines = Vec::new();
+        let visible = self.prepared.iter().enumerate().filter(|(_, draw)| draw.layers.intersects(layers) && camera.sees(draw.layers));
+        for (index, draw) in visible {
+            match self.mask_pipelines.get(device, &self.layouts, draw.mesh.layout) {
+                Ok(pipeline) => pipelines.push((pipeline, index)),
+                Err(e) => log::warn!("Skipping mesh mask: {}", e),
+            }
+        }
+        let view_projection = camera.view_projection(target_size).to_cols_array_2d();
+        queue.write_buffer(&self.mask_view, 0, bytemuck::bytes_of(&view_projection));
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("mesh mask"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        let (Some(draw_bind_group), Some(instances)) = (&self.draw_bind_group, self.instances.buffer()) else {
+            return;
+        };
+        if !camera.viewport.is_full() {
+            let (x, y, width, height) = camera.viewport.pixels(target_size);
+            pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+            pass.set_scissor_rect(x, y, width, height);
+        }
+        pass.set_bind_group(0, &self.mask_view_bind_group, &[]);
+        for (pipeline, index) in &pipelines {
+            let draw = &self.prepared[*index];
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(1, draw_bind_group, &[draw.uniform.dynamic_offset()]);
+            draw.draw(&mut pass, instances, stats);
+        }
+    }
+
+    /// Renders the prepared draws `camera` sees into `target` through `view`, which must not be
+    /// used for another camera in the same frame. Clears `depth` first. Both have the view's
+    /// sample count. Draws into `camera.viewport` only, but `load` applies to all of `target`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_view(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &MeshView,
+        target: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+        depth: &wgpu::TextureView,
+        camera: &Camera,
+        target_size: (u32, u32),
+        stats: &mut RenderStats,
+    ) {
+        self.write_view(queue, view, camera.view_projection(target_size), camera.position);
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("meshes"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        if !camera.viewport.is_full() {
+            let (x, y, width, height) = camera.viewport.pixels(target_size);
+            pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+            pass.set_scissor_rect(x, y, width, height);
+        }
+        self.draw_view(&mut pass, view, &self.lighting_bind_group, camera, false, stats);
+    }
+
+    /// Like `render_view`, into `camera.viewport` of targets other cameras draw into as well:
+    /// only the viewport is cleared, color per `camera.clear`, through `passes`, which must
+    /// have the view's sample count.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_camera(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &MeshView,
+        passes: &ViewportPasses,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        camera: &Camera,
+        target_size: (u32, u32),
+        stats: &mut RenderStats,
+    ) {
+        self.write_view(queue, view, camera.view_projection(target_size), camera.position);
+        let mut pass = passes.begin(encoder, camera, target, Some(depth), target_size);
+        self.draw_view(&mut pass, view, &self.lighting_bind_group, camera, false, stats);
+    }
+
+    /// Draws the frame's `draw_transparent` meshes `camera` sees into `pass`, begun by
+    /// `WeightedBlendedOit::begin_pass` over the depth `render` drew the main view with, when
+    /// `transparency` is `TransparencyMode::WeightedBlended`. The engine does it after the
+    /// opaque passes.
+    pub fn render_transparent<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, camera: &Camera, stats: &mut RenderStats) {
+        if self.frame_transparency != TransparencyMode::WeightedBlended {
+            return;
+        }
+        let (Some(draw_bind_group), Some(instances)) = (&self.draw_bind_group, self.instances.buffer()) else {
+            return;
+        };
+        pass.set_bind_group(0, &self.main_view.bind_group, &[]);
+        pass.set_bind_group(2, &self.lighting_bind_group, &[]);
+        for (_, draw) in self.prepared_transparent.iter().filter(|(_, draw)| camera.sees(draw.layers)) {
+            pass.set_pipeline(&draw.pipeline);
+            pass.set_bind_group(1, draw_bind_group, &[draw.uniform.dynamic_offset()]);
+            draw.draw(pass, instances, stats);
+        }
+    }
+
+    // `reflection` leaves out the draws that show the planar reflection, for rendering into it.
+    // Sorted transparent draws follow the opaque ones, farthest first.
+    fn draw_view<'a>(
+        &'a self,
+        pass: &mut wgpu::RenderPass<'a>,
+        view: &'a MeshView,
+        lighting: &'a wgpu::BindGroup,
+        camera: &Camera,
+        reflection: bool,
+        stats: &mut RenderStats,
+    ) {
+        let (Some(draw_bind_group), Some(instances)) = (&self.draw_bind_group, self.instances.buffer()) else {
+            return;
+        };
+        pass.set_bind_group(0, &view.bind_group, &[]);
+        pass.set_bind_group(2, lighting, &[]);
+        let reflective = |draw: &PreparedDraw| draw.material.as_ref().is_some_and(|material| material.features().has_planar_reflection());
+        let mut transparent = Vec::new();
+        if self.frame_transparency == TransparencyMode::Sorted {
+            transparent.extend(self.prepared_transparent.iter().map(|(position, draw)| (position.distance_squared(camera.position), draw)));
+            transparent.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        }
+        let draws = self.prepared.iter().chain(transparent.into_iter().map(|(_, draw)| draw));
+        for draw in draws.filter(|draw| camera.sees(draw.layers) && !(reflection && reflective(draw))) {
+            pass.set_pipeline(draw.pipeline(view.sample_count));
+            pass.set_bind_group(1, draw_bind_group, &[draw.uniform.dynamic_offset()]);
+            if let Some(material) = &draw.material {
+                pass.set_bind_group(3, material.bind_group(), &[]);
+            }
+            draw.draw(pass, instances, stats);
+        }
+    }
+}
+
+/// Files in `shader_reload::SHADER_DIRECTORY` of the shaders every lit pipeline includes, in
+/// the order they are prepended: the vertex input, then the lighting.
+pub const LIT_INCLUDES: [&str; 8] = [
+    "mesh_vertex.wgsl",
+    "light_profiles.wgsl",
+    "lights.wgsl",
+    "shadows.wgsl",
+    "local_shadows.wgsl",
+    "environment.wgsl",
+    "reflection_probes.wgsl",
+    "light_probes.wgsl",
+];
+
+/// Those the PBR shader also includes for materials with a lightmap or a planar reflection.
+pub const MATERIAL_INCLUDES: [&str; 2] = ["lightmap.wgsl", "planar_reflection.wgsl"];
+
+/// The shaders `MeshRenderer` assembles its lit pipelines from besides the mesh or PBR shader
+/// itself. `default` is the built-in ones: `MESH_VERTEX_SHADER`, the lighting shaders from
+/// `LIGHT_PROFILE_SHADER` to `LIGHT_PROBE_SHADER`, `LIGHTMAP_SHADER` and
+/// `PLANAR_REFLECTION_SHADER`.
+#[derive(Clone, Debug)]
+pub struct LitIncludes {
+    vertex: String,
+    lighting: Vec<String>,
+    lightmap: String,
+    planar_reflection: String,
+}
+
+impl Default for LitIncludes {
+    fn default() -> Self {
+        let lighting = [
+            LIGHT_PROFILE_SHADER,
+            LIGHTS_SHADER,
+            SHADOW_SHADER,
+            LOCAL_SHADOW_SHADER,
+            ENVIRONMENT_SHADER,
+            REFLECTION_PROBE_SHADER,
+            LIGHT_PROBE_SHADER,
+        ];
+        LitIncludes {
+            vertex: MESH_VERTEX_SHADER.to_string(),
+            lighting: lighting.map(str::to_string).to_vec(),
+            lightmap: LIGHTMAP_SHADER.to_string(),
+            planar_reflection: PLANAR_REFLECTION_SHADER.to_string(),
+        }
+    }
+}
+
+impl LitIncludes {
+    /// The `LIT_INCLUDES` and `MATERIAL_INCLUDES` as `read` returns them by file name, e.g.
+    /// from disk after an edit.
+    pub fn read<E>(mut read: impl FnMut(&str) -> Result<String, E>) -> Result<Self, E> {
+        Ok(LitIncludes {
+            vertex: read(LIT_INCLUDES[0])?,
+            lighting: LIT_INCLUDES[1..].iter().map(|file| read(file)).collect::<Result<_, _>>()?,
+            lightmap: read(MATERIAL_INCLUDES[0])?,
+            planar_reflection: read(MATERIAL_INCLUDES[1])?,
+        })
+    }
+
+    /// `source` with the light buffer, light profiles, shadow maps, environment lighting and
+    /// reflection and light probes it reads declared in front.
+    fn lit(&self, source: &str) -> String {
+        format!("{}\n{}", self.lighting.join("\n"), source)
+    }
+
+    /// The PBR shader with the `#ifdef`s of `features` resolved, after the `features` shaders
+    /// they need. Any other name counts as undefined here, so the shader leaves vertex
+    /// attributes to the accessors of the vertex input, which `MeshPipelines` resolves.
+    fn material(&self, source: &str, features: MaterialFeatures) -> Result<String, ShaderError> {
+        let source = shader_variants::preprocess(source, |name| features.shader_features().any(|feature| feature == name))?;
+        Ok(self.lit(&format!("{}\n{}", self.features(features), source)))
+    }
+
+    /// The lightmap shader for materials with a lightmap and the planar reflection one for
+    /// reflective ones.
+    fn features(&self, features: MaterialFeatures) -> String {
+        let shaders = [
+            (features.has_lightmap(), &self.lightmap),
+            (features.has_planar_reflection(), &self.planar_reflection),
+        ];
+        let shaders: Vec<_> = shaders.into_iter().filter_map(|(needed, shader)| needed.then_some(shader.as_str())).collect();
+        shaders.join("\n")
+    }
+}
+
+fn build_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    vertex_buffer: wgpu::VertexBufferLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[vertex_buffer, DrawInstance::buffer_layout()],
+        },
+        primitive: wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: MeshRenderer::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: "fs_main",
+            targets: &[wgpu::ColorTargetState {
+                format: color_format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        multiview: None,
+    })
+}
+
+/// Like `build_pipeline`, into the `WeightedBlendedOit` targets, depth tested without writing
+/// depth so every transparent layer is accumulated.
+fn build_oit_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    vertex_buffer: wgpu::VertexBufferLayout,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("mesh oit"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[vertex_buffer, DrawInstance::buffer_layout()],
+        },
+        primitive: wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: MeshRenderer::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: "fs_main",
+            targets: &WeightedBlendedOit::color_targets(),
+        }),
+        multiview: None,
+    })
+}
+
+/// Writes `view`'s value: every fragment adds one for overdraw, depth test off; the others keep
+/// the nearest surface's.
+fn build_debug_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    vertex_buffer: wgpu::VertexBufferLayout,
+    view: DebugView,
+) -> wgpu::RenderPipeline {
+    let overdraw = view == DebugView::Overdraw;
+    let add = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("debug view"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[vertex_buffer, DrawInstance::buffer_layout()],
+        },
+        primitive: wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: MeshRenderer::DEPTH_FORMAT,
+            depth_write_enabled: !overdraw,
+            depth_compare: if overdraw { wgpu::CompareFunction::Always } else { wgpu::CompareFunction::Less },
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: "fs_main",
+            targets: &[wgpu::ColorTargetState {
+                format: DebugViews::FORMAT,
+                blend: overdraw.then_some(wgpu::BlendState { color: add, alpha: add }),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        multiview: None,
+    })
+}
+
+fn build_caster_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    vertex_buffer: wgpu::VertexBufferLayout,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("shadow caster"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[vertex_buffer, DrawInstance::buffer_layout()],
+        },
+        primitive: wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DirectionalShadows::FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            // steep surfaces need more bias than the shader's constant one
+            bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: None,
+        multiview: None,
+    })
+}
+
+fn build_velocity_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    vertex_buffer: wgpu::VertexBufferLayout,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("mesh velocity"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[
+                vertex_buffer,
+                DrawInstance::model_buffer_layout(),
+                DrawInstance::previous_model_buffer_layout(),
+            ],
+        },
+        primitive: wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        // the surfaces the mesh pass left in the depth buffer, which stays as it is
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: MeshRenderer::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: "fs_main",
+            targets: &[CameraVelocity::FORMAT.into()],
+        }),
+        multiview: None,
+    })
+}
+
+fn build_mask_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    vertex_buffer: wgpu::VertexBufferLayout,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("mesh mask"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[vertex_buffer, DrawInstance::model_buffer_layout()],
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: "fs_main",
+            targets: &[Outline::MASK_FORMAT.into()],
+        }),
+        multiview: None,
+    })
+}
+
+/// The depth buffer `MeshRenderer` draws with, for a target of `size`.
+pub fn depth_texture(device: &wgpu::Device, (width, height): (u32, u32)) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth buffer"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: MeshRenderer::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
@@ -0,0 +1,386 @@
+use glam::{Vec2, Vec3, Vec4Swizzles};
+
+#[cfg(feature = "egui")]
+use crate::engine::AppContext;
+use crate::{
+    camera::{Camera, Projection},
+    dynamic_buffer::{DynamicBuffer, DynamicSlice, FRAMES_IN_FLIGHT},
+    mesh_renderer::{self, MeshRenderer, MeshView},
+    postprocess::{fullscreen_module, FullscreenPipeline, RenderTarget},
+    render_layers::RenderLayers,
+    render_stats::RenderStats,
+    sprite::{
+        batch::{SpriteBatch, SpriteSpace},
+        UvRect,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MinimapSettings {
+    /// World units from the center to the edge of the map.
+    pub radius: f32,
+    /// Height above the center the map looks down from; anything higher is cut off.
+    pub altitude: f32,
+    /// How far below the camera the map still shows.
+    pub depth: f32,
+    /// Turns the map with the followed heading, so forward is always up; otherwise -Z is.
+    pub rotate: bool,
+    /// Round map with everything outside the circle transparent.
+    pub circular: bool,
+    pub background: wgpu::Color,
+    /// What the map camera sees: the scene plus the minimap-only layer, without the editor's.
+    pub layers: RenderLayers,
+}
+
+impl Default for MinimapSettings {
+    fn default() -> Self {
+        MinimapSettings {
+            radius: 50.0,
+            altitude: 100.0,
+            depth: 200.0,
+            rotate: true,
+            circular: true,
+            background: wgpu::Color {
+                r: 0.05,
+                g: 0.06,
+                b: 0.08,
+                a: 1.0,
+            },
+            layers: RenderLayers::ALL.without(RenderLayers::EDITOR | RenderLayers::FIRST_PERSON),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MarkerShape {
+    Dot = 0,
+    Ring = 1,
+    Square = 2,
+    /// Points along the marker's heading, for the player and vehicles.
+    Arrow = 3,
+}
+
+/// An icon drawn over the map at a world position, e.g. the player, quest targets or enemies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Marker {
+    pub position: Vec3,
+    /// Radians around Y, 0 pointing along -Z, like the camera controllers' yaw.
+    pub heading: f32,
+    pub shape: MarkerShape,
+    /// Linear RGBA.
+    pub color: [f32; 4],
+    /// Width in map pixels.
+    pub size: f32,
+    /// Keeps the marker at the edge of the map while out of range, pointing the way to it.
+    pub clamp_to_edge: bool,
+    pub visible: bool,
+}
+
+impl Default for Marker {
+    fn default() -> Self {
+        Marker {
+            position: Vec3::ZERO,
+            heading: 0.0,
+            shape: MarkerShape::Dot,
+            color: [1.0; 4],
+            size: 8.0,
+            clamp_to_edge: false,
+            visible: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MarkerId(usize);
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MarkerInstance {
+    // center in clip space, half size in clip space, rotation
+    placement: [f32; 4],
+    color: [f32; 4],
+    shape: u32,
+}
+
+impl MarkerInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32x4,
+        2 => Uint32,
+    ];
+}
+
+/// A top-down map around a followed point: the world's meshes seen by an orthographic camera
+/// from above, rendered into a texture with markers on top. Show it with `ui` through egui,
+/// or with `write_sprite` and `view` on the sprite layer.
+pub struct Minimap {
+    pub settings: MinimapSettings,
+    center: Vec3,
+    heading: f32,
+    size: u32,
+    markers: Vec<Option<Marker>>,
+    color: RenderTarget,
+    depth: wgpu::TextureView,
+    mesh_view: MeshView,
+    marker_pipeline: wgpu::RenderPipeline,
+    mask: FullscreenPipeline,
+    instances: DynamicBuffer,
+    #[cfg(feature = "egui")]
+    texture_id: Option<egui::TextureId>,
+}
+
+impl Minimap {
+    /// A map texture `size` pixels square that `meshes` can render into.
+    pub fn new(device: &wgpu::Device, meshes: &MeshRenderer, size: u32) -> Self {
+        let format = meshes.color_format();
+        let module = fullscreen_module(device, "minimap", include_str!("shaders/minimap.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("minimap markers"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let marker_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("minimap markers"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_marker",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<MarkerInstance>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &MarkerInstance::ATTRIBUTES,
+                }],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_marker",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            multiview: None,
+        });
+        // the mask outputs alpha 1 outside the circle, which scales everything there to zero
+        let erase = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let mask = FullscreenPipeline::new(
+            device,
+            "minimap mask",
+            &module,
+            "fs_mask",
+            &[],
+            format,
+            Some(wgpu::BlendState {
+                color: erase,
+                alpha: erase,
+            }),
+        );
+
+        Minimap {
+            settings: MinimapSettings::default(),
+            center: Vec3::ZERO,
+            heading: 0.0,
+            size,
+            markers: Vec::new(),
+            color: RenderTarget::new(device, "minimap", size, size, format),
+            depth: mesh_renderer::depth_texture(device, (size, size)),
+            mesh_view: meshes.create_view(device),
+            marker_pipeline,
+            mask,
+            instances: DynamicBuffer::new(device, "minimap markers", wgpu::BufferUsages::VERTEX, FRAMES_IN_FLIGHT),
+            #[cfg(feature = "egui")]
+            texture_id: None,
+        }
+    }
+
+    /// Centers the map on `position`, turned to `heading` if the map rotates. Call every frame
+    /// with the player's position and yaw.
+    pub fn follow(&mut self, position: Vec3, heading: f32) {
+        self.center = position;
+        self.heading = heading;
+    }
+
+    pub fn add_marker(&mut self, marker: Marker) -> MarkerId {
+        match self.markers.iter().position(Option::is_none) {
+            Some(index) => {
+                self.markers[index] = Some(marker);
+                MarkerId(index)
+            }
+            None => {
+                self.markers.push(Some(marker));
+                MarkerId(self.markers.len() - 1)
+            }
+        }
+    }
+
+    pub fn remove_marker(&mut self, id: MarkerId) -> Option<Marker> {
+        self.markers.get_mut(id.0)?.take()
+    }
+
+    pub fn marker(&self, id: MarkerId) -> Option<&Marker> {
+        self.markers.get(id.0)?.as_ref()
+    }
+
+    /// For moving a marker every frame along with what it marks.
+    pub fn marker_mut(&mut self, id: MarkerId) -> Option<&mut Marker> {
+        self.markers.get_mut(id.0)?.as_mut()
+    }
+
+    pub fn clear_markers(&mut self) {
+        self.markers.clear();
+    }
+
+    /// The camera the map is rendered with.
+    pub fn camera(&self) -> Camera {
+        let settings = &self.settings;
+        let up = if settings.rotate {
+            Vec3::new(-self.heading.sin(), 0.0, -self.heading.cos())
+        } else {
+            -Vec3::Z
+        };
+        Camera {
+            position: self.center + Vec3::Y * settings.altitude,
+            forward: -Vec3::Y,
+            up,
+            projection: Projection::Orthographic {
+                height: settings.radius * 2.0,
+                near: 0.0,
+                far: settings.altitude + settings.depth,
+            },
+            clear: Some(settings.background),
+            layers: settings.layers,
+            ..Camera::default()
+        }
+    }
+
+    /// Where `position` shows up on the map, in [0, 1] from the top left; `None` outside it.
+    pub fn world_to_map(&self, position: Vec3) -> Option<Vec2> {
+        let clip = self.camera().view_projection((self.size, self.size)) * position.extend(1.0);
+        let uv = Vec2::new(clip.x * 0.5 + 0.5, 0.5 - clip.y * 0.5);
+        let inside = if self.settings.circular {
+            clip.xy().length() <= 1.0
+        } else {
+            clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0
+        };
+        inside.then_some(uv)
+    }
+
+    fn marker_instances(&self, camera: &Camera) -> Vec<MarkerInstance> {
+        let view_projection = camera.view_projection((self.size, self.size));
+        let pixel = 2.0 / self.size.max(1) as f32;
+        let mut instances = Vec::new();
+        for marker in self.markers.iter().flatten().filter(|marker| marker.visible) {
+            let mut center = (view_projection * marker.position.extend(1.0)).xy();
+            let half_size = marker.size * 0.5 * pixel;
+            let mut rotation = {
+                let along = (view_projection * Vec3::new(-marker.heading.sin(), 0.0, -marker.heading.cos()).extend(0.0)).xy();
+                (-along.x).atan2(along.y)
+            };
+            // how far the marker's center may go so all of it stays on the map
+            let limit = 1.0 - half_size;
+            let outside = if self.settings.circular {
+                center.length() / limit
+            } else {
+                center.abs().max_element() / limit
+            };
+            if outside > 1.0 {
+                if !marker.clamp_to_edge {
+                    continue;
+                }
+                center /= outside;
+                if marker.shape == MarkerShape::Arrow {
+                    rotation = (-center.x).atan2(center.y);
+                }
+            }
+            instances.push(MarkerInstance {
+                placement: [center.x, center.y, half_size, rotation],
+                color: marker.color,
+                shape: marker.shape as u32,
+            });
+        }
+        instances
+    }
+
+    /// Renders the map with the frame's meshes, which must already be prepared as they are
+    /// during `App::render`.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        meshes: &MeshRenderer,
+        stats: &mut RenderStats,
+    ) {
+        let camera = self.camera();
+        let size = (self.size, self.size);
+        let load = wgpu::LoadOp::Clear(self.settings.background);
+        meshes.render_view(queue, encoder, &self.mesh_view, &self.color.view, load, &self.depth, &camera, size, stats);
+
+        let instances = self.marker_instances(&camera);
+        self.instances.begin_frame();
+        let slice: Option<DynamicSlice> = (!instances.is_empty()).then(|| self.instances.push(&instances));
+        self.instances.finish(device, queue);
+        if let Some(buffer) = slice.and_then(|slice| self.instances.slice(slice)) {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("minimap markers"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.color.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&self.marker_pipeline);
+            pass.set_vertex_buffer(0, buffer);
+            pass.draw(0..4, 0..instances.len() as u32);
+        }
+
+        if self.settings.circular {
+            self.mask.draw(encoder, &self.color.view, &[], None);
+        }
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.color.texture
+    }
+
+    /// The map, for binding with `SpriteRenderer::texture_bind_group`.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.color.view
+    }
+
+    /// Adds the map to `batch` as one sprite of `size` at `center`, e.g. in a HUD drawn with an
+    /// orthographic camera.
+    pub fn write_sprite(&self, batch: &mut SpriteBatch, center: Vec3, size: Vec2) {
+        batch.sprite(SpriteSpace::World, center, size, UvRect::FULL, [1.0; 4]);
+    }
+
+    /// Registers the map texture with the window's UI, once, so `ui` can show it.
+    #[cfg(feature = "egui")]
+    pub fn register_ui(&mut self, context: &mut AppContext) {
+        if self.texture_id.is_none() {
+            self.texture_id = context.register_ui_texture(&self.color.texture);
+        }
+    }
+
+    /// Shows the map `size` points wide; nothing until `register_ui` was called.
+    #[cfg(feature = "egui")]
+    pub fn ui(&self, ui: &mut egui::Ui, size: f32) -> Option<egui::Response> {
+        self.texture_id.map(|id| ui.image(id, egui::vec2(size, size)))
+    }
+}
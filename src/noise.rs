@@ -0,0 +1,540 @@
+use std::num::NonZeroU64;
+
+use glam::{IVec3, Vec2, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::shader_variants::preprocess;
+
+/// WGSL versions of `perlin3`, `worley3` and fractal noise, for shaders that compute noise
+/// themselves. Prepend it to the shader source; it declares no bindings.
+pub const NOISE_SHADER: &str = include_str!("shaders/noise.wgsl");
+
+fn hash(x: u32) -> u32 {
+    let h = x.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+    let h = ((h >> ((h >> 28) + 4)) ^ h).wrapping_mul(277_803_737);
+    (h >> 22) ^ h
+}
+
+fn cell_hash(cell: IVec3, period: i32, seed: u32) -> u32 {
+    let cell = if period > 0 {
+        IVec3::new(cell.x.rem_euclid(period), cell.y.rem_euclid(period), cell.z.rem_euclid(period))
+    } else {
+        cell
+    };
+    hash(cell.x as u32 ^ hash(cell.y as u32 ^ hash(cell.z as u32 ^ hash(seed))))
+}
+
+/// Three 10 bit fractions of a hash, in [0, 1).
+fn unit3(h: u32) -> Vec3 {
+    Vec3::new((h & 1023) as f32, ((h >> 10) & 1023) as f32, ((h >> 20) & 1023) as f32) / 1024.0
+}
+
+fn gradient3(h: u32) -> Vec3 {
+    // never zero: no 10 bit fraction lands exactly on the middle
+    (unit3(h) * 2.0 - Vec3::splat(0.999)).normalize()
+}
+
+fn fade(t: Vec3) -> Vec3 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// Gradient noise in about [-1, 1].
+pub fn perlin3(p: Vec3, seed: u32) -> f32 {
+    perlin3_tiled(p, 0, seed)
+}
+
+pub fn perlin2(p: Vec2, seed: u32) -> f32 {
+    perlin3(p.extend(0.0), seed)
+}
+
+/// `perlin3` repeating every `period` units on every axis, for textures that wrap. A period of
+/// zero doesn't repeat.
+pub fn perlin3_tiled(p: Vec3, period: i32, seed: u32) -> f32 {
+    let floored = p.floor();
+    let cell = floored.as_ivec3();
+    let f = p - floored;
+    let u = fade(f);
+    let corner = |x: i32, y: i32, z: i32| {
+        let offset = IVec3::new(x, y, z);
+        gradient3(cell_hash(cell + offset, period, seed)).dot(f - offset.as_vec3())
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u.x);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u.x);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u.x);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u.x);
+    // unit gradients peak at sqrt(3) / 2
+    lerp(lerp(x00, x10, u.y), lerp(x01, x11, u.y), u.z) * 1.1547
+}
+
+/// Cellular noise: the distance to the nearest of one random point per unit cell, clamped to
+/// [0, 1]. Inverted it gives the billowy look clouds are built from.
+pub fn worley3(p: Vec3, seed: u32) -> f32 {
+    worley3_tiled(p, 0, seed)
+}
+
+pub fn worley2(p: Vec2, seed: u32) -> f32 {
+    worley3(p.extend(0.0), seed)
+}
+
+pub fn worley3_tiled(p: Vec3, period: i32, seed: u32) -> f32 {
+    let floored = p.floor();
+    let cell = floored.as_ivec3();
+    let f = p - floored;
+    let mut nearest = 8.0f32;
+    for z in -1..=1 {
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let offset = IVec3::new(x, y, z);
+                let point = offset.as_vec3() + unit3(cell_hash(cell + offset, period, seed));
+                nearest = nearest.min((point - f).length_squared());
+            }
+        }
+    }
+    nearest.sqrt().min(1.0)
+}
+
+/// Simplex noise in about [-1, 1]: like Perlin noise with fewer grid artifacts and cheaper in
+/// higher dimensions, but it doesn't tile.
+pub fn simplex2(p: Vec2, seed: u32) -> f32 {
+    const F2: f32 = 0.366_025_42;
+    const G2: f32 = 0.211_324_87;
+    let s = (p.x + p.y) * F2;
+    let cell = (p + Vec2::splat(s)).floor();
+    let t = (cell.x + cell.y) * G2;
+    let d0 = p - (cell - Vec2::splat(t));
+    let step = if d0.x > d0.y { Vec2::X } else { Vec2::Y };
+    let corners = [
+        (Vec2::ZERO, d0),
+        (step, d0 - step + Vec2::splat(G2)),
+        (Vec2::ONE, d0 - Vec2::ONE + Vec2::splat(2.0 * G2)),
+    ];
+    let mut sum = 0.0;
+    for (offset, d) in corners {
+        let falloff = 0.5 - d.length_squared();
+        if falloff > 0.0 {
+            let h = cell_hash((cell + offset).as_ivec2().extend(0), 0, seed);
+            let angle = (h >> 8) as f32 / (1u32 << 24) as f32 * std::f32::consts::TAU;
+            let falloff = falloff * falloff;
+            sum += falloff * falloff * Vec2::new(angle.cos(), angle.sin()).dot(d);
+        }
+    }
+    sum * 70.0
+}
+
+pub fn simplex3(p: Vec3, seed: u32) -> f32 {
+    const F3: f32 = 1.0 / 3.0;
+    const G3: f32 = 1.0 / 6.0;
+    let s = (p.x + p.y + p.z) * F3;
+    let cell = (p + Vec3::splat(s)).floor();
+    let t = (cell.x + cell.y + cell.z) * G3;
+    let d0 = p - (cell - Vec3::splat(t));
+    // which of the six tetrahedra of the skewed cube p is in
+    let (first, second) = if d0.x >= d0.y {
+        if d0.y >= d0.z {
+            (Vec3::X, Vec3::new(1.0, 1.0, 0.0))
+        } else if d0.x >= d0.z {
+            (Vec3::X, Vec3::new(1.0, 0.0, 1.0))
+        } else {
+            (Vec3::Z, Vec3::new(1.0, 0.0, 1.0))
+        }
+    } else if d0.y < d0.z {
+        (Vec3::Z, Vec3::new(0.0, 1.0, 1.0))
+    } else if d0.x < d0.z {
+        (Vec3::Y, Vec3::new(0.0, 1.0, 1.0))
+    } else {
+        (Vec3::Y, Vec3::new(1.0, 1.0, 0.0))
+    };
+    let corners = [
+        (Vec3::ZERO, d0),
+        (first, d0 - first + Vec3::splat(G3)),
+        (second, d0 - second + Vec3::splat(2.0 * G3)),
+        (Vec3::ONE, d0 - Vec3::ONE + Vec3::splat(3.0 * G3)),
+    ];
+    let mut sum = 0.0;
+    for (offset, d) in corners {
+        let falloff = 0.6 - d.length_squared();
+        if falloff > 0.0 {
+            let falloff = falloff * falloff;
+            sum += falloff * falloff * gradient3(cell_hash((cell + offset).as_ivec3(), 0, seed)).dot(d);
+        }
+    }
+    sum * 32.0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseKind {
+    Perlin,
+    Simplex,
+    Worley,
+}
+
+/// Fractal noise for gameplay and content code, e.g. terrain heights, wind, or wobble in
+/// particle motion: `octaves` layers of `kind` noise, each `lacunarity` times the frequency and
+/// `gain` times the amplitude of the one before.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    pub kind: NoiseKind,
+    pub seed: u32,
+    pub frequency: f32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            kind: NoiseKind::Perlin,
+            seed: 0,
+            frequency: 1.0,
+            octaves: 4,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Noise {
+    pub fn new(kind: NoiseKind, frequency: f32) -> Self {
+        Noise {
+            kind,
+            frequency,
+            ..Noise::default()
+        }
+    }
+
+    fn octaves(&self, mut sample: impl FnMut(f32, u32) -> f32) -> f32 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = self.frequency;
+        for octave in 0..self.octaves.max(1) {
+            sum += sample(frequency, self.seed.wrapping_add(octave)) * amplitude;
+            total += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        (sum / total).clamp(0.0, 1.0)
+    }
+
+    /// In [0, 1]; Perlin and simplex noise are remapped from their signed range.
+    pub fn sample3(&self, p: Vec3) -> f32 {
+        self.octaves(|frequency, seed| match self.kind {
+            NoiseKind::Perlin => perlin3(p * frequency, seed) * 0.5 + 0.5,
+            NoiseKind::Simplex => simplex3(p * frequency, seed) * 0.5 + 0.5,
+            NoiseKind::Worley => worley3(p * frequency, seed),
+        })
+    }
+
+    pub fn sample2(&self, p: Vec2) -> f32 {
+        self.octaves(|frequency, seed| match self.kind {
+            NoiseKind::Perlin => perlin2(p * frequency, seed) * 0.5 + 0.5,
+            NoiseKind::Simplex => simplex2(p * frequency, seed) * 0.5 + 0.5,
+            NoiseKind::Worley => worley2(p * frequency, seed),
+        })
+    }
+}
+
+/// The noise kinds that can tile, and so can fill a texture that wraps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TilingNoise {
+    Perlin,
+    Worley,
+}
+
+/// What one channel of a noise texture holds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseLayer {
+    pub kind: TilingNoise,
+    /// Noise cells across the texture; the texture tiles because this is a whole number.
+    pub frequency: u32,
+    /// Each octave doubles the frequency and scales the amplitude by `gain`.
+    pub octaves: u32,
+    pub gain: f32,
+    pub seed: u32,
+    pub invert: bool,
+}
+
+impl NoiseLayer {
+    pub fn new(kind: TilingNoise, frequency: u32) -> Self {
+        NoiseLayer {
+            kind,
+            frequency: frequency.max(1),
+            octaves: 1,
+            gain: 0.5,
+            seed: 0,
+            invert: false,
+        }
+    }
+
+    pub fn with_octaves(mut self, octaves: u32, gain: f32) -> Self {
+        self.octaves = octaves;
+        self.gain = gain;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn inverted(mut self) -> Self {
+        self.invert = !self.invert;
+        self
+    }
+
+    /// The layer's value at `uvw` in texture space, in [0, 1]: what the GPU generator writes,
+    /// for CPU fallbacks and for sampling the same noise in game code.
+    pub fn sample(&self, uvw: Vec3) -> f32 {
+        let p = uvw * self.frequency as f32;
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut scale = 1.0;
+        let mut period = self.frequency as i32;
+        for octave in 0..self.octaves.max(1) {
+            let seed = self.seed.wrapping_add(octave);
+            let value = match self.kind {
+                TilingNoise::Perlin => perlin3_tiled(p * scale, period, seed) * 0.5 + 0.5,
+                TilingNoise::Worley => worley3_tiled(p * scale, period, seed),
+            };
+            sum += value * amplitude;
+            total += amplitude;
+            amplitude *= self.gain;
+            scale *= 2.0;
+            period *= 2;
+        }
+        let value = (sum / total).clamp(0.0, 1.0);
+        if self.invert {
+            1.0 - value
+        } else {
+            value
+        }
+    }
+}
+
+/// A tiling `Rgba8Unorm` noise texture, square or cubic, with a layer per channel. Cloud shapes,
+/// for instance, use Perlin-Worley in red and inverted Worley octaves in the others.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoiseTextureDescriptor {
+    pub size: u32,
+    /// 3D textures are `size` texels deep.
+    pub three_d: bool,
+    /// Channels without a layer are zero.
+    pub channels: [Option<NoiseLayer>; 4],
+}
+
+impl NoiseTextureDescriptor {
+    fn extent(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.size,
+            height: self.size,
+            depth_or_array_layers: if self.three_d { self.size } else { 1 },
+        }
+    }
+
+    /// The texels generated on the CPU, row by row and slice by slice, for devices without
+    /// compute shaders. Slow for large 3D textures.
+    pub fn texels(&self) -> Vec<[u8; 4]> {
+        let extent = self.extent();
+        let mut texels = Vec::with_capacity((self.size * self.size * extent.depth_or_array_layers) as usize);
+        for z in 0..extent.depth_or_array_layers {
+            for y in 0..self.size {
+                for x in 0..self.size {
+                    let mut uvw = (Vec3::new(x as f32, y as f32, z as f32) + 0.5) / self.size as f32;
+                    if !self.three_d {
+                        uvw.z = 0.0;
+                    }
+                    texels.push(self.channels.map(|layer| {
+                        layer.map_or(0, |layer| (layer.sample(uvw) * 255.0 + 0.5) as u8)
+                    }));
+                }
+            }
+        }
+        texels
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct LayerUniform {
+    kind: u32,
+    frequency: u32,
+    octaves: u32,
+    seed: u32,
+    gain: f32,
+    invert: u32,
+    enabled: u32,
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    size: u32,
+    depth: u32,
+    _padding: [u32; 2],
+    layers: [LayerUniform; 4],
+}
+
+/// Generates noise textures with compute shaders, at load time or whenever their settings
+/// change. Needs compute shader support; check `Capabilities::compute` first and fall back to
+/// `NoiseTextureDescriptor::texels` without it.
+pub struct NoiseTextureGenerator {
+    pipeline_2d: wgpu::ComputePipeline,
+    pipeline_3d: wgpu::ComputePipeline,
+    layout_2d: wgpu::BindGroupLayout,
+    layout_3d: wgpu::BindGroupLayout,
+}
+
+impl NoiseTextureGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let source = include_str!("shaders/noise_texture.wgsl");
+        let create = |three_d: bool| {
+            let entry = preprocess(source, |name| three_d && name == "THREE_D").expect("noise texture shader is valid");
+            let label = if three_d { "noise texture 3d" } else { "noise texture 2d" };
+            let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", NOISE_SHADER, entry).into()),
+            });
+            let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: NonZeroU64::new(std::mem::size_of::<Params>() as u64),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba8Unorm,
+                            view_dimension: if three_d {
+                                wgpu::TextureViewDimension::D3
+                            } else {
+                                wgpu::TextureViewDimension::D2
+                            },
+                        },
+                        count: None,
+                    },
+                ],
+            });
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                module: &module,
+                entry_point: "main",
+            });
+            (pipeline, bind_group_layout)
+        };
+        let (pipeline_2d, layout_2d) = create(false);
+        let (pipeline_3d, layout_3d) = create(true);
+        NoiseTextureGenerator {
+            pipeline_2d,
+            pipeline_3d,
+            layout_2d,
+            layout_3d,
+        }
+    }
+
+    /// Records the dispatch that fills a new texture into `encoder`. The texture can be sampled,
+    /// with a repeating sampler, once the encoder is submitted.
+    pub fn generate(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        label: &str,
+        descriptor: &NoiseTextureDescriptor,
+    ) -> wgpu::Texture {
+        let extent = descriptor.extent();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: if descriptor.three_d {
+                wgpu::TextureDimension::D3
+            } else {
+                wgpu::TextureDimension::D2
+            },
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+        });
+
+        let mut params = Params {
+            size: descriptor.size,
+            depth: extent.depth_or_array_layers,
+            _padding: [0; 2],
+            layers: [LayerUniform::default(); 4],
+        };
+        for (uniform, layer) in params.layers.iter_mut().zip(&descriptor.channels) {
+            if let Some(layer) = layer {
+                *uniform = LayerUniform {
+                    kind: match layer.kind {
+                        TilingNoise::Perlin => 0,
+                        TilingNoise::Worley => 1,
+                    },
+                    frequency: layer.frequency.max(1),
+                    octaves: layer.octaves,
+                    seed: layer.seed,
+                    gain: layer.gain,
+                    invert: layer.invert as u32,
+                    enabled: 1,
+                    _padding: 0,
+                };
+            }
+        }
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("noise texture params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let (pipeline, layout, workgroup) = if descriptor.three_d {
+            (&self.pipeline_3d, &self.layout_3d, [4, 4, 4])
+        } else {
+            (&self.pipeline_2d, &self.layout_2d, [8, 8, 1])
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+        });
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some(label) });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch(
+            extent.width.div_ceil(workgroup[0]),
+            extent.height.div_ceil(workgroup[1]),
+            extent.depth_or_array_layers.div_ceil(workgroup[2]),
+        );
+        drop(pass);
+        texture
+    }
+}
@@ -0,0 +1,194 @@
+use crate::postprocess::{fullscreen_module, texture_entry, FullscreenPipeline, RenderTarget};
+
+/// WGSL helpers for transparent shaders drawn into a `WeightedBlendedOit` pass: prepend this
+/// to the shader source and return `oit_output(color, position.z)` from the fragment stage.
+pub const OIT_SHADER: &str = include_str!("shaders/oit.wgsl");
+
+/// How transparent geometry is composited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransparencyMode {
+    /// Sorted back to front and alpha blended. Exact, but sorting is per object, so
+    /// intersecting or heavily overlapping surfaces can pop.
+    Sorted,
+    /// Weighted blended OIT: no sorting and no popping, at the cost of approximate results
+    /// where many layers of similar opacity overlap.
+    WeightedBlended,
+}
+
+/// Accumulation and revealage targets for weighted blended order-independent transparency,
+/// plus the pass that resolves them over the opaque scene. With MSAA the targets are drawn
+/// multisampled, to match the scene's depth, and resolved as the pass ends.
+pub struct WeightedBlendedOit {
+    accum: RenderTarget,
+    revealage: RenderTarget,
+    // drawn into in place of `accum` and `revealage` when the scene is multisampled
+    multisampled: Option<(wgpu::TextureView, wgpu::TextureView)>,
+    sample_count: u32,
+    resolve: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+}
+
+impl WeightedBlendedOit {
+    pub const ACCUM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    pub const REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+    /// `output_format` is the format of the opaque color target the result is resolved into,
+    /// `sample_count` that of the depth the transparent geometry is tested against.
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat, width: u32, height: u32, sample_count: u32) -> Self {
+        let module = fullscreen_module(device, "oit resolve", include_str!("shaders/oit_resolve.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("oit resolve layout"),
+            entries: &[texture_entry(0), texture_entry(1)],
+        });
+        let resolve = FullscreenPipeline::new(
+            device,
+            "oit resolve",
+            &module,
+            "fs_main",
+            &[&layout],
+            output_format,
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+        );
+        let (accum, revealage, multisampled) = Self::create_targets(device, width, height, sample_count);
+
+        WeightedBlendedOit {
+            accum,
+            revealage,
+            multisampled,
+            sample_count,
+            resolve,
+            layout,
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> (RenderTarget, RenderTarget, Option<(wgpu::TextureView, wgpu::TextureView)>) {
+        let multisampled = |label, format| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: width.max(1),
+                        height: height.max(1),
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        (
+            RenderTarget::new(device, "oit accumulation", width, height, Self::ACCUM_FORMAT),
+            RenderTarget::new(device, "oit revealage", width, height, Self::REVEALAGE_FORMAT),
+            (sample_count > 1).then(|| {
+                (
+                    multisampled("oit accumulation multisampled", Self::ACCUM_FORMAT),
+                    multisampled("oit revealage multisampled", Self::REVEALAGE_FORMAT),
+                )
+            }),
+        )
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32, sample_count: u32) {
+        if self.accum.width != width || self.accum.height != height || self.sample_count != sample_count {
+            let (accum, revealage, multisampled) = Self::create_targets(device, width, height, sample_count);
+            self.accum = accum;
+            self.revealage = revealage;
+            self.multisampled = multisampled;
+            self.sample_count = sample_count;
+        }
+    }
+
+    /// Color targets for transparent pipelines drawn into `begin_pass`. Their depth state
+    /// should test against the opaque depth without writing it.
+    pub fn color_targets() -> [wgpu::ColorTargetState; 2] {
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        // revealage is the product of (1 - alpha) over every layer
+        let revealage = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::OneMinusSrc,
+            operation: wgpu::BlendOperation::Add,
+        };
+        [
+            wgpu::ColorTargetState {
+                format: Self::ACCUM_FORMAT,
+                blend: Some(wgpu::BlendState {
+                    color: additive,
+                    alpha: additive,
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            },
+            wgpu::ColorTargetState {
+                format: Self::REVEALAGE_FORMAT,
+                blend: Some(wgpu::BlendState {
+                    color: revealage,
+                    alpha: revealage,
+                }),
+                write_mask: wgpu::ColorWrites::RED,
+            },
+        ]
+    }
+
+    /// Clears the targets and starts the pass transparent geometry is drawn in, depth tested
+    /// against the opaque scene's `depth`, which has the sample count the targets were made for.
+    pub fn begin_pass<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder, depth: &'a wgpu::TextureView) -> wgpu::RenderPass<'a> {
+        let attachment = |target: &'a RenderTarget, multisampled: Option<&'a wgpu::TextureView>, clear| wgpu::RenderPassColorAttachment {
+            view: multisampled.unwrap_or(&target.view),
+            resolve_target: multisampled.map(|_| &target.view),
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear),
+                // the multisampled targets are only needed until they are resolved
+                store: multisampled.is_none(),
+            },
+        };
+        let (accum, revealage) = match &self.multisampled {
+            Some((accum, revealage)) => (Some(accum), Some(revealage)),
+            None => (None, None),
+        };
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("oit accumulation"),
+            color_attachments: &[
+                attachment(&self.accum, accum, wgpu::Color::TRANSPARENT),
+                attachment(&self.revealage, revealage, wgpu::Color::WHITE),
+            ],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        })
+    }
+
+    /// Blends the accumulated transparency over `output`, which holds the opaque scene.
+    pub fn resolve(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("oit resolve bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&self.accum.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.revealage.view),
+                },
+            ],
+        });
+        self.resolve.draw(encoder, output, &[&bind_group], None);
+    }
+}
@@ -0,0 +1,264 @@
+use std::ops::RangeInclusive;
+
+use glam::Vec3;
+
+use super::{Curve, EmitterShape, ParticleEffect, ParticleEmitter};
+
+/// Editable curve plot: drag keys to move them, click empty space to add one and right-click a
+/// key to remove it. Returns true if the curve changed.
+pub fn curve_editor(ui: &mut egui::Ui, id_source: &str, curve: &mut Curve, range: RangeInclusive<f32>) -> bool {
+    const KEY_RADIUS: f32 = 4.0;
+    let (response, painter) = ui.allocate_painter(egui::vec2(ui.available_width(), 80.0), egui::Sense::click_and_drag());
+    let rect = response.rect;
+    let id = ui.make_persistent_id(id_source);
+    let (min, max) = (*range.start(), *range.end());
+    let span = (max - min).max(f32::EPSILON);
+    let to_screen = |(t, v): (f32, f32)| egui::pos2(rect.left() + t * rect.width(), rect.bottom() - (v - min) / span * rect.height());
+    let from_screen = |pos: egui::Pos2| {
+        (
+            ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0),
+            (min + (rect.bottom() - pos.y) / rect.height() * span).clamp(min, max),
+        )
+    };
+    let nearest = |curve: &Curve, pos: egui::Pos2| {
+        curve
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(index, &key)| (index, to_screen(key).distance(pos)))
+            .filter(|&(_, distance)| distance <= KEY_RADIUS * 2.0)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    };
+
+    let mut changed = false;
+    let pointer = response.interact_pointer_pos();
+    if response.drag_started() {
+        if let Some(index) = pointer.and_then(|pos| nearest(curve, pos)) {
+            ui.memory().data.insert_temp(id, index);
+        }
+    }
+    let dragged: Option<usize> = ui.memory().data.get_temp(id);
+    if let (true, Some(index), Some(pos)) = (response.dragged(), dragged, pointer) {
+        let (t, v) = from_screen(pos);
+        // neighbours bound the key so the curve stays sorted while dragging
+        let lower = index.checked_sub(1).map_or(0.0, |i| curve.keys[i].0);
+        let upper = curve.keys.get(index + 1).map_or(1.0, |key| key.0);
+        curve.keys[index] = (t.clamp(lower, upper), v);
+        changed = true;
+    }
+    if response.drag_released() {
+        ui.memory().data.remove::<usize>(id);
+    }
+    if response.clicked() {
+        if let Some(pos) = pointer.filter(|&pos| nearest(curve, pos).is_none()) {
+            let (t, v) = from_screen(pos);
+            curve.insert(t, v);
+            changed = true;
+        }
+    }
+    if response.secondary_clicked() && curve.keys.len() > 1 {
+        if let Some(index) = pointer.and_then(|pos| nearest(curve, pos)) {
+            curve.keys.remove(index);
+            changed = true;
+        }
+    }
+
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+    painter.rect_stroke(rect, 2.0, visuals.widgets.noninteractive.bg_stroke);
+    let samples = (0..=64).map(|i| i as f32 / 64.0).map(|t| to_screen((t, curve.evaluate(t)))).collect();
+    painter.add(egui::Shape::line(samples, egui::Stroke::new(1.5, visuals.selection.bg_fill)));
+    for &key in &curve.keys {
+        painter.circle_filled(to_screen(key), KEY_RADIUS, visuals.strong_text_color());
+    }
+    painter.text(rect.left_top() + egui::vec2(4.0, 2.0), egui::Align2::LEFT_TOP, format!("{}", max), egui::TextStyle::Small, visuals.text_color());
+    painter.text(rect.left_bottom() + egui::vec2(4.0, -2.0), egui::Align2::LEFT_BOTTOM, format!("{}", min), egui::TextStyle::Small, visuals.text_color());
+    changed
+}
+
+fn vector(ui: &mut egui::Ui, label: &str, vector: &mut Vec3, speed: f64, range: RangeInclusive<f32>) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut values = vector.to_array();
+        for (value, axis) in values.iter_mut().zip(["X ", "Y ", "Z "]) {
+            ui.add(egui::DragValue::new(value).speed(speed).clamp_range(range.clone()).prefix(axis));
+        }
+        *vector = Vec3::from(values);
+    });
+}
+
+/// Window for authoring particle effects. It owns a preview emitter the application updates
+/// with `update` and draws in the viewport through `preview().write_sprites`, so edits show
+/// up live.
+pub struct ParticleEditor {
+    pub open: bool,
+    pub paused: bool,
+    path: String,
+    status: Option<Result<String, String>>,
+    preview: ParticleEmitter,
+}
+
+impl ParticleEditor {
+    pub fn new(effect: ParticleEffect, preview_position: Vec3) -> Self {
+        ParticleEditor {
+            open: true,
+            paused: false,
+            path: "effects/new.particles".to_owned(),
+            status: None,
+            preview: ParticleEmitter::new(effect, preview_position),
+        }
+    }
+
+    pub fn effect(&self) -> &ParticleEffect {
+        &self.preview.effect
+    }
+
+    pub fn preview(&self) -> &ParticleEmitter {
+        &self.preview
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if !self.paused {
+            self.preview.update(dt);
+            // keep a one-shot effect visible while it's being edited
+            if self.preview.is_finished() {
+                self.preview.restart();
+            }
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::CtxRef) {
+        let mut open = self.open;
+        egui::Window::new("Particle editor").open(&mut open).show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| self.ui(ui));
+        });
+        self.open = open;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("File");
+            ui.text_edit_singleline(&mut self.path);
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Load").clicked() {
+                self.status = Some(match ParticleEffect::load(&self.path) {
+                    Ok(effect) => {
+                        self.preview.effect = effect;
+                        self.preview.restart();
+                        Ok(format!("Loaded {}", self.path))
+                    }
+                    Err(e) => Err(format!("Failed to load {}: {}", self.path, e)),
+                });
+            }
+            if ui.button("Save").clicked() {
+                self.status = Some(match self.preview.effect.save(&self.path) {
+                    Ok(()) => Ok(format!("Saved {}", self.path)),
+                    Err(e) => Err(format!("Failed to save {}: {}", self.path, e)),
+                });
+            }
+            if ui.button("Restart").clicked() {
+                self.preview.restart();
+            }
+            ui.checkbox(&mut self.paused, "Paused");
+        });
+        match &self.status {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(message)) => {
+                ui.colored_label(egui::Color32::RED, message);
+            }
+            None => {}
+        }
+        ui.label(format!("{} particles", self.preview.len()));
+        ui.separator();
+
+        let effect = &mut self.preview.effect;
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut effect.name);
+        });
+        let range = |ui: &mut egui::Ui, label: &str, values: &mut [f32; 2], speed: f64| {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                let [min, max] = values;
+                ui.add(egui::DragValue::new(min).speed(speed).clamp_range(0.0..=*max).prefix("min "));
+                ui.add(egui::DragValue::new(max).speed(speed).clamp_range(*min..=f32::MAX).prefix("max "));
+            });
+        };
+
+        egui::CollapsingHeader::new("Emission").default_open(true).show(ui, |ui| {
+            ui.add(egui::Slider::new(&mut effect.rate, 0.0..=1000.0).text("Rate"));
+            ui.add(egui::Slider::new(&mut effect.burst, 0..=1000).text("Burst"));
+            ui.add(egui::Slider::new(&mut effect.max_particles, 1..=10000).text("Max particles"));
+            ui.add(egui::Slider::new(&mut effect.duration, 0.1..=30.0).text("Duration"));
+            ui.checkbox(&mut effect.looping, "Looping");
+        });
+
+        egui::CollapsingHeader::new("Shape").default_open(true).show(ui, |ui| {
+            let name = match effect.shape {
+                EmitterShape::Point => "Point",
+                EmitterShape::Sphere { .. } => "Sphere",
+                EmitterShape::Cone { .. } => "Cone",
+                EmitterShape::Box { .. } => "Box",
+            };
+            egui::ComboBox::from_id_source("particle shape").selected_text(name).show_ui(ui, |ui| {
+                let shapes = [
+                    ("Point", EmitterShape::Point),
+                    ("Sphere", EmitterShape::Sphere { radius: 0.5 }),
+                    (
+                        "Cone",
+                        EmitterShape::Cone {
+                            angle: 25f32.to_radians(),
+                            radius: 0.1,
+                        },
+                    ),
+                    ("Box", EmitterShape::Box { half_extents: Vec3::splat(0.5) }),
+                ];
+                for (label, shape) in shapes {
+                    if ui.selectable_label(name == label, label).clicked() && name != label {
+                        effect.shape = shape;
+                    }
+                }
+            });
+            match &mut effect.shape {
+                EmitterShape::Point => {}
+                EmitterShape::Sphere { radius } => {
+                    ui.add(egui::Slider::new(radius, 0.0..=10.0).text("Radius"));
+                }
+                EmitterShape::Cone { angle, radius } => {
+                    ui.add(egui::Slider::new(angle, 0.0..=std::f32::consts::PI).text("Angle"));
+                    ui.add(egui::Slider::new(radius, 0.0..=10.0).text("Radius"));
+                }
+                EmitterShape::Box { half_extents } => {
+                    vector(ui, "Half extents", half_extents, 0.01, 0.0..=100.0);
+                }
+            }
+        });
+
+        egui::CollapsingHeader::new("Particles").default_open(true).show(ui, |ui| {
+            range(ui, "Lifetime", &mut effect.lifetime, 0.01);
+            range(ui, "Speed", &mut effect.speed, 0.05);
+            range(ui, "Size", &mut effect.size, 0.005);
+            vector(ui, "Gravity", &mut effect.gravity, 0.05, -100.0..=100.0);
+            ui.add(egui::Slider::new(&mut effect.drag, 0.0..=10.0).text("Drag"));
+            ui.horizontal(|ui| {
+                ui.label("Start color");
+                ui.color_edit_button_rgba_unmultiplied(&mut effect.start_color);
+                ui.label("End color");
+                ui.color_edit_button_rgba_unmultiplied(&mut effect.end_color);
+            });
+        });
+
+        egui::CollapsingHeader::new("Over lifetime").default_open(true).show(ui, |ui| {
+            ui.label("Size");
+            curve_editor(ui, "size over life", &mut effect.size_over_life, 0.0..=4.0);
+            ui.label("Alpha");
+            curve_editor(ui, "alpha over life", &mut effect.alpha_over_life, 0.0..=1.0);
+            ui.label("Speed");
+            curve_editor(ui, "speed over life", &mut effect.speed_over_life, 0.0..=4.0);
+        });
+    }
+}
@@ -0,0 +1,459 @@
+#[cfg(feature = "egui")]
+pub mod editor;
+
+use std::{fmt, path::Path};
+
+use glam::{Vec2, Vec3};
+
+use crate::{
+    load_profile::{LoadStage, LoadTimer},
+    random::Rng,
+    sprite::{
+        batch::{SpriteBatch, SpriteSpace},
+        UvRect,
+    },
+};
+
+#[derive(Debug)]
+pub enum ParticleEffectError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ParticleEffectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParticleEffectError::Io(e) => write!(f, "{}", e),
+            ParticleEffectError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ParticleEffectError {}
+
+/// A piecewise linear curve over a particle's normalized age, 0 at birth and 1 at death.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve {
+    /// `(time, value)` keys sorted by time.
+    pub keys: Vec<(f32, f32)>,
+}
+
+impl Curve {
+    pub fn constant(value: f32) -> Self {
+        Curve {
+            keys: vec![(0.0, value), (1.0, value)],
+        }
+    }
+
+    pub fn linear(from: f32, to: f32) -> Self {
+        Curve {
+            keys: vec![(0.0, from), (1.0, to)],
+        }
+    }
+
+    pub fn evaluate(&self, t: f32) -> f32 {
+        let next = self.keys.partition_point(|&(time, _)| time <= t);
+        match (next.checked_sub(1).map(|i| self.keys[i]), self.keys.get(next)) {
+            (Some((t0, v0)), Some(&(t1, v1))) if t1 > t0 => v0 + (v1 - v0) * (t - t0) / (t1 - t0),
+            (Some((_, value)), _) | (None, Some(&(_, value))) => value,
+            (None, None) => 0.0,
+        }
+    }
+
+    /// Inserts a key, keeping the keys sorted, and returns its index.
+    pub fn insert(&mut self, time: f32, value: f32) -> usize {
+        let index = self.keys.partition_point(|&(t, _)| t <= time);
+        self.keys.insert(index, (time, value));
+        index
+    }
+
+    /// Restores the time order after keys were moved.
+    pub fn sort(&mut self) {
+        self.keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmitterShape {
+    Point,
+    /// Spawns inside the sphere, moving outwards.
+    Sphere { radius: f32 },
+    /// Spawns on a disc of `radius`, moving up within `angle` radians of +Y.
+    Cone { angle: f32, radius: f32 },
+    /// Spawns inside the box, moving up.
+    Box { half_extents: Vec3 },
+}
+
+/// Everything that defines how an effect looks, saved as a `.particles` text file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParticleEffect {
+    pub name: String,
+    pub max_particles: u32,
+    /// Particles spawned per second.
+    pub rate: f32,
+    /// Particles spawned at once whenever emission (re)starts.
+    pub burst: u32,
+    /// Emission length in seconds before looping or stopping.
+    pub duration: f32,
+    pub looping: bool,
+    pub shape: EmitterShape,
+    /// Ranges are `[min, max]`, picked at random per particle.
+    pub lifetime: [f32; 2],
+    pub speed: [f32; 2],
+    pub size: [f32; 2],
+    pub gravity: Vec3,
+    /// Fraction of velocity lost per second.
+    pub drag: f32,
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    /// Multiplies the particle's start size.
+    pub size_over_life: Curve,
+    /// Multiplies the color's alpha.
+    pub alpha_over_life: Curve,
+    /// Multiplies the particle's speed.
+    pub speed_over_life: Curve,
+}
+
+impl Default for ParticleEffect {
+    fn default() -> Self {
+        ParticleEffect {
+            name: "New effect".to_owned(),
+            max_particles: 500,
+            rate: 50.0,
+            burst: 0,
+            duration: 2.0,
+            looping: true,
+            shape: EmitterShape::Cone {
+                angle: 25f32.to_radians(),
+                radius: 0.1,
+            },
+            lifetime: [1.0, 1.5],
+            speed: [1.0, 2.0],
+            size: [0.1, 0.2],
+            gravity: Vec3::ZERO,
+            drag: 0.0,
+            start_color: [1.0, 0.8, 0.4, 1.0],
+            end_color: [1.0, 0.2, 0.1, 1.0],
+            size_over_life: Curve::constant(1.0),
+            alpha_over_life: Curve::linear(1.0, 0.0),
+            speed_over_life: Curve::constant(1.0),
+        }
+    }
+}
+
+impl ParticleEffect {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ParticleEffectError> {
+        let path = path.as_ref();
+        let mut timer = LoadTimer::for_path("particle effect", path);
+        let text = std::fs::read_to_string(path).map_err(ParticleEffectError::Io)?;
+        timer.add_file_bytes(text.len());
+        timer.end(LoadStage::Read);
+        let effect = Self::parse(&text)?;
+        timer.end(LoadStage::Decode);
+        timer.finish();
+        Ok(effect)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ParticleEffectError> {
+        std::fs::write(path, self.to_text()).map_err(ParticleEffectError::Io)
+    }
+
+    /// One `key values...` line per property, `#` comments allowed. Keys a file leaves out keep
+    /// their default, so older files still load after new properties are added.
+    pub fn parse(text: &str) -> Result<Self, ParticleEffectError> {
+        let mut effect = ParticleEffect::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let error = |message: String| ParticleEffectError::Parse { line: index + 1, message };
+            let numbers = || -> Result<Vec<f32>, ParticleEffectError> {
+                rest.split_whitespace()
+                    .map(|word| word.parse().map_err(|_| error(format!("'{}' is not a number", word))))
+                    .collect()
+            };
+            let exactly = |count: usize| -> Result<Vec<f32>, ParticleEffectError> {
+                let values = numbers()?;
+                if values.len() == count {
+                    Ok(values)
+                } else {
+                    Err(error(format!("{} takes {} numbers, found {}", key, count, values.len())))
+                }
+            };
+            let curve = || -> Result<Curve, ParticleEffectError> {
+                let values = numbers()?;
+                if values.is_empty() || values.len() % 2 != 0 {
+                    return Err(error(format!("{} takes time value pairs", key)));
+                }
+                let mut curve = Curve {
+                    keys: values.chunks(2).map(|pair| (pair[0], pair[1])).collect(),
+                };
+                curve.sort();
+                Ok(curve)
+            };
+
+            match key {
+                "name" => effect.name = rest.trim().to_owned(),
+                "max_particles" => effect.max_particles = exactly(1)?[0].max(0.0) as u32,
+                "rate" => effect.rate = exactly(1)?[0],
+                "burst" => effect.burst = exactly(1)?[0].max(0.0) as u32,
+                "duration" => effect.duration = exactly(1)?[0],
+                "looping" => {
+                    effect.looping = match rest.trim() {
+                        "true" => true,
+                        "false" => false,
+                        other => return Err(error(format!("looping is true or false, found '{}'", other))),
+                    }
+                }
+                "shape" => {
+                    let mut words = rest.split_whitespace();
+                    let kind = words.next().unwrap_or("");
+                    let values: Vec<f32> = words
+                        .map(|word| word.parse().map_err(|_| error(format!("'{}' is not a number", word))))
+                        .collect::<Result<_, _>>()?;
+                    effect.shape = match (kind, values.as_slice()) {
+                        ("point", []) => EmitterShape::Point,
+                        ("sphere", [radius]) => EmitterShape::Sphere { radius: *radius },
+                        ("cone", [angle, radius]) => EmitterShape::Cone {
+                            angle: angle.to_radians(),
+                            radius: *radius,
+                        },
+                        ("box", [x, y, z]) => EmitterShape::Box {
+                            half_extents: Vec3::new(*x, *y, *z),
+                        },
+                        _ => return Err(error(format!("unknown shape '{}'", rest.trim()))),
+                    };
+                }
+                "lifetime" => effect.lifetime = range(&exactly(2)?),
+                "speed" => effect.speed = range(&exactly(2)?),
+                "size" => effect.size = range(&exactly(2)?),
+                "gravity" => effect.gravity = Vec3::from_slice(&exactly(3)?),
+                "drag" => effect.drag = exactly(1)?[0],
+                "start_color" => effect.start_color = color(&exactly(4)?),
+                "end_color" => effect.end_color = color(&exactly(4)?),
+                "size_over_life" => effect.size_over_life = curve()?,
+                "alpha_over_life" => effect.alpha_over_life = curve()?,
+                "speed_over_life" => effect.speed_over_life = curve()?,
+                _ => return Err(error(format!("unknown property '{}'", key))),
+            }
+        }
+        Ok(effect)
+    }
+
+    pub fn to_text(&self) -> String {
+        let list = |values: &[f32]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(" ");
+        let curve = |curve: &Curve| list(&curve.keys.iter().flat_map(|&(t, v)| [t, v]).collect::<Vec<_>>());
+        let shape = match self.shape {
+            EmitterShape::Point => "point".to_owned(),
+            EmitterShape::Sphere { radius } => format!("sphere {}", radius),
+            EmitterShape::Cone { angle, radius } => format!("cone {} {}", angle.to_degrees(), radius),
+            EmitterShape::Box { half_extents } => format!("box {}", list(&half_extents.to_array())),
+        };
+        [
+            "# particle effect".to_owned(),
+            format!("name {}", self.name),
+            format!("max_particles {}", self.max_particles),
+            format!("rate {}", self.rate),
+            format!("burst {}", self.burst),
+            format!("duration {}", self.duration),
+            format!("looping {}", self.looping),
+            format!("shape {}", shape),
+            format!("lifetime {}", list(&self.lifetime)),
+            format!("speed {}", list(&self.speed)),
+            format!("size {}", list(&self.size)),
+            format!("gravity {}", list(&self.gravity.to_array())),
+            format!("drag {}", self.drag),
+            format!("start_color {}", list(&self.start_color)),
+            format!("end_color {}", list(&self.end_color)),
+            format!("size_over_life {}", curve(&self.size_over_life)),
+            format!("alpha_over_life {}", curve(&self.alpha_over_life)),
+            format!("speed_over_life {}", curve(&self.speed_over_life)),
+        ]
+        .join("\n")
+            + "\n"
+    }
+}
+
+fn range(values: &[f32]) -> [f32; 2] {
+    [values[0].min(values[1]), values[0].max(values[1])]
+}
+
+fn color(values: &[f32]) -> [f32; 4] {
+    [values[0], values[1], values[2], values[3]]
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    position: Vec3,
+    velocity: Vec3,
+    age: f32,
+    lifetime: f32,
+    size: f32,
+}
+
+/// Simulates one instance of an effect on the CPU.
+#[derive(Clone, Debug)]
+pub struct ParticleEmitter {
+    pub effect: ParticleEffect,
+    pub position: Vec3,
+    particles: Vec<Particle>,
+    time: f32,
+    spawn_debt: f32,
+    emitting: bool,
+    burst_pending: bool,
+    rng: Rng,
+}
+
+impl ParticleEmitter {
+    pub fn new(effect: ParticleEffect, position: Vec3) -> Self {
+        ParticleEmitter {
+            effect,
+            position,
+            particles: Vec::new(),
+            time: 0.0,
+            spawn_debt: 0.0,
+            emitting: true,
+            burst_pending: true,
+            rng: Rng::new(0x2545_f491),
+        }
+    }
+
+    /// Draws the emitter's randomness from `rng`, e.g. one forked from the `Random` resource so
+    /// the effect plays out the same in a replay.
+    pub fn with_rng(mut self, rng: Rng) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Clears every particle and starts emitting from the beginning.
+    pub fn restart(&mut self) {
+        self.particles.clear();
+        self.time = 0.0;
+        self.spawn_debt = 0.0;
+        self.emitting = true;
+        self.burst_pending = true;
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// True once a non-looping effect has stopped emitting and its last particle died.
+    pub fn is_finished(&self) -> bool {
+        !self.emitting && self.particles.is_empty()
+    }
+
+    fn random(&mut self) -> f32 {
+        self.rng.next_f32()
+    }
+
+    fn random_range(&mut self, [min, max]: [f32; 2]) -> f32 {
+        min + (max - min) * self.random()
+    }
+
+    fn random_direction(&mut self) -> Vec3 {
+        let z = self.random() * 2.0 - 1.0;
+        let angle = self.random() * std::f32::consts::TAU;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        Vec3::new(r * angle.cos(), z, r * angle.sin())
+    }
+
+    fn spawn(&mut self) {
+        if self.particles.len() >= self.effect.max_particles as usize {
+            return;
+        }
+        let (offset, direction) = match self.effect.shape {
+            EmitterShape::Point => (Vec3::ZERO, self.random_direction()),
+            EmitterShape::Sphere { radius } => {
+                let direction = self.random_direction();
+                (direction * radius * self.random().cbrt(), direction)
+            }
+            EmitterShape::Cone { angle, radius } => {
+                let around = self.random() * std::f32::consts::TAU;
+                let distance = radius * self.random().sqrt();
+                let tilt = angle * self.random().sqrt();
+                (
+                    Vec3::new(around.cos(), 0.0, around.sin()) * distance,
+                    Vec3::new(tilt.sin() * around.cos(), tilt.cos(), tilt.sin() * around.sin()),
+                )
+            }
+            EmitterShape::Box { half_extents } => {
+                let point = Vec3::new(self.random(), self.random(), self.random()) * 2.0 - Vec3::ONE;
+                (point * half_extents, Vec3::Y)
+            }
+        };
+        let particle = Particle {
+            position: self.position + offset,
+            velocity: direction * self.random_range(self.effect.speed),
+            age: 0.0,
+            lifetime: self.random_range(self.effect.lifetime).max(0.001),
+            size: self.random_range(self.effect.size),
+        };
+        self.particles.push(particle);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let effect = &self.effect;
+        let drag = (1.0 - effect.drag * dt).max(0.0);
+        self.particles.retain_mut(|particle| {
+            particle.age += dt;
+            if particle.age >= particle.lifetime {
+                return false;
+            }
+            particle.velocity = particle.velocity * drag + effect.gravity * dt;
+            let speed = effect.speed_over_life.evaluate(particle.age / particle.lifetime);
+            particle.position += particle.velocity * speed * dt;
+            true
+        });
+
+        if !self.emitting {
+            return;
+        }
+        if self.burst_pending {
+            self.burst_pending = false;
+            for _ in 0..self.effect.burst {
+                self.spawn();
+            }
+        }
+        self.spawn_debt += self.effect.rate.max(0.0) * dt;
+        while self.spawn_debt >= 1.0 {
+            self.spawn_debt -= 1.0;
+            self.spawn();
+        }
+        self.time += dt;
+        if self.time >= self.effect.duration {
+            if self.effect.looping {
+                self.time -= self.effect.duration.max(f32::EPSILON);
+                self.burst_pending = true;
+            } else {
+                self.emitting = false;
+            }
+        }
+    }
+
+    /// Adds every live particle to `batch` as a camera-facing sprite using `uv`.
+    pub fn write_sprites(&self, batch: &mut SpriteBatch, uv: UvRect) {
+        self.write_sprites_scaled(batch, uv, Vec2::ONE);
+    }
+
+    /// Like `write_sprites`, with sprite width and height multiplied by `scale`, e.g. to draw
+    /// rain as streaks.
+    pub fn write_sprites_scaled(&self, batch: &mut SpriteBatch, uv: UvRect, scale: Vec2) {
+        for particle in &self.particles {
+            let t = particle.age / particle.lifetime;
+            let mut color = [0.0; 4];
+            for (channel, (start, end)) in color.iter_mut().zip(self.effect.start_color.iter().zip(&self.effect.end_color)) {
+                *channel = start + (end - start) * t;
+            }
+            color[3] *= self.effect.alpha_over_life.evaluate(t).clamp(0.0, 1.0);
+            let size = particle.size * self.effect.size_over_life.evaluate(t).max(0.0);
+            batch.sprite(SpriteSpace::World, particle.position, Vec2::splat(size) * scale, uv, color);
+        }
+    }
+}
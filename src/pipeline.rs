@@ -0,0 +1,119 @@
+use std::sync::{mpsc, Arc};
+
+/// Builds pipelines on background threads so creating a new material or shader variant never
+/// stalls the frame that asked for it.
+///
+/// wgpu error scopes are per device rather than per thread, so background compiles are not
+/// wrapped in `gpu_error::capture`; validation errors reach the uncaptured error handler, which
+/// includes the pipeline label in its message.
+#[derive(Clone)]
+pub struct PipelineCompiler {
+    device: Arc<wgpu::Device>,
+}
+
+impl PipelineCompiler {
+    pub fn new(device: Arc<wgpu::Device>) -> Self {
+        PipelineCompiler { device }
+    }
+
+    /// Runs `build` on a worker thread. The returned handle yields nothing until it finishes.
+    pub fn compile<P, F>(&self, label: impl Into<String>, build: F) -> AsyncPipeline<P>
+    where
+        P: Send + 'static,
+        F: FnOnce(&wgpu::Device) -> P + Send + 'static,
+    {
+        let label = label.into();
+        let (sender, receiver) = mpsc::channel();
+        let device = self.device.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("pipeline: {}", label))
+            .spawn(move || {
+                sender.send(build(&device)).ok();
+            });
+
+        let state = match spawned {
+            Ok(_) => State::Compiling(receiver),
+            Err(e) => {
+                log::error!("Failed to spawn compile thread for pipeline \"{}\": {}", label, e);
+                State::Failed
+            }
+        };
+        AsyncPipeline { label, state }
+    }
+
+    pub fn compile_render<F>(&self, label: impl Into<String>, build: F) -> AsyncPipeline<wgpu::RenderPipeline>
+    where
+        F: FnOnce(&wgpu::Device) -> wgpu::RenderPipeline + Send + 'static,
+    {
+        self.compile(label, build)
+    }
+
+    pub fn compile_compute<F>(&self, label: impl Into<String>, build: F) -> AsyncPipeline<wgpu::ComputePipeline>
+    where
+        F: FnOnce(&wgpu::Device) -> wgpu::ComputePipeline + Send + 'static,
+    {
+        self.compile(label, build)
+    }
+}
+
+enum State<P> {
+    Compiling(mpsc::Receiver<P>),
+    Ready(P),
+    Failed,
+}
+
+/// A pipeline that may still be compiling. Draw code either skips the draw while `get` returns
+/// `None`, or renders with a cheaper placeholder through `get_or`.
+pub struct AsyncPipeline<P> {
+    label: String,
+    state: State<P>,
+}
+
+impl<P> AsyncPipeline<P> {
+    /// Wraps an already built pipeline, for code paths that compile synchronously.
+    pub fn ready(label: impl Into<String>, pipeline: P) -> Self {
+        AsyncPipeline {
+            label: label.into(),
+            state: State::Ready(pipeline),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    fn poll(&mut self) {
+        if let State::Compiling(receiver) = &self.state {
+            match receiver.try_recv() {
+                Ok(pipeline) => self.state = State::Ready(pipeline),
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    // the builder panicked; the panic message was already printed by the thread
+                    log::error!("Compiling pipeline \"{}\" failed", self.label);
+                    self.state = State::Failed;
+                }
+            }
+        }
+    }
+
+    pub fn get(&mut self) -> Option<&P> {
+        self.poll();
+        match &self.state {
+            State::Ready(pipeline) => Some(pipeline),
+            _ => None,
+        }
+    }
+
+    pub fn get_or<'a>(&'a mut self, fallback: &'a P) -> &'a P {
+        self.get().unwrap_or(fallback)
+    }
+
+    pub fn is_ready(&mut self) -> bool {
+        self.get().is_some()
+    }
+
+    pub fn has_failed(&mut self) -> bool {
+        self.poll();
+        matches!(self.state, State::Failed)
+    }
+}
@@ -0,0 +1,314 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::{
+    camera::Camera,
+    postprocess::{linear_sampler, sampler_entry, texture_entry},
+};
+
+/// WGSL for mirror and water materials: declares the reflection at bindings 11 to 13 of
+/// `PLANAR_REFLECTION_GROUP`, after the light profiles, with `planar_reflection(position,
+/// distortion)` and `planar_reflection_color(position, distortion)`. The PBR shader includes it
+/// for materials with `PbrMaterial::planar_reflection`.
+pub const PLANAR_REFLECTION_SHADER: &str = include_str!("shaders/planar_reflection.wgsl");
+
+/// Bind group index `PLANAR_REFLECTION_SHADER` declares its bindings at, the lighting group's.
+pub const PLANAR_REFLECTION_GROUP: u32 = 2;
+
+/// Points with `normal.dot(p) + distance == 0`; the normal points to the reflected side.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        let normal = normal.normalize_or_zero();
+        Plane {
+            normal,
+            distance: -normal.dot(point),
+        }
+    }
+
+    /// Water at `height`, reflecting what is above it.
+    pub fn horizontal(height: f32) -> Self {
+        Plane::new(Vec3::new(0.0, height, 0.0), Vec3::Y)
+    }
+
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+
+    pub fn to_vec4(self) -> Vec4 {
+        self.normal.extend(self.distance)
+    }
+
+    /// Mirrors world space about the plane.
+    pub fn reflection(&self) -> Mat4 {
+        let [x, y, z] = self.normal.to_array();
+        let d = self.distance;
+        Mat4::from_cols(
+            Vec4::new(1.0 - 2.0 * x * x, -2.0 * x * y, -2.0 * x * z, 0.0),
+            Vec4::new(-2.0 * x * y, 1.0 - 2.0 * y * y, -2.0 * y * z, 0.0),
+            Vec4::new(-2.0 * x * z, -2.0 * y * z, 1.0 - 2.0 * z * z, 0.0),
+            Vec4::new(-2.0 * x * d, -2.0 * y * d, -2.0 * z * d, 1.0),
+        )
+    }
+}
+
+/// Replaces the near plane of `projection` with `clip_plane`, given in view space, so nothing
+/// behind it is drawn without needing clip distances in every shader (Lengyel's oblique frustum,
+/// for depth in 0 to 1). The plane has to face away from the eye.
+pub fn oblique_projection(projection: Mat4, clip_plane: Vec4) -> Mat4 {
+    // the frustum corner opposite the new near plane, which should stay on the far plane
+    let corner = projection.inverse() * Vec4::new(clip_plane.x.signum(), clip_plane.y.signum(), 1.0, 1.0);
+    let mut rows = projection.transpose();
+    rows.z_axis = clip_plane / clip_plane.dot(corner);
+    rows.transpose()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PlanarReflectionSettings {
+    /// Size of the reflection texture relative to the camera's viewport; water that distorts
+    /// the reflection gets away with a quarter.
+    pub resolution_scale: f32,
+    /// Lowers the clip plane so geometry touching the surface doesn't show a seam.
+    pub clip_offset: f32,
+}
+
+impl Default for PlanarReflectionSettings {
+    fn default() -> Self {
+        PlanarReflectionSettings {
+            resolution_scale: 0.5,
+            clip_offset: 0.02,
+        }
+    }
+}
+
+/// The mirrored view to render the scene from into `color` and `depth`. The view is flipped
+/// horizontally as well, which undoes the mirror's flip of the triangle winding, so the usual
+/// back face culling works.
+pub struct ReflectionTarget<'a> {
+    pub color: &'a wgpu::TextureView,
+    pub depth: &'a wgpu::TextureView,
+    pub size: (u32, u32),
+    /// Already clips everything behind the plane.
+    pub view_projection: Mat4,
+    pub position: Vec3,
+    /// The plane in world space, for shaders that also want to discard against it.
+    pub clip_plane: Vec4,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    view_projection: [[f32; 4]; 4],
+    plane: [f32; 4],
+}
+
+struct Targets {
+    size: (u32, u32),
+    color: wgpu::TextureView,
+    depth: wgpu::TextureView,
+}
+
+/// One reflecting plane's mirrored camera and the texture it renders into, for mirrors and
+/// water. Each plane costs an extra scene render, so keep them few.
+pub struct PlanarReflection {
+    pub plane: Plane,
+    pub settings: PlanarReflectionSettings,
+    targets: Targets,
+    uniform: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    bind_group: wgpu::BindGroup,
+}
+
+impl PlanarReflection {
+    /// Format of the reflection color attachment.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    pub fn new(device: &wgpu::Device, plane: Plane) -> Self {
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("planar reflection params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("planar reflection layout"),
+            entries: &Self::layout_entries(),
+        });
+        let sampler = linear_sampler(device);
+        let targets = Self::create_targets(device, (1, 1));
+        let bind_group = Self::create_bind_group(device, &layout, &uniform, &targets, &sampler);
+
+        PlanarReflection {
+            plane,
+            settings: PlanarReflectionSettings::default(),
+            targets,
+            uniform,
+            layout,
+            sampler,
+            bind_group,
+        }
+    }
+
+    fn create_targets(device: &wgpu::Device, (width, height): (u32, u32)) -> Targets {
+        let create = |label, format| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        Targets {
+            size: (width, height),
+            color: create("planar reflection", Self::FORMAT),
+            depth: create("planar reflection depth", Self::DEPTH_FORMAT),
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform: &wgpu::Buffer,
+        targets: &Targets,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("planar reflection bind group"),
+            layout,
+            entries: &Self::entries(uniform, targets, sampler),
+        })
+    }
+
+    fn entries<'a>(uniform: &'a wgpu::Buffer, targets: &'a Targets, sampler: &'a wgpu::Sampler) -> [wgpu::BindGroupEntry<'a>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 12,
+                resource: wgpu::BindingResource::TextureView(&targets.color),
+            },
+            wgpu::BindGroupEntry {
+                binding: 13,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ]
+    }
+
+    /// The entries `PLANAR_REFLECTION_SHADER` expects, for building a combined lighting layout.
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 11,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            texture_entry(12),
+            sampler_entry(13),
+        ]
+    }
+
+    /// The resources of `layout_entries`, for a combined lighting bind group. They change when
+    /// `render` resizes the reflection.
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 3] {
+        Self::entries(&self.uniform, &self.targets, &self.sampler)
+    }
+
+    /// For mirror and water pipelines with these bindings alone, at `PLANAR_REFLECTION_GROUP`.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// Changes when the reflection texture is resized, so fetch it after `render`.
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn texture(&self) -> &wgpu::TextureView {
+        &self.targets.color
+    }
+
+    /// View-projection and position of `camera` mirrored about the plane, with the near plane
+    /// replaced by the reflecting plane and x flipped back so triangles keep their winding.
+    pub fn reflected_view(&self, camera: &Camera, target_size: (u32, u32)) -> (Mat4, Vec3) {
+        let (_, _, width, height) = camera.viewport.pixels(target_size);
+        let reflection = self.plane.reflection();
+        let view = camera.view() * reflection;
+        let clip_plane = Plane {
+            distance: self.plane.distance + self.settings.clip_offset,
+            ..self.plane
+        };
+        // planes transform by the inverse transpose
+        let view_plane = view.inverse().transpose() * clip_plane.to_vec4();
+        let projection = oblique_projection(camera.projection.matrix(width as f32 / height.max(1) as f32), view_plane);
+        let flip = Mat4::from_scale(Vec3::new(-1.0, 1.0, 1.0));
+        (flip * projection * view, reflection.transform_point3(camera.position))
+    }
+
+    /// Renders the reflection for `camera`: `render` draws the scene from the mirrored view into
+    /// the target it's given. Skipped, leaving the last reflection, when the camera is behind
+    /// the plane and can't see its reflecting side.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        target_size: (u32, u32),
+        render: impl FnOnce(&mut wgpu::CommandEncoder, ReflectionTarget),
+    ) {
+        if self.plane.signed_distance(camera.position) <= 0.0 {
+            return;
+        }
+        let (_, _, width, height) = camera.viewport.pixels(target_size);
+        let scale = self.settings.resolution_scale.clamp(0.05, 1.0);
+        let size = (
+            ((width as f32 * scale) as u32).max(1),
+            ((height as f32 * scale) as u32).max(1),
+        );
+        if self.targets.size != size {
+            self.targets = Self::create_targets(device, size);
+            self.bind_group = Self::create_bind_group(device, &self.layout, &self.uniform, &self.targets, &self.sampler);
+        }
+
+        let (view_projection, position) = self.reflected_view(camera, target_size);
+        let params = Params {
+            view_projection: view_projection.to_cols_array_2d(),
+            plane: self.plane.to_vec4().into(),
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&params));
+
+        render(
+            encoder,
+            ReflectionTarget {
+                color: &self.targets.color,
+                depth: &self.targets.depth,
+                size,
+                view_projection,
+                position,
+                clip_plane: self.plane.to_vec4(),
+            },
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use std::num::NonZeroU64;
+
+use wgpu::util::DeviceExt;
+
+use crate::readback::Readbacks;
+
+const BIN_COUNT: u64 = 256;
+const WORKGROUP_SIZE: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExposureMode {
+    /// Meters the scene every frame and adapts towards it.
+    Auto,
+    /// Fixed exposure value at ISO 100; higher is darker.
+    Manual { ev: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AutoExposureSettings {
+    /// Whether the engine meters the scene and hands the exposure to the tonemapper. Off by
+    /// default: scenes lit in arbitrary units rather than cd/m² need `compensation` tuned first.
+    pub enabled: bool,
+    pub mode: ExposureMode,
+    /// Darkest scene the exposure will adapt to, as EV100. Also bounds the metering histogram.
+    pub min_ev: f32,
+    /// Brightest scene the exposure will adapt to, as EV100.
+    pub max_ev: f32,
+    /// Added brightness in stops on top of the metered exposure.
+    pub compensation: f32,
+    /// Adaptation rate when the scene gets brighter; larger is faster.
+    pub speed_up: f32,
+    /// Adaptation rate when the scene gets darker. Eyes adjust to the dark more slowly.
+    pub speed_down: f32,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        AutoExposureSettings {
+            enabled: false,
+            mode: ExposureMode::Auto,
+            min_ev: -4.0,
+            max_ev: 16.0,
+            compensation: 0.0,
+            speed_up: 3.0,
+            speed_down: 1.0,
+        }
+    }
+}
+
+impl AutoExposureSettings {
+    #[cfg(feature = "egui")]
+    /// Mode, range and adaptation controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Enabled");
+        let mut manual = matches!(self.mode, ExposureMode::Manual { .. });
+        if ui.checkbox(&mut manual, "Manual").changed() {
+            self.mode = if manual { ExposureMode::Manual { ev: 8.0 } } else { ExposureMode::Auto };
+        }
+        match &mut self.mode {
+            ExposureMode::Manual { ev } => {
+                ui.add(egui::Slider::new(ev, -4.0..=16.0).text("EV100"));
+            }
+            ExposureMode::Auto => {
+                ui.add(egui::Slider::new(&mut self.min_ev, -8.0..=20.0).text("Min EV100"));
+                ui.add(egui::Slider::new(&mut self.max_ev, -8.0..=20.0).text("Max EV100"));
+                ui.add(egui::Slider::new(&mut self.speed_up, 0.1..=10.0).text("Adapt to bright"));
+                ui.add(egui::Slider::new(&mut self.speed_down, 0.1..=10.0).text("Adapt to dark"));
+            }
+        }
+        ui.add(egui::Slider::new(&mut self.compensation, -6.0..=6.0).text("Compensation (stops)"));
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    speed_up: f32,
+    speed_down: f32,
+    min_ev: f32,
+    max_ev: f32,
+    compensation: f32,
+    delta_time: f32,
+}
+
+/// Read by the tonemapper as `struct { luminance: f32; exposure: f32; }`, which multiplies the
+/// HDR color by `exposure` before tonemapping.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Exposure {
+    luminance: f32,
+    exposure: f32,
+}
+
+impl Exposure {
+    fn from_ev(ev: f32) -> Self {
+        Exposure {
+            // inverse of the EV100 metering in the shader
+            luminance: 2f32.powf(ev) * 12.5 / 100.0,
+            exposure: 1.0 / (1.2 * 2f32.powf(ev)),
+        }
+    }
+}
+
+/// Eye adaptation: builds a luminance histogram of the HDR scene in a compute pass and eases
+/// the exposure towards its average. The result stays on the GPU in `exposure_buffer`, so
+/// metering never stalls on a readback. Needs compute shaders; check `Capabilities::compute`
+/// and use `ExposureMode::Manual` without them.
+pub struct AutoExposure {
+    pub settings: AutoExposureSettings,
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    uniform: wgpu::Buffer,
+    histogram: wgpu::Buffer,
+    exposure: wgpu::Buffer,
+    manual_ev: Option<f32>,
+}
+
+impl AutoExposure {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("../shaders/auto_exposure.wgsl"));
+        let storage = |binding, size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(size),
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("auto exposure layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(std::mem::size_of::<Params>() as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage(2, BIN_COUNT * 4),
+                storage(3, std::mem::size_of::<Exposure>() as u64),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("auto exposure pipeline layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("auto exposure params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let histogram = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("luminance histogram"),
+            contents: &[0; BIN_COUNT as usize * 4],
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        // start from a typical indoor exposure rather than adapting up from black
+        let exposure = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("exposure"),
+            contents: bytemuck::bytes_of(&Exposure::from_ev(8.0)),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        AutoExposure {
+            settings: AutoExposureSettings::default(),
+            histogram_pipeline: pipeline("luminance histogram", "build_histogram"),
+            average_pipeline: pipeline("exposure adaptation", "average"),
+            layout,
+            uniform,
+            histogram,
+            exposure,
+            manual_ev: None,
+        }
+    }
+
+    /// The current exposure, for `Tonemapper::render` to bind as a uniform.
+    pub fn exposure_buffer(&self) -> &wgpu::Buffer {
+        &self.exposure
+    }
+
+    /// Reads back the adapted scene luminance in cd/m² and the exposure applied to it, for
+    /// debug readouts and benchmarks. `callback` runs from `Readbacks::poll` once the GPU has
+    /// caught up, usually a couple of frames later.
+    pub fn read_exposure(
+        &self,
+        readbacks: &mut Readbacks,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        callback: impl FnOnce(f32, f32) + 'static,
+    ) {
+        let size = std::mem::size_of::<Exposure>() as u64;
+        readbacks.read_buffer(device, encoder, &self.exposure, 0, size, move |result| match result {
+            Ok(bytes) => {
+                let exposure: Exposure = bytemuck::pod_read_unaligned(&bytes);
+                callback(exposure.luminance, exposure.exposure);
+            }
+            Err(e) => log::error!("Failed to read back the exposure: {}", e),
+        });
+    }
+
+    /// Meters `hdr` and adapts the exposure by `delta_time` seconds. With a manual exposure this
+    /// only uploads the fixed value when it changes.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr: &wgpu::TextureView,
+        (width, height): (u32, u32),
+        delta_time: f32,
+    ) {
+        let settings = self.settings;
+        if let ExposureMode::Manual { ev } = settings.mode {
+            let ev = ev - settings.compensation;
+            if self.manual_ev != Some(ev) {
+                queue.write_buffer(&self.exposure, 0, bytemuck::bytes_of(&Exposure::from_ev(ev)));
+                self.manual_ev = Some(ev);
+            }
+            return;
+        }
+        self.manual_ev = None;
+
+        let (min_ev, max_ev) = (settings.min_ev.min(settings.max_ev), settings.max_ev.max(settings.min_ev));
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&Params {
+                // EV100 to log2 luminance is an offset of log2(12.5 / 100) = -3
+                min_log_luminance: min_ev - 3.0,
+                log_luminance_range: (max_ev - min_ev).max(1.0),
+                speed_up: settings.speed_up.max(0.0),
+                speed_down: settings.speed_down.max(0.0),
+                min_ev,
+                max_ev,
+                compensation: settings.compensation,
+                delta_time,
+            }),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("auto exposure bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(hdr),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.histogram.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.exposure.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("auto exposure"),
+        });
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_pipeline(&self.histogram_pipeline);
+        pass.dispatch(width.div_ceil(WORKGROUP_SIZE), height.div_ceil(WORKGROUP_SIZE), 1);
+        // also clears the histogram for the next frame
+        pass.set_pipeline(&self.average_pipeline);
+        pass.dispatch(1, 1, 1);
+    }
+}
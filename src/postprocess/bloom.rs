@@ -0,0 +1,262 @@
+use super::{
+    fullscreen_module, linear_sampler, sampler_entry, texture_entry, uniform_entry,
+    stack::{PostContext, PostEffect, PostStage},
+    FullscreenPipeline, RenderTarget,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// Scene brightness where bloom starts, in HDR units. Ignored for emissive sources.
+    pub threshold: f32,
+    /// Width of the soft transition around `threshold`, as a fraction of it.
+    pub knee: f32,
+    pub intensity: f32,
+    /// How much each smaller, wider level adds onto the next: higher spreads the glow further.
+    pub scatter: f32,
+    /// Half-resolution levels in the chain; more reach further at little cost.
+    pub levels: u32,
+    /// Adds the broadest emissive blur onto the scene, as a cheap stand-in for the light bright
+    /// emissive surfaces spill onto their surroundings. 0 disables it.
+    pub area_light_strength: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        BloomSettings {
+            enabled: true,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.05,
+            scatter: 0.7,
+            levels: 6,
+            area_light_strength: 0.0,
+        }
+    }
+}
+
+impl BloomSettings {
+    #[cfg(feature = "egui")]
+    /// Threshold, strength and spread controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.threshold, 0.0..=8.0).text("Threshold"));
+        ui.add(egui::Slider::new(&mut self.knee, 0.0..=1.0).text("Knee"));
+        ui.add(egui::Slider::new(&mut self.intensity, 0.0..=1.0).text("Intensity"));
+        ui.add(egui::Slider::new(&mut self.scatter, 0.0..=1.0).text("Scatter"));
+        ui.add(egui::Slider::new(&mut self.levels, 1..=8).text("Levels"));
+    }
+}
+
+/// What glows.
+#[derive(Clone, Copy)]
+pub enum BloomSource<'a> {
+    /// Whatever in the scene is brighter than the threshold.
+    Scene,
+    /// Only emissive surfaces: a buffer holding the emissive radiance the materials wrote.
+    Emissive(&'a wgpu::TextureView),
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    scatter: f32,
+    area_light_strength: f32,
+    _padding: [f32; 3],
+}
+
+/// HDR bloom over a chain of half-resolution levels, run before tonemapping. With an emissive
+/// source only emissive materials glow, however bright the rest of the scene gets.
+pub struct Bloom {
+    pub settings: BloomSettings,
+    prefilter: FullscreenPipeline,
+    downsample: FullscreenPipeline,
+    upsample: FullscreenPipeline,
+    composite: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+    levels: Vec<RenderTarget>,
+}
+
+impl Bloom {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    /// Chain level the area light approximation reads, after the upsample has summed every
+    /// level below it.
+    const AREA_LIGHT_LEVEL: usize = 3;
+
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "bloom", include_str!("../shaders/bloom.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom layout"),
+            entries: &[uniform_entry(0), sampler_entry(1), texture_entry(2), texture_entry(3), texture_entry(4)],
+        });
+        let pipeline = |label, entry_point, format, blend| {
+            FullscreenPipeline::new(device, label, &module, entry_point, &[&layout], format, blend)
+        };
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bloom params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Bloom {
+            settings: BloomSettings::default(),
+            prefilter: pipeline("bloom prefilter", "fs_prefilter", Self::FORMAT, None),
+            downsample: pipeline("bloom downsample", "fs_downsample", Self::FORMAT, None),
+            upsample: pipeline("bloom upsample", "fs_upsample", Self::FORMAT, Some(additive)),
+            composite: pipeline("bloom composite", "fs_composite", output_format, None),
+            layout,
+            sampler: linear_sampler(device),
+            uniform,
+            levels: Vec::new(),
+        }
+    }
+
+    fn ensure_levels(&mut self, device: &wgpu::Device, (width, height): (u32, u32)) {
+        // stop before levels get smaller than a few pixels
+        let max_levels = (width.min(height).max(1).ilog2()).saturating_sub(2).max(1);
+        let count = self.settings.levels.clamp(1, max_levels) as usize;
+        let first = ((width / 2).max(1), (height / 2).max(1));
+        if self.levels.len() == count && self.levels.first().is_some_and(|level| (level.width, level.height) == first) {
+            return;
+        }
+        self.levels = (0..count as u32)
+            .map(|level| {
+                let (w, h) = ((width >> (level + 1)).max(1), (height >> (level + 1)).max(1));
+                RenderTarget::new(device, "bloom level", w, h, Self::FORMAT)
+            })
+            .collect();
+    }
+
+    /// Writes `color` with bloom added to `output`. `size` is the size of `color`, and of the
+    /// emissive buffer if there is one. When disabled this is a plain copy so the pass can stay
+    /// in the chain.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        size: (u32, u32),
+        source: BloomSource,
+        output: &wgpu::TextureView,
+    ) {
+        self.ensure_levels(device, size);
+        let settings = self.settings;
+        let (threshold, knee) = match source {
+            BloomSource::Scene => (settings.threshold.max(0.0), settings.knee.clamp(0.0, 1.0)),
+            BloomSource::Emissive(_) => (0.0, 0.0),
+        };
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&Params {
+                threshold,
+                knee,
+                intensity: if settings.enabled { settings.intensity.max(0.0) } else { 0.0 },
+                scatter: settings.scatter.clamp(0.0, 1.0),
+                area_light_strength: if settings.enabled { settings.area_light_strength.max(0.0) } else { 0.0 },
+                _padding: [0.0; 3],
+            }),
+        );
+
+        let bind_group = |source: &wgpu::TextureView, bloom: &wgpu::TextureView, glow: &wgpu::TextureView| {
+            let texture = |binding, view| wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(view),
+            };
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bloom bind group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    texture(2, source),
+                    texture(3, bloom),
+                    texture(4, glow),
+                ],
+            })
+        };
+        let clear = Some(wgpu::Color::BLACK);
+
+        if settings.enabled {
+            let first = match source {
+                BloomSource::Scene => color,
+                BloomSource::Emissive(emissive) => emissive,
+            };
+            let levels = &self.levels;
+            self.prefilter
+                .draw(encoder, &levels[0].view, &[&bind_group(first, first, first)], clear);
+            for pair in levels.windows(2) {
+                let (from, to) = (&pair[0].view, &pair[1].view);
+                self.downsample.draw(encoder, to, &[&bind_group(from, from, from)], clear);
+            }
+            for pair in levels.windows(2).rev() {
+                let (to, from) = (&pair[0].view, &pair[1].view);
+                self.upsample.draw(encoder, to, &[&bind_group(from, from, from)], None);
+            }
+        }
+
+        let bloom = &self.levels[0].view;
+        let glow = &self.levels[Self::AREA_LIGHT_LEVEL.min(self.levels.len() - 1)].view;
+        self.composite.draw(encoder, output, &[&bind_group(color, bloom, glow)], clear);
+    }
+}
+
+impl PostEffect for Bloom {
+    fn name(&self) -> &str {
+        "Bloom"
+    }
+
+    fn shader_file(&self) -> Option<&'static str> {
+        Some("bloom.wgsl")
+    }
+
+    fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.prefilter.set_module(device, module);
+        self.downsample.set_module(device, module);
+        self.upsample.set_module(device, module);
+        self.composite.set_module(device, module);
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Hdr
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+
+    fn render(&mut self, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        let (device, queue, size) = (context.device, context.queue, context.size);
+        Bloom::render(self, device, queue, context.encoder, input, size, BloomSource::Scene, output);
+    }
+
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.settings.ui(ui);
+    }
+}
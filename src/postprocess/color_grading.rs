@@ -0,0 +1,419 @@
+use std::{fmt, path::Path};
+
+use super::{
+    fullscreen_module, linear_sampler, sampler_entry,
+    stack::{PostContext, PostEffect, PostStage},
+    texture_entry, uniform_entry, FullscreenPipeline,
+};
+
+#[derive(Debug)]
+pub enum CubeError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for CubeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CubeError::Io(e) => write!(f, "{}", e),
+            CubeError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for CubeError {}
+
+/// A 3D lookup table in the Adobe/Resolve `.cube` format. Entries are stored with red varying
+/// fastest, which is also the texel order of a 3D texture.
+#[derive(Clone, Debug)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    pub size: u32,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub data: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    /// Largest `LUT_3D_SIZE` the format allows.
+    pub const MAX_SIZE: u32 = 256;
+
+    /// A LUT that maps every color to itself.
+    pub fn identity(size: u32) -> Self {
+        let size = size.clamp(2, Self::MAX_SIZE);
+        let scale = 1.0 / (size - 1) as f32;
+        let mut data = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.push([r as f32 * scale, g as f32 * scale, b as f32 * scale]);
+                }
+            }
+        }
+        CubeLut {
+            title: None,
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            data,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CubeError> {
+        let source = std::fs::read_to_string(path).map_err(CubeError::Io)?;
+        Self::parse(&source)
+    }
+
+    /// Parses the text of a `.cube` file. Only 3D tables are supported; 1D shaper LUTs are
+    /// rejected rather than silently ignored.
+    pub fn parse(source: &str) -> Result<Self, CubeError> {
+        let error = |line: usize, message: &str| CubeError::Parse {
+            line: line + 1,
+            message: message.into(),
+        };
+        let floats = |line: usize, values: &str| -> Result<[f32; 3], CubeError> {
+            let mut parsed = [0.0; 3];
+            let mut parts = values.split_whitespace();
+            for value in parsed.iter_mut() {
+                *value = parts
+                    .next()
+                    .and_then(|part| part.parse().ok())
+                    .ok_or_else(|| error(line, "expected three numbers"))?;
+            }
+            if parts.next().is_some() {
+                return Err(error(line, "expected three numbers"));
+            }
+            Ok(parsed)
+        };
+
+        let mut lut = CubeLut {
+            title: None,
+            size: 0,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            data: Vec::new(),
+        };
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            match keyword {
+                "TITLE" => lut.title = Some(rest.trim().trim_matches('"').into()),
+                "LUT_3D_SIZE" => {
+                    let size: u32 = rest.trim().parse().map_err(|_| error(number, "invalid LUT_3D_SIZE"))?;
+                    if !(2..=Self::MAX_SIZE).contains(&size) {
+                        return Err(error(number, "LUT_3D_SIZE must be between 2 and 256"));
+                    }
+                    lut.size = size;
+                    lut.data.reserve((size * size * size) as usize);
+                }
+                "LUT_1D_SIZE" => return Err(error(number, "1D LUTs are not supported")),
+                "DOMAIN_MIN" => lut.domain_min = floats(number, rest)?,
+                "DOMAIN_MAX" => lut.domain_max = floats(number, rest)?,
+                // LUT_3D_INPUT_RANGE and other vendor keywords
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {}
+                _ => {
+                    if lut.size == 0 {
+                        return Err(error(number, "table data before LUT_3D_SIZE"));
+                    }
+                    lut.data.push(floats(number, line)?);
+                }
+            }
+        }
+
+        if lut.size == 0 {
+            return Err(error(source.lines().count(), "missing LUT_3D_SIZE"));
+        }
+        let expected = (lut.size * lut.size * lut.size) as usize;
+        if lut.data.len() != expected {
+            return Err(error(
+                source.lines().count(),
+                &format!("expected {} table entries, found {}", expected, lut.data.len()),
+            ));
+        }
+        if (0..3).any(|i| lut.domain_max[i] <= lut.domain_min[i]) {
+            return Err(error(source.lines().count(), "DOMAIN_MAX must be greater than DOMAIN_MIN"));
+        }
+        Ok(lut)
+    }
+}
+
+/// Half floats keep smooth gradients through the LUT and are filterable everywhere, unlike
+/// 32-bit float textures.
+const LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+pub(crate) fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = (bits >> 16) & 0x8000;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if value.is_nan() {
+        (sign | 0x7e00) as u16
+    } else if exponent >= 0x1f {
+        (sign | 0x7c00) as u16
+    } else if exponent <= 0 {
+        // LUT values this small are indistinguishable from zero
+        sign as u16
+    } else {
+        // rounding may carry into the exponent, which is still the correct result
+        ((sign | ((exponent as u32) << 10)) + ((mantissa + 0x1000) >> 13)) as u16
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ColorGradingSettings {
+    pub enabled: bool,
+    /// Raises the shadows per channel; 0 is neutral.
+    pub lift: [f32; 3],
+    /// Midtone power per channel; 1 is neutral.
+    pub gamma: [f32; 3],
+    /// Scales the highlights per channel; 1 is neutral.
+    pub gain: [f32; 3],
+    /// How much of the LUT result to blend in over the lift/gamma/gain output.
+    pub lut_strength: f32,
+}
+
+impl Default for ColorGradingSettings {
+    fn default() -> Self {
+        ColorGradingSettings {
+            enabled: true,
+            lift: [0.0; 3],
+            gamma: [1.0; 3],
+            gain: [1.0; 3],
+            lut_strength: 1.0,
+        }
+    }
+}
+
+impl ColorGradingSettings {
+    #[cfg(feature = "egui")]
+    /// Lift/gamma/gain and LUT strength controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let channels = |ui: &mut egui::Ui, label: &str, values: &mut [f32; 3], range: std::ops::RangeInclusive<f32>| {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                for (value, channel) in values.iter_mut().zip(["R ", "G ", "B "]) {
+                    ui.add(
+                        egui::DragValue::new(value)
+                            .speed(0.005)
+                            .clamp_range(range.clone())
+                            .prefix(channel),
+                    );
+                }
+            });
+        };
+        channels(ui, "Lift", &mut self.lift, -0.5..=0.5);
+        channels(ui, "Gamma", &mut self.gamma, 0.1..=4.0);
+        channels(ui, "Gain", &mut self.gain, 0.0..=4.0);
+        ui.add(egui::Slider::new(&mut self.lut_strength, 0.0..=1.0).text("LUT strength"));
+        if ui.button("Reset").clicked() {
+            *self = ColorGradingSettings {
+                enabled: self.enabled,
+                ..Default::default()
+            };
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    lift: [f32; 4],
+    gamma: [f32; 4],
+    gain: [f32; 4],
+    domain_min: [f32; 4],
+    domain_max: [f32; 4],
+    lut_size: f32,
+    lut_strength: f32,
+    _padding: [f32; 2],
+}
+
+/// Applies lift/gamma/gain and then a 3D LUT. Runs on display-referred color, so as a
+/// `PostEffect` it is a display one, after tonemapping. Starts out with an identity LUT until
+/// `set_lut` is called.
+pub struct ColorGrading {
+    pub settings: ColorGradingSettings,
+    pipeline: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+    lut: wgpu::TextureView,
+    lut_size: u32,
+    domain: ([f32; 3], [f32; 3]),
+}
+
+impl ColorGrading {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, output_format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "color grading", include_str!("../shaders/color_grading.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("color grading layout"),
+            entries: &[
+                uniform_entry(0),
+                sampler_entry(1),
+                texture_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline = FullscreenPipeline::new(device, "color grading", &module, "fs_main", &[&layout], output_format, None);
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("color grading params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let identity = CubeLut::identity(2);
+        ColorGrading {
+            settings: ColorGradingSettings::default(),
+            pipeline,
+            layout,
+            sampler: linear_sampler(device),
+            uniform,
+            lut: upload_lut(device, queue, &identity),
+            lut_size: identity.size,
+            domain: (identity.domain_min, identity.domain_max),
+        }
+    }
+
+    /// Replaces the active LUT, e.g. with one from `CubeLut::load`.
+    pub fn set_lut(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lut: &CubeLut) {
+        self.lut = upload_lut(device, queue, lut);
+        self.lut_size = lut.size;
+        self.domain = (lut.domain_min, lut.domain_max);
+    }
+
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        let settings = if self.settings.enabled {
+            self.settings
+        } else {
+            ColorGradingSettings {
+                lut_strength: 0.0,
+                ..Default::default()
+            }
+        };
+        let extend = |[r, g, b]: [f32; 3]| [r, g, b, 0.0];
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&Params {
+                lift: extend(settings.lift),
+                gamma: extend(settings.gamma),
+                gain: extend(settings.gain),
+                domain_min: extend(self.domain.0),
+                domain_max: extend(self.domain.1),
+                lut_size: self.lut_size as f32,
+                lut_strength: settings.lut_strength.clamp(0.0, 1.0),
+                _padding: [0.0; 2],
+            }),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("color grading bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(color),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&self.lut),
+                },
+            ],
+        });
+        self.pipeline.draw(encoder, output, &[&bind_group], Some(wgpu::Color::BLACK));
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn name(&self) -> &str {
+        "Color grading"
+    }
+
+    fn shader_file(&self) -> Option<&'static str> {
+        Some("color_grading.wgsl")
+    }
+
+    fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.pipeline.set_module(device, module);
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Display
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+
+    fn render(&mut self, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        ColorGrading::render(self, context.device, context.queue, context.encoder, input, output);
+    }
+
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.settings.ui(ui);
+    }
+}
+
+fn upload_lut(device: &wgpu::Device, queue: &wgpu::Queue, lut: &CubeLut) -> wgpu::TextureView {
+    let size = wgpu::Extent3d {
+        width: lut.size,
+        height: lut.size,
+        depth_or_array_layers: lut.size,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(lut.title.as_deref().unwrap_or("color grading lut")),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: LUT_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+    let texels: Vec<[u16; 4]> = lut
+        .data
+        .iter()
+        .map(|&[r, g, b]| [f16_bits(r), f16_bits(g), f16_bits(b), f16_bits(1.0)])
+        .collect();
+    queue.write_texture(
+        texture.as_image_copy(),
+        bytemuck::cast_slice(&texels),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(lut.size * 8),
+            rows_per_image: std::num::NonZeroU32::new(lut.size),
+        },
+        size,
+    );
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
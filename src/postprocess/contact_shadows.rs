@@ -0,0 +1,223 @@
+use glam::Vec3;
+
+use super::{depth_texture_entry, fullscreen_module, texture_entry, uniform_entry, FullscreenPipeline, RenderTarget};
+use crate::camera::Camera;
+
+/// March steps per pixel. Fewer steps miss thin occluders and show more noise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContactShadowQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl ContactShadowQuality {
+    pub fn steps(self) -> u32 {
+        match self {
+            ContactShadowQuality::Low => 8,
+            ContactShadowQuality::Medium => 16,
+            ContactShadowQuality::High => 32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ContactShadowSettings {
+    pub enabled: bool,
+    pub quality: ContactShadowQuality,
+    /// How far towards the light each pixel looks for an occluder, in world units. Keep it
+    /// short; the shadow map handles everything further away.
+    pub length: f32,
+    /// How deep geometry is assumed to be behind the depth buffer. Too thin misses occluders,
+    /// too thick casts shadows from things that only look close on screen.
+    pub thickness: f32,
+    /// Darkening of fully occluded pixels, 0 to 1.
+    pub intensity: f32,
+}
+
+impl ContactShadowSettings {
+    #[cfg(feature = "egui")]
+    /// On/off, quality and ray controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Enabled");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            egui::ComboBox::from_label("Quality")
+                .selected_text(format!("{:?}", self.quality))
+                .show_ui(ui, |ui| {
+                    for quality in [ContactShadowQuality::Low, ContactShadowQuality::Medium, ContactShadowQuality::High] {
+                        ui.selectable_value(&mut self.quality, quality, format!("{:?}", quality));
+                    }
+                });
+            ui.add(egui::Slider::new(&mut self.length, 0.01..=2.0).text("Length"));
+            ui.add(egui::Slider::new(&mut self.thickness, 0.001..=0.5).logarithmic(true).text("Thickness"));
+            ui.add(egui::Slider::new(&mut self.intensity, 0.0..=1.0).text("Intensity"));
+        });
+    }
+}
+
+impl Default for ContactShadowSettings {
+    fn default() -> Self {
+        ContactShadowSettings {
+            enabled: true,
+            quality: ContactShadowQuality::Medium,
+            length: 0.3,
+            thickness: 0.05,
+            intensity: 0.8,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    projection: [[f32; 4]; 4],
+    inverse_projection: [[f32; 4]; 4],
+    light_direction: [f32; 4],
+    length: f32,
+    thickness: f32,
+    intensity: f32,
+    steps: f32,
+}
+
+/// Short-range screen-space shadows from one light, grounding objects where the shadow map is
+/// too coarse. Renders a single-channel mask, 1 where lit, that lighting multiplies the light's
+/// contribution by, or that `apply` darkens the lit scene with. The engine does the latter with
+/// `ShadowSettings::contact`, which also darkens the ambient light where it does.
+pub struct ContactShadows {
+    pub settings: ContactShadowSettings,
+    pipeline: FullscreenPipeline,
+    apply: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    apply_layout: wgpu::BindGroupLayout,
+    uniform: wgpu::Buffer,
+    mask: Option<RenderTarget>,
+}
+
+impl ContactShadows {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+    /// `output_format` is the format of the scenes `apply` darkens.
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("contact shadow layout"),
+            entries: &[uniform_entry(0), depth_texture_entry(1)],
+        });
+        let module = fullscreen_module(device, "contact shadows", include_str!("../shaders/contact_shadows.wgsl"));
+        let pipeline = FullscreenPipeline::new(device, "contact shadows", &module, "fs_main", &[&layout], Self::FORMAT, None);
+        let apply_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("contact shadow apply layout"),
+            entries: &[texture_entry(2)],
+        });
+        // multiplies the scene's color by the mask
+        let multiply = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::Src,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+        let apply = FullscreenPipeline::new(
+            device,
+            "contact shadow apply",
+            &module,
+            "fs_apply",
+            &[&apply_layout],
+            output_format,
+            Some(multiply),
+        );
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("contact shadow params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        ContactShadows {
+            settings: ContactShadowSettings::default(),
+            pipeline,
+            apply,
+            layout,
+            apply_layout,
+            uniform,
+            mask: None,
+        }
+    }
+
+    /// The mask from the last `render`, at the size of the depth buffer.
+    pub fn mask(&self) -> Option<&wgpu::TextureView> {
+        self.mask.as_ref().map(|mask| &mask.view)
+    }
+
+    /// Traces `depth`, which was rendered through `camera` into a target of `target_size`,
+    /// towards a light travelling in `light_direction`. When disabled the mask is cleared to
+    /// fully lit so lighting can sample it unconditionally.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: &wgpu::TextureView,
+        target_size: (u32, u32),
+        camera: &Camera,
+        light_direction: Vec3,
+    ) -> &wgpu::TextureView {
+        let (width, height) = target_size;
+        if !matches!(&self.mask, Some(mask) if (mask.width, mask.height) == target_size) {
+            self.mask = Some(RenderTarget::new(device, "contact shadow mask", width, height, Self::FORMAT));
+        }
+        let settings = self.settings;
+        let (_, _, viewport_width, viewport_height) = camera.viewport.pixels(target_size);
+        let projection = camera.projection.matrix(viewport_width as f32 / viewport_height as f32);
+        let towards_light = camera.view().transform_vector3(-light_direction).normalize_or_zero();
+        let params = Params {
+            projection: projection.to_cols_array_2d(),
+            inverse_projection: projection.inverse().to_cols_array_2d(),
+            light_direction: towards_light.extend(0.0).into(),
+            length: settings.length.max(0.0),
+            thickness: settings.thickness.max(0.001),
+            intensity: settings.intensity.clamp(0.0, 1.0),
+            steps: if settings.enabled { settings.quality.steps() as f32 } else { 0.0 },
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&params));
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("contact shadow bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(depth),
+                },
+            ],
+        });
+        let mask = &self.mask.as_ref().unwrap().view;
+        self.pipeline.draw(encoder, mask, &[&bind_group], Some(wgpu::Color::WHITE));
+        mask
+    }
+
+    /// Darkens `output`, a scene of the depth `render` traced, by the mask from it.
+    pub fn apply(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        let Some(mask) = &self.mask else {
+            return;
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("contact shadow apply bind group"),
+            layout: &self.apply_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&mask.view),
+            }],
+        });
+        self.apply.draw(encoder, output, &[&bind_group], None);
+    }
+}
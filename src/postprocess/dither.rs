@@ -0,0 +1,297 @@
+use wgpu::util::DeviceExt;
+
+use super::{
+    fullscreen_module, linear_sampler, sampler_entry, texture_entry, uniform_entry,
+    stack::{PostContext, PostEffect, PostStage},
+    FullscreenPipeline,
+};
+
+/// Side of the tiling blue noise texture.
+pub const BLUE_NOISE_SIZE: usize = 64;
+
+/// A tileable `size` by `size` blue noise threshold map by void and cluster: every value from 0
+/// to 1 appears once, and the pixels below any threshold are spread as evenly as possible.
+pub fn blue_noise(size: usize) -> Vec<f32> {
+    let count = size * size;
+    // gaussian energy, wrapping around so the pattern tiles; negligible past `reach`
+    let sigma = 1.5f32;
+    let reach = (sigma * 4.0).ceil() as isize;
+    let mut energy = vec![0.0f32; count];
+    let mut pattern = vec![false; count];
+    let toggle = |energy: &mut [f32], pattern: &mut [bool], index: usize| {
+        pattern[index] = !pattern[index];
+        let sign = if pattern[index] { 1.0 } else { -1.0 };
+        let (x, y) = ((index % size) as isize, (index / size) as isize);
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let i = (y + dy).rem_euclid(size as isize) as usize * size + (x + dx).rem_euclid(size as isize) as usize;
+                energy[i] += sign * (-((dx * dx + dy * dy) as f32) / (2.0 * sigma * sigma)).exp();
+            }
+        }
+    };
+    // tightest cluster among set pixels, largest void among clear ones
+    let extreme = |energy: &[f32], pattern: &[bool], set: bool| {
+        let candidates = (0..count).filter(|&i| pattern[i] == set);
+        if set {
+            candidates.max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        } else {
+            candidates.min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+        }
+        .unwrap()
+    };
+
+    // a deterministic sprinkle of a tenth of the pixels, relaxed until it is evenly spread
+    let mut state = 0x2545_f491_u32;
+    let initial = (count / 10).max(1);
+    while pattern.iter().filter(|&&set| set).count() < initial {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let index = state as usize % count;
+        if !pattern[index] {
+            toggle(&mut energy, &mut pattern, index);
+        }
+    }
+    loop {
+        let cluster = extreme(&energy, &pattern, true);
+        toggle(&mut energy, &mut pattern, cluster);
+        let void = extreme(&energy, &pattern, false);
+        if void == cluster {
+            toggle(&mut energy, &mut pattern, cluster);
+            break;
+        }
+        toggle(&mut energy, &mut pattern, void);
+    }
+
+    let mut rank = vec![0usize; count];
+    let (initial_energy, initial_pattern) = (energy.clone(), pattern.clone());
+    // ranks below the initial pattern: remove clusters one at a time
+    for r in (0..initial).rev() {
+        let cluster = extreme(&energy, &pattern, true);
+        toggle(&mut energy, &mut pattern, cluster);
+        rank[cluster] = r;
+    }
+    // ranks above it: fill voids one at a time
+    let (mut energy, mut pattern) = (initial_energy, initial_pattern);
+    for r in initial..count {
+        let void = extreme(&energy, &pattern, false);
+        toggle(&mut energy, &mut pattern, void);
+        rank[void] = r;
+    }
+    rank.into_iter().map(|r| (r as f32 + 0.5) / count as f32).collect()
+}
+
+/// Bits per channel of `format` after quantization, or `None` for float formats that don't band.
+pub fn quantization_bits(format: wgpu::TextureFormat) -> Option<u32> {
+    use wgpu::TextureFormat::*;
+    match format {
+        Rgba8Unorm | Rgba8UnormSrgb | Bgra8Unorm | Bgra8UnormSrgb | R8Unorm | Rg8Unorm => Some(8),
+        Rgb10a2Unorm => Some(10),
+        R16Unorm | Rg16Unorm | Rgba16Unorm => Some(16),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DitherSettings {
+    pub enabled: bool,
+    /// Noise amplitude in quantization steps of the output; 1 hides banding with the least grain.
+    pub strength: f32,
+    /// Smooths gradients that still band after dithering, e.g. 8-bit sky textures.
+    pub debanding: bool,
+    /// Largest difference, in display units, that still counts as a smooth gradient.
+    pub deband_threshold: f32,
+    /// How far apart debanding looks for neighbours, in pixels. Should exceed the band width.
+    pub deband_radius: f32,
+}
+
+impl DitherSettings {
+    #[cfg(feature = "egui")]
+    /// Grain and debanding controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.strength, 0.0..=4.0).text("Strength"));
+        ui.checkbox(&mut self.debanding, "Debanding");
+        ui.add_enabled(
+            self.debanding,
+            egui::Slider::new(&mut self.deband_threshold, 0.0..=0.05).text("Deband threshold"),
+        );
+        ui.add_enabled(self.debanding, egui::Slider::new(&mut self.deband_radius, 1.0..=32.0).text("Deband radius"));
+    }
+}
+
+impl Default for DitherSettings {
+    fn default() -> Self {
+        DitherSettings {
+            enabled: true,
+            strength: 1.0,
+            debanding: false,
+            deband_threshold: 0.006,
+            deband_radius: 12.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    step: f32,
+    strength: f32,
+    deband_threshold: f32,
+    deband_radius: f32,
+    noise_offset: [f32; 2],
+    _padding: [f32; 2],
+}
+
+/// The final pass before display: blue noise dithering by one step of the output format, plus
+/// optional debanding. Runs on display-referred color, after tonemapping and grading. The noise
+/// moves every frame so temporal filters and the eye average it out. As a `PostEffect` it belongs
+/// last in the `PostStage::Display` effects, writing the surface.
+pub struct Dither {
+    pub settings: DitherSettings,
+    pipeline: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+    noise: wgpu::TextureView,
+    step: f32,
+    // frames rendered through `PostEffect::render`, for the noise offset
+    frame: u64,
+}
+
+impl Dither {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, output_format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "dither", include_str!("../shaders/dither.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("dither layout"),
+            entries: &[uniform_entry(0), sampler_entry(1), texture_entry(2), texture_entry(3)],
+        });
+        let pipeline = FullscreenPipeline::new(device, "dither", &module, "fs_main", &[&layout], output_format, None);
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("dither params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texels: Vec<u8> = blue_noise(BLUE_NOISE_SIZE)
+            .into_iter()
+            .map(|value| (value * 255.0).round() as u8)
+            .collect();
+        let noise = device
+            .create_texture_with_data(
+                queue,
+                &wgpu::TextureDescriptor {
+                    label: Some("blue noise"),
+                    size: wgpu::Extent3d {
+                        width: BLUE_NOISE_SIZE as u32,
+                        height: BLUE_NOISE_SIZE as u32,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::R8Unorm,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                },
+                &texels,
+            )
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        Dither {
+            settings: DitherSettings::default(),
+            pipeline,
+            layout,
+            sampler: linear_sampler(device),
+            uniform,
+            noise,
+            step: quantization_bits(output_format).map_or(0.0, |bits| 1.0 / ((1u64 << bits) - 1) as f32),
+            frame: 0,
+        }
+    }
+
+    /// Writes `color` to `output`. `frame` picks the noise offset; pass an increasing counter.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        frame: u64,
+        output: &wgpu::TextureView,
+    ) {
+        let settings = self.settings;
+        // R2 sequence: successive offsets land far apart without repeating soon
+        let r2 = |alpha: f64| ((0.5 + alpha * frame as f64).fract() * BLUE_NOISE_SIZE as f64).floor() as f32;
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&Params {
+                step: if settings.enabled { self.step } else { 0.0 },
+                strength: settings.strength.max(0.0),
+                deband_threshold: settings.deband_threshold.max(0.0),
+                deband_radius: if settings.enabled && settings.debanding { settings.deband_radius.max(0.0) } else { 0.0 },
+                noise_offset: [r2(0.754_877_666_246_692_8), r2(0.569_840_290_998_053_3)],
+                _padding: [0.0; 2],
+            }),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("dither bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(color),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&self.noise),
+                },
+            ],
+        });
+        self.pipeline.draw(encoder, output, &[&bind_group], Some(wgpu::Color::BLACK));
+    }
+}
+
+impl PostEffect for Dither {
+    fn name(&self) -> &str {
+        "Dither"
+    }
+
+    fn shader_file(&self) -> Option<&'static str> {
+        Some("dither.wgsl")
+    }
+
+    fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.pipeline.set_module(device, module);
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Display
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+
+    fn render(&mut self, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        self.frame = self.frame.wrapping_add(1);
+        Dither::render(self, context.device, context.queue, context.encoder, input, self.frame, output);
+    }
+
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.settings.ui(ui);
+    }
+}
@@ -0,0 +1,246 @@
+use bytemuck::Zeroable;
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+
+use super::{
+    depth_texture_entry, fullscreen_module, linear_sampler, sampler_entry,
+    stack::{PostContext, PostEffect, PostStage},
+    texture_entry, uniform_entry, FullscreenPipeline, RenderTarget,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct DepthOfFieldSettings {
+    pub enabled: bool,
+    /// Distance from the camera that is perfectly sharp, in world units.
+    pub focus_distance: f32,
+    /// How far in front of or behind the focal plane the blur reaches full strength.
+    pub focus_range: f32,
+    /// Largest blur radius in pixels; acts as the aperture size.
+    pub max_radius: f32,
+}
+
+impl Default for DepthOfFieldSettings {
+    fn default() -> Self {
+        DepthOfFieldSettings {
+            enabled: true,
+            focus_distance: 10.0,
+            focus_range: 8.0,
+            max_radius: 8.0,
+        }
+    }
+}
+
+impl DepthOfFieldSettings {
+    /// Focuses on `target`, e.g. the entity selected in the editor.
+    pub fn focus_on(&mut self, camera_position: Vec3, camera_forward: Vec3, target: Vec3) {
+        let distance = (target - camera_position).dot(camera_forward.normalize_or_zero());
+        self.focus_distance = distance.max(0.01);
+    }
+
+    #[cfg(feature = "egui")]
+    /// Focus and aperture controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.focus_distance, 0.1..=200.0).logarithmic(true).text("Focus distance"));
+        ui.add(egui::Slider::new(&mut self.focus_range, 0.1..=100.0).logarithmic(true).text("Focus range"));
+        ui.add(egui::Slider::new(&mut self.max_radius, 0.0..=32.0).text("Max radius"));
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    texel_size: [f32; 2],
+    near: f32,
+    far: f32,
+    focus_distance: f32,
+    focus_range: f32,
+    max_radius: f32,
+    _padding: f32,
+}
+
+/// Circle-of-confusion depth of field. Reads the scene color and depth and writes the blurred
+/// result to a separate output. As a `PostEffect` it runs on the HDR scene, with the camera's
+/// clip planes.
+pub struct DepthOfField {
+    pub settings: DepthOfFieldSettings,
+    coc: FullscreenPipeline,
+    blur: FullscreenPipeline,
+    composite: FullscreenPipeline,
+    coc_layout: wgpu::BindGroupLayout,
+    blur_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+    targets: Option<(RenderTarget, RenderTarget)>,
+}
+
+impl DepthOfField {
+    const INTERMEDIATE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "depth of field", include_str!("../shaders/dof.wgsl"));
+        let layout = |label, entries: &[wgpu::BindGroupLayoutEntry]| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries,
+            })
+        };
+        let coc_layout = layout(
+            "dof coc layout",
+            &[uniform_entry(0), sampler_entry(1), texture_entry(2), depth_texture_entry(3)],
+        );
+        let blur_layout = layout("dof blur layout", &[uniform_entry(0), sampler_entry(1), texture_entry(4)]);
+        let composite_layout = layout(
+            "dof composite layout",
+            &[uniform_entry(0), sampler_entry(1), texture_entry(2), texture_entry(5)],
+        );
+
+        let coc = FullscreenPipeline::new(device, "dof coc", &module, "fs_coc", &[&coc_layout], Self::INTERMEDIATE_FORMAT, None);
+        let blur = FullscreenPipeline::new(device, "dof blur", &module, "fs_blur", &[&blur_layout], Self::INTERMEDIATE_FORMAT, None);
+        let composite = FullscreenPipeline::new(device, "dof composite", &module, "fs_composite", &[&composite_layout], output_format, None);
+
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("dof params"),
+            contents: bytemuck::bytes_of(&Params::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        DepthOfField {
+            settings: DepthOfFieldSettings::default(),
+            coc,
+            blur,
+            composite,
+            coc_layout,
+            blur_layout,
+            composite_layout,
+            sampler: linear_sampler(device),
+            uniform,
+            targets: None,
+        }
+    }
+
+    fn ensure_targets(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if !matches!(&self.targets, Some((t, _)) if t.width == width && t.height == height) {
+            self.targets = Some((
+                RenderTarget::new(device, "dof coc", width, height, Self::INTERMEDIATE_FORMAT),
+                RenderTarget::new(device, "dof blur", width, height, Self::INTERMEDIATE_FORMAT),
+            ));
+        }
+    }
+
+    /// `near` and `far` are the camera's clip planes, used to linearize `depth`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        (width, height): (u32, u32),
+        (near, far): (f32, f32),
+        output: &wgpu::TextureView,
+    ) {
+        let settings = self.settings;
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&Params {
+                texel_size: [1.0 / width.max(1) as f32, 1.0 / height.max(1) as f32],
+                near,
+                far,
+                focus_distance: settings.focus_distance,
+                focus_range: settings.focus_range.max(0.001),
+                max_radius: if settings.enabled { settings.max_radius } else { 0.0 },
+                _padding: 0.0,
+            }),
+        );
+
+        self.ensure_targets(device, width, height);
+        let (coc_target, blur_target) = self.targets.as_ref().unwrap();
+        let bind_group = |layout, entries: &[wgpu::BindGroupEntry]| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("dof bind group"),
+                layout,
+                entries,
+            })
+        };
+        let params = wgpu::BindGroupEntry {
+            binding: 0,
+            resource: self.uniform.as_entire_binding(),
+        };
+        let sampler = wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&self.sampler),
+        };
+        let view = |binding, view| wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        };
+
+        let coc_group = bind_group(&self.coc_layout, &[params.clone(), sampler.clone(), view(2, color), view(3, depth)]);
+        self.coc.draw(encoder, &coc_target.view, &[&coc_group], Some(wgpu::Color::BLACK));
+
+        let blur_group = bind_group(&self.blur_layout, &[params.clone(), sampler.clone(), view(4, &coc_target.view)]);
+        self.blur.draw(encoder, &blur_target.view, &[&blur_group], Some(wgpu::Color::BLACK));
+
+        let composite_group = bind_group(&self.composite_layout, &[params, sampler, view(2, color), view(5, &blur_target.view)]);
+        self.composite.draw(encoder, output, &[&composite_group], Some(wgpu::Color::BLACK));
+    }
+}
+
+impl PostEffect for DepthOfField {
+    fn name(&self) -> &str {
+        "Depth of field"
+    }
+
+    fn shader_file(&self) -> Option<&'static str> {
+        Some("dof.wgsl")
+    }
+
+    fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.coc.set_module(device, module);
+        self.blur.set_module(device, module);
+        self.composite.set_module(device, module);
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Hdr
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+
+    fn reads_depth(&self) -> bool {
+        true
+    }
+
+    fn render(&mut self, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        // the stack only runs depth-reading effects when there is depth
+        let Some(depth) = context.depth else {
+            return;
+        };
+        let clip_planes = context.camera.projection.depth_range();
+        DepthOfField::render(
+            self,
+            context.device,
+            context.queue,
+            context.encoder,
+            input,
+            depth,
+            context.size,
+            clip_planes,
+            output,
+        );
+    }
+
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.settings.ui(ui);
+    }
+}
@@ -0,0 +1,161 @@
+use super::{
+    fullscreen_module, linear_sampler, sampler_entry, texture_entry, uniform_entry,
+    stack::{PostContext, PostEffect, PostStage},
+    FullscreenPipeline,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct FxaaSettings {
+    pub enabled: bool,
+    /// Contrast, relative to the brightest neighbour, that counts as an edge. Lower smooths more
+    /// edges and blurs more texture detail.
+    pub edge_threshold: f32,
+    /// Contrast below which nothing is smoothed, whatever the brightness.
+    pub edge_threshold_min: f32,
+    /// How much details smaller than a pixel are blended away, 0 to 1.
+    pub subpixel: f32,
+}
+
+impl Default for FxaaSettings {
+    fn default() -> Self {
+        FxaaSettings {
+            enabled: true,
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            subpixel: 0.75,
+        }
+    }
+}
+
+impl FxaaSettings {
+    #[cfg(feature = "egui")]
+    /// Threshold and subpixel controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.edge_threshold, 0.063..=0.333).text("Edge threshold"));
+        ui.add(egui::Slider::new(&mut self.edge_threshold_min, 0.0..=0.0833).text("Minimum contrast"));
+        ui.add(egui::Slider::new(&mut self.subpixel, 0.0..=1.0).text("Subpixel"));
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    edge_threshold: f32,
+    edge_threshold_min: f32,
+    subpixel: f32,
+    linear_input: f32,
+}
+
+/// Fast approximate antialiasing: smooths edges found by luma contrast in the final image, a
+/// cheap alternative or complement to MSAA that also catches shading and alpha-tested edges.
+/// Runs on display-referred color, after tonemapping.
+pub struct Fxaa {
+    pub settings: FxaaSettings,
+    pipeline: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+    linear_input: bool,
+}
+
+impl Fxaa {
+    /// Reads and writes textures of `format`.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "fxaa", include_str!("../shaders/fxaa.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("fxaa layout"),
+            entries: &[uniform_entry(0), sampler_entry(1), texture_entry(2)],
+        });
+        let pipeline = FullscreenPipeline::new(device, "fxaa", &module, "fs_main", &[&layout], format, None);
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("fxaa params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Fxaa {
+            settings: FxaaSettings::default(),
+            pipeline,
+            layout,
+            sampler: linear_sampler(device),
+            uniform,
+            linear_input: format.describe().srgb,
+        }
+    }
+
+    /// Writes `color`, antialiased, to `output`.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        let settings = self.settings;
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&Params {
+                edge_threshold: settings.edge_threshold.max(0.0),
+                edge_threshold_min: settings.edge_threshold_min.max(0.0),
+                subpixel: settings.subpixel.clamp(0.0, 1.0),
+                linear_input: if self.linear_input { 1.0 } else { 0.0 },
+            }),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("fxaa bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(color),
+                },
+            ],
+        });
+        self.pipeline.draw(encoder, output, &[&bind_group], Some(wgpu::Color::BLACK));
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn name(&self) -> &str {
+        "FXAA"
+    }
+
+    fn shader_file(&self) -> Option<&'static str> {
+        Some("fxaa.wgsl")
+    }
+
+    fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.pipeline.set_module(device, module);
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Display
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+
+    fn render(&mut self, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        Fxaa::render(self, context.device, context.queue, context.encoder, input, output);
+    }
+
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.settings.ui(ui);
+    }
+}
@@ -0,0 +1,245 @@
+pub mod auto_exposure;
+pub mod bloom;
+pub mod color_grading;
+pub mod contact_shadows;
+pub mod dither;
+pub mod dof;
+pub mod fxaa;
+pub mod motion_blur;
+pub mod motion_vectors;
+pub mod outline;
+pub mod screen_droplets;
+pub mod screen_flash;
+pub mod stack;
+pub mod tonemap;
+pub mod upscale;
+pub mod vignette;
+pub mod volumetric_fog;
+
+/// Vertex stage shared by every fullscreen pass; fragment shaders are appended to it and take a
+/// `FullscreenOutput` with `uv` in [0, 1], origin top left.
+pub const FULLSCREEN_SHADER: &str = include_str!("../shaders/fullscreen.wgsl");
+
+/// A render pipeline that draws a single fullscreen triangle.
+pub struct FullscreenPipeline {
+    pipeline: wgpu::RenderPipeline,
+    label: String,
+    // kept for `set_module`
+    layout: wgpu::PipelineLayout,
+    entry_point: String,
+    target: wgpu::ColorTargetState,
+    sample_count: u32,
+}
+
+impl FullscreenPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        module: &wgpu::ShaderModule,
+        entry_point: &str,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        format: wgpu::TextureFormat,
+        blend: Option<wgpu::BlendState>,
+    ) -> Self {
+        let target = wgpu::ColorTargetState {
+            format,
+            blend,
+            write_mask: wgpu::ColorWrites::ALL,
+        };
+        Self::multisampled(device, label, module, entry_point, bind_group_layouts, target, 1)
+    }
+
+    /// Like `new`, for drawing into targets of `sample_count` samples.
+    pub fn multisampled(
+        device: &wgpu::Device,
+        label: &str,
+        module: &wgpu::ShaderModule,
+        entry_point: &str,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        target: wgpu::ColorTargetState,
+        sample_count: u32,
+    ) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create(device, label, &layout, module, entry_point, target.clone(), sample_count);
+
+        FullscreenPipeline {
+            pipeline,
+            label: label.into(),
+            layout,
+            entry_point: entry_point.into(),
+            target,
+            sample_count,
+        }
+    }
+
+    /// Rebuilds the pipeline from `module`, an edited version of the one it was created with.
+    pub fn set_module(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.pipeline = Self::create(
+            device,
+            &self.label,
+            &self.layout,
+            module,
+            &self.entry_point,
+            self.target.clone(),
+            self.sample_count,
+        );
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::PipelineLayout,
+        module: &wgpu::ShaderModule,
+        entry_point: &str,
+        target: wgpu::ColorTargetState,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point,
+                targets: &[target],
+            }),
+            multiview: None,
+        })
+    }
+
+    /// Draws into `target`. With `clear` unset the existing contents are kept, for blending.
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView, bind_groups: &[&wgpu::BindGroup], clear: Option<wgpu::Color>) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&self.label),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            pass.set_bind_group(index as u32, bind_group, &[]);
+        }
+        pass.draw(0..3, 0..1);
+    }
+}
+
+/// Compiles a fragment shader together with the fullscreen vertex stage.
+pub fn fullscreen_module(device: &wgpu::Device, label: &str, fragment_source: &str) -> wgpu::ShaderModule {
+    device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", FULLSCREEN_SHADER, fragment_source).into()),
+    })
+}
+
+pub fn uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+pub fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+pub fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+pub fn depth_texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Depth,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+pub fn linear_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("postprocess sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+/// A screen-sized intermediate texture that passes render into and later sample from.
+pub struct RenderTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTarget {
+    pub fn new(device: &wgpu::Device, label: &str, width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        RenderTarget {
+            texture,
+            view,
+            width,
+            height,
+        }
+    }
+}
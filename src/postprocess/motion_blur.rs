@@ -0,0 +1,256 @@
+use glam::Mat4;
+use wgpu::util::DeviceExt;
+
+use super::{
+    depth_texture_entry, fullscreen_module, linear_sampler, sampler_entry,
+    motion_vectors::{MotionVectors, MOTION_VECTORS_SHADER},
+    stack::{PostContext, PostEffect, PostStage},
+    texture_entry, uniform_entry, FullscreenPipeline,
+};
+
+/// Fills velocity textures, the per-pixel screen-space motion in uv units per frame (current
+/// minus previous position), with the camera's motion, as if the scene were static. Object
+/// pipelines then draw their own velocities over it with depth testing, like
+/// `MeshRenderer::render_velocity`. Consumed by motion blur and TAA.
+pub struct CameraVelocity {
+    pipeline: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    uniform: wgpu::Buffer,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct CameraParams {
+    inverse_view_projection: [[f32; 4]; 4],
+    previous_view_projection: [[f32; 4]; 4],
+}
+
+impl CameraVelocity {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let module = fullscreen_module(device, "camera velocity", include_str!("../shaders/velocity.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("camera velocity layout"),
+            entries: &[uniform_entry(0), depth_texture_entry(1)],
+        });
+        let pipeline = FullscreenPipeline::new(device, "camera velocity", &module, "fs_main", &[&layout], Self::FORMAT, None);
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("camera velocity params"),
+            contents: bytemuck::bytes_of(&CameraParams {
+                inverse_view_projection: Mat4::IDENTITY.to_cols_array_2d(),
+                previous_view_projection: Mat4::IDENTITY.to_cols_array_2d(),
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        CameraVelocity {
+            pipeline,
+            layout,
+            uniform,
+        }
+    }
+
+    /// Overwrites `output` with the velocity every pixel of `depth` would have if only the camera
+    /// moved between `previous_view_projection` and `view_projection`.
+    #[allow(clippy::too_many_arguments)]
+    /// Writes `color` blurred along the velocity of `motion_vectors`, from
+    /// `MotionVectors::bind_group`, into `output`, all of `size`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        view_projection: Mat4,
+        previous_view_projection: Mat4,
+    ) {
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&CameraParams {
+                inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+                previous_view_projection: previous_view_projection.to_cols_array_2d(),
+            }),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("camera velocity bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(depth),
+                },
+            ],
+        });
+        self.pipeline.draw(encoder, output, &[&bind_group], Some(wgpu::Color::TRANSPARENT));
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MotionBlurSettings {
+    pub enabled: bool,
+    /// Like a film camera: 180 degrees exposes for half the frame time.
+    pub shutter_angle: f32,
+    /// Longest blur streak in pixels.
+    pub max_length: f32,
+}
+
+#[cfg(feature = "egui")]
+impl MotionBlurSettings {
+    /// Shutter and streak length controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.shutter_angle, 0.0..=360.0).text("Shutter angle"));
+        ui.add(egui::Slider::new(&mut self.max_length, 0.0..=64.0).text("Max length"));
+    }
+}
+
+impl Default for MotionBlurSettings {
+    fn default() -> Self {
+        MotionBlurSettings {
+            enabled: true,
+            shutter_angle: 180.0,
+            max_length: 32.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlurParams {
+    texel_size: [f32; 2],
+    shutter: f32,
+    max_length: f32,
+}
+
+/// Blurs the HDR scene along its velocities. As a `PostEffect` it reads the velocity the engine
+/// renders for it, from the camera and the meshes.
+pub struct MotionBlur {
+    pub settings: MotionBlurSettings,
+    pipeline: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+}
+
+impl MotionBlur {
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let source = format!("{}\n{}", MOTION_VECTORS_SHADER, include_str!("../shaders/motion_blur.wgsl"));
+        let module = fullscreen_module(device, "motion blur", &source);
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("motion blur layout"),
+            entries: &[uniform_entry(0), sampler_entry(1), texture_entry(2)],
+        });
+        let motion_vectors_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("motion blur motion vectors layout"),
+            entries: &MotionVectors::layout_entries(),
+        });
+        let pipeline = FullscreenPipeline::new(
+            device,
+            "motion blur",
+            &module,
+            "fs_main",
+            &[&layout, &motion_vectors_layout],
+            output_format,
+            None,
+        );
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("motion blur params"),
+            size: std::mem::size_of::<BlurParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        MotionBlur {
+            settings: MotionBlurSettings::default(),
+            pipeline,
+            layout,
+            sampler: linear_sampler(device),
+            uniform,
+        }
+    }
+
+    /// Writes `color` blurred along `velocity`, both of `size`, into `output`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        motion_vectors: &wgpu::BindGroup,
+        (width, height): (u32, u32),
+        output: &wgpu::TextureView,
+    ) {
+        let settings = self.settings;
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&BlurParams {
+                texel_size: [1.0 / width.max(1) as f32, 1.0 / height.max(1) as f32],
+                shutter: if settings.enabled { settings.shutter_angle / 360.0 } else { 0.0 },
+                max_length: settings.max_length.max(0.0),
+            }),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("motion blur bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(color),
+                },
+            ],
+        });
+        self.pipeline.draw(encoder, output, &[&bind_group, motion_vectors], Some(wgpu::Color::BLACK));
+    }
+}
+
+impl PostEffect for MotionBlur {
+    fn name(&self) -> &str {
+        "Motion blur"
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Hdr
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+
+    fn reads_velocity(&self) -> bool {
+        true
+    }
+
+    fn render(&mut self, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        // the stack only runs velocity-reading effects when there is velocity
+        let Some(motion_vectors) = context.motion_vectors else {
+            return;
+        };
+        MotionBlur::render(self, context.device, context.queue, context.encoder, input, motion_vectors, context.size, output);
+    }
+
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.settings.ui(ui);
+    }
+}
@@ -0,0 +1,217 @@
+use std::{collections::HashMap, hash::Hash};
+
+use glam::Mat4;
+use wgpu::util::DeviceExt;
+
+use super::{texture_entry, uniform_entry};
+use crate::camera::Camera;
+
+/// WGSL for post effects that reproject using the velocity buffer and camera history. Put it
+/// before the fragment source, which calls its functions; it binds at `MOTION_VECTORS_GROUP`
+/// with `MotionVectors::layout_entries`, leaving group 0 to the effect itself.
+pub const MOTION_VECTORS_SHADER: &str = include_str!("../shaders/motion_vectors.wgsl");
+
+/// Bind group index `MOTION_VECTORS_SHADER` declares its bindings at.
+pub const MOTION_VECTORS_GROUP: u32 = 1;
+
+/// A camera's matrices this frame and last frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameMatrices {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub previous_view: Mat4,
+    pub previous_projection: Mat4,
+}
+
+impl FrameMatrices {
+    /// Matrices for a camera that didn't move, e.g. on its first frame.
+    pub fn still(view: Mat4, projection: Mat4) -> Self {
+        FrameMatrices {
+            view,
+            projection,
+            previous_view: view,
+            previous_projection: projection,
+        }
+    }
+
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection * self.view
+    }
+
+    pub fn previous_view_projection(&self) -> Mat4 {
+        self.previous_projection * self.previous_view
+    }
+
+    /// Maps this frame's clip space to last frame's for static geometry.
+    pub fn reprojection(&self) -> Mat4 {
+        self.previous_view_projection() * self.view_projection().inverse()
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MatricesUniform {
+    view: [[f32; 4]; 4],
+    projection: [[f32; 4]; 4],
+    view_projection: [[f32; 4]; 4],
+    inverse_view_projection: [[f32; 4]; 4],
+    previous_view: [[f32; 4]; 4],
+    previous_projection: [[f32; 4]; 4],
+    previous_view_projection: [[f32; 4]; 4],
+    reprojection: [[f32; 4]; 4],
+}
+
+impl From<&FrameMatrices> for MatricesUniform {
+    fn from(matrices: &FrameMatrices) -> Self {
+        MatricesUniform {
+            view: matrices.view.to_cols_array_2d(),
+            projection: matrices.projection.to_cols_array_2d(),
+            view_projection: matrices.view_projection().to_cols_array_2d(),
+            inverse_view_projection: matrices.view_projection().inverse().to_cols_array_2d(),
+            previous_view: matrices.previous_view.to_cols_array_2d(),
+            previous_projection: matrices.previous_projection.to_cols_array_2d(),
+            previous_view_projection: matrices.previous_view_projection().to_cols_array_2d(),
+            reprojection: matrices.reprojection().to_cols_array_2d(),
+        }
+    }
+}
+
+/// Remembers a camera's matrices from one frame to the next. Keep one per camera.
+#[derive(Clone, Debug, Default)]
+pub struct CameraHistory {
+    previous: Option<(Mat4, Mat4)>,
+}
+
+impl CameraHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// This frame's matrices for `camera` rendering into a target of `target_size`, paired with
+    /// last frame's. Call once per frame.
+    pub fn advance(&mut self, camera: &Camera, target_size: (u32, u32)) -> FrameMatrices {
+        let (_, _, width, height) = camera.viewport.pixels(target_size);
+        let view = camera.view();
+        let projection = camera.projection.matrix(width as f32 / height as f32);
+        let (previous_view, previous_projection) = self.previous.unwrap_or((view, projection));
+        self.previous = Some((view, projection));
+        FrameMatrices {
+            view,
+            projection,
+            previous_view,
+            previous_projection,
+        }
+    }
+
+    /// Forgets the previous frame, so a camera cut doesn't produce one frame of huge velocities.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// Last frame's model matrix of every moving object, for object pipelines that write their own
+/// velocities.
+pub struct ObjectHistory<K> {
+    transforms: HashMap<K, (Mat4, u64)>,
+    frame: u64,
+}
+
+impl<K: Copy + Eq + Hash> Default for ObjectHistory<K> {
+    fn default() -> Self {
+        ObjectHistory {
+            transforms: HashMap::new(),
+            frame: 0,
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash> ObjectHistory<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `model` for this frame and returns the one recorded last frame, or `model` itself
+    /// for objects that just appeared.
+    pub fn update(&mut self, key: K, model: Mat4) -> Mat4 {
+        self.transforms
+            .insert(key, (model, self.frame))
+            .map_or(model, |(previous, _)| previous)
+    }
+
+    /// Ends the frame, forgetting objects that weren't updated in it.
+    pub fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.transforms.retain(|_, (_, seen)| *seen == frame);
+        self.frame += 1;
+    }
+}
+
+/// This and last frame's camera matrices with a velocity texture, as one bind group that post
+/// effects read at `MOTION_VECTORS_GROUP`. The engine keeps one for its camera, renders the
+/// velocity into the frame graph and hands effects that `reads_velocity` the bind group as
+/// `PostContext::motion_vectors`.
+pub struct MotionVectors {
+    matrices: FrameMatrices,
+    layout: wgpu::BindGroupLayout,
+    uniform: wgpu::Buffer,
+}
+
+impl MotionVectors {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("motion vectors layout"),
+            entries: &Self::layout_entries(),
+        });
+        let matrices = FrameMatrices::still(Mat4::IDENTITY, Mat4::IDENTITY);
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("frame matrices"),
+            contents: bytemuck::bytes_of(&MatricesUniform::from(&matrices)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        MotionVectors {
+            matrices,
+            layout,
+            uniform,
+        }
+    }
+
+    /// The entries `MOTION_VECTORS_SHADER` expects. Effects build their pipelines with a layout
+    /// of these, which the engine's bind group fits.
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+        [uniform_entry(0), texture_entry(1)]
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// The matrices from the last `update`.
+    pub fn matrices(&self) -> &FrameMatrices {
+        &self.matrices
+    }
+
+    /// Uploads this frame's matrices.
+    pub fn update(&mut self, queue: &wgpu::Queue, matrices: FrameMatrices) {
+        self.matrices = matrices;
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&MatricesUniform::from(&matrices)));
+    }
+
+    /// The matrices with `velocity`, this frame's `CameraVelocity::FORMAT` texture.
+    pub fn bind_group(&self, device: &wgpu::Device, velocity: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("motion vectors bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(velocity),
+                },
+            ],
+        })
+    }
+}
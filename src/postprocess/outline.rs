@@ -0,0 +1,271 @@
+use wgpu::util::DeviceExt;
+
+use super::{fullscreen_module, texture_entry, FullscreenPipeline, RenderTarget};
+
+/// Largest outline the jump flood can reach, in pixels.
+const MAX_THICKNESS: f32 = 1024.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct OutlineStyle {
+    /// Straight alpha; alpha below 1 lets the scene show through.
+    pub color: [f32; 4],
+    /// Outline width in pixels outside the selected silhouette.
+    pub thickness: f32,
+}
+
+impl OutlineStyle {
+    #[cfg(feature = "egui")]
+    /// Color and thickness controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            ui.color_edit_button_rgba_unmultiplied(&mut self.color);
+        });
+        ui.add(egui::Slider::new(&mut self.thickness, 0.0..=32.0).text("Thickness"));
+    }
+}
+
+impl Default for OutlineStyle {
+    fn default() -> Self {
+        OutlineStyle {
+            color: [1.0, 0.6, 0.1, 1.0],
+            thickness: 3.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct StyleParams {
+    color: [f32; 4],
+    thickness: f32,
+    _padding: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct JumpParams {
+    step: f32,
+    _padding: [f32; 3],
+}
+
+/// Outlines whatever was drawn into the mask, e.g. the editor selection or an interactable the
+/// player is looking at. Use one `Outline` per style; each keeps its own mask.
+pub struct Outline {
+    pub style: OutlineStyle,
+    seed: FullscreenPipeline,
+    jump: FullscreenPipeline,
+    composite: FullscreenPipeline,
+    seed_layout: wgpu::BindGroupLayout,
+    jump_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    style_uniform: wgpu::Buffer,
+    /// One uniform per power-of-two step, largest first.
+    steps: Vec<(u32, wgpu::Buffer)>,
+    mask: RenderTarget,
+    seeds: [RenderTarget; 2],
+}
+
+impl Outline {
+    pub const MASK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+    // pixel coordinates need more precision than half floats have past 2048
+    const SEED_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg32Float;
+
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        let module = fullscreen_module(device, "outline", include_str!("../shaders/outline.wgsl"));
+        let uniform = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let seeds = wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = |label, entries: &[wgpu::BindGroupLayoutEntry]| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries,
+            })
+        };
+        let seed_layout = layout("outline seed layout", &[texture_entry(1)]);
+        let jump_layout = layout("outline jump layout", &[seeds, uniform(3)]);
+        let composite_layout = layout("outline composite layout", &[uniform(0), texture_entry(1), seeds]);
+
+        let seed = FullscreenPipeline::new(device, "outline seed", &module, "fs_seed", &[&seed_layout], Self::SEED_FORMAT, None);
+        let jump = FullscreenPipeline::new(device, "outline jump", &module, "fs_jump", &[&jump_layout], Self::SEED_FORMAT, None);
+        let composite = FullscreenPipeline::new(
+            device,
+            "outline composite",
+            &module,
+            "fs_composite",
+            &[&composite_layout],
+            output_format,
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+        );
+
+        let style_uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("outline style"),
+            size: std::mem::size_of::<StyleParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let steps = (0..=(MAX_THICKNESS as u32).trailing_zeros())
+            .rev()
+            .map(|power| {
+                let step = 1 << power;
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("outline jump step"),
+                    contents: bytemuck::bytes_of(&JumpParams {
+                        step: step as f32,
+                        _padding: [0.0; 3],
+                    }),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                (step, buffer)
+            })
+            .collect();
+        let (mask, seeds) = Self::create_targets(device, width, height);
+
+        Outline {
+            style: OutlineStyle::default(),
+            seed,
+            jump,
+            composite,
+            seed_layout,
+            jump_layout,
+            composite_layout,
+            style_uniform,
+            steps,
+            mask,
+            seeds,
+        }
+    }
+
+    fn create_targets(device: &wgpu::Device, width: u32, height: u32) -> (RenderTarget, [RenderTarget; 2]) {
+        (
+            RenderTarget::new(device, "outline mask", width, height, Self::MASK_FORMAT),
+            [
+                RenderTarget::new(device, "outline seeds a", width, height, Self::SEED_FORMAT),
+                RenderTarget::new(device, "outline seeds b", width, height, Self::SEED_FORMAT),
+            ],
+        )
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if self.mask.width != width || self.mask.height != height {
+            let (mask, seeds) = Self::create_targets(device, width, height);
+            self.mask = mask;
+            self.seeds = seeds;
+        }
+    }
+
+    /// Clears the mask and starts a pass to draw the highlighted objects into, with any
+    /// pipeline writing 1 to `MASK_FORMAT`. Pass `depth` to only outline the visible parts,
+    /// with depth writes disabled in the mask pipeline.
+    pub fn begin_mask_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        depth: Option<&'a wgpu::TextureView>,
+    ) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("outline mask"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &self.mask.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: depth.map(|view| wgpu::RenderPassDepthStencilAttachment {
+                view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        })
+    }
+
+    /// The mask, for drawing into with a pass of your own instead of `begin_mask_pass`, like
+    /// `MeshRenderer::render_mask` does.
+    pub fn mask(&self) -> &wgpu::TextureView {
+        &self.mask.view
+    }
+
+    /// Draws the outline of the mask over `output`.
+    pub fn render(&self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        let thickness = self.style.thickness.clamp(0.0, MAX_THICKNESS);
+        if thickness <= 0.0 || self.style.color[3] <= 0.0 {
+            return;
+        }
+        queue.write_buffer(
+            &self.style_uniform,
+            0,
+            bytemuck::bytes_of(&StyleParams {
+                color: self.style.color,
+                thickness,
+                _padding: [0.0; 3],
+            }),
+        );
+
+        let bind_group = |layout, entries: &[wgpu::BindGroupEntry]| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("outline bind group"),
+                layout,
+                entries,
+            })
+        };
+        let view = |binding, view| wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        };
+
+        let seed_group = bind_group(&self.seed_layout, &[view(1, &self.mask.view)]);
+        self.seed.draw(encoder, &self.seeds[0].view, &[&seed_group], None);
+
+        // flooding from the largest step that still matters down to 1
+        let reach = thickness.ceil() as u32 + 1;
+        let mut current = 0;
+        for (_, uniform) in self.steps.iter().filter(|(step, _)| *step < reach.next_power_of_two()) {
+            let group = bind_group(
+                &self.jump_layout,
+                &[
+                    view(2, &self.seeds[current].view),
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: uniform.as_entire_binding(),
+                    },
+                ],
+            );
+            self.jump.draw(encoder, &self.seeds[1 - current].view, &[&group], None);
+            current = 1 - current;
+        }
+
+        let composite_group = bind_group(
+            &self.composite_layout,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.style_uniform.as_entire_binding(),
+                },
+                view(1, &self.mask.view),
+                view(2, &self.seeds[current].view),
+            ],
+        );
+        self.composite.draw(encoder, output, &[&composite_group], None);
+    }
+}
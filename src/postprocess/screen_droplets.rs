@@ -0,0 +1,145 @@
+use bytemuck::Zeroable;
+use wgpu::util::DeviceExt;
+
+use super::{fullscreen_module, linear_sampler, sampler_entry, texture_entry, uniform_entry, FullscreenPipeline};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ScreenDropletSettings {
+    pub enabled: bool,
+    /// UV offset a droplet's edge refracts by; larger looks like thicker drops.
+    pub refraction: f32,
+    /// Droplet cells across the screen height; more gives smaller drops.
+    pub density: f32,
+    /// Seconds for the screen to fill up in full rain.
+    pub build_up_time: f32,
+    /// Seconds for the droplets to dry off once out of the rain.
+    pub dry_time: f32,
+}
+
+impl Default for ScreenDropletSettings {
+    fn default() -> Self {
+        ScreenDropletSettings {
+            enabled: true,
+            refraction: 0.04,
+            density: 12.0,
+            build_up_time: 4.0,
+            dry_time: 6.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    aspect: f32,
+    time: f32,
+    coverage: f32,
+    refraction: f32,
+    density: f32,
+    _padding: [f32; 3],
+}
+
+/// Raindrops running down the lens, refracting the scene behind them. They build up while
+/// `update` is given rain and dry off after, so walking under a roof clears them gradually.
+/// Reads the scene color and writes the result to a separate output.
+pub struct ScreenDroplets {
+    pub settings: ScreenDropletSettings,
+    coverage: f32,
+    time: f32,
+    pipeline: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+}
+
+impl ScreenDroplets {
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "screen droplets", include_str!("../shaders/screen_droplets.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("screen droplets layout"),
+            entries: &[uniform_entry(0), sampler_entry(1), texture_entry(2)],
+        });
+        let pipeline = FullscreenPipeline::new(device, "screen droplets", &module, "fs_main", &[&layout], output_format, None);
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("screen droplets params"),
+            contents: bytemuck::bytes_of(&Params::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        ScreenDroplets {
+            settings: ScreenDropletSettings::default(),
+            coverage: 0.0,
+            time: 0.0,
+            pipeline,
+            layout,
+            sampler: linear_sampler(device),
+            uniform,
+        }
+    }
+
+    /// `rain` is how hard it rains on the camera right now, 0 to 1: `Weather::rain`, scaled
+    /// down when the camera is sheltered or looking at the ground.
+    pub fn update(&mut self, dt: f32, rain: f32) {
+        self.time += dt;
+        let rain = rain.clamp(0.0, 1.0);
+        if rain > self.coverage {
+            self.coverage = (self.coverage + dt / self.settings.build_up_time.max(0.001)).min(rain);
+        } else {
+            self.coverage = (self.coverage - dt / self.settings.dry_time.max(0.001)).max(rain);
+        }
+    }
+
+    /// How much of the screen is covered, from 0 to 1.
+    pub fn coverage(&self) -> f32 {
+        self.coverage
+    }
+
+    /// Clears the lens at once, e.g. after a cut.
+    pub fn clear(&mut self) {
+        self.coverage = 0.0;
+    }
+
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        (width, height): (u32, u32),
+        output: &wgpu::TextureView,
+    ) {
+        let settings = self.settings;
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&Params {
+                aspect: width.max(1) as f32 / height.max(1) as f32,
+                // wrapped so the hash inputs keep their precision in long sessions
+                time: self.time % 1000.0,
+                coverage: if settings.enabled { self.coverage } else { 0.0 },
+                refraction: settings.refraction,
+                density: settings.density.max(1.0),
+                _padding: [0.0; 3],
+            }),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("screen droplets bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(color),
+                },
+            ],
+        });
+        self.pipeline.draw(encoder, output, &[&bind_group], Some(wgpu::Color::BLACK));
+    }
+}
@@ -0,0 +1,121 @@
+use super::{
+    fullscreen_module,
+    stack::{PostContext, PostEffect, PostStage},
+    texture_entry, uniform_entry, FullscreenPipeline,
+};
+use crate::camera_shake::Impulse;
+
+/// A full-screen color flash that fades out, e.g. white for an explosion or red for damage.
+/// Runs late, on the tonemapped image; as a `PostEffect` it is skipped while not flashing.
+pub struct ScreenFlash {
+    pub enabled: bool,
+    color: [f32; 3],
+    impulse: Impulse,
+    pipeline: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    uniform: wgpu::Buffer,
+}
+
+impl ScreenFlash {
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "screen flash", include_str!("../shaders/screen_flash.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("screen flash layout"),
+            entries: &[uniform_entry(0), texture_entry(1)],
+        });
+        let pipeline = FullscreenPipeline::new(device, "screen flash", &module, "fs_main", &[&layout], output_format, None);
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("screen flash color"),
+            size: std::mem::size_of::<[f32; 4]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        ScreenFlash {
+            enabled: true,
+            color: [1.0; 3],
+            impulse: Impulse::default(),
+            pipeline,
+            layout,
+            uniform,
+        }
+    }
+
+    /// Starts a flash at `opacity` that fades out over `duration` seconds. A weaker flash does
+    /// not cut off a stronger one that is still fading.
+    pub fn flash(&mut self, color: [f32; 3], opacity: f32, duration: f32) {
+        let opacity = opacity.clamp(0.0, 1.0);
+        if opacity >= self.impulse.value() {
+            self.color = color;
+        }
+        self.impulse.trigger(opacity, duration);
+    }
+
+    /// Fades the flash; the engine calls it every frame for the one in its post stack.
+    pub fn update(&mut self, dt: f32) {
+        self.impulse.update(dt);
+    }
+
+    /// Writes `color` with the flash mixed in into `output`.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        let [r, g, b] = self.color;
+        let opacity = self.impulse.value();
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&[r, g, b, opacity]));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("screen flash bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(color),
+                },
+            ],
+        });
+        self.pipeline.draw(encoder, output, &[&bind_group], None);
+    }
+}
+
+impl PostEffect for ScreenFlash {
+    fn name(&self) -> &str {
+        "Screen flash"
+    }
+
+    fn shader_file(&self) -> Option<&'static str> {
+        Some("screen_flash.wgsl")
+    }
+
+    fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.pipeline.set_module(device, module);
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Display
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn is_idle(&self) -> bool {
+        self.impulse.value() <= 0.0
+    }
+
+    fn render(&mut self, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        ScreenFlash::render(self, context.device, context.queue, context.encoder, input, output);
+    }
+}
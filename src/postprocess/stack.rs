@@ -0,0 +1,179 @@
+use std::any::Any;
+
+use super::motion_vectors::FrameMatrices;
+use crate::{camera::Camera, lights::FrameLight};
+
+/// Where in the frame an effect runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostStage {
+    /// On the HDR scene, in `Tonemapper::HDR_FORMAT`, before tonemapping.
+    Hdr,
+    /// On the tonemapped image, in the surface's format, before the UI.
+    Display,
+}
+
+/// What an effect gets to render with.
+pub struct PostContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    /// Size of the input and output, in pixels.
+    pub size: (u32, u32),
+    /// The camera the scene was rendered from.
+    pub camera: &'a Camera,
+    /// The camera's matrices this frame and last.
+    pub matrices: FrameMatrices,
+    /// The frame's lights.
+    pub lights: &'a [FrameLight],
+    /// The scene's single-sampled depth, always there for effects that `reads_depth`.
+    pub depth: Option<&'a wgpu::TextureView>,
+    /// The scene's screen-space motion, in `CameraVelocity::FORMAT`, always there for effects
+    /// that `reads_velocity`.
+    pub velocity: Option<&'a wgpu::TextureView>,
+    /// `matrices` and `velocity` bound for `MOTION_VECTORS_SHADER` at `MOTION_VECTORS_GROUP`,
+    /// there whenever `velocity` is.
+    pub motion_vectors: Option<&'a wgpu::BindGroup>,
+}
+
+/// One effect in a `PostStack`: reads the previous effect's output and writes all of its own,
+/// both textures of its stage's format.
+pub trait PostEffect: Any {
+    fn name(&self) -> &str;
+
+    fn stage(&self) -> PostStage;
+
+    fn enabled(&self) -> bool;
+
+    fn set_enabled(&mut self, enabled: bool);
+
+    /// Whether an enabled effect has nothing to do this frame, like a flash that faded out;
+    /// skipped like a disabled one.
+    fn is_idle(&self) -> bool {
+        false
+    }
+
+    /// Whether the effect needs `PostContext::depth`. The engine skips such effects in frames
+    /// without it: when nothing drew depth, or the scene is multisampled.
+    fn reads_depth(&self) -> bool {
+        false
+    }
+
+    /// Whether the effect needs `PostContext::velocity`, which the engine renders only in frames
+    /// with such an effect, and only when there is depth to render it against.
+    fn reads_velocity(&self) -> bool {
+        false
+    }
+
+    /// File of the effect's fragment shader in `shader_reload::SHADER_DIRECTORY`, which the
+    /// engine watches while it runs. `None` for effects that can't be reloaded.
+    fn shader_file(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Rebuilds the effect's pipelines from `module`, its edited `shader_file` after
+    /// `FULLSCREEN_SHADER`.
+    fn set_shader(&mut self, _device: &wgpu::Device, _module: &wgpu::ShaderModule) {}
+
+    /// Writes `input` with the effect applied into `output`, overwriting all of it.
+    fn render(&mut self, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView);
+
+    /// The effect's settings, for the settings panel.
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, _ui: &mut egui::Ui) {}
+}
+
+/// The post effects the engine runs every frame, in order within each stage, each on the output
+/// of the one before through offscreen textures. Disabled effects are skipped entirely.
+#[derive(Default)]
+pub struct PostStack {
+    effects: Vec<Box<dyn PostEffect>>,
+}
+
+impl PostStack {
+    pub fn new() -> Self {
+        PostStack::default()
+    }
+
+    /// Adds `effect` after the others of its stage.
+    pub fn with(mut self, effect: impl PostEffect + 'static) -> Self {
+        self.push(effect);
+        self
+    }
+
+    pub fn push(&mut self, effect: impl PostEffect + 'static) {
+        self.effects.push(Box::new(effect));
+    }
+
+    /// Inserts `effect` at `index` among all effects, e.g. before one found with `position`.
+    pub fn insert(&mut self, index: usize, effect: impl PostEffect + 'static) {
+        self.effects.insert(index.min(self.effects.len()), Box::new(effect));
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn PostEffect>> {
+        let index = self.position(name)?;
+        Some(self.effects.remove(index))
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.effects.iter().position(|effect| effect.name() == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut (dyn PostEffect + 'static)> {
+        self.effects.iter_mut().find(|effect| effect.name() == name).map(|effect| effect.as_mut())
+    }
+
+    /// The first effect of type `T`, for its own API, e.g. `ColorGrading::set_lut`.
+    pub fn find_mut<T: PostEffect>(&mut self) -> Option<&mut T> {
+        self.effects.iter_mut().find_map(|effect| (effect.as_mut() as &mut dyn Any).downcast_mut::<T>())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut (dyn PostEffect + 'static)> {
+        self.effects.iter_mut().map(|effect| effect.as_mut())
+    }
+
+    /// Turns the effect called `name` on or off; false if there is none.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        self.get_mut(name).map(|effect| effect.set_enabled(enabled)).is_some()
+    }
+
+    /// Indices of the enabled effects of `stage`, in the order they run.
+    pub fn active(&self, stage: PostStage) -> Vec<usize> {
+        (0..self.effects.len())
+            .filter(|&index| {
+                let effect = &self.effects[index];
+                effect.stage() == stage && effect.enabled() && !effect.is_idle()
+            })
+            .collect()
+    }
+
+    pub fn name(&self, index: usize) -> &str {
+        self.effects[index].name()
+    }
+
+    pub fn reads_depth(&self, index: usize) -> bool {
+        self.effects[index].reads_depth()
+    }
+
+    pub fn reads_velocity(&self, index: usize) -> bool {
+        self.effects[index].reads_velocity()
+    }
+
+    /// Renders the effect at `index`, as returned by `active`.
+    pub fn render(&mut self, index: usize, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        self.effects[index].render(context, input, output);
+    }
+
+    #[cfg(feature = "egui")]
+    /// A checkbox per effect, with its settings below, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        for effect in &mut self.effects {
+            let mut enabled = effect.enabled();
+            if ui.checkbox(&mut enabled, effect.name()).changed() {
+                effect.set_enabled(enabled);
+            }
+            if enabled {
+                ui.indent(effect.name().to_owned(), |ui| effect.ui(ui));
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use wgpu::util::DeviceExt;
+
+use super::{fullscreen_module, texture_entry, uniform_entry, FullscreenPipeline};
+
+/// The curve HDR brightness is compressed into display range with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TonemapOperator {
+    /// Filmic, with a toe and a soft shoulder; highlights desaturate towards white.
+    #[default]
+    Aces,
+    /// Keeps hues; everything up to `TonemapSettings::white_point` stays below white.
+    Reinhard,
+    /// Clamps to 1, for comparing against the rendered values.
+    Clamp,
+}
+
+impl TonemapOperator {
+    pub const ALL: [TonemapOperator; 3] = [TonemapOperator::Aces, TonemapOperator::Reinhard, TonemapOperator::Clamp];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TonemapOperator::Aces => "ACES",
+            TonemapOperator::Reinhard => "Reinhard",
+            TonemapOperator::Clamp => "Clamp",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TonemapSettings {
+    pub operator: TonemapOperator,
+    /// Brightness in stops applied before the curve; 0 leaves the scene as rendered.
+    pub exposure: f32,
+    /// Scene brightness `Reinhard` maps to white.
+    pub white_point: f32,
+}
+
+impl Default for TonemapSettings {
+    fn default() -> Self {
+        TonemapSettings {
+            operator: TonemapOperator::Aces,
+            exposure: 0.0,
+            white_point: 4.0,
+        }
+    }
+}
+
+impl TonemapSettings {
+    #[cfg(feature = "egui")]
+    /// Operator and exposure controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Operator")
+            .selected_text(self.operator.name())
+            .show_ui(ui, |ui| {
+                for operator in TonemapOperator::ALL {
+                    ui.selectable_value(&mut self.operator, operator, operator.name());
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.exposure, -6.0..=6.0).text("Exposure (stops)"));
+        if self.operator == TonemapOperator::Reinhard {
+            ui.add(egui::Slider::new(&mut self.white_point, 1.0..=16.0).text("White point"));
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    operator: u32,
+    exposure: f32,
+    white_point: f32,
+    _padding: f32,
+}
+
+/// Maps the HDR scene, rendered in `HDR_FORMAT`, into the display-referred output. Runs after
+/// the HDR post effects and before anything drawn in display range, like the UI.
+pub struct Tonemapper {
+    pub settings: TonemapSettings,
+    pipeline: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    uniform: wgpu::Buffer,
+    // an exposure of 1, bound when nothing is metering the scene
+    unmetered: wgpu::Buffer,
+}
+
+impl Tonemapper {
+    /// Format of the scene before tonemapping.
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "tonemap", include_str!("../shaders/tonemap.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tonemap layout"),
+            entries: &[uniform_entry(0), texture_entry(1), uniform_entry(2)],
+        });
+        let entry_point = if output_format.describe().srgb { "fs_linear" } else { "fs_encode" };
+        let pipeline = FullscreenPipeline::new(device, "tonemap", &module, entry_point, &[&layout], output_format, None);
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("tonemap params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let unmetered = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("unmetered exposure"),
+            contents: bytemuck::cast_slice(&[0.0f32, 1.0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        Tonemapper {
+            settings: TonemapSettings::default(),
+            pipeline,
+            layout,
+            uniform,
+            unmetered,
+        }
+    }
+
+    /// Rebuilds the pipeline from `module`, an edited `shaders/tonemap.wgsl` after
+    /// `FULLSCREEN_SHADER`.
+    pub fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.pipeline.set_module(device, module);
+    }
+
+    /// Tonemaps `hdr` into `output`, which must be the same size, overwriting all of it.
+    /// `metered` is `AutoExposure::exposure_buffer`, applied on top of `settings.exposure`.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        metered: Option<&wgpu::Buffer>,
+    ) {
+        let params = Params {
+            operator: self.settings.operator as u32,
+            exposure: 2f32.powf(self.settings.exposure),
+            white_point: self.settings.white_point.max(1.0),
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&params));
+        // `hdr` is usually a transient texture, so it is bound anew each frame
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tonemap bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(hdr),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: metered.unwrap_or(&self.unmetered).as_entire_binding(),
+                },
+            ],
+        });
+        self.pipeline.draw(encoder, output, &[&bind_group], None);
+    }
+}
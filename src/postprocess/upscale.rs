@@ -0,0 +1,142 @@
+use super::{fullscreen_module, linear_sampler, sampler_entry, texture_entry, uniform_entry, FullscreenPipeline};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpscaleFilter {
+    Bilinear,
+    /// Bilinear followed by contrast-adaptive sharpening. `sharpness` runs from 0 (none) to 1.
+    Sharpened { sharpness: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ResolutionSettings {
+    /// Internal resolution as a fraction of the output, per axis.
+    pub scale: f32,
+    pub filter: UpscaleFilter,
+}
+
+impl Default for ResolutionSettings {
+    fn default() -> Self {
+        ResolutionSettings {
+            scale: 1.0,
+            filter: UpscaleFilter::Sharpened { sharpness: 0.5 },
+        }
+    }
+}
+
+impl ResolutionSettings {
+    pub const MIN_SCALE: f32 = 0.25;
+
+    #[cfg(feature = "egui")]
+    /// Scale and filter controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.scale, Self::MIN_SCALE..=1.0).text("Render scale"));
+        let mut sharpened = matches!(self.filter, UpscaleFilter::Sharpened { .. });
+        if ui.checkbox(&mut sharpened, "Sharpen").changed() {
+            self.filter = if sharpened { UpscaleFilter::Sharpened { sharpness: 0.5 } } else { UpscaleFilter::Bilinear };
+        }
+        if let UpscaleFilter::Sharpened { sharpness } = &mut self.filter {
+            ui.add(egui::Slider::new(sharpness, 0.0..=1.0).text("Sharpness"));
+        }
+    }
+
+    /// Internal render size for an output of `output_size`.
+    pub fn internal_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        let scale = self.scale.clamp(Self::MIN_SCALE, 1.0);
+        (
+            ((width as f32 * scale).round() as u32).max(1),
+            ((height as f32 * scale).round() as u32).max(1),
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    output_texel: [f32; 2],
+    sharpness: f32,
+    _padding: f32,
+}
+
+/// Scales the scene, rendered at `settings.internal_size`, up to the output. The engine sizes
+/// the scene's targets from the settings and upscales after tonemapping, since the sharpening
+/// expects display values, and before the display effects and the UI, which stay at full
+/// resolution.
+pub struct ResolutionScaler {
+    pub settings: ResolutionSettings,
+    bilinear: FullscreenPipeline,
+    sharpen: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+}
+
+impl ResolutionScaler {
+    /// Writes textures of `output_format`, the surface's or whatever the upscale writes to.
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "upscale", include_str!("../shaders/upscale.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("upscale layout"),
+            entries: &[uniform_entry(0), sampler_entry(1), texture_entry(2)],
+        });
+        let bilinear = FullscreenPipeline::new(device, "upscale bilinear", &module, "fs_bilinear", &[&layout], output_format, None);
+        let sharpen = FullscreenPipeline::new(device, "upscale sharpen", &module, "fs_sharpen", &[&layout], output_format, None);
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("upscale params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        ResolutionScaler {
+            settings: ResolutionSettings::default(),
+            bilinear,
+            sharpen,
+            layout,
+            sampler: linear_sampler(device),
+            uniform,
+        }
+    }
+
+    /// Scales `input` up to all of `output`, which is `output_size`.
+    pub fn upscale(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        (output_width, output_height): (u32, u32),
+    ) {
+        let (pipeline, sharpness) = match self.settings.filter {
+            UpscaleFilter::Bilinear => (&self.bilinear, 0.0),
+            UpscaleFilter::Sharpened { sharpness } => (&self.sharpen, sharpness.clamp(0.0, 1.0)),
+        };
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&Params {
+                output_texel: [1.0 / output_width.max(1) as f32, 1.0 / output_height.max(1) as f32],
+                sharpness,
+                _padding: 0.0,
+            }),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("upscale bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+            ],
+        });
+        pipeline.draw(encoder, output, &[&bind_group], Some(wgpu::Color::BLACK));
+    }
+}
@@ -0,0 +1,166 @@
+use super::{
+    fullscreen_module, texture_entry, uniform_entry,
+    stack::{PostContext, PostEffect, PostStage},
+    FullscreenPipeline,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct VignetteSettings {
+    pub enabled: bool,
+    /// How far the corners are pulled towards `color`, 0 to 1.
+    pub intensity: f32,
+    /// Distance from the center where the falloff starts, 1 being the middle of the nearer
+    /// edge.
+    pub radius: f32,
+    /// Width of the falloff beyond `radius`.
+    pub smoothness: f32,
+    /// 1 keeps the falloff circular on any screen shape, 0 stretches it into an ellipse.
+    pub roundness: f32,
+    /// Linear RGB the corners fade to; black darkens them.
+    pub color: [f32; 3],
+}
+
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        VignetteSettings {
+            enabled: true,
+            intensity: 0.35,
+            radius: 0.6,
+            smoothness: 0.9,
+            roundness: 1.0,
+            color: [0.0; 3],
+        }
+    }
+}
+
+impl VignetteSettings {
+    #[cfg(feature = "egui")]
+    /// Shape and strength controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.intensity, 0.0..=1.0).text("Intensity"));
+        ui.add(egui::Slider::new(&mut self.radius, 0.0..=1.5).text("Radius"));
+        ui.add(egui::Slider::new(&mut self.smoothness, 0.01..=2.0).text("Smoothness"));
+        ui.add(egui::Slider::new(&mut self.roundness, 0.0..=1.0).text("Roundness"));
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            ui.color_edit_button_rgb(&mut self.color);
+        });
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    color: [f32; 4],
+    intensity: f32,
+    radius: f32,
+    smoothness: f32,
+    roundness: f32,
+}
+
+/// Darkens, or tints, the image towards its corners. Runs on the HDR scene, before tonemapping,
+/// so bright highlights in the corners still roll off naturally.
+pub struct Vignette {
+    pub settings: VignetteSettings,
+    pipeline: FullscreenPipeline,
+    layout: wgpu::BindGroupLayout,
+    uniform: wgpu::Buffer,
+}
+
+impl Vignette {
+    /// Reads and writes textures of `format`.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "vignette", include_str!("../shaders/vignette.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("vignette layout"),
+            entries: &[uniform_entry(0), texture_entry(1)],
+        });
+        let pipeline = FullscreenPipeline::new(device, "vignette", &module, "fs_main", &[&layout], format, None);
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("vignette params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Vignette {
+            settings: VignetteSettings::default(),
+            pipeline,
+            layout,
+            uniform,
+        }
+    }
+
+    /// Writes `color`, the size of `output`, with the vignette applied to `output`.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+    ) {
+        let settings = self.settings;
+        let [r, g, b] = settings.color;
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::bytes_of(&Params {
+                color: [r, g, b, 1.0],
+                intensity: settings.intensity.clamp(0.0, 1.0),
+                radius: settings.radius.max(0.0),
+                smoothness: settings.smoothness.max(0.0),
+                roundness: settings.roundness.clamp(0.0, 1.0),
+            }),
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("vignette bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(color),
+                },
+            ],
+        });
+        self.pipeline.draw(encoder, output, &[&bind_group], Some(wgpu::Color::BLACK));
+    }
+}
+
+impl PostEffect for Vignette {
+    fn name(&self) -> &str {
+        "Vignette"
+    }
+
+    fn shader_file(&self) -> Option<&'static str> {
+        Some("vignette.wgsl")
+    }
+
+    fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.pipeline.set_module(device, module);
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Hdr
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+
+    fn render(&mut self, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        Vignette::render(self, context.device, context.queue, context.encoder, input, output);
+    }
+
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.settings.ui(ui);
+    }
+}
@@ -0,0 +1,476 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use super::{
+    depth_texture_entry, fullscreen_module, linear_sampler, sampler_entry,
+    stack::{PostContext, PostEffect, PostStage},
+    texture_entry, uniform_entry, FullscreenPipeline,
+};
+use crate::{
+    camera::Camera,
+    lights::{FrameLight, LightKind},
+};
+
+/// Froxel grid resolution and shaft sample count. Lower tiers use bigger cells, which mostly
+/// shows as softer edges where fog meets geometry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FogQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl FogQuality {
+    /// Screen pixels per froxel column.
+    pub fn tile_size(self) -> u32 {
+        match self {
+            FogQuality::Low => 16,
+            FogQuality::Medium | FogQuality::High => 8,
+        }
+    }
+
+    pub fn slices(self) -> u32 {
+        match self {
+            FogQuality::Low => 32,
+            FogQuality::Medium => 64,
+            FogQuality::High => 128,
+        }
+    }
+
+    fn shaft_samples(self) -> u32 {
+        match self {
+            FogQuality::Low => 16,
+            FogQuality::Medium => 32,
+            FogQuality::High => 64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VolumetricFogSettings {
+    pub enabled: bool,
+    pub quality: FogQuality,
+    /// Extinction per world unit at `base_height` and below.
+    pub density: f32,
+    /// How quickly the fog thins out above `base_height`.
+    pub height_falloff: f32,
+    pub base_height: f32,
+    /// Henyey-Greenstein anisotropy: 0 scatters evenly, towards 1 glows around the sun.
+    pub anisotropy: f32,
+    /// How far from the camera the froxel grid reaches. Fog beyond it is not rendered.
+    pub distance: f32,
+    /// Sky light scattered by the fog regardless of the sun.
+    pub ambient: Vec3,
+    /// How strongly geometry between a pixel and the sun darkens the scattering; 0 disables
+    /// light shafts.
+    pub shaft_strength: f32,
+}
+
+impl VolumetricFogSettings {
+    #[cfg(feature = "egui")]
+    /// Quality, density and scattering controls, for the settings panel.
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Quality")
+            .selected_text(format!("{:?}", self.quality))
+            .show_ui(ui, |ui| {
+                for quality in [FogQuality::Low, FogQuality::Medium, FogQuality::High] {
+                    ui.selectable_value(&mut self.quality, quality, format!("{:?}", quality));
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.density, 0.0..=0.2).text("Density"));
+        ui.add(egui::Slider::new(&mut self.height_falloff, 0.0..=1.0).text("Height falloff"));
+        ui.add(egui::Slider::new(&mut self.base_height, -50.0..=50.0).text("Base height"));
+        ui.add(egui::Slider::new(&mut self.anisotropy, -0.9..=0.9).text("Anisotropy"));
+        ui.add(egui::Slider::new(&mut self.distance, 10.0..=500.0).text("Distance"));
+        ui.add(egui::Slider::new(&mut self.shaft_strength, 0.0..=1.0).text("Light shafts"));
+    }
+}
+
+impl Default for VolumetricFogSettings {
+    fn default() -> Self {
+        VolumetricFogSettings {
+            enabled: true,
+            quality: FogQuality::Medium,
+            density: 0.02,
+            height_falloff: 0.1,
+            base_height: 0.0,
+            anisotropy: 0.6,
+            distance: 150.0,
+            ambient: Vec3::splat(0.1),
+            shaft_strength: 0.8,
+        }
+    }
+}
+
+/// The view the fog is rendered for.
+#[derive(Clone, Copy, Debug)]
+pub struct FogCamera {
+    pub view_projection: Mat4,
+    pub position: Vec3,
+    pub forward: Vec3,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl FogCamera {
+    pub fn new(camera: &Camera, target_size: (u32, u32)) -> Self {
+        let (near, far) = camera.projection.depth_range();
+        FogCamera {
+            view_projection: camera.view_projection(target_size),
+            position: camera.position,
+            forward: camera.forward,
+            near,
+            far,
+        }
+    }
+}
+
+/// The directional light that scatters through the fog.
+#[derive(Clone, Copy, Debug)]
+pub struct FogLight {
+    /// Direction the light travels in.
+    pub direction: Vec3,
+    /// Color times intensity, in the same units as the HDR scene.
+    pub color: Vec3,
+}
+
+impl FogLight {
+    /// The first directional light of `lights`, or darkness without one.
+    pub fn sun(lights: &[FrameLight]) -> Self {
+        lights
+            .iter()
+            .find(|light| light.kind == LightKind::Directional)
+            .map_or(FogLight { direction: -Vec3::Y, color: Vec3::ZERO }, |light| FogLight {
+                direction: light.direction,
+                color: light.color,
+            })
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    inverse_view_projection: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    camera_forward: [f32; 4],
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    ambient: [f32; 4],
+    grid: [f32; 4],
+    near: f32,
+    far: f32,
+    distance: f32,
+    density: f32,
+    height_falloff: f32,
+    base_height: f32,
+    anisotropy: f32,
+    shaft_strength: f32,
+    sun_uv: [f32; 2],
+    sun_visibility: f32,
+    shaft_samples: f32,
+}
+
+struct Volumes {
+    grid: (u32, u32, u32),
+    injected: wgpu::TextureView,
+    integrated: wgpu::TextureView,
+}
+
+/// Froxel-based volumetric fog with height falloff and in-scattering from one directional light.
+/// Runs on the HDR scene before tonemapping. The volume passes are compute shaders; check
+/// `Capabilities::compute` first. As a `PostEffect` it scatters the first directional light of
+/// the frame.
+pub struct VolumetricFog {
+    pub settings: VolumetricFogSettings,
+    inject: wgpu::ComputePipeline,
+    integrate: wgpu::ComputePipeline,
+    apply: FullscreenPipeline,
+    inject_layout: wgpu::BindGroupLayout,
+    integrate_layout: wgpu::BindGroupLayout,
+    apply_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+    volumes: Option<Volumes>,
+}
+
+impl VolumetricFog {
+    const VOLUME_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let compute_uniform = wgpu::BindGroupLayoutEntry {
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ..uniform_entry(0)
+        };
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: Self::VOLUME_FORMAT,
+                view_dimension: wgpu::TextureViewDimension::D3,
+            },
+            count: None,
+        };
+        let volume = |binding, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D3,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = |label, entries: &[wgpu::BindGroupLayoutEntry]| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries,
+            })
+        };
+        let inject_layout = layout("fog inject layout", &[compute_uniform, storage(1)]);
+        let integrate_layout = layout(
+            "fog integrate layout",
+            &[compute_uniform, volume(2, wgpu::ShaderStages::COMPUTE), storage(3)],
+        );
+        let apply_layout = layout(
+            "fog apply layout",
+            &[
+                uniform_entry(0),
+                sampler_entry(1),
+                texture_entry(2),
+                depth_texture_entry(3),
+                volume(4, wgpu::ShaderStages::FRAGMENT),
+            ],
+        );
+
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("../shaders/volumetric_fog.wgsl"));
+        let compute = |label, layout, entry_point| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let inject = compute("fog inject", &inject_layout, "inject");
+        let integrate = compute("fog integrate", &integrate_layout, "integrate");
+
+        let module = fullscreen_module(device, "fog apply", include_str!("../shaders/volumetric_fog_apply.wgsl"));
+        let apply = FullscreenPipeline::new(device, "fog apply", &module, "fs_main", &[&apply_layout], output_format, None);
+
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("fog params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        VolumetricFog {
+            settings: VolumetricFogSettings::default(),
+            inject,
+            integrate,
+            apply,
+            inject_layout,
+            integrate_layout,
+            apply_layout,
+            sampler: linear_sampler(device),
+            uniform,
+            volumes: None,
+        }
+    }
+
+    fn ensure_volumes(&mut self, device: &wgpu::Device, width: u32, height: u32) -> (u32, u32, u32) {
+        let quality = self.settings.quality;
+        let grid = (
+            width.div_ceil(quality.tile_size()).max(1),
+            height.div_ceil(quality.tile_size()).max(1),
+            quality.slices(),
+        );
+        if !matches!(&self.volumes, Some(volumes) if volumes.grid == grid) {
+            let create = |label| {
+                device
+                    .create_texture(&wgpu::TextureDescriptor {
+                        label: Some(label),
+                        size: wgpu::Extent3d {
+                            width: grid.0,
+                            height: grid.1,
+                            depth_or_array_layers: grid.2,
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D3,
+                        format: Self::VOLUME_FORMAT,
+                        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+                    })
+                    .create_view(&wgpu::TextureViewDescriptor::default())
+            };
+            self.volumes = Some(Volumes {
+                grid,
+                injected: create("fog injected"),
+                integrated: create("fog integrated"),
+            });
+        }
+        grid
+    }
+
+    /// Writes `color` with fog applied to `output`. When disabled this is a plain copy so the
+    /// pass can stay in the chain.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        color: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        (width, height): (u32, u32),
+        camera: &FogCamera,
+        light: &FogLight,
+        output: &wgpu::TextureView,
+    ) {
+        let settings = self.settings;
+        let grid = self.ensure_volumes(device, width, height);
+
+        // the sun is a point at infinity opposite the light direction
+        let sun = camera.view_projection * (-light.direction).extend(0.0);
+        let (sun_uv, sun_visibility) = if sun.w > 0.0 {
+            let uv = [sun.x / sun.w * 0.5 + 0.5, 0.5 - sun.y / sun.w * 0.5];
+            // fade shafts out as the sun leaves the screen
+            let outside = uv.iter().map(|c| (c - c.clamp(0.0, 1.0)).abs()).fold(0.0, f32::max);
+            (uv, (1.0 - outside * 2.0).max(0.0))
+        } else {
+            ([0.0; 2], 0.0)
+        };
+
+        let params = Params {
+            inverse_view_projection: camera.view_projection.inverse().to_cols_array_2d(),
+            camera_position: camera.position.extend(1.0).into(),
+            camera_forward: camera.forward.normalize_or_zero().extend(0.0).into(),
+            light_direction: light.direction.normalize_or_zero().extend(0.0).into(),
+            light_color: light.color.extend(0.0).into(),
+            ambient: settings.ambient.extend(0.0).into(),
+            grid: Vec4::new(grid.0 as f32, grid.1 as f32, grid.2 as f32, 0.0).into(),
+            near: camera.near,
+            far: camera.far,
+            distance: settings.distance.max(camera.near * 2.0),
+            density: if settings.enabled { settings.density.max(0.0) } else { 0.0 },
+            height_falloff: settings.height_falloff.max(0.0),
+            base_height: settings.base_height,
+            anisotropy: settings.anisotropy.clamp(-0.99, 0.99),
+            shaft_strength: settings.shaft_strength.clamp(0.0, 1.0),
+            sun_uv,
+            sun_visibility,
+            shaft_samples: if settings.enabled { settings.quality.shaft_samples() as f32 } else { 0.0 },
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&params));
+
+        let volumes = self.volumes.as_ref().unwrap();
+        let bind_group = |layout, entries: &[wgpu::BindGroupEntry]| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("fog bind group"),
+                layout,
+                entries,
+            })
+        };
+        let uniform = wgpu::BindGroupEntry {
+            binding: 0,
+            resource: self.uniform.as_entire_binding(),
+        };
+        let view = |binding, view| wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        };
+
+        let inject_group = bind_group(&self.inject_layout, &[uniform.clone(), view(1, &volumes.injected)]);
+        let integrate_group = bind_group(
+            &self.integrate_layout,
+            &[uniform.clone(), view(2, &volumes.injected), view(3, &volumes.integrated)],
+        );
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("volumetric fog"),
+            });
+            pass.set_pipeline(&self.inject);
+            pass.set_bind_group(0, &inject_group, &[]);
+            pass.dispatch(grid.0.div_ceil(4), grid.1.div_ceil(4), grid.2.div_ceil(4));
+            pass.set_pipeline(&self.integrate);
+            pass.set_bind_group(0, &integrate_group, &[]);
+            pass.dispatch(grid.0.div_ceil(8), grid.1.div_ceil(8), 1);
+        }
+
+        let apply_group = bind_group(
+            &self.apply_layout,
+            &[
+                uniform,
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                view(2, color),
+                view(3, depth),
+                view(4, &volumes.integrated),
+            ],
+        );
+        self.apply.draw(encoder, output, &[&apply_group], Some(wgpu::Color::BLACK));
+    }
+}
+
+impl PostEffect for VolumetricFog {
+    fn name(&self) -> &str {
+        "Volumetric fog"
+    }
+
+    fn shader_file(&self) -> Option<&'static str> {
+        Some("volumetric_fog_apply.wgsl")
+    }
+
+    fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.apply.set_module(device, module);
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Hdr
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+
+    fn reads_depth(&self) -> bool {
+        true
+    }
+
+    fn render(&mut self, context: &mut PostContext, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        // the stack only runs depth-reading effects when there is depth
+        let Some(depth) = context.depth else {
+            return;
+        };
+        let camera = FogCamera::new(context.camera, context.size);
+        let light = FogLight::sun(context.lights);
+        VolumetricFog::render(
+            self,
+            context.device,
+            context.queue,
+            context.encoder,
+            input,
+            depth,
+            context.size,
+            &camera,
+            &light,
+            output,
+        );
+    }
+
+    #[cfg(feature = "egui")]
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        self.settings.ui(ui);
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+/// Where a game keeps its files, following the platform's conventions instead of the working
+/// directory, which depends on how the game was started:
+///
+/// | | Linux | Windows | macOS |
+/// |---|---|---|---|
+/// | config | `$XDG_CONFIG_HOME/<app>` | `%APPDATA%\<org>\<app>\config` | `~/Library/Application Support/<app>` |
+/// | cache | `$XDG_CACHE_HOME/<app>` | `%LOCALAPPDATA%\<org>\<app>\cache` | `~/Library/Caches/<app>` |
+/// | data | `$XDG_DATA_HOME/<app>` | `%APPDATA%\<org>\<app>\data` | `~/Library/Application Support/<app>` |
+///
+/// with the XDG directories defaulting to `~/.config`, `~/.cache` and `~/.local/share`. Without
+/// a home directory everything goes into a `user` directory next to the executable.
+///
+/// Assets ship with the game rather than belonging to the user, so they are looked up from the
+/// executable instead, see `assets_dir`.
+#[derive(Clone, Debug)]
+pub struct ProjectDirs {
+    config: PathBuf,
+    cache: PathBuf,
+    data: PathBuf,
+    assets: PathBuf,
+}
+
+impl ProjectDirs {
+    /// The directories of `application`, published by `organization`, which only Windows puts
+    /// in the path and may be empty.
+    pub fn new(organization: &str, application: &str) -> Self {
+        let executable_dir = executable_dir();
+        let (config, cache, data) = user_dirs(organization, application).unwrap_or_else(|| {
+            let user = executable_dir.join("user");
+            (user.join("config"), user.join("cache"), user.join("data"))
+        });
+        ProjectDirs {
+            config,
+            cache,
+            data,
+            assets: find_assets(&executable_dir),
+        }
+    }
+
+    /// Looks for assets in `directory` instead, e.g. one given on the command line.
+    pub fn with_assets(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.assets = directory.into();
+        self
+    }
+
+    /// Settings the player changes, kept across updates.
+    pub fn config_dir(&self) -> &Path {
+        &self.config
+    }
+
+    /// Files that can be rebuilt when missing, like compiled shaders.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache
+    }
+
+    /// Files the game writes and needs back, like saves.
+    pub fn data_dir(&self) -> &Path {
+        &self.data
+    }
+
+    /// For `save::slots::SaveSlots::new`.
+    pub fn saves_dir(&self) -> PathBuf {
+        self.data.join("saves")
+    }
+
+    /// For `crash::install`.
+    pub fn crash_reports_dir(&self) -> PathBuf {
+        self.data.join("crash_reports")
+    }
+
+    /// The first `assets` directory found next to the executable or in one of its parents,
+    /// which also finds the project's own from `target/debug` during development. Next to the
+    /// executable if there is none.
+    pub fn assets_dir(&self) -> &Path {
+        &self.assets
+    }
+
+    /// `path` within the assets directory; absolute paths are kept as they are.
+    pub fn asset(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.assets.join(path)
+    }
+}
+
+fn executable_dir() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|executable| executable.parent().map(Path::to_path_buf))
+        .unwrap_or_default()
+}
+
+fn find_assets(executable_dir: &Path) -> PathBuf {
+    executable_dir
+        .ancestors()
+        .map(|directory| directory.join("assets"))
+        .find(|assets| assets.is_dir())
+        .unwrap_or_else(|| executable_dir.join("assets"))
+}
+
+/// Only absolute paths count; a relative one would again depend on the working directory.
+fn env_dir(variable: &str) -> Option<PathBuf> {
+    std::env::var_os(variable).map(PathBuf::from).filter(|path| path.is_absolute())
+}
+
+#[cfg(target_os = "windows")]
+fn user_dirs(organization: &str, application: &str) -> Option<(PathBuf, PathBuf, PathBuf)> {
+    let roaming = env_dir("APPDATA")?.join(organization).join(application);
+    let local = env_dir("LOCALAPPDATA")?.join(organization).join(application);
+    Some((roaming.join("config"), local.join("cache"), roaming.join("data")))
+}
+
+#[cfg(target_os = "macos")]
+fn user_dirs(_organization: &str, application: &str) -> Option<(PathBuf, PathBuf, PathBuf)> {
+    let library = env_dir("HOME")?.join("Library");
+    let support = library.join("Application Support").join(application);
+    Some((support.clone(), library.join("Caches").join(application), support))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn user_dirs(_organization: &str, application: &str) -> Option<(PathBuf, PathBuf, PathBuf)> {
+    let home = env_dir("HOME");
+    let xdg = |variable: &str, fallback: &str| env_dir(variable).or_else(|| Some(home.as_ref()?.join(fallback)));
+    Some((
+        xdg("XDG_CONFIG_HOME", ".config")?.join(application),
+        xdg("XDG_CACHE_HOME", ".cache")?.join(application),
+        xdg("XDG_DATA_HOME", ".local/share")?.join(application),
+    ))
+}
@@ -0,0 +1,309 @@
+use std::{collections::HashMap, hash::Hasher, ops::Range};
+
+use glam::Vec3;
+
+use crate::{
+    replay::StateHasher,
+    save::{Persist, SaveError, SaveReader, SaveWriter},
+};
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+const DEFAULT_STREAM: u64 = 1_442_695_040_888_963_407;
+
+/// Spreads the bits of similar seeds, e.g. 1, 2, 3, so the generators they start don't begin
+/// with similar output (splitmix64's finalizer).
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// A small, fast generator (PCG32) whose output depends on nothing but its seed, on every
+/// platform, so anything built on it replays exactly. Not for cryptography.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng::with_stream(seed, DEFAULT_STREAM)
+    }
+
+    /// Generators with the same seed but different `stream`s produce unrelated sequences.
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng = Rng {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(mix(seed));
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `range`, without the bias of taking a remainder. Panics if `range` is empty.
+    pub fn range_u32(&mut self, range: Range<u32>) -> u32 {
+        assert!(range.start < range.end, "empty range {:?}", range);
+        let span = range.end - range.start;
+        // Lemire's method: retry the few values that would make some results more likely
+        let mut product = self.next_u32() as u64 * span as u64;
+        if (product as u32) < span {
+            let threshold = span.wrapping_neg() % span;
+            while (product as u32) < threshold {
+                product = self.next_u32() as u64 * span as u64;
+            }
+        }
+        range.start + (product >> 32) as u32
+    }
+
+    /// Uniform in `range`. Panics if `range` is empty.
+    pub fn range_i32(&mut self, range: Range<i32>) -> i32 {
+        assert!(range.start < range.end, "empty range {:?}", range);
+        let span = range.end.wrapping_sub(range.start) as u32;
+        range.start.wrapping_add(self.range_u32(0..span) as i32)
+    }
+
+    /// Uniform in `[min, max)`.
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// True with the given probability.
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        Some(&items[self.range_u32(0..items.len() as u32) as usize])
+    }
+
+    /// An index into `weights`, each picked in proportion to its weight. `None` if no weight is
+    /// positive.
+    pub fn weighted(&mut self, weights: &[f32]) -> Option<usize> {
+        let total: f32 = weights.iter().filter(|weight| **weight > 0.0).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut target = self.next_f32() * total;
+        for (index, &weight) in weights.iter().enumerate().filter(|(_, weight)| **weight > 0.0) {
+            if target < weight {
+                return Some(index);
+            }
+            target -= weight;
+        }
+        // rounding can leave a sliver past the last weight
+        weights.iter().rposition(|weight| *weight > 0.0)
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for index in (1..items.len()).rev() {
+            items.swap(index, self.range_u32(0..index as u32 + 1) as usize);
+        }
+    }
+
+    /// A uniformly distributed unit vector.
+    pub fn direction(&mut self) -> Vec3 {
+        let z = self.next_f32() * 2.0 - 1.0;
+        let angle = self.next_f32() * std::f32::consts::TAU;
+        let radius = (1.0 - z * z).sqrt();
+        Vec3::new(radius * angle.cos(), radius * angle.sin(), z)
+    }
+
+    /// A uniformly distributed point inside the unit sphere.
+    pub fn in_unit_sphere(&mut self) -> Vec3 {
+        self.direction() * self.next_f32().cbrt()
+    }
+}
+
+impl Persist for Rng {
+    fn save(&self, writer: &mut SaveWriter) {
+        writer.write_u64(self.state);
+        writer.write_u64(self.increment);
+    }
+
+    fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+        Ok(Rng {
+            state: reader.read_u64()?,
+            increment: reader.read_u64()? | 1,
+        })
+    }
+}
+
+/// The engine's source of randomness: one seed, and an independent named stream per user, e.g.
+/// "loot", "particles" or "terrain". A stream's sequence only depends on the seed and its name,
+/// so adding a roll to one system doesn't change what every other system rolls, and the replay
+/// reseeding it before `Simulation::reset` makes a whole session replay exactly.
+#[derive(Clone, Debug)]
+pub struct Random {
+    seed: u64,
+    streams: HashMap<String, Rng>,
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Random::new(0)
+    }
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random {
+            seed,
+            streams: HashMap::new(),
+        }
+    }
+
+    /// A seed that differs every run, for sessions that don't need to be reproducible. Record it
+    /// to be able to replay the session anyway.
+    pub fn entropy_seed() -> u64 {
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        mix(time ^ ((std::process::id() as u64) << 32))
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts every stream from `seed`.
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.streams.clear();
+    }
+
+    /// The stream called `name`, started the first time it is asked for.
+    pub fn stream(&mut self, name: &str) -> &mut Rng {
+        if !self.streams.contains_key(name) {
+            let mut hasher = StateHasher::new();
+            hasher.write(name.as_bytes());
+            self.streams.insert(name.to_owned(), Rng::with_stream(self.seed, hasher.finish()));
+        }
+        self.streams.get_mut(name).unwrap()
+    }
+
+    /// A generator of its own, seeded from the next value of the stream called `name`, for
+    /// something that keeps it around, like a particle emitter. Stays deterministic as long as
+    /// things are forked in the same order.
+    pub fn fork(&mut self, name: &str) -> Rng {
+        Rng::new(self.stream(name).next_u64())
+    }
+}
+
+impl Persist for Random {
+    fn save(&self, writer: &mut SaveWriter) {
+        writer.write_u64(self.seed);
+        let mut streams: Vec<_> = self.streams.iter().collect();
+        streams.sort_by_key(|(name, _)| *name);
+        writer.write_u32(streams.len() as u32);
+        for (name, rng) in streams {
+            writer.write_str(name);
+            rng.save(writer);
+        }
+    }
+
+    fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+        let mut random = Random::new(reader.read_u64()?);
+        for _ in 0..reader.read_u32()? {
+            let name = reader.read_str()?.to_owned();
+            random.streams.insert(name, Rng::load(reader)?);
+        }
+        Ok(random)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take(rng: &mut Rng, count: usize) -> Vec<u32> {
+        (0..count).map(|_| rng.next_u32()).collect()
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        assert_eq!(take(&mut Rng::new(42), 16), take(&mut Rng::new(42), 16));
+        assert_ne!(take(&mut Rng::new(42), 16), take(&mut Rng::new(43), 16));
+        assert_ne!(take(&mut Rng::with_stream(42, 1), 16), take(&mut Rng::with_stream(42, 2), 16));
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let mut random = Random::new(7);
+        let loot = take(random.stream("loot"), 8);
+
+        // rolling another stream first doesn't change what "loot" rolls
+        let mut other = Random::new(7);
+        take(other.stream("particles"), 100);
+        assert_eq!(take(other.stream("loot"), 8), loot);
+        assert_ne!(take(other.stream("terrain"), 8), loot);
+
+        // nor does asking for it again, which continues it
+        let mut again = Random::new(7);
+        let mut expected = take(again.stream("loot"), 4);
+        expected.extend(take(again.stream("loot"), 4));
+        assert_eq!(expected, loot);
+
+        random.reseed(7);
+        assert_eq!(take(random.stream("loot"), 8), loot);
+    }
+
+    #[test]
+    fn forks_are_deterministic() {
+        let mut a = Random::new(3);
+        let mut b = Random::new(3);
+        assert_eq!(take(&mut a.fork("emitter"), 8), take(&mut b.fork("emitter"), 8));
+        assert_ne!(take(&mut a.fork("emitter"), 8), take(&mut Random::new(3).fork("emitter"), 8));
+    }
+
+    #[test]
+    fn ranges() {
+        let mut rng = Rng::new(1);
+        for _ in 0..1000 {
+            assert!((10..13).contains(&rng.range_u32(10..13)));
+            assert!((-5..5).contains(&rng.range_i32(-5..5)));
+            let value = rng.next_f32();
+            assert!((0.0..1.0).contains(&value));
+        }
+        assert_eq!(rng.weighted(&[0.0, -1.0]), None);
+        assert_eq!(rng.weighted(&[0.0, 2.0, 0.0]), Some(1));
+    }
+
+    #[test]
+    fn persists() {
+        let mut random = Random::new(11);
+        take(random.stream("loot"), 3);
+        let mut writer = SaveWriter::new();
+        random.save(&mut writer);
+        let bytes = writer.into_bytes();
+        let mut loaded = Random::load(&mut SaveReader::new(&bytes)).unwrap();
+        assert_eq!(loaded.seed(), 11);
+        assert_eq!(take(loaded.stream("loot"), 8), take(random.stream("loot"), 8));
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    future::Future,
+    io::Write,
+    num::NonZeroU32,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+/// Tightly packed texels copied back from a texture, top row first.
+#[derive(Clone, Debug)]
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub bytes: Vec<u8>,
+}
+
+impl TextureData {
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.format.describe().block_size as usize
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &[u8] {
+        let size = self.bytes_per_pixel();
+        let start = (y as usize * self.width as usize + x as usize) * size;
+        &self.bytes[start..start + size]
+    }
+
+    /// The first channel of a 32-bit integer texel, e.g. an object ID from a picking buffer.
+    pub fn texel_u32(&self, x: u32, y: u32) -> u32 {
+        let pixel = self.pixel(x, y);
+        u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]])
+    }
+
+    /// RGBA pixels of an 8-bit RGBA or BGRA texture, `None` for other formats.
+    pub fn rgba8(&self) -> Option<Vec<[u8; 4]>> {
+        use wgpu::TextureFormat::*;
+        let swap = match self.format {
+            Rgba8Unorm | Rgba8UnormSrgb => false,
+            Bgra8Unorm | Bgra8UnormSrgb => true,
+            _ => return None,
+        };
+        Some(
+            self.bytes
+                .chunks_exact(4)
+                .map(|p| if swap { [p[2], p[1], p[0], p[3]] } else { [p[0], p[1], p[2], p[3]] })
+                .collect(),
+        )
+    }
+
+    /// Writes an 8-bit color texture as a binary PPM, dropping alpha, for screenshots.
+    pub fn write_ppm(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let pixels = self.rgba8().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("can't save {:?} pixels as a PPM image", self.format),
+            )
+        })?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        write!(file, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for [r, g, b, _] in pixels {
+            file.write_all(&[r, g, b])?;
+        }
+        file.flush()
+    }
+}
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+enum Callback {
+    Buffer(Box<dyn FnOnce(Result<Vec<u8>, wgpu::BufferAsyncError>)>),
+    Texture {
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        padded_bytes_per_row: u32,
+        callback: Box<dyn FnOnce(Result<TextureData, wgpu::BufferAsyncError>)>,
+    },
+}
+
+struct Request {
+    buffer: wgpu::Buffer,
+    size: wgpu::BufferAddress,
+    map: Option<MapFuture>,
+    callback: Callback,
+}
+
+/// Copies GPU data back to the CPU without stalling: the copy is recorded into this frame's
+/// encoder, the staging buffer is mapped once that work is submitted, and the callback runs
+/// from `poll` on whichever later frame the GPU has caught up by.
+#[derive(Default)]
+pub struct Readbacks {
+    requests: Vec<Request>,
+}
+
+impl Readbacks {
+    pub fn new() -> Self {
+        Readbacks::default()
+    }
+
+    /// Reads `size` bytes of `source` from `offset`; both must be multiples of
+    /// `wgpu::COPY_BUFFER_ALIGNMENT` and `source` needs `COPY_SRC` usage.
+    pub fn read_buffer(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        size: wgpu::BufferAddress,
+        callback: impl FnOnce(Result<Vec<u8>, wgpu::BufferAsyncError>) + 'static,
+    ) {
+        let buffer = staging_buffer(device, size);
+        encoder.copy_buffer_to_buffer(source, offset, &buffer, 0, size);
+        self.requests.push(Request {
+            buffer,
+            size,
+            map: None,
+            callback: Callback::Buffer(Box::new(callback)),
+        });
+    }
+
+    /// Reads a `width` by `height` region of mip 0, layer 0 of `texture` starting at `origin`.
+    /// `texture` needs `COPY_SRC` usage; combined depth-stencil and packed depth formats can't be
+    /// copied out.
+    #[allow(clippy::too_many_arguments)]
+    pub fn read_texture(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        origin: (u32, u32),
+        (width, height): (u32, u32),
+        callback: impl FnOnce(Result<TextureData, wgpu::BufferAsyncError>) + 'static,
+    ) {
+        // rows of a texture copy have to start 256 byte aligned in the buffer
+        let bytes_per_row = width * format.describe().block_size as u32;
+        let padded_bytes_per_row = bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let size = padded_bytes_per_row as u64 * height as u64;
+        let buffer = staging_buffer(device, size);
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: origin.0,
+                    y: origin.1,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.requests.push(Request {
+            buffer,
+            size,
+            map: None,
+            callback: Callback::Texture {
+                width,
+                height,
+                format,
+                padded_bytes_per_row,
+                callback: Box::new(callback),
+            },
+        });
+    }
+
+    /// Starts mapping the readbacks recorded since the last call. Call right after submitting
+    /// the command buffers they were recorded into; mapping earlier would fail validation.
+    pub fn submitted(&mut self) {
+        for request in self.requests.iter_mut().filter(|request| request.map.is_none()) {
+            request.map = Some(Box::pin(request.buffer.slice(..request.size).map_async(wgpu::MapMode::Read)));
+        }
+    }
+
+    /// Runs the callbacks of readbacks the GPU has finished. Call once per frame.
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        if self.requests.iter().all(|request| request.map.is_none()) {
+            return;
+        }
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut context = Context::from_waker(Waker::noop());
+        let mut index = 0;
+        while index < self.requests.len() {
+            let result = match self.requests[index].map.as_mut().map(|map| map.as_mut().poll(&mut context)) {
+                Some(Poll::Ready(result)) => result,
+                _ => {
+                    index += 1;
+                    continue;
+                }
+            };
+            let request = self.requests.swap_remove(index);
+            request.finish(result);
+        }
+    }
+
+    /// Readbacks whose callback hasn't run yet.
+    pub fn pending(&self) -> usize {
+        self.requests.len()
+    }
+}
+
+impl Request {
+    fn finish(self, result: Result<(), wgpu::BufferAsyncError>) {
+        let bytes = result.map(|()| {
+            let bytes = self.buffer.slice(..self.size).get_mapped_range().to_vec();
+            self.buffer.unmap();
+            bytes
+        });
+        match self.callback {
+            Callback::Buffer(callback) => callback(bytes),
+            Callback::Texture {
+                width,
+                height,
+                format,
+                padded_bytes_per_row,
+                callback,
+            } => callback(bytes.map(|padded| {
+                let bytes_per_row = (width * format.describe().block_size as u32) as usize;
+                let bytes = padded
+                    .chunks(padded_bytes_per_row as usize)
+                    .flat_map(|row| &row[..bytes_per_row])
+                    .copied()
+                    .collect();
+                TextureData {
+                    width,
+                    height,
+                    format,
+                    bytes,
+                }
+            })),
+        }
+    }
+}
+
+fn staging_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback"),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,450 @@
+use glam::{Mat4, Vec3};
+
+use crate::postprocess::{fullscreen_module, linear_sampler, sampler_entry, texture_entry, FullscreenPipeline};
+
+/// WGSL for lit shaders: declares the probes at bindings 14 to 16 of `REFLECTION_PROBE_GROUP`,
+/// after the planar reflection, and `probe_reflection(position, direction, roughness, sky)`.
+pub const REFLECTION_PROBE_SHADER: &str = include_str!("shaders/reflection_probes.wgsl");
+
+/// Bind group index `REFLECTION_PROBE_SHADER` declares its bindings at, the lighting group's.
+pub const REFLECTION_PROBE_GROUP: u32 = 2;
+
+/// Probes the shader blends between; more can exist, the nearest `MAX_ACTIVE_PROBES` to the
+/// camera are uploaded.
+pub const MAX_ACTIVE_PROBES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProbeShape {
+    /// Influence falls off with distance from the center. Suits open areas.
+    Sphere { radius: f32 },
+    /// Axis-aligned box, with the reflection ray intersected against it so walls and floors
+    /// of a room line up with the capture.
+    Box { half_extents: Vec3 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// Captured once when the probe is added.
+    OnLoad,
+    /// Captured only when `ReflectionProbes::request_capture` is called, e.g. after a door
+    /// opens or the time of day changes.
+    OnDemand,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ReflectionProbe {
+    pub position: Vec3,
+    pub shape: ProbeShape,
+    /// Width of the band inside the shape where the probe fades into its surroundings.
+    pub blend_distance: f32,
+    pub capture: CaptureMode,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl ReflectionProbe {
+    pub fn sphere(position: Vec3, radius: f32) -> Self {
+        ReflectionProbe {
+            position,
+            shape: ProbeShape::Sphere { radius },
+            blend_distance: radius * 0.2,
+            capture: CaptureMode::OnLoad,
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+
+    pub fn room(position: Vec3, half_extents: Vec3) -> Self {
+        ReflectionProbe {
+            position,
+            shape: ProbeShape::Box { half_extents },
+            blend_distance: half_extents.min_element() * 0.2,
+            capture: CaptureMode::OnLoad,
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+
+    fn volume(&self) -> f32 {
+        match self.shape {
+            ProbeShape::Sphere { radius } => radius * radius * radius * 4.19,
+            ProbeShape::Box { half_extents } => half_extents.x * half_extents.y * half_extents.z * 8.0,
+        }
+    }
+
+    /// View-projection for each cube face, in +X, -X, +Y, -Y, +Z, -Z order. The shader looks
+    /// the faces up by these views rather than cubemap addressing, so they aren't mirrored.
+    pub fn face_view_projections(&self) -> [Mat4; 6] {
+        let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, self.near, self.far);
+        let faces = [
+            (Vec3::X, Vec3::Y),
+            (-Vec3::X, Vec3::Y),
+            (Vec3::Y, -Vec3::Z),
+            (-Vec3::Y, Vec3::Z),
+            (Vec3::Z, Vec3::Y),
+            (-Vec3::Z, Vec3::Y),
+        ];
+        faces.map(|(forward, up)| projection * Mat4::look_at_rh(self.position, self.position + forward, up))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProbeId(usize);
+
+/// One face of a probe being captured; render the scene into `color` and `depth`, clearing
+/// both, color to transparent where the sky shows through.
+pub struct CaptureTarget<'a> {
+    pub color: &'a wgpu::TextureView,
+    pub depth: &'a wgpu::TextureView,
+    pub view_projection: Mat4,
+    pub position: Vec3,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuProbe {
+    position: [f32; 4],
+    extents: [f32; 4],
+    shape: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuProbeData {
+    header: [u32; 4],
+    probes: [GpuProbe; MAX_ACTIVE_PROBES],
+}
+
+struct Slot {
+    probe: ReflectionProbe,
+    dirty: bool,
+}
+
+/// Reflection probe captures, six layers per probe of one texture array, which unlike cube
+/// arrays every device supports, plus the uniform lit shaders read them from.
+pub struct ReflectionProbes {
+    resolution: u32,
+    mip_levels: u32,
+    faces: wgpu::Texture,
+    face_array: wgpu::TextureView,
+    depth: wgpu::TextureView,
+    slots: Vec<Option<Slot>>,
+    uniform: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    downsample: FullscreenPipeline,
+    downsample_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    probe_sampler: wgpu::Sampler,
+}
+
+impl ReflectionProbes {
+    /// Format of the capture color attachment.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// `resolution` is the size of each face, a power of two; `capacity` the number of probes
+    /// that can exist at once.
+    pub fn new(device: &wgpu::Device, resolution: u32, capacity: u32) -> Self {
+        let resolution = resolution.next_power_of_two().max(8);
+        // stop at 4x4 faces, past which the chain only smears the sky
+        let mip_levels = resolution.trailing_zeros() - 1;
+        let faces = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("reflection probes"),
+            size: wgpu::Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: capacity.max(1) * 6,
+            },
+            mip_level_count: mip_levels,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let face_array = faces.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("reflection probe faces"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let depth = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("reflection probe depth"),
+                size: wgpu::Extent3d {
+                    width: resolution,
+                    height: resolution,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("reflection probe data"),
+            size: std::mem::size_of::<GpuProbeData>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("reflection probe layout"),
+            entries: &Self::layout_entries(),
+        });
+        let probe_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("reflection probe sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("reflection probe bind group"),
+            layout: &layout,
+            entries: &Self::entries(&uniform, &face_array, &probe_sampler),
+        });
+
+        let module = fullscreen_module(device, "reflection probe mips", include_str!("shaders/reflection_downsample.wgsl"));
+        let downsample_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("reflection probe mip layout"),
+            entries: &[sampler_entry(0), texture_entry(1)],
+        });
+        let downsample = FullscreenPipeline::new(
+            device,
+            "reflection probe mips",
+            &module,
+            "fs_main",
+            &[&downsample_layout],
+            Self::FORMAT,
+            None,
+        );
+
+        ReflectionProbes {
+            resolution,
+            mip_levels,
+            faces,
+            face_array,
+            depth,
+            slots: (0..capacity.max(1)).map(|_| None).collect(),
+            uniform,
+            layout,
+            bind_group,
+            downsample,
+            downsample_layout,
+            sampler: linear_sampler(device),
+            probe_sampler,
+        }
+    }
+
+    fn entries<'a>(
+        uniform: &'a wgpu::Buffer,
+        face_array: &'a wgpu::TextureView,
+        sampler: &'a wgpu::Sampler,
+    ) -> [wgpu::BindGroupEntry<'a>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 14,
+                resource: uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 15,
+                resource: wgpu::BindingResource::TextureView(face_array),
+            },
+            wgpu::BindGroupEntry {
+                binding: 16,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ]
+    }
+
+    /// The entries `REFLECTION_PROBE_SHADER` expects, for building a combined lighting layout.
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 14,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 15,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            sampler_entry(16),
+        ]
+    }
+
+    /// The resources of `layout_entries`, for a combined lighting bind group.
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 3] {
+        Self::entries(&self.uniform, &self.face_array, &self.probe_sampler)
+    }
+
+    /// For lit pipelines with these bindings alone, at `REFLECTION_PROBE_GROUP`.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Six layers per probe, in the order of `ReflectionProbe::face_view_projections`.
+    pub fn faces(&self) -> &wgpu::TextureView {
+        &self.face_array
+    }
+
+    /// Returns `None` when every slot is taken.
+    pub fn add(&mut self, probe: ReflectionProbe) -> Option<ProbeId> {
+        let index = self.slots.iter().position(Option::is_none)?;
+        self.slots[index] = Some(Slot {
+            probe,
+            dirty: probe.capture == CaptureMode::OnLoad,
+        });
+        Some(ProbeId(index))
+    }
+
+    pub fn remove(&mut self, id: ProbeId) {
+        if let Some(slot) = self.slots.get_mut(id.0) {
+            *slot = None;
+        }
+    }
+
+    pub fn get_mut(&mut self, id: ProbeId) -> Option<&mut ReflectionProbe> {
+        self.slots.get_mut(id.0)?.as_mut().map(|slot| &mut slot.probe)
+    }
+
+    /// Recaptures the probe during the next `capture_pending`.
+    pub fn request_capture(&mut self, id: ProbeId) {
+        if let Some(Some(slot)) = self.slots.get_mut(id.0) {
+            slot.dirty = true;
+        }
+    }
+
+    pub fn has_pending_captures(&self) -> bool {
+        self.pending_captures() > 0
+    }
+
+    /// Probes the next `capture_pending` captures.
+    pub fn pending_captures(&self) -> usize {
+        self.slots.iter().flatten().filter(|slot| slot.dirty).count()
+    }
+
+    fn face_view(&self, layer: u32, mip: u32) -> wgpu::TextureView {
+        self.faces.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("reflection probe face"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_mip_level: mip,
+            mip_level_count: std::num::NonZeroU32::new(1),
+            base_array_layer: layer,
+            array_layer_count: std::num::NonZeroU32::new(1),
+            ..Default::default()
+        })
+    }
+
+    /// Captures every probe waiting for one: `render` draws the scene into each face, then the
+    /// mip chain is rebuilt. Captures are expensive, so keep this to load time or a few
+    /// on-demand probes per frame.
+    pub fn capture_pending(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        mut render: impl FnMut(&mut wgpu::CommandEncoder, CaptureTarget),
+    ) {
+        for index in 0..self.slots.len() {
+            let probe = match &mut self.slots[index] {
+                Some(slot) if slot.dirty => {
+                    slot.dirty = false;
+                    slot.probe
+                }
+                _ => continue,
+            };
+
+            for (face, view_projection) in probe.face_view_projections().into_iter().enumerate() {
+                let layer = index as u32 * 6 + face as u32;
+                let color = self.face_view(layer, 0);
+                render(
+                    encoder,
+                    CaptureTarget {
+                        color: &color,
+                        depth: &self.depth,
+                        view_projection,
+                        position: probe.position,
+                    },
+                );
+
+                for mip in 1..self.mip_levels {
+                    let source = self.face_view(layer, mip - 1);
+                    let target = self.face_view(layer, mip);
+                    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("reflection probe mip bind group"),
+                        layout: &self.downsample_layout,
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: wgpu::BindingResource::Sampler(&self.sampler),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 1,
+                                resource: wgpu::BindingResource::TextureView(&source),
+                            },
+                        ],
+                    });
+                    self.downsample.draw(encoder, &target, &[&bind_group], Some(wgpu::Color::BLACK));
+                }
+            }
+        }
+    }
+
+    /// Uploads the probes nearest `camera`, smallest first so local probes win over the large
+    /// ones they sit inside.
+    pub fn update(&self, queue: &wgpu::Queue, camera: Vec3) {
+        let mut active: Vec<(usize, &ReflectionProbe)> = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| slot.as_ref().map(|slot| (index, &slot.probe)))
+            .collect();
+        if active.len() > MAX_ACTIVE_PROBES {
+            active.sort_by(|a, b| {
+                a.1.position
+                    .distance_squared(camera)
+                    .total_cmp(&b.1.position.distance_squared(camera))
+            });
+            active.truncate(MAX_ACTIVE_PROBES);
+        }
+        active.sort_by(|a, b| a.1.volume().total_cmp(&b.1.volume()));
+
+        let mut data = GpuProbeData {
+            header: [active.len() as u32, self.mip_levels - 1, 0, 0],
+            probes: [GpuProbe::default(); MAX_ACTIVE_PROBES],
+        };
+        for (gpu, (index, probe)) in data.probes.iter_mut().zip(active) {
+            let (extents, is_box) = match probe.shape {
+                ProbeShape::Sphere { radius } => (Vec3::splat(radius), 0.0),
+                ProbeShape::Box { half_extents } => (half_extents, 1.0),
+            };
+            *gpu = GpuProbe {
+                position: probe.position.extend(index as f32).into(),
+                extents: extents.extend(probe.blend_distance).into(),
+                shape: [is_box, 0.0, 0.0, 0.0],
+            };
+        }
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&data));
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+}
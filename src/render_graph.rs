@@ -0,0 +1,413 @@
+use std::{cell::Cell, fmt};
+
+#[cfg(feature = "egui")]
+use crate::gpu_memory;
+use crate::gpu_memory::GpuMemory;
+use crate::transient::{AliasingStats, TransientDesc, TransientPool, TransientRequest};
+
+/// A texture passes read and write, imported from outside the graph or created by it for the
+/// frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+enum Source<'a> {
+    Imported(&'a wgpu::TextureView),
+    Transient(TransientDesc),
+}
+
+struct Texture<'a> {
+    name: String,
+    source: Source<'a>,
+    clear: Option<wgpu::Color>,
+}
+
+type Run<'a, S> = Box<dyn FnOnce(&mut PassContext, &mut S) + 'a>;
+
+struct Pass<'a, S> {
+    name: String,
+    reads: Vec<TextureHandle>,
+    writes: Vec<TextureHandle>,
+    ui: bool,
+    run: Run<'a, S>,
+}
+
+impl<S> Pass<'_, S> {
+    fn reads(&self, texture: TextureHandle) -> bool {
+        self.reads.contains(&texture)
+    }
+
+    fn writes(&self, texture: TextureHandle) -> bool {
+        self.writes.contains(&texture)
+    }
+}
+
+/// Declares a pass's attachments; `run` adds it to the graph.
+pub struct PassBuilder<'g, 'a, S> {
+    graph: &'g mut RenderGraph<'a, S>,
+    name: String,
+    reads: Vec<TextureHandle>,
+    writes: Vec<TextureHandle>,
+    ui: bool,
+}
+
+impl<'a, S> PassBuilder<'_, 'a, S> {
+    /// The pass samples `texture`, or loads it to draw over when it writes it too.
+    pub fn reads(mut self, texture: TextureHandle) -> Self {
+        self.reads.push(texture);
+        self
+    }
+
+    pub fn writes(mut self, texture: TextureHandle) -> Self {
+        self.writes.push(texture);
+        self
+    }
+
+    /// Runs the pass after every pass that isn't one, whatever order they were added in, so
+    /// the UI ends up over the whole frame.
+    pub fn ui(mut self) -> Self {
+        self.ui = true;
+        self
+    }
+
+    pub fn run(self, run: impl FnOnce(&mut PassContext, &mut S) + 'a) {
+        self.graph.passes.push(Pass {
+            name: self.name,
+            reads: self.reads,
+            writes: self.writes,
+            ui: self.ui,
+            run: Box::new(run),
+        });
+    }
+}
+
+/// What a pass records with: the frame's encoder and the views of its textures. Anything else
+/// it needs comes with the graph's state.
+pub struct PassContext<'p> {
+    pub encoder: &'p mut wgpu::CommandEncoder,
+    views: &'p [Option<&'p wgpu::TextureView>],
+    clears: &'p [Cell<Option<wgpu::Color>>],
+}
+
+impl<'p> PassContext<'p> {
+    /// Panics if the pass didn't declare `texture`.
+    pub fn view(&self, texture: TextureHandle) -> &'p wgpu::TextureView {
+        self.views[texture.0].expect("texture used by a pass that didn't declare it")
+    }
+
+    /// The color `texture` still has to be cleared to, if nothing has drawn into it yet this
+    /// frame. Whoever draws first takes it.
+    pub fn clear(&self, texture: TextureHandle) -> &'p Cell<Option<wgpu::Color>> {
+        &self.clears[texture.0]
+    }
+
+    /// Clears `texture` if nothing has drawn into it yet this frame, else loads it.
+    pub fn load_op(&self, texture: TextureHandle) -> wgpu::LoadOp<wgpu::Color> {
+        self.clear(texture).take().map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear)
+    }
+
+    /// Does `texture`'s clear now if nothing has drawn into it yet, for passes that only sample
+    /// it.
+    pub fn flush_clear(&mut self, texture: TextureHandle) {
+        if let Some(color) = self.clear(texture).take() {
+            self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("clear"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: self.view(texture),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(color),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RenderGraphError {
+    /// A pass reads a texture the graph creates, but no pass writes it.
+    NeverWritten { pass: String, texture: String },
+    /// The passes whose reads and writes order them in a circle.
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderGraphError::NeverWritten { pass, texture } => {
+                write!(f, "pass \"{}\" reads \"{}\", which no pass writes", pass, texture)
+            }
+            RenderGraphError::Cycle(passes) => write!(f, "passes are ordered in a cycle: {}", passes.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for RenderGraphError {}
+
+/// What a compiled graph runs, for the stats panel.
+#[derive(Clone, Debug, Default)]
+pub struct GraphStats {
+    /// Pass names in the order they ran.
+    pub passes: Vec<String>,
+    /// Passes left out because nothing reads what they write.
+    pub culled: Vec<String>,
+    pub transients: AliasingStats,
+}
+
+/// One frame's passes and the textures they pass between each other. Passes declare what they
+/// read and write, and `execute` runs them in an order that respects it:
+///
+/// - a pass that reads a texture runs after the passes that only write it, which produce it,
+///   whatever order they were added in;
+/// - otherwise passes touching the same texture keep the order they were added in, so a pass
+///   that draws over a texture runs after the earlier ones drawing into it;
+/// - `ui` passes run last.
+///
+/// Passes that don't share textures also keep the order they were added in. A pass that only
+/// writes textures created by the graph is skipped when nothing reads them afterwards; created
+/// textures are backed by a `TransientPool` for just the passes using them.
+///
+/// `compile` does the ordering and allocation; `CompiledGraph::execute` then runs the passes.
+///
+/// `S` is whatever state the passes share; each pass gets it mutably while it runs.
+pub struct RenderGraph<'a, S> {
+    textures: Vec<Texture<'a>>,
+    passes: Vec<Pass<'a, S>>,
+}
+
+impl<'a, S> Default for RenderGraph<'a, S> {
+    fn default() -> Self {
+        RenderGraph {
+            textures: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+}
+
+impl<'a, S> RenderGraph<'a, S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A texture from outside the graph, such as the window's surface. With `clear` set the
+    /// first pass to draw into it clears it, see `PassContext::load_op`. Passes writing
+    /// imported textures always run.
+    pub fn import(&mut self, name: impl Into<String>, view: &'a wgpu::TextureView, clear: Option<wgpu::Color>) -> TextureHandle {
+        self.add_texture(name.into(), Source::Imported(view), clear)
+    }
+
+    /// A texture for this frame only, cleared to transparent by the first pass that draws into
+    /// it. It may share memory with other created textures that aren't in use at the same time.
+    pub fn create(&mut self, name: impl Into<String>, desc: TransientDesc) -> TextureHandle {
+        self.add_texture(name.into(), Source::Transient(desc), Some(wgpu::Color::TRANSPARENT))
+    }
+
+    /// Like `create`, but cleared to `clear`, e.g. a multisampled stand-in for the surface.
+    pub fn create_cleared(&mut self, name: impl Into<String>, desc: TransientDesc, clear: wgpu::Color) -> TextureHandle {
+        self.add_texture(name.into(), Source::Transient(desc), Some(clear))
+    }
+
+    fn add_texture(&mut self, name: String, source: Source<'a>, clear: Option<wgpu::Color>) -> TextureHandle {
+        self.textures.push(Texture { name, source, clear });
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    pub fn pass<'g>(&'g mut self, name: impl Into<String>) -> PassBuilder<'g, 'a, S> {
+        PassBuilder {
+            graph: self,
+            name: name.into(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+            ui: false,
+        }
+    }
+
+    /// The indices of the passes to run, in order, and of the ones nothing needs.
+    fn schedule(&self) -> Result<(Vec<usize>, Vec<usize>), RenderGraphError> {
+        let count = self.passes.len();
+        let mut successors = vec![Vec::new(); count];
+        for texture in (0..self.textures.len()).map(TextureHandle) {
+            let users: Vec<usize> = (0..count)
+                .filter(|&i| self.passes[i].reads(texture) || self.passes[i].writes(texture))
+                .collect();
+            for (position, &a) in users.iter().enumerate() {
+                for &b in &users[position + 1..] {
+                    let (a_pass, b_pass) = (&self.passes[a], &self.passes[b]);
+                    // `ui` passes are ordered after the others below
+                    if (!a_pass.writes(texture) && !b_pass.writes(texture)) || a_pass.ui != b_pass.ui {
+                        continue;
+                    }
+                    // a later producer still runs before an earlier reader
+                    if b_pass.writes(texture) && !b_pass.reads(texture) && a_pass.reads(texture) {
+                        successors[b].push(a);
+                    } else {
+                        successors[a].push(b);
+                    }
+                }
+            }
+            let created = matches!(self.textures[texture.0].source, Source::Transient(_));
+            if created && !users.iter().any(|&i| self.passes[i].writes(texture)) {
+                if let Some(&reader) = users.first() {
+                    return Err(RenderGraphError::NeverWritten {
+                        pass: self.passes[reader].name.clone(),
+                        texture: self.textures[texture.0].name.clone(),
+                    });
+                }
+            }
+        }
+        for ui in (0..count).filter(|&i| self.passes[i].ui) {
+            for other in (0..count).filter(|&i| !self.passes[i].ui) {
+                successors[other].push(ui);
+            }
+        }
+
+        // order by the dependencies, otherwise by insertion, like `ecs::Schedule`
+        let mut incoming = vec![0; count];
+        for &next in successors.iter().flatten() {
+            incoming[next] += 1;
+        }
+        let mut order = Vec::with_capacity(count);
+        let mut placed = vec![false; count];
+        while order.len() < count {
+            let Some(next) = (0..count).find(|&i| !placed[i] && incoming[i] == 0) else {
+                let cycle = (0..count).filter(|&i| !placed[i]).map(|i| self.passes[i].name.clone()).collect();
+                return Err(RenderGraphError::Cycle(cycle));
+            };
+            placed[next] = true;
+            order.push(next);
+            for &successor in &successors[next] {
+                incoming[successor] -= 1;
+            }
+        }
+
+        // walk back from the end, keeping passes that write something still needed
+        let mut needed: Vec<bool> = self
+            .textures
+            .iter()
+            .map(|texture| matches!(texture.source, Source::Imported(_)))
+            .collect();
+        let mut kept = vec![false; count];
+        for &i in order.iter().rev() {
+            let pass = &self.passes[i];
+            if pass.writes.is_empty() || pass.writes.iter().any(|texture| needed[texture.0]) {
+                kept[i] = true;
+                for texture in &pass.reads {
+                    needed[texture.0] = true;
+                }
+            }
+        }
+        let (run, culled) = order.into_iter().partition(|&i| kept[i]);
+        Ok((run, culled))
+    }
+
+    /// Orders the passes and backs the created textures from `pool`, which tracks what it
+    /// allocates and frees in `memory`, ready to `execute`.
+    pub fn compile<'p>(
+        self,
+        device: &wgpu::Device,
+        pool: &'p mut TransientPool,
+        memory: &mut GpuMemory,
+    ) -> Result<CompiledGraph<'p, S>, RenderGraphError>
+    where
+        'a: 'p,
+    {
+        let (order, culled) = self.schedule()?;
+
+        // each created texture is needed from the first to the last pass using it
+        let mut requests = Vec::new();
+        let mut request_of = vec![None; self.textures.len()];
+        for (handle, texture) in self.textures.iter().enumerate() {
+            let Source::Transient(desc) = texture.source else {
+                continue;
+            };
+            let mut users = order.iter().enumerate().filter(|(_, &i)| {
+                let pass = &self.passes[i];
+                pass.reads(TextureHandle(handle)) || pass.writes(TextureHandle(handle))
+            });
+            let Some((first_pass, _)) = users.next() else {
+                continue;
+            };
+            let last_pass = users.next_back().map_or(first_pass, |(position, _)| position);
+            request_of[handle] = Some(requests.len());
+            requests.push(TransientRequest {
+                desc,
+                first_pass,
+                last_pass,
+            });
+        }
+        pool.trim(memory);
+        let slots = pool.allocate(device, memory, &requests);
+        let pool: &'p TransientPool = pool;
+
+        let views = self
+            .textures
+            .iter()
+            .zip(&request_of)
+            .map(|(texture, request)| match texture.source {
+                Source::Imported(view) => Some(view),
+                Source::Transient(_) => request.map(|request| pool.view(slots[request])),
+            })
+            .collect();
+        let stats = GraphStats {
+            passes: order.iter().map(|&i| self.passes[i].name.clone()).collect(),
+            culled: culled.iter().map(|&i| self.passes[i].name.clone()).collect(),
+            transients: pool.stats(),
+        };
+        let mut passes: Vec<Option<Pass<S>>> = self.passes.into_iter().map(Some).collect();
+        let passes = order.into_iter().map(|i| passes[i].take().unwrap()).collect();
+        Ok(CompiledGraph {
+            views,
+            clears: self.textures.iter().map(|texture| Cell::new(texture.clear)).collect(),
+            passes,
+            stats,
+        })
+    }
+}
+
+/// A `RenderGraph` with its passes in order and its textures allocated.
+pub struct CompiledGraph<'p, S> {
+    views: Vec<Option<&'p wgpu::TextureView>>,
+    clears: Vec<Cell<Option<wgpu::Color>>>,
+    passes: Vec<Pass<'p, S>>,
+    stats: GraphStats,
+}
+
+impl<S> CompiledGraph<'_, S> {
+    pub fn stats(&self) -> &GraphStats {
+        &self.stats
+    }
+
+    /// Runs the passes into `encoder`, handing each `state`.
+    pub fn execute(self, encoder: &mut wgpu::CommandEncoder, state: &mut S) -> GraphStats {
+        for pass in self.passes {
+            let mut context = PassContext {
+                encoder: &mut *encoder,
+                views: &self.views,
+                clears: &self.clears,
+            };
+            (pass.run)(&mut context, state);
+        }
+        self.stats
+    }
+}
+
+impl GraphStats {
+    #[cfg(feature = "egui")]
+    /// Lists the passes in the order they ran, for a debug panel.
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        for (number, pass) in self.passes.iter().enumerate() {
+            ui.label(format!("{}: {}", number + 1, pass));
+        }
+        for pass in &self.culled {
+            ui.colored_label(egui::Color32::GRAY, format!("culled: {}", pass));
+        }
+        ui.label(format!(
+            "Transient textures: {} ({} of {} requested)",
+            self.transients.textures,
+            gpu_memory::format_bytes(self.transients.allocated_bytes),
+            gpu_memory::format_bytes(self.transients.requested_bytes)
+        ));
+    }
+}
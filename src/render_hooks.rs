@@ -0,0 +1,187 @@
+use std::cell::Cell;
+
+use crate::{
+    camera::{Camera, CameraBuffer},
+    mesh_renderer::MeshRenderer,
+    render_stats::RenderStats,
+    render_world::RenderWorld,
+};
+
+/// Where in the frame a hook runs. The points keep their place as the engine's own passes
+/// change, so plugins can rely on them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HookPoint {
+    /// After `App::render`, before the queued meshes are drawn. The mesh pass clears the depth
+    /// buffer, so only color written here survives.
+    BeforeOpaque,
+    /// After the meshes, with their depth buffer, e.g. for outlines, decals or debug overlays
+    /// that should be depth tested against the scene.
+    AfterOpaque,
+    /// Before post-processing; with no post effects, right before the UI.
+    BeforePost,
+    /// Over everything, the UI included, right before the frame is submitted.
+    AfterUi,
+}
+
+impl HookPoint {
+    pub const ALL: [HookPoint; 4] = [
+        HookPoint::BeforeOpaque,
+        HookPoint::AfterOpaque,
+        HookPoint::BeforePost,
+        HookPoint::AfterUi,
+    ];
+}
+
+/// What a hook can use to record its passes: the frame's targets and what the engine rendered
+/// them from.
+pub struct HookContext<'a> {
+    pub point: HookPoint,
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    /// The HDR scene this frame, multisampled with MSAA, tonemapped into the window's surface
+    /// before the UI. After the UI, the surface itself.
+    pub target: &'a wgpu::TextureView,
+    /// `Tonemapper::HDR_FORMAT`, or the surface's after the UI.
+    pub format: wgpu::TextureFormat,
+    /// Samples per pixel of `target`. 1 after the UI.
+    pub sample_count: u32,
+    /// The mesh pass's depth buffer, in `MeshRenderer::DEPTH_FORMAT`, with the scene's sample
+    /// count: only attachable together with `target` before the UI.
+    pub depth: &'a wgpu::TextureView,
+    /// Size of `target`: the scene's internal resolution before the UI, the window's after it.
+    pub size: winit::dpi::PhysicalSize<u32>,
+    pub camera: &'a Camera,
+    pub camera_buffer: &'a CameraBuffer,
+    /// The mesh renderer, for its lights and the shadow atlas.
+    pub meshes: &'a MeshRenderer,
+    pub render_world: &'a RenderWorld,
+    pub stats: &'a mut RenderStats,
+    clear: &'a Cell<Option<wgpu::Color>>,
+}
+
+impl<'a> HookContext<'a> {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        point: HookPoint,
+        device: &'a wgpu::Device,
+        queue: &'a wgpu::Queue,
+        encoder: &'a mut wgpu::CommandEncoder,
+        target: &'a wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        depth: &'a wgpu::TextureView,
+        size: winit::dpi::PhysicalSize<u32>,
+        camera: &'a Camera,
+        camera_buffer: &'a CameraBuffer,
+        meshes: &'a MeshRenderer,
+        render_world: &'a RenderWorld,
+        stats: &'a mut RenderStats,
+        clear: &'a Cell<Option<wgpu::Color>>,
+    ) -> Self {
+        HookContext {
+            point,
+            device,
+            queue,
+            encoder,
+            target,
+            format,
+            sample_count,
+            depth,
+            size,
+            camera,
+            camera_buffer,
+            meshes,
+            render_world,
+            stats,
+            clear,
+        }
+    }
+
+    /// Like `RenderContext::target_load_op`: clears `target` if nothing has drawn into it yet
+    /// this frame.
+    pub fn target_load_op(&mut self) -> wgpu::LoadOp<wgpu::Color> {
+        match self.clear.take() {
+            Some(color) => wgpu::LoadOp::Clear(color),
+            None => wgpu::LoadOp::Load,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HookId(u64);
+
+struct Hook {
+    id: HookId,
+    name: String,
+    point: HookPoint,
+    enabled: bool,
+    run: Box<dyn FnMut(&mut HookContext)>,
+}
+
+/// Passes added to the engine's frame by apps and plugins. Hooks at the same point run in the
+/// order they were added.
+#[derive(Default)]
+pub struct RenderHooks {
+    hooks: Vec<Hook>,
+    next_id: u64,
+}
+
+impl RenderHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `run` at `point` every frame until removed. `name` labels it in the stats panel.
+    pub fn add(&mut self, point: HookPoint, name: impl Into<String>, run: impl FnMut(&mut HookContext) + 'static) -> HookId {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        self.hooks.push(Hook {
+            id,
+            name: name.into(),
+            point,
+            enabled: true,
+            run: Box::new(run),
+        });
+        id
+    }
+
+    /// Returns false if the hook was already removed.
+    pub fn remove(&mut self, id: HookId) -> bool {
+        let count = self.hooks.len();
+        self.hooks.retain(|hook| hook.id != id);
+        self.hooks.len() != count
+    }
+
+    /// Skips the hook until enabled again, keeping its place in the order.
+    pub fn set_enabled(&mut self, id: HookId, enabled: bool) {
+        if let Some(hook) = self.hooks.iter_mut().find(|hook| hook.id == id) {
+            hook.enabled = enabled;
+        }
+    }
+
+    /// Names of the enabled hooks at `point`, in the order they run.
+    pub fn names(&self, point: HookPoint) -> impl Iterator<Item = &str> {
+        self.hooks
+            .iter()
+            .filter(move |hook| hook.point == point && hook.enabled)
+            .map(|hook| hook.name.as_str())
+    }
+
+    /// Runs the enabled hooks at `context.point`. The engine calls it at each point.
+    pub fn run(&mut self, context: &mut HookContext) {
+        for hook in &mut self.hooks {
+            if hook.point == context.point && hook.enabled {
+                (hook.run)(context);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
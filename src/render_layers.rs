@@ -0,0 +1,109 @@
+use std::ops::{BitAnd, BitOr, Not};
+
+/// Bitmask of up to 32 render layers. Entities, cameras and lights each carry one; an entity is
+/// drawn by a camera, lit by a light or casts a light's shadow only if their masks intersect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RenderLayers(pub u32);
+
+impl RenderLayers {
+    /// What everything is on unless told otherwise.
+    pub const DEFAULT: RenderLayers = RenderLayers(1 << 0);
+    /// First-person weapons and arms, drawn by the player camera only and usually kept out of
+    /// shadow casting so they don't shadow the world oddly.
+    pub const FIRST_PERSON: RenderLayers = RenderLayers(1 << 1);
+    /// Gizmos, grids and helpers seen only by editor cameras.
+    pub const EDITOR: RenderLayers = RenderLayers(1 << 2);
+    /// Markers and icons seen only by the minimap camera.
+    pub const MINIMAP: RenderLayers = RenderLayers(1 << 3);
+    /// Outlined by the engine, e.g. the editor's selection or what the player can interact
+    /// with. Add it to the layers a draw is seen on.
+    pub const OUTLINED: RenderLayers = RenderLayers(1 << 4);
+    /// First layer free for game-specific use.
+    pub const FIRST_CUSTOM: u32 = 8;
+
+    pub const ALL: RenderLayers = RenderLayers(u32::MAX);
+    pub const NONE: RenderLayers = RenderLayers(0);
+
+    /// Just layer `index`, which must be below 32.
+    pub const fn layer(index: u32) -> Self {
+        RenderLayers(1 << index)
+    }
+
+    pub const fn with(self, other: RenderLayers) -> Self {
+        RenderLayers(self.0 | other.0)
+    }
+
+    pub const fn without(self, other: RenderLayers) -> Self {
+        RenderLayers(self.0 & !other.0)
+    }
+
+    pub const fn contains(self, other: RenderLayers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersects(self, other: RenderLayers) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Indices of the layers set, lowest first.
+    pub fn iter(self) -> impl Iterator<Item = u32> {
+        (0..32).filter(move |index| self.0 & (1 << index) != 0)
+    }
+}
+
+impl Default for RenderLayers {
+    fn default() -> Self {
+        RenderLayers::DEFAULT
+    }
+}
+
+impl BitOr for RenderLayers {
+    type Output = RenderLayers;
+
+    fn bitor(self, other: RenderLayers) -> RenderLayers {
+        self.with(other)
+    }
+}
+
+impl BitAnd for RenderLayers {
+    type Output = RenderLayers;
+
+    fn bitand(self, other: RenderLayers) -> RenderLayers {
+        RenderLayers(self.0 & other.0)
+    }
+}
+
+impl Not for RenderLayers {
+    type Output = RenderLayers;
+
+    fn not(self) -> RenderLayers {
+        RenderLayers(!self.0)
+    }
+}
+
+/// Layer masks for a light: which objects it lights, and the subset of those that cast its
+/// shadows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LightLayers {
+    pub lit: RenderLayers,
+    pub shadow_casters: RenderLayers,
+}
+
+impl Default for LightLayers {
+    fn default() -> Self {
+        LightLayers {
+            lit: RenderLayers::ALL,
+            shadow_casters: RenderLayers::ALL.without(RenderLayers::FIRST_PERSON),
+        }
+    }
+}
+
+impl LightLayers {
+    pub fn lights(&self, object: RenderLayers) -> bool {
+        self.lit.intersects(object)
+    }
+
+    pub fn casts_shadow(&self, object: RenderLayers) -> bool {
+        self.shadow_casters.intersects(object)
+    }
+}
@@ -0,0 +1,56 @@
+use crate::batching::BatchStats;
+
+/// What one frame drew, filled in by the renderer as it goes, shown in the stats overlay and
+/// recorded by benchmarks. Reset it at the start of every frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    /// Entities in the scene, drawn or not.
+    pub entities: u32,
+    /// Objects that survived culling for the main camera.
+    pub visible_objects: u32,
+    pub draw_calls: u32,
+    pub triangles: u64,
+    /// Lights affecting the frame after culling.
+    pub lights: u32,
+    /// Shadow maps or atlas tiles rendered this frame.
+    pub shadow_casters: u32,
+}
+
+impl RenderStats {
+    /// Counts one draw call of `instances` instances of a mesh with `triangles` triangles.
+    pub fn record_draw(&mut self, triangles: u32, instances: u32) {
+        self.draw_calls += 1;
+        self.triangles += triangles as u64 * instances as u64;
+    }
+
+    /// Counts the draws of a built `DrawQueue`, one per batch.
+    pub fn record_batches(&mut self, batches: BatchStats) {
+        self.draw_calls += batches.batches as u32;
+        self.visible_objects += batches.draws as u32;
+    }
+
+    /// Every counter by name, for display and benchmark output.
+    pub fn counters(&self) -> [(&'static str, u64); 6] {
+        [
+            ("entities", self.entities as u64),
+            ("visible_objects", self.visible_objects as u64),
+            ("draw_calls", self.draw_calls as u64),
+            ("triangles", self.triangles),
+            ("lights", self.lights as u64),
+            ("shadow_casters", self.shadow_casters as u64),
+        ]
+    }
+
+    #[cfg(feature = "egui")]
+    /// One line per counter, for the stats overlay.
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        egui::Grid::new("render stats").show(ui, |ui| {
+            for (name, value) in self.counters() {
+                let label = name.replace('_', " ");
+                ui.label(label[..1].to_uppercase() + &label[1..]);
+                ui.label(value.to_string());
+                ui.end_row();
+            }
+        });
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use glam::Mat4;
+
+use crate::{
+    camera::{Camera, CameraTarget},
+    ecs::World,
+    lights::FrameLight,
+    material::Material,
+    mesh::Mesh,
+    mesh_renderer::MeshRenderer,
+    render_layers::RenderLayers,
+    scene::{self, Light, MeshInstance, Transform},
+};
+
+/// A visible `MeshInstance` as it was when extracted.
+#[derive(Clone, Debug)]
+pub struct ExtractedMesh {
+    pub mesh: Arc<Mesh>,
+    pub transform: Mat4,
+    /// Linear RGBA tint.
+    pub color: [f32; 4],
+    pub material: Option<Arc<Material>>,
+    /// The entity's `RenderLayers` component, `RenderLayers::DEFAULT` without one.
+    pub layers: RenderLayers,
+}
+
+/// What the renderer needs from the world for one frame: its cameras, lights and visible meshes,
+/// copied out by `extract` once the simulation is done with the frame. Rendering works from
+/// this alone, so the world can change again while the frame is encoded, and the render world
+/// can be handed to another thread.
+#[derive(Debug, Default)]
+pub struct RenderWorld {
+    /// The world's camera, see `scene::camera_system`.
+    pub camera: Option<Camera>,
+    /// The world's other active cameras in render order, drawn after `camera` into their
+    /// viewports and targets.
+    pub cameras: Vec<Camera>,
+    pub lights: Vec<FrameLight>,
+    pub meshes: Vec<ExtractedMesh>,
+}
+
+impl RenderWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the contents with `world`'s, keeping the allocations.
+    pub fn extract(&mut self, world: &World) {
+        self.cameras = scene::scene_cameras(world);
+        self.camera = self
+            .cameras
+            .iter()
+            .position(|camera| camera.target == CameraTarget::Surface)
+            .map(|index| self.cameras.remove(index));
+        self.lights.clear();
+        self.lights.extend(
+            world
+                .query2::<Light, Transform>()
+                .map(|(_, light, transform)| light.frame_light(transform)),
+        );
+        self.meshes.clear();
+        self.meshes.extend(
+            world
+                .query2::<MeshInstance, Transform>()
+                .filter(|(_, instance, _)| instance.visible)
+                .map(|(entity, instance, transform)| ExtractedMesh {
+                    mesh: instance.mesh.clone(),
+                    transform: transform.matrix(),
+                    color: instance.color,
+                    material: instance.material.clone(),
+                    layers: world.get::<RenderLayers>(entity).copied().unwrap_or(RenderLayers::DEFAULT),
+                }),
+        );
+    }
+
+    /// Hands the extracted frame to the renderer: the camera replaces `camera` if there was
+    /// one, the lights replace the mesh renderer's if there were any, and the meshes are
+    /// queued.
+    pub fn submit(&self, camera: &mut Camera, meshes: &mut MeshRenderer) {
+        if let Some(scene_camera) = &self.camera {
+            *camera = scene_camera.clone();
+        }
+        if !self.lights.is_empty() {
+            meshes.lights.clone_from(&self.lights);
+        }
+        for extracted in &self.meshes {
+            match &extracted.material {
+                Some(material) => meshes.draw_material(
+                    &extracted.mesh,
+                    extracted.transform,
+                    extracted.color,
+                    material,
+                    extracted.layers,
+                ),
+                None => meshes.draw_on_layers(&extracted.mesh, extracted.transform, extracted.color, extracted.layers),
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.camera = None;
+        self.cameras.clear();
+        self.lights.clear();
+        self.meshes.clear();
+    }
+}
@@ -0,0 +1,471 @@
+use std::{fmt, hash::Hasher, path::Path};
+
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+
+use crate::random::Random;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    /// A timestep that isn't a positive, finite number of seconds.
+    Timestep(f32),
+    /// The replayed state no longer matches the recording.
+    Desync { frame: usize, expected: u64, found: u64 },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "{}", e),
+            ReplayError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            ReplayError::Timestep(timestep) => write!(f, "timestep {} is not a positive number of seconds", timestep),
+            ReplayError::Desync { frame, expected, found } => {
+                write!(f, "desync at frame {}: expected checksum {:016x}, found {:016x}", frame, expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// The input a simulation step sees, reduced to what can be recorded and fed back exactly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    Key { scancode: u32, pressed: bool },
+    CursorMoved { x: f32, y: f32 },
+    /// 0 left, 1 right, 2 middle, 3 and up for other buttons.
+    MouseButton { button: u16, pressed: bool },
+    /// In lines; pixel deltas are converted at 20 pixels a line.
+    Scroll { x: f32, y: f32 },
+    Focused(bool),
+}
+
+impl InputEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        let pressed = |state: &ElementState| *state == ElementState::Pressed;
+        match event {
+            WindowEvent::KeyboardInput { input, .. } => Some(InputEvent::Key {
+                scancode: input.scancode,
+                pressed: pressed(&input.state),
+            }),
+            WindowEvent::CursorMoved { position, .. } => Some(InputEvent::CursorMoved {
+                x: position.x as f32,
+                y: position.y as f32,
+            }),
+            WindowEvent::MouseInput { state, button, .. } => Some(InputEvent::MouseButton {
+                button: match button {
+                    MouseButton::Left => 0,
+                    MouseButton::Right => 1,
+                    MouseButton::Middle => 2,
+                    MouseButton::Other(other) => other.saturating_add(3),
+                },
+                pressed: pressed(state),
+            }),
+            WindowEvent::MouseWheel { delta, .. } => Some(match delta {
+                MouseScrollDelta::LineDelta(x, y) => InputEvent::Scroll { x: *x, y: *y },
+                MouseScrollDelta::PixelDelta(position) => InputEvent::Scroll {
+                    x: position.x as f32 / 20.0,
+                    y: position.y as f32 / 20.0,
+                },
+            }),
+            WindowEvent::Focused(focused) => Some(InputEvent::Focused(*focused)),
+            _ => None,
+        }
+    }
+}
+
+/// FNV-1a, for checksums that stay the same across runs, platforms and compiler versions,
+/// unlike `DefaultHasher`.
+#[derive(Clone, Copy, Debug)]
+pub struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        StateHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StateHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hashes the exact bits, so even the smallest drift shows up.
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+}
+
+impl Hasher for StateHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Game state that can be re-simulated from a recording. Everything random has to come from
+/// the `Random` passed to `reset`, the engine's, reseeded with the recording's seed, and
+/// everything time dependent from `dt`, otherwise replays drift.
+pub trait Simulation {
+    fn reset(&mut self, random: &mut Random);
+    fn step(&mut self, dt: f32, inputs: &[InputEvent]);
+    /// Hash of the state that matters, e.g. built with `StateHasher`.
+    fn checksum(&self) -> u64;
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordedFrame {
+    pub inputs: Vec<InputEvent>,
+    pub checksum: Option<u64>,
+}
+
+/// A session: the seed it started from, the fixed step it ran at and the input of every step.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recording {
+    pub seed: u64,
+    pub timestep: f32,
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn new(seed: u64, timestep: f32) -> Self {
+        Recording {
+            seed,
+            timestep,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames.len() as f32 * self.timestep
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let text = std::fs::read_to_string(path).map_err(ReplayError::Io)?;
+        Self::parse(&text)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        std::fs::write(path, self.to_text()).map_err(ReplayError::Io)
+    }
+
+    /// A `seed` and `timestep` header, then a `frame [checksum]` line per step followed by the
+    /// step's input, one `key`, `cursor`, `button`, `scroll` or `focus` line per event.
+    pub fn parse(text: &str) -> Result<Self, ReplayError> {
+        let mut recording = Recording::new(0, 1.0 / 60.0);
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let error = |message: String| ReplayError::Parse { line: index + 1, message };
+            let number = |word: &str| word.parse::<f32>().map_err(|_| error(format!("'{}' is not a number", word)));
+            let state = |word: &str| match word {
+                "down" => Ok(true),
+                "up" => Ok(false),
+                _ => Err(error(format!("expected 'down' or 'up', found '{}'", word))),
+            };
+            let event = match words.as_slice() {
+                ["seed", seed] => {
+                    recording.seed = seed.parse().map_err(|_| error(format!("'{}' is not a seed", seed)))?;
+                    continue;
+                }
+                ["timestep", timestep] => {
+                    recording.timestep = number(timestep)?;
+                    if !valid_timestep(recording.timestep) {
+                        return Err(error(ReplayError::Timestep(recording.timestep).to_string()));
+                    }
+                    continue;
+                }
+                ["frame"] => {
+                    recording.frames.push(RecordedFrame::default());
+                    continue;
+                }
+                ["frame", checksum] => {
+                    let checksum = u64::from_str_radix(checksum, 16).map_err(|_| error(format!("'{}' is not a checksum", checksum)))?;
+                    recording.frames.push(RecordedFrame {
+                        inputs: Vec::new(),
+                        checksum: Some(checksum),
+                    });
+                    continue;
+                }
+                ["key", scancode, pressed] => InputEvent::Key {
+                    scancode: scancode.parse().map_err(|_| error(format!("'{}' is not a scancode", scancode)))?,
+                    pressed: state(pressed)?,
+                },
+                ["cursor", x, y] => InputEvent::CursorMoved { x: number(x)?, y: number(y)? },
+                ["button", button, pressed] => InputEvent::MouseButton {
+                    button: button.parse().map_err(|_| error(format!("'{}' is not a mouse button", button)))?,
+                    pressed: state(pressed)?,
+                },
+                ["scroll", x, y] => InputEvent::Scroll { x: number(x)?, y: number(y)? },
+                ["focus", focused] => InputEvent::Focused(state(focused)?),
+                _ => return Err(error(format!("unrecognized line '{}'", line))),
+            };
+            match recording.frames.last_mut() {
+                Some(frame) => frame.inputs.push(event),
+                None => return Err(error("input before the first frame".to_owned())),
+            }
+        }
+        Ok(recording)
+    }
+
+    pub fn to_text(&self) -> String {
+        let state = |pressed: bool| if pressed { "down" } else { "up" };
+        let mut lines = vec![
+            "# replay".to_owned(),
+            format!("seed {}", self.seed),
+            format!("timestep {}", self.timestep),
+        ];
+        for frame in &self.frames {
+            lines.push(match frame.checksum {
+                Some(checksum) => format!("frame {:016x}", checksum),
+                None => "frame".to_owned(),
+            });
+            // f32's Display is the shortest text that parses back to the same bits
+            lines.extend(frame.inputs.iter().map(|input| match *input {
+                InputEvent::Key { scancode, pressed } => format!("key {} {}", scancode, state(pressed)),
+                InputEvent::CursorMoved { x, y } => format!("cursor {} {}", x, y),
+                InputEvent::MouseButton { button, pressed } => format!("button {} {}", button, state(pressed)),
+                InputEvent::Scroll { x, y } => format!("scroll {} {}", x, y),
+                InputEvent::Focused(focused) => format!("focus {}", state(focused)),
+            }));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+fn valid_timestep(timestep: f32) -> bool {
+    timestep.is_finite() && timestep > 0.0
+}
+
+/// Records a simulation's input as it runs. Call `step` from `App::fixed_update` with
+/// `AppContext::step_inputs`, started with the engine's fixed step, so the recording has the
+/// same steps the game ran.
+pub struct Recorder {
+    recording: Recording,
+    checksums: bool,
+}
+
+impl Recorder {
+    /// Restarts `random`'s streams from its seed and resets `simulation` with it, so the
+    /// recording starts from a known state. Fails for a `timestep` that isn't positive and
+    /// finite, which no replay could step through.
+    pub fn start(simulation: &mut impl Simulation, random: &mut Random, timestep: f32) -> Result<Self, ReplayError> {
+        if !valid_timestep(timestep) {
+            return Err(ReplayError::Timestep(timestep));
+        }
+        random.reseed(random.seed());
+        simulation.reset(random);
+        Ok(Recorder {
+            recording: Recording::new(random.seed(), timestep),
+            checksums: false,
+        })
+    }
+
+    /// Stores the state checksum after every step so replays can pinpoint where they diverge.
+    pub fn with_checksums(mut self) -> Self {
+        self.checksums = true;
+        self
+    }
+
+    /// Runs one step with `inputs`, the input since the previous one, and records it.
+    pub fn step(&mut self, simulation: &mut impl Simulation, inputs: &[InputEvent]) {
+        simulation.step(self.recording.timestep, inputs);
+        let checksum = self.checksums.then(|| simulation.checksum());
+        self.recording.frames.push(RecordedFrame {
+            inputs: inputs.to_vec(),
+            checksum,
+        });
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+}
+
+/// Steps a simulation through a recording, checking each recorded checksum when verifying.
+/// Call `step` from `App::fixed_update`, like `Recorder`.
+pub struct Replayer<'a> {
+    recording: &'a Recording,
+    frame: usize,
+    verify: bool,
+}
+
+impl<'a> Replayer<'a> {
+    /// Reseeds `random` with the recording's seed and resets `simulation` with it.
+    pub fn start(recording: &'a Recording, simulation: &mut impl Simulation, random: &mut Random) -> Self {
+        random.reseed(recording.seed);
+        simulation.reset(random);
+        Replayer {
+            recording,
+            frame: 0,
+            verify: true,
+        }
+    }
+
+    /// Skip checksum checks, e.g. to watch a replay after changing the simulation on purpose.
+    pub fn without_verification(mut self) -> Self {
+        self.verify = false;
+        self
+    }
+
+    /// The frame the next `step` runs.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.recording.frames.len()
+    }
+
+    /// Runs the next recorded step. Returns false once the recording is exhausted.
+    pub fn step(&mut self, simulation: &mut impl Simulation) -> Result<bool, ReplayError> {
+        let Some(recorded) = self.recording.frames.get(self.frame) else {
+            return Ok(false);
+        };
+        simulation.step(self.recording.timestep, &recorded.inputs);
+        if let (true, Some(expected)) = (self.verify, recorded.checksum) {
+            let found = simulation.checksum();
+            if found != expected {
+                return Err(ReplayError::Desync {
+                    frame: self.frame,
+                    expected,
+                    found,
+                });
+            }
+        }
+        self.frame += 1;
+        Ok(true)
+    }
+
+    /// Runs the rest of the recording, stopping at the first desync.
+    pub fn run(&mut self, simulation: &mut impl Simulation) -> Result<(), ReplayError> {
+        while self.step(simulation)? {}
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sums a random start and the cursor positions it is stepped with, and `dt` on top from
+    /// `drift_from` on.
+    #[derive(Default)]
+    struct Counter {
+        total: f32,
+        steps: usize,
+        drift_from: Option<usize>,
+    }
+
+    impl Simulation for Counter {
+        fn reset(&mut self, random: &mut Random) {
+            self.total = random.stream("counter").next_f32();
+            self.steps = 0;
+        }
+
+        fn step(&mut self, dt: f32, inputs: &[InputEvent]) {
+            for input in inputs {
+                if let InputEvent::CursorMoved { x, y } = input {
+                    self.total += x + y;
+                }
+            }
+            if self.drift_from.is_some_and(|frame| self.steps >= frame) {
+                self.total += dt;
+            }
+            self.steps += 1;
+        }
+
+        fn checksum(&self) -> u64 {
+            let mut hasher = StateHasher::new();
+            hasher.write_f32(self.total);
+            hasher.finish()
+        }
+    }
+
+    fn record(simulation: &mut Counter) -> Recording {
+        let mut random = Random::new(7);
+        // streams already drawn from are restarted
+        random.stream("counter").next_u32();
+        let mut recorder = Recorder::start(simulation, &mut random, 0.25).unwrap().with_checksums();
+        recorder.step(simulation, &[InputEvent::CursorMoved { x: 0.1, y: 2.5 }, InputEvent::Key { scancode: 30, pressed: true }]);
+        recorder.step(simulation, &[]);
+        recorder.step(
+            simulation,
+            &[
+                InputEvent::MouseButton { button: 1, pressed: false },
+                InputEvent::Scroll { x: -1.0, y: 0.3 },
+                InputEvent::Focused(false),
+            ],
+        );
+        recorder.finish()
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let recording = record(&mut Counter::default());
+        assert_eq!(recording.frames.len(), 3);
+        assert_eq!(Recording::parse(&recording.to_text()).unwrap(), recording);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = Recording::parse("seed 1\nkey 30 down\n").unwrap_err();
+        assert!(matches!(error, ReplayError::Parse { line: 2, .. }));
+        let error = Recording::parse("seed 1\nframe\nkey 30 sideways\n").unwrap_err();
+        assert!(matches!(error, ReplayError::Parse { line: 3, .. }));
+    }
+
+    #[test]
+    fn rejects_timesteps_that_never_advance() {
+        for timestep in [0.0, -0.5, f32::NAN, f32::INFINITY] {
+            let started = Recorder::start(&mut Counter::default(), &mut Random::new(0), timestep);
+            assert!(matches!(started, Err(ReplayError::Timestep(_))));
+            let text = format!("seed 0\ntimestep {}\nframe\n", timestep);
+            assert!(matches!(Recording::parse(&text), Err(ReplayError::Parse { line: 2, .. })));
+        }
+    }
+
+    #[test]
+    fn replay_matches_recording() {
+        let recording = record(&mut Counter::default());
+        let mut simulation = Counter::default();
+        let mut replayer = Replayer::start(&recording, &mut simulation, &mut Random::new(1));
+        replayer.run(&mut simulation).unwrap();
+        assert!(replayer.is_finished());
+    }
+
+    #[test]
+    fn replay_detects_desync() {
+        let recording = record(&mut Counter::default());
+        let mut simulation = Counter {
+            drift_from: Some(1),
+            ..Counter::default()
+        };
+        let mut replayer = Replayer::start(&recording, &mut simulation, &mut Random::default());
+        assert!(matches!(replayer.run(&mut simulation), Err(ReplayError::Desync { frame: 1, .. })));
+        assert_eq!(replayer.frame(), 1);
+
+        let mut simulation = Counter {
+            drift_from: Some(1),
+            ..Counter::default()
+        };
+        Replayer::start(&recording, &mut simulation, &mut Random::default())
+            .without_verification()
+            .run(&mut simulation)
+            .unwrap();
+    }
+}
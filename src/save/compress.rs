@@ -0,0 +1,180 @@
+use super::{SaveError, SaveReader};
+
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 14;
+/// Most output bytes one input byte can decompress to: each 255 of a match length's extra
+/// bytes adds 255.
+const MAX_EXPANSION: usize = 255;
+
+/// A small LZ77 compressor after LZ4's block format: sequences of a token byte, some literal
+/// bytes and a back reference into the output. Fast, and good at the runs of zeros and repeated
+/// records saves are mostly made of.
+pub(super) fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() / 2 + 16);
+    // the last position each hashed 4 byte prefix was seen at
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut position = 0;
+    while position + MIN_MATCH <= input.len() {
+        let prefix = &input[position..position + MIN_MATCH];
+        let key = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+        let hash = (key.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+        let candidate = table[hash];
+        table[hash] = position;
+
+        if candidate == usize::MAX
+            || position - candidate > MAX_OFFSET
+            || input[candidate..candidate + MIN_MATCH] != *prefix
+        {
+            position += 1;
+            continue;
+        }
+        let mut length = MIN_MATCH;
+        while position + length < input.len() && input[candidate + length] == input[position + length] {
+            length += 1;
+        }
+        write_sequence(
+            &mut output,
+            &input[anchor..position],
+            Some((position - candidate, length)),
+        );
+        position += length;
+        anchor = position;
+    }
+    // the last sequence is only literals, which is how the decompressor knows it's done
+    write_sequence(&mut output, &input[anchor..], None);
+    output
+}
+
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_length = matched.map_or(0, |(_, length)| length - MIN_MATCH);
+    output.push(((literals.len().min(15) << 4) | match_length.min(15)) as u8);
+    if literals.len() >= 15 {
+        write_length(output, literals.len() - 15);
+    }
+    output.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_length >= 15 {
+            write_length(output, match_length - 15);
+        }
+    }
+}
+
+fn write_length(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 255 {
+        output.push(255);
+        length -= 255;
+    }
+    output.push(length as u8);
+}
+
+fn read_length(reader: &mut SaveReader<'_>) -> Result<usize, SaveError> {
+    let mut length = 0;
+    loop {
+        let byte = reader.read_u8()?;
+        length += byte as usize;
+        if byte != 255 {
+            return Ok(length);
+        }
+    }
+}
+
+/// Decompresses data that should come out at `size` bytes, failing rather than producing more.
+pub(super) fn decompress(input: &[u8], size: usize) -> Result<Vec<u8>, SaveError> {
+    let corrupt = |message: &str| SaveError::Format(format!("corrupt compressed data: {}", message));
+    // `size` comes from the file, so don't trust it with more than `input` could produce
+    let mut output = Vec::with_capacity(size.min(input.len().saturating_mul(MAX_EXPANSION)));
+    let mut reader = SaveReader::new(input);
+    loop {
+        let token = reader.read_u8()?;
+        let mut literals = (token >> 4) as usize;
+        if literals == 15 {
+            literals += read_length(&mut reader)?;
+        }
+        if output.len() + literals > size {
+            return Err(corrupt("too long"));
+        }
+        output.extend_from_slice(reader.take(literals)?);
+        if reader.remaining() == 0 {
+            break;
+        }
+
+        let offset = reader.read_u16()? as usize;
+        let mut length = (token & 15) as usize + MIN_MATCH;
+        if token & 15 == 15 {
+            length += read_length(&mut reader)?;
+        }
+        if offset == 0 || offset > output.len() {
+            return Err(corrupt("reference before the start"));
+        }
+        if output.len() + length > size {
+            return Err(corrupt("too long"));
+        }
+        // byte by byte, since a match may overlap the bytes it is producing
+        let start = output.len() - offset;
+        for index in start..start + length {
+            output.push(output[index]);
+        }
+    }
+    if output.len() != size {
+        return Err(corrupt("too short"));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(input: &[u8]) {
+        let compressed = compress(input);
+        assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(b"");
+        round_trip(b"abc");
+        round_trip(b"a save made of a save made of a save");
+        // long runs need the extra length bytes of both literals and matches
+        round_trip(&[0; 5000]);
+        let mut x: u32 = 1;
+        let noise: Vec<u8> = (0..3000)
+            .map(|_| {
+                x = x.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (x >> 24) as u8
+            })
+            .collect();
+        round_trip(&noise);
+        let records: Vec<u8> = (0..2000u32).flat_map(|index| [(index % 7) as u8, 0, 0, 1, (index % 3) as u8]).collect();
+        round_trip(&records);
+    }
+
+    #[test]
+    fn compresses_repetition() {
+        assert!(compress(&[0; 5000]).len() < 50);
+    }
+
+    #[test]
+    fn rejects_corrupt_input() {
+        let input: Vec<u8> = b"hello hello hello hello hello".repeat(20);
+        let compressed = compress(&input);
+        assert!(decompress(&compressed, input.len() - 1).is_err());
+        assert!(decompress(&compressed, input.len() + 1).is_err());
+        assert!(decompress(&compressed[..compressed.len() / 2], input.len()).is_err());
+        assert!(decompress(&[], 0).is_err());
+        // a match reaching back before the first byte
+        assert!(decompress(&[0x10, b'a', 0x05, 0x00, 0x00], 5).is_err());
+        // a zero offset
+        assert!(decompress(&[0x10, b'a', 0x00, 0x00, 0x00], 5).is_err());
+    }
+
+    #[test]
+    fn ignores_oversized_headers() {
+        // a huge size must fail on the data rather than allocate up front
+        assert!(decompress(&[0x10, b'a'], usize::MAX).is_err());
+        assert!(decompress(&compress(b"abcd"), 1 << 40).is_err());
+    }
+}
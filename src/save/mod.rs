@@ -0,0 +1,521 @@
+mod compress;
+pub mod slots;
+
+use std::fmt;
+
+use glam::{Quat, Vec2, Vec3, Vec4};
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    /// The file is truncated, corrupt or not a save at all.
+    Format(String),
+    /// A section was written by a newer build than this one can read.
+    Version {
+        section: String,
+        found: u32,
+        supported: u32,
+    },
+    /// Slot names become file names, so they are limited to letters, digits, `-` and `_`.
+    InvalidSlot(String),
+    /// The background thread of an async save or load died before it finished.
+    Aborted,
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "{}", e),
+            SaveError::Format(message) => write!(f, "invalid save: {}", message),
+            SaveError::Version {
+                section,
+                found,
+                supported,
+            } => write!(
+                f,
+                "section \"{}\" has version {}, this build reads up to version {}",
+                section, found, supported
+            ),
+            SaveError::InvalidSlot(slot) => write!(f, "invalid save slot name \"{}\"", slot),
+            SaveError::Aborted => write!(f, "the save thread stopped before finishing"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+/// Builds the bytes of a save section. Everything is little endian with fixed widths, and
+/// lengths are written as `u32`s.
+#[derive(Clone, Debug, Default)]
+pub struct SaveWriter {
+    bytes: Vec<u8>,
+}
+
+impl SaveWriter {
+    pub fn new() -> Self {
+        SaveWriter::default()
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i64(&mut self, value: i64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    /// A length prefixed byte string.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_bytes(value.as_bytes());
+    }
+
+    pub fn write<T: Persist>(&mut self, value: &T) {
+        value.save(self);
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads back what a `SaveWriter` wrote, in the same order.
+#[derive(Clone, Debug)]
+pub struct SaveReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SaveReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        SaveReader { bytes, position: 0 }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], SaveError> {
+        if count > self.remaining() {
+            return Err(SaveError::Format(format!(
+                "needed {} more bytes at offset {}, only {} left",
+                count,
+                self.position,
+                self.remaining()
+            )));
+        }
+        let bytes = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], SaveError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveError> {
+        Ok(u16::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveError> {
+        Ok(u32::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveError> {
+        Ok(u64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, SaveError> {
+        Ok(i32::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64, SaveError> {
+        Ok(i64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, SaveError> {
+        Ok(f32::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, SaveError> {
+        Ok(f64::from_le_bytes(self.take_array()?))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SaveError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(SaveError::Format(format!("{} is not a bool", value))),
+        }
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], SaveError> {
+        let length = self.read_u32()? as usize;
+        self.take(length)
+    }
+
+    pub fn read_str(&mut self) -> Result<&'a str, SaveError> {
+        std::str::from_utf8(self.read_bytes()?).map_err(|e| SaveError::Format(e.to_string()))
+    }
+
+    pub fn read<T: Persist>(&mut self) -> Result<T, SaveError> {
+        T::load(self)
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+}
+
+/// A value that can be written to and read back from a save. Implementations read exactly what
+/// they wrote; versioning happens per section, see `SaveRegistry::register`.
+pub trait Persist: Sized {
+    fn save(&self, writer: &mut SaveWriter);
+    fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError>;
+}
+
+macro_rules! persist_primitive {
+    ($($ty:ty => $write:ident, $read:ident;)*) => {
+        $(
+            impl Persist for $ty {
+                fn save(&self, writer: &mut SaveWriter) {
+                    writer.$write(*self);
+                }
+
+                fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+                    reader.$read()
+                }
+            }
+        )*
+    };
+}
+
+persist_primitive! {
+    u8 => write_u8, read_u8;
+    u16 => write_u16, read_u16;
+    u32 => write_u32, read_u32;
+    u64 => write_u64, read_u64;
+    i32 => write_i32, read_i32;
+    i64 => write_i64, read_i64;
+    f32 => write_f32, read_f32;
+    f64 => write_f64, read_f64;
+    bool => write_bool, read_bool;
+}
+
+impl Persist for String {
+    fn save(&self, writer: &mut SaveWriter) {
+        writer.write_str(self);
+    }
+
+    fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+        reader.read_str().map(str::to_owned)
+    }
+}
+
+impl<T: Persist> Persist for Option<T> {
+    fn save(&self, writer: &mut SaveWriter) {
+        writer.write_bool(self.is_some());
+        if let Some(value) = self {
+            value.save(writer);
+        }
+    }
+
+    fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+        Ok(if reader.read_bool()? {
+            Some(T::load(reader)?)
+        } else {
+            None
+        })
+    }
+}
+
+impl<T: Persist> Persist for Vec<T> {
+    fn save(&self, writer: &mut SaveWriter) {
+        writer.write_u32(self.len() as u32);
+        for value in self {
+            value.save(writer);
+        }
+    }
+
+    fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+        let length = reader.read_u32()? as usize;
+        // every value takes at least a byte, so a corrupt length can't allocate more than the
+        // data could hold
+        let mut values = Vec::with_capacity(length.min(reader.remaining()));
+        for _ in 0..length {
+            values.push(T::load(reader)?);
+        }
+        Ok(values)
+    }
+}
+
+impl<T: Persist, const N: usize> Persist for [T; N] {
+    fn save(&self, writer: &mut SaveWriter) {
+        for value in self {
+            value.save(writer);
+        }
+    }
+
+    fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+        let values = (0..N).map(|_| T::load(reader)).collect::<Result<Vec<_>, _>>()?;
+        Ok(values.try_into().unwrap_or_else(|_| unreachable!()))
+    }
+}
+
+impl<A: Persist, B: Persist> Persist for (A, B) {
+    fn save(&self, writer: &mut SaveWriter) {
+        self.0.save(writer);
+        self.1.save(writer);
+    }
+
+    fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+        Ok((A::load(reader)?, B::load(reader)?))
+    }
+}
+
+macro_rules! persist_glam {
+    ($($ty:ident: $n:literal;)*) => {
+        $(
+            impl Persist for $ty {
+                fn save(&self, writer: &mut SaveWriter) {
+                    self.to_array().save(writer);
+                }
+
+                fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+                    <[f32; $n]>::load(reader).map(|array| $ty::from_slice(&array))
+                }
+            }
+        )*
+    };
+}
+
+persist_glam! {
+    Vec2: 2;
+    Vec3: 3;
+    Vec4: 4;
+    Quat: 4;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Section {
+    name: String,
+    version: u32,
+    bytes: Vec<u8>,
+}
+
+/// The contents of a save: named sections of bytes, each with the version of the code that
+/// wrote it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SaveData {
+    sections: Vec<Section>,
+}
+
+impl SaveData {
+    pub fn new() -> Self {
+        SaveData::default()
+    }
+
+    /// Adds a section, replacing an earlier one with the same name.
+    pub fn insert(&mut self, name: impl Into<String>, version: u32, bytes: Vec<u8>) {
+        let name = name.into();
+        self.sections.retain(|section| section.name != name);
+        self.sections.push(Section { name, version, bytes });
+    }
+
+    /// The version and bytes of the section called `name`.
+    pub fn section(&self, name: &str) -> Option<(u32, &[u8])> {
+        self.sections
+            .iter()
+            .find(|section| section.name == name)
+            .map(|section| (section.version, section.bytes.as_slice()))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.sections.iter().map(|section| section.name.as_str())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = SaveWriter::new();
+        writer.write_u32(self.sections.len() as u32);
+        for section in &self.sections {
+            writer.write_str(&section.name);
+            writer.write_u32(section.version);
+            writer.write_bytes(&section.bytes);
+        }
+        writer.into_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SaveError> {
+        let mut reader = SaveReader::new(bytes);
+        let count = reader.read_u32()?;
+        let mut data = SaveData::new();
+        for _ in 0..count {
+            let name = reader.read_str()?.to_owned();
+            let version = reader.read_u32()?;
+            let bytes = reader.read_bytes()?.to_vec();
+            data.sections.push(Section { name, version, bytes });
+        }
+        if reader.remaining() > 0 {
+            return Err(SaveError::Format(format!(
+                "{} bytes after the last section",
+                reader.remaining()
+            )));
+        }
+        Ok(data)
+    }
+}
+
+type SaveFn<W> = Box<dyn Fn(&W, &mut SaveWriter)>;
+type LoadFn<W> = Box<dyn Fn(&mut W, &mut SaveReader<'_>, u32) -> Result<(), SaveError>>;
+
+struct Entry<W> {
+    name: String,
+    version: u32,
+    save: SaveFn<W>,
+    load: LoadFn<W>,
+}
+
+/// What goes into a save of a `W`, the game's world or whatever holds its state. Each registered
+/// piece becomes one section, so adding, removing or changing one doesn't invalidate the others.
+///
+/// `capture` and `restore` only copy bytes in and out of `W` and are cheap enough to run on the
+/// frame; compression and file access happen in `SaveSlots`, which can do them in the background.
+pub struct SaveRegistry<W> {
+    entries: Vec<Entry<W>>,
+}
+
+impl<W> Default for SaveRegistry<W> {
+    fn default() -> Self {
+        SaveRegistry { entries: Vec::new() }
+    }
+}
+
+impl<W> SaveRegistry<W> {
+    pub fn new() -> Self {
+        SaveRegistry::default()
+    }
+
+    /// Registers a section of the save. Bump `version` whenever what `save` writes changes;
+    /// `load` gets the version the section was actually written with so it can still read
+    /// older saves, and sections from a newer build are rejected before `load` sees them.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        version: u32,
+        save: impl Fn(&W, &mut SaveWriter) + 'static,
+        load: impl Fn(&mut W, &mut SaveReader<'_>, u32) -> Result<(), SaveError> + 'static,
+    ) {
+        let name = name.into();
+        self.entries.retain(|entry| entry.name != name);
+        self.entries.push(Entry {
+            name,
+            version,
+            save: Box::new(save),
+            load: Box::new(load),
+        });
+    }
+
+    /// Registers a single value of `W` that is saved as a whole, e.g. the player's inventory or
+    /// the quest log. Its section stays at version 1; use `register` once it needs migrating.
+    pub fn register_resource<T: Persist + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        get: impl Fn(&W) -> &T + 'static,
+        get_mut: impl Fn(&mut W) -> &mut T + 'static,
+    ) {
+        self.register(
+            name,
+            1,
+            move |world, writer| get(world).save(writer),
+            move |world, reader, _| {
+                *get_mut(world) = T::load(reader)?;
+                Ok(())
+            },
+        );
+    }
+
+    pub fn capture(&self, world: &W) -> SaveData {
+        let mut data = SaveData::new();
+        for entry in &self.entries {
+            let mut writer = SaveWriter::new();
+            (entry.save)(world, &mut writer);
+            data.insert(entry.name.clone(), entry.version, writer.into_bytes());
+        }
+        data
+    }
+
+    /// Loads every registered section found in `data` into `world`. Sections missing from the
+    /// save, e.g. ones registered after it was written, leave `world` as it was, and sections
+    /// nothing is registered for are ignored. Versions are checked up front, but a section that
+    /// fails to decode leaves the ones before it loaded, so restore into a freshly reset world.
+    pub fn restore(&self, world: &mut W, data: &SaveData) -> Result<(), SaveError> {
+        for entry in &self.entries {
+            if let Some((version, _)) = data.section(&entry.name) {
+                if version > entry.version {
+                    return Err(SaveError::Version {
+                        section: entry.name.clone(),
+                        found: version,
+                        supported: entry.version,
+                    });
+                }
+            }
+        }
+        for entry in &self.entries {
+            let Some((version, bytes)) = data.section(&entry.name) else {
+                continue;
+            };
+            let mut reader = SaveReader::new(bytes);
+            (entry.load)(world, &mut reader, version)?;
+            if reader.remaining() > 0 {
+                return Err(SaveError::Format(format!(
+                    "section \"{}\" has {} bytes left after loading",
+                    entry.name,
+                    reader.remaining()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    hash::Hasher,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{compress, SaveData, SaveError, SaveReader, SaveWriter};
+use crate::replay::StateHasher;
+
+const MAGIC: &[u8; 4] = b"WESV";
+/// Version of the file layout itself; the data's own versions are per section.
+const FORMAT_VERSION: u32 = 1;
+const EXTENSION: &str = "sav";
+
+/// What a slot list shows about a save, readable without decompressing the rest of the file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaveHeader {
+    /// Shown to the player, unlike the slot name, which is the file name.
+    pub name: String,
+    /// When the save was made, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Seconds played up to the save.
+    pub play_time: f64,
+}
+
+impl SaveHeader {
+    /// A header timestamped now.
+    pub fn new(name: impl Into<String>, play_time: f64) -> Self {
+        SaveHeader {
+            name: name.into(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            play_time,
+        }
+    }
+
+    fn write(&self, writer: &mut SaveWriter) {
+        writer.write_str(&self.name);
+        writer.write_u64(self.timestamp);
+        writer.write_f64(self.play_time);
+    }
+
+    fn read(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+        Ok(SaveHeader {
+            name: reader.read_str()?.to_owned(),
+            timestamp: reader.read_u64()?,
+            play_time: reader.read_f64()?,
+        })
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hasher = StateHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// The bytes of a save file: magic and format version, the header, then the sections,
+/// compressed unless that wouldn't make them smaller, with a checksum of the uncompressed data.
+pub fn encode(header: &SaveHeader, data: &SaveData, compression: bool) -> Vec<u8> {
+    let raw = data.to_bytes();
+    let compressed = compression
+        .then(|| compress::compress(&raw))
+        .filter(|compressed| compressed.len() < raw.len());
+
+    let mut writer = SaveWriter::new();
+    MAGIC.iter().for_each(|&byte| writer.write_u8(byte));
+    writer.write_u32(FORMAT_VERSION);
+    header.write(&mut writer);
+    writer.write_bool(compressed.is_some());
+    writer.write_u32(raw.len() as u32);
+    writer.write_u64(checksum(&raw));
+    writer.write_bytes(compressed.as_deref().unwrap_or(&raw));
+    writer.into_bytes()
+}
+
+fn decode_header<'a>(bytes: &'a [u8]) -> Result<(SaveHeader, SaveReader<'a>), SaveError> {
+    let mut reader = SaveReader::new(bytes);
+    if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err(SaveError::Format("not a save file".into()));
+    }
+    let format = reader.read_u32()?;
+    if format > FORMAT_VERSION {
+        return Err(SaveError::Version {
+            section: "file".into(),
+            found: format,
+            supported: FORMAT_VERSION,
+        });
+    }
+    let header = SaveHeader::read(&mut reader)?;
+    Ok((header, reader))
+}
+
+pub fn decode(bytes: &[u8]) -> Result<(SaveHeader, SaveData), SaveError> {
+    let (header, mut reader) = decode_header(bytes)?;
+    let compressed = reader.read_bool()?;
+    let size = reader.read_u32()? as usize;
+    let expected = reader.read_u64()?;
+    let payload = reader.read_bytes()?;
+    let raw = if compressed {
+        compress::decompress(payload, size)?
+    } else {
+        payload.to_vec()
+    };
+    if raw.len() != size || checksum(&raw) != expected {
+        return Err(SaveError::Format("checksum mismatch".into()));
+    }
+    Ok((header, SaveData::from_bytes(&raw)?))
+}
+
+/// Named save slots, one file each in a directory, usually `ProjectDirs::saves_dir`. Writes go to a temporary file that replaces
+/// the slot's only once complete, so a crash mid-save keeps the previous save.
+#[derive(Clone, Debug)]
+pub struct SaveSlots {
+    directory: PathBuf,
+    /// Compress saves before writing them. On by default.
+    pub compression: bool,
+}
+
+impl SaveSlots {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        SaveSlots {
+            directory: directory.into(),
+            compression: true,
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn path(&self, slot: &str) -> Result<PathBuf, SaveError> {
+        let valid = !slot.is_empty() && slot.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(SaveError::InvalidSlot(slot.to_owned()));
+        }
+        Ok(self.directory.join(slot).with_extension(EXTENSION))
+    }
+
+    pub fn save(&self, slot: &str, header: &SaveHeader, data: &SaveData) -> Result<(), SaveError> {
+        let path = self.path(slot)?;
+        write_file(&path, &encode(header, data, self.compression))
+    }
+
+    pub fn load(&self, slot: &str) -> Result<(SaveHeader, SaveData), SaveError> {
+        let path = self.path(slot)?;
+        decode(&std::fs::read(path).map_err(SaveError::Io)?)
+    }
+
+    /// Compresses and writes the save on a background thread. `data` comes from
+    /// `SaveRegistry::capture`, which is the only part that has to run on the frame.
+    pub fn save_async(&self, slot: &str, header: SaveHeader, data: SaveData) -> SaveTask<()> {
+        let path = self.path(slot);
+        let compression = self.compression;
+        SaveTask::spawn(format!("save: {}", slot), move || {
+            write_file(&path?, &encode(&header, &data, compression))
+        })
+    }
+
+    /// Reads and decompresses a save on a background thread; pass the result to
+    /// `SaveRegistry::restore` once the task finishes.
+    pub fn load_async(&self, slot: &str) -> SaveTask<(SaveHeader, SaveData)> {
+        let path = self.path(slot);
+        SaveTask::spawn(format!("load: {}", slot), move || {
+            decode(&std::fs::read(path?).map_err(SaveError::Io)?)
+        })
+    }
+
+    /// The slots in the directory with their headers, most recent first. Only the headers are
+    /// read; files that aren't saves are skipped.
+    pub fn list(&self) -> Vec<(String, SaveHeader)> {
+        let entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+            Err(e) => {
+                log::error!("Failed to list saves in {}: {}", self.directory.display(), e);
+                return Vec::new();
+            }
+        };
+        let mut slots: Vec<_> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != EXTENSION {
+                    return None;
+                }
+                let slot = path.file_stem()?.to_str()?.to_owned();
+                match std::fs::read(&path)
+                    .map_err(SaveError::Io)
+                    .and_then(|bytes| decode_header(&bytes).map(|(header, _)| header))
+                {
+                    Ok(header) => Some((slot, header)),
+                    Err(e) => {
+                        log::warn!("Skipping save {}: {}", path.display(), e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        slots.sort_by_key(|(_, header)| std::cmp::Reverse(header.timestamp));
+        slots
+    }
+
+    pub fn delete(&self, slot: &str) -> Result<(), SaveError> {
+        std::fs::remove_file(self.path(slot)?).map_err(SaveError::Io)
+    }
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), SaveError> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory).map_err(SaveError::Io)?;
+    }
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, bytes).map_err(SaveError::Io)?;
+    std::fs::rename(&temporary, path).map_err(SaveError::Io)
+}
+
+enum State<T> {
+    Running(mpsc::Receiver<Result<T, SaveError>>),
+    Finished(Option<Result<T, SaveError>>),
+}
+
+/// A save or load running in the background. Check on it once per frame with `poll`.
+pub struct SaveTask<T> {
+    state: State<T>,
+}
+
+impl<T: Send + 'static> SaveTask<T> {
+    fn spawn(name: String, work: impl FnOnce() -> Result<T, SaveError> + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let spawned = std::thread::Builder::new().name(name).spawn(move || {
+            sender.send(work()).ok();
+        });
+        let state = match spawned {
+            Ok(_) => State::Running(receiver),
+            Err(e) => State::Finished(Some(Err(SaveError::Io(e)))),
+        };
+        SaveTask { state }
+    }
+
+    fn update(&mut self) {
+        if let State::Running(receiver) = &self.state {
+            match receiver.try_recv() {
+                Ok(result) => self.state = State::Finished(Some(result)),
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => self.state = State::Finished(Some(Err(SaveError::Aborted))),
+            }
+        }
+    }
+
+    /// The result, once, when the task has finished; `None` while it is still running and
+    /// every time after the result was taken.
+    pub fn poll(&mut self) -> Option<Result<T, SaveError>> {
+        self.update();
+        match &mut self.state {
+            State::Finished(result) => result.take(),
+            State::Running(_) => None,
+        }
+    }
+
+    pub fn is_finished(&mut self) -> bool {
+        self.update();
+        matches!(self.state, State::Finished(_))
+    }
+
+    /// Blocks until the task finishes, e.g. to make sure a save on quit is written.
+    pub fn wait(mut self) -> Result<T, SaveError> {
+        match std::mem::replace(&mut self.state, State::Finished(None)) {
+            State::Running(receiver) => receiver.recv().unwrap_or(Err(SaveError::Aborted)),
+            State::Finished(result) => result.unwrap_or(Err(SaveError::Aborted)),
+        }
+    }
+}
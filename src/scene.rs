@@ -0,0 +1,291 @@
+use std::sync::Arc;
+
+use glam::{Mat4, Quat, Vec3};
+
+use crate::{
+    camera::{Camera, CameraTarget},
+    ecs::World,
+    light_profiles::{CookieId, ProfileId},
+    lights::{FrameLight, LightKind},
+    material::Material,
+    mesh::Mesh,
+    mesh_renderer::MeshRenderer,
+    render_layers::RenderLayers,
+    spline::FollowPath,
+};
+
+/// Where an entity is. Entities with a `camera::Camera` view from it, ignoring the camera's own
+/// position and orientation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Transform {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// Turned so `forward` points at `target`, with `up` kept as close to up as possible.
+    pub fn looking_at(mut self, target: Vec3, up: Vec3) -> Self {
+        let view = Mat4::look_at_rh(self.translation, target, up);
+        self.rotation = Quat::from_mat4(&view.inverse()).normalize();
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Between `self` at 0 and `other` at 1, e.g. the states before and after the last fixed
+    /// update at `alpha`.
+    pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        Transform {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// -Z turned by the rotation, the way cameras and lights point.
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * -Vec3::Z
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.rotation * Vec3::X
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::Y
+    }
+}
+
+/// Draws a mesh at the entity's `Transform` with the engine's mesh renderer.
+#[derive(Clone, Debug)]
+pub struct MeshInstance {
+    pub mesh: Arc<Mesh>,
+    /// Linear RGBA tint.
+    pub color: [f32; 4],
+    /// Shades the mesh with the PBR shader; the basic lit one without.
+    pub material: Option<Arc<Material>>,
+    pub visible: bool,
+}
+
+impl MeshInstance {
+    pub fn new(mesh: Arc<Mesh>) -> Self {
+        MeshInstance {
+            mesh,
+            color: [1.0; 4],
+            material: None,
+            visible: true,
+        }
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_material(mut self, material: Arc<Material>) -> Self {
+        self.material = Some(material);
+        self
+    }
+}
+
+/// A light at the entity's `Transform`, shining along its forward. Directional by default,
+/// like the sun.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear RGB.
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Distance at which point and spot lights have faded out completely.
+    pub range: f32,
+    /// An IES profile shaping a point or spot light, e.g. a loaded `LightProfile`'s id.
+    pub profile: Option<ProfileId>,
+    /// A cookie projected along a spot light's cone.
+    pub cookie: Option<CookieId>,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Light {
+            kind: LightKind::Directional,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: 10.0,
+            profile: None,
+            cookie: None,
+        }
+    }
+}
+
+impl Light {
+    pub fn point(range: f32) -> Self {
+        Light {
+            kind: LightKind::Point,
+            range,
+            ..Default::default()
+        }
+    }
+
+    /// A spot light with a cone of `outer_angle` half angle in radians, fading out from
+    /// `inner_angle`.
+    pub fn spot(range: f32, inner_angle: f32, outer_angle: f32) -> Self {
+        Light {
+            kind: LightKind::Spot { inner_angle, outer_angle },
+            range,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_color(mut self, color: Vec3) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_profile(mut self, profile: ProfileId) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    pub fn with_cookie(mut self, cookie: CookieId) -> Self {
+        self.cookie = Some(cookie);
+        self
+    }
+
+    /// The light placed by `transform`, as the renderer takes it.
+    pub fn frame_light(&self, transform: &Transform) -> FrameLight {
+        FrameLight {
+            kind: self.kind,
+            position: transform.translation,
+            direction: transform.forward(),
+            color: self.color * self.intensity,
+            range: self.range,
+            profile: self.profile,
+            cookie: self.cookie,
+        }
+    }
+}
+
+/// Includes the scene components in `World::snapshot`. The engine calls it for its world.
+pub fn register_snapshot_components(world: &mut World) {
+    world.snapshot_component::<Transform>();
+    world.snapshot_component::<MeshInstance>();
+    world.snapshot_component::<Light>();
+    world.snapshot_component::<Camera>();
+    world.snapshot_component::<RenderLayers>();
+}
+
+/// The world's active camera entities in render order, placed by their `Transform`s.
+pub fn scene_cameras(world: &World) -> Vec<Camera> {
+    let mut cameras: Vec<Camera> = world
+        .query2::<Camera, Transform>()
+        .filter(|(_, camera, _)| camera.active)
+        .map(|(_, camera, transform)| Camera {
+            position: transform.translation,
+            forward: transform.forward(),
+            up: transform.up(),
+            ..camera.clone()
+        })
+        .collect();
+    cameras.sort_by_key(|camera| camera.order);
+    cameras
+}
+
+/// The world's camera entity, if it has one: the active surface camera with the lowest
+/// `order`, placed by its `Transform`. The engine renders the others after it, see
+/// `RenderWorld::cameras`.
+pub fn scene_camera(world: &World) -> Option<Camera> {
+    scene_cameras(world).into_iter().find(|camera| camera.target == CameraTarget::Surface)
+}
+
+/// Renders from the world's camera entity if it has one, see `scene_camera`. Returns false,
+/// leaving `camera` as it is, without one.
+pub fn camera_system(world: &World, camera: &mut Camera) -> bool {
+    let Some(scene_camera) = scene_camera(world) else {
+        return false;
+    };
+    *camera = scene_camera;
+    true
+}
+
+/// Lights the mesh renderer with the world's `Light`s. Worlds without any keep the renderer's
+/// lights as they are.
+pub fn light_system(world: &World, meshes: &mut MeshRenderer) {
+    let lights: Vec<FrameLight> = world
+        .query2::<Light, Transform>()
+        .map(|(_, light, transform)| light.frame_light(transform))
+        .collect();
+    if !lights.is_empty() {
+        meshes.lights = lights;
+    }
+}
+
+/// Queues every visible `MeshInstance` for this frame, on the entity's `RenderLayers` if it
+/// has them.
+pub fn mesh_system(world: &World, meshes: &mut MeshRenderer) {
+    for (entity, instance, transform) in world.query2::<MeshInstance, Transform>() {
+        if !instance.visible {
+            continue;
+        }
+        let layers = world.get::<RenderLayers>(entity).copied().unwrap_or(RenderLayers::DEFAULT);
+        match &instance.material {
+            Some(material) => meshes.draw_material(&instance.mesh, transform.matrix(), instance.color, material, layers),
+            None => meshes.draw_on_layers(&instance.mesh, transform.matrix(), instance.color, layers),
+        }
+    }
+}
+
+/// Advances every `FollowPath` by `dt` and puts the entity's `Transform` where it is on the
+/// path, turned along it for paths with `orient` set.
+pub fn follow_path_system(world: &mut World, dt: f32) {
+    let samples: Vec<_> = world
+        .query_mut::<FollowPath>()
+        .map(|(entity, mut follow)| (entity, follow.orient, follow.advance(dt)))
+        .collect();
+    for (entity, orient, sample) in samples {
+        let Some(mut transform) = world.get_mut::<Transform>(entity) else {
+            continue;
+        };
+        transform.translation = sample.position;
+        // a vertical tangent leaves the rotation as it was rather than spinning around it
+        if orient && sample.tangent.cross(Vec3::Y).length_squared() > f32::EPSILON {
+            *transform = transform.looking_at(sample.position + sample.tangent, Vec3::Y);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::gpu_error;
+
+/// The engine's own shaders, in the source tree they were built from.
+pub const SHADER_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
+
+/// A shader registered with `ShaderWatcher::watch`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderId(usize);
+
+struct WatchedShader {
+    label: String,
+    files: Vec<String>,
+    modified: Vec<Option<SystemTime>>,
+    error: Option<String>,
+}
+
+/// Polls WGSL files for changes so pipelines can be rebuilt while the engine runs. Each watched
+/// shader is one or more files, concatenated in order, e.g. `fullscreen.wgsl` then the pass's
+/// own shader.
+///
+/// Sources are read back and compiled by whoever owns the pipeline: check `changed` after
+/// `update`, then `reload` with a closure building the new pipeline. A shader that fails to
+/// compile keeps its previous pipeline, and the error is listed in `errors` (and the engine's
+/// error window) until a later edit fixes it.
+pub struct ShaderWatcher {
+    directory: PathBuf,
+    /// Without it `update` never reports changes.
+    pub enabled: bool,
+    /// Seconds between checks of the files' modification times.
+    pub poll_interval: f32,
+    since_poll: f32,
+    shaders: Vec<WatchedShader>,
+    changed: Vec<ShaderId>,
+}
+
+impl ShaderWatcher {
+    /// Watches files in `directory`, which need not exist; shaders there just never change.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        ShaderWatcher {
+            directory: directory.into(),
+            enabled: true,
+            poll_interval: 0.5,
+            since_poll: 0.0,
+            shaders: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Starts watching the shader made of `files`, relative to the directory. The files as they
+    /// are now count as already loaded.
+    pub fn watch(&mut self, label: impl Into<String>, files: &[&str]) -> ShaderId {
+        let files: Vec<String> = files.iter().map(|file| file.to_string()).collect();
+        let modified = files.iter().map(|file| self.modified(file)).collect();
+        self.shaders.push(WatchedShader {
+            label: label.into(),
+            files,
+            modified,
+            error: None,
+        });
+        ShaderId(self.shaders.len() - 1)
+    }
+
+    fn modified(&self, file: &str) -> Option<SystemTime> {
+        std::fs::metadata(self.directory.join(file)).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /// Checks for edits once every `poll_interval`. Shaders edited since the last check are in
+    /// `changed` until the next call.
+    pub fn update(&mut self, dt: f32) {
+        self.changed.clear();
+        self.since_poll += dt;
+        if !self.enabled || self.since_poll < self.poll_interval {
+            return;
+        }
+        self.since_poll = 0.0;
+
+        for index in 0..self.shaders.len() {
+            let mut changed = false;
+            for file in 0..self.shaders[index].files.len() {
+                // a file missing for a moment, as some editors save by replacing it, is not an edit
+                let Some(modified) = self.modified(&self.shaders[index].files[file]) else {
+                    continue;
+                };
+                let known = &mut self.shaders[index].modified[file];
+                if *known != Some(modified) {
+                    *known = Some(modified);
+                    changed = true;
+                }
+            }
+            if changed {
+                self.changed.push(ShaderId(index));
+            }
+        }
+    }
+
+    /// Shaders whose files changed at the last `update`.
+    pub fn changed(&self) -> &[ShaderId] {
+        &self.changed
+    }
+
+    pub fn is_changed(&self, id: ShaderId) -> bool {
+        self.changed.contains(&id)
+    }
+
+    pub fn label(&self, id: ShaderId) -> &str {
+        &self.shaders[id.0].label
+    }
+
+    /// The shader's files read from disk and joined.
+    pub fn source(&self, id: ShaderId) -> Result<String, ShaderReloadError> {
+        let mut source = String::new();
+        for file in &self.shaders[id.0].files {
+            source.push_str(&self.read(file)?);
+            source.push('\n');
+        }
+        Ok(source)
+    }
+
+    /// One file of the directory, for shaders whose owner assembles them itself.
+    pub fn read(&self, file: &str) -> Result<String, ShaderReloadError> {
+        let path = self.directory.join(file);
+        std::fs::read_to_string(&path).map_err(|error| ShaderReloadError::Read { path, error })
+    }
+
+    /// Reads and compiles the shader, then passes the module to `build` for the new pipeline.
+    /// Returns `None` if reading, compiling or building failed, in which case the caller keeps
+    /// what it had.
+    pub fn reload<T>(&mut self, device: &wgpu::Device, id: ShaderId, build: impl FnOnce(&wgpu::ShaderModule) -> T) -> Option<T> {
+        let result = self.source(id).and_then(|source| {
+            let label = self.label(id);
+            gpu_error::capture(device, format!("reloading shader \"{}\"", label), || {
+                let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                    label: Some(label),
+                    source: wgpu::ShaderSource::Wgsl(source.into()),
+                });
+                build(&module)
+            })
+            .map_err(ShaderReloadError::Compile)
+        });
+        match result {
+            Ok(value) => {
+                self.report(id, Ok::<_, ShaderReloadError>(()));
+                Some(value)
+            }
+            Err(e) => {
+                self.report(id, Err(e));
+                None
+            }
+        }
+    }
+
+    /// Records the outcome of a reload done without `reload`, e.g. through
+    /// `MeshRenderer::set_shader`, so failures are listed with the others.
+    pub fn report(&mut self, id: ShaderId, result: Result<(), impl fmt::Display>) {
+        let shader = &mut self.shaders[id.0];
+        match result {
+            Ok(()) => {
+                if shader.error.take().is_some() {
+                    log::info!("Shader \"{}\" reloaded", shader.label);
+                }
+            }
+            Err(e) => {
+                let message = e.to_string();
+                log::warn!("Keeping the previous \"{}\" shader: {}", shader.label, message);
+                shader.error = Some(message);
+            }
+        }
+    }
+
+    /// The shaders whose last reload failed, with why.
+    pub fn errors(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.shaders
+            .iter()
+            .filter_map(|shader| Some((shader.label.as_str(), shader.error.as_deref()?)))
+    }
+
+    /// A window listing the failed reloads, shown only while there are some.
+    #[cfg(feature = "egui")]
+    pub fn error_window(&self, context: &egui::CtxRef) {
+        if self.errors().next().is_none() {
+            return;
+        }
+        egui::Window::new("Shader errors").default_width(480.0).show(context, |ui| {
+            egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                for (label, error) in self.errors() {
+                    ui.strong(label);
+                    ui.add(egui::Label::new(egui::RichText::new(error).monospace().color(egui::Color32::LIGHT_RED)));
+                    ui.separator();
+                }
+            });
+        });
+    }
+}
+
+#[derive(Debug)]
+pub enum ShaderReloadError {
+    Read { path: PathBuf, error: std::io::Error },
+    Compile(gpu_error::GpuError),
+}
+
+impl fmt::Display for ShaderReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderReloadError::Read { path, error } => write!(f, "failed to read {}: {}", path.display(), error),
+            ShaderReloadError::Compile(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ShaderReloadError {}
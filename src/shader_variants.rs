@@ -0,0 +1,245 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::gpu_error::{self, GpuError};
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Preprocess { line: usize, message: String },
+    UnknownFeature(String),
+    Compile(GpuError),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Preprocess { line, message } => write!(f, "line {}: {}", line, message),
+            ShaderError::UnknownFeature(name) => write!(f, "unknown shader feature \"{}\"", name),
+            ShaderError::Compile(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// Strips WGSL source down to one variant. Supports `#ifdef NAME`, `#ifndef NAME`, `#else` and
+/// `#endif` on their own lines; lines in inactive branches are blanked so error line numbers
+/// still match the original file. Any other line starting with `#` is an error, as WGSL has
+/// none.
+pub fn preprocess(source: &str, defined: impl Fn(&str) -> bool) -> Result<String, ShaderError> {
+    // one entry per open block: (this branch active, any enclosing block inactive, seen #else,
+    // the line it opened at)
+    let mut stack: Vec<(bool, bool, bool, usize)> = Vec::new();
+    let mut out = String::with_capacity(source.len());
+    let error = |line: usize, message: String| ShaderError::Preprocess { line: line + 1, message };
+
+    for (number, line) in source.lines().enumerate() {
+        let trimmed = line.trim();
+        let active = stack.last().is_none_or(|&(active, ..)| active);
+        if trimmed.starts_with('#') {
+            let mut words = trimmed.split_whitespace();
+            let directive = words.next().unwrap_or_default();
+            let arguments: Vec<&str> = words.collect();
+            match (directive, arguments.as_slice()) {
+                ("#ifdef", [name]) => stack.push((active && defined(name), !active, false, number)),
+                ("#ifndef", [name]) => stack.push((active && !defined(name), !active, false, number)),
+                ("#ifdef" | "#ifndef", _) => return Err(error(number, format!("{} takes one name", directive))),
+                ("#else", []) => match stack.last_mut() {
+                    Some((_, _, true, _)) => return Err(error(number, "second #else in a block".into())),
+                    Some((active, parent_inactive, seen_else, _)) => {
+                        *active = !*active && !*parent_inactive;
+                        *seen_else = true;
+                    }
+                    None => return Err(error(number, "#else without #ifdef".into())),
+                },
+                ("#endif", []) => {
+                    if stack.pop().is_none() {
+                        return Err(error(number, "#endif without #ifdef".into()));
+                    }
+                }
+                ("#else" | "#endif", _) => return Err(error(number, format!("{} takes no arguments", directive))),
+                _ => return Err(error(number, format!("unknown directive {}", directive))),
+            }
+        } else if active {
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+
+    if let Some(&(.., line)) = stack.last() {
+        return Err(error(line, "unterminated #ifdef".into()));
+    }
+    Ok(out)
+}
+
+/// A set of enabled feature bits, e.g. skinned + shadows + normal mapping.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VariantKey(pub u64);
+
+impl VariantKey {
+    pub fn with(self, feature: usize) -> Self {
+        VariantKey(self.0 | 1 << feature)
+    }
+
+    pub fn has(self, feature: usize) -> bool {
+        self.0 & (1 << feature) != 0
+    }
+}
+
+/// A WGSL template whose permutations are compiled the first time they are asked for and
+/// cached afterwards, so a draw only pays for the features it uses.
+pub struct ShaderVariants {
+    label: String,
+    source: String,
+    features: Vec<&'static str>,
+    modules: HashMap<VariantKey, Arc<wgpu::ShaderModule>>,
+}
+
+impl ShaderVariants {
+    /// `features` are the `#ifdef` names the template understands; their index is their bit in
+    /// a `VariantKey`. At most 64 are supported.
+    pub fn new(label: impl Into<String>, source: impl Into<String>, features: &[&'static str]) -> Self {
+        assert!(features.len() <= 64, "at most 64 shader features are supported");
+        ShaderVariants {
+            label: label.into(),
+            source: source.into(),
+            features: features.to_vec(),
+            modules: HashMap::new(),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn feature(&self, name: &str) -> Option<usize> {
+        self.features.iter().position(|&f| f == name)
+    }
+
+    /// Builds a key from feature names.
+    pub fn key(&self, names: &[&str]) -> Result<VariantKey, ShaderError> {
+        names.iter().try_fold(VariantKey::default(), |key, name| {
+            self.feature(name)
+                .map(|feature| key.with(feature))
+                .ok_or_else(|| ShaderError::UnknownFeature(name.to_string()))
+        })
+    }
+
+    /// The preprocessed source of one variant.
+    pub fn source(&self, key: VariantKey) -> Result<String, ShaderError> {
+        preprocess(&self.source, |name| self.feature(name).is_some_and(|feature| key.has(feature)))
+    }
+
+    pub fn get(&mut self, device: &wgpu::Device, key: VariantKey) -> Result<Arc<wgpu::ShaderModule>, ShaderError> {
+        if let Some(module) = self.modules.get(&key) {
+            return Ok(module.clone());
+        }
+
+        let source = self.source(key)?;
+        let label = format!("{} {}", self.label, self.describe(key));
+        let module = gpu_error::capture(device, format!("compiling shader \"{}\"", label), || {
+            device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some(&label),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            })
+        })
+        .map_err(ShaderError::Compile)?;
+
+        let module = Arc::new(module);
+        self.modules.insert(key, module.clone());
+        Ok(module)
+    }
+
+    /// Replaces the template, dropping every cached variant.
+    pub fn set_source(&mut self, source: impl Into<String>) {
+        self.source = source.into();
+        self.modules.clear();
+    }
+
+    pub fn compiled_variants(&self) -> usize {
+        self.modules.len()
+    }
+
+    /// Feature names of a key, for labels and debug output.
+    pub fn describe(&self, key: VariantKey) -> String {
+        let names: Vec<_> = self
+            .features
+            .iter()
+            .enumerate()
+            .filter(|&(feature, _)| key.has(feature))
+            .map(|(_, &name)| name)
+            .collect();
+        format!("[{}]", names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(source: &str, defined: &[&str]) -> Vec<String> {
+        let out = preprocess(source, |name| defined.contains(&name)).unwrap();
+        out.lines().map(|line| line.trim().to_string()).collect()
+    }
+
+    fn error_line(source: &str) -> usize {
+        match preprocess(source, |_| true) {
+            Err(ShaderError::Preprocess { line, .. }) => line,
+            other => panic!("expected a preprocess error, got {:?}", other.map_err(|e| e.to_string())),
+        }
+    }
+
+    const NESTED: &str = "a
+#ifdef OUTER
+b
+#ifndef INNER
+c
+#else
+d
+#endif
+#else
+e
+#ifdef INNER
+f
+#endif
+#endif
+g";
+
+    #[test]
+    fn nested_blocks() {
+        // inactive lines are blanked, not removed
+        assert_eq!(lines(NESTED, &[]), ["a", "", "", "", "", "", "", "", "", "e", "", "", "", "", "g"]);
+        assert_eq!(lines(NESTED, &["OUTER"]), ["a", "", "b", "", "c", "", "", "", "", "", "", "", "", "", "g"]);
+        assert_eq!(lines(NESTED, &["OUTER", "INNER"]), ["a", "", "b", "", "", "", "d", "", "", "", "", "", "", "", "g"]);
+        // an #else inside an inactive block stays inactive
+        assert_eq!(lines(NESTED, &["INNER"]), ["a", "", "", "", "", "", "", "", "", "e", "", "f", "", "", "g"]);
+    }
+
+    #[test]
+    fn unbalanced_blocks() {
+        assert_eq!(error_line("a\n#endif\n"), 2);
+        assert_eq!(error_line("#else\n"), 1);
+        assert_eq!(error_line("#ifdef A\nb\n"), 1);
+        assert_eq!(error_line("#ifdef A\n#ifdef B\n#endif\n"), 1);
+        assert_eq!(error_line("#ifdef A\n#else\n#else\n#endif\n"), 3);
+    }
+
+    #[test]
+    fn malformed_directives() {
+        assert_eq!(error_line("#define A 1\n"), 1);
+        assert_eq!(error_line("a\n#if A\n#endif\n"), 2);
+        assert_eq!(error_line("#ifdefA\n#endif\n"), 1);
+        assert_eq!(error_line("#ifdef\n#endif\n"), 1);
+        assert_eq!(error_line("#ifdef A B\n#endif\n"), 1);
+        assert_eq!(error_line("#ifdef A\n#endif A\n"), 2);
+    }
+
+    #[test]
+    fn variant_sources() {
+        let variants = ShaderVariants::new("test", "#ifdef SKINNED\nskin\n#endif\n#ifdef SHADOWS\nshadow\n#endif", &["SKINNED", "SHADOWS"]);
+        let key = variants.key(&["SHADOWS"]).unwrap();
+        assert_eq!(key, VariantKey::default().with(1));
+        assert_eq!(variants.source(key).unwrap(), "\n\n\n\nshadow\n\n");
+        assert_eq!(variants.describe(key.with(0)), "[SKINNED, SHADOWS]");
+        assert!(matches!(variants.key(&["FOG"]), Err(ShaderError::UnknownFeature(name)) if name == "FOG"));
+    }
+}
@@ -0,0 +1,104 @@
+// Luminance histogram over the HDR scene, then a weighted average that adapts the exposure over
+// time. Bin 0 holds pixels darker than the histogram range and is left out of the average.
+
+struct Params {
+    min_log_luminance: f32;
+    log_luminance_range: f32;
+    speed_up: f32;
+    speed_down: f32;
+    min_ev: f32;
+    max_ev: f32;
+    compensation: f32;
+    delta_time: f32;
+};
+
+struct Exposure {
+    luminance: f32;
+    exposure: f32;
+};
+
+struct Histogram {
+    bins: array<atomic<u32>, 256>;
+};
+
+let BIN_COUNT: u32 = 256u;
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var hdr_texture: texture_2d<f32>;
+[[group(0), binding(2)]] var<storage, read_write> histogram: Histogram;
+[[group(0), binding(3)]] var<storage, read_write> exposure: Exposure;
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+var<workgroup> weighted: array<u32, 256>;
+
+fn bin_for(color: vec3<f32>) -> u32 {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if (luminance < 0.0001) {
+        return 0u;
+    }
+    let t = clamp((log2(luminance) - params.min_log_luminance) / params.log_luminance_range, 0.0, 1.0);
+    return u32(t * 254.0 + 1.0);
+}
+
+[[stage(compute), workgroup_size(16, 16)]]
+fn build_histogram(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+    [[builtin(local_invocation_index)]] index: u32,
+) {
+    atomicStore(&local_bins[index], 0u);
+    workgroupBarrier();
+
+    let size = textureDimensions(hdr_texture);
+    if (i32(id.x) < size.x && i32(id.y) < size.y) {
+        let color = textureLoad(hdr_texture, vec2<i32>(id.xy), 0).rgb;
+        atomicAdd(&local_bins[bin_for(color)], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram.bins[index], atomicLoad(&local_bins[index]));
+}
+
+fn ev_to_exposure(ev: f32) -> f32 {
+    // saturation-based sensitivity at ISO 100
+    return 1.0 / (1.2 * exp2(ev));
+}
+
+[[stage(compute), workgroup_size(256)]]
+fn average([[builtin(local_invocation_index)]] index: u32) {
+    let count = atomicLoad(&histogram.bins[index]);
+    weighted[index] = count * index;
+    atomicStore(&histogram.bins[index], 0u);
+    workgroupBarrier();
+
+    var stride = BIN_COUNT / 2u;
+    loop {
+        if (stride == 0u) {
+            break;
+        }
+        if (index < stride) {
+            weighted[index] = weighted[index] + weighted[index + stride];
+        }
+        workgroupBarrier();
+        stride = stride / 2u;
+    }
+
+    if (index == 0u) {
+        let size = textureDimensions(hdr_texture);
+        let lit_pixels = max(f32(size.x * size.y) - f32(count), 1.0);
+        let average_bin = f32(weighted[0]) / lit_pixels - 1.0;
+        let log_luminance = average_bin / 254.0 * params.log_luminance_range + params.min_log_luminance;
+        let target = exp2(log_luminance);
+
+        let previous = exposure.luminance;
+        var speed = params.speed_down;
+        if (target > previous) {
+            speed = params.speed_up;
+        }
+        let luminance = previous + (target - previous) * (1.0 - exp(-params.delta_time * speed));
+
+        // EV100 of the adapted luminance, with the reflected-light meter constant K = 12.5
+        let ev = clamp(log2(luminance * 100.0 / 12.5), params.min_ev, params.max_ev);
+        exposure.luminance = luminance;
+        exposure.exposure = ev_to_exposure(ev - params.compensation);
+    }
+}
@@ -0,0 +1,111 @@
+// Bloom over a chain of half-resolution levels: a 13-tap downsample from the thresholded scene
+// (or an emissive-only buffer) to the smallest level, then a 3x3 tent upsample back up, each
+// level added onto the one above. The composite adds the largest level to the scene, plus a
+// broad low-frequency level as a stand-in for the light emissive surfaces spill around them.
+
+struct Params {
+    threshold: f32;
+    knee: f32;
+    intensity: f32;
+    // weight of each upsampled level against the one it is added to
+    scatter: f32;
+    area_light_strength: f32;
+    _padding0: f32;
+    _padding: vec2<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var input_sampler: sampler;
+[[group(0), binding(2)]] var source_texture: texture_2d<f32>;
+[[group(0), binding(3)]] var bloom_texture: texture_2d<f32>;
+[[group(0), binding(4)]] var glow_texture: texture_2d<f32>;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// 13 taps in overlapping 2x2 boxes, which avoids the shimmering of a plain 2x2 box filter
+fn downsample(uv: vec2<f32>, karis: bool) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    let a = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(-2.0, -2.0)).rgb;
+    let b = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(0.0, -2.0)).rgb;
+    let c = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(2.0, -2.0)).rgb;
+    let d = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(-2.0, 0.0)).rgb;
+    let e = textureSample(source_texture, input_sampler, uv).rgb;
+    let f = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(2.0, 0.0)).rgb;
+    let g = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(-2.0, 2.0)).rgb;
+    let h = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(0.0, 2.0)).rgb;
+    let i = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(2.0, 2.0)).rgb;
+    let j = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(-1.0, -1.0)).rgb;
+    let k = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(1.0, -1.0)).rgb;
+    let l = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(-1.0, 1.0)).rgb;
+    let m = textureSample(source_texture, input_sampler, uv + texel * vec2<f32>(1.0, 1.0)).rgb;
+
+    let center = (j + k + l + m) * 0.25;
+    let corner_a = (a + b + d + e) * 0.25;
+    let corner_b = (b + c + e + f) * 0.25;
+    let corner_c = (d + e + g + h) * 0.25;
+    let corner_d = (e + f + h + i) * 0.25;
+    if (!karis) {
+        return center * 0.5 + (corner_a + corner_b + corner_c + corner_d) * 0.125;
+    }
+    // on the first level, weighting boxes by inverse luminance keeps single bright pixels from
+    // flickering as large blobs
+    let weights = vec4<f32>(
+        1.0 / (1.0 + luminance(corner_a)),
+        1.0 / (1.0 + luminance(corner_b)),
+        1.0 / (1.0 + luminance(corner_c)),
+        1.0 / (1.0 + luminance(corner_d))
+    ) * 0.125;
+    let center_weight = 0.5 / (1.0 + luminance(center));
+    let result = center * center_weight + corner_a * weights.x + corner_b * weights.y + corner_c * weights.z + corner_d * weights.w;
+    return result / (center_weight + dot(weights, vec4<f32>(1.0)));
+}
+
+// soft threshold: a quadratic knee around the threshold instead of a hard cut
+fn prefilter(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = params.threshold * params.knee;
+    let soft = clamp(brightness - params.threshold + knee, 0.0, 2.0 * knee);
+    let curve = soft * soft / (4.0 * knee + 0.00001);
+    let contribution = max(curve, brightness - params.threshold) / max(brightness, 0.00001);
+    return color * contribution;
+}
+
+[[stage(fragment)]]
+fn fs_prefilter(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(prefilter(downsample(in.uv, true)), 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_downsample(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(downsample(in.uv, false), 1.0);
+}
+
+fn tent(texture: texture_2d<f32>, uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(texture));
+    var sum = textureSample(texture, input_sampler, uv).rgb * 4.0;
+    sum = sum + textureSample(texture, input_sampler, uv + texel * vec2<f32>(-1.0, 0.0)).rgb * 2.0;
+    sum = sum + textureSample(texture, input_sampler, uv + texel * vec2<f32>(1.0, 0.0)).rgb * 2.0;
+    sum = sum + textureSample(texture, input_sampler, uv + texel * vec2<f32>(0.0, -1.0)).rgb * 2.0;
+    sum = sum + textureSample(texture, input_sampler, uv + texel * vec2<f32>(0.0, 1.0)).rgb * 2.0;
+    sum = sum + textureSample(texture, input_sampler, uv + texel * vec2<f32>(-1.0, -1.0)).rgb;
+    sum = sum + textureSample(texture, input_sampler, uv + texel * vec2<f32>(1.0, -1.0)).rgb;
+    sum = sum + textureSample(texture, input_sampler, uv + texel * vec2<f32>(-1.0, 1.0)).rgb;
+    sum = sum + textureSample(texture, input_sampler, uv + texel * vec2<f32>(1.0, 1.0)).rgb;
+    return sum / 16.0;
+}
+
+// blended additively onto the next larger level
+[[stage(fragment)]]
+fn fs_upsample(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(tent(source_texture, in.uv) * params.scatter, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_composite(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let scene = textureSample(source_texture, input_sampler, in.uv);
+    let bloom = tent(bloom_texture, in.uv) * params.intensity;
+    let glow = textureSample(glow_texture, input_sampler, in.uv).rgb * params.area_light_strength;
+    return vec4<f32>(scene.rgb + bloom + glow, scene.a);
+}
@@ -0,0 +1,220 @@
+// Volumetric clouds in a flat layer between two altitudes. `fs_march` ray marches the layer at a
+// reduced resolution with a per-frame jittered start, `fs_reproject` blends that into the
+// previous frames' result seen from the new camera, and `fs_apply` composites the accumulated
+// clouds over the HDR scene.
+
+struct Params {
+    inverse_view_projection: mat4x4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    camera_position: vec4<f32>;
+    // direction towards the sun
+    sun_direction: vec4<f32>;
+    sun_color: vec4<f32>;
+    ambient: vec4<f32>;
+    wind: vec4<f32>;
+    // bottom, top, max distance, coverage
+    layer: vec4<f32>;
+    // inverse shape, detail and weather sizes, detail strength
+    shape: vec4<f32>;
+    // density, anisotropy
+    lighting: vec4<f32>;
+    // steps, light steps, frame, share of the new frame in the result
+    march: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var noise_sampler: sampler;
+[[group(0), binding(2)]] var shape_noise: texture_3d<f32>;
+[[group(0), binding(3)]] var detail_noise: texture_3d<f32>;
+[[group(0), binding(4)]] var depth_texture: texture_depth_2d;
+[[group(0), binding(5)]] var input_sampler: sampler;
+[[group(0), binding(6)]] var current_texture: texture_2d<f32>;
+[[group(0), binding(7)]] var history_texture: texture_2d<f32>;
+[[group(0), binding(8)]] var color_texture: texture_2d<f32>;
+[[group(0), binding(9)]] var clouds_texture: texture_2d<f32>;
+
+let PI: f32 = 3.14159265;
+
+fn remap(value: f32, low: f32, high: f32, new_low: f32, new_high: f32) -> f32 {
+    return new_low + (value - low) * (new_high - new_low) / (high - low);
+}
+
+fn view_ray(uv: vec2<f32>) -> vec3<f32> {
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let near = params.inverse_view_projection * vec4<f32>(ndc, 0.0, 1.0);
+    let far = params.inverse_view_projection * vec4<f32>(ndc, 1.0, 1.0);
+    return normalize(far.xyz / far.w - near.xyz / near.w);
+}
+
+// distances along `ray` where it enters and leaves the cloud layer; empty if y <= x
+fn layer_span(origin: vec3<f32>, ray: vec3<f32>) -> vec2<f32> {
+    let bottom = params.layer.x;
+    let top = params.layer.y;
+    if (abs(ray.y) < 0.00001) {
+        if (origin.y >= bottom && origin.y <= top) {
+            return vec2<f32>(0.0, params.layer.z);
+        }
+        return vec2<f32>(1.0, 0.0);
+    }
+    let t0 = (bottom - origin.y) / ray.y;
+    let t1 = (top - origin.y) / ray.y;
+    return vec2<f32>(max(min(t0, t1), 0.0), min(max(t0, t1), params.layer.z));
+}
+
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+// extinction at `p`; `cheap` skips the detail erosion, for light marches
+fn cloud_density(p: vec3<f32>, cheap: bool) -> f32 {
+    let height = clamp((p.y - params.layer.x) / (params.layer.y - params.layer.x), 0.0, 1.0);
+    let moved = p + params.wind.xyz;
+
+    // the red channel of a large, flat slice of the shape noise says where clouds form
+    let weather_uv = moved.xz * params.shape.z;
+    let weather = textureSampleLevel(shape_noise, noise_sampler, vec3<f32>(weather_uv.x, 0.5, weather_uv.y), 0.0).r;
+    let coverage = clamp(params.layer.w * (0.5 + weather), 0.0, 1.0);
+    if (coverage <= 0.0) {
+        return 0.0;
+    }
+
+    // Perlin eroded by Worley octaves, rounded at the bottom of the layer and thinning at the top
+    let shape = textureSampleLevel(shape_noise, noise_sampler, moved * params.shape.x, 0.0);
+    let worley = shape.g * 0.625 + shape.b * 0.25 + shape.a * 0.125;
+    var base = remap(shape.r, worley - 1.0, 1.0, 0.0, 1.0);
+    let profile = clamp(remap(height, 0.0, 0.1, 0.0, 1.0), 0.0, 1.0) * clamp(remap(height, 0.3, 1.0, 1.0, 0.0), 0.0, 1.0);
+    base = clamp(remap(base * profile, 1.0 - coverage, 1.0, 0.0, 1.0), 0.0, 1.0) * coverage;
+    if (base <= 0.0 || cheap) {
+        return base * params.lighting.x;
+    }
+
+    // wispy edges at the bottom, billows further up
+    let detail = textureSampleLevel(detail_noise, noise_sampler, moved * params.shape.y, 0.0);
+    let detail_fbm = detail.r * 0.625 + detail.g * 0.25 + detail.b * 0.125;
+    let erosion = mix(detail_fbm, 1.0 - detail_fbm, clamp(height * 10.0, 0.0, 1.0)) * params.shape.w;
+    return clamp(remap(base, erosion, 1.0, 0.0, 1.0), 0.0, 1.0) * params.lighting.x;
+}
+
+// how much sunlight reaches `p` through the cloud above it
+fn sun_transmittance(p: vec3<f32>) -> f32 {
+    let steps = i32(params.march.y);
+    let sun = params.sun_direction.xyz;
+    var step = (params.layer.y - params.layer.x) / max(params.march.y, 1.0) * 0.5;
+    var position = p;
+    var optical_depth = 0.0;
+    for (var i = 0; i < steps; i = i + 1) {
+        position = position + sun * step;
+        optical_depth = optical_depth + cloud_density(position, true) * step;
+        // longer steps further out, where detail matters less
+        step = step * 1.3;
+    }
+    // a second, weaker falloff stands in for light scattered more than once
+    return max(exp(-optical_depth), exp(-optical_depth * 0.25) * 0.7);
+}
+
+[[stage(fragment)]]
+fn fs_march(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let steps = i32(params.march.x);
+    if (steps == 0) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    let origin = params.camera_position.xyz;
+    let ray = view_ray(in.uv);
+    var span = layer_span(origin, ray);
+
+    // geometry in front of or inside the layer hides what is behind it
+    let size = textureDimensions(depth_texture);
+    let texel = clamp(vec2<i32>(in.uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    let depth = textureLoad(depth_texture, texel, 0);
+    if (depth < 1.0) {
+        let ndc = vec2<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+        let world = params.inverse_view_projection * vec4<f32>(ndc, depth, 1.0);
+        span.y = min(span.y, length(world.xyz / world.w - origin));
+    }
+    if (span.y <= span.x) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    // interleaved gradient noise, shifted every frame so reprojection averages the banding away
+    let pixel = in.position.xy + params.march.z * 5.588238;
+    let jitter = fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+
+    let step = (span.y - span.x) / f32(steps);
+    let cos_theta = dot(ray, params.sun_direction.xyz);
+    // forward scattering for the silver lining, a little back scattering for the shadowed side
+    let g = params.lighting.y;
+    let phase = mix(henyey_greenstein(cos_theta, g), henyey_greenstein(cos_theta, -0.5 * g), 0.25);
+
+    var scattered = vec3<f32>(0.0);
+    var transmittance = 1.0;
+    var t = span.x + step * jitter;
+    for (var i = 0; i < steps; i = i + 1) {
+        let position = origin + ray * t;
+        let density = cloud_density(position, false);
+        if (density > 0.0) {
+            let height = clamp((position.y - params.layer.x) / (params.layer.y - params.layer.x), 0.0, 1.0);
+            let light = params.sun_color.rgb * phase * sun_transmittance(position)
+                + params.ambient.rgb * mix(0.5, 1.0, height);
+            let sample_transmittance = exp(-density * step);
+            // same energy-conserving integration as the volumetric fog
+            scattered = scattered + transmittance * light * (1.0 - sample_transmittance);
+            transmittance = transmittance * sample_transmittance;
+            if (transmittance < 0.01) {
+                break;
+            }
+        }
+        t = t + step;
+    }
+
+    // fade out towards the horizon instead of cutting off at the max distance
+    let fade = 1.0 - smoothStep(0.6 * params.layer.z, params.layer.z, span.x);
+    return vec4<f32>(scattered * fade, mix(1.0, transmittance, fade));
+}
+
+[[stage(fragment)]]
+fn fs_reproject(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let current = textureSampleLevel(current_texture, input_sampler, in.uv, 0.0);
+    if (params.march.w >= 1.0) {
+        return current;
+    }
+
+    // where this pixel's clouds were on screen last frame, taking the point a little way into
+    // the layer as their depth
+    let origin = params.camera_position.xyz;
+    let ray = view_ray(in.uv);
+    let span = layer_span(origin, ray);
+    if (span.y <= span.x) {
+        return current;
+    }
+    let point = origin + ray * mix(span.x, span.y, 0.25);
+    let previous = params.previous_view_projection * vec4<f32>(point, 1.0);
+    if (previous.w <= 0.0) {
+        return current;
+    }
+    let previous_uv = vec2<f32>(previous.x / previous.w * 0.5 + 0.5, 0.5 - previous.y / previous.w * 0.5);
+    if (any(previous_uv < vec2<f32>(0.0)) || any(previous_uv > vec2<f32>(1.0))) {
+        return current;
+    }
+
+    // clamp the history to this frame's neighbourhood, so disoccluded clouds don't ghost
+    let texel = 1.0 / vec2<f32>(textureDimensions(current_texture));
+    var low = current;
+    var high = current;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let neighbour = textureSampleLevel(current_texture, input_sampler, in.uv + vec2<f32>(f32(x), f32(y)) * texel, 0.0);
+            low = min(low, neighbour);
+            high = max(high, neighbour);
+        }
+    }
+    let history = clamp(textureSampleLevel(history_texture, input_sampler, previous_uv, 0.0), low, high);
+    return mix(history, current, params.march.w);
+}
+
+[[stage(fragment)]]
+fn fs_apply(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let clouds = textureSample(clouds_texture, input_sampler, in.uv);
+    let color = textureSample(color_texture, input_sampler, in.uv);
+    return vec4<f32>(color.rgb * clouds.a + clouds.rgb, color.a);
+}
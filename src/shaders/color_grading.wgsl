@@ -0,0 +1,37 @@
+// Lift/gamma/gain followed by a 3D LUT lookup. Expects display-referred input in [0, 1], i.e.
+// after tonemapping.
+
+struct Params {
+    lift: vec4<f32>;
+    gamma: vec4<f32>;
+    gain: vec4<f32>;
+    domain_min: vec4<f32>;
+    domain_max: vec4<f32>;
+    lut_size: f32;
+    lut_strength: f32;
+    _padding: vec2<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var input_sampler: sampler;
+[[group(0), binding(2)]] var color_texture: texture_2d<f32>;
+[[group(0), binding(3)]] var lut_texture: texture_3d<f32>;
+
+fn lift_gamma_gain(color: vec3<f32>) -> vec3<f32> {
+    let lifted = params.gain.rgb * (color + params.lift.rgb * (vec3<f32>(1.0) - color));
+    return pow(max(lifted, vec3<f32>(0.0)), vec3<f32>(1.0) / max(params.gamma.rgb, vec3<f32>(0.01)));
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let input = textureSample(color_texture, input_sampler, in.uv);
+    let graded = lift_gamma_gain(input.rgb);
+
+    let normalized = clamp((graded - params.domain_min.rgb) / (params.domain_max.rgb - params.domain_min.rgb), vec3<f32>(0.0), vec3<f32>(1.0));
+    // sample texel centers so the ends of the domain map to the first and last entries
+    let scale = (params.lut_size - 1.0) / params.lut_size;
+    let offset = 0.5 / params.lut_size;
+    let lut = textureSample(lut_texture, input_sampler, normalized * scale + offset).rgb;
+
+    return vec4<f32>(mix(graded, lut, params.lut_strength), input.a);
+}
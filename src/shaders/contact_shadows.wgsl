@@ -0,0 +1,74 @@
+// Screen-space contact shadows: marches a short ray from each pixel towards the light through the
+// depth buffer and darkens the pixel if the ray passes just behind visible geometry. Catches the
+// small-scale occlusion where objects meet the ground that a shadow map is too coarse for.
+
+struct Params {
+    projection: mat4x4<f32>;
+    inverse_projection: mat4x4<f32>;
+    // view space, pointing towards the light
+    light_direction: vec4<f32>;
+    length: f32;
+    thickness: f32;
+    intensity: f32;
+    steps: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var depth_texture: texture_depth_2d;
+[[group(0), binding(2)]] var mask_texture: texture_2d<f32>;
+
+fn view_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = params.inverse_projection * ndc;
+    return position.xyz / position.w;
+}
+
+// interleaved gradient noise, so neighbouring pixels start their march at different offsets
+fn jitter(position: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(position, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(depth_texture);
+    let depth = textureLoad(depth_texture, vec2<i32>(in.position.xy), 0);
+    let steps = i32(params.steps);
+    if (depth >= 1.0 || steps == 0) {
+        return vec4<f32>(1.0);
+    }
+
+    let origin = view_position(in.uv, depth);
+    // thin things need a thin slab, far things a thicker one as depth precision drops
+    let thickness = params.thickness * max(1.0, -origin.z * 0.05);
+    let step = params.light_direction.xyz * (params.length / f32(steps));
+    var position = origin + step * jitter(in.position.xy);
+    var occlusion = 0.0;
+    for (var i = 0; i < steps; i = i + 1) {
+        position = position + step;
+        let clip = params.projection * vec4<f32>(position, 1.0);
+        if (clip.w <= 0.0) {
+            break;
+        }
+        let ndc = clip.xy / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        if (any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0))) {
+            break;
+        }
+        let scene_depth = textureLoad(depth_texture, vec2<i32>(uv * vec2<f32>(size)), 0);
+        let scene = view_position(uv, scene_depth);
+        // view space looks down -z, so geometry in front of the ray has a larger z
+        let behind = scene.z - position.z;
+        if (behind > 0.0 && behind < thickness) {
+            // fade out towards the end of the ray to hide where it stops
+            occlusion = 1.0 - f32(i) / f32(steps);
+            break;
+        }
+    }
+    return vec4<f32>(1.0 - occlusion * params.intensity);
+}
+
+// the mask as a factor for the scene's color, which the pipeline multiplies by it
+[[stage(fragment)]]
+fn fs_apply(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(textureLoad(mask_texture, vec2<i32>(in.position.xy), 0).rrr, 1.0);
+}
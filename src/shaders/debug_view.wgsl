@@ -0,0 +1,62 @@
+// Debug variants of the mesh shaders write one value per pixel instead of a color: red holds
+// the value, green marks the pixel as covered. `DebugViews` histograms and colors it. Needs
+// `LIGHTS_SHADER` and `SHADOW_SHADER` before it and one of the `DEBUG_` names defined.
+
+// textures are assumed this size where the shader has none, for the mip view
+let DEBUG_REFERENCE_TEXTURE_SIZE: f32 = 1024.0;
+
+fn debug_light_count(position: vec3<f32>) -> f32 {
+    var count = 0.0;
+    for (var i = 0u; i < light_count(); i = i + 1u) {
+        let radiance = light_sample(i, position).radiance;
+        if (max(radiance.r, max(radiance.g, radiance.b)) > 0.0) {
+            count = count + 1.0;
+        }
+    }
+    return count;
+}
+
+// 1 for the first cascade; 0 past the last one or without a shadowed light
+fn debug_shadow_cascade(position: vec3<f32>, camera_position: vec3<f32>) -> f32 {
+    if (shadowed_light() == 0xffffffffu) {
+        return 0.0;
+    }
+    let count = u32(directional_shadows.params.x);
+    let depth = dot(position - camera_position, directional_shadows.camera_forward.xyz);
+    var cascade = 0u;
+    loop {
+        if (cascade >= count || depth <= directional_shadows.splits[cascade]) {
+            break;
+        }
+        cascade = cascade + 1u;
+    }
+    if (cascade >= count) {
+        return 0.0;
+    }
+    return f32(cascade + 1u);
+}
+
+// the mip a trilinear sampler picks for a texture of `size` texels
+fn debug_mip_level(uv: vec2<f32>, size: vec2<f32>) -> f32 {
+    let texels = uv * size;
+    let dx = dpdx(texels);
+    let dy = dpdy(texels);
+    return max(0.5 * log2(max(dot(dx, dx), dot(dy, dy))), 0.0);
+}
+
+fn debug_output(position: vec3<f32>, camera_position: vec3<f32>, uv: vec2<f32>, texture_size: vec2<f32>) -> vec4<f32> {
+    var value = 0.0;
+#ifdef DEBUG_OVERDRAW
+    value = 1.0;
+#endif
+#ifdef DEBUG_LIGHT_COUNT
+    value = debug_light_count(position);
+#endif
+#ifdef DEBUG_SHADOW_CASCADES
+    value = debug_shadow_cascade(position, camera_position);
+#endif
+#ifdef DEBUG_MIP_LEVELS
+    value = debug_mip_level(uv, texture_size);
+#endif
+    return vec4<f32>(value, 1.0, 0.0, 1.0);
+}
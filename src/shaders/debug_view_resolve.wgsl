@@ -0,0 +1,73 @@
+// Histograms the debug view's values in a compute pass, then colors them over the frame: a heat
+// ramp from blue to white, or a distinct color per shadow cascade.
+
+struct DebugParams {
+    // `DebugView` index
+    view: u32;
+    bins: u32;
+    // value the ramp reaches white at
+    max_value: f32;
+    // opacity over the frame
+    opacity: f32;
+};
+
+struct Histogram {
+    bins: array<atomic<u32>, 16>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: DebugParams;
+[[group(0), binding(1)]] var values: texture_2d<f32>;
+[[group(0), binding(2)]] var<storage, read_write> histogram: Histogram;
+
+[[stage(compute), workgroup_size(16, 16)]]
+fn build_histogram([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(values);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y) {
+        return;
+    }
+    let value = textureLoad(values, vec2<i32>(id.xy), 0);
+    if (value.g > 0.0) {
+        atomicAdd(&histogram.bins[min(u32(value.r), params.bins - 1u)], 1u);
+    }
+}
+
+fn heat(t: f32) -> vec3<f32> {
+    // past the top of the ramp goes to white
+    if (t > 1.0) {
+        return vec3<f32>(1.0);
+    }
+    var stops = array<vec3<f32>, 5>(
+        vec3<f32>(0.0, 0.1, 0.6),
+        vec3<f32>(0.0, 0.7, 0.7),
+        vec3<f32>(0.2, 0.9, 0.1),
+        vec3<f32>(1.0, 0.8, 0.0),
+        vec3<f32>(1.0, 0.1, 0.0),
+    );
+    let x = max(t, 0.0) * 4.0;
+    let index = min(u32(x), 3u);
+    return mix(stops[index], stops[index + 1u], x - f32(index));
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(values);
+    let value = textureLoad(values, vec2<i32>(in.uv * vec2<f32>(size)), 0);
+    if (value.g <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    var color: vec3<f32>;
+    // shadow cascades
+    if (params.view == 3u) {
+        var cascades = array<vec3<f32>, 5>(
+            vec3<f32>(0.5, 0.5, 0.5),
+            vec3<f32>(1.0, 0.2, 0.2),
+            vec3<f32>(0.2, 1.0, 0.2),
+            vec3<f32>(0.2, 0.4, 1.0),
+            vec3<f32>(1.0, 1.0, 0.2),
+        );
+        color = cascades[min(u32(value.r), 4u)];
+    } else {
+        color = heat(value.r / params.max_value);
+    }
+    return vec4<f32>(color, params.opacity);
+}
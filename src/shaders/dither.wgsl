@@ -0,0 +1,68 @@
+// Output pass for display-referred color: optional debanding of smooth gradients, then blue
+// noise dithering by about one step of the output format so quantization shows as fine grain
+// instead of bands.
+
+struct Params {
+    // size of one quantization step of the output, 0 to skip dithering
+    step: f32;
+    strength: f32;
+    deband_threshold: f32;
+    deband_radius: f32;
+    // per-frame offset into the noise tile, in pixels
+    noise_offset: vec2<f32>;
+    _padding: vec2<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var input_sampler: sampler;
+[[group(0), binding(2)]] var color_texture: texture_2d<f32>;
+[[group(0), binding(3)]] var noise_texture: texture_2d<f32>;
+
+fn blue_noise(coords: vec2<i32>, offset: vec2<i32>) -> f32 {
+    let size = textureDimensions(noise_texture);
+    let wrapped = (coords + offset + vec2<i32>(params.noise_offset)) % size;
+    return textureLoad(noise_texture, wrapped, 0).r;
+}
+
+// uniform [0, 1] to triangular [-1, 1], which keeps the noise level independent of the signal
+fn triangular(value: f32) -> f32 {
+    let centered = value * 2.0 - 1.0;
+    return sign(centered) * (1.0 - sqrt(max(1.0 - abs(centered), 0.0)));
+}
+
+// replaces the pixel by the average of four neighbours at a random rotation when they are all
+// close to it, which smooths banded gradients but leaves edges and texture alone
+fn deband(uv: vec2<f32>, color: vec3<f32>, coords: vec2<i32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(color_texture));
+    let angle = blue_noise(coords, vec2<i32>(17, 43)) * 6.2831853;
+    let radius = params.deband_radius * (0.5 + 0.5 * blue_noise(coords, vec2<i32>(31, 7)));
+    let offset = vec2<f32>(cos(angle), sin(angle)) * radius * texel;
+    let perpendicular = vec2<f32>(-offset.y, offset.x);
+    let a = textureSample(color_texture, input_sampler, uv + offset).rgb;
+    let b = textureSample(color_texture, input_sampler, uv - offset).rgb;
+    let c = textureSample(color_texture, input_sampler, uv + perpendicular).rgb;
+    let d = textureSample(color_texture, input_sampler, uv - perpendicular).rgb;
+    let average = (a + b + c + d) * 0.25;
+    let difference = max(max(abs(a - color), abs(b - color)), max(abs(c - color), abs(d - color)));
+    if (max(difference.r, max(difference.g, difference.b)) < params.deband_threshold) {
+        return average;
+    }
+    return color;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let coords = vec2<i32>(in.position.xy);
+    let input = textureSample(color_texture, input_sampler, in.uv);
+    var color = input.rgb;
+    if (params.deband_radius > 0.0) {
+        color = deband(in.uv, color, coords);
+    }
+    // decorrelated channels, so the grain doesn't tint
+    let noise = vec3<f32>(
+        triangular(blue_noise(coords, vec2<i32>(0, 0))),
+        triangular(blue_noise(coords, vec2<i32>(21, 11))),
+        triangular(blue_noise(coords, vec2<i32>(37, 29)))
+    );
+    return vec4<f32>(color + noise * params.step * params.strength, input.a);
+}
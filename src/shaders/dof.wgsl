@@ -0,0 +1,71 @@
+// Depth of field: circle of confusion from depth, a gather blur that keeps the near and far
+// fields apart, and a composite back over the sharp image.
+
+struct Params {
+    texel_size: vec2<f32>;
+    near: f32;
+    far: f32;
+    focus_distance: f32;
+    focus_range: f32;
+    max_radius: f32;
+    _padding: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var input_sampler: sampler;
+[[group(0), binding(2)]] var color_texture: texture_2d<f32>;
+[[group(0), binding(3)]] var depth_texture: texture_depth_2d;
+[[group(0), binding(4)]] var coc_texture: texture_2d<f32>;
+
+fn linear_depth(depth: f32) -> f32 {
+    return params.near * params.far / (params.far - depth * (params.far - params.near));
+}
+
+// signed: negative in front of the focal plane, positive behind it, in [-1, 1]
+[[stage(fragment)]]
+fn fs_coc(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(depth_texture);
+    let coords = vec2<i32>(in.uv * vec2<f32>(size));
+    let distance = linear_depth(textureLoad(depth_texture, coords, 0));
+    let coc = clamp((distance - params.focus_distance) / params.focus_range, -1.0, 1.0);
+    let color = textureSample(color_texture, input_sampler, in.uv).rgb;
+    return vec4<f32>(color, coc);
+}
+
+let SAMPLE_COUNT: i32 = 32;
+let GOLDEN_ANGLE: f32 = 2.39996323;
+
+[[stage(fragment)]]
+fn fs_blur(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let center = textureSample(coc_texture, input_sampler, in.uv);
+    let center_coc = center.a;
+    // the near field is gathered from neighbours too, so a blurry foreground edge spreads over
+    // the sharp background behind it
+    var sum = vec4<f32>(center.rgb, 1.0);
+    for (var i: i32 = 1; i < SAMPLE_COUNT; i = i + 1) {
+        let t = f32(i) / f32(SAMPLE_COUNT);
+        let radius = sqrt(t) * params.max_radius;
+        let angle = f32(i) * GOLDEN_ANGLE;
+        let offset = vec2<f32>(cos(angle), sin(angle)) * radius * params.texel_size;
+        let s = textureSample(coc_texture, input_sampler, in.uv + offset);
+        let sample_radius = abs(s.a) * params.max_radius;
+        // a sample contributes when its own blur reaches this pixel; far samples may not bleed
+        // onto geometry in front of them
+        var weight = smoothStep(radius - 1.0, radius + 1.0, sample_radius);
+        if (s.a > center_coc && center_coc < 0.0) {
+            weight = 0.0;
+        }
+        sum = sum + vec4<f32>(s.rgb * weight, weight);
+    }
+    return vec4<f32>(sum.rgb / sum.a, center_coc);
+}
+
+[[group(0), binding(5)]] var blur_texture: texture_2d<f32>;
+
+[[stage(fragment)]]
+fn fs_composite(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let sharp = textureSample(color_texture, input_sampler, in.uv);
+    let blurred = textureSample(blur_texture, input_sampler, in.uv);
+    let amount = smoothStep(0.0, 1.0, abs(blurred.a) * params.max_radius);
+    return vec4<f32>(mix(sharp.rgb, blurred.rgb, amount), sharp.a);
+}
@@ -0,0 +1,23 @@
+// Image-based lighting from the environment map: a cube of irradiance for diffuse light and one
+// prefiltered by roughness down its mips for specular. Binds with the view at group 0.
+
+[[group(0), binding(2)]] var environment_irradiance: texture_cube<f32>;
+[[group(0), binding(3)]] var environment_specular: texture_cube<f32>;
+[[group(0), binding(4)]] var environment_sampler: sampler;
+
+// light a white Lambert surface facing `normal` reflects
+fn environment_diffuse(normal: vec3<f32>) -> vec3<f32> {
+    return textureSampleLevel(environment_irradiance, environment_sampler, normal, 0.0).rgb;
+}
+
+fn environment_reflection(direction: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let level = roughness * f32(textureNumLevels(environment_specular) - 1);
+    return textureSampleLevel(environment_specular, environment_sampler, direction, level).rgb;
+}
+
+// Karis' analytic fit of the split-sum BRDF integral: the scale and bias to f0
+fn environment_brdf(n_dot_v: f32, roughness: f32) -> vec2<f32> {
+    let r = roughness * vec4<f32>(-1.0, -0.0275, -0.572, 0.022) + vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    return vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+}
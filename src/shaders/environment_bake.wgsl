@@ -0,0 +1,128 @@
+// Bakes an environment map one cube face at a time: the equirectangular image into the skybox
+// cube, then the skybox into irradiance and a specular cube prefiltered by roughness.
+
+struct BakeParams {
+    face: u32;
+    roughness: f32;
+    // mip the irradiance is gathered from, and the skybox's face size
+    source_level: f32;
+    source_size: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: BakeParams;
+[[group(0), binding(1)]] var equirect: texture_2d<f32>;
+[[group(0), binding(2)]] var source: texture_cube<f32>;
+[[group(0), binding(3)]] var source_sampler: sampler;
+
+let PI: f32 = 3.14159265;
+
+// the direction a cube face texel at `uv` looks along, in the order and orientation cube
+// textures are addressed
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let p = uv * 2.0 - 1.0;
+    var direction: vec3<f32>;
+    if (face == 0u) {
+        direction = vec3<f32>(1.0, -p.y, -p.x);
+    } else if (face == 1u) {
+        direction = vec3<f32>(-1.0, -p.y, p.x);
+    } else if (face == 2u) {
+        direction = vec3<f32>(p.x, 1.0, p.y);
+    } else if (face == 3u) {
+        direction = vec3<f32>(p.x, -1.0, -p.y);
+    } else if (face == 4u) {
+        direction = vec3<f32>(p.x, -p.y, 1.0);
+    } else {
+        direction = vec3<f32>(-p.x, -p.y, -1.0);
+    }
+    return normalize(direction);
+}
+
+// two unit vectors perpendicular to `n` and each other
+fn tangent_frame(n: vec3<f32>) -> mat3x3<f32> {
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(n.y) > 0.999) {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(cross(up, n));
+    return mat3x3<f32>(right, cross(n, right), n);
+}
+
+// 32-bit float textures aren't filterable everywhere, so the equirect is filtered by hand
+fn equirect_texel(x: i32, y: i32, size: vec2<i32>) -> vec3<f32> {
+    let wrapped = vec2<i32>((x % size.x + size.x) % size.x, clamp(y, 0, size.y - 1));
+    return textureLoad(equirect, wrapped, 0).rgb;
+}
+
+[[stage(fragment)]]
+fn fs_equirect(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let direction = face_direction(params.face, in.uv);
+    let size = textureDimensions(equirect);
+    let uv = vec2<f32>(atan2(direction.z, direction.x) / (2.0 * PI) + 0.5, acos(clamp(direction.y, -1.0, 1.0)) / PI);
+    let texel = uv * vec2<f32>(size) - 0.5;
+    let base = floor(texel);
+    let f = texel - base;
+    let x = i32(base.x);
+    let y = i32(base.y);
+    let top = mix(equirect_texel(x, y, size), equirect_texel(x + 1, y, size), f.x);
+    let bottom = mix(equirect_texel(x, y + 1, size), equirect_texel(x + 1, y + 1, size), f.x);
+    return vec4<f32>(mix(top, bottom, f.y), 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_irradiance(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let frame = tangent_frame(face_direction(params.face, in.uv));
+    var sum = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi = phi + 0.05) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta = theta + 0.05) {
+            let local = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let radiance = textureSampleLevel(source, source_sampler, frame * local, params.source_level).rgb;
+            sum = sum + radiance * cos(theta) * sin(theta);
+            count = count + 1.0;
+        }
+    }
+    // divided by pi, so a uniform environment comes out as its own radiance
+    return vec4<f32>(PI * sum / count, 1.0);
+}
+
+let SPECULAR_SAMPLES: u32 = 128u;
+
+fn radical_inverse(index: u32) -> f32 {
+    var bits = (index << 16u) | (index >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+// GGX importance sampling with the view along the normal, after Karis
+[[stage(fragment)]]
+fn fs_prefilter(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let n = face_direction(params.face, in.uv);
+    let frame = tangent_frame(n);
+    let a = params.roughness * params.roughness;
+    let a2 = a * a;
+    // solid angle of a skybox texel, to pick the mip that matches each sample's footprint
+    let texel_solid_angle = 4.0 * PI / (6.0 * params.source_size * params.source_size);
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < SPECULAR_SAMPLES; i = i + 1u) {
+        let xi = vec2<f32>(f32(i) / f32(SPECULAR_SAMPLES), radical_inverse(i));
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a2 - 1.0) * xi.y));
+        let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+        let h = frame * vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if (n_dot_l > 0.0) {
+            let d = cos_theta * cos_theta * (a2 - 1.0) + 1.0;
+            let pdf = a2 / (PI * d * d) / 4.0 + 0.0001;
+            let sample_solid_angle = 1.0 / (f32(SPECULAR_SAMPLES) * pdf);
+            let level = select(max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0), 0.0, a2 == 0.0);
+            sum = sum + textureSampleLevel(source, source_sampler, l, level).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+    return vec4<f32>(sum / max(weight, 0.0001), 1.0);
+}
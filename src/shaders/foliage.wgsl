@@ -0,0 +1,80 @@
+// Instanced foliage. Instances thin out with distance, each disappearing at its own threshold by
+// shrinking into the ground, and sway in the wind the more the higher up a vertex is.
+
+struct View {
+    view_projection: mat4x4<f32>;
+    camera_position: vec4<f32>;
+    // direction the light travels in
+    light_direction: vec4<f32>;
+    light_color: vec4<f32>;
+    ambient: vec4<f32>;
+    // wind direction times strength, time, sway frequency
+    wind: vec4<f32>;
+    // start and end of the distance fade, how far up a mesh is fully swaying
+    fade: vec4<f32>;
+    base_color: vec4<f32>;
+    tip_color: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> view: View;
+
+struct Instance {
+    // position on the ground, and scale
+    [[location(8)]] offset_scale: vec4<f32>;
+    // rotation around Y, sway phase, fade threshold, color variation
+    [[location(9)]] params: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] normal: vec3<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(vertex: MeshVertex, instance: Instance) -> VertexOutput {
+    var out: VertexOutput;
+    let origin = instance.offset_scale.xyz;
+    let distance = length(origin.xz - view.camera_position.xz);
+    let fade = 1.0 - smoothStep(view.fade.x, view.fade.y, distance);
+    // shrink out over the last tenth of the fade instead of popping
+    let scale = instance.offset_scale.w * clamp((fade - instance.params.z) * 10.0, 0.0, 1.0);
+    if (scale <= 0.0) {
+        // outside the clip volume, so the whole instance is dropped
+        out.position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        return out;
+    }
+
+    let yaw = instance.params.x;
+    let rotation = mat3x3<f32>(
+        vec3<f32>(cos(yaw), 0.0, -sin(yaw)),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(sin(yaw), 0.0, cos(yaw)),
+    );
+    var position = rotation * vertex.position * scale;
+    let height = clamp(vertex.position.y / view.fade.z, 0.0, 1.0);
+    let wave = sin(view.wind.z * view.wind.w + instance.params.y + dot(origin.xz, vec2<f32>(0.07, 0.05)));
+    // bend with the wind, waving around a lean in its direction
+    let sway = view.wind.xy * (0.6 + 0.4 * wave) * height * height * scale;
+    position = position + vec3<f32>(sway.x, -0.25 * dot(sway, sway), sway.y);
+
+    out.position = view.view_projection * vec4<f32>(origin + position, 1.0);
+    out.normal = rotation * vertex.normal;
+    let color = mix(view.base_color, view.tip_color, height) * (0.85 + 0.3 * instance.params.w);
+    out.color = vertex_color(vertex) * color;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput, [[builtin(front_facing)]] front: bool) -> [[location(0)]] vec4<f32> {
+    // blades are seen from both sides
+    var normal = normalize(in.normal);
+    if (!front) {
+        normal = -normal;
+    }
+    // some light comes through the leaves from behind
+    let facing = dot(normal, -view.light_direction.xyz);
+    let diffuse = max(facing, 0.0) + max(-facing, 0.0) * 0.35;
+    let light = view.light_color.rgb * diffuse + view.ambient.rgb;
+    return vec4<f32>(in.color.rgb * light, in.color.a);
+}
@@ -0,0 +1,122 @@
+// FXAA on the display-referred image: finds edges by luma contrast, walks along each edge to
+// its ends and blends across it by how close the pixel is to the nearer end, plus a subpixel
+// blend for details smaller than a pixel.
+
+struct Params {
+    // contrast, relative to the brightest neighbour, below which a pixel isn't an edge
+    edge_threshold: f32;
+    // absolute contrast below which nothing is smoothed, so dark areas keep their noise
+    edge_threshold_min: f32;
+    subpixel: f32;
+    // 1 when the input is sampled as linear color, which luma is then encoded for
+    linear_input: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var input_sampler: sampler;
+[[group(0), binding(2)]] var color_texture: texture_2d<f32>;
+
+fn luma(color: vec3<f32>) -> f32 {
+    let value = dot(color, vec3<f32>(0.299, 0.587, 0.114));
+    // close enough to sRGB encoding for comparing contrast
+    return select(value, sqrt(value), params.linear_input > 0.5);
+}
+
+fn sample_luma(uv: vec2<f32>) -> f32 {
+    return luma(textureSampleLevel(color_texture, input_sampler, uv, 0.0).rgb);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(color_texture));
+    let uv = in.uv;
+    let center = textureSampleLevel(color_texture, input_sampler, uv, 0.0);
+    let luma_m = luma(center.rgb);
+    let luma_n = sample_luma(uv + vec2<f32>(0.0, -texel.y));
+    let luma_s = sample_luma(uv + vec2<f32>(0.0, texel.y));
+    let luma_w = sample_luma(uv + vec2<f32>(-texel.x, 0.0));
+    let luma_e = sample_luma(uv + vec2<f32>(texel.x, 0.0));
+    let luma_min = min(luma_m, min(min(luma_n, luma_s), min(luma_w, luma_e)));
+    let luma_max = max(luma_m, max(max(luma_n, luma_s), max(luma_w, luma_e)));
+    let range = luma_max - luma_min;
+    if (range < max(params.edge_threshold_min, luma_max * params.edge_threshold)) {
+        return center;
+    }
+
+    let luma_nw = sample_luma(uv + vec2<f32>(-texel.x, -texel.y));
+    let luma_ne = sample_luma(uv + vec2<f32>(texel.x, -texel.y));
+    let luma_sw = sample_luma(uv + vec2<f32>(-texel.x, texel.y));
+    let luma_se = sample_luma(uv + vec2<f32>(texel.x, texel.y));
+    let edge_horizontal = abs(luma_nw + luma_sw - 2.0 * luma_w)
+        + 2.0 * abs(luma_n + luma_s - 2.0 * luma_m)
+        + abs(luma_ne + luma_se - 2.0 * luma_e);
+    let edge_vertical = abs(luma_nw + luma_ne - 2.0 * luma_n)
+        + 2.0 * abs(luma_w + luma_e - 2.0 * luma_m)
+        + abs(luma_sw + luma_se - 2.0 * luma_s);
+    // a horizontal edge is crossed vertically and followed horizontally
+    let horizontal = edge_horizontal >= edge_vertical;
+
+    let luma_before = select(luma_w, luma_n, horizontal);
+    let luma_after = select(luma_e, luma_s, horizontal);
+    let gradient_before = abs(luma_before - luma_m);
+    let gradient_after = abs(luma_after - luma_m);
+    let gradient_scaled = 0.25 * max(gradient_before, gradient_after);
+    var step_length = select(texel.x, texel.y, horizontal);
+    var luma_local_average = 0.5 * (luma_after + luma_m);
+    if (gradient_before >= gradient_after) {
+        step_length = -step_length;
+        luma_local_average = 0.5 * (luma_before + luma_m);
+    }
+
+    // search from halfway between the pixel and its steepest neighbour, in both directions
+    var edge_uv = uv;
+    if (horizontal) {
+        edge_uv.y = edge_uv.y + 0.5 * step_length;
+    } else {
+        edge_uv.x = edge_uv.x + 0.5 * step_length;
+    }
+    let offset = select(vec2<f32>(0.0, texel.y), vec2<f32>(texel.x, 0.0), horizontal);
+    var uv1 = edge_uv - offset;
+    var uv2 = edge_uv + offset;
+    var luma_end1 = sample_luma(uv1) - luma_local_average;
+    var luma_end2 = sample_luma(uv2) - luma_local_average;
+    var reached1 = abs(luma_end1) >= gradient_scaled;
+    var reached2 = abs(luma_end2) >= gradient_scaled;
+    var steps = array<f32, 10>(1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 4.0, 8.0);
+    for (var i = 0; i < 10; i = i + 1) {
+        if (reached1 && reached2) {
+            break;
+        }
+        if (!reached1) {
+            uv1 = uv1 - offset * steps[i];
+            luma_end1 = sample_luma(uv1) - luma_local_average;
+            reached1 = abs(luma_end1) >= gradient_scaled;
+        }
+        if (!reached2) {
+            uv2 = uv2 + offset * steps[i];
+            luma_end2 = sample_luma(uv2) - luma_local_average;
+            reached2 = abs(luma_end2) >= gradient_scaled;
+        }
+    }
+
+    let distance1 = select(uv.y - uv1.y, uv.x - uv1.x, horizontal);
+    let distance2 = select(uv2.y - uv.y, uv2.x - uv.x, horizontal);
+    let nearer_is_1 = distance1 < distance2;
+    let edge_offset = 0.5 - min(distance1, distance2) / (distance1 + distance2);
+    // only blend when the nearer end leads away from this pixel's side of the edge
+    let luma_end = select(luma_end2, luma_end1, nearer_is_1);
+    var final_offset = select(0.0, edge_offset, (luma_end < 0.0) != (luma_m < luma_local_average));
+
+    let luma_average = (2.0 * (luma_n + luma_s + luma_w + luma_e) + luma_nw + luma_ne + luma_sw + luma_se) / 12.0;
+    let subpixel = clamp(abs(luma_average - luma_m) / range, 0.0, 1.0);
+    let subpixel_smooth = (3.0 - 2.0 * subpixel) * subpixel * subpixel;
+    final_offset = max(final_offset, subpixel_smooth * subpixel_smooth * params.subpixel);
+
+    var final_uv = uv;
+    if (horizontal) {
+        final_uv.y = final_uv.y + final_offset * step_length;
+    } else {
+        final_uv.x = final_uv.x + final_offset * step_length;
+    }
+    return vec4<f32>(textureSampleLevel(color_texture, input_sampler, final_uv, 0.0).rgb, center.a);
+}
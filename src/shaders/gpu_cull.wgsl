@@ -0,0 +1,52 @@
+// Cluster culling: one invocation per cluster tests its bounding sphere against the frustum and
+// its normal cone against the camera, and appends survivors to the visible list. Every visible
+// cluster adds a full meshlet's worth of vertices to the indirect draw.
+
+struct VisibleClusters {
+    data: array<u32>;
+};
+
+struct DrawIndirect {
+    vertex_count: atomic<u32>;
+    instance_count: u32;
+    first_vertex: u32;
+    first_instance: u32;
+};
+
+[[group(1), binding(0)]] var<storage, read_write> visible: VisibleClusters;
+[[group(1), binding(1)]] var<storage, read_write> draw: DrawIndirect;
+
+[[stage(compute), workgroup_size(64)]]
+fn cull([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= params.counts.x) {
+        return;
+    }
+
+    let cluster = clusters.data[index];
+    let instance = instances.data[cluster.x];
+    let meshlet = meshlets.data[cluster.y];
+    let center = (instance.model * vec4<f32>(meshlet.center, 1.0)).xyz;
+    let scale = max(length(instance.model[0].xyz), max(length(instance.model[1].xyz), length(instance.model[2].xyz)));
+    let radius = meshlet.radius * scale;
+
+    if (params.counts.y != 0u) {
+        for (var i = 0; i < 6; i = i + 1) {
+            let plane = params.frustum[i];
+            if (dot(plane.xyz, center) + plane.w < -radius) {
+                return;
+            }
+        }
+    }
+    if (params.counts.z != 0u && meshlet.cone_cutoff < 1.0) {
+        // assumes uniform scale, as the bounding sphere does
+        let axis = normalize((instance.model * vec4<f32>(meshlet.cone_axis, 0.0)).xyz);
+        let offset = center - params.camera_position.xyz;
+        if (dot(offset, axis) >= meshlet.cone_cutoff * length(offset) + radius) {
+            return;
+        }
+    }
+
+    let slot = atomicAdd(&draw.vertex_count, MAX_TRIANGLES * 3u) / (MAX_TRIANGLES * 3u);
+    visible.data[slot] = index;
+}
@@ -0,0 +1,77 @@
+// Shared by the GPU-driven passes: the meshlets of every mesh and the instances placing them,
+// all in storage buffers. A cluster is one meshlet of one instance.
+
+struct Params {
+    view_projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    // left, right, bottom, top, near, far; inside where dot(xyz, p) + w > 0
+    frustum: array<vec4<f32>, 6>;
+    camera_position: vec4<f32>;
+    // direction the light travels in
+    light_direction: vec4<f32>;
+    light_color: vec4<f32>;
+    ambient: vec4<f32>;
+    // x cluster count, y 1 for frustum culling, z 1 for cone culling
+    counts: vec4<u32>;
+};
+
+struct Meshlet {
+    center: vec3<f32>;
+    radius: f32;
+    cone_axis: vec3<f32>;
+    cone_cutoff: f32;
+    vertex_offset: u32;
+    vertex_count: u32;
+    triangle_offset: u32;
+    triangle_count: u32;
+};
+
+struct Instance {
+    model: mat4x4<f32>;
+    color: vec4<f32>;
+    // x first meshlet, y meshlet count
+    meshlets: vec4<u32>;
+};
+
+struct Vertex {
+    position: vec4<f32>;
+    normal: vec4<f32>;
+};
+
+struct Meshlets {
+    data: array<Meshlet>;
+};
+
+struct Instances {
+    data: array<Instance>;
+};
+
+// instance and meshlet index of each cluster
+struct Clusters {
+    data: array<vec2<u32>>;
+};
+
+struct Words {
+    data: array<u32>;
+};
+
+struct Vertices {
+    data: array<Vertex>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var<storage, read> meshlets: Meshlets;
+[[group(0), binding(2)]] var<storage, read> instances: Instances;
+[[group(0), binding(3)]] var<storage, read> clusters: Clusters;
+[[group(0), binding(4)]] var<storage, read> meshlet_data: Words;
+[[group(0), binding(5)]] var<storage, read> vertices: Vertices;
+
+// must match MAX_MESHLET_TRIANGLES in meshlets.rs
+let MAX_TRIANGLES: u32 = 124u;
+
+// mesh vertex index of one corner of a meshlet triangle
+fn triangle_vertex(meshlet: Meshlet, triangle: u32, corner: u32) -> u32 {
+    let packed = meshlet_data.data[meshlet.triangle_offset + triangle];
+    let local = (packed >> (corner * 8u)) & 255u;
+    return meshlet_data.data[meshlet.vertex_offset + local];
+}
@@ -0,0 +1,58 @@
+// Ambient lighting from the light probe grid. `ProbeLighting` holds the probe spherical
+// harmonics sampled at one position, already convolved for diffuse. The grid binds after the
+// reflection probes in the lighting group 2, its coefficients in nine slabs along x.
+
+struct ProbeLighting {
+    coefficients: array<vec4<f32>, 9>;
+};
+
+// light a white Lambertian surface facing `n` reflects; multiply by albedo
+fn probe_diffuse(lighting: ProbeLighting, n: vec3<f32>) -> vec3<f32> {
+    let c = lighting.coefficients;
+    let result = c[0].rgb * 0.282095
+        + c[1].rgb * (0.488603 * n.y)
+        + c[2].rgb * (0.488603 * n.z)
+        + c[3].rgb * (0.488603 * n.x)
+        + c[4].rgb * (1.092548 * n.x * n.y)
+        + c[5].rgb * (1.092548 * n.y * n.z)
+        + c[6].rgb * (0.315392 * (3.0 * n.z * n.z - 1.0))
+        + c[7].rgb * (1.092548 * n.x * n.z)
+        + c[8].rgb * (0.546274 * (n.x * n.x - n.y * n.y));
+    return max(result, vec3<f32>(0.0));
+}
+
+struct LightProbeGridParams {
+    // xyz the first probe's position, w 1 when there is a grid
+    origin: vec4<f32>;
+    // xyz distance between neighboring probes
+    spacing: vec4<f32>;
+    // xyz probes along each axis
+    counts: vec4<f32>;
+};
+
+[[group(2), binding(17)]] var<uniform> light_probe_grid: LightProbeGridParams;
+[[group(2), binding(18)]] var light_probe_texture: texture_3d<f32>;
+[[group(2), binding(19)]] var light_probe_sampler: sampler;
+
+// coefficient `index` at `cell`, in probes from the first, blended by the sampler within its slab
+fn probe_grid_coefficient(index: u32, cell: vec3<f32>) -> vec4<f32> {
+    let counts = light_probe_grid.counts.xyz;
+    let x = (f32(index) * counts.x + cell.x + 0.5) / (9.0 * counts.x);
+    let uvw = vec3<f32>(x, (cell.yz + 0.5) / counts.yz);
+    return vec4<f32>(textureSampleLevel(light_probe_texture, light_probe_sampler, uvw, 0.0).rgb, 0.0);
+}
+
+// the grid's `probe_diffuse` at `position`, clamped to the grid's edge like
+// `LightProbeGrid::sample`, or `fallback` without a grid
+fn probe_grid_irradiance(position: vec3<f32>, n: vec3<f32>, fallback: vec3<f32>) -> vec3<f32> {
+    if (light_probe_grid.origin.w < 0.5) {
+        return fallback;
+    }
+    let local = (position - light_probe_grid.origin.xyz) / max(light_probe_grid.spacing.xyz, vec3<f32>(0.0001));
+    let cell = clamp(local, vec3<f32>(0.0), light_probe_grid.counts.xyz - 1.0);
+    var lighting: ProbeLighting;
+    for (var i = 0u; i < 9u; i = i + 1u) {
+        lighting.coefficients[i] = probe_grid_coefficient(i, cell);
+    }
+    return probe_diffuse(lighting, n);
+}
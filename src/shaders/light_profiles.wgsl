@@ -0,0 +1,41 @@
+// IES photometric profiles and projected light cookies. Profiles store candela over the
+// horizontal angle around the light's axis in u and the vertical angle from it in v, normalized
+// to the brightest direction. The bindings follow the local shadows in group 2.
+
+[[group(2), binding(7)]] var light_profiles: texture_2d_array<f32>;
+[[group(2), binding(8)]] var light_cookies: texture_2d_array<f32>;
+[[group(2), binding(9)]] var light_profile_sampler: sampler;
+[[group(2), binding(10)]] var light_cookie_sampler: sampler;
+
+// the `up` of a light shining along `forward`: world up made perpendicular, or world z when the
+// light points straight up or down
+fn light_up(forward: vec3<f32>) -> vec3<f32> {
+    let reference = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(forward.y) > 0.99);
+    return normalize(reference - forward * dot(reference, forward));
+}
+
+// relative intensity towards the surface; `to_surface` points from the light to the surface,
+// `forward` down the light's axis and `up` towards its 90 degree horizontal angle
+fn ies_attenuation(profile: i32, to_surface: vec3<f32>, forward: vec3<f32>, up: vec3<f32>) -> f32 {
+    let direction = normalize(to_surface);
+    let vertical = acos(clamp(dot(direction, forward), -1.0, 1.0));
+    let right = cross(forward, up);
+    let horizontal = atan2(dot(direction, up), dot(direction, right));
+    let uv = vec2<f32>(fract(horizontal / 6.2831853), vertical / 3.1415927);
+    return textureSampleLevel(light_profiles, light_profile_sampler, uv, profile, 0.0).r;
+}
+
+// cookie color towards the surface, the cookie spanning a cone of `tan_half_angle` around
+// `forward` with its top towards `up`; black outside it
+fn light_cookie(cookie: i32, to_surface: vec3<f32>, forward: vec3<f32>, up: vec3<f32>, tan_half_angle: f32) -> vec3<f32> {
+    let depth = dot(to_surface, forward);
+    if (depth <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+    let ndc = vec2<f32>(dot(to_surface, cross(forward, up)), dot(to_surface, up)) / (depth * tan_half_angle);
+    if (any(abs(ndc) > vec2<f32>(1.0))) {
+        return vec3<f32>(0.0);
+    }
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    return textureSampleLevel(light_cookies, light_cookie_sampler, uv, cookie, 0.0).rgb;
+}
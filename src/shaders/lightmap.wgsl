@@ -0,0 +1,15 @@
+// Baked lighting for static meshes. The lightmap replaces the ambient term for the surfaces it
+// covers; lights that are still dynamic add their direct contribution on top. It binds with the
+// material, after its texture slots.
+
+[[group(3), binding(7)]] var lightmap_texture: texture_2d<f32>;
+[[group(3), binding(8)]] var lightmap_sampler: sampler;
+
+fn lightmap_irradiance(lightmap_uv: vec2<f32>, intensity: f32) -> vec3<f32> {
+    return textureSample(lightmap_texture, lightmap_sampler, lightmap_uv).rgb * intensity;
+}
+
+// `dynamic_light` is the direct lighting from non-baked lights, already multiplied by albedo
+fn apply_lightmap(albedo: vec3<f32>, lightmap_uv: vec2<f32>, intensity: f32, dynamic_light: vec3<f32>) -> vec3<f32> {
+    return albedo * lightmap_irradiance(lightmap_uv, intensity) + dynamic_light;
+}
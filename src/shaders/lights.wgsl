@@ -0,0 +1,78 @@
+// The frame's directional, point and spot lights, next to the view uniform in group 0.
+// `light_sample` says where a lit position sees one of them and how much arrives, shaped by the
+// light's IES profile and cookie. Needs `LIGHT_PROFILE_SHADER` before it.
+
+struct Light {
+    // xyz position, w range
+    position_range: vec4<f32>;
+    // xyz direction the light travels in, w kind: 0 directional, 1 point, 2 spot
+    direction_kind: vec4<f32>;
+    // linear rgb times intensity
+    color: vec4<f32>;
+    // xy cosines of the spot cone's inner and outer half angle, z index of the light's shadow
+    // among the lights of its kind
+    spot: vec4<f32>;
+    // x layer of the IES profile, y layer of the cookie, each -1 without one
+    profile: vec4<f32>;
+};
+
+struct Lights {
+    // x light count
+    header: vec4<u32>;
+    lights: array<Light, 16>;
+};
+
+[[group(0), binding(1)]] var<uniform> lights: Lights;
+
+struct LightSample {
+    // unit vector from the position towards the light
+    to_light: vec3<f32>;
+    radiance: vec3<f32>;
+};
+
+fn light_count() -> u32 {
+    return min(lights.header.x, 16u);
+}
+
+// 0 directional, 1 point, 2 spot
+fn light_kind(index: u32) -> u32 {
+    return u32(lights.lights[index].direction_kind.w);
+}
+
+// which of `LOCAL_SHADOW_SHADER`'s spot or point shadows belongs to the light
+fn light_shadow_index(index: u32) -> u32 {
+    return u32(lights.lights[index].spot.z);
+}
+
+fn light_sample(index: u32, position: vec3<f32>) -> LightSample {
+    let light = lights.lights[index];
+    var out: LightSample;
+    let kind = u32(light.direction_kind.w);
+    if (kind == 0u) {
+        out.to_light = -light.direction_kind.xyz;
+        out.radiance = light.color.rgb;
+        return out;
+    }
+    let offset = light.position_range.xyz - position;
+    let distance_squared = max(dot(offset, offset), 0.0001);
+    out.to_light = offset * inverseSqrt(distance_squared);
+    // inverse square falloff, windowed to reach zero at the range
+    let range = light.position_range.w;
+    let window = clamp(1.0 - pow(distance_squared / (range * range), 2.0), 0.0, 1.0);
+    var attenuation = window * window / distance_squared;
+    var color = light.color.rgb;
+    let forward = light.direction_kind.xyz;
+    if (light.profile.x >= 0.0) {
+        attenuation = attenuation * ies_attenuation(i32(light.profile.x), -out.to_light, forward, light_up(forward));
+    }
+    if (kind == 2u) {
+        let cos_angle = dot(-out.to_light, forward);
+        attenuation = attenuation * smoothStep(light.spot.y, light.spot.x, cos_angle);
+        if (light.profile.y >= 0.0) {
+            let tan_half_angle = sqrt(1.0 - light.spot.y * light.spot.y) / max(light.spot.y, 0.0001);
+            color = color * light_cookie(i32(light.profile.y), -out.to_light, forward, light_up(forward), tan_half_angle);
+        }
+    }
+    out.radiance = color * attenuation;
+    return out;
+}
@@ -0,0 +1,108 @@
+// Shadows of spot and point lights. Spot lights render into tiles of one depth atlas, point
+// lights into six layers of a depth array, one per cube face. The bindings start at 3 in group 2,
+// after the directional shadow's, so one lighting bind group holds both. `local_shadow` needs
+// `LIGHTS_SHADER` before it.
+
+struct SpotShadow {
+    view_projection: mat4x4<f32>;
+    // uv offset in xy and scale in zw of the light's atlas tile; zero scale means no shadow
+    atlas_rect: vec4<f32>;
+    // xyz light position, w bias in world units
+    position_bias: vec4<f32>;
+};
+
+struct PointShadow {
+    // one per cube face, in +X, -X, +Y, -Y, +Z, -Z order
+    faces: array<mat4x4<f32>, 6>;
+    // xyz light position, w bias in world units
+    position_bias: vec4<f32>;
+    // x layer of the first face, w 1 if the light has a shadow
+    params: vec4<f32>;
+};
+
+struct LocalShadowData {
+    // x spot count, y point count
+    header: vec4<u32>;
+    spots: array<SpotShadow, 16>;
+    points: array<PointShadow, 4>;
+};
+
+[[group(2), binding(3)]] var<uniform> local_shadows: LocalShadowData;
+[[group(2), binding(4)]] var shadow_atlas: texture_depth_2d;
+[[group(2), binding(5)]] var point_shadow_faces: texture_depth_2d_array;
+[[group(2), binding(6)]] var shadow_sampler: sampler_comparison;
+
+// 1 where lit; a 2x2 PCF kernel on top of the hardware comparison filtering
+fn spot_shadow(index: u32, position: vec3<f32>) -> f32 {
+    let shadow = local_shadows.spots[index];
+    if (index >= local_shadows.header.x || shadow.atlas_rect.z <= 0.0) {
+        return 1.0;
+    }
+    let biased = position + normalize(shadow.position_bias.xyz - position) * shadow.position_bias.w;
+    let clip = shadow.view_projection * vec4<f32>(biased, 1.0);
+    if (clip.w <= 0.0) {
+        return 1.0;
+    }
+    let ndc = clip.xyz / clip.w;
+    let local_uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (any(local_uv < vec2<f32>(0.0)) || any(local_uv > vec2<f32>(1.0)) || ndc.z > 1.0) {
+        return 1.0;
+    }
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_atlas));
+    // keep taps inside the tile so neighbouring lights don't bleed in
+    let low = shadow.atlas_rect.xy + texel;
+    let high = shadow.atlas_rect.xy + shadow.atlas_rect.zw - texel;
+    let uv = shadow.atlas_rect.xy + local_uv * shadow.atlas_rect.zw;
+    var lit = 0.0;
+    for (var i = 0; i < 4; i = i + 1) {
+        let offset = vec2<f32>(f32(i & 1) - 0.5, f32(i >> 1u) - 0.5) * texel;
+        lit = lit + textureSampleCompareLevel(shadow_atlas, shadow_sampler, clamp(uv + offset, low, high), ndc.z);
+    }
+    return lit * 0.25;
+}
+
+fn point_shadow(index: u32, position: vec3<f32>) -> f32 {
+    let shadow = local_shadows.points[index];
+    if (index >= local_shadows.header.y || shadow.params.w < 0.5) {
+        return 1.0;
+    }
+    let offset = position - shadow.position_bias.xyz;
+    let biased = position - normalize(offset) * shadow.position_bias.w;
+    // the face the offset's major axis points through; each negative face follows its positive
+    let axis = abs(offset);
+    var face = 4u;
+    var negative = offset.z < 0.0;
+    if (axis.x >= axis.y && axis.x >= axis.z) {
+        face = 0u;
+        negative = offset.x < 0.0;
+    } else if (axis.y >= axis.z) {
+        face = 2u;
+        negative = offset.y < 0.0;
+    }
+    if (negative) {
+        face = face + 1u;
+    }
+    let clip = local_shadows.points[index].faces[face] * vec4<f32>(biased, 1.0);
+    if (clip.w <= 0.0) {
+        return 1.0;
+    }
+    let ndc = clip.xyz / clip.w;
+    if (ndc.z > 1.0) {
+        return 1.0;
+    }
+    let uv = clamp(vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5), vec2<f32>(0.0), vec2<f32>(1.0));
+    return textureSampleCompareLevel(point_shadow_faces, shadow_sampler, uv, i32(shadow.params.x) + i32(face), ndc.z);
+}
+
+// the shadow of light `index` of `LIGHTS_SHADER`: its spot or point shadow, 1 for directional
+// lights
+fn local_shadow(index: u32, position: vec3<f32>) -> f32 {
+    let kind = light_kind(index);
+    if (kind == 1u) {
+        return point_shadow(light_shadow_index(index), position);
+    }
+    if (kind == 2u) {
+        return spot_shadow(light_shadow_index(index), position);
+    }
+    return 1.0;
+}
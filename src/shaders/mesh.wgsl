@@ -0,0 +1,91 @@
+// Basic lit mesh shader: Lambert diffuse from the frame's lights plus ambient and the
+// environment's irradiance, or the light probe grid's when there is one, tinted by the draw's color and
+// the vertex colors, with the lights' shadows. Needs the lighting shaders `lit_source` prepends
+// before it. With OIT defined it writes the weighted blended transparency targets of `oit.wgsl`
+// instead.
+
+struct View {
+    view_projection: mat4x4<f32>;
+    // w scales the environment's lighting, 0 without one
+    ambient: vec4<f32>;
+    // wetness, snow cover
+    weather: vec4<f32>;
+    camera_position: vec4<f32>;
+};
+
+// placement of all the draw's instances
+struct Draw {
+    model: mat4x4<f32>;
+    // inverse transpose of `model`, for normals
+    normal_matrix: mat4x4<f32>;
+    color: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> view: View;
+[[group(1), binding(0)]] var<uniform> draw: Draw;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] color: vec4<f32>;
+#ifdef DEBUG_VIEW
+    [[location(3)]] uv: vec2<f32>;
+#endif
+};
+
+[[stage(vertex)]]
+fn vs_main(vertex: MeshVertex, instance: DrawInstance) -> VertexOutput {
+    var out: VertexOutput;
+    let model = draw.model * instance_model(instance);
+    let world_position = model * vec4<f32>(vertex.position, 1.0);
+    out.position = view.view_projection * world_position;
+    out.world_position = world_position.xyz;
+    out.normal = (draw.normal_matrix * instance_normal_matrix(instance) * vec4<f32>(vertex.normal, 0.0)).xyz;
+    out.color = vertex_color(vertex) * draw.color * instance.color;
+#ifdef DEBUG_VIEW
+    out.uv = vertex.uv;
+#endif
+    return out;
+}
+
+// SurfaceWeather::modulate for a fully rough surface
+fn apply_weather(color: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let wet = color * (1.0 - 0.5 * view.weather.x);
+    let snow = view.weather.y;
+    let coverage = smoothStep(0.0, 1.0, (normal.y - (1.0 - snow * 1.2)) / 0.2) * sqrt(snow);
+    return mix(wet, vec3<f32>(0.9, 0.92, 0.95), coverage);
+}
+
+fn shade(in: VertexOutput) -> vec4<f32> {
+    let normal = normalize(in.normal);
+    let irradiance = probe_grid_irradiance(in.world_position, normal, environment_diffuse(normal) * view.ambient.w);
+    var light = view.ambient.rgb + irradiance;
+    for (var i = 0u; i < light_count(); i = i + 1u) {
+        let sample = light_sample(i, in.world_position);
+        var radiance = sample.radiance;
+        if (i == shadowed_light()) {
+            radiance = radiance * directional_shadow(in.world_position, normal, view.camera_position.xyz);
+        } else {
+            radiance = radiance * local_shadow(i, in.world_position);
+        }
+        light = light + radiance * max(dot(normal, sample.to_light), 0.0);
+    }
+#ifdef DEBUG_VIEW
+    return debug_output(in.world_position, view.camera_position.xyz, in.uv, vec2<f32>(DEBUG_REFERENCE_TEXTURE_SIZE));
+#else
+    return vec4<f32>(apply_weather(in.color.rgb, normal) * light, in.color.a);
+#endif
+}
+
+#ifdef OIT
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> OitOutput {
+    return oit_output(shade(in), in.position.z);
+}
+#else
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return shade(in);
+}
+#endif
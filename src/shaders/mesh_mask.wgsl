@@ -0,0 +1,33 @@
+// Silhouettes of outlined meshes for `Outline`'s mask: 1 wherever one covers the pixel, hidden
+// or not, so the outline follows the whole object.
+
+struct MaskView {
+    view_projection: mat4x4<f32>;
+};
+
+struct Draw {
+    model: mat4x4<f32>;
+    normal_matrix: mat4x4<f32>;
+    color: vec4<f32>;
+};
+
+struct MaskInstance {
+    [[location(8)]] model_0: vec4<f32>;
+    [[location(9)]] model_1: vec4<f32>;
+    [[location(10)]] model_2: vec4<f32>;
+    [[location(11)]] model_3: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> mask_view: MaskView;
+[[group(1), binding(0)]] var<uniform> draw: Draw;
+
+[[stage(vertex)]]
+fn vs_main(vertex: MeshVertex, instance: MaskInstance) -> [[builtin(position)]] vec4<f32> {
+    let model = draw.model * mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    return mask_view.view_projection * (model * vec4<f32>(vertex.position, 1.0));
+}
+
+[[stage(fragment)]]
+fn fs_main() -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(1.0);
+}
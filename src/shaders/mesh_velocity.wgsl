@@ -0,0 +1,53 @@
+// Screen-space velocity of meshes, in uv units per frame: current position minus previous
+// position, from this and last frame's camera and instance transforms. Drawn over the camera
+// motion against the scene's depth, so only the visible surfaces write theirs.
+
+struct VelocityView {
+    view_projection: mat4x4<f32>;
+    previous_view_projection: mat4x4<f32>;
+};
+
+struct Draw {
+    model: mat4x4<f32>;
+    normal_matrix: mat4x4<f32>;
+    color: vec4<f32>;
+};
+
+// the instance's transform and last frame's, which already includes the draw's
+struct VelocityInstance {
+    [[location(8)]] model_0: vec4<f32>;
+    [[location(9)]] model_1: vec4<f32>;
+    [[location(10)]] model_2: vec4<f32>;
+    [[location(11)]] model_3: vec4<f32>;
+    [[location(12)]] previous_0: vec4<f32>;
+    [[location(13)]] previous_1: vec4<f32>;
+    [[location(14)]] previous_2: vec4<f32>;
+    [[location(15)]] previous_3: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> velocity_view: VelocityView;
+[[group(1), binding(0)]] var<uniform> draw: Draw;
+
+struct VelocityOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] clip: vec4<f32>;
+    [[location(1)]] previous_clip: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(vertex: MeshVertex, instance: VelocityInstance) -> VelocityOutput {
+    var out: VelocityOutput;
+    // the same product as the mesh shader's, so the depth test passes where it drew
+    let model = draw.model * mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let previous_model = mat4x4<f32>(instance.previous_0, instance.previous_1, instance.previous_2, instance.previous_3);
+    out.position = velocity_view.view_projection * (model * vec4<f32>(vertex.position, 1.0));
+    out.clip = out.position;
+    out.previous_clip = velocity_view.previous_view_projection * (previous_model * vec4<f32>(vertex.position, 1.0));
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VelocityOutput) -> [[location(0)]] vec4<f32> {
+    let ndc = in.clip.xy / in.clip.w - in.previous_clip.xy / in.previous_clip.w;
+    return vec4<f32>(ndc.x * 0.5, -ndc.y * 0.5, 0.0, 0.0);
+}
@@ -0,0 +1,83 @@
+// Vertex input of meshes. Locations follow `VertexSemantic::location`; everything past position,
+// normal and UV is optional. Shaders go through the accessors below, which fall back to white,
+// the first UV set and a tangent built from the normal when the mesh doesn't have them.
+
+struct MeshVertex {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] uv: vec2<f32>;
+#ifdef VERTEX_COLOR
+    [[location(3)]] color: vec4<f32>;
+#endif
+#ifdef UV2
+    [[location(4)]] uv2: vec2<f32>;
+#endif
+#ifdef VERTEX_TANGENT
+    [[location(5)]] tangent: vec4<f32>;
+#endif
+#ifdef JOINTS
+    [[location(6)]] joints: vec4<u32>;
+#endif
+#ifdef WEIGHTS
+    [[location(7)]] weights: vec4<f32>;
+#endif
+};
+
+// Per-instance input of `MeshRenderer` draws, after the mesh's own attributes: the instance's
+// transform, the inverse transpose of it for normals, and its color.
+struct DrawInstance {
+    [[location(8)]] model_0: vec4<f32>;
+    [[location(9)]] model_1: vec4<f32>;
+    [[location(10)]] model_2: vec4<f32>;
+    [[location(11)]] model_3: vec4<f32>;
+    [[location(12)]] normal_0: vec4<f32>;
+    [[location(13)]] normal_1: vec4<f32>;
+    [[location(14)]] normal_2: vec4<f32>;
+    [[location(15)]] color: vec4<f32>;
+};
+
+fn instance_model(instance: DrawInstance) -> mat4x4<f32> {
+    return mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+}
+
+fn instance_normal_matrix(instance: DrawInstance) -> mat4x4<f32> {
+    return mat4x4<f32>(instance.normal_0, instance.normal_1, instance.normal_2, vec4<f32>(0.0, 0.0, 0.0, 1.0));
+}
+
+fn vertex_color(vertex: MeshVertex) -> vec4<f32> {
+#ifdef VERTEX_COLOR
+    return vertex.color;
+#else
+    return vec4<f32>(1.0);
+#endif
+}
+
+// lightmap or detail map coordinates
+fn vertex_uv2(vertex: MeshVertex) -> vec2<f32> {
+#ifdef UV2
+    return vertex.uv2;
+#else
+    return vertex.uv;
+#endif
+}
+
+// whether `vertex_uv2` is the mesh's own second UV set, e.g. for lightmaps
+fn vertex_has_uv2() -> bool {
+#ifdef UV2
+    return true;
+#else
+    return false;
+#endif
+}
+
+fn vertex_tangent(vertex: MeshVertex) -> vec4<f32> {
+#ifdef VERTEX_TANGENT
+    return vertex.tangent;
+#else
+    var axis = vec3<f32>(0.0, 1.0, 0.0);
+    if (abs(vertex.normal.y) > 0.99) {
+        axis = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    return vec4<f32>(normalize(cross(axis, vertex.normal)), 1.0);
+#endif
+}
@@ -0,0 +1,66 @@
+// Minimap overlays: marker icons as instanced quads shaped in the fragment shader, and the mask
+// that makes a round map by erasing everything outside the circle.
+
+struct MarkerInstance {
+    // center and half size in clip space, rotation in radians
+    [[location(0)]] placement: vec4<f32>;
+    [[location(1)]] color: vec4<f32>;
+    [[location(2)]] shape: u32;
+};
+
+struct MarkerOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    // -1 to 1 across the icon, +y pointing along its heading
+    [[location(0)]] local: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+    [[location(2), interpolate(flat)]] shape: u32;
+};
+
+[[stage(vertex)]]
+fn vs_marker([[builtin(vertex_index)]] index: u32, instance: MarkerInstance) -> MarkerOutput {
+    var out: MarkerOutput;
+    let corner = vec2<f32>(f32(index & 1u), f32((index >> 1u) & 1u)) * 2.0 - 1.0;
+    let c = cos(instance.placement.w);
+    let s = sin(instance.placement.w);
+    let rotated = vec2<f32>(c * corner.x - s * corner.y, s * corner.x + c * corner.y);
+    out.position = vec4<f32>(instance.placement.xy + rotated * instance.placement.z, 0.0, 1.0);
+    out.local = corner;
+    out.color = instance.color;
+    out.shape = instance.shape;
+    return out;
+}
+
+// signed distance to the arrow's outline, negative inside: a triangle pointing up with a V cut
+// into its base
+fn arrow_distance(p: vec2<f32>) -> f32 {
+    let x = abs(p.x);
+    let side = dot(vec2<f32>(x, p.y - 0.9), normalize(vec2<f32>(2.0, 1.0)));
+    let notch = -dot(vec2<f32>(x, p.y + 0.35), normalize(vec2<f32>(0.6, 1.0)));
+    return max(side, notch);
+}
+
+[[stage(fragment)]]
+fn fs_marker(in: MarkerOutput) -> [[location(0)]] vec4<f32> {
+    let p = in.local;
+    var distance: f32;
+    if (in.shape == 1u) {
+        distance = abs(length(p) - 0.75) - 0.2;
+    } else if (in.shape == 2u) {
+        distance = max(abs(p.x), abs(p.y)) - 0.85;
+    } else if (in.shape == 3u) {
+        distance = arrow_distance(p);
+    } else {
+        distance = length(p) - 0.9;
+    }
+    // about a pixel of antialiasing whatever the icon's size
+    let width = fwidth(distance);
+    let coverage = 1.0 - smoothStep(-width, width, distance);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
+
+[[stage(fragment)]]
+fn fs_mask(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let distance = length(in.uv * 2.0 - 1.0);
+    let width = fwidth(distance);
+    return vec4<f32>(0.0, 0.0, 0.0, smoothStep(1.0 - width, 1.0, distance));
+}
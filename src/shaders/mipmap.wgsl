@@ -0,0 +1,15 @@
+// Builds a texture's mip chain: each texel of a level is the average of the 2x2 texels of the
+// level above it, clamped at the edge for odd sizes.
+
+[[group(0), binding(0)]] var source_texture: texture_2d<f32>;
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let last = textureDimensions(source_texture) - vec2<i32>(1, 1);
+    let origin = vec2<i32>(in.position.xy) * 2;
+    let color = textureLoad(source_texture, min(origin, last), 0)
+        + textureLoad(source_texture, min(origin + vec2<i32>(1, 0), last), 0)
+        + textureLoad(source_texture, min(origin + vec2<i32>(0, 1), last), 0)
+        + textureLoad(source_texture, min(origin + vec2<i32>(1, 1), last), 0);
+    return color * 0.25;
+}
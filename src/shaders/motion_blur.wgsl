@@ -0,0 +1,32 @@
+// Blurs each pixel along its velocity. The velocity is scaled by the shutter fraction and
+// clamped to a maximum length in pixels. Goes after the motion vectors shader.
+
+struct Params {
+    texel_size: vec2<f32>;
+    shutter: f32;
+    max_length: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var input_sampler: sampler;
+[[group(0), binding(2)]] var color_texture: texture_2d<f32>;
+
+let SAMPLE_COUNT: i32 = 12;
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    var velocity = motion_vector(vec2<i32>(in.position.xy)) * params.shutter;
+    let length_px = length(velocity / params.texel_size);
+    if (length_px > params.max_length) {
+        velocity = velocity * (params.max_length / length_px);
+    }
+
+    let center = textureSample(color_texture, input_sampler, in.uv);
+    var sum = vec3<f32>(0.0);
+    for (var i: i32 = 0; i < SAMPLE_COUNT; i = i + 1) {
+        // centered on the pixel, so blur extends both ways like a real open shutter
+        let t = f32(i) / f32(SAMPLE_COUNT - 1) - 0.5;
+        sum = sum + textureSample(color_texture, input_sampler, in.uv - velocity * t).rgb;
+    }
+    return vec4<f32>(sum / f32(SAMPLE_COUNT), center.a);
+}
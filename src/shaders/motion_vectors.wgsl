@@ -0,0 +1,34 @@
+// This and last frame's camera matrices plus the velocity buffer, for post effects that
+// reproject: TAA, temporal denoisers, motion-aware sharpening. Velocities are in uv units per
+// frame, current position minus previous position.
+
+struct FrameMatrices {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    view_projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    previous_view: mat4x4<f32>;
+    previous_projection: mat4x4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    // clip space from this frame's clip space, for pixels that only moved with the camera
+    reprojection: mat4x4<f32>;
+};
+
+[[group(1), binding(0)]] var<uniform> frame_matrices: FrameMatrices;
+[[group(1), binding(1)]] var velocity_texture: texture_2d<f32>;
+
+fn motion_vector(coords: vec2<i32>) -> vec2<f32> {
+    return textureLoad(velocity_texture, coords, 0).xy;
+}
+
+// where the surface under `uv` was last frame
+fn previous_uv(uv: vec2<f32>, coords: vec2<i32>) -> vec2<f32> {
+    return uv - motion_vector(coords);
+}
+
+// for object pipelines writing their own velocities: clip positions with this and last frame's
+// model and camera matrices
+fn clip_motion(clip: vec4<f32>, previous_clip: vec4<f32>) -> vec2<f32> {
+    let ndc = clip.xy / clip.w - previous_clip.xy / previous_clip.w;
+    return vec2<f32>(ndc.x * 0.5, -ndc.y * 0.5);
+}
@@ -0,0 +1,87 @@
+// Tiling gradient (Perlin) and cellular (Worley) noise, shared by the noise texture generator
+// and any shader that wants noise of its own. Matches the CPU versions in noise.rs. A `period`
+// above zero makes the noise repeat every `period` units, zero leaves it unbounded.
+
+fn noise_hash(x: u32) -> u32 {
+    var h = x * 747796405u + 2891336453u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    return (h >> 22u) ^ h;
+}
+
+fn noise_cell_hash(cell: vec3<i32>, period: i32, seed: u32) -> u32 {
+    var wrapped = cell;
+    if (period > 0) {
+        let p = vec3<i32>(period);
+        wrapped = ((cell % p) + p) % p;
+    }
+    return noise_hash(u32(wrapped.x) ^ noise_hash(u32(wrapped.y) ^ noise_hash(u32(wrapped.z) ^ noise_hash(seed))));
+}
+
+// three 10 bit fractions of a hash, in [0, 1)
+fn noise_unit3(h: u32) -> vec3<f32> {
+    return vec3<f32>(f32(h & 1023u), f32((h >> 10u) & 1023u), f32((h >> 20u) & 1023u)) / 1024.0;
+}
+
+fn perlin_corner(cell: vec3<i32>, corner: vec3<i32>, f: vec3<f32>, period: i32, seed: u32) -> f32 {
+    let gradient = normalize(noise_unit3(noise_cell_hash(cell + corner, period, seed)) * 2.0 - 0.999);
+    return dot(gradient, f - vec3<f32>(corner));
+}
+
+// In about [-1, 1].
+fn perlin3(p: vec3<f32>, period: i32, seed: u32) -> f32 {
+    let floored = floor(p);
+    let cell = vec3<i32>(floored);
+    let f = p - floored;
+    let u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    let x00 = mix(perlin_corner(cell, vec3<i32>(0, 0, 0), f, period, seed), perlin_corner(cell, vec3<i32>(1, 0, 0), f, period, seed), u.x);
+    let x10 = mix(perlin_corner(cell, vec3<i32>(0, 1, 0), f, period, seed), perlin_corner(cell, vec3<i32>(1, 1, 0), f, period, seed), u.x);
+    let x01 = mix(perlin_corner(cell, vec3<i32>(0, 0, 1), f, period, seed), perlin_corner(cell, vec3<i32>(1, 0, 1), f, period, seed), u.x);
+    let x11 = mix(perlin_corner(cell, vec3<i32>(0, 1, 1), f, period, seed), perlin_corner(cell, vec3<i32>(1, 1, 1), f, period, seed), u.x);
+    return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z) * 1.1547;
+}
+
+// Distance to the nearest feature point, one per unit cell, clamped to [0, 1].
+fn worley3(p: vec3<f32>, period: i32, seed: u32) -> f32 {
+    let floored = floor(p);
+    let cell = vec3<i32>(floored);
+    let f = p - floored;
+    var nearest = 8.0;
+    for (var z: i32 = -1; z <= 1; z = z + 1) {
+        for (var y: i32 = -1; y <= 1; y = y + 1) {
+            for (var x: i32 = -1; x <= 1; x = x + 1) {
+                let offset = vec3<i32>(x, y, z);
+                let point = vec3<f32>(offset) + noise_unit3(noise_cell_hash(cell + offset, period, seed));
+                let delta = point - f;
+                nearest = min(nearest, dot(delta, delta));
+            }
+        }
+    }
+    return min(sqrt(nearest), 1.0);
+}
+
+let NOISE_PERLIN: u32 = 0u;
+let NOISE_WORLEY: u32 = 1u;
+
+// Octaves of `kind` noise, each at twice the frequency and `gain` times the amplitude of the
+// last, normalized to [0, 1]. Stays tiling, since every octave's period doubles too.
+fn noise_fbm(kind: u32, p: vec3<f32>, period: i32, octaves: u32, gain: f32, seed: u32) -> f32 {
+    var sum = 0.0;
+    var total = 0.0;
+    var amplitude = 1.0;
+    var scale = 1.0;
+    var octave_period = period;
+    for (var octave: u32 = 0u; octave < max(octaves, 1u); octave = octave + 1u) {
+        var value: f32;
+        if (kind == NOISE_WORLEY) {
+            value = worley3(p * scale, octave_period, seed + octave);
+        } else {
+            value = perlin3(p * scale, octave_period, seed + octave) * 0.5 + 0.5;
+        }
+        sum = sum + value * amplitude;
+        total = total + amplitude;
+        amplitude = amplitude * gain;
+        scale = scale * 2.0;
+        octave_period = octave_period * 2;
+    }
+    return clamp(sum / total, 0.0, 1.0);
+}
@@ -0,0 +1,67 @@
+// Fills a tiling noise texture, one noise layer per channel. Prepended with noise.wgsl.
+
+struct Layer {
+    kind: u32;
+    frequency: u32;
+    octaves: u32;
+    seed: u32;
+    gain: f32;
+    invert: u32;
+    enabled: u32;
+    padding: u32;
+};
+
+struct Params {
+    size: u32;
+    depth: u32;
+    padding0: u32;
+    padding1: u32;
+    layers: array<Layer, 4>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: Params;
+#ifdef THREE_D
+[[group(0), binding(1)]]
+var output: texture_storage_3d<rgba8unorm, write>;
+#else
+[[group(0), binding(1)]]
+var output: texture_storage_2d<rgba8unorm, write>;
+#endif
+
+fn layer_value(layer: Layer, uvw: vec3<f32>) -> f32 {
+    if (layer.enabled == 0u) {
+        return 0.0;
+    }
+    let value = noise_fbm(layer.kind, uvw * f32(layer.frequency), i32(layer.frequency), layer.octaves, layer.gain, layer.seed);
+    if (layer.invert != 0u) {
+        return 1.0 - value;
+    }
+    return value;
+}
+
+#ifdef THREE_D
+[[stage(compute), workgroup_size(4, 4, 4)]]
+#else
+[[stage(compute), workgroup_size(8, 8, 1)]]
+#endif
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (id.x >= params.size || id.y >= params.size || id.z >= params.depth) {
+        return;
+    }
+    var uvw = (vec3<f32>(id) + 0.5) / f32(params.size);
+#ifndef THREE_D
+    uvw.z = 0.0;
+#endif
+    let color = vec4<f32>(
+        layer_value(params.layers[0], uvw),
+        layer_value(params.layers[1], uvw),
+        layer_value(params.layers[2], uvw),
+        layer_value(params.layers[3], uvw),
+    );
+#ifdef THREE_D
+    textureStore(output, vec3<i32>(id), color);
+#else
+    textureStore(output, vec2<i32>(id.xy), color);
+#endif
+}
@@ -0,0 +1,23 @@
+// Weighted blended order-independent transparency (McGuire and Bavoil 2013). Transparent
+// fragment shaders prepend this and return `oit_output(color, position.z)` instead of blending.
+
+struct OitOutput {
+    [[location(0)]] accum: vec4<f32>;
+    [[location(1)]] revealage: f32;
+};
+
+// favours fragments that are close and opaque; range chosen to stay within half-float precision
+fn oit_weight(depth: f32, alpha: f32) -> f32 {
+    let a = min(1.0, alpha * 10.0) + 0.01;
+    let d = 1.0 - depth * 0.9;
+    return clamp(a * a * a * 1e8 * d * d * d, 1e-2, 3e3);
+}
+
+// `color` is straight (not premultiplied) alpha; `depth` is the fragment's window-space z
+fn oit_output(color: vec4<f32>, depth: f32) -> OitOutput {
+    var out: OitOutput;
+    let weight = oit_weight(depth, color.a);
+    out.accum = vec4<f32>(color.rgb * color.a, color.a) * weight;
+    out.revealage = color.a;
+    return out;
+}
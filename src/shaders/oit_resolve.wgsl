@@ -0,0 +1,17 @@
+// Composites the weighted blended transparency targets over the opaque scene, which is blended
+// with the result using (src alpha, 1 - src alpha).
+
+[[group(0), binding(0)]] var accum_texture: texture_2d<f32>;
+[[group(0), binding(1)]] var revealage_texture: texture_2d<f32>;
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let coords = vec2<i32>(in.position.xy);
+    let revealage = textureLoad(revealage_texture, coords, 0).r;
+    if (revealage >= 0.9999) {
+        discard;
+    }
+    let accum = textureLoad(accum_texture, coords, 0);
+    let average = accum.rgb / clamp(accum.a, 1e-4, 5e4);
+    return vec4<f32>(average, 1.0 - revealage);
+}
@@ -0,0 +1,65 @@
+// Selection outlines by jump flooding: every pixel finds the nearest pixel of the selection mask
+// in log2(thickness) passes, so cost doesn't grow with outline width.
+
+struct Style {
+    color: vec4<f32>;
+    thickness: f32;
+};
+
+struct Jump {
+    step: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> style: Style;
+[[group(0), binding(1)]] var mask_texture: texture_2d<f32>;
+[[group(0), binding(2)]] var seed_texture: texture_2d<f32>;
+[[group(0), binding(3)]] var<uniform> jump: Jump;
+
+// seeds hold the pixel coordinates of the nearest mask pixel, or -1 where none is known yet
+[[stage(fragment)]]
+fn fs_seed(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let coords = vec2<i32>(in.position.xy);
+    if (textureLoad(mask_texture, coords, 0).r > 0.5) {
+        return vec4<f32>(in.position.xy, 0.0, 0.0);
+    }
+    return vec4<f32>(-1.0, -1.0, 0.0, 0.0);
+}
+
+[[stage(fragment)]]
+fn fs_jump(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(seed_texture);
+    let coords = vec2<i32>(in.position.xy);
+    let step = i32(jump.step);
+    var best = vec2<f32>(-1.0);
+    var best_distance = 1e20;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let sample = coords + vec2<i32>(x, y) * step;
+            if (all(sample >= vec2<i32>(0)) && all(sample < size)) {
+                let seed = textureLoad(seed_texture, sample, 0).xy;
+                let offset = seed - in.position.xy;
+                let distance = dot(offset, offset);
+                if (seed.x >= 0.0 && distance < best_distance) {
+                    best = seed;
+                    best_distance = distance;
+                }
+            }
+        }
+    }
+    return vec4<f32>(best, 0.0, 0.0);
+}
+
+[[stage(fragment)]]
+fn fs_composite(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let coords = vec2<i32>(in.position.xy);
+    if (textureLoad(mask_texture, coords, 0).r > 0.5) {
+        discard;
+    }
+    let seed = textureLoad(seed_texture, coords, 0).xy;
+    if (seed.x < 0.0) {
+        discard;
+    }
+    // a one pixel ramp at the outer edge keeps the outline anti-aliased
+    let coverage = clamp(style.thickness + 0.5 - distance(seed, in.position.xy), 0.0, 1.0);
+    return vec4<f32>(style.color.rgb, style.color.a * coverage);
+}
@@ -0,0 +1,205 @@
+// Metallic-roughness PBR mesh shader: GGX specular and Lambert diffuse under the frame's lights,
+// plus ambient and image-based lighting from the environment, with the light probe grid's
+// diffuse and the reflection probes' specular where they cover the surface, both scaled by
+// occlusion. Each texture slot is an #ifdef; without it the material's factor is used alone.
+// With LIGHTMAP, and `LIGHTMAP_SHADER` before it, meshes with a second UV set take their diffuse
+// indirect light from the lightmap instead of the ambient and the environment. With
+// PLANAR_REFLECTION, and `PLANAR_REFLECTION_SHADER` before it, the specular reflection comes from
+// the planar reflection wherever it rendered something, for mirrors and water. Needs the lighting
+// shaders `lit_source` prepends before it, the material at group 3 after the lighting group 2.
+
+struct View {
+    view_projection: mat4x4<f32>;
+    // w scales the environment's lighting, 0 without one
+    ambient: vec4<f32>;
+    // wetness, snow cover
+    weather: vec4<f32>;
+    camera_position: vec4<f32>;
+};
+
+// placement of all the draw's instances
+struct Draw {
+    model: mat4x4<f32>;
+    // inverse transpose of `model`, for normals
+    normal_matrix: mat4x4<f32>;
+    color: vec4<f32>;
+};
+
+struct MaterialParams {
+    base_color: vec4<f32>;
+    emissive: vec4<f32>;
+    // metallic, roughness, normal scale, occlusion strength
+    factors: vec4<f32>;
+    // cutoff
+    alpha: vec4<f32>;
+    // lightmap intensity, planar reflection distortion
+    lighting: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> view: View;
+[[group(1), binding(0)]] var<uniform> draw: Draw;
+[[group(3), binding(0)]] var<uniform> material: MaterialParams;
+[[group(3), binding(1)]] var material_sampler: sampler;
+#ifdef ALBEDO_MAP
+[[group(3), binding(2)]] var albedo_map: texture_2d<f32>;
+#endif
+#ifdef NORMAL_MAP
+[[group(3), binding(3)]] var normal_map: texture_2d<f32>;
+#endif
+#ifdef METALLIC_ROUGHNESS_MAP
+[[group(3), binding(4)]] var metallic_roughness_map: texture_2d<f32>;
+#endif
+#ifdef OCCLUSION_MAP
+[[group(3), binding(5)]] var occlusion_map: texture_2d<f32>;
+#endif
+#ifdef EMISSIVE_MAP
+[[group(3), binding(6)]] var emissive_map: texture_2d<f32>;
+#endif
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] tangent: vec4<f32>;
+    [[location(3)]] uv: vec2<f32>;
+    [[location(4)]] color: vec4<f32>;
+    [[location(5)]] uv2: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(vertex: MeshVertex, instance: DrawInstance) -> VertexOutput {
+    var out: VertexOutput;
+    let model = draw.model * instance_model(instance);
+    let world_position = model * vec4<f32>(vertex.position, 1.0);
+    out.position = view.view_projection * world_position;
+    out.world_position = world_position.xyz;
+    out.normal = (draw.normal_matrix * instance_normal_matrix(instance) * vec4<f32>(vertex.normal, 0.0)).xyz;
+    let tangent = vertex_tangent(vertex);
+    out.tangent = vec4<f32>((model * vec4<f32>(tangent.xyz, 0.0)).xyz, tangent.w);
+    out.uv = vertex.uv;
+    out.uv2 = vertex_uv2(vertex);
+    out.color = vertex_color(vertex) * draw.color * instance.color;
+    return out;
+}
+
+let PI: f32 = 3.14159265;
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    var base_color = in.color * material.base_color;
+#ifdef ALBEDO_MAP
+    base_color = base_color * textureSample(albedo_map, material_sampler, in.uv);
+#endif
+    var metallic = material.factors.x;
+    var roughness = material.factors.y;
+#ifdef METALLIC_ROUGHNESS_MAP
+    let metallic_roughness = textureSample(metallic_roughness_map, material_sampler, in.uv);
+    metallic = metallic * metallic_roughness.b;
+    roughness = roughness * metallic_roughness.g;
+#endif
+    var occlusion = 1.0;
+#ifdef OCCLUSION_MAP
+    occlusion = 1.0 + material.factors.w * (textureSample(occlusion_map, material_sampler, in.uv).r - 1.0);
+#endif
+    var emissive = material.emissive.rgb;
+#ifdef EMISSIVE_MAP
+    emissive = emissive * textureSample(emissive_map, material_sampler, in.uv).rgb;
+#endif
+    let surface_normal = normalize(in.normal);
+    var normal = surface_normal;
+#ifdef NORMAL_MAP
+    let tangent = normalize(in.tangent.xyz - normal * dot(normal, in.tangent.xyz));
+    let bitangent = cross(normal, tangent) * in.tangent.w;
+    var local = textureSample(normal_map, material_sampler, in.uv).xyz * 2.0 - 1.0;
+    local = vec3<f32>(local.xy * material.factors.z, local.z);
+    normal = normalize(tangent * local.x + bitangent * local.y + normal * local.z);
+#endif
+    if (base_color.a < material.alpha.x) {
+        discard;
+    }
+
+    // SurfaceWeather::modulate
+    let wetness = view.weather.x;
+    var albedo = base_color.rgb * (1.0 - 0.5 * wetness * roughness);
+    roughness = roughness * (1.0 - 0.7 * wetness);
+    let snow = view.weather.y;
+    let coverage = smoothStep(0.0, 1.0, (normal.y - (1.0 - snow * 1.2)) / 0.2) * sqrt(snow);
+    albedo = mix(albedo, vec3<f32>(0.9, 0.92, 0.95), coverage);
+    roughness = mix(roughness, 0.8, coverage);
+    metallic = metallic * (1.0 - coverage);
+    // fully smooth surfaces make the highlight a singularity
+    roughness = max(roughness, 0.045);
+
+    let v = normalize(view.camera_position.xyz - in.world_position);
+    let n_dot_v = max(dot(normal, v), 0.0001);
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let diffuse_color = albedo * (1.0 - metallic);
+    var direct = vec3<f32>(0.0);
+    for (var i = 0u; i < light_count(); i = i + 1u) {
+        let sample = light_sample(i, in.world_position);
+        var radiance = sample.radiance;
+        if (i == shadowed_light()) {
+            // offset along the geometric normal; the normal map's would make the bias bumpy
+            radiance = radiance * directional_shadow(in.world_position, surface_normal, view.camera_position.xyz);
+        } else {
+            radiance = radiance * local_shadow(i, in.world_position);
+        }
+        let l = sample.to_light;
+        let h = normalize(v + l);
+        let n_dot_l = max(dot(normal, l), 0.0);
+        let n_dot_h = max(dot(normal, h), 0.0);
+        let fresnel = fresnel_schlick(max(dot(h, v), 0.0), f0);
+        let specular = distribution_ggx(n_dot_h, roughness) * geometry_smith(n_dot_v, n_dot_l, roughness) * fresnel
+            / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+        let diffuse = (1.0 - fresnel) * diffuse_color / PI;
+        // the radiance is scaled so a white Lambert surface facing the light comes out as bright
+        // as in the basic mesh shader
+        direct = direct + (diffuse + specular) * radiance * n_dot_l * PI;
+    }
+    var ambient = view.ambient.rgb * (diffuse_color + f0 * (1.0 - roughness) * 0.5) * occlusion;
+    var irradiance = probe_grid_irradiance(in.world_position, normal, environment_diffuse(normal) * view.ambient.w);
+#ifdef LIGHTMAP
+    if (vertex_has_uv2()) {
+        ambient = view.ambient.rgb * f0 * (1.0 - roughness) * 0.5 * occlusion;
+        irradiance = lightmap_irradiance(in.uv2, material.lighting.x);
+    }
+#endif
+    // split-sum approximation: prefiltered radiance times the BRDF's response to f0
+    let brdf = environment_brdf(n_dot_v, roughness);
+    let reflected = reflect(-v, normal);
+    let sky = environment_reflection(reflected, roughness) * view.ambient.w;
+    var specular_light = probe_reflection(in.world_position, reflected, roughness, sky);
+#ifdef PLANAR_REFLECTION
+    // the normal map's tilt away from the surface ripples the mirror image
+    let planar = planar_reflection(in.world_position, (normal - surface_normal).xz * material.lighting.y);
+    specular_light = mix(specular_light, planar.rgb, planar.a);
+#endif
+    let reflection = specular_light * (f0 * brdf.x + brdf.y);
+    let environment = (diffuse_color * irradiance + reflection) * occlusion;
+#ifdef DEBUG_VIEW
+    var texture_size = vec2<f32>(DEBUG_REFERENCE_TEXTURE_SIZE);
+#ifdef ALBEDO_MAP
+    texture_size = vec2<f32>(textureDimensions(albedo_map));
+#endif
+    return debug_output(in.world_position, view.camera_position.xyz, in.uv, texture_size);
+#else
+    return vec4<f32>(direct + ambient + environment + emissive, base_color.a);
+#endif
+}
@@ -0,0 +1,26 @@
+// Mirror and water reflections from a planar reflection texture. The surface point is projected
+// with the mirrored camera, which maps points on the plane onto themselves in the reflection.
+// The bindings follow the light profiles in the lighting group 2.
+
+struct PlanarReflectionParams {
+    view_projection: mat4x4<f32>;
+    // xyz normal, w distance: dot(normal, p) + w is 0 on the plane
+    plane: vec4<f32>;
+};
+
+[[group(2), binding(11)]] var<uniform> planar_reflection_params: PlanarReflectionParams;
+[[group(2), binding(12)]] var planar_reflection_texture: texture_2d<f32>;
+[[group(2), binding(13)]] var planar_reflection_sampler: sampler;
+
+// the reflection in rgb and its coverage in a, 0 where nothing was drawn; `distortion` shifts the
+// lookup in texture space, e.g. a water normal's xz times 0.02
+fn planar_reflection(position: vec3<f32>, distortion: vec2<f32>) -> vec4<f32> {
+    let clip = planar_reflection_params.view_projection * vec4<f32>(position, 1.0);
+    let uv = vec2<f32>(clip.x / clip.w * 0.5 + 0.5, 0.5 - clip.y / clip.w * 0.5) + distortion;
+    let clamped = clamp(uv, vec2<f32>(0.001), vec2<f32>(0.999));
+    return textureSample(planar_reflection_texture, planar_reflection_sampler, clamped);
+}
+
+fn planar_reflection_color(position: vec3<f32>, distortion: vec2<f32>) -> vec3<f32> {
+    return planar_reflection(position, distortion).rgb;
+}
@@ -0,0 +1,16 @@
+// Builds a reflection probe's mip chain one face at a time. Each level is a 4-tap box filter of
+// the one above, a cheap stand-in for prefiltering by roughness.
+
+[[group(0), binding(0)]] var source_sampler: sampler;
+[[group(0), binding(1)]] var source_texture: texture_2d<f32>;
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    let offset = texel * 0.5;
+    let color = textureSample(source_texture, source_sampler, in.uv + vec2<f32>(-offset.x, -offset.y))
+        + textureSample(source_texture, source_sampler, in.uv + vec2<f32>(offset.x, -offset.y))
+        + textureSample(source_texture, source_sampler, in.uv + vec2<f32>(-offset.x, offset.y))
+        + textureSample(source_texture, source_sampler, in.uv + vec2<f32>(offset.x, offset.y));
+    return color * 0.25;
+}
@@ -0,0 +1,91 @@
+// Local specular reflections from reflection probes, blended smallest probe first and falling
+// back to the sky where no probe covers the surface, or the capture shows nothing. The probes
+// bind after the planar reflection in the lighting group 2, six layers of the face array each.
+
+struct ReflectionProbe {
+    // xyz center, w the probe's slot, whose faces start at layer 6 * w
+    position: vec4<f32>;
+    // xyz half extents (the radius for spheres), w blend distance
+    extents: vec4<f32>;
+    // x is 1 for box probes, which get parallax correction
+    shape: vec4<f32>;
+};
+
+struct ReflectionProbeData {
+    // x probe count, y highest mip level
+    header: vec4<u32>;
+    probes: array<ReflectionProbe, 16>;
+};
+
+[[group(2), binding(14)]] var<uniform> reflection_probes: ReflectionProbeData;
+[[group(2), binding(15)]] var reflection_faces: texture_2d_array<f32>;
+[[group(2), binding(16)]] var reflection_sampler: sampler;
+
+fn probe_weight(probe: ReflectionProbe, position: vec3<f32>) -> f32 {
+    let offset = position - probe.position.xyz;
+    var inside: f32;
+    if (probe.shape.x > 0.5) {
+        let distance_to_faces = probe.extents.xyz - abs(offset);
+        inside = min(distance_to_faces.x, min(distance_to_faces.y, distance_to_faces.z));
+    } else {
+        inside = probe.extents.x - length(offset);
+    }
+    return clamp(inside / max(probe.extents.w, 0.0001), 0.0, 1.0);
+}
+
+// intersects the reflection ray with the probe box, so nearby walls line up with the capture
+fn probe_direction(probe: ReflectionProbe, position: vec3<f32>, direction: vec3<f32>) -> vec3<f32> {
+    if (probe.shape.x < 0.5) {
+        return direction;
+    }
+    let box_max = probe.position.xyz + probe.extents.xyz;
+    let box_min = probe.position.xyz - probe.extents.xyz;
+    let far = max((box_max - position) / direction, (box_min - position) / direction);
+    let distance = min(far.x, min(far.y, far.z));
+    return position + direction * distance - probe.position.xyz;
+}
+
+// the face `direction` points through, in +X, -X, +Y, -Y, +Z, -Z order, as z and the texture
+// coordinates on it in xy, from the same forward and up vectors the faces were captured with
+fn probe_face(direction: vec3<f32>) -> vec3<f32> {
+    let axis = abs(direction);
+    var face = 4u;
+    var forward = vec3<f32>(0.0, 0.0, sign(direction.z));
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if (axis.x >= axis.y && axis.x >= axis.z) {
+        face = 0u;
+        forward = vec3<f32>(sign(direction.x), 0.0, 0.0);
+    } else if (axis.y >= axis.z) {
+        face = 2u;
+        forward = vec3<f32>(0.0, sign(direction.y), 0.0);
+        up = vec3<f32>(0.0, 0.0, -forward.y);
+    }
+    // each negative face follows its positive one
+    if (forward.x + forward.y + forward.z < 0.0) {
+        face = face + 1u;
+    }
+    let right = cross(forward, up);
+    let along = max(dot(direction, forward), 0.0001);
+    let uv = vec2<f32>(dot(direction, right), -dot(direction, up)) / along * 0.5 + 0.5;
+    return vec3<f32>(uv, f32(face));
+}
+
+// `sky` is the skybox radiance along `direction` at the same roughness
+fn probe_reflection(position: vec3<f32>, direction: vec3<f32>, roughness: f32, sky: vec3<f32>) -> vec3<f32> {
+    let level = roughness * f32(reflection_probes.header.y);
+    var color = vec3<f32>(0.0);
+    var coverage = 0.0;
+    let count = min(reflection_probes.header.x, 16u);
+    for (var i = 0u; i < count; i = i + 1u) {
+        let probe = reflection_probes.probes[i];
+        let weight = probe_weight(probe, position) * (1.0 - coverage);
+        if (weight > 0.0) {
+            let face = probe_face(probe_direction(probe, position, direction));
+            let layer = i32(probe.position.w) * 6 + i32(face.z);
+            let capture = textureSampleLevel(reflection_faces, reflection_sampler, face.xy, layer, level);
+            color = color + (capture.rgb + sky * (1.0 - capture.a)) * weight;
+            coverage = coverage + weight;
+        }
+    }
+    return color + sky * (1.0 - coverage);
+}
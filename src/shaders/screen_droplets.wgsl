@@ -0,0 +1,68 @@
+// Raindrops on the lens. The screen is split into cells holding at most one drop each; a drop
+// appears, sags down a little and shrinks as it evaporates, then reappears elsewhere in its cell.
+// Inside a drop the scene is sampled mirrored around its center, like through a small lens.
+
+struct Params {
+    aspect: f32;
+    time: f32;
+    coverage: f32;
+    refraction: f32;
+    density: f32;
+    _padding0: f32;
+    _padding1: f32;
+    _padding2: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var input_sampler: sampler;
+[[group(0), binding(2)]] var color_texture: texture_2d<f32>;
+
+fn hash3(p: vec2<f32>) -> vec3<f32> {
+    let q = vec3<f32>(dot(p, vec2<f32>(127.1, 311.7)), dot(p, vec2<f32>(269.5, 183.3)), dot(p, vec2<f32>(419.2, 371.9)));
+    return fract(sin(q) * 43758.5453);
+}
+
+// offset to refract by and how much of a drop edge this is, for one layer of cells
+fn droplet_layer(uv: vec2<f32>, cells: f32, seed: f32) -> vec3<f32> {
+    let p = uv * vec2<f32>(params.aspect, 1.0) * cells;
+    let cell = floor(p);
+    let local = fract(p);
+    let random = hash3(cell + seed);
+    if (random.z > params.coverage) {
+        return vec3<f32>(0.0);
+    }
+
+    let period = 3.0 + 4.0 * random.x;
+    let cycle = params.time / period + random.y;
+    // each cycle the drop picks a new spot
+    let spot = hash3(cell + seed + floor(cycle));
+    let life = fract(cycle);
+    let center = vec2<f32>(0.25 + 0.5 * spot.x, 0.25 + 0.3 * spot.y + 0.2 * life * life);
+    let radius = (0.1 + 0.15 * spot.z) * sqrt(1.0 - life);
+
+    let offset = (local - center) / max(radius, 0.001);
+    let distance = length(offset);
+    if (distance >= 1.0) {
+        return vec3<f32>(0.0);
+    }
+    // fades in quickly so drops don't pop
+    let strength = smoothStep(0.0, 0.05, life);
+    let edge = smoothStep(0.6, 1.0, distance) * strength;
+    return vec3<f32>(-offset * params.refraction * (1.0 - distance * distance) * strength, edge);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    var drop = vec3<f32>(0.0);
+    if (params.coverage > 0.0) {
+        drop = droplet_layer(in.uv, params.density, 0.0);
+        if (all(drop == vec3<f32>(0.0))) {
+            drop = droplet_layer(in.uv, params.density * 2.3, 17.0);
+        }
+    }
+    // offsets are in aspect corrected units
+    let uv = clamp(in.uv + drop.xy * vec2<f32>(1.0 / params.aspect, 1.0), vec2<f32>(0.0), vec2<f32>(1.0));
+    let color = textureSample(color_texture, input_sampler, uv);
+    // the rim catches less light from behind
+    return vec4<f32>(color.rgb * (1.0 - 0.35 * drop.z), color.a);
+}
@@ -0,0 +1,14 @@
+// Mixes the screen towards a flat color by its alpha.
+
+struct Flash {
+    color: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> flash: Flash;
+[[group(0), binding(1)]] var color_texture: texture_2d<f32>;
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureLoad(color_texture, vec2<i32>(in.position.xy), 0);
+    return vec4<f32>(mix(color.rgb, flash.color.rgb, flash.color.a), color.a);
+}
@@ -0,0 +1,19 @@
+// Depth-only pass drawing meshes into a cascade of the directional shadow atlas.
+
+struct ShadowView {
+    view_projection: mat4x4<f32>;
+};
+
+struct Draw {
+    model: mat4x4<f32>;
+    normal_matrix: mat4x4<f32>;
+    color: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> shadow_view: ShadowView;
+[[group(1), binding(0)]] var<uniform> draw: Draw;
+
+[[stage(vertex)]]
+fn vs_main(vertex: MeshVertex, instance: DrawInstance) -> [[builtin(position)]] vec4<f32> {
+    return shadow_view.view_projection * draw.model * instance_model(instance) * vec4<f32>(vertex.position, 1.0);
+}
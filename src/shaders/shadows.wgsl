@@ -0,0 +1,65 @@
+// Cascaded shadow map of one directional light, the cascades packed into tiles of a depth
+// atlas. `directional_shadow` picks the cascade by view depth and filters it with PCF.
+
+struct DirectionalShadowData {
+    cascades: array<mat4x4<f32>, 4>;
+    // uv offset in xy and scale in zw of each cascade's atlas tile
+    atlas_rects: array<vec4<f32>, 4>;
+    // distance from the camera each cascade reaches
+    splits: vec4<f32>;
+    // world size of a texel in each cascade
+    texel_sizes: vec4<f32>;
+    // x cascade count, y index of the shadowed light or -1, z depth bias, w normal bias in texels
+    params: vec4<f32>;
+    camera_forward: vec4<f32>;
+};
+
+[[group(2), binding(0)]] var<uniform> directional_shadows: DirectionalShadowData;
+[[group(2), binding(1)]] var directional_shadow_atlas: texture_depth_2d;
+[[group(2), binding(2)]] var directional_shadow_sampler: sampler_comparison;
+
+// index of the light `directional_shadow` belongs to; no light has it when nothing is shadowed
+fn shadowed_light() -> u32 {
+    if (directional_shadows.params.y < 0.0) {
+        return 0xffffffffu;
+    }
+    return u32(directional_shadows.params.y);
+}
+
+// 1 where lit; a 3x3 PCF kernel on top of the hardware comparison filtering
+fn directional_shadow(position: vec3<f32>, normal: vec3<f32>, camera_position: vec3<f32>) -> f32 {
+    let count = u32(directional_shadows.params.x);
+    let depth = dot(position - camera_position, directional_shadows.camera_forward.xyz);
+    var cascade = 0u;
+    loop {
+        if (cascade >= count || depth <= directional_shadows.splits[cascade]) {
+            break;
+        }
+        cascade = cascade + 1u;
+    }
+    if (cascade >= count) {
+        return 1.0;
+    }
+    let biased = position + normal * directional_shadows.params.w * directional_shadows.texel_sizes[cascade];
+    let clip = directional_shadows.cascades[cascade] * vec4<f32>(biased, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let local_uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if (any(local_uv < vec2<f32>(0.0)) || any(local_uv > vec2<f32>(1.0)) || ndc.z > 1.0) {
+        return 1.0;
+    }
+    let rect = directional_shadows.atlas_rects[cascade];
+    let texel = 1.0 / vec2<f32>(textureDimensions(directional_shadow_atlas));
+    // keep taps inside the tile so neighbouring cascades don't bleed in
+    let low = rect.xy + texel;
+    let high = rect.xy + rect.zw - texel;
+    let uv = rect.xy + local_uv * rect.zw;
+    let reference = ndc.z - directional_shadows.params.z;
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit = lit + textureSampleCompareLevel(directional_shadow_atlas, directional_shadow_sampler, clamp(uv + offset, low, high), reference);
+        }
+    }
+    return lit / 9.0;
+}
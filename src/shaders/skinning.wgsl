@@ -0,0 +1,69 @@
+// Linear blend skinning. Reads packed SkinnedVertex data, writes SkinnedOutputVertex data that
+// is bound as a regular vertex buffer by the mesh pipelines.
+
+struct Params {
+    vertex_count: u32;
+    joint_count: u32;
+};
+
+struct Words {
+    data: array<u32>;
+};
+
+struct Floats {
+    data: array<f32>;
+};
+
+struct Palette {
+    joints: array<mat4x4<f32>>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var<storage, read> input: Words;
+[[group(0), binding(2)]] var<storage, read> palette: Palette;
+[[group(0), binding(3)]] var<storage, read_write> output: Floats;
+
+// must match SkinnedVertex / SkinnedOutputVertex in skinning.rs
+let INPUT_STRIDE: u32 = 16u;
+let OUTPUT_STRIDE: u32 = 8u;
+
+fn read_f32(index: u32) -> f32 {
+    return bitcast<f32>(input.data[index]);
+}
+
+fn joint_matrix(joint: u32) -> mat4x4<f32> {
+    return palette.joints[min(joint, params.joint_count - 1u)];
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let v = id.x;
+    if (v >= params.vertex_count) {
+        return;
+    }
+
+    let base = v * INPUT_STRIDE;
+    let position = vec4<f32>(read_f32(base), read_f32(base + 1u), read_f32(base + 2u), 1.0);
+    let normal = vec4<f32>(read_f32(base + 3u), read_f32(base + 4u), read_f32(base + 5u), 0.0);
+    let uv = vec2<f32>(read_f32(base + 6u), read_f32(base + 7u));
+    let joints = vec4<u32>(input.data[base + 8u], input.data[base + 9u], input.data[base + 10u], input.data[base + 11u]);
+    let weights = vec4<f32>(read_f32(base + 12u), read_f32(base + 13u), read_f32(base + 14u), read_f32(base + 15u));
+
+    let skin = joint_matrix(joints.x) * weights.x
+        + joint_matrix(joints.y) * weights.y
+        + joint_matrix(joints.z) * weights.z
+        + joint_matrix(joints.w) * weights.w;
+
+    let skinned_position = skin * position;
+    let skinned_normal = normalize((skin * normal).xyz);
+
+    let out = v * OUTPUT_STRIDE;
+    output.data[out] = skinned_position.x;
+    output.data[out + 1u] = skinned_position.y;
+    output.data[out + 2u] = skinned_position.z;
+    output.data[out + 3u] = skinned_normal.x;
+    output.data[out + 4u] = skinned_normal.y;
+    output.data[out + 5u] = skinned_normal.z;
+    output.data[out + 6u] = uv.x;
+    output.data[out + 7u] = uv.y;
+}
@@ -0,0 +1,84 @@
+// Preetham sky: the Perez distribution scales the zenith's luminance and chromaticity for every
+// view direction, then xyY goes to linear sRGB. Adds the sun disk above the horizon and sunlit
+// ground below it.
+
+struct Params {
+    inverse_view_projection: mat4x4<f32>;
+    // direction towards the sun, and its zenith angle in w
+    sun_direction: vec4<f32>;
+    sun_color: vec4<f32>;
+    ground: vec4<f32>;
+    night: vec4<f32>;
+    // coefficients A to E, each for Y, x and y
+    perez: array<vec4<f32>, 5>;
+    // Y, x and y at the zenith
+    zenith: vec4<f32>;
+    sun_size: f32;
+    sky_intensity: f32;
+    twilight: f32;
+    padding: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+
+let PI: f32 = 3.14159265;
+// the disk is drawn this much brighter than the light it casts, enough to bloom
+let SUN_DISK_BRIGHTNESS: f32 = 50.0;
+
+fn perez(cos_theta: f32, gamma: f32, cos_gamma: f32) -> vec3<f32> {
+    let a = params.perez[0].xyz;
+    let b = params.perez[1].xyz;
+    let c = params.perez[2].xyz;
+    let d = params.perez[3].xyz;
+    let e = params.perez[4].xyz;
+    return (1.0 + a * exp(b / cos_theta)) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn sky(ray: vec3<f32>, sun: vec3<f32>) -> vec3<f32> {
+    let cos_gamma = clamp(dot(ray, sun), -1.0, 1.0);
+    let gamma = acos(cos_gamma);
+    // the distribution blows up at the horizon, so hold its value just above it
+    let cos_theta = max(ray.y, 0.01);
+    let theta_s = params.sun_direction.w;
+    let relative = perez(cos_theta, gamma, cos_gamma) / perez(1.0, theta_s, cos(theta_s));
+    let luminance = params.zenith.x * relative.x;
+    let chromaticity = vec2<f32>(params.zenith.y * relative.y, max(params.zenith.z * relative.z, 0.0001));
+    let xyz = vec3<f32>(
+        chromaticity.x / chromaticity.y * luminance,
+        luminance,
+        (1.0 - chromaticity.x - chromaticity.y) / chromaticity.y * luminance,
+    );
+    let rgb = mat3x3<f32>(
+        vec3<f32>(3.2406, -0.9689, 0.0557),
+        vec3<f32>(-1.5372, 1.8758, -0.2040),
+        vec3<f32>(-0.4986, 0.0415, 1.0570),
+    ) * xyz;
+    return max(rgb, vec3<f32>(0.0)) * params.sky_intensity * params.twilight;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let ndc = vec2<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    let near = params.inverse_view_projection * vec4<f32>(ndc, 0.0, 1.0);
+    let far = params.inverse_view_projection * vec4<f32>(ndc, 1.0, 1.0);
+    let ray = normalize(far.xyz / far.w - near.xyz / near.w);
+    let sun = params.sun_direction.xyz;
+
+    var color = sky(ray, sun) + params.night.rgb;
+
+    let gamma = acos(clamp(dot(ray, sun), -1.0, 1.0));
+    if (gamma < params.sun_size && ray.y > 0.0) {
+        // limb darkening
+        let u = gamma / params.sun_size;
+        let limb = 1.0 - 0.6 * (1.0 - sqrt(1.0 - u * u));
+        let edge = clamp((1.0 - u) * 10.0, 0.0, 1.0);
+        color = color + params.sun_color.rgb * SUN_DISK_BRIGHTNESS * limb * edge;
+    }
+
+    // diffuse ground lit by the sun and the sky overhead, fading in from the horizon haze
+    let ambient = params.zenith.x * params.sky_intensity * params.twilight + params.night.rgb;
+    let ground = params.ground.rgb * (params.sun_color.rgb * max(sun.y, 0.0) / PI + ambient);
+    let below = clamp(-ray.y * 20.0, 0.0, 1.0);
+    color = mix(color, ground, below * below * (3.0 - 2.0 * below));
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,21 @@
+// Draws the environment map behind everything, looked up along each pixel's view ray.
+
+struct SkyboxParams {
+    inverse_view_projection: mat4x4<f32>;
+    // x intensity
+    intensity: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: SkyboxParams;
+[[group(0), binding(1)]] var environment: texture_cube<f32>;
+[[group(0), binding(2)]] var environment_sampler: sampler;
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let ndc = vec2<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    let near = params.inverse_view_projection * vec4<f32>(ndc, 0.0, 1.0);
+    let far = params.inverse_view_projection * vec4<f32>(ndc, 1.0, 1.0);
+    let ray = normalize(far.xyz / far.w - near.xyz / near.w);
+    let color = textureSampleLevel(environment, environment_sampler, ray, 0.0).rgb;
+    return vec4<f32>(color * params.intensity.x, 1.0);
+}
@@ -0,0 +1,66 @@
+// Instanced sprite quads. World sprites are camera-facing quads sized in world units; screen
+// sprites are anchored at a world point but sized and offset in pixels, so they stay the same
+// size at any distance.
+
+struct View {
+    view_projection: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
+    // xy: target size in pixels
+    viewport: vec4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> view: View;
+[[group(1), binding(0)]] var sprite_sampler: sampler;
+[[group(1), binding(1)]] var sprite_texture: texture_2d<f32>;
+
+struct Instance {
+    [[location(0)]] center: vec3<f32>;
+    [[location(1)]] space: u32;
+    [[location(2)]] offset: vec2<f32>;
+    [[location(3)]] size: vec2<f32>;
+    [[location(4)]] uv_min: vec2<f32>;
+    [[location(5)]] uv_max: vec2<f32>;
+    [[location(6)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+// must match SpriteSpace in batch.rs
+let SPACE_SCREEN: u32 = 1u;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32, instance: Instance) -> VertexOutput {
+    // triangle strip corners, y up
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+    let local = instance.offset + (corner - 0.5) * instance.size;
+
+    var out: VertexOutput;
+    if (instance.space == SPACE_SCREEN) {
+        out.position = view.view_projection * vec4<f32>(instance.center, 1.0);
+        let pixels = 2.0 / view.viewport.xy;
+        out.position = vec4<f32>(out.position.xy + local * pixels * out.position.w, out.position.zw);
+    } else {
+        let position = instance.center + view.right.xyz * local.x + view.up.xyz * local.y;
+        out.position = view.view_projection * vec4<f32>(position, 1.0);
+    }
+    out.uv = vec2<f32>(
+        mix(instance.uv_min.x, instance.uv_max.x, corner.x),
+        mix(instance.uv_min.y, instance.uv_max.y, 1.0 - corner.y),
+    );
+    out.color = instance.color;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(sprite_texture, sprite_sampler, in.uv) * in.color;
+    if (color.a <= 0.0) {
+        discard;
+    }
+    return color;
+}
@@ -0,0 +1,67 @@
+// Maps the HDR scene to display range: the metered and manual exposure, then the operator's
+// curve. Targets without sRGB encoding get the encoding done here.
+
+struct Params {
+    // 0 ACES, 1 Reinhard, 2 clamp
+    operator: u32;
+    // linear multiplier, from the exposure in stops
+    exposure: f32;
+    // the brightness Reinhard maps to white
+    white_point: f32;
+    _padding: f32;
+};
+
+struct MeteredExposure {
+    luminance: f32;
+    exposure: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var hdr: texture_2d<f32>;
+// from auto exposure, or an exposure of 1 without it
+[[group(0), binding(2)]] var<uniform> metered: MeteredExposure;
+
+// Narkowicz's fit of the ACES reference rendering transform
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = color * (2.51 * color + 0.03);
+    let b = color * (2.43 * color + 0.59) + 0.14;
+    return clamp(a / b, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// extended Reinhard on luminance, so saturated colors keep their hue
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    if (luminance <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+    let white = params.white_point * params.white_point;
+    let mapped = luminance * (1.0 + luminance / white) / (1.0 + luminance);
+    return clamp(color * (mapped / luminance), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn srgb_from_linear(linear: vec3<f32>) -> vec3<f32> {
+    let lower = linear * 12.92;
+    let higher = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(higher, lower, linear <= vec3<f32>(0.0031308));
+}
+
+fn tonemap(position: vec4<f32>) -> vec3<f32> {
+    let color = max(textureLoad(hdr, vec2<i32>(position.xy), 0).rgb * params.exposure * metered.exposure, vec3<f32>(0.0));
+    if (params.operator == 0u) {
+        return aces(color);
+    }
+    if (params.operator == 1u) {
+        return reinhard(color);
+    }
+    return clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+[[stage(fragment)]]
+fn fs_linear(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(tonemap(in.position), 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_encode(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(srgb_from_linear(tonemap(in.position)), 1.0);
+}
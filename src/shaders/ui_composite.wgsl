@@ -0,0 +1,29 @@
+// Draws the UI layer over the finished frame. The layer is sRGB, so loads come back linear with
+// alpha premultiplied, blended with (1, 1 - alpha). Targets without sRGB encoding get the
+// encoding done here, on the unpremultiplied color so edges don't darken.
+
+[[group(0), binding(0)]] var layer: texture_2d<f32>;
+
+fn srgb_from_linear(linear: vec3<f32>) -> vec3<f32> {
+    let lower = linear * 12.92;
+    let higher = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(higher, lower, linear <= vec3<f32>(0.0031308));
+}
+
+fn load_layer(position: vec4<f32>) -> vec4<f32> {
+    return textureLoad(layer, vec2<i32>(position.xy), 0);
+}
+
+[[stage(fragment)]]
+fn fs_linear(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    return load_layer(in.position);
+}
+
+[[stage(fragment)]]
+fn fs_encode(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let color = load_layer(in.position);
+    if (color.a <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>(srgb_from_linear(color.rgb / color.a) * color.a, color.a);
+}
@@ -0,0 +1,42 @@
+// Upscales the internal-resolution scene to the output. `fs_bilinear` just filters;
+// `fs_sharpen` follows it with FSR1-style robust contrast-adaptive sharpening (RCAS), which
+// sharpens less where it would clip or ring.
+
+struct Params {
+    output_texel: vec2<f32>;
+    sharpness: f32;
+    _padding: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var input_sampler: sampler;
+[[group(0), binding(2)]] var scene_texture: texture_2d<f32>;
+
+[[stage(fragment)]]
+fn fs_bilinear(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(scene_texture, input_sampler, in.uv);
+}
+
+// the lobe strength RCAS never exceeds, to stay out of ringing
+let RCAS_LIMIT: f32 = 0.1875;
+
+[[stage(fragment)]]
+fn fs_sharpen(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let texel = params.output_texel;
+    let center = textureSample(scene_texture, input_sampler, in.uv);
+    let up = textureSample(scene_texture, input_sampler, in.uv - vec2<f32>(0.0, texel.y)).rgb;
+    let left = textureSample(scene_texture, input_sampler, in.uv - vec2<f32>(texel.x, 0.0)).rgb;
+    let right = textureSample(scene_texture, input_sampler, in.uv + vec2<f32>(texel.x, 0.0)).rgb;
+    let down = textureSample(scene_texture, input_sampler, in.uv + vec2<f32>(0.0, texel.y)).rgb;
+
+    let lowest = min(min(up, down), min(left, min(right, center.rgb)));
+    let highest = max(max(up, down), max(left, max(right, center.rgb)));
+    // largest negative lobe that keeps the result inside the neighbourhood's range
+    let hit_min = lowest / (4.0 * max(highest, vec3<f32>(0.0001)));
+    let hit_max = (vec3<f32>(1.0) - highest) / min(4.0 * lowest - vec3<f32>(4.0), vec3<f32>(-0.0001));
+    let per_channel = max(-hit_min, hit_max);
+    let lobe = max(-RCAS_LIMIT, min(max(per_channel.r, max(per_channel.g, per_channel.b)), 0.0)) * params.sharpness;
+
+    let sharpened = (lobe * (up + down + left + right) + center.rgb) / (4.0 * lobe + 1.0);
+    return vec4<f32>(sharpened, center.a);
+}
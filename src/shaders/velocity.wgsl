@@ -0,0 +1,30 @@
+// Screen-space velocity from camera motion: reconstructs each pixel's world position from depth
+// and reprojects it with last frame's view-projection. Moving objects overwrite their pixels
+// with their own velocities afterwards.
+
+struct Params {
+    inverse_view_projection: mat4x4<f32>;
+    previous_view_projection: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var depth_texture: texture_depth_2d;
+
+fn uv_to_ndc(uv: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+}
+
+fn ndc_to_uv(ndc: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+// velocity in uv units per frame: current position minus previous position
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(depth_texture);
+    let depth = textureLoad(depth_texture, vec2<i32>(in.uv * vec2<f32>(size)), 0);
+    let world = params.inverse_view_projection * vec4<f32>(uv_to_ndc(in.uv), depth, 1.0);
+    let previous_clip = params.previous_view_projection * vec4<f32>(world.xyz / world.w, 1.0);
+    let previous_uv = ndc_to_uv(previous_clip.xy / previous_clip.w);
+    return vec4<f32>(in.uv - previous_uv, 0.0, 0.0);
+}
@@ -0,0 +1,20 @@
+// Clears one camera's viewport inside a shared target. The color comes from the blend constant
+// and depth is reset to the far plane, so neighbouring viewports are left alone.
+
+struct ClearOutput {
+    [[location(0)]] color: vec4<f32>;
+    [[builtin(frag_depth)]] depth: f32;
+};
+
+[[stage(fragment)]]
+fn fs_clear(in: FullscreenOutput) -> ClearOutput {
+    var out: ClearOutput;
+    out.color = vec4<f32>(1.0);
+    out.depth = 1.0;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_clear_color(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(1.0);
+}
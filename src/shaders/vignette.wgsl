@@ -0,0 +1,26 @@
+// Darkens the image towards its corners, on the HDR scene like the lens falloff it imitates.
+
+struct Params {
+    color: vec4<f32>;
+    intensity: f32;
+    // distance from the center where darkening starts, 1 being the middle of an edge
+    radius: f32;
+    smoothness: f32;
+    // 1 keeps the falloff round on wide screens, 0 stretches it to the screen's shape
+    roundness: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var color_texture: texture_2d<f32>;
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let size = vec2<f32>(textureDimensions(color_texture));
+    let color = textureLoad(color_texture, vec2<i32>(in.position.xy), 0);
+    let aspect = mix(1.0, size.x / size.y, params.roundness);
+    let centered = (in.uv * 2.0 - 1.0) * vec2<f32>(aspect, 1.0);
+    let t = clamp((length(centered) - params.radius) / max(params.smoothness, 0.0001), 0.0, 1.0);
+    let falloff = t * t * (3.0 - 2.0 * t);
+    let amount = falloff * params.intensity;
+    return vec4<f32>(mix(color.rgb, color.rgb * params.color.rgb, amount), color.a);
+}
@@ -0,0 +1,39 @@
+// Visibility buffer rasterization: every visible cluster draws MAX_TRIANGLES triangles, the
+// ones past its triangle count collapse to nothing. Pixels store which triangle covers them,
+// plus one so that 0 means empty.
+
+struct VisibleClusters {
+    data: array<u32>;
+};
+
+[[group(1), binding(0)]] var<storage, read> visible: VisibleClusters;
+
+struct RasterOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0), interpolate(flat)]] id: u32;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> RasterOutput {
+    var out: RasterOutput;
+    let slot = vertex_index / (MAX_TRIANGLES * 3u);
+    let triangle = (vertex_index / 3u) % MAX_TRIANGLES;
+    let cluster_index = visible.data[slot];
+    let cluster = clusters.data[cluster_index];
+    let meshlet = meshlets.data[cluster.y];
+    // slot in the top 25 bits, triangle in the low 7
+    out.id = ((slot << 7u) | triangle) + 1u;
+    if (triangle >= meshlet.triangle_count) {
+        out.position = vec4<f32>(0.0);
+        return out;
+    }
+
+    let vertex = vertices.data[triangle_vertex(meshlet, triangle, vertex_index % 3u)];
+    out.position = params.view_projection * (instances.data[cluster.x].model * vertex.position);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: RasterOutput) -> [[location(0)]] u32 {
+    return in.id;
+}
@@ -0,0 +1,68 @@
+// Visibility buffer resolve: refetches the triangle under each pixel, intersects the pixel's view
+// ray with it for barycentrics and shades the interpolated normal. Empty pixels are discarded so
+// whatever was drawn before, such as the sky, stays; the rest write the visibility depth, which
+// is tested against the scene's.
+
+struct VisibleClusters {
+    data: array<u32>;
+};
+
+[[group(1), binding(0)]] var<storage, read> visible: VisibleClusters;
+[[group(1), binding(1)]] var visibility: texture_2d<u32>;
+[[group(1), binding(2)]] var visibility_depth: texture_depth_2d;
+
+struct ResolveOutput {
+    [[location(0)]] color: vec4<f32>;
+    [[builtin(frag_depth)]] depth: f32;
+};
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> ResolveOutput {
+    let pixel = vec2<i32>(in.position.xy);
+    let id = textureLoad(visibility, pixel, 0).r;
+    if (id == 0u) {
+        discard;
+    }
+    let slot = (id - 1u) >> 7u;
+    let triangle = (id - 1u) & 127u;
+    let cluster_index = visible.data[slot];
+    let cluster = clusters.data[cluster_index];
+    let meshlet = meshlets.data[cluster.y];
+    let instance = instances.data[cluster.x];
+
+    let v0 = vertices.data[triangle_vertex(meshlet, triangle, 0u)];
+    let v1 = vertices.data[triangle_vertex(meshlet, triangle, 1u)];
+    let v2 = vertices.data[triangle_vertex(meshlet, triangle, 2u)];
+    let p0 = (instance.model * v0.position).xyz;
+    let p1 = (instance.model * v1.position).xyz;
+    let p2 = (instance.model * v2.position).xyz;
+
+    let ndc = vec2<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    let near = params.inverse_view_projection * vec4<f32>(ndc, 0.0, 1.0);
+    let far = params.inverse_view_projection * vec4<f32>(ndc, 1.0, 1.0);
+    let origin = near.xyz / near.w;
+    let direction = normalize(far.xyz / far.w - origin);
+
+    // Moller-Trumbore, keeping only the barycentrics
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let p = cross(direction, edge2);
+    let determinant = dot(edge1, p);
+    var barycentrics = vec3<f32>(1.0 / 3.0);
+    if (abs(determinant) > 0.0000001) {
+        let t = origin - p0;
+        let u = dot(t, p) / determinant;
+        let v = dot(direction, cross(t, edge1)) / determinant;
+        barycentrics = vec3<f32>(1.0 - u - v, u, v);
+    }
+
+    let model = mat3x3<f32>(instance.model[0].xyz, instance.model[1].xyz, instance.model[2].xyz);
+    let local_normal = v0.normal.xyz * barycentrics.x + v1.normal.xyz * barycentrics.y + v2.normal.xyz * barycentrics.z;
+    let normal = normalize(model * local_normal);
+    let diffuse = max(dot(normal, -params.light_direction.xyz), 0.0);
+    let color = instance.color.rgb * (params.light_color.rgb * diffuse + params.ambient.rgb);
+    var out: ResolveOutput;
+    out.color = vec4<f32>(color, instance.color.a);
+    out.depth = textureLoad(visibility_depth, pixel, 0);
+    return out;
+}
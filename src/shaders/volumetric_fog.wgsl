@@ -0,0 +1,85 @@
+// Froxel volumetric fog. `inject` evaluates fog density and in-scattered light for every cell of
+// a view-aligned grid whose slices are spaced exponentially in depth; `integrate` then marches
+// each column front to back so every cell holds the light scattered towards the camera and the
+// transmittance up to that depth.
+
+struct Params {
+    inverse_view_projection: mat4x4<f32>;
+    camera_position: vec4<f32>;
+    camera_forward: vec4<f32>;
+    // direction the light travels in, and its color times intensity
+    light_direction: vec4<f32>;
+    light_color: vec4<f32>;
+    ambient: vec4<f32>;
+    grid: vec4<f32>;
+    near: f32;
+    far: f32;
+    distance: f32;
+    density: f32;
+    height_falloff: f32;
+    base_height: f32;
+    anisotropy: f32;
+    shaft_strength: f32;
+    sun_uv: vec2<f32>;
+    sun_visibility: f32;
+    shaft_samples: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var injected_output: texture_storage_3d<rgba16float, write>;
+[[group(0), binding(2)]] var injected: texture_3d<f32>;
+[[group(0), binding(3)]] var integrated_output: texture_storage_3d<rgba16float, write>;
+
+let PI: f32 = 3.14159265;
+
+// view distance of the near edge of slice `slice`
+fn slice_distance(slice: f32) -> f32 {
+    return params.near * pow(params.distance / params.near, slice / params.grid.z);
+}
+
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denominator * sqrt(denominator));
+}
+
+[[stage(compute), workgroup_size(4, 4, 4)]]
+fn inject([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let cell = vec3<f32>(id);
+    if (any(cell >= params.grid.xyz)) {
+        return;
+    }
+
+    let uv = (cell.xy + 0.5) / params.grid.xy;
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let far_point = params.inverse_view_projection * vec4<f32>(ndc, 1.0, 1.0);
+    let ray = normalize(far_point.xyz / far_point.w - params.camera_position.xyz);
+    let distance = slice_distance(cell.z + 0.5);
+    let position = params.camera_position.xyz + ray * (distance / dot(ray, params.camera_forward.xyz));
+
+    let density = params.density * exp(-params.height_falloff * max(position.y - params.base_height, 0.0));
+    let phase = henyey_greenstein(dot(ray, -params.light_direction.xyz), params.anisotropy);
+    let light = params.light_color.rgb * phase + params.ambient.rgb / (4.0 * PI);
+    // scattering albedo of 1: fog absorbs nothing, so extinction equals scattering
+    textureStore(injected_output, vec3<i32>(id), vec4<f32>(light * density, density));
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn integrate([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (any(vec2<f32>(id.xy) >= params.grid.xy)) {
+        return;
+    }
+
+    var scattered = vec3<f32>(0.0);
+    var transmittance = 1.0;
+    let slices = i32(params.grid.z);
+    for (var z = 0; z < slices; z = z + 1) {
+        let cell = textureLoad(injected, vec3<i32>(vec2<i32>(id.xy), z), 0);
+        let thickness = slice_distance(f32(z + 1)) - slice_distance(f32(z));
+        let extinction = max(cell.a, 0.000001);
+        let slice_transmittance = exp(-extinction * thickness);
+        // energy-conserving integration of constant scattering over the slice (Hillaire 2015)
+        scattered = scattered + transmittance * (cell.rgb - cell.rgb * slice_transmittance) / extinction;
+        transmittance = transmittance * slice_transmittance;
+        textureStore(integrated_output, vec3<i32>(vec2<i32>(id.xy), z), vec4<f32>(scattered, transmittance));
+    }
+}
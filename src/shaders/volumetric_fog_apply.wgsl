@@ -0,0 +1,65 @@
+// Applies the integrated froxel volume to the HDR scene. Light shafts come from marching the
+// depth buffer towards the sun: pixels whose path to the sun crosses geometry get less of the
+// directional scattering.
+
+struct Params {
+    inverse_view_projection: mat4x4<f32>;
+    camera_position: vec4<f32>;
+    camera_forward: vec4<f32>;
+    light_direction: vec4<f32>;
+    light_color: vec4<f32>;
+    ambient: vec4<f32>;
+    grid: vec4<f32>;
+    near: f32;
+    far: f32;
+    distance: f32;
+    density: f32;
+    height_falloff: f32;
+    base_height: f32;
+    anisotropy: f32;
+    shaft_strength: f32;
+    sun_uv: vec2<f32>;
+    sun_visibility: f32;
+    shaft_samples: f32;
+};
+
+[[group(0), binding(0)]] var<uniform> params: Params;
+[[group(0), binding(1)]] var input_sampler: sampler;
+[[group(0), binding(2)]] var color_texture: texture_2d<f32>;
+[[group(0), binding(3)]] var depth_texture: texture_depth_2d;
+[[group(0), binding(4)]] var fog_volume: texture_3d<f32>;
+
+fn linear_depth(depth: f32) -> f32 {
+    return params.near * params.far / (params.far - depth * (params.far - params.near));
+}
+
+fn sky_visibility(uv: vec2<f32>, size: vec2<i32>) -> f32 {
+    let samples = i32(params.shaft_samples);
+    if (samples == 0 || params.sun_visibility <= 0.0) {
+        return 1.0;
+    }
+    let step = (params.sun_uv - uv) / f32(samples);
+    var lit = 0.0;
+    for (var i = 0; i < samples; i = i + 1) {
+        let sample_uv = clamp(uv + step * (f32(i) + 0.5), vec2<f32>(0.0), vec2<f32>(0.999));
+        if (textureLoad(depth_texture, vec2<i32>(sample_uv * vec2<f32>(size)), 0) >= 1.0) {
+            lit = lit + 1.0;
+        }
+    }
+    return mix(1.0, lit / f32(samples), params.shaft_strength * params.sun_visibility);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: FullscreenOutput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(depth_texture);
+    let depth = textureLoad(depth_texture, vec2<i32>(in.uv * vec2<f32>(size)), 0);
+    let distance = min(linear_depth(depth), params.distance);
+    // slices store the integral up to their far edge, which is half a texel past their center
+    let slice = log(max(distance, params.near) / params.near) / log(params.distance / params.near);
+    let w = slice - 0.5 / params.grid.z;
+    let fog = textureSample(fog_volume, input_sampler, vec3<f32>(in.uv, clamp(w, 0.0, 1.0)));
+
+    let color = textureSample(color_texture, input_sampler, in.uv);
+    let scattered = fog.rgb * sky_visibility(in.uv, size);
+    return vec4<f32>(color.rgb * fog.a + scattered, color.a);
+}
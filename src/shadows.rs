@@ -0,0 +1,418 @@
+use bytemuck::Zeroable;
+use glam::{Mat4, Vec3};
+
+use crate::{
+    camera::{Camera, Projection},
+    local_shadows::{AtlasTile, ShadowAtlas},
+    postprocess::contact_shadows::ContactShadowSettings,
+    render_layers::{LightLayers, RenderLayers},
+};
+
+/// WGSL for lit shaders: `directional_shadow(position, normal, camera_position)` and
+/// `shadowed_light()`, with bindings 0 to 2 of `SHADOW_GROUP`.
+pub const SHADOW_SHADER: &str = include_str!("shaders/shadows.wgsl");
+
+/// Bind group index `SHADOW_SHADER` declares its bindings at.
+pub const SHADOW_GROUP: u32 = 2;
+
+/// Cascades a directional light can have.
+pub const MAX_CASCADES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Size of each cascade's square shadow map, in pixels.
+    pub resolution: u32,
+    /// 1 to `MAX_CASCADES`. Each covers a slice of the view further out than the last, at the
+    /// same resolution.
+    pub cascades: u32,
+    /// How far from the camera shadows reach.
+    pub max_distance: f32,
+    /// How the slices are split: 0 evenly, 1 logarithmically, which gives near slices more
+    /// detail.
+    pub split_lambda: f32,
+    /// Moves receivers towards the light before the depth test, in depth units.
+    pub depth_bias: f32,
+    /// Moves receivers along their normal before the depth test, in shadow map texels.
+    pub normal_bias: f32,
+    /// How far behind a cascade casters are still drawn, for tall objects outside the view.
+    pub caster_distance: f32,
+    /// Objects on these layers cast shadows.
+    pub casters: RenderLayers,
+    /// Screen-space shadows from the sun on top of the shadow maps, for the contact the maps
+    /// are too coarse for.
+    pub contact: ContactShadowSettings,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            enabled: true,
+            resolution: 1024,
+            cascades: 3,
+            max_distance: 60.0,
+            split_lambda: 0.7,
+            depth_bias: 0.0005,
+            normal_bias: 1.5,
+            caster_distance: 50.0,
+            casters: LightLayers::default().shadow_casters,
+            // off until asked for, like the other screen-space effects
+            contact: ContactShadowSettings {
+                enabled: false,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl ShadowSettings {
+    fn cascade_count(&self) -> usize {
+        self.cascades.clamp(1, MAX_CASCADES as u32) as usize
+    }
+
+    /// Side of the atlas the cascades are packed into.
+    pub fn atlas_size(&self) -> u32 {
+        let resolution = self.resolution.max(1).next_power_of_two();
+        if self.cascade_count() > 1 {
+            resolution * 2
+        } else {
+            resolution
+        }
+    }
+
+    /// Far distance of each cascade from the camera, along its view direction.
+    pub fn splits(&self, near: f32) -> Vec<f32> {
+        let count = self.cascade_count();
+        let far = self.max_distance.max(near * 2.0);
+        (1..=count)
+            .map(|i| {
+                let fraction = i as f32 / count as f32;
+                let logarithmic = near * (far / near).powf(fraction);
+                let uniform = near + (far - near) * fraction;
+                uniform + (logarithmic - uniform) * self.split_lambda.clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// One cascade of a frame: the light's view-projection covering a slice of the camera's view.
+#[derive(Clone, Copy, Debug)]
+pub struct Cascade {
+    pub view_projection: Mat4,
+    pub tile: AtlasTile,
+    /// Distance from the camera the cascade reaches.
+    pub far: f32,
+    /// World size of one shadow map texel.
+    pub texel_size: f32,
+}
+
+/// Fits an orthographic view along `direction` around the slice of `camera`'s view between
+/// `near` and `far`. The view is a sphere around the slice, snapped to whole texels, so the
+/// shadow doesn't shimmer as the camera turns or moves.
+fn fit_cascade(camera: &Camera, aspect: f32, direction: Vec3, near: f32, far: f32, resolution: u32, caster_distance: f32) -> (Mat4, f32) {
+    let (forward, right, up) = camera.basis();
+    let half_extents = |distance: f32| match camera.projection {
+        Projection::Perspective { fov_y, .. } => {
+            let half_height = (fov_y * 0.5).tan() * distance;
+            (half_height * aspect, half_height)
+        }
+        Projection::Orthographic { height, .. } => (height * 0.5 * aspect, height * 0.5),
+    };
+    let mut corners = Vec::with_capacity(8);
+    for distance in [near, far] {
+        let (half_width, half_height) = half_extents(distance);
+        let center = camera.position + forward * distance;
+        for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            corners.push(center + right * half_width * x + up * half_height * y);
+        }
+    }
+    let center = corners.iter().fold(Vec3::ZERO, |sum, &corner| sum + corner) / corners.len() as f32;
+    let radius = corners.iter().map(|corner| corner.distance(center)).fold(0.0f32, f32::max);
+    // rounded up so the texel size stays put while the camera turns
+    let radius = (radius * 16.0).ceil() / 16.0;
+    let texel_size = radius * 2.0 / resolution as f32;
+
+    let direction = direction.normalize_or_zero();
+    let light_up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+    let light_rotation = Mat4::look_at_rh(Vec3::ZERO, direction, light_up);
+    let local = light_rotation.transform_point3(center);
+    let snapped = Vec3::new(
+        (local.x / texel_size).floor() * texel_size,
+        (local.y / texel_size).floor() * texel_size,
+        local.z,
+    );
+    let center = light_rotation.inverse().transform_point3(snapped);
+
+    let eye = center - direction * (radius + caster_distance);
+    let view = Mat4::look_at_rh(eye, center, light_up);
+    let projection = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, radius * 2.0 + caster_distance);
+    (projection * view, texel_size)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    cascades: [[[f32; 4]; 4]; MAX_CASCADES],
+    atlas_rects: [[f32; 4]; MAX_CASCADES],
+    splits: [f32; 4],
+    texel_sizes: [f32; 4],
+    // cascade count, light index, depth bias, normal bias
+    params: [f32; 4],
+    camera_forward: [f32; 4],
+}
+
+/// Cascaded shadow maps for one directional light, packed into a depth atlas. `update` fits
+/// the cascades to the camera each frame and the renderer draws the casters into them;
+/// `SHADOW_SHADER` samples them with PCF.
+pub struct DirectionalShadows {
+    pub settings: ShadowSettings,
+    atlas_size: u32,
+    atlas_view: wgpu::TextureView,
+    uniform: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    cascades: Vec<Cascade>,
+}
+
+impl DirectionalShadows {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    pub fn new(device: &wgpu::Device, settings: ShadowSettings) -> Self {
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("directional shadow data"),
+            size: std::mem::size_of::<ShadowUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("directional shadow sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("directional shadow layout"),
+            entries: &Self::layout_entries(),
+        });
+        let atlas_size = settings.atlas_size();
+        let atlas_view = Self::create_atlas(device, atlas_size);
+        let bind_group = Self::create_bind_group(device, &layout, &uniform, &atlas_view, &sampler);
+        DirectionalShadows {
+            settings,
+            atlas_size,
+            atlas_view,
+            uniform,
+            sampler,
+            layout,
+            bind_group,
+            cascades: Vec::new(),
+        }
+    }
+
+    fn create_atlas(device: &wgpu::Device, size: u32) -> wgpu::TextureView {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("directional shadow atlas"),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform: &wgpu::Buffer,
+        atlas_view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("directional shadow bind group"),
+            layout,
+            entries: &Self::entries(uniform, atlas_view, sampler),
+        })
+    }
+
+    fn entries<'a>(
+        uniform: &'a wgpu::Buffer,
+        atlas_view: &'a wgpu::TextureView,
+        sampler: &'a wgpu::Sampler,
+    ) -> [wgpu::BindGroupEntry<'a>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(atlas_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ]
+    }
+
+    /// The entries `SHADOW_SHADER` expects, for building a combined lighting layout.
+    pub fn layout_entries() -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<ShadowUniform>() as u64),
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+        ]
+    }
+
+    /// The resources of `layout_entries`, for a combined lighting bind group. They change when
+    /// `update` reallocates the atlas.
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 3] {
+        Self::entries(&self.uniform, &self.atlas_view, &self.sampler)
+    }
+
+    /// For lit pipelines with these bindings alone, at `SHADOW_GROUP`.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn atlas(&self) -> &wgpu::TextureView {
+        &self.atlas_view
+    }
+
+    /// The cascades planned by the last `update`; empty when nothing casts a shadow.
+    pub fn cascades(&self) -> &[Cascade] {
+        &self.cascades
+    }
+
+    /// Fits the cascades to `camera` for the light at `light_index` of the frame's lights,
+    /// travelling in `direction`, and uploads what the shader needs. Without a light, or with
+    /// shadows disabled, everything is lit. Reallocates the atlas if the resolution or cascade
+    /// count changed.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        target_size: (u32, u32),
+        light: Option<(usize, Vec3)>,
+    ) {
+        let settings = self.settings;
+        if settings.atlas_size() != self.atlas_size {
+            self.atlas_size = settings.atlas_size();
+            self.atlas_view = Self::create_atlas(device, self.atlas_size);
+            self.bind_group = Self::create_bind_group(device, &self.layout, &self.uniform, &self.atlas_view, &self.sampler);
+        }
+
+        self.cascades.clear();
+        let mut uniform = ShadowUniform::zeroed();
+        match light {
+            Some((index, direction)) if settings.enabled => {
+                let resolution = settings.resolution.max(1).next_power_of_two();
+                let count = settings.cascade_count();
+                let tiles = ShadowAtlas {
+                    size: self.atlas_size,
+                    min_tile: resolution,
+                }
+                .pack(&vec![resolution; count]);
+                let near = match camera.projection {
+                    Projection::Perspective { near, .. } | Projection::Orthographic { near, .. } => near.max(0.01),
+                };
+                let (_, _, width, height) = camera.viewport.pixels(target_size);
+                let aspect = width as f32 / height as f32;
+                let mut slice_near = near;
+                for (far, tile) in settings.splits(near).into_iter().zip(tiles) {
+                    let tile = tile.expect("the atlas fits every cascade");
+                    let (view_projection, texel_size) =
+                        fit_cascade(camera, aspect, direction, slice_near, far, resolution, settings.caster_distance);
+                    self.cascades.push(Cascade {
+                        view_projection,
+                        tile,
+                        far,
+                        texel_size,
+                    });
+                    slice_near = far;
+                }
+
+                let atlas_size = self.atlas_size as f32;
+                for (i, cascade) in self.cascades.iter().enumerate() {
+                    uniform.cascades[i] = cascade.view_projection.to_cols_array_2d();
+                    uniform.atlas_rects[i] = [
+                        cascade.tile.x as f32 / atlas_size,
+                        cascade.tile.y as f32 / atlas_size,
+                        cascade.tile.size as f32 / atlas_size,
+                        cascade.tile.size as f32 / atlas_size,
+                    ];
+                    uniform.splits[i] = cascade.far;
+                    uniform.texel_sizes[i] = cascade.texel_size;
+                }
+                uniform.params = [count as f32, index as f32, settings.depth_bias, settings.normal_bias];
+                uniform.camera_forward = camera.forward.normalize_or_zero().extend(0.0).into();
+            }
+            _ => uniform.params[1] = -1.0,
+        }
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    /// Clears the atlas and calls `draw` with a pass over it for every cascade, its viewport
+    /// already set.
+    pub fn render<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        mut draw: impl FnMut(&mut wgpu::RenderPass<'a>, usize, &Cascade),
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("directional shadows"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.atlas_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        for (index, cascade) in self.cascades.iter().enumerate() {
+            let AtlasTile { x, y, size } = cascade.tile;
+            pass.set_viewport(x as f32, y as f32, size as f32, size as f32, 0.0, 1.0);
+            pass.set_scissor_rect(x, y, size, size);
+            draw(&mut pass, index, cascade);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::{num::NonZeroU64, sync::Arc};
+
+use glam::Mat4;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    mesh::Mesh,
+    vertex_layout::{VertexLayout, VertexLayouts},
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Bind-pose vertex with up to four joint influences, as uploaded for GPU skinning.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinnedVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+}
+
+/// What the skinning pre-pass writes: the same layout static meshes use, so skinned output can
+/// be drawn, culled and instanced like any other mesh.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinnedOutputVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+impl SkinnedOutputVertex {
+    pub fn layout() -> VertexLayout {
+        VertexLayout::standard()
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    vertex_count: u32,
+    joint_count: u32,
+}
+
+/// The compute pipeline shared by every skinned mesh.
+pub struct GpuSkinning {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl GpuSkinning {
+    /// Needs compute shader support; check `Capabilities::compute` first.
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("shaders/skinning.wgsl"));
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("skinning bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(std::mem::size_of::<Params>() as u64),
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("skinning pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("skinning pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        GpuSkinning {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+
+    /// Uploads a mesh whose skinned output is drawn like any other `Mesh`, with its layout
+    /// registered in `layouts`. `MeshRenderer::upload_skinned` calls this with its own.
+    pub fn create_mesh(
+        &self,
+        device: &wgpu::Device,
+        layouts: &mut VertexLayouts,
+        label: &str,
+        vertices: &[SkinnedVertex],
+        indices: &[u32],
+        joint_count: u32,
+    ) -> SkinnedMesh {
+        assert!(joint_count > 0, "a skinned mesh needs at least one joint");
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("skinning params"),
+            contents: bytemuck::bytes_of(&Params {
+                vertex_count: vertices.len() as u32,
+                joint_count,
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let input = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("skinning bind pose"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let palette = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("skinning palette"),
+            contents: bytemuck::cast_slice(&vec![Mat4::IDENTITY; joint_count as usize]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let output_size = (vertices.len() * std::mem::size_of::<SkinnedOutputVertex>()) as u64;
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: output_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+            mapped_at_creation: false,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("skinning bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: input.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: palette.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: output.as_entire_binding(),
+                },
+            ],
+        });
+
+        SkinnedMesh {
+            _uniform: uniform,
+            _input: input,
+            palette,
+            mesh: Arc::new(Mesh {
+                vertex_buffer: output,
+                index_buffer,
+                index_count: indices.len() as u32,
+                layout: layouts.register(SkinnedOutputVertex::layout()),
+                bytes: output_size + std::mem::size_of_val(indices) as u64,
+            }),
+            bind_group,
+            vertex_count: vertices.len() as u32,
+            joint_count,
+        }
+    }
+
+    /// Records the skinning dispatches. Run this before any pass that draws the outputs.
+    pub fn dispatch<'a>(&'a self, pass: &mut wgpu::ComputePass<'a>, meshes: impl IntoIterator<Item = &'a SkinnedMesh>) {
+        pass.set_pipeline(&self.pipeline);
+        for mesh in meshes {
+            pass.set_bind_group(0, &mesh.bind_group, &[]);
+            pass.dispatch(mesh.vertex_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+}
+
+/// GPU buffers for one skinned mesh instance. `mesh` holds the skinned vertices, so it goes
+/// through the same batching, instancing and shadow passes as static meshes.
+pub struct SkinnedMesh {
+    _uniform: wgpu::Buffer,
+    _input: wgpu::Buffer,
+    palette: wgpu::Buffer,
+    mesh: Arc<Mesh>,
+    bind_group: wgpu::BindGroup,
+    vertex_count: u32,
+    joint_count: u32,
+}
+
+impl SkinnedMesh {
+    /// Uploads this frame's joint matrices (joint world transform times inverse bind matrix).
+    pub fn set_palette(&self, queue: &wgpu::Queue, joints: &[Mat4]) {
+        let count = joints.len().min(self.joint_count as usize);
+        queue.write_buffer(&self.palette, 0, bytemuck::cast_slice(&joints[..count]));
+    }
+
+    /// Skinned vertices laid out as `SkinnedOutputVertex`.
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        &self.mesh.vertex_buffer
+    }
+
+    /// The skinned output with the mesh's indices, for `MeshRenderer::draw`.
+    pub fn mesh(&self) -> &Arc<Mesh> {
+        &self.mesh
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+}
@@ -0,0 +1,243 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use glam::{Vec3, Vec4};
+
+use crate::{
+    camera::Camera,
+    postprocess::{fullscreen_module, uniform_entry, volumetric_fog::FogLight, FullscreenPipeline},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct SkySettings {
+    /// Local solar time in hours, 12 being noon.
+    pub time_of_day: f32,
+    /// Degrees north of the equator; sets how high the sun climbs.
+    pub latitude: f32,
+    /// 1 to 365, for the sun's seasonal declination.
+    pub day_of_year: f32,
+    /// Overrides the direction towards the sun that `time_of_day` would give.
+    pub sun_direction: Option<Vec3>,
+    /// Haziness: 2 is a clear sky, 10 a hazy one.
+    pub turbidity: f32,
+    /// Scales the model's luminance, in kcd/m², into scene units.
+    pub sky_intensity: f32,
+    /// Sun color times intensity at the zenith of a perfectly clear sky, in scene units.
+    pub sun_intensity: f32,
+    /// Angular radius of the sun disk in degrees.
+    pub sun_size: f32,
+    /// Color below the horizon, lit by the sun.
+    pub ground_albedo: Vec3,
+    /// Sky color left when the sun is well below the horizon.
+    pub night_color: Vec3,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        SkySettings {
+            time_of_day: 10.0,
+            latitude: 45.0,
+            day_of_year: 172.0,
+            sun_direction: None,
+            turbidity: 2.5,
+            sky_intensity: 0.1,
+            sun_intensity: 3.0,
+            sun_size: 0.27,
+            ground_albedo: Vec3::splat(0.3),
+            night_color: Vec3::new(0.002, 0.003, 0.006),
+        }
+    }
+}
+
+/// The sun as a directional light.
+#[derive(Clone, Copy, Debug)]
+pub struct SunLight {
+    /// Direction the light travels in.
+    pub direction: Vec3,
+    /// Color times intensity after the atmosphere, in the same units as the HDR scene.
+    pub color: Vec3,
+}
+
+impl From<SunLight> for FogLight {
+    fn from(sun: SunLight) -> Self {
+        FogLight {
+            direction: sun.direction,
+            color: sun.color,
+        }
+    }
+}
+
+// how quickly light fades as the sun sets: full at 2 degrees above the horizon, none at 4 below
+fn twilight(sun: Vec3) -> f32 {
+    let elevation = sun.y.clamp(-1.0, 1.0).asin().to_degrees();
+    let t = ((elevation + 4.0) / 6.0).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl SkySettings {
+    /// Unit vector towards the sun, Y up with north along -Z and east along +X.
+    pub fn sun_direction(&self) -> Vec3 {
+        if let Some(direction) = self.sun_direction {
+            return direction.normalize_or_zero();
+        }
+        let declination = self.declination();
+        let hour_angle = ((self.time_of_day - 12.0) * 15.0).to_radians();
+        let latitude = self.latitude.to_radians();
+        // equatorial to horizontal coordinates
+        let up = latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+        let east = -declination.cos() * hour_angle.sin();
+        let north = latitude.cos() * declination.sin() - latitude.sin() * declination.cos() * hour_angle.cos();
+        Vec3::new(east, up, -north).normalize_or_zero()
+    }
+
+    fn declination(&self) -> f32 {
+        (-23.44f32).to_radians() * (2.0 * PI * (self.day_of_year + 10.0) / 365.0).cos()
+    }
+
+    /// Local solar hours at which the sun crosses the horizon at this latitude and day of the
+    /// year, or `None` during a polar day or night.
+    pub fn sunrise_sunset(&self) -> Option<(f32, f32)> {
+        let cos_hour_angle = -self.latitude.to_radians().tan() * self.declination().tan();
+        if !(-1.0..=1.0).contains(&cos_hour_angle) {
+            return None;
+        }
+        let hours = cos_hour_angle.acos().to_degrees() / 15.0;
+        Some((12.0 - hours, 12.0 + hours))
+    }
+
+    /// The sun after the atmosphere's extinction, Preetham's Rayleigh and aerosol terms for red,
+    /// green and blue wavelengths. Reddens towards the horizon and fades out below it.
+    pub fn sun_light(&self) -> SunLight {
+        let sun = self.sun_direction();
+        let zenith = sun.y.clamp(-1.0, 1.0).acos().min(FRAC_PI_2);
+        // Kasten and Young's relative air mass, finite at the horizon
+        let air_mass = 1.0 / (zenith.cos() + 0.50572 * (96.07995 - zenith.to_degrees()).powf(-1.6364));
+        let beta = 0.04608 * self.turbidity.clamp(1.7, 10.0) - 0.04586;
+        let transmittance = Vec3::new(0.68, 0.55, 0.44).to_array().map(|wavelength: f32| {
+            let rayleigh = 0.008735 * wavelength.powf(-4.08);
+            let aerosol = beta * wavelength.powf(-1.3);
+            (-(rayleigh + aerosol) * air_mass).exp()
+        });
+        SunLight {
+            direction: -sun,
+            color: Vec3::from(transmittance) * self.sun_intensity * twilight(sun),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    inverse_view_projection: [[f32; 4]; 4],
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    ground: [f32; 4],
+    night: [f32; 4],
+    // Perez coefficients A to E, each for Y, x and y
+    perez: [[f32; 4]; 5],
+    // zenith Y, x and y
+    zenith: [f32; 4],
+    sun_size: f32,
+    sky_intensity: f32,
+    twilight: f32,
+    _padding: f32,
+}
+
+/// Procedural sky after Preetham, Shirley and Smits' analytic daylight model, lit by a sun that
+/// follows the time of day. Draw it first into the HDR target and render the scene over it;
+/// `SkySettings::sun_light` gives the matching directional light.
+pub struct Sky {
+    pub settings: SkySettings,
+    pipeline: FullscreenPipeline,
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Sky {
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "sky", include_str!("shaders/sky.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sky layout"),
+            entries: &[uniform_entry(0)],
+        });
+        let pipeline = FullscreenPipeline::new(device, "sky", &module, "fs_main", &[&layout], output_format, None);
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sky params"),
+            size: std::mem::size_of::<Params>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sky bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+        });
+
+        Sky {
+            settings: SkySettings::default(),
+            pipeline,
+            uniform,
+            bind_group,
+        }
+    }
+
+    /// Rebuilds the pipeline from `module`, an edited `shaders/sky.wgsl` after
+    /// `FULLSCREEN_SHADER`.
+    pub fn set_shader(&mut self, device: &wgpu::Device, module: &wgpu::ShaderModule) {
+        self.pipeline.set_module(device, module);
+    }
+
+    pub fn sun_light(&self) -> SunLight {
+        self.settings.sun_light()
+    }
+
+    /// Fills `output`, a target of `target_size`, with the sky as seen by `camera`.
+    pub fn render(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, camera: &Camera, (width, height): (u32, u32), output: &wgpu::TextureView) {
+        let settings = &self.settings;
+        let sun = settings.sun_direction();
+        let turbidity = settings.turbidity.clamp(1.7, 10.0);
+        // the model only holds for a sun above the horizon; below it the sky just fades out
+        let theta_s = sun.y.clamp(-1.0, 1.0).acos().min(FRAC_PI_2 - 0.001);
+
+        let perez = |t: [[f32; 2]; 5]| t.map(|[slope, offset]| slope * turbidity + offset);
+        let y = perez([[0.1787, -1.4630], [-0.3554, 0.4275], [-0.0227, 5.3251], [0.1206, -2.5771], [-0.0670, 0.3703]]);
+        let x = perez([[-0.0193, -0.2592], [-0.0665, 0.0008], [-0.0004, 0.2125], [-0.0641, -0.8989], [-0.0033, 0.0452]]);
+        let yc = perez([[-0.0167, -0.2608], [-0.0950, 0.0092], [-0.0079, 0.2102], [-0.0441, -1.6537], [-0.0109, 0.0529]]);
+
+        let chi = (4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192;
+        let powers = Vec4::new(theta_s.powi(3), theta_s * theta_s, theta_s, 1.0);
+        let chromaticity = |t2: [f32; 4], t1: [f32; 4], t0: [f32; 4]| {
+            turbidity * turbidity * Vec4::from(t2).dot(powers) + turbidity * Vec4::from(t1).dot(powers) + Vec4::from(t0).dot(powers)
+        };
+        let zenith_x = chromaticity(
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        );
+        let zenith_y = chromaticity(
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        );
+
+        let view_projection = camera.projection.matrix(width as f32 / height.max(1) as f32) * camera.view();
+        let params = Params {
+            inverse_view_projection: view_projection.inverse().to_cols_array_2d(),
+            sun_direction: sun.extend(theta_s).into(),
+            sun_color: settings.sun_light().color.extend(0.0).into(),
+            ground: settings.ground_albedo.extend(0.0).into(),
+            night: settings.night_color.extend(0.0).into(),
+            perez: std::array::from_fn(|i| [y[i], x[i], yc[i], 0.0]),
+            zenith: [zenith_luminance.max(0.0), zenith_x, zenith_y, 0.0],
+            sun_size: settings.sun_size.to_radians(),
+            sky_intensity: settings.sky_intensity,
+            twilight: twilight(sun),
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&params));
+        self.pipeline.draw(encoder, output, &[&self.bind_group], Some(wgpu::Color::BLACK));
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt;
+
+use glam::Vec3;
+
+pub trait Curve {
+    /// Position on the curve for `t` in `[0, 1]`.
+    fn sample(&self, t: f32) -> Vec3;
+    /// Derivative with respect to `t` (not normalized).
+    fn derivative(&self, t: f32) -> Vec3;
+}
+
+/// Uniform Catmull-Rom spline through `points`.
+/// Open splines clamp the end points so the curve passes through every point.
+#[derive(Clone, Debug)]
+pub struct CatmullRom {
+    pub points: Vec<Vec3>,
+    pub closed: bool,
+}
+
+impl CatmullRom {
+    pub fn new(points: Vec<Vec3>) -> Self {
+        CatmullRom { points, closed: false }
+    }
+
+    pub fn closed(points: Vec<Vec3>) -> Self {
+        CatmullRom { points, closed: true }
+    }
+
+    fn segment_count(&self) -> usize {
+        match (self.points.len(), self.closed) {
+            (0..=1, _) => 0,
+            (n, true) => n,
+            (n, false) => n - 1,
+        }
+    }
+
+    fn point(&self, i: isize) -> Vec3 {
+        let n = self.points.len() as isize;
+        if self.closed {
+            self.points[i.rem_euclid(n) as usize]
+        } else {
+            self.points[i.clamp(0, n - 1) as usize]
+        }
+    }
+
+    /// Segment index and local parameter for a global `t`.
+    fn locate(&self, t: f32) -> (isize, f32) {
+        let segments = self.segment_count();
+        let scaled = t.clamp(0.0, 1.0) * segments as f32;
+        let i = (scaled.floor() as usize).min(segments - 1);
+        (i as isize, scaled - i as f32)
+    }
+
+    fn control(&self, i: isize) -> [Vec3; 4] {
+        [self.point(i - 1), self.point(i), self.point(i + 1), self.point(i + 2)]
+    }
+}
+
+impl Curve for CatmullRom {
+    fn sample(&self, t: f32) -> Vec3 {
+        match self.points.len() {
+            0 => return Vec3::ZERO,
+            1 => return self.points[0],
+            _ => {}
+        }
+        let (i, u) = self.locate(t);
+        let [p0, p1, p2, p3] = self.control(i);
+        let u2 = u * u;
+        let u3 = u2 * u;
+        0.5 * ((2.0 * p1)
+            + (p2 - p0) * u
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * u2
+            + (3.0 * p1 - p0 - 3.0 * p2 + p3) * u3)
+    }
+
+    fn derivative(&self, t: f32) -> Vec3 {
+        if self.points.len() < 2 {
+            return Vec3::ZERO;
+        }
+        let (i, u) = self.locate(t);
+        let [p0, p1, p2, p3] = self.control(i);
+        let u2 = u * u;
+        let d = 0.5
+            * ((p2 - p0)
+                + 2.0 * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * u
+                + 3.0 * (3.0 * p1 - p0 - 3.0 * p2 + p3) * u2);
+        d * self.segment_count() as f32
+    }
+}
+
+/// `Bezier::new` was given a number of points other than 3n + 1.
+#[derive(Debug)]
+pub struct BezierError {
+    pub points: usize,
+}
+
+impl fmt::Display for BezierError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bezier path needs 3n + 1 points, got {}", self.points)
+    }
+}
+
+impl std::error::Error for BezierError {}
+
+/// Piecewise cubic Bezier path. Points are laid out as
+/// `[p0, c0, c1, p1, c2, c3, p2, ...]`, sharing end points between segments.
+#[derive(Clone, Debug)]
+pub struct Bezier {
+    pub points: Vec<Vec3>,
+}
+
+impl Bezier {
+    pub fn new(points: Vec<Vec3>) -> Result<Self, BezierError> {
+        if points.len() < 4 || !(points.len() - 1).is_multiple_of(3) {
+            return Err(BezierError { points: points.len() });
+        }
+        Ok(Bezier { points })
+    }
+
+    fn segment_count(&self) -> usize {
+        (self.points.len() - 1) / 3
+    }
+
+    fn locate(&self, t: f32) -> ([Vec3; 4], f32) {
+        let segments = self.segment_count();
+        let scaled = t.clamp(0.0, 1.0) * segments as f32;
+        let i = (scaled.floor() as usize).min(segments - 1);
+        let p = &self.points[i * 3..i * 3 + 4];
+        ([p[0], p[1], p[2], p[3]], scaled - i as f32)
+    }
+}
+
+impl Curve for Bezier {
+    fn sample(&self, t: f32) -> Vec3 {
+        let ([p0, p1, p2, p3], u) = self.locate(t);
+        let v = 1.0 - u;
+        v * v * v * p0 + 3.0 * v * v * u * p1 + 3.0 * v * u * u * p2 + u * u * u * p3
+    }
+
+    fn derivative(&self, t: f32) -> Vec3 {
+        let ([p0, p1, p2, p3], u) = self.locate(t);
+        let v = 1.0 - u;
+        let d = 3.0 * v * v * (p1 - p0) + 6.0 * v * u * (p2 - p1) + 3.0 * u * u * (p3 - p2);
+        d * self.segment_count() as f32
+    }
+}
+
+/// A curve with a precomputed arc-length table, so it can be traversed at constant speed.
+pub struct Path {
+    curve: Box<dyn Curve + Send + Sync>,
+    /// Cumulative length at evenly spaced values of `t`.
+    lengths: Vec<f32>,
+}
+
+impl Path {
+    pub const DEFAULT_SAMPLES: usize = 256;
+
+    pub fn new(curve: impl Curve + Send + Sync + 'static) -> Self {
+        Self::with_samples(curve, Self::DEFAULT_SAMPLES)
+    }
+
+    pub fn with_samples(curve: impl Curve + Send + Sync + 'static, samples: usize) -> Self {
+        let samples = samples.max(1);
+        let mut lengths = Vec::with_capacity(samples + 1);
+        lengths.push(0.0);
+        let mut previous = curve.sample(0.0);
+        let mut total = 0.0;
+        for i in 1..=samples {
+            let point = curve.sample(i as f32 / samples as f32);
+            total += point.distance(previous);
+            lengths.push(total);
+            previous = point;
+        }
+
+        Path {
+            curve: Box::new(curve),
+            lengths,
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.lengths.last().unwrap()
+    }
+
+    /// Curve parameter `t` that lies `distance` units along the path.
+    pub fn t_at_distance(&self, distance: f32) -> f32 {
+        let distance = distance.clamp(0.0, self.length());
+        let i = self.lengths.partition_point(|&l| l < distance);
+        if i == 0 {
+            return 0.0;
+        }
+        let (l0, l1) = (self.lengths[i - 1], self.lengths[i]);
+        let local = if l1 > l0 { (distance - l0) / (l1 - l0) } else { 0.0 };
+        let samples = (self.lengths.len() - 1) as f32;
+        ((i - 1) as f32 + local) / samples
+    }
+
+    pub fn position_at_distance(&self, distance: f32) -> Vec3 {
+        self.curve.sample(self.t_at_distance(distance))
+    }
+
+    /// Normalized direction of travel `distance` units along the path.
+    pub fn tangent_at_distance(&self, distance: f32) -> Vec3 {
+        self.curve
+            .derivative(self.t_at_distance(distance))
+            .normalize_or_zero()
+    }
+
+    pub fn curve(&self) -> &dyn Curve {
+        self.curve.as_ref()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathMode {
+    Once,
+    Loop,
+    PingPong,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PathSample {
+    pub position: Vec3,
+    pub tangent: Vec3,
+}
+
+/// Moves something (a camera, an object) along a `Path` at a constant speed. As a component,
+/// the engine advances it every frame and moves the entity's `Transform` along, see
+/// `scene::follow_path_system`.
+pub struct FollowPath {
+    pub path: std::sync::Arc<Path>,
+    pub speed: f32,
+    pub mode: PathMode,
+    pub distance: f32,
+    /// Also turns the entity to face the direction of travel.
+    pub orient: bool,
+    direction: f32,
+}
+
+impl FollowPath {
+    pub fn new(path: std::sync::Arc<Path>, speed: f32, mode: PathMode) -> Self {
+        FollowPath {
+            path,
+            speed,
+            mode,
+            distance: 0.0,
+            orient: false,
+            direction: 1.0,
+        }
+    }
+
+    pub fn with_orient(mut self) -> Self {
+        self.orient = true;
+        self
+    }
+
+    pub fn finished(&self) -> bool {
+        self.mode == PathMode::Once && self.distance >= self.path.length()
+    }
+
+    pub fn advance(&mut self, dt: f32) -> PathSample {
+        let length = self.path.length();
+        self.distance += self.speed * self.direction * dt;
+        if length > 0.0 {
+            match self.mode {
+                PathMode::Once => self.distance = self.distance.clamp(0.0, length),
+                PathMode::Loop => self.distance = self.distance.rem_euclid(length),
+                PathMode::PingPong => {
+                    let period = self.distance.rem_euclid(2.0 * length);
+                    if period > length {
+                        self.distance = 2.0 * length - period;
+                        self.direction = -self.direction;
+                    } else {
+                        self.distance = period;
+                    }
+                }
+            }
+        } else {
+            self.distance = 0.0;
+        }
+        self.sample()
+    }
+
+    pub fn sample(&self) -> PathSample {
+        PathSample {
+            position: self.path.position_at_distance(self.distance),
+            tangent: self.path.tangent_at_distance(self.distance) * self.direction,
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use glam::{Vec2, Vec3};
+
+use super::{nine_slice::NineSlice, UvRect};
+use crate::{
+    camera::Camera,
+    dynamic_buffer::{DynamicBuffer, DynamicSlice, FRAMES_IN_FLIGHT},
+    postprocess::linear_sampler,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SpriteSpace {
+    /// Faces the camera, sized in world units. With an orthographic camera looking down -Z this
+    /// is a plain 2D sprite.
+    World = 0,
+    /// Anchored at a world point but sized and offset in pixels, for health bars and markers.
+    Screen = 1,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteInstance {
+    pub center: [f32; 3],
+    pub space: u32,
+    /// Shift from `center` along the camera's right and up, in world units or pixels.
+    pub offset: [f32; 2],
+    pub size: [f32; 2],
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+    /// Multiplies the texture color.
+    pub color: [f32; 4],
+}
+
+impl SpriteInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Uint32,
+        2 => Float32x2,
+        3 => Float32x2,
+        4 => Float32x2,
+        5 => Float32x2,
+        6 => Float32x4,
+    ];
+
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+
+    pub fn new(space: SpriteSpace, center: Vec3, size: Vec2, uv: UvRect, color: [f32; 4]) -> Self {
+        SpriteInstance {
+            center: center.into(),
+            space: space as u32,
+            offset: [0.0; 2],
+            size: size.into(),
+            uv_min: uv.min,
+            uv_max: uv.max,
+            color,
+        }
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset.into();
+        self
+    }
+}
+
+/// Sprites sharing one texture, drawn in the order they were added.
+#[derive(Clone, Debug, Default)]
+pub struct SpriteBatch {
+    instances: Vec<SpriteInstance>,
+}
+
+impl SpriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, instance: SpriteInstance) {
+        self.instances.push(instance);
+    }
+
+    pub fn sprite(&mut self, space: SpriteSpace, center: Vec3, size: Vec2, uv: UvRect, color: [f32; 4]) {
+        self.push(SpriteInstance::new(space, center, size, uv, color));
+    }
+
+    /// A nine-slice panel of `size`, centered on `center` shifted by `offset`. `border_scale`
+    /// converts the slice's texture pixels into the units of `space`; 1 keeps screen-space
+    /// borders pixel exact.
+    #[allow(clippy::too_many_arguments)]
+    pub fn nine_slice(
+        &mut self,
+        space: SpriteSpace,
+        center: Vec3,
+        offset: Vec2,
+        size: Vec2,
+        slice: &NineSlice,
+        border_scale: f32,
+        color: [f32; 4],
+    ) {
+        for (piece_offset, piece_size, uv) in slice.pieces(size, border_scale) {
+            self.push(SpriteInstance::new(space, center, piece_size, uv, color).with_offset(offset + piece_offset));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.instances.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn instances(&self) -> &[SpriteInstance] {
+        &self.instances
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct ViewUniform {
+    view_projection: [[f32; 4]; 4],
+    right: [f32; 4],
+    up: [f32; 4],
+    viewport: [f32; 4],
+}
+
+/// Draws sprite batches into a scene or UI pass without going through egui.
+pub struct SpriteRenderer {
+    pipeline: wgpu::RenderPipeline,
+    texture_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+    view_bind_group: wgpu::BindGroup,
+    instances: DynamicBuffer,
+    instance_slice: Option<DynamicSlice>,
+    /// Instance range of each batch passed to the last `prepare`.
+    ranges: Vec<std::ops::Range<u32>>,
+}
+
+impl SpriteRenderer {
+    /// With a `depth_format`, sprites are depth tested against the scene without writing depth.
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat, depth_format: Option<wgpu::TextureFormat>) -> Self {
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("../shaders/sprite.wgsl"));
+        let view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sprite view layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sprite texture layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sprite pipeline layout"),
+            bind_group_layouts: &[&view_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("sprites"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[SpriteInstance::layout()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            multiview: None,
+        });
+        let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sprite view"),
+            size: std::mem::size_of::<ViewUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let view_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sprite view bind group"),
+            layout: &view_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+        });
+
+        SpriteRenderer {
+            pipeline,
+            texture_layout,
+            sampler: linear_sampler(device),
+            uniform,
+            view_bind_group,
+            instances: DynamicBuffer::new(device, "sprite instances", wgpu::BufferUsages::VERTEX, FRAMES_IN_FLIGHT),
+            instance_slice: None,
+            ranges: Vec::new(),
+        }
+    }
+
+    /// Bind group for a sprite texture; create one per atlas and keep it.
+    pub fn texture_bind_group(&self, device: &wgpu::Device, texture: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sprite texture bind group"),
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(texture),
+                },
+            ],
+        })
+    }
+
+    /// Uploads the batches and the view of `camera` rendering into a target of `target_size`.
+    /// Call before starting the pass `draw` records into.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera, target_size: (u32, u32), batches: &[&SpriteBatch]) {
+        let forward = camera.forward.normalize_or_zero();
+        let right = forward.cross(camera.up).normalize_or_zero();
+        let up = right.cross(forward);
+        let (_, _, width, height) = camera.viewport.pixels(target_size);
+        let view = ViewUniform {
+            view_projection: camera.view_projection(target_size).to_cols_array_2d(),
+            right: right.extend(0.0).into(),
+            up: up.extend(0.0).into(),
+            viewport: [width as f32, height as f32, 0.0, 0.0],
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&view));
+
+        self.ranges.clear();
+        self.instances.begin_frame();
+        self.instance_slice = None;
+        let mut instances = Vec::with_capacity(batches.iter().map(|batch| batch.len()).sum());
+        for batch in batches {
+            let start = instances.len() as u32;
+            instances.extend_from_slice(&batch.instances);
+            self.ranges.push(start..instances.len() as u32);
+        }
+        if instances.is_empty() {
+            return;
+        }
+        self.instance_slice = Some(self.instances.push(&instances));
+        self.instances.finish(device, queue);
+    }
+
+    /// Draws the batches from the last `prepare`, each with the texture bind group at the same
+    /// index in `textures`.
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, textures: &[&'a wgpu::BindGroup]) {
+        let Some(instances) = self.instance_slice.and_then(|slice| self.instances.slice(slice)) else {
+            return;
+        };
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.view_bind_group, &[]);
+        pass.set_vertex_buffer(0, instances);
+        for (range, texture) in self.ranges.iter().zip(textures) {
+            if !range.is_empty() {
+                pass.set_bind_group(1, texture, &[]);
+                pass.draw(0..4, range.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use super::{Atlas, UvRect};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopMode {
+    /// Plays through once and holds the last frame.
+    Once,
+    Loop,
+    /// Forwards, then backwards without repeating the end frames.
+    PingPong,
+}
+
+/// A named moment in a sprite animation, reached when `frame` starts showing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameEvent {
+    /// Position in the animation's frame sequence, not the atlas index.
+    pub frame: usize,
+    pub name: String,
+}
+
+/// A sequence of atlas frames played at a fixed rate.
+#[derive(Clone, Debug)]
+pub struct SpriteAnimation {
+    pub name: String,
+    /// Atlas frame indices, in playback order.
+    pub frames: Vec<usize>,
+    pub fps: f32,
+    pub mode: LoopMode,
+    pub events: Vec<FrameEvent>,
+}
+
+impl SpriteAnimation {
+    pub fn new(name: &str, frames: impl IntoIterator<Item = usize>, fps: f32, mode: LoopMode) -> Self {
+        SpriteAnimation {
+            name: name.to_owned(),
+            frames: frames.into_iter().collect(),
+            fps,
+            mode,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn with_event(mut self, frame: usize, name: &str) -> Self {
+        self.events.push(FrameEvent {
+            frame,
+            name: name.to_owned(),
+        });
+        self
+    }
+
+    /// Which frame of the sequence is showing `step` frames after the start.
+    fn position(&self, step: u64) -> usize {
+        let count = self.frames.len() as u64;
+        if count <= 1 {
+            return 0;
+        }
+        let position = match self.mode {
+            LoopMode::Once => step.min(count - 1),
+            LoopMode::Loop => step % count,
+            LoopMode::PingPong => {
+                let period = 2 * count - 2;
+                let phase = step % period;
+                if phase < count {
+                    phase
+                } else {
+                    period - phase
+                }
+            }
+        };
+        position as usize
+    }
+
+    /// Seconds for one pass through the sequence.
+    pub fn duration(&self) -> f32 {
+        if self.fps > 0.0 {
+            self.frames.len() as f32 / self.fps
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Plays sprite animations for one sprite, whether a 2D character or a billboard effect such as
+/// an explosion in 3D.
+#[derive(Clone, Debug)]
+pub struct Flipbook {
+    animation: Arc<SpriteAnimation>,
+    /// Playback rate; 1 plays at the animation's fps.
+    pub speed: f32,
+    time: f32,
+    /// The last step whose events fired, `None` before the first update.
+    last_step: Option<u64>,
+    events: Vec<String>,
+}
+
+impl Flipbook {
+    pub fn new(animation: Arc<SpriteAnimation>) -> Self {
+        Flipbook {
+            animation,
+            speed: 1.0,
+            time: 0.0,
+            last_step: None,
+            events: Vec::new(),
+        }
+    }
+
+    /// Switches to `animation` from its first frame. Playing the animation that is already
+    /// playing does nothing, so this can be called every frame from gameplay state.
+    pub fn play(&mut self, animation: &Arc<SpriteAnimation>) {
+        if !Arc::ptr_eq(&self.animation, animation) {
+            self.restart(animation.clone());
+        }
+    }
+
+    /// Starts `animation` from its first frame even if it is already playing.
+    pub fn restart(&mut self, animation: Arc<SpriteAnimation>) {
+        self.animation = animation;
+        self.time = 0.0;
+        self.last_step = None;
+    }
+
+    pub fn animation(&self) -> &SpriteAnimation {
+        &self.animation
+    }
+
+    fn step(&self) -> u64 {
+        let step = (self.time * self.animation.fps).max(0.0) as u64;
+        match self.animation.mode {
+            LoopMode::Once => step.min(self.animation.frames.len().saturating_sub(1) as u64),
+            LoopMode::Loop | LoopMode::PingPong => step,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.events.clear();
+        self.time += dt * self.speed.max(0.0);
+        let step = self.step();
+        let first = self.last_step.map_or(0, |last| last + 1);
+        if first > step {
+            return;
+        }
+        // a long hitch can pass many frames; fire their events, but at most one cycle's worth
+        let count = self.animation.frames.len().max(1) as u64;
+        let animation = &self.animation;
+        for step in first.max(step.saturating_sub(count * 2 - 1))..=step {
+            let position = animation.position(step);
+            self.events
+                .extend(animation.events.iter().filter(|event| event.frame == position).map(|event| event.name.clone()));
+        }
+        self.last_step = Some(step);
+    }
+
+    /// Events reached during the last update.
+    pub fn events(&self) -> &[String] {
+        &self.events
+    }
+
+    /// Position in the animation's frame sequence.
+    pub fn position(&self) -> usize {
+        self.animation.position(self.step())
+    }
+
+    /// The atlas frame to show.
+    pub fn frame(&self) -> usize {
+        self.animation.frames.get(self.position()).copied().unwrap_or(0)
+    }
+
+    pub fn uv(&self, atlas: &Atlas) -> UvRect {
+        atlas.frame(self.frame())
+    }
+
+    /// True once a `LoopMode::Once` animation has shown its last frame for a full frame time.
+    pub fn is_finished(&self) -> bool {
+        self.animation.mode == LoopMode::Once && self.time >= self.animation.duration()
+    }
+}
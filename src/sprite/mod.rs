@@ -0,0 +1,70 @@
+pub mod batch;
+pub mod flipbook;
+pub mod nine_slice;
+
+/// A rectangle in normalized texture coordinates, origin top left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl UvRect {
+    pub const FULL: UvRect = UvRect {
+        min: [0.0, 0.0],
+        max: [1.0, 1.0],
+    };
+
+    pub fn flipped_x(self) -> Self {
+        UvRect {
+            min: [self.max[0], self.min[1]],
+            max: [self.min[0], self.max[1]],
+        }
+    }
+}
+
+/// Where each frame sits in a sprite sheet texture.
+#[derive(Clone, Debug, Default)]
+pub struct Atlas {
+    /// Texture size in pixels.
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<UvRect>,
+}
+
+impl Atlas {
+    /// Frames from explicit pixel rectangles `(x, y, width, height)`, as packing tools export them.
+    pub fn from_rects(width: u32, height: u32, rects: &[(u32, u32, u32, u32)]) -> Self {
+        let (w, h) = (width as f32, height as f32);
+        let frames = rects
+            .iter()
+            .map(|&(x, y, frame_width, frame_height)| UvRect {
+                min: [x as f32 / w, y as f32 / h],
+                max: [(x + frame_width) as f32 / w, (y + frame_height) as f32 / h],
+            })
+            .collect();
+        Atlas { width, height, frames }
+    }
+
+    /// A sheet of equally sized frames, numbered left to right and then top to bottom.
+    pub fn grid(width: u32, height: u32, columns: u32, rows: u32) -> Self {
+        let (frame_width, frame_height) = (width / columns.max(1), height / rows.max(1));
+        let rects: Vec<_> = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column * frame_width, row * frame_height, frame_width, frame_height)))
+            .collect();
+        Self::from_rects(width, height, &rects)
+    }
+
+    pub fn frame(&self, index: usize) -> UvRect {
+        self.frames.get(index).copied().unwrap_or(UvRect::FULL)
+    }
+
+    /// Pixel size of a frame, for sizing sprites to match the art.
+    pub fn frame_size(&self, index: usize) -> (f32, f32) {
+        let frame = self.frame(index);
+        (
+            (frame.max[0] - frame.min[0]).abs() * self.width as f32,
+            (frame.max[1] - frame.min[1]).abs() * self.height as f32,
+        )
+    }
+}
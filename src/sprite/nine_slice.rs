@@ -0,0 +1,69 @@
+use glam::Vec2;
+
+use super::{Atlas, UvRect};
+
+/// A sprite cut into a 3x3 grid by its borders: corners keep their size, edges stretch along
+/// one axis and the center along both, so panels and bars scale without distorting their frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NineSlice {
+    pub uv: UvRect,
+    /// Size of the sprite in texture pixels.
+    pub pixel_size: Vec2,
+    /// Border widths in texture pixels: left, right, top, bottom.
+    pub borders: [f32; 4],
+}
+
+impl NineSlice {
+    pub fn new(atlas: &Atlas, frame: usize, borders: [f32; 4]) -> Self {
+        let (width, height) = atlas.frame_size(frame);
+        NineSlice {
+            uv: atlas.frame(frame),
+            pixel_size: Vec2::new(width, height),
+            borders,
+        }
+    }
+
+    /// Offset from the panel center, size and UVs of each non-empty piece of a panel `size` large.
+    /// Borders that don't fit are shrunk evenly rather than overlapping.
+    pub fn pieces(&self, size: Vec2, border_scale: f32) -> Vec<(Vec2, Vec2, UvRect)> {
+        let [left, right, top, bottom] = self.borders.map(|border| border.max(0.0));
+        let fit = |start: f32, end: f32, length: f32| {
+            let (start, end) = (start * border_scale, end * border_scale);
+            let shrink = if start + end > length && start + end > 0.0 {
+                length / (start + end)
+            } else {
+                1.0
+            };
+            [start * shrink, (length - (start + end) * shrink).max(0.0), end * shrink]
+        };
+        let columns = fit(left, right, size.x.abs());
+        let rows = fit(top, bottom, size.y.abs());
+
+        // texture coordinates of the cuts, in the same left/top first order
+        let uv_span = Vec2::new(self.uv.max[0] - self.uv.min[0], self.uv.max[1] - self.uv.min[1]);
+        let pixel = uv_span / self.pixel_size.max(Vec2::ONE);
+        let u = [self.uv.min[0], self.uv.min[0] + left * pixel.x, self.uv.max[0] - right * pixel.x, self.uv.max[0]];
+        let v = [self.uv.min[1], self.uv.min[1] + top * pixel.y, self.uv.max[1] - bottom * pixel.y, self.uv.max[1]];
+
+        let mut pieces = Vec::with_capacity(9);
+        let mut y = size.y.abs() * 0.5;
+        for (row, height) in rows.into_iter().enumerate() {
+            let mut x = -size.x.abs() * 0.5;
+            for (column, width) in columns.into_iter().enumerate() {
+                if width > 0.0 && height > 0.0 {
+                    pieces.push((
+                        Vec2::new(x + width * 0.5, y - height * 0.5),
+                        Vec2::new(width, height),
+                        UvRect {
+                            min: [u[column], v[row]],
+                            max: [u[column + 1], v[row + 1]],
+                        },
+                    ));
+                }
+                x += width;
+            }
+            y -= height;
+        }
+        pieces
+    }
+}
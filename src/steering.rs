@@ -0,0 +1,210 @@
+use glam::{Quat, Vec3};
+
+/// A sphere agents steer around.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Obstacle {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+/// Something that moves itself, e.g. an AI controlled character: behaviours like `seek` and
+/// `arrive` return steering forces, which are added up with `steer` and turned into motion by
+/// `update`, Reynolds style. Copy `position` and `facing` to the entity's transform afterwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Agent {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub max_speed: f32,
+    /// How hard the agent can accelerate or turn, in units per second squared.
+    pub max_force: f32,
+    /// Used for avoidance; the agent is treated as a sphere.
+    pub radius: f32,
+    /// Keeps movement on the XZ plane, for walking agents.
+    pub planar: bool,
+    force: Vec3,
+}
+
+impl Agent {
+    pub fn new(position: Vec3, max_speed: f32, max_force: f32, radius: f32) -> Self {
+        Agent {
+            position,
+            velocity: Vec3::ZERO,
+            max_speed,
+            max_force,
+            radius,
+            planar: true,
+            force: Vec3::ZERO,
+        }
+    }
+
+    fn flatten(&self, vector: Vec3) -> Vec3 {
+        if self.planar {
+            Vec3::new(vector.x, 0.0, vector.z)
+        } else {
+            vector
+        }
+    }
+
+    /// The force that turns the current velocity into `desired`, as fast as `max_force` allows
+    /// going from standstill to full speed.
+    fn towards(&self, desired: Vec3) -> Vec3 {
+        self.flatten(desired - self.velocity) * (self.max_force / self.max_speed.max(1e-4))
+    }
+
+    /// Full speed towards `target`.
+    pub fn seek(&self, target: Vec3) -> Vec3 {
+        self.towards(self.flatten(target - self.position).normalize_or_zero() * self.max_speed)
+    }
+
+    /// Full speed away from `threat`.
+    pub fn flee(&self, threat: Vec3) -> Vec3 {
+        -self.seek(threat)
+    }
+
+    /// Towards `target`, slowing down within `slowing_radius` so the agent stops on it.
+    pub fn arrive(&self, target: Vec3, slowing_radius: f32) -> Vec3 {
+        let offset = self.flatten(target - self.position);
+        let distance = offset.length();
+        if distance < 1e-4 {
+            return self.towards(Vec3::ZERO);
+        }
+        let speed = self.max_speed * (distance / slowing_radius.max(1e-4)).min(1.0);
+        self.towards(offset / distance * speed)
+    }
+
+    /// Sideways away from the nearest obstacle the agent would hit within `look_ahead` seconds
+    /// at its current velocity, harder the closer it is. Zero if the way is clear.
+    pub fn avoid(&self, obstacles: &[Obstacle], look_ahead: f32) -> Vec3 {
+        let velocity = self.flatten(self.velocity);
+        let speed = velocity.length();
+        if speed < 1e-4 {
+            return Vec3::ZERO;
+        }
+        let forward = velocity / speed;
+        let range = speed * look_ahead;
+
+        let mut nearest: Option<(f32, Vec3)> = None;
+        for obstacle in obstacles {
+            let offset = self.flatten(obstacle.center - self.position);
+            let clearance = obstacle.radius + self.radius;
+            let along = offset.dot(forward);
+            if along < 0.0 || along > range + clearance {
+                continue;
+            }
+            let lateral = offset - forward * along;
+            if lateral.length() < clearance && nearest.is_none_or(|(distance, _)| along < distance) {
+                nearest = Some((along, lateral));
+            }
+        }
+
+        let Some((along, lateral)) = nearest else {
+            return Vec3::ZERO;
+        };
+        // dead ahead, pick a side
+        let away = (-lateral).try_normalize().unwrap_or_else(|| forward.cross(Vec3::Y).normalize_or_zero());
+        away * self.max_force * (1.0 - along / (range + self.radius)).clamp(0.2, 1.0)
+    }
+
+    /// Away from `neighbours` closer than `distance`, e.g. other agents in a crowd.
+    pub fn separate(&self, neighbours: &[Vec3], distance: f32) -> Vec3 {
+        let mut force = Vec3::ZERO;
+        for &neighbour in neighbours {
+            let offset = self.flatten(self.position - neighbour);
+            let length = offset.length();
+            if length > 1e-4 && length < distance {
+                // stronger the closer they are
+                force += offset / length * (1.0 - length / distance);
+            }
+        }
+        force * self.max_force
+    }
+
+    /// Adds a behaviour's force for this update; scale it to weigh behaviours against each other.
+    pub fn steer(&mut self, force: Vec3) {
+        self.force += force;
+    }
+
+    /// Applies the forces added since the last update, limited to `max_force`, and moves.
+    pub fn update(&mut self, dt: f32) {
+        let force = self.flatten(self.force).clamp_length_max(self.max_force);
+        self.velocity = self.flatten(self.velocity + force * dt).clamp_length_max(self.max_speed);
+        self.position += self.velocity * dt;
+        self.force = Vec3::ZERO;
+    }
+
+    /// The rotation that turns `forward`, the model's front, towards where the agent is moving,
+    /// or `None` while it stands still. Planar agents only turn around Y.
+    pub fn facing(&self, forward: Vec3) -> Option<Quat> {
+        let direction = self.flatten(self.velocity).try_normalize()?;
+        if self.planar {
+            let forward = self.flatten(forward).try_normalize()?;
+            let angle = forward.cross(direction).y.atan2(forward.dot(direction));
+            Some(Quat::from_rotation_y(angle))
+        } else {
+            Some(Quat::from_rotation_arc(forward.normalize(), direction))
+        }
+    }
+}
+
+/// Steers an agent along a path of corner points, such as a navigation mesh query returns:
+/// seeking each point in turn and arriving at the last.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathFollower {
+    path: Vec<Vec3>,
+    current: usize,
+    /// How close the agent has to get to a corner before heading for the next.
+    pub waypoint_radius: f32,
+    /// Where the agent starts slowing down before the end of the path.
+    pub slowing_radius: f32,
+}
+
+impl PathFollower {
+    pub fn new(waypoint_radius: f32, slowing_radius: f32) -> Self {
+        PathFollower {
+            path: Vec::new(),
+            current: 0,
+            waypoint_radius,
+            slowing_radius,
+        }
+    }
+
+    /// Starts following `path` from its first point.
+    pub fn set_path(&mut self, path: Vec<Vec3>) {
+        self.path = path;
+        self.current = 0;
+    }
+
+    pub fn path(&self) -> &[Vec3] {
+        &self.path
+    }
+
+    /// The point the agent is heading for.
+    pub fn target(&self) -> Option<Vec3> {
+        self.path.get(self.current).copied()
+    }
+
+    /// The force for this update, moving on to the next corner once `agent` is close enough
+    /// to the current one. Zero without a path.
+    pub fn steer(&mut self, agent: &Agent) -> Vec3 {
+        let close = |point: Vec3| agent.flatten(point - agent.position).length() < self.waypoint_radius;
+        while self.current + 1 < self.path.len() && close(self.path[self.current]) {
+            self.current += 1;
+        }
+        match self.target() {
+            Some(target) if self.current + 1 == self.path.len() => agent.arrive(target, self.slowing_radius),
+            Some(target) => agent.seek(target),
+            None => Vec3::ZERO,
+        }
+    }
+
+    /// True once `agent` has reached the end of the path, or if there is no path.
+    pub fn is_finished(&self, agent: &Agent) -> bool {
+        match self.path.last() {
+            Some(&last) => {
+                self.current + 1 == self.path.len()
+                    && agent.flatten(last - agent.position).length() < self.waypoint_radius
+            }
+            None => true,
+        }
+    }
+}
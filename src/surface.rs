@@ -0,0 +1,78 @@
+use std::fmt;
+
+/// Formats the engine can present to. wgpu 0.12 can't list the formats a surface supports, so
+/// `select_format` takes the surface's preferred format if it is one of these rather than
+/// picking from them; the sRGB ones let the UI composite skip its manual gamma conversion.
+pub const SUPPORTED_FORMATS: [wgpu::TextureFormat; 4] = [
+    wgpu::TextureFormat::Bgra8UnormSrgb,
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Bgra8Unorm,
+    wgpu::TextureFormat::Rgba8Unorm,
+];
+
+#[derive(Debug)]
+pub enum SurfaceFormatError {
+    /// The adapter cannot present to this surface at all.
+    SurfaceUnsupported { adapter: wgpu::AdapterInfo },
+    /// The adapter can present, but none of the formats the engine renders to.
+    NoCompatibleFormat {
+        adapter: wgpu::AdapterInfo,
+        preferred: Option<wgpu::TextureFormat>,
+    },
+}
+
+impl fmt::Display for SurfaceFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SurfaceFormatError::SurfaceUnsupported { adapter } => write!(
+                f,
+                "adapter \"{}\" ({:?}) cannot present to the window surface; \
+                 try a different backend or GPU",
+                adapter.name, adapter.backend
+            ),
+            SurfaceFormatError::NoCompatibleFormat { adapter, preferred } => {
+                write!(
+                    f,
+                    "adapter \"{}\" ({:?}) offers no usable surface format",
+                    adapter.name, adapter.backend
+                )?;
+                if let Some(preferred) = preferred {
+                    write!(f, " (preferred {:?} is not one of", preferred)?;
+                } else {
+                    write!(f, " (expected one of")?;
+                }
+                write!(f, " {:?}); try a different backend or update your graphics drivers", SUPPORTED_FORMATS)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SurfaceFormatError {}
+
+/// Picks the swapchain format for `surface`.
+///
+/// wgpu 0.12 only reports a single preferred format (chosen deterministically from the four
+/// WebGPU swapchain formats) and has no composite alpha negotiation, so the surface is always
+/// opaque. This checks that the choice is something the UI composite handles and that the
+/// adapter can render to it, and otherwise fails with an explanation instead of panicking.
+pub fn select_format(surface: &wgpu::Surface, adapter: &wgpu::Adapter) -> Result<wgpu::TextureFormat, SurfaceFormatError> {
+    if !adapter.is_surface_supported(surface) {
+        return Err(SurfaceFormatError::SurfaceUnsupported {
+            adapter: adapter.get_info(),
+        });
+    }
+
+    let preferred = surface.get_preferred_format(adapter);
+    preferred
+        .filter(|format| SUPPORTED_FORMATS.contains(format))
+        .filter(|&format| {
+            adapter
+                .get_texture_format_features(format)
+                .allowed_usages
+                .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+        })
+        .ok_or_else(|| SurfaceFormatError::NoCompatibleFormat {
+            adapter: adapter.get_info(),
+            preferred,
+        })
+}
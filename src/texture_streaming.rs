@@ -0,0 +1,301 @@
+use std::{
+    collections::BTreeMap,
+    num::NonZeroU32,
+    sync::{mpsc, Arc},
+};
+
+use crate::gpu_memory::{AllocationId, GpuMemory};
+
+/// Where the texel data of a streamed texture comes from. Levels are indexed like the full mip
+/// chain (0 is the most detailed) and hold tightly packed rows of blocks.
+pub trait MipSource: Send + Sync + 'static {
+    fn load_mip(&self, level: u32) -> Result<Vec<u8>, String>;
+}
+
+/// A mip chain that is already in memory.
+pub struct MipChain(pub Vec<Vec<u8>>);
+
+impl MipSource for MipChain {
+    fn load_mip(&self, level: u32) -> Result<Vec<u8>, String> {
+        self.0
+            .get(level as usize)
+            .cloned()
+            .ok_or_else(|| format!("mip level {} is missing", level))
+    }
+}
+
+type LoadedMip = (u32, Result<Vec<u8>, String>);
+
+/// Picks the most detailed mip worth keeping for a texture that covers roughly
+/// `screen_width x screen_height` pixels on screen.
+pub fn desired_first_mip(size: wgpu::Extent3d, mip_level_count: u32, screen_width: f32, screen_height: f32) -> u32 {
+    let ratio = (size.width as f32 / screen_width.max(1.0)).max(size.height as f32 / screen_height.max(1.0));
+    let mip = if ratio > 1.0 { ratio.log2().floor() as u32 } else { 0 };
+    mip.min(mip_level_count.saturating_sub(1))
+}
+
+/// A texture that starts out with only its smallest mips resident and grows (or shrinks) its
+/// mip chain on demand. Higher mips are read from the `MipSource` on a worker thread, so
+/// requesting detail never blocks the frame.
+///
+/// Only the resident mips are allocated: growing reallocates the texture and copies the levels
+/// it already had, so `view` changes whenever `update` returns true and bind groups that
+/// reference it need to be rebuilt.
+pub struct StreamedTexture {
+    label: String,
+    size: wgpu::Extent3d,
+    mip_level_count: u32,
+    dimension: wgpu::TextureDimension,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    first_mip: u32,
+    wanted_mip: u32,
+    source: Arc<dyn MipSource>,
+    loading: Option<mpsc::Receiver<LoadedMip>>,
+    loaded: BTreeMap<u32, Vec<u8>>,
+    allocation: AllocationId,
+}
+
+impl StreamedTexture {
+    /// Creates the texture with its `resident_mips` smallest levels, which are loaded right away.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        memory: &mut GpuMemory,
+        label: impl Into<String>,
+        desc: &wgpu::TextureDescriptor,
+        resident_mips: u32,
+        source: Arc<dyn MipSource>,
+    ) -> Result<Self, String> {
+        let label = label.into();
+        let resident_mips = resident_mips.clamp(1, desc.mip_level_count);
+        let first_mip = desc.mip_level_count - resident_mips;
+        let usage = desc.usage | wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST;
+        let full = wgpu::TextureDescriptor { usage, ..desc.clone() };
+        let allocation = memory.track_evictable_texture(&full, resident_mips);
+
+        let (texture, view) = create(device, &label, &full, first_mip);
+        for level in first_mip..desc.mip_level_count {
+            let data = source.load_mip(level)?;
+            write_level(queue, &texture, &full, first_mip, level, &data);
+        }
+        memory.set_first_mip(allocation, first_mip);
+
+        Ok(StreamedTexture {
+            label,
+            size: desc.size,
+            mip_level_count: desc.mip_level_count,
+            dimension: desc.dimension,
+            format: desc.format,
+            usage,
+            texture,
+            view,
+            first_mip,
+            wanted_mip: first_mip,
+            source,
+            loading: None,
+            loaded: BTreeMap::new(),
+            allocation,
+        })
+    }
+
+    fn desc(&self) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            label: None,
+            size: self.size,
+            mip_level_count: self.mip_level_count,
+            sample_count: 1,
+            dimension: self.dimension,
+            format: self.format,
+            usage: self.usage,
+        }
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// Most detailed mip level currently on the GPU.
+    pub fn first_mip(&self) -> u32 {
+        self.first_mip
+    }
+
+    pub fn allocation(&self) -> AllocationId {
+        self.allocation
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Asks for mips down to `first_mip` to become resident. Loading starts immediately in the
+    /// background; asking for less detail than is resident does nothing (see `evict_to`).
+    pub fn request(&mut self, first_mip: u32) {
+        let first_mip = first_mip.min(self.mip_level_count - 1);
+        if first_mip >= self.wanted_mip {
+            return;
+        }
+        self.wanted_mip = first_mip;
+        if self.loading.is_none() {
+            self.start_loading();
+        }
+    }
+
+    fn start_loading(&mut self) {
+        let levels: Vec<u32> = (self.wanted_mip..self.first_mip)
+            .rev()
+            .filter(|level| !self.loaded.contains_key(level))
+            .collect();
+        if levels.is_empty() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let source = self.source.clone();
+        // smallest first, so detail arrives progressively even when the requested jump is large
+        std::thread::spawn(move || {
+            for level in levels {
+                if sender.send((level, source.load_mip(level))).is_err() {
+                    break;
+                }
+            }
+        });
+        self.loading = Some(receiver);
+    }
+
+    /// Uploads whatever finished loading since the last call. Returns true if the texture was
+    /// reallocated, in which case `view` is new.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, memory: &mut GpuMemory) -> bool {
+        if let Some(receiver) = &self.loading {
+            loop {
+                match receiver.try_recv() {
+                    Ok((level, Ok(data))) => {
+                        // levels requested before an eviction lowered the target are dropped
+                        if level >= self.wanted_mip {
+                            self.loaded.insert(level, data);
+                        }
+                    }
+                    Ok((level, Err(e))) => {
+                        log::error!("Failed to stream mip {} of \"{}\": {}", level, self.label, e);
+                        // stop asking for levels that can't be loaded
+                        self.wanted_mip = self.wanted_mip.max(level + 1);
+                    }
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        self.loading = None;
+                        break;
+                    }
+                }
+            }
+        }
+
+        // only grow through a contiguous run of loaded levels directly above the resident ones
+        let mut new_first = self.first_mip;
+        while new_first > self.wanted_mip && self.loaded.contains_key(&(new_first - 1)) {
+            new_first -= 1;
+        }
+
+        let grown = new_first < self.first_mip;
+        if grown {
+            self.reallocate(device, encoder, new_first);
+            let desc = self.desc();
+            for level in new_first..self.first_mip {
+                let data = self.loaded.remove(&level).unwrap();
+                write_level(queue, &self.texture, &desc, new_first, level, &data);
+            }
+            self.first_mip = new_first;
+            memory.set_first_mip(self.allocation, new_first);
+        }
+
+        if self.loading.is_none() && self.wanted_mip < self.first_mip {
+            self.start_loading();
+        }
+        grown
+    }
+
+    /// Drops every mip more detailed than `first_mip`, e.g. to apply a `MipChange` from the
+    /// memory budget. Returns true if the texture was reallocated.
+    pub fn evict_to(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, memory: &mut GpuMemory, first_mip: u32) -> bool {
+        let first_mip = first_mip.min(self.mip_level_count - 1);
+        self.wanted_mip = self.wanted_mip.max(first_mip);
+        self.loaded.retain(|&level, _| level >= first_mip);
+        if first_mip <= self.first_mip {
+            return false;
+        }
+        self.reallocate(device, encoder, first_mip);
+        self.first_mip = first_mip;
+        memory.set_first_mip(self.allocation, first_mip);
+        true
+    }
+
+    /// Replaces the texture with one whose base level is `new_first`, carrying over every level
+    /// the old and new textures have in common.
+    fn reallocate(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, new_first: u32) {
+        let desc = self.desc();
+        let (texture, view) = create(device, &self.label, &desc, new_first);
+        let is_3d = desc.dimension == wgpu::TextureDimension::D3;
+        for level in new_first.max(self.first_mip)..self.mip_level_count {
+            encoder.copy_texture_to_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: level - self.first_mip,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level - new_first,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                desc.size.mip_level_size(level, is_3d).physical_size(desc.format),
+            );
+        }
+        self.texture = texture;
+        self.view = view;
+    }
+}
+
+fn create(device: &wgpu::Device, label: &str, full: &wgpu::TextureDescriptor, first_mip: u32) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: full.size.mip_level_size(first_mip, full.dimension == wgpu::TextureDimension::D3),
+        mip_level_count: full.mip_level_count - first_mip,
+        sample_count: 1,
+        dimension: full.dimension,
+        format: full.format,
+        usage: full.usage,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+/// Uploads `level` of the full chain into `texture`, whose own level 0 is `first_mip`.
+fn write_level(queue: &wgpu::Queue, texture: &wgpu::Texture, full: &wgpu::TextureDescriptor, first_mip: u32, level: u32, data: &[u8]) {
+    let info = full.format.describe();
+    let size = full.size.mip_level_size(level, full.dimension == wgpu::TextureDimension::D3);
+    let blocks_x = size.width.div_ceil(info.block_dimensions.0 as u32);
+    let blocks_y = size.height.div_ceil(info.block_dimensions.1 as u32);
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: level - first_mip,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(blocks_x * info.block_size as u32),
+            rows_per_image: NonZeroU32::new(blocks_y),
+        },
+        size.physical_size(full.format),
+    );
+}
@@ -0,0 +1,173 @@
+use crate::{
+    save::{Persist, SaveError, SaveReader, SaveWriter},
+    sky::SkySettings,
+};
+
+/// Moments of the day gameplay can react to: shops opening, monsters spawning, lamps lighting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DayEvent {
+    Dawn { day: u32 },
+    Dusk { day: u32 },
+    /// Midnight passed and `day` began.
+    NewDay { day: u32 },
+}
+
+/// The game clock: hours of the in-game day advancing at `day_length` real seconds per day.
+/// `apply` moves the `Sky`'s sun, and with it the sky colors and sunlight; `daylight` gives
+/// a factor for the lights the sky doesn't drive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeOfDay {
+    hours: f32,
+    day: u32,
+    /// Real seconds one in-game day takes.
+    pub day_length: f32,
+    pub paused: bool,
+    /// Hour the sun rises; see `SkySettings::sunrise_sunset` to match the sky.
+    pub dawn: f32,
+    /// Hour the sun sets.
+    pub dusk: f32,
+    /// Hours `daylight` takes to go from night to day around dawn and back around dusk.
+    pub twilight: f32,
+    events: Vec<DayEvent>,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        TimeOfDay {
+            hours: 8.0,
+            day: 0,
+            day_length: 20.0 * 60.0,
+            paused: false,
+            dawn: 6.0,
+            dusk: 18.0,
+            twilight: 1.0,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// A clock at `hours` on day 0, with days `day_length` real seconds long.
+    pub fn new(hours: f32, day_length: f32) -> Self {
+        TimeOfDay {
+            hours: hours.rem_euclid(24.0),
+            day_length,
+            ..Self::default()
+        }
+    }
+
+    /// Takes dawn and dusk from the sky's sunrise and sunset, so events fire as the sun
+    /// crosses the horizon. Keeps the current ones during a polar day or night.
+    pub fn with_sky(mut self, sky: &SkySettings) -> Self {
+        if let Some((sunrise, sunset)) = sky.sunrise_sunset() {
+            self.dawn = sunrise;
+            self.dusk = sunset;
+        }
+        self
+    }
+
+    /// Hours into the current day, from 0 up to 24.
+    pub fn hours(&self) -> f32 {
+        self.hours
+    }
+
+    /// Days passed since day 0.
+    pub fn day(&self) -> u32 {
+        self.day
+    }
+
+    /// Jumps to `hours` on the current day without firing events, e.g. when sleeping or from a
+    /// debug menu.
+    pub fn set_hours(&mut self, hours: f32) {
+        self.hours = hours.rem_euclid(24.0);
+    }
+
+    pub fn set_day(&mut self, day: u32) {
+        self.day = day;
+    }
+
+    pub fn is_day(&self) -> bool {
+        self.hours >= self.dawn && self.hours < self.dusk
+    }
+
+    /// 1 during the day and 0 at night, easing across `twilight` around dawn and dusk. Scale
+    /// ambient light with it, or its inverse for lamps that come on at night.
+    pub fn daylight(&self) -> f32 {
+        let twilight = self.twilight.max(0.001);
+        let ease = |t: f32| {
+            let t = t.clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        };
+        let rise = ease((self.hours - self.dawn) / twilight + 0.5);
+        let set = ease((self.dusk - self.hours) / twilight + 0.5);
+        rise.min(set)
+    }
+
+    /// Advances the clock by `dt` real seconds. After a long frame, events up to a day's worth
+    /// are fired.
+    pub fn update(&mut self, dt: f32) {
+        self.events.clear();
+        if self.paused || self.day_length <= 0.0 {
+            return;
+        }
+        let start = self.day as f64 * 24.0 + self.hours as f64;
+        let end = start + dt.max(0.0) as f64 * 24.0 / self.day_length as f64;
+
+        let first_day = (end - 24.0).max(start).div_euclid(24.0) as u32;
+        let mut fired = Vec::new();
+        for day in first_day..=end.div_euclid(24.0) as u32 {
+            let midnight = day as f64 * 24.0;
+            for (hour, event) in [
+                (0.0, DayEvent::NewDay { day }),
+                (self.dawn as f64, DayEvent::Dawn { day }),
+                (self.dusk as f64, DayEvent::Dusk { day }),
+            ] {
+                let time = midnight + hour;
+                if time > start && time <= end && time > end - 24.0 {
+                    fired.push((time, event));
+                }
+            }
+        }
+        fired.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.events.extend(fired.into_iter().map(|(_, event)| event));
+
+        self.day = end.div_euclid(24.0) as u32;
+        self.hours = end.rem_euclid(24.0) as f32;
+    }
+
+    /// Events reached during the last update, in order.
+    pub fn events(&self) -> &[DayEvent] {
+        &self.events
+    }
+
+    /// Puts the sky's sun where this clock says it is, dropping any fixed sun direction.
+    pub fn apply(&self, sky: &mut SkySettings) {
+        sky.time_of_day = self.hours;
+        sky.sun_direction = None;
+    }
+}
+
+impl Persist for TimeOfDay {
+    fn save(&self, writer: &mut SaveWriter) {
+        writer.write_f32(self.hours);
+        writer.write_u32(self.day);
+        writer.write_f32(self.day_length);
+        writer.write_bool(self.paused);
+        writer.write_f32(self.dawn);
+        writer.write_f32(self.dusk);
+        writer.write_f32(self.twilight);
+    }
+
+    fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+        Ok(TimeOfDay {
+            hours: reader.read_f32()?.rem_euclid(24.0),
+            day: reader.read_u32()?,
+            day_length: reader.read_f32()?,
+            paused: reader.read_bool()?,
+            dawn: reader.read_f32()?,
+            dusk: reader.read_f32()?,
+            twilight: reader.read_f32()?,
+            events: Vec::new(),
+        })
+    }
+}
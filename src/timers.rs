@@ -0,0 +1,225 @@
+enum Action {
+    Wait(f32),
+    WaitFrames(u32),
+    WaitUntil(Box<dyn FnMut() -> bool>),
+    Run(Box<dyn FnMut()>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Repeat {
+    Times(u32),
+    Forever,
+}
+
+/// A script of waits and actions that runs over several frames, like a coroutine: build it
+/// with `wait`, `wait_frames`, `wait_until` and `then`, e.g. open a door, wait two seconds,
+/// wait until the player is through and close it again, and start it with `Timers::run`.
+pub struct Sequence {
+    actions: Vec<Action>,
+    repeat: Repeat,
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Sequence {
+            actions: Vec::new(),
+            repeat: Repeat::Times(1),
+        }
+    }
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits `seconds` of update time.
+    pub fn wait(mut self, seconds: f32) -> Self {
+        self.actions.push(Action::Wait(seconds.max(0.0)));
+        self
+    }
+
+    /// Waits for `frames` updates; `wait_frames(1)` continues on the next update.
+    pub fn wait_frames(mut self, frames: u32) -> Self {
+        self.actions.push(Action::WaitFrames(frames));
+        self
+    }
+
+    /// Waits until `condition` returns true, checking it once per update.
+    pub fn wait_until(mut self, condition: impl FnMut() -> bool + 'static) -> Self {
+        self.actions.push(Action::WaitUntil(Box::new(condition)));
+        self
+    }
+
+    pub fn then(mut self, action: impl FnMut() + 'static) -> Self {
+        self.actions.push(Action::Run(Box::new(action)));
+        self
+    }
+
+    /// Plays the whole sequence `times` times in total.
+    pub fn repeat(mut self, times: u32) -> Self {
+        self.repeat = Repeat::Times(times);
+        self
+    }
+
+    /// Plays the sequence until it is cancelled.
+    pub fn forever(mut self) -> Self {
+        self.repeat = Repeat::Forever;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+struct Running {
+    id: TimerId,
+    sequence: Sequence,
+    current: usize,
+    elapsed: f32,
+    frames: u32,
+    iterations: u32,
+}
+
+impl Running {
+    /// Advances by `dt`, returning true once the sequence has finished.
+    fn update(&mut self, dt: f32) -> bool {
+        let mut remaining = dt;
+        // an action entered during this update doesn't get this update's frame
+        let mut entered = false;
+        let mut pass_start = remaining;
+        loop {
+            let Some(action) = self.sequence.actions.get_mut(self.current) else {
+                self.iterations += 1;
+                let again = match self.sequence.repeat {
+                    Repeat::Times(times) => self.iterations < times,
+                    Repeat::Forever => true,
+                };
+                if !again {
+                    return true;
+                }
+                self.current = 0;
+                // a pass that took no time would repeat forever within this one update
+                if remaining == pass_start || self.sequence.actions.is_empty() {
+                    return false;
+                }
+                pass_start = remaining;
+                continue;
+            };
+            match action {
+                Action::Wait(duration) => {
+                    self.elapsed += remaining;
+                    if self.elapsed < *duration {
+                        return false;
+                    }
+                    // carry the overshoot into what follows so repeating timers don't drift
+                    remaining = self.elapsed - *duration;
+                    self.elapsed = 0.0;
+                }
+                Action::WaitFrames(frames) => {
+                    if !entered {
+                        self.frames += 1;
+                    }
+                    if self.frames < *frames {
+                        return false;
+                    }
+                    self.frames = 0;
+                }
+                Action::WaitUntil(condition) => {
+                    if !condition() {
+                        return false;
+                    }
+                }
+                Action::Run(action) => action(),
+            }
+            self.current += 1;
+            entered = true;
+        }
+    }
+}
+
+/// Delayed and repeating callbacks and running `Sequence`s. The engine advances them once per
+/// fixed step, right before `App::fixed_update`, so they follow game time: they stop while
+/// `paused` and stay deterministic under the fixed timestep. An "update" below is one step.
+#[derive(Default)]
+pub struct Timers {
+    /// Stops `update` from advancing anything, waits and frame counts alike.
+    pub paused: bool,
+    running: Vec<Running>,
+    next_id: u64,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn run(&mut self, sequence: Sequence) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+        self.running.push(Running {
+            id,
+            sequence,
+            current: 0,
+            elapsed: 0.0,
+            frames: 0,
+            iterations: 0,
+        });
+        id
+    }
+
+    /// Calls `callback` once, `delay` seconds from now.
+    pub fn after(&mut self, delay: f32, callback: impl FnOnce() + 'static) -> TimerId {
+        self.run(Sequence::new().wait(delay).then(once(callback)))
+    }
+
+    /// Calls `callback` every `interval` seconds until cancelled. After a long frame it is
+    /// called once for every interval that passed.
+    pub fn every(&mut self, interval: f32, callback: impl FnMut() + 'static) -> TimerId {
+        self.run(Sequence::new().wait(interval).then(callback).forever())
+    }
+
+    /// Calls `callback` once, `frames` updates from now.
+    pub fn after_frames(&mut self, frames: u32, callback: impl FnOnce() + 'static) -> TimerId {
+        self.run(Sequence::new().wait_frames(frames).then(once(callback)))
+    }
+
+    /// Stops a timer or sequence before its next action. Returns false if it already finished.
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        let count = self.running.len();
+        self.running.retain(|running| running.id != id);
+        self.running.len() != count
+    }
+
+    pub fn cancel_all(&mut self) {
+        self.running.clear();
+    }
+
+    pub fn is_running(&self, id: TimerId) -> bool {
+        self.running.iter().any(|running| running.id == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.running.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.paused {
+            return;
+        }
+        self.running.retain_mut(|running| !running.update(dt));
+    }
+}
+
+fn once(callback: impl FnOnce() + 'static) -> impl FnMut() + 'static {
+    let mut callback = Some(callback);
+    move || {
+        if let Some(callback) = callback.take() {
+            callback();
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::gpu_memory::{texture_size, AllocationId, GpuMemory, MemoryCategory};
+
+/// Everything that has to match for two transient attachments to share a texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TransientDesc {
+    pub size: wgpu::Extent3d,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+    pub sample_count: u32,
+    pub mip_level_count: u32,
+}
+
+impl TransientDesc {
+    pub fn attachment(width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        TransientDesc {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            sample_count: 1,
+            mip_level_count: 1,
+        }
+    }
+
+    fn texture_desc(&self) -> wgpu::TextureDescriptor<'static> {
+        wgpu::TextureDescriptor {
+            label: Some("transient attachment"),
+            size: self.size,
+            mip_level_count: self.mip_level_count,
+            sample_count: self.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: self.usage,
+        }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        texture_size(&self.texture_desc(), 0)
+    }
+}
+
+/// A transient attachment needed from pass `first_pass` through `last_pass` (inclusive), in
+/// execution order.
+#[derive(Clone, Copy, Debug)]
+pub struct TransientRequest {
+    pub desc: TransientDesc,
+    pub first_pass: usize,
+    pub last_pass: usize,
+}
+
+struct Slot {
+    desc: TransientDesc,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    allocation: AllocationId,
+    last_used_frame: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AliasingStats {
+    /// Bytes the requests would take if each had its own texture.
+    pub requested_bytes: u64,
+    /// Bytes actually backing them this frame.
+    pub allocated_bytes: u64,
+    pub textures: usize,
+}
+
+/// Backs short-lived attachments (depth buffers, velocity, post effect ping-pong targets) with a small set of
+/// textures. Attachments whose lifetimes don't overlap and whose descriptors match share the
+/// same texture, and textures are kept across frames so steady-state frames allocate nothing.
+///
+/// wgpu has no placed resources, so aliasing happens at texture granularity: only attachments
+/// with identical descriptors can share.
+#[derive(Default)]
+pub struct TransientPool {
+    slots: Vec<Slot>,
+    frame: u64,
+    stats: AliasingStats,
+}
+
+impl TransientPool {
+    /// Frames a texture may go unused before it is freed.
+    pub const RETAIN_FRAMES: u64 = 3;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assigns a texture to every request, returning the slot index for each, in request order.
+    /// New textures are tracked in `memory` as `MemoryCategory::RenderTargets`.
+    pub fn allocate(&mut self, device: &wgpu::Device, memory: &mut GpuMemory, requests: &[TransientRequest]) -> Vec<usize> {
+        self.frame += 1;
+
+        let mut order: Vec<usize> = (0..requests.len()).collect();
+        order.sort_by_key(|&i| (requests[i].first_pass, requests[i].last_pass));
+
+        // per slot: the last pass it is busy until this frame, or None if not claimed yet
+        let mut busy_until: Vec<Option<usize>> = vec![None; self.slots.len()];
+        let mut assignment = vec![0; requests.len()];
+        for i in order {
+            let request = &requests[i];
+            let free = (0..self.slots.len()).find(|&slot| {
+                self.slots[slot].desc == request.desc && busy_until[slot].is_none_or(|last| last < request.first_pass)
+            });
+            let slot = match free {
+                Some(slot) => slot,
+                None => {
+                    let desc = request.desc.texture_desc();
+                    let texture = device.create_texture(&desc);
+                    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                    self.slots.push(Slot {
+                        desc: request.desc,
+                        texture,
+                        view,
+                        allocation: memory.track_texture(MemoryCategory::RenderTargets, &desc),
+                        last_used_frame: self.frame,
+                    });
+                    busy_until.push(None);
+                    self.slots.len() - 1
+                }
+            };
+            busy_until[slot] = Some(request.last_pass);
+            self.slots[slot].last_used_frame = self.frame;
+            assignment[i] = slot;
+        }
+
+        self.stats = AliasingStats {
+            requested_bytes: requests.iter().map(|r| r.desc.bytes()).sum(),
+            allocated_bytes: busy_until
+                .iter()
+                .zip(&self.slots)
+                .filter(|(busy, _)| busy.is_some())
+                .map(|(_, slot)| slot.desc.bytes())
+                .sum(),
+            textures: busy_until.iter().filter(|busy| busy.is_some()).count(),
+        };
+        assignment
+    }
+
+    pub fn view(&self, slot: usize) -> &wgpu::TextureView {
+        &self.slots[slot].view
+    }
+
+    pub fn texture(&self, slot: usize) -> &wgpu::Texture {
+        &self.slots[slot].texture
+    }
+
+    pub fn stats(&self) -> AliasingStats {
+        self.stats
+    }
+
+    /// Frees textures nothing has needed for a few frames, e.g. after a resize or after an
+    /// effect was switched off. Slot indices from earlier `allocate` calls become invalid.
+    pub fn trim(&mut self, memory: &mut GpuMemory) {
+        let frame = self.frame;
+        self.slots.retain(|slot| {
+            let keep = frame - slot.last_used_frame < Self::RETAIN_FRAMES;
+            if !keep {
+                memory.release(slot.allocation);
+            }
+            keep
+        });
+    }
+
+    /// Total bytes held by the pool, including textures kept around for reuse.
+    pub fn resident_bytes(&self) -> u64 {
+        self.slots.iter().map(|slot| slot.desc.bytes()).sum()
+    }
+}
@@ -0,0 +1,392 @@
+use std::{cell::Cell, rc::Rc};
+
+use glam::{Quat, Vec2, Vec3, Vec4};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ease {
+    Linear,
+    InQuad,
+    OutQuad,
+    InOutQuad,
+    InCubic,
+    OutCubic,
+    InOutCubic,
+    InSine,
+    OutSine,
+    InOutSine,
+    InBack,
+    OutBack,
+    OutElastic,
+    OutBounce,
+}
+
+impl Ease {
+    pub fn apply(self, t: f32) -> f32 {
+        use std::f32::consts::PI;
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Ease::Linear => t,
+            Ease::InQuad => t * t,
+            Ease::OutQuad => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::InOutQuad => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Ease::InCubic => t * t * t,
+            Ease::OutCubic => 1.0 - (1.0 - t).powi(3),
+            Ease::InOutCubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Ease::InSine => 1.0 - (t * PI / 2.0).cos(),
+            Ease::OutSine => (t * PI / 2.0).sin(),
+            Ease::InOutSine => -((PI * t).cos() - 1.0) / 2.0,
+            Ease::InBack => {
+                const C1: f32 = 1.70158;
+                (C1 + 1.0) * t * t * t - C1 * t * t
+            }
+            Ease::OutBack => {
+                const C1: f32 = 1.70158;
+                1.0 + (C1 + 1.0) * (t - 1.0).powi(3) + C1 * (t - 1.0).powi(2)
+            }
+            Ease::OutElastic => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+                }
+            }
+            Ease::OutBounce => {
+                const N: f32 = 7.5625;
+                const D: f32 = 2.75;
+                if t < 1.0 / D {
+                    N * t * t
+                } else if t < 2.0 / D {
+                    let t = t - 1.5 / D;
+                    N * t * t + 0.75
+                } else if t < 2.5 / D {
+                    let t = t - 2.25 / D;
+                    N * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D;
+                    N * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+pub trait Tweenable: Copy + 'static {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self;
+}
+
+impl Tweenable for f32 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Tweenable for Vec2 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(to, t)
+    }
+}
+
+impl Tweenable for Vec3 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(to, t)
+    }
+}
+
+impl Tweenable for Vec4 {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from.lerp(to, t)
+    }
+}
+
+impl Tweenable for Quat {
+    fn interpolate(from: Self, to: Self, t: f32) -> Self {
+        from.slerp(to, t)
+    }
+}
+
+/// A value that can be driven by tweens while still being read elsewhere.
+#[derive(Debug, Default)]
+pub struct Animated<T: Copy>(Rc<Cell<T>>);
+
+impl<T: Copy> Animated<T> {
+    pub fn new(value: T) -> Self {
+        Animated(Rc::new(Cell::new(value)))
+    }
+
+    pub fn get(&self) -> T {
+        self.0.get()
+    }
+
+    pub fn set(&self, value: T) {
+        self.0.set(value)
+    }
+}
+
+impl<T: Copy> Clone for Animated<T> {
+    fn clone(&self) -> Self {
+        Animated(self.0.clone())
+    }
+}
+
+trait Step {
+    fn start(&mut self);
+    fn apply(&mut self, t: f32);
+}
+
+struct PropertyStep<T: Tweenable> {
+    target: Animated<T>,
+    from: Option<T>,
+    to: T,
+}
+
+impl<T: Tweenable> Step for PropertyStep<T> {
+    fn start(&mut self) {
+        // capture the starting value lazily so chained steps continue from wherever the previous one ended
+        self.from = Some(self.target.get());
+    }
+
+    fn apply(&mut self, t: f32) {
+        let from = self.from.unwrap_or(self.to);
+        self.target.set(T::interpolate(from, self.to, t));
+    }
+}
+
+struct SetterStep<T: Tweenable, F: FnMut(T)> {
+    from: T,
+    to: T,
+    setter: F,
+}
+
+impl<T: Tweenable, F: FnMut(T)> Step for SetterStep<T, F> {
+    fn start(&mut self) {}
+
+    fn apply(&mut self, t: f32) {
+        (self.setter)(T::interpolate(self.from, self.to, t));
+    }
+}
+
+struct Delay;
+
+impl Step for Delay {
+    fn start(&mut self) {}
+    fn apply(&mut self, _t: f32) {}
+}
+
+struct Segment {
+    step: Box<dyn Step>,
+    duration: f32,
+    ease: Ease,
+    on_complete: Option<Box<dyn FnOnce()>>,
+}
+
+/// A sequence of one or more interpolation steps, built with `Tween::to` and `then`.
+pub struct Tween {
+    segments: Vec<Segment>,
+    on_complete: Option<Box<dyn FnOnce()>>,
+    on_cancel: Option<Box<dyn FnOnce()>>,
+}
+
+impl Tween {
+    fn segment(step: impl Step + 'static, duration: f32, ease: Ease) -> Self {
+        Tween {
+            segments: vec![Segment {
+                step: Box::new(step),
+                duration: duration.max(0.0),
+                ease,
+                on_complete: None,
+            }],
+            on_complete: None,
+            on_cancel: None,
+        }
+    }
+
+    /// Animate `target` from its value when this step starts to `to`.
+    pub fn to<T: Tweenable>(target: &Animated<T>, to: T, duration: f32, ease: Ease) -> Self {
+        Self::segment(
+            PropertyStep {
+                target: target.clone(),
+                from: None,
+                to,
+            },
+            duration,
+            ease,
+        )
+    }
+
+    /// Animate between two fixed values, passing each intermediate value to `setter`.
+    pub fn with<T: Tweenable>(from: T, to: T, duration: f32, ease: Ease, setter: impl FnMut(T) + 'static) -> Self {
+        Self::segment(SetterStep { from, to, setter }, duration, ease)
+    }
+
+    pub fn delay(duration: f32) -> Self {
+        Self::segment(Delay, duration, Ease::Linear)
+    }
+
+    /// Append `next` so it starts once every step in `self` has finished.
+    pub fn then(mut self, mut next: Tween) -> Self {
+        if let Some(callback) = next.on_complete.take() {
+            next.segments.last_mut().unwrap().on_complete = Some(callback);
+        }
+        if let Some(callback) = self.on_complete.take() {
+            self.segments.last_mut().unwrap().on_complete = Some(callback);
+        }
+        self.segments.append(&mut next.segments);
+        // cancelling the sequence cancels both
+        self.on_cancel = match (self.on_cancel.take(), next.on_cancel.take()) {
+            (Some(first), Some(second)) => Some(Box::new(move || {
+                first();
+                second();
+            })),
+            (first, second) => first.or(second),
+        };
+        self
+    }
+
+    pub fn on_complete(mut self, callback: impl FnOnce() + 'static) -> Self {
+        self.on_complete = Some(Box::new(callback));
+        self
+    }
+
+    pub fn on_cancel(mut self, callback: impl FnOnce() + 'static) -> Self {
+        self.on_cancel = Some(Box::new(callback));
+        self
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.segments.iter().map(|s| s.duration).sum()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TweenId(u64);
+
+struct Playing {
+    id: TweenId,
+    tween: Tween,
+    current: usize,
+    elapsed: f32,
+    started: bool,
+}
+
+/// Owns all running tweens and advances them from the engine's update loop.
+#[derive(Default)]
+pub struct Tweens {
+    playing: Vec<Playing>,
+    next_id: u64,
+}
+
+impl Tweens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn play(&mut self, tween: Tween) -> TweenId {
+        let id = TweenId(self.next_id);
+        self.next_id += 1;
+        self.playing.push(Playing {
+            id,
+            tween,
+            current: 0,
+            elapsed: 0.0,
+            started: false,
+        });
+        id
+    }
+
+    /// Shorthand for `play(Tween::to(target, to, duration, ease))`.
+    pub fn tween<T: Tweenable>(&mut self, target: &Animated<T>, to: T, duration: f32, ease: Ease) -> TweenId {
+        self.play(Tween::to(target, to, duration, ease))
+    }
+
+    /// Stop a tween where it is, running its cancel callback. Returns false if it already finished.
+    pub fn cancel(&mut self, id: TweenId) -> bool {
+        match self.playing.iter().position(|p| p.id == id) {
+            Some(index) => {
+                let playing = self.playing.remove(index);
+                if let Some(callback) = playing.tween.on_cancel {
+                    callback();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn cancel_all(&mut self) {
+        for playing in std::mem::take(&mut self.playing) {
+            if let Some(callback) = playing.tween.on_cancel {
+                callback();
+            }
+        }
+    }
+
+    pub fn is_playing(&self, id: TweenId) -> bool {
+        self.playing.iter().any(|p| p.id == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.playing.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.playing.is_empty()
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let mut finished = Vec::new();
+        for playing in &mut self.playing {
+            let mut remaining = dt;
+            loop {
+                let segment = &mut playing.tween.segments[playing.current];
+                if !playing.started {
+                    segment.step.start();
+                    playing.started = true;
+                }
+
+                playing.elapsed += remaining;
+                let t = if segment.duration > 0.0 {
+                    playing.elapsed / segment.duration
+                } else {
+                    1.0
+                };
+                segment.step.apply(segment.ease.apply(t));
+                if t < 1.0 {
+                    break;
+                }
+
+                // carry the overshoot into the next segment so chains don't drift
+                remaining = playing.elapsed - segment.duration;
+                playing.elapsed = 0.0;
+                playing.started = false;
+                if let Some(callback) = segment.on_complete.take() {
+                    callback();
+                }
+                playing.current += 1;
+                if playing.current == playing.tween.segments.len() {
+                    finished.push(playing.id);
+                    break;
+                }
+            }
+        }
+
+        for id in finished {
+            let index = self.playing.iter().position(|p| p.id == id).unwrap();
+            let playing = self.playing.remove(index);
+            if let Some(callback) = playing.tween.on_complete {
+                callback();
+            }
+        }
+    }
+}
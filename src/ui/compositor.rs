@@ -0,0 +1,56 @@
+use crate::postprocess::{fullscreen_module, texture_entry, FullscreenPipeline};
+
+/// Draws the UI over the finished, display-referred frame. The UI backend renders into a layer
+/// of `LAYER_FORMAT`, which is composited with premultiplied alpha and encoded for the output
+/// format, so the UI looks the same whether or not the surface is sRGB and whatever the scene
+/// was rendered in.
+pub struct UiCompositor {
+    layout: wgpu::BindGroupLayout,
+    pipeline: FullscreenPipeline,
+}
+
+impl UiCompositor {
+    /// What the UI backend draws into: blending happens in linear space, like on an sRGB
+    /// surface, and the layer stays 8 bits a channel.
+    pub const LAYER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let module = fullscreen_module(device, "ui composite", include_str!("../shaders/ui_composite.wgsl"));
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("ui composite layout"),
+            entries: &[texture_entry(0)],
+        });
+        let entry_point = if output_format.describe().srgb { "fs_linear" } else { "fs_encode" };
+        let pipeline = FullscreenPipeline::new(
+            device,
+            "ui composite",
+            &module,
+            entry_point,
+            &[&layout],
+            output_format,
+            Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+        );
+        UiCompositor { layout, pipeline }
+    }
+
+    /// Blends `layer`, the size of `output`, over it, clearing `output` first with `clear`.
+    pub fn composite(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        layer: &wgpu::TextureView,
+        output: &wgpu::TextureView,
+        clear: Option<wgpu::Color>,
+    ) {
+        // the layer is a transient texture, so it is bound anew each frame
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("ui composite bind group"),
+            layout: &self.layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(layer),
+            }],
+        });
+        self.pipeline.draw(encoder, output, &[&bind_group], clear);
+    }
+}
@@ -0,0 +1,235 @@
+use egui::FontDefinitions;
+use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+use egui_winit_platform::{Platform, PlatformDescriptor};
+use winit::{event::Event, window::Window};
+
+use super::{paint_cache::PaintCache, platform_output, UiBackend};
+use crate::{
+    cursor::{Cursor, CursorIcon},
+    gpu_error::{self, GpuError},
+};
+
+/// egui for one window, through `egui_winit_platform` for input and `egui_wgpu_backend` for
+/// drawing. Each window has its own egui context, so their layouts and input never mix.
+pub struct EguiBackend {
+    platform: Platform,
+    render_pass: RenderPass,
+    paint_cache: PaintCache,
+    /// The window's size and scale as of the last `end_frame`.
+    screen: ScreenDescriptor,
+    cursor: Option<Cursor>,
+    /// Whether the last `end_frame` changed anything, so the vertex buffers need uploading.
+    changed: bool,
+    output_format: wgpu::TextureFormat,
+    sample_count: u32,
+    /// What the UI is drawn into and resolved from with multisampling, with its size.
+    multisampled: Option<(wgpu::TextureView, (u32, u32))>,
+}
+
+impl EguiBackend {
+    /// Draws into targets of `output_format`, antialiased with `sample_count` samples if more
+    /// than one.
+    pub fn new(
+        device: &wgpu::Device,
+        output_format: wgpu::TextureFormat,
+        sample_count: u32,
+        window: &Window,
+    ) -> Result<Self, GpuError> {
+        let size = window.inner_size();
+        let platform = Platform::new(PlatformDescriptor {
+            physical_width: size.width,
+            physical_height: size.height,
+            scale_factor: window.scale_factor(),
+            font_definitions: FontDefinitions::default(),
+            style: Default::default(),
+        });
+        let render_pass = gpu_error::capture(device, "creating the egui render pass", || {
+            RenderPass::new(device, output_format, sample_count)
+        })?;
+        Ok(EguiBackend {
+            platform,
+            render_pass,
+            paint_cache: PaintCache::new(),
+            screen: ScreenDescriptor {
+                physical_width: size.width,
+                physical_height: size.height,
+                scale_factor: window.scale_factor() as f32,
+            },
+            cursor: None,
+            changed: true,
+            output_format,
+            sample_count,
+            multisampled: None,
+        })
+    }
+
+    pub fn context(&self) -> egui::CtxRef {
+        self.platform.context()
+    }
+
+    fn multisampled_texture(&self, device: &wgpu::Device, (width, height): (u32, u32)) -> wgpu::TextureView {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("egui multisampled"),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: self.sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.output_format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// The renderer, for registering user textures.
+    pub fn render_pass(&mut self) -> &mut RenderPass {
+        &mut self.render_pass
+    }
+}
+
+impl<E> UiBackend<E> for EguiBackend {
+    fn handle_event(&mut self, event: &Event<'_, E>) {
+        self.platform.handle_event(event);
+    }
+
+    fn captures_event(&self, event: &Event<'_, E>) -> bool {
+        self.platform.captures_event(event)
+    }
+
+    fn begin_frame(&mut self, time: f64) {
+        self.platform.update_time(time);
+        self.platform.begin_frame();
+    }
+
+    fn end_frame(&mut self, window: &Window) {
+        // the cursor is applied by `Cursors`, which also knows what the game wants
+        let (output, shapes) = self.platform.end_frame(None);
+        let context = self.platform.context();
+        self.cursor = match output.cursor_icon {
+            egui::CursorIcon::Default if context.is_pointer_over_area() || context.is_using_pointer() => {
+                Some(Cursor::Icon(CursorIcon::Default))
+            }
+            egui::CursorIcon::Default => None,
+            egui::CursorIcon::None => Some(Cursor::Hidden),
+            icon => Some(Cursor::Icon(winit_cursor_icon(icon))),
+        };
+        handle_platform_output(&output, window);
+        let size = window.inner_size();
+        self.screen = ScreenDescriptor {
+            physical_width: size.width,
+            physical_height: size.height,
+            scale_factor: window.scale_factor() as f32,
+        };
+        self.changed |= self.paint_cache.update(&self.platform.context(), shapes, (size.width, size.height));
+    }
+
+    fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        clear: Option<wgpu::Color>,
+    ) {
+        let screen_descriptor = &self.screen;
+        self.render_pass.update_texture(device, queue, &self.platform.context().font_image());
+        self.render_pass.update_user_textures(device, queue);
+        if self.changed {
+            self.render_pass.update_buffers(device, queue, self.paint_cache.jobs(), screen_descriptor);
+            self.changed = false;
+        }
+        let result = if self.sample_count > 1 {
+            let size = (screen_descriptor.physical_width, screen_descriptor.physical_height);
+            if self.multisampled.as_ref().map(|(_, multisampled_size)| *multisampled_size) != Some(size) {
+                self.multisampled = Some((self.multisampled_texture(device, size), size));
+            }
+            let (view, _) = self.multisampled.as_ref().unwrap();
+            // the samples aren't kept between frames, so this always clears
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("egui multisampled"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: Some(target),
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(clear.unwrap_or(wgpu::Color::TRANSPARENT)),
+                        store: false,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            self.render_pass.execute_with_renderpass(&mut pass, self.paint_cache.jobs(), screen_descriptor)
+        } else {
+            self.render_pass.execute(encoder, target, self.paint_cache.jobs(), screen_descriptor, clear)
+        };
+        if let Err(e) = result {
+            log::error!("Failed to draw the UI: {}", e);
+        }
+    }
+
+    fn cursor(&self) -> Option<Cursor> {
+        self.cursor
+    }
+
+    fn egui_context(&self) -> Option<egui::CtxRef> {
+        Some(self.platform.context())
+    }
+
+    fn register_texture(&mut self, device: &wgpu::Device, texture: &wgpu::Texture) -> Option<egui::TextureId> {
+        Some(self.render_pass.egui_texture_from_wgpu_texture(device, texture, wgpu::FilterMode::Linear))
+    }
+}
+
+/// What egui asks of the platform besides the cursor: clipboard writes, opening links and
+/// where the text caret is, so IME candidate windows show up next to it.
+fn handle_platform_output(output: &egui::Output, window: &Window) {
+    if !output.copied_text.is_empty() {
+        let text = output.copied_text.clone();
+        // the clipboard tools are separate processes; don't stall the frame on them
+        std::thread::spawn(move || {
+            if let Err(e) = platform_output::copy_to_clipboard(&text) {
+                log::error!("Failed to copy to the clipboard: {}", e);
+            }
+        });
+    }
+    if let Some(open_url) = &output.open_url {
+        if let Err(e) = platform_output::open_url(&open_url.url) {
+            log::error!("Failed to open {}: {}", open_url.url, e);
+        }
+    }
+    if let Some(position) = output.text_cursor_pos {
+        window.set_ime_position(winit::dpi::LogicalPosition::new(position.x, position.y));
+    }
+}
+
+fn winit_cursor_icon(icon: egui::CursorIcon) -> CursorIcon {
+    match icon {
+        egui::CursorIcon::Default | egui::CursorIcon::None => CursorIcon::Default,
+        egui::CursorIcon::ContextMenu => CursorIcon::ContextMenu,
+        egui::CursorIcon::Help => CursorIcon::Help,
+        egui::CursorIcon::PointingHand => CursorIcon::Hand,
+        egui::CursorIcon::Progress => CursorIcon::Progress,
+        egui::CursorIcon::Wait => CursorIcon::Wait,
+        egui::CursorIcon::Cell => CursorIcon::Cell,
+        egui::CursorIcon::Crosshair => CursorIcon::Crosshair,
+        egui::CursorIcon::Text => CursorIcon::Text,
+        egui::CursorIcon::VerticalText => CursorIcon::VerticalText,
+        egui::CursorIcon::Alias => CursorIcon::Alias,
+        egui::CursorIcon::Copy => CursorIcon::Copy,
+        egui::CursorIcon::Move => CursorIcon::Move,
+        egui::CursorIcon::NoDrop => CursorIcon::NoDrop,
+        egui::CursorIcon::NotAllowed => CursorIcon::NotAllowed,
+        egui::CursorIcon::Grab => CursorIcon::Grab,
+        egui::CursorIcon::Grabbing => CursorIcon::Grabbing,
+        egui::CursorIcon::AllScroll => CursorIcon::AllScroll,
+        egui::CursorIcon::ResizeHorizontal => CursorIcon::EwResize,
+        egui::CursorIcon::ResizeNeSw => CursorIcon::NeswResize,
+        egui::CursorIcon::ResizeNwSe => CursorIcon::NwseResize,
+        egui::CursorIcon::ResizeVertical => CursorIcon::NsResize,
+        egui::CursorIcon::ZoomIn => CursorIcon::ZoomIn,
+        egui::CursorIcon::ZoomOut => CursorIcon::ZoomOut,
+    }
+}
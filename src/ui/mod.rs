@@ -0,0 +1,156 @@
+pub mod compositor;
+#[cfg(feature = "egui")]
+pub mod egui_backend;
+#[cfg(feature = "egui")]
+pub mod paint_cache;
+pub mod platform_output;
+
+use std::collections::HashMap;
+
+use winit::{
+    event::Event,
+    window::{Window, WindowId},
+};
+
+use crate::cursor::Cursor;
+
+/// What the engine needs from a UI library: feeding it window events, running a frame and
+/// drawing the result over the rendered scene. `E` is the event loop's user event type.
+///
+/// A backend belongs to one window: it only sees that window's events and draws at that
+/// window's size and scale, so several windows each get their own through `WindowUis`.
+///
+/// egui is the default, behind the `egui` feature; `NoUi` stands in for builds without any.
+pub trait UiBackend<E = ()> {
+    fn handle_event(&mut self, event: &Event<'_, E>);
+
+    /// Whether the UI wants the event to itself, e.g. a click on a panel or typing into a text
+    /// field, so game input should ignore it.
+    fn captures_event(&self, _event: &Event<'_, E>) -> bool {
+        false
+    }
+
+    /// Starts a frame; `time` is in seconds since startup and drives UI animations.
+    fn begin_frame(&mut self, time: f64);
+
+    /// Finishes the frame laid out for `window`, which must be the window this backend is for.
+    fn end_frame(&mut self, window: &Window);
+
+    /// Draws the last finished frame over `target`, clearing it first if `clear` is set.
+    /// `target` is at the window's size as of the last `end_frame`: with egui, a
+    /// `compositor::UiCompositor` layer; otherwise the window's own surface texture.
+    fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        clear: Option<wgpu::Color>,
+    );
+
+    /// The cursor the UI asked for in the last frame, `None` if it doesn't mind what the game
+    /// shows, e.g. when the pointer isn't over any of it. Goes to `Cursors::apply`.
+    fn cursor(&self) -> Option<Cursor> {
+        None
+    }
+
+    /// The egui context to build this frame's UI with, between `begin_frame` and `end_frame`.
+    #[cfg(feature = "egui")]
+    fn egui_context(&self) -> Option<egui::CtxRef> {
+        None
+    }
+
+    /// Makes `texture` drawable with `egui::Image`, e.g. a render target. `None` if this
+    /// backend can't show textures.
+    #[cfg(feature = "egui")]
+    fn register_texture(&mut self, _device: &wgpu::Device, _texture: &wgpu::Texture) -> Option<egui::TextureId> {
+        None
+    }
+}
+
+/// The backend of game builds without a UI, or with one drawn entirely in game code.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoUi;
+
+impl<E> UiBackend<E> for NoUi {
+    fn handle_event(&mut self, _event: &Event<'_, E>) {}
+
+    fn begin_frame(&mut self, _time: f64) {}
+
+    fn end_frame(&mut self, _window: &Window) {}
+
+    fn render(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        clear: Option<wgpu::Color>,
+    ) {
+        // nothing to draw, but the caller still expects the clear
+        if let Some(color) = clear {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("clear"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(color),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+        }
+    }
+}
+
+/// The UI of every window, keyed by window. Window events are routed to the UI of the window
+/// they happened in; everything else, like device events, reaches all of them.
+pub struct WindowUis<E> {
+    uis: HashMap<WindowId, Box<dyn UiBackend<E>>>,
+}
+
+impl<E> WindowUis<E> {
+    pub fn new() -> Self {
+        WindowUis { uis: HashMap::new() }
+    }
+
+    pub fn insert(&mut self, window: WindowId, ui: Box<dyn UiBackend<E>>) {
+        self.uis.insert(window, ui);
+    }
+
+    /// Drops a window's UI, e.g. when the window closes.
+    pub fn remove(&mut self, window: WindowId) -> Option<Box<dyn UiBackend<E>>> {
+        self.uis.remove(&window)
+    }
+
+    pub fn get_mut(&mut self, window: WindowId) -> Option<&mut (dyn UiBackend<E> + 'static)> {
+        self.uis.get_mut(&window).map(|ui| ui.as_mut())
+    }
+
+    pub fn handle_event(&mut self, event: &Event<'_, E>) {
+        match event {
+            Event::WindowEvent { window_id, .. } => {
+                if let Some(ui) = self.uis.get_mut(window_id) {
+                    ui.handle_event(event);
+                }
+            }
+            _ => self.uis.values_mut().for_each(|ui| ui.handle_event(event)),
+        }
+    }
+
+    /// Whether the UI of the window an event happened in wants it to itself.
+    pub fn captures_event(&self, event: &Event<'_, E>) -> bool {
+        match event {
+            Event::WindowEvent { window_id, .. } => self.uis.get(window_id).is_some_and(|ui| ui.captures_event(event)),
+            _ => false,
+        }
+    }
+}
+
+impl<E> Default for WindowUis<E> {
+    fn default() -> Self {
+        WindowUis::new()
+    }
+}
@@ -0,0 +1,41 @@
+use egui::{epaint::ClippedShape, ClippedMesh};
+
+/// Last frame's tessellated egui output. The loop redraws continuously, but most frames the UI
+/// looks exactly as before; then tessellation and the vertex upload are skipped and the cached
+/// paint jobs are drawn again.
+#[derive(Default)]
+pub struct PaintCache {
+    shapes: Vec<ClippedShape>,
+    jobs: Vec<ClippedMesh>,
+    /// Target size in pixels, pixels per point and font texture version the jobs were made for.
+    key: Option<(u32, u32, f32, u64)>,
+}
+
+impl PaintCache {
+    pub fn new() -> Self {
+        PaintCache::default()
+    }
+
+    /// Takes this frame's shapes from `end_frame` and tessellates them if anything visible
+    /// changed. Returns whether it did, in which case the paint jobs' buffers need uploading
+    /// again.
+    pub fn update(&mut self, context: &egui::CtxRef, shapes: Vec<ClippedShape>, target_size: (u32, u32)) -> bool {
+        let key = Some((target_size.0, target_size.1, context.pixels_per_point(), context.font_image().version));
+        if key == self.key && shapes == self.shapes {
+            return false;
+        }
+        self.jobs = context.tessellate(shapes.clone());
+        self.shapes = shapes;
+        self.key = key;
+        true
+    }
+
+    pub fn jobs(&self) -> &[ClippedMesh] {
+        &self.jobs
+    }
+
+    /// Forces the next `update` to tessellate, e.g. after the renderer lost its buffers.
+    pub fn invalidate(&mut self) {
+        self.key = None;
+    }
+}
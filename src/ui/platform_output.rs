@@ -0,0 +1,71 @@
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+/// The schemes `open_url` hands to the system: anything else, e.g. `file:` or a custom
+/// protocol handler, could run programs.
+const ALLOWED_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+fn is_allowed(url: &str) -> bool {
+    let Some((scheme, _)) = url.split_once(':') else {
+        return false;
+    };
+    // control characters and spaces are never part of a valid url and could split it
+    ALLOWED_SCHEMES.iter().any(|allowed| scheme.eq_ignore_ascii_case(allowed))
+        && !url.chars().any(|c| c.is_control() || c.is_whitespace())
+}
+
+/// Opens an http, https or mailto `url` with the system's default handler, normally the
+/// browser. Other urls are refused.
+pub fn open_url(url: &str) -> io::Result<()> {
+    // a known scheme also means the url can't start with `-` and be taken for an option
+    if !is_allowed(url) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "only http, https and mailto links are opened"));
+    }
+    let mut command = if cfg!(target_os = "windows") {
+        // unlike `cmd /C start`, doesn't interpret `&`, `|` or `^` in the url
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+    let mut child = command.arg(url).stdin(Stdio::null()).stdout(Stdio::null()).spawn()?;
+    // reap it once the handler has taken over, without waiting here
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Puts `text` on the system clipboard through the platform's clipboard tool: `pbcopy`, `clip`,
+/// or on Linux `wl-copy` under Wayland and `xclip` or `xsel` under X11.
+pub fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let candidates: &[(&str, &[&str])] = if cfg!(target_os = "windows") {
+        &[("clip", &[])]
+    } else if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        &[("wl-copy", &[]), ("xclip", &["-selection", "clipboard"]), ("xsel", &["--clipboard", "--input"])]
+    } else {
+        &[("xclip", &["-selection", "clipboard"]), ("xsel", &["--clipboard", "--input"])]
+    };
+
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no clipboard tool found");
+    for (program, args) in candidates {
+        match Command::new(program).args(*args).stdin(Stdio::piped()).stdout(Stdio::null()).spawn() {
+            Ok(mut child) => {
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(text.as_bytes())?;
+                }
+                // the X11 and Wayland tools fork to keep serving the selection, so this returns
+                // as soon as the text is handed over
+                child.wait()?;
+                return Ok(());
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
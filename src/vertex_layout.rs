@@ -0,0 +1,264 @@
+use std::{collections::HashMap, fmt};
+
+/// What a vertex attribute means. Each semantic has a fixed shader location, so a shader reads
+/// e.g. the second UV set from the same location whatever else the mesh carries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VertexSemantic {
+    Position,
+    Normal,
+    Uv0,
+    /// Linear RGBA.
+    Color,
+    /// Second UV set, for lightmaps and detail maps.
+    Uv1,
+    /// Tangent in xyz, bitangent sign in w.
+    Tangent,
+    Joints,
+    Weights,
+}
+
+impl VertexSemantic {
+    pub const ALL: [VertexSemantic; 8] = [
+        VertexSemantic::Position,
+        VertexSemantic::Normal,
+        VertexSemantic::Uv0,
+        VertexSemantic::Color,
+        VertexSemantic::Uv1,
+        VertexSemantic::Tangent,
+        VertexSemantic::Joints,
+        VertexSemantic::Weights,
+    ];
+
+    /// Position, normal and the first UV set, which every mesh layout has.
+    pub const REQUIRED: [VertexSemantic; 3] = [VertexSemantic::Position, VertexSemantic::Normal, VertexSemantic::Uv0];
+
+    pub fn location(self) -> u32 {
+        match self {
+            VertexSemantic::Position => 0,
+            VertexSemantic::Normal => 1,
+            VertexSemantic::Uv0 => 2,
+            VertexSemantic::Color => 3,
+            VertexSemantic::Uv1 => 4,
+            VertexSemantic::Tangent => 5,
+            VertexSemantic::Joints => 6,
+            VertexSemantic::Weights => 7,
+        }
+    }
+
+    /// The `#ifdef` name mesh shaders use to test for this attribute, `None` for the required
+    /// ones.
+    pub fn shader_feature(self) -> Option<&'static str> {
+        match self {
+            VertexSemantic::Position | VertexSemantic::Normal | VertexSemantic::Uv0 => None,
+            VertexSemantic::Color => Some("VERTEX_COLOR"),
+            VertexSemantic::Uv1 => Some("UV2"),
+            VertexSemantic::Tangent => Some("VERTEX_TANGENT"),
+            VertexSemantic::Joints => Some("JOINTS"),
+            VertexSemantic::Weights => Some("WEIGHTS"),
+        }
+    }
+
+    /// The format used when a layout doesn't ask for a more compact one.
+    pub fn default_format(self) -> wgpu::VertexFormat {
+        match self {
+            VertexSemantic::Position | VertexSemantic::Normal => wgpu::VertexFormat::Float32x3,
+            VertexSemantic::Uv0 | VertexSemantic::Uv1 => wgpu::VertexFormat::Float32x2,
+            VertexSemantic::Color => wgpu::VertexFormat::Unorm8x4,
+            VertexSemantic::Tangent | VertexSemantic::Weights => wgpu::VertexFormat::Float32x4,
+            VertexSemantic::Joints => wgpu::VertexFormat::Uint16x4,
+        }
+    }
+
+    /// Whether `format` decodes to the type mesh shaders declare for this semantic.
+    pub fn accepts(self, format: wgpu::VertexFormat) -> bool {
+        use wgpu::VertexFormat::*;
+        match self {
+            VertexSemantic::Position | VertexSemantic::Normal => format == Float32x3,
+            VertexSemantic::Uv0 | VertexSemantic::Uv1 => matches!(format, Float32x2 | Float16x2 | Unorm16x2),
+            VertexSemantic::Color | VertexSemantic::Weights => {
+                matches!(format, Float32x4 | Float16x4 | Unorm8x4 | Unorm16x4)
+            }
+            VertexSemantic::Tangent => matches!(format, Float32x4 | Float16x4 | Snorm8x4 | Snorm16x4),
+            VertexSemantic::Joints => matches!(format, Uint8x4 | Uint16x4 | Uint32x4),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexElement {
+    pub semantic: VertexSemantic,
+    pub format: wgpu::VertexFormat,
+}
+
+impl VertexElement {
+    pub fn new(semantic: VertexSemantic, format: wgpu::VertexFormat) -> Self {
+        VertexElement { semantic, format }
+    }
+}
+
+impl From<VertexSemantic> for VertexElement {
+    fn from(semantic: VertexSemantic) -> Self {
+        VertexElement::new(semantic, semantic.default_format())
+    }
+}
+
+#[derive(Debug)]
+pub enum VertexLayoutError {
+    Missing(VertexSemantic),
+    Duplicate(VertexSemantic),
+    Format(VertexElement),
+}
+
+impl fmt::Display for VertexLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VertexLayoutError::Missing(semantic) => write!(f, "vertex layout has no {:?} attribute", semantic),
+            VertexLayoutError::Duplicate(semantic) => write!(f, "vertex layout has more than one {:?} attribute", semantic),
+            VertexLayoutError::Format(element) => {
+                write!(f, "{:?} can't be stored as {:?}", element.semantic, element.format)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VertexLayoutError {}
+
+/// The attributes of an interleaved vertex buffer, in the order they are stored. Importers and
+/// procedural meshes describe what they produce with one of these, and `MeshPipelines` builds
+/// pipelines to match.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexLayout {
+    elements: Vec<VertexElement>,
+    attributes: Vec<wgpu::VertexAttribute>,
+    stride: wgpu::BufferAddress,
+}
+
+impl VertexLayout {
+    pub fn new(elements: impl IntoIterator<Item = impl Into<VertexElement>>) -> Result<Self, VertexLayoutError> {
+        let elements: Vec<VertexElement> = elements.into_iter().map(Into::into).collect();
+        for (index, element) in elements.iter().enumerate() {
+            if elements[..index].iter().any(|other| other.semantic == element.semantic) {
+                return Err(VertexLayoutError::Duplicate(element.semantic));
+            }
+            if !element.semantic.accepts(element.format) {
+                return Err(VertexLayoutError::Format(*element));
+            }
+        }
+        if let Some(&missing) = VertexSemantic::REQUIRED
+            .iter()
+            .find(|&&semantic| elements.iter().all(|element| element.semantic != semantic))
+        {
+            return Err(VertexLayoutError::Missing(missing));
+        }
+
+        let mut stride = 0;
+        let attributes = elements
+            .iter()
+            .map(|element| {
+                let attribute = wgpu::VertexAttribute {
+                    format: element.format,
+                    offset: stride,
+                    shader_location: element.semantic.location(),
+                };
+                stride += element.format.size();
+                attribute
+            })
+            .collect();
+        Ok(VertexLayout {
+            elements,
+            attributes,
+            stride,
+        })
+    }
+
+    /// Position, normal and UV: what static meshes and skinning output use.
+    pub fn standard() -> Self {
+        VertexLayout::new(VertexSemantic::REQUIRED).unwrap()
+    }
+
+    pub fn elements(&self) -> &[VertexElement] {
+        &self.elements
+    }
+
+    pub fn stride(&self) -> wgpu::BufferAddress {
+        self.stride
+    }
+
+    pub fn has(&self, semantic: VertexSemantic) -> bool {
+        self.elements.iter().any(|element| element.semantic == semantic)
+    }
+
+    /// Offset and format of an attribute within a vertex.
+    pub fn attribute(&self, semantic: VertexSemantic) -> Option<&wgpu::VertexAttribute> {
+        self.elements
+            .iter()
+            .position(|element| element.semantic == semantic)
+            .map(|index| &self.attributes[index])
+    }
+
+    pub fn buffer_layout(&self) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.stride,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &self.attributes,
+        }
+    }
+
+    /// `#ifdef` names of the optional attributes present, for selecting the matching variant of
+    /// a mesh shader.
+    pub fn shader_features(&self) -> Vec<&'static str> {
+        self.elements.iter().filter_map(|element| element.semantic.shader_feature()).collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VertexLayoutId(pub u32);
+
+/// Every vertex layout in use. Identical layouts share an id, so pipelines can be cached per
+/// id no matter how many meshes use a layout.
+pub struct VertexLayouts {
+    layouts: Vec<VertexLayout>,
+    ids: HashMap<Vec<VertexElement>, VertexLayoutId>,
+}
+
+impl VertexLayouts {
+    /// The standard layout is always registered, as `VertexLayouts::STANDARD`.
+    pub const STANDARD: VertexLayoutId = VertexLayoutId(0);
+
+    pub fn new() -> Self {
+        let mut layouts = VertexLayouts {
+            layouts: Vec::new(),
+            ids: HashMap::new(),
+        };
+        layouts.register(VertexLayout::standard());
+        layouts
+    }
+
+    pub fn register(&mut self, layout: VertexLayout) -> VertexLayoutId {
+        if let Some(&id) = self.ids.get(&layout.elements) {
+            return id;
+        }
+        let id = VertexLayoutId(self.layouts.len() as u32);
+        self.ids.insert(layout.elements.clone(), id);
+        self.layouts.push(layout);
+        id
+    }
+
+    pub fn get(&self, id: VertexLayoutId) -> &VertexLayout {
+        &self.layouts[id.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.layouts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layouts.is_empty()
+    }
+}
+
+impl Default for VertexLayouts {
+    fn default() -> Self {
+        VertexLayouts::new()
+    }
+}
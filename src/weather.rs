@@ -0,0 +1,344 @@
+use glam::{Vec2, Vec3};
+
+use crate::{
+    particles::{Curve, EmitterShape, ParticleEffect, ParticleEmitter},
+    random::Rng,
+    save::{Persist, SaveError, SaveReader, SaveWriter},
+    sprite::{batch::SpriteBatch, UvRect},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precipitation {
+    Clear,
+    Rain,
+    Snow,
+}
+
+impl Precipitation {
+    fn to_u8(self) -> u8 {
+        match self {
+            Precipitation::Clear => 0,
+            Precipitation::Rain => 1,
+            Precipitation::Snow => 2,
+        }
+    }
+
+    fn from_u8(value: u8) -> Result<Self, SaveError> {
+        match value {
+            0 => Ok(Precipitation::Clear),
+            1 => Ok(Precipitation::Rain),
+            2 => Ok(Precipitation::Snow),
+            value => Err(SaveError::Format(format!("{} is not a precipitation", value))),
+        }
+    }
+}
+
+/// How weather has changed surfaces, for materials to darken wet ground and whiten snowy tops.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SurfaceWeather {
+    /// 0 dry to 1 soaked.
+    pub wetness: f32,
+    /// 0 bare to 1 fully covered, starting with the surfaces facing straight up.
+    pub snow: f32,
+}
+
+impl SurfaceWeather {
+    pub const SNOW_COLOR: Vec3 = glam::const_vec3!([0.9, 0.92, 0.95]);
+
+    /// Fraction of a surface with world `normal` under snow.
+    pub fn snow_coverage(&self, normal: Vec3) -> f32 {
+        let snow = self.snow.clamp(0.0, 1.0);
+        // slopes fill in as the cover grows; nothing sticks to walls or overhangs
+        let threshold = 1.0 - snow * 1.2;
+        let t = ((normal.normalize_or_zero().y - threshold) / 0.2).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t) * snow.sqrt()
+    }
+
+    /// Applies the weather to a material's linear base `color` and `roughness`; the mesh
+    /// shader does the same per pixel.
+    pub fn modulate(&self, color: Vec3, roughness: f32, normal: Vec3) -> (Vec3, f32) {
+        let wetness = self.wetness.clamp(0.0, 1.0);
+        // water fills the pores, so rough surfaces darken the most
+        let color = color * (1.0 - 0.5 * wetness * roughness);
+        let roughness = roughness * (1.0 - 0.7 * wetness);
+        let coverage = self.snow_coverage(normal);
+        (color.lerp(Self::SNOW_COLOR, coverage), roughness + (0.8 - roughness) * coverage)
+    }
+}
+
+/// The current weather, as a resource systems read from: particles, materials, wind for
+/// foliage and clouds. Changes blend in over `transition_time`, passing through clear weather
+/// when switching between rain and snow.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weather {
+    precipitation: Precipitation,
+    intensity: f32,
+    target: Precipitation,
+    target_intensity: f32,
+    /// Seconds a change from nothing to full intensity takes.
+    pub transition_time: f32,
+    /// Wind velocity in units per second; rain slants and snow drifts with it.
+    pub wind: Vec3,
+    wetness: f32,
+    snow: f32,
+    /// Seconds of full rain it takes to soak surfaces.
+    pub wetting_time: f32,
+    /// Seconds surfaces take to dry completely once it stops raining.
+    pub drying_time: f32,
+    /// Seconds of full snowfall it takes to cover everything.
+    pub snow_time: f32,
+    /// Seconds the full cover takes to melt once it stops snowing; melting wets surfaces.
+    pub melt_time: f32,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Weather {
+            precipitation: Precipitation::Clear,
+            intensity: 0.0,
+            target: Precipitation::Clear,
+            target_intensity: 0.0,
+            transition_time: 10.0,
+            wind: Vec3::ZERO,
+            wetness: 0.0,
+            snow: 0.0,
+            wetting_time: 60.0,
+            drying_time: 300.0,
+            snow_time: 600.0,
+            melt_time: 1200.0,
+        }
+    }
+}
+
+impl Weather {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts changing to `precipitation` at `intensity` from 0 to 1.
+    pub fn set(&mut self, precipitation: Precipitation, intensity: f32) {
+        self.target = precipitation;
+        self.target_intensity = if precipitation == Precipitation::Clear {
+            0.0
+        } else {
+            intensity.clamp(0.0, 1.0)
+        };
+    }
+
+    /// Switches immediately, e.g. when loading into a level that is already raining. Surfaces
+    /// keep their wetness and snow.
+    pub fn set_immediate(&mut self, precipitation: Precipitation, intensity: f32) {
+        self.set(precipitation, intensity);
+        self.precipitation = self.target;
+        self.intensity = self.target_intensity;
+    }
+
+    pub fn precipitation(&self) -> Precipitation {
+        self.precipitation
+    }
+
+    /// How hard it is raining or snowing right now, from 0 to 1.
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn rain(&self) -> f32 {
+        if self.precipitation == Precipitation::Rain {
+            self.intensity
+        } else {
+            0.0
+        }
+    }
+
+    pub fn snow(&self) -> f32 {
+        if self.precipitation == Precipitation::Snow {
+            self.intensity
+        } else {
+            0.0
+        }
+    }
+
+    pub fn surface(&self) -> SurfaceWeather {
+        SurfaceWeather {
+            wetness: self.wetness,
+            snow: self.snow,
+        }
+    }
+
+    /// Overrides the accumulated wetness and snow cover, e.g. for a level that starts snowed in.
+    pub fn set_surface(&mut self, surface: SurfaceWeather) {
+        self.wetness = surface.wetness.clamp(0.0, 1.0);
+        self.snow = surface.snow.clamp(0.0, 1.0);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let step = dt / self.transition_time.max(0.001);
+        if self.precipitation != self.target && self.precipitation != Precipitation::Clear {
+            // fade out what is falling before the new kind starts
+            self.intensity = (self.intensity - step).max(0.0);
+            if self.intensity == 0.0 {
+                self.precipitation = Precipitation::Clear;
+            }
+        } else {
+            if self.precipitation == Precipitation::Clear {
+                self.precipitation = self.target;
+            }
+            let difference = self.target_intensity - self.intensity;
+            self.intensity += difference.clamp(-step, step);
+        }
+
+        let rain = self.rain();
+        let snow = self.snow();
+        let rate = |time: f32| dt / time.max(0.001);
+        let melted = if snow > 0.0 { 0.0 } else { self.snow.min(rate(self.melt_time)) };
+        self.snow = (self.snow + snow * rate(self.snow_time) - melted).clamp(0.0, 1.0);
+        // melting snow wets surfaces like a light rain
+        let wetting = if melted > 0.0 { rain.max(0.5) } else { rain };
+        if wetting > 0.0 {
+            self.wetness += wetting * rate(self.wetting_time);
+        } else {
+            self.wetness -= rate(self.drying_time);
+        }
+        self.wetness = self.wetness.clamp(0.0, 1.0);
+    }
+}
+
+impl Persist for Weather {
+    fn save(&self, writer: &mut SaveWriter) {
+        writer.write_u8(self.precipitation.to_u8());
+        writer.write_f32(self.intensity);
+        writer.write_u8(self.target.to_u8());
+        writer.write_f32(self.target_intensity);
+        writer.write_f32(self.transition_time);
+        self.wind.save(writer);
+        writer.write_f32(self.wetness);
+        writer.write_f32(self.snow);
+        writer.write_f32(self.wetting_time);
+        writer.write_f32(self.drying_time);
+        writer.write_f32(self.snow_time);
+        writer.write_f32(self.melt_time);
+    }
+
+    fn load(reader: &mut SaveReader<'_>) -> Result<Self, SaveError> {
+        Ok(Weather {
+            precipitation: Precipitation::from_u8(reader.read_u8()?)?,
+            intensity: reader.read_f32()?,
+            target: Precipitation::from_u8(reader.read_u8()?)?,
+            target_intensity: reader.read_f32()?,
+            transition_time: reader.read_f32()?,
+            wind: Vec3::load(reader)?,
+            wetness: reader.read_f32()?,
+            snow: reader.read_f32()?,
+            wetting_time: reader.read_f32()?,
+            drying_time: reader.read_f32()?,
+            snow_time: reader.read_f32()?,
+            melt_time: reader.read_f32()?,
+        })
+    }
+}
+
+struct Precipitator {
+    emitter: ParticleEmitter,
+    max_rate: f32,
+    fall_speed: f32,
+    /// Drops and flakes stay in a box around the camera this tall.
+    height: f32,
+}
+
+impl Precipitator {
+    fn new(effect: ParticleEffect, fall_speed: f32, height: f32, rng: Rng) -> Self {
+        Precipitator {
+            max_rate: effect.rate,
+            emitter: ParticleEmitter::new(effect, Vec3::ZERO).with_rng(rng),
+            fall_speed,
+            height,
+        }
+    }
+
+    fn update(&mut self, dt: f32, intensity: f32, wind: Vec3, camera_position: Vec3) {
+        let effect = &mut self.emitter.effect;
+        effect.rate = self.max_rate * intensity;
+        // drag pulls every particle towards falling straight through the wind
+        effect.gravity = (wind - Vec3::Y * self.fall_speed) * effect.drag;
+        // spawn upwind so the slanted fall still passes the camera
+        let drift = wind * (self.height / self.fall_speed.max(0.01)) * 0.5;
+        self.emitter.position = camera_position + Vec3::Y * self.height * 0.5 - drift;
+        self.emitter.update(dt);
+    }
+}
+
+/// Rain and snow falling around the camera. Particles spawn in a box that follows it but fall
+/// in world space, so moving through the weather looks right.
+pub struct WeatherParticles {
+    rain: Precipitator,
+    snow: Precipitator,
+    /// Rain streaks are this many times longer than wide.
+    pub rain_stretch: f32,
+}
+
+impl WeatherParticles {
+    /// Particles within `radius` of the camera; more fill a larger area at the same density.
+    /// `seed` picks their randomness, e.g. from the `Random` resource for replays.
+    pub fn new(radius: f32, seed: u64) -> Self {
+        let area = (radius * radius / 100.0).max(0.01);
+        let fade = Curve {
+            keys: vec![(0.0, 0.0), (0.1, 1.0), (0.9, 1.0), (1.0, 0.0)],
+        };
+        let rain = ParticleEffect {
+            name: "Rain".to_owned(),
+            max_particles: (7000.0 * area) as u32,
+            rate: 5000.0 * area,
+            shape: EmitterShape::Box {
+                half_extents: Vec3::new(radius, 0.5, radius),
+            },
+            lifetime: [1.1, 1.3],
+            speed: [-10.0, -9.0],
+            size: [0.01, 0.015],
+            drag: 2.0,
+            start_color: [0.7, 0.75, 0.8, 0.35],
+            end_color: [0.7, 0.75, 0.8, 0.35],
+            alpha_over_life: fade.clone(),
+            ..ParticleEffect::default()
+        };
+        let snow = ParticleEffect {
+            name: "Snow".to_owned(),
+            max_particles: (4000.0 * area) as u32,
+            rate: 500.0 * area,
+            shape: EmitterShape::Box {
+                half_extents: Vec3::new(radius, 0.5, radius),
+            },
+            lifetime: [7.0, 8.0],
+            speed: [-1.2, -0.8],
+            size: [0.02, 0.04],
+            drag: 1.0,
+            start_color: [1.0, 1.0, 1.0, 0.9],
+            end_color: [1.0, 1.0, 1.0, 0.9],
+            alpha_over_life: fade,
+            ..ParticleEffect::default()
+        };
+        WeatherParticles {
+            rain: Precipitator::new(rain, 10.0, 12.0, Rng::with_stream(seed, 1)),
+            snow: Precipitator::new(snow, 1.0, 8.0, Rng::with_stream(seed, 2)),
+            rain_stretch: 30.0,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32, weather: &Weather, camera_position: Vec3) {
+        self.rain.update(dt, weather.rain(), weather.wind, camera_position);
+        self.snow.update(dt, weather.snow(), weather.wind, camera_position);
+    }
+
+    pub fn len(&self) -> usize {
+        self.rain.emitter.len() + self.snow.emitter.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds the drops and flakes to `batch`, both using the round soft sprite at `uv`.
+    pub fn write_sprites(&self, batch: &mut SpriteBatch, uv: UvRect) {
+        self.rain.emitter.write_sprites_scaled(batch, uv, Vec2::new(1.0, self.rain_stretch));
+        self.snow.emitter.write_sprites(batch, uv);
+    }
+}